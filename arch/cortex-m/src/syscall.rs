@@ -316,6 +316,86 @@ impl<A: CortexMVariant> kernel::syscall::UserspaceKernelBoundary for SysCall<A>
         (switch_reason, Some(new_stack_pointer as *const u8))
     }
 
+    unsafe fn get_registers(
+        &self,
+        accessible_memory_start: *const u8,
+        app_brk: *const u8,
+        state: &CortexMStoredState,
+    ) -> Option<kernel::syscall::ProcessRegisters> {
+        // PC and LR are part of the frame the hardware stacked on the process
+        // stack, so they can only be read if the stack pointer is valid.
+        if state.psp < accessible_memory_start as usize
+            || state.psp.saturating_add(SVC_FRAME_SIZE) > app_brk as usize
+        {
+            return None;
+        }
+        let stack_pointer = state.psp as *const usize;
+        Some(kernel::syscall::ProcessRegisters {
+            pc: ptr::read(stack_pointer.offset(6)),
+            lr: ptr::read(stack_pointer.offset(5)),
+            sp: state.psp,
+        })
+    }
+
+    unsafe fn get_debug_register(
+        &self,
+        accessible_memory_start: *const u8,
+        app_brk: *const u8,
+        state: &CortexMStoredState,
+        index: usize,
+    ) -> Option<usize> {
+        // GDB numbers the registers r0 to r15. The registers the hardware
+        // stacks (r0-r3, r12, lr and pc) can only be read if the stack pointer
+        // is valid.
+        let frame_offset = match index {
+            4..=11 => return Some(state.regs[index - 4]),
+            13 => return Some(state.psp),
+            0..=3 => index,
+            12 => 4,
+            14 => 5,
+            15 => 6,
+            _ => return None,
+        };
+        if state.psp < accessible_memory_start as usize
+            || state.psp.saturating_add(SVC_FRAME_SIZE) > app_brk as usize
+        {
+            return None;
+        }
+        Some(ptr::read((state.psp as *const usize).add(frame_offset)))
+    }
+
+    unsafe fn set_debug_register(
+        &self,
+        accessible_memory_start: *const u8,
+        app_brk: *const u8,
+        state: &mut CortexMStoredState,
+        index: usize,
+        value: usize,
+    ) -> Result<(), ErrorCode> {
+        let frame_offset = match index {
+            4..=11 => {
+                state.regs[index - 4] = value;
+                return Ok(());
+            }
+            13 => {
+                state.psp = value;
+                return Ok(());
+            }
+            0..=3 => index,
+            12 => 4,
+            14 => 5,
+            15 => 6,
+            _ => return Err(ErrorCode::INVAL),
+        };
+        if state.psp < accessible_memory_start as usize
+            || state.psp.saturating_add(SVC_FRAME_SIZE) > app_brk as usize
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        ptr::write((state.psp as *mut usize).add(frame_offset), value);
+        Ok(())
+    }
+
     unsafe fn print_context(
         &self,
         accessible_memory_start: *const u8,
@@ -433,4 +513,9 @@ impl<A: CortexMVariant> kernel::syscall::UserspaceKernelBoundary for SysCall<A>
             Err(ErrorCode::SIZE)
         }
     }
+
+    fn load_context(&self, state: &mut CortexMStoredState, input: &[u8]) -> Result<(), ErrorCode> {
+        *state = CortexMStoredState::try_from(input)?;
+        Ok(())
+    }
 }
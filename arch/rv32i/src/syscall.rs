@@ -449,6 +449,54 @@ impl kernel::syscall::UserspaceKernelBoundary for SysCall {
         (ret, Some(new_stack_pointer as *const u8))
     }
 
+    unsafe fn get_registers(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &Riscv32iStoredState,
+    ) -> Option<kernel::syscall::ProcessRegisters> {
+        Some(kernel::syscall::ProcessRegisters {
+            pc: state.pc as usize,
+            lr: state.regs[R_RA] as usize,
+            sp: state.regs[R_SP] as usize,
+        })
+    }
+
+    unsafe fn get_debug_register(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &Riscv32iStoredState,
+        index: usize,
+    ) -> Option<usize> {
+        // GDB numbers the registers x0 to x31 followed by the PC. `regs`
+        // starts at x1, as x0 is always zero.
+        match index {
+            0 => Some(0),
+            1..=31 => Some(state.regs[index - 1] as usize),
+            32 => Some(state.pc as usize),
+            _ => None,
+        }
+    }
+
+    unsafe fn set_debug_register(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &mut Riscv32iStoredState,
+        index: usize,
+        value: usize,
+    ) -> Result<(), ErrorCode> {
+        match index {
+            // Writes to x0 are ignored, as they are by the hardware.
+            0 => {}
+            1..=31 => state.regs[index - 1] = value as u32,
+            32 => state.pc = value as u32,
+            _ => return Err(ErrorCode::INVAL),
+        }
+        Ok(())
+    }
+
     unsafe fn print_context(
         &self,
         _accessible_memory_start: *const u8,
@@ -543,4 +591,9 @@ impl kernel::syscall::UserspaceKernelBoundary for SysCall {
             Err(ErrorCode::SIZE)
         }
     }
+
+    fn load_context(&self, state: &mut Riscv32iStoredState, input: &[u8]) -> Result<(), ErrorCode> {
+        *state = Riscv32iStoredState::try_from(input)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,168 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for loading applications while the kernel is running.
+//!
+//! This creates the kernel's dynamic process loader on top of the board's
+//! flash controller and the app loader syscall driver which exposes it to
+//! userspace. The syscall driver needs a grant, so the component must be
+//! created before processes are loaded. The board then loads its boot
+//! processes with `load_and_check_processes_with_remainder()` and gives the
+//! process memory that is still free to the loader.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let (app_loader, dynamic_loader) = components::app_loader::AppLoaderComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_loader::DRIVER_NUM,
+//!     chip,
+//!     fault_policy,
+//!     app_flash,
+//!     &base_peripherals.nvmc,
+//! )
+//! .finalize(components::app_loader_component_static!(
+//!     nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>,
+//!     nrf52840::nvmc::Nvmc,
+//!     512
+//! ));
+//!
+//! // After loading processes:
+//! let _ = dynamic_loader.add_free_memory(remaining_app_memory, &process_management_capability);
+//! ```
+//!
+//! The returned loader also implements `ProcessUninstall`, which the board
+//! can use to permanently remove processes and reuse their flash and memory.
+
+use capsules_extra::app_loader::AppLoader;
+use capsules_extra::nonvolatile_to_pages::NonvolatileToPages;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::dynamic_process_loading::{DynamicProcessLoader, DynamicProcessLoading};
+use kernel::hil;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+use kernel::platform::chip::Chip;
+use kernel::process::ProcessFaultPolicy;
+
+#[macro_export]
+macro_rules! app_loader_component_static {
+    ($C:ty, $F:ty, $buffer_size: literal) => {{
+        let buffer = kernel::static_buf!([u8; $buffer_size]);
+        let padding_buffer = kernel::static_buf!([u8; 16]);
+        let page_buffer = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let nv_to_page = kernel::static_buf!(
+            capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, $F>
+        );
+        let loader = kernel::static_buf!(kernel::dynamic_process_loading::DynamicProcessLoader<$C>);
+        let app_loader = kernel::static_buf!(capsules_extra::app_loader::AppLoader);
+        (
+            buffer,
+            padding_buffer,
+            page_buffer,
+            nv_to_page,
+            loader,
+            app_loader,
+        )
+    };};
+}
+
+pub struct AppLoaderComponent<
+    C: 'static + Chip,
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    const BUF_LEN: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    chip: &'static C,
+    fault_policy: &'static dyn ProcessFaultPolicy,
+    app_flash: &'static [u8],
+    storage: &'static F,
+}
+
+impl<
+        C: 'static + Chip,
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+        const BUF_LEN: usize,
+    > AppLoaderComponent<C, F, BUF_LEN>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        chip: &'static C,
+        fault_policy: &'static dyn ProcessFaultPolicy,
+        app_flash: &'static [u8],
+        storage: &'static F,
+    ) -> AppLoaderComponent<C, F, BUF_LEN> {
+        AppLoaderComponent {
+            board_kernel,
+            driver_num,
+            chip,
+            fault_policy,
+            app_flash,
+            storage,
+        }
+    }
+}
+
+impl<
+        C: 'static + Chip,
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+        const BUF_LEN: usize,
+    > Component for AppLoaderComponent<C, F, BUF_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; 16]>,
+        &'static mut MaybeUninit<<F as hil::flash::Flash>::Page>,
+        &'static mut MaybeUninit<NonvolatileToPages<'static, F>>,
+        &'static mut MaybeUninit<DynamicProcessLoader<C>>,
+        &'static mut MaybeUninit<AppLoader>,
+    );
+    type Output = (&'static AppLoader, &'static DynamicProcessLoader<C>);
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let process_management_cap = create_capability!(capabilities::ProcessManagementCapability);
+
+        let buffer = static_buffer.0.write([0; BUF_LEN]);
+        let padding_buffer = static_buffer.1.write([0; 16]);
+
+        let flash_pagebuffer = static_buffer
+            .2
+            .write(<F as hil::flash::Flash>::Page::default());
+
+        let nv_to_page = static_buffer
+            .3
+            .write(NonvolatileToPages::new(self.storage, flash_pagebuffer));
+        self.storage.set_client(nv_to_page);
+
+        let loader = static_buffer.4.write(DynamicProcessLoader::new(
+            self.board_kernel,
+            self.chip,
+            self.fault_policy,
+            self.app_flash,
+            nv_to_page,
+            padding_buffer,
+            &process_management_cap,
+        ));
+        loader.register();
+        nv_to_page.set_client(loader);
+
+        let app_loader = static_buffer.5.write(AppLoader::new(
+            loader,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            buffer,
+        ));
+        loader.set_client(app_loader);
+
+        (app_loader, loader)
+    }
+}
@@ -0,0 +1,96 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for rollback protection of another credentials checker.
+//!
+//! The highest accepted version of each application is stored with kernel
+//! permissions in a key-value store, usually a virtual KV user of the same
+//! stack that serves the KV syscall driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let virtual_kv_rollback = components::kv::VirtualKVPermissionsComponent::new(kv_store_mux)
+//!     .finalize(components::virtual_kv_permissions_component_static!(
+//!         KVStorePermissionsType
+//!     ));
+//!
+//! let checker = components::appid_rollback::AppCheckerRollbackComponent::new(
+//!     signature_checker,
+//!     virtual_kv_rollback,
+//! )
+//! .finalize(components::app_checker_rollback_component_static!(
+//!     AppCheckerSignatureType,
+//!     VirtualKVPermissionsType,
+//! ));
+//! ```
+
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::kv::KVPermissions;
+use kernel::process_checker::rollback::AppCheckerRollback;
+use kernel::process_checker::CredentialsCheckingPolicy;
+use kernel::storage_permissions::StoragePermissions;
+
+#[macro_export]
+macro_rules! app_checker_rollback_component_static {
+    ($C:ty, $KV:ty $(,)?) => {{
+        let checker =
+            kernel::static_buf!(kernel::process_checker::rollback::AppCheckerRollback<$C, $KV>);
+        let key_buffer = kernel::static_buf!([u8; 64]);
+        let value_buffer = kernel::static_buf!([u8; 32]);
+
+        (checker, key_buffer, value_buffer)
+    };};
+}
+
+pub struct AppCheckerRollbackComponent<
+    C: 'static + CredentialsCheckingPolicy<'static>,
+    KV: 'static + KVPermissions<'static>,
+> {
+    checker: &'static C,
+    kv: &'static KV,
+}
+
+impl<C: 'static + CredentialsCheckingPolicy<'static>, KV: 'static + KVPermissions<'static>>
+    AppCheckerRollbackComponent<C, KV>
+{
+    pub fn new(checker: &'static C, kv: &'static KV) -> AppCheckerRollbackComponent<C, KV> {
+        AppCheckerRollbackComponent { checker, kv }
+    }
+}
+
+impl<C: 'static + CredentialsCheckingPolicy<'static>, KV: 'static + KVPermissions<'static>>
+    Component for AppCheckerRollbackComponent<C, KV>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<AppCheckerRollback<C, KV>>,
+        &'static mut MaybeUninit<[u8; 64]>,
+        &'static mut MaybeUninit<[u8; 32]>,
+    );
+
+    type Output = &'static AppCheckerRollback<C, KV>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+
+        let key_buffer = s.1.write([0; 64]);
+        let value_buffer = s.2.write([0; 32]);
+
+        let checker = s.0.write(AppCheckerRollback::new(
+            self.checker,
+            self.kv,
+            StoragePermissions::new_kernel_permissions(&storage_cap),
+            key_buffer,
+            value_buffer,
+        ));
+
+        self.checker.set_client(checker);
+        self.kv.set_client(checker);
+
+        checker
+    }
+}
@@ -0,0 +1,130 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a credentials checker that verifies RSA signature
+//! credentials against public keys compiled into the board.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static TRUSTED_KEYS: [kernel::process_checker::rsa::RsaPublicKey; 1] =
+//!     [kernel::process_checker::rsa::RsaPublicKey {
+//!         modulus: &RSA3072_MODULUS,
+//!         exponent: &[0x01, 0x00, 0x01],
+//!     }];
+//!
+//! let sha = components::sha::ShaSoftware256Component::new()
+//!     .finalize(components::sha_software_256_component_static!());
+//! let rsa = static_init!(
+//!     capsules_extra::public_key_crypto::rsa_math::RsaMathSoftware<'static>,
+//!     capsules_extra::public_key_crypto::rsa_math::RsaMathSoftware::new()
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(rsa);
+//!
+//! let checker = components::appid_rsa::AppCheckerRsaComponent::new(
+//!     sha,
+//!     rsa,
+//!     kernel::process_checker::rsa::RsaPadding::Pkcs1v15,
+//!     &TRUSTED_KEYS,
+//! )
+//! .finalize(components::app_checker_rsa_component_static!(
+//!     capsules_extra::public_key_crypto::rsa_math::RsaMathSoftware<'static>,
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     32,
+//!     384,
+//! ));
+//! ```
+
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::digest;
+use kernel::hil::public_key_crypto::rsa_math;
+use kernel::process_checker::rsa::{AppCheckerRsa, RsaPadding, RsaPublicKey};
+
+/// `$BUF_LEN` is the length in bytes of the largest RSA modulus to check.
+#[macro_export]
+macro_rules! app_checker_rsa_component_static {
+    ($R:ty, $H:ty, $HL:expr, $BUF_LEN:expr $(,)?) => {{
+        let checker = kernel::static_buf!(
+            kernel::process_checker::rsa::AppCheckerRsa<$R, $H, $HL>
+        );
+        let hash_buffer = kernel::static_buf!([u8; $HL]);
+        let message_buffer = kernel::static_buf!([u8; $BUF_LEN]);
+        let result_buffer = kernel::static_buf!([u8; $BUF_LEN]);
+
+        (checker, hash_buffer, message_buffer, result_buffer)
+    };};
+}
+
+pub struct AppCheckerRsaComponent<
+    R: 'static + rsa_math::RsaCryptoBase<'static>,
+    H: 'static + digest::DigestDataHash<'static, HL>,
+    const HL: usize,
+    const BUF_LEN: usize,
+> {
+    hasher: &'static H,
+    rsa: &'static R,
+    padding: RsaPadding,
+    trusted_keys: &'static [RsaPublicKey],
+}
+
+impl<
+        R: 'static + rsa_math::RsaCryptoBase<'static>,
+        H: 'static + digest::DigestDataHash<'static, HL>,
+        const HL: usize,
+        const BUF_LEN: usize,
+    > AppCheckerRsaComponent<R, H, HL, BUF_LEN>
+{
+    pub fn new(
+        hasher: &'static H,
+        rsa: &'static R,
+        padding: RsaPadding,
+        trusted_keys: &'static [RsaPublicKey],
+    ) -> AppCheckerRsaComponent<R, H, HL, BUF_LEN> {
+        AppCheckerRsaComponent {
+            hasher,
+            rsa,
+            padding,
+            trusted_keys,
+        }
+    }
+}
+
+impl<
+        R: 'static + rsa_math::RsaCryptoBase<'static>,
+        H: 'static + digest::DigestDataHash<'static, HL>,
+        const HL: usize,
+        const BUF_LEN: usize,
+    > Component for AppCheckerRsaComponent<R, H, HL, BUF_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<AppCheckerRsa<R, H, HL>>,
+        &'static mut MaybeUninit<[u8; HL]>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+    );
+
+    type Output = &'static AppCheckerRsa<R, H, HL>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let hash_buffer = s.1.write([0; HL]);
+        let message_buffer = s.2.write([0; BUF_LEN]);
+        let result_buffer = s.3.write([0; BUF_LEN]);
+
+        let checker = s.0.write(AppCheckerRsa::new(
+            self.hasher,
+            self.rsa,
+            self.padding,
+            self.trusted_keys,
+            hash_buffer,
+            message_buffer,
+            result_buffer,
+        ));
+
+        digest::DigestDataHash::set_client(self.hasher, checker);
+        self.rsa.set_client(checker);
+
+        checker
+    }
+}
@@ -0,0 +1,124 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a credentials checker that verifies signature credentials
+//! against public keys compiled into the board.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static TRUSTED_KEYS: [&[u8]; 1] = [&[
+//!     0x04, 0x6b, 0x17, 0xd1, // ... 65-byte SEC1 encoded P-256 public key
+//! ]];
+//!
+//! let sha = components::sha::ShaSoftware256Component::new()
+//!     .finalize(components::sha_software_256_component_static!());
+//! let verifier = static_init!(
+//!     capsules_signature_sw::ecdsa_p256::EcdsaP256SignatureVerifier<'static>,
+//!     capsules_signature_sw::ecdsa_p256::EcdsaP256SignatureVerifier::new()
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(verifier);
+//!
+//! let checker = components::appid_signature::AppCheckerSignatureComponent::new(
+//!     sha,
+//!     verifier,
+//!     tock_tbf::types::TbfFooterV2CredentialsType::EcdsaNistP256,
+//!     &TRUSTED_KEYS,
+//! )
+//! .finalize(components::app_checker_signature_component_static!(
+//!     capsules_signature_sw::ecdsa_p256::EcdsaP256SignatureVerifier<'static>,
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     32,
+//!     64,
+//! ));
+//! ```
+
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::digest;
+use kernel::hil::public_key_crypto::signature;
+use kernel::process_checker::signature::AppCheckerSignature;
+use tock_tbf::types::TbfFooterV2CredentialsType;
+
+#[macro_export]
+macro_rules! app_checker_signature_component_static {
+    ($S:ty, $H:ty, $HL:expr, $SL:expr $(,)?) => {{
+        let checker = kernel::static_buf!(
+            kernel::process_checker::signature::AppCheckerSignature<$S, $H, $HL, $SL>
+        );
+        let hash_buffer = kernel::static_buf!([u8; $HL]);
+        let signature_buffer = kernel::static_buf!([u8; $SL]);
+
+        (checker, hash_buffer, signature_buffer)
+    };};
+}
+
+pub struct AppCheckerSignatureComponent<
+    S: 'static + signature::SignatureVerify<'static, HL, SL>,
+    H: 'static + digest::DigestDataHash<'static, HL>,
+    const HL: usize,
+    const SL: usize,
+> {
+    hasher: &'static H,
+    verifier: &'static S,
+    credential_type: TbfFooterV2CredentialsType,
+    trusted_keys: &'static [&'static [u8]],
+}
+
+impl<
+        S: 'static + signature::SignatureVerify<'static, HL, SL>,
+        H: 'static + digest::DigestDataHash<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > AppCheckerSignatureComponent<S, H, HL, SL>
+{
+    pub fn new(
+        hasher: &'static H,
+        verifier: &'static S,
+        credential_type: TbfFooterV2CredentialsType,
+        trusted_keys: &'static [&'static [u8]],
+    ) -> AppCheckerSignatureComponent<S, H, HL, SL> {
+        AppCheckerSignatureComponent {
+            hasher,
+            verifier,
+            credential_type,
+            trusted_keys,
+        }
+    }
+}
+
+impl<
+        S: 'static + signature::SignatureVerify<'static, HL, SL>,
+        H: 'static + digest::DigestDataHash<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > Component for AppCheckerSignatureComponent<S, H, HL, SL>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<AppCheckerSignature<S, H, HL, SL>>,
+        &'static mut MaybeUninit<[u8; HL]>,
+        &'static mut MaybeUninit<[u8; SL]>,
+    );
+
+    type Output = &'static AppCheckerSignature<S, H, HL, SL>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let hash_buffer = s.1.write([0; HL]);
+        let signature_buffer = s.2.write([0; SL]);
+
+        let checker = s.0.write(AppCheckerSignature::new(
+            self.hasher,
+            self.verifier,
+            self.credential_type,
+            self.trusted_keys,
+            hash_buffer,
+            signature_buffer,
+        ));
+
+        digest::DigestDataHash::set_client(self.hasher, checker);
+        self.verifier.set_verify_client(checker);
+
+        checker
+    }
+}
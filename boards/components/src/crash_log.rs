@@ -0,0 +1,110 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the persistent crash log.
+//!
+//! The crash log keeps the record of the last kernel panic or process fault
+//! in a reserved flash page. This component creates the capsule, reads the
+//! page at boot and registers the capsule with the kernel so process faults
+//! are recorded. The board's panic handler must pass the returned crash log
+//! to `kernel::debug::panic_record_crash` to record other panics.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let crash_log = components::crash_log::CrashLogComponent::new(
+//!     board_kernel,
+//!     capsules_extra::crash_log::DRIVER_NUM,
+//!     &base_peripherals.nvmc,
+//!     CRASH_LOG_PAGE,
+//! )
+//! .finalize(components::crash_log_component_static!(nrf52840::nvmc::Nvmc));
+//! // Add the `crash` command to a process console, if the board has one.
+//! let command = static_init!(
+//!     capsules_extra::crash_log::CrashCommand<'static>,
+//!     capsules_extra::crash_log::CrashCommand::new(crash_log)
+//! );
+//! let entry = static_init!(
+//!     capsules_core::process_console::CommandEntry<'static>,
+//!     capsules_core::process_console::CommandEntry::new("crash", "crash", command)
+//! );
+//! process_console.add_command(entry);
+//! ```
+
+use capsules_extra::crash_log::FlashCrashLog;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+
+#[macro_export]
+macro_rules! crash_log_component_static {
+    ($F:ty $(,)?) => {{
+        let page_buffer = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let crash_log = kernel::static_buf!(capsules_extra::crash_log::FlashCrashLog<'static, $F>);
+        (page_buffer, crash_log)
+    };};
+}
+
+pub struct CrashLogComponent<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, FlashCrashLog<'static, F>>,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    flash: &'static F,
+    page_number: usize,
+}
+
+impl<
+        F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, FlashCrashLog<'static, F>>,
+    > CrashLogComponent<F>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        flash: &'static F,
+        page_number: usize,
+    ) -> CrashLogComponent<F> {
+        CrashLogComponent {
+            board_kernel,
+            driver_num,
+            flash,
+            page_number,
+        }
+    }
+}
+
+impl<
+        F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, FlashCrashLog<'static, F>>,
+    > Component for CrashLogComponent<F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<<F as hil::flash::Flash>::Page>,
+        &'static mut MaybeUninit<FlashCrashLog<'static, F>>,
+    );
+    type Output = &'static FlashCrashLog<'static, F>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let process_management_cap = create_capability!(capabilities::ProcessManagementCapability);
+
+        let page_buffer = static_buffer
+            .0
+            .write(<F as hil::flash::Flash>::Page::default());
+
+        let crash_log = static_buffer.1.write(FlashCrashLog::new(
+            self.flash,
+            self.page_number,
+            page_buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.flash.set_client(crash_log);
+        let _ = crash_log.load();
+        self.board_kernel
+            .set_crash_recorder(crash_log, &process_management_cap);
+
+        crash_log
+    }
+}
@@ -0,0 +1,63 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the inter-process publish/subscribe event bus.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let event_bus = components::event_bus::EventBusComponent::new(
+//!     board_kernel,
+//!     capsules_extra::event_bus::DRIVER_NUM,
+//!     4,
+//! )
+//! .finalize(components::event_bus_component_static!());
+//! ```
+
+use capsules_extra::event_bus::EventBus;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+#[macro_export]
+macro_rules! event_bus_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::event_bus::EventBus)
+    };};
+}
+
+pub struct EventBusComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    max_fanout: usize,
+}
+
+impl EventBusComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        max_fanout: usize,
+    ) -> EventBusComponent {
+        EventBusComponent {
+            board_kernel,
+            driver_num,
+            max_fanout,
+        }
+    }
+}
+
+impl Component for EventBusComponent {
+    type StaticInput = &'static mut MaybeUninit<EventBus>;
+    type Output = &'static EventBus;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        static_buffer.write(EventBus::new(
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            self.max_fanout,
+        ))
+    }
+}
@@ -0,0 +1,112 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a fault policy that restarts processes with an exponential
+//! backoff.
+//!
+//! This provides one Component, BackoffRestartFaultPolicyComponent.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let fault_policy = components::fault_policy::BackoffRestartFaultPolicyComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     kernel::process::FaultPolicyRequest::RestartWithBackoff {
+//!         initial_delay_ms: 100,
+//!         max_delay_ms: 60_000,
+//!         max_restarts: None,
+//!     },
+//!     kernel::process::BackoffBounds {
+//!         min_delay_ms: 10,
+//!         max_delay_ms: 600_000,
+//!         max_restarts: None,
+//!     },
+//! )
+//! .finalize(components::backoff_restart_fault_policy_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     NUM_PROCS
+//! ));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+use kernel::process::{BackoffBounds, BackoffRestartFaultPolicy, FaultPolicyRequest};
+
+#[macro_export]
+macro_rules! backoff_restart_fault_policy_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let policy = kernel::static_buf!(
+            kernel::process::BackoffRestartFaultPolicy<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $N,
+            >
+        );
+
+        (alarm, policy)
+    };};
+}
+
+pub struct BackoffRestartFaultPolicyComponent<
+    A: 'static + time::Alarm<'static>,
+    const NUM_PROCS: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    default: FaultPolicyRequest,
+    bounds: BackoffBounds,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize>
+    BackoffRestartFaultPolicyComponent<A, NUM_PROCS>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        default: FaultPolicyRequest,
+        bounds: BackoffBounds,
+    ) -> BackoffRestartFaultPolicyComponent<A, NUM_PROCS> {
+        BackoffRestartFaultPolicyComponent {
+            board_kernel,
+            alarm_mux,
+            default,
+            bounds,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> Component
+    for BackoffRestartFaultPolicyComponent<A, NUM_PROCS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<
+            BackoffRestartFaultPolicy<'static, VirtualMuxAlarm<'static, A>, NUM_PROCS>,
+        >,
+    );
+    type Output =
+        &'static BackoffRestartFaultPolicy<'static, VirtualMuxAlarm<'static, A>, NUM_PROCS>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let policy_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        policy_alarm.setup();
+
+        let policy = static_buffer.1.write(BackoffRestartFaultPolicy::new(
+            self.board_kernel,
+            policy_alarm,
+            self.default,
+            self.bounds,
+        ));
+        policy_alarm.set_alarm_client(policy);
+
+        policy
+    }
+}
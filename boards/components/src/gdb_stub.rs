@@ -0,0 +1,117 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the GDB remote serial protocol stub.
+//!
+//! This provides one Component, GdbStubComponent, which runs a GDB stub for
+//! debugging a userspace process. The stub needs a UART of its own: GDB's
+//! packets cannot share a serial link with the process console or the
+//! userspace console.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let gdb_stub = components::gdb_stub::GdbStubComponent::new(
+//!     board_kernel,
+//!     virtio_console,
+//!     mux_alarm,
+//!     capsules_extra::gdb_stub::RISCV_BREAKPOINTS,
+//! )
+//! .finalize(components::gdb_stub_component_static!(
+//!     qemu_rv32_virt_chip::chip::QemuRv32VirtClint<'static>
+//! ));
+//! let _ = gdb_stub.start();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::gdb_stub::{self, GdbStub};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! gdb_stub_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let rx_buffer = kernel::static_buf!([u8; capsules_extra::gdb_stub::RX_BUF_LEN]);
+        let packet_buffer = kernel::static_buf!([u8; capsules_extra::gdb_stub::PACKET_BUF_LEN]);
+        let tx_buffer = kernel::static_buf!([u8; capsules_extra::gdb_stub::TX_BUF_LEN]);
+        let gdb_stub = kernel::static_buf!(
+            capsules_extra::gdb_stub::GdbStub<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                components::gdb_stub::Capability,
+            >
+        );
+
+        (alarm, rx_buffer, packet_buffer, tx_buffer, gdb_stub)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+unsafe impl capabilities::ProcessDebugCapability for Capability {}
+
+pub struct GdbStubComponent<A: 'static + Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    uart: &'static dyn hil::uart::UartData<'static>,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    breakpoint_instructions: &'static [(usize, &'static [u8])],
+}
+
+impl<A: 'static + Alarm<'static>> GdbStubComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        uart: &'static dyn hil::uart::UartData<'static>,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        breakpoint_instructions: &'static [(usize, &'static [u8])],
+    ) -> GdbStubComponent<A> {
+        GdbStubComponent {
+            board_kernel,
+            uart,
+            alarm_mux,
+            breakpoint_instructions,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for GdbStubComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; gdb_stub::RX_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; gdb_stub::PACKET_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; gdb_stub::TX_BUF_LEN]>,
+        &'static mut MaybeUninit<GdbStub<'static, VirtualMuxAlarm<'static, A>, Capability>>,
+    );
+    type Output = &'static GdbStub<'static, VirtualMuxAlarm<'static, A>, Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let gdb_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        gdb_alarm.setup();
+
+        let rx_buffer = static_buffer.1.write([0; gdb_stub::RX_BUF_LEN]);
+        let packet_buffer = static_buffer.2.write([0; gdb_stub::PACKET_BUF_LEN]);
+        let tx_buffer = static_buffer.3.write([0; gdb_stub::TX_BUF_LEN]);
+
+        let stub = static_buffer.4.write(GdbStub::new(
+            self.uart,
+            gdb_alarm,
+            self.board_kernel,
+            self.breakpoint_instructions,
+            rx_buffer,
+            packet_buffer,
+            tx_buffer,
+            Capability,
+        ));
+        self.uart.set_transmit_client(stub);
+        self.uart.set_receive_client(stub);
+        gdb_alarm.set_alarm_client(stub);
+
+        stub
+    }
+}
@@ -4,7 +4,10 @@
 
 //! Components for KV stack capsules.
 
+use capsules_core::virtualizers::virtual_aes_ccm::{MuxAES128CCM, VirtualAES128CCM};
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules_extra::kv_driver::KVStoreDriver;
+use capsules_extra::kv_store_encryption::KVStoreEncryption;
 use capsules_extra::kv_store_permissions::KVStorePermissions;
 use capsules_extra::tickv::{KVSystem, KeyType};
 use capsules_extra::tickv_kv_store::TicKVKVStore;
@@ -14,6 +17,10 @@ use kernel::capabilities;
 use kernel::component::Component;
 use kernel::create_capability;
 use kernel::hil;
+use kernel::hil::symmetric_encryption::{
+    AES128Ctr, AES128, AES128CBC, AES128CCM, AES128ECB, AES128_KEY_SIZE,
+};
+use kernel::hil::time::Alarm;
 
 ///////////////////////
 // KV Userspace Driver
@@ -21,54 +28,80 @@ use kernel::hil;
 
 #[macro_export]
 macro_rules! kv_driver_component_static {
-    ($V:ty $(,)?) => {{
-        let kv = kernel::static_buf!(capsules_extra::kv_driver::KVStoreDriver<'static, $V>);
+    ($V:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let kv = kernel::static_buf!(
+            capsules_extra::kv_driver::KVStoreDriver<
+                'static,
+                $V,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
         let key_buffer = kernel::static_buf!([u8; 64]);
         let value_buffer = kernel::static_buf!([u8; 256]);
 
-        (kv, key_buffer, value_buffer)
+        (alarm, kv, key_buffer, value_buffer)
     };};
 }
 
-pub type KVDriverComponentType<V> = capsules_extra::kv_driver::KVStoreDriver<'static, V>;
+pub type KVDriverComponentType<V, A> =
+    capsules_extra::kv_driver::KVStoreDriver<'static, V, VirtualMuxAlarm<'static, A>>;
 
-pub struct KVDriverComponent<V: hil::kv::KVPermissions<'static> + 'static> {
+pub struct KVDriverComponent<
+    V: hil::kv::KVPermissions<'static> + 'static,
+    A: Alarm<'static> + 'static,
+> {
     kv: &'static V,
+    alarm_mux: &'static MuxAlarm<'static, A>,
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
 }
 
-impl<V: hil::kv::KVPermissions<'static>> KVDriverComponent<V> {
-    pub fn new(kv: &'static V, board_kernel: &'static kernel::Kernel, driver_num: usize) -> Self {
+impl<V: hil::kv::KVPermissions<'static>, A: Alarm<'static>> KVDriverComponent<V, A> {
+    pub fn new(
+        kv: &'static V,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+    ) -> Self {
         Self {
             kv,
+            alarm_mux,
             board_kernel,
             driver_num,
         }
     }
 }
 
-impl<V: hil::kv::KVPermissions<'static>> Component for KVDriverComponent<V> {
+impl<V: hil::kv::KVPermissions<'static>, A: Alarm<'static>> Component for KVDriverComponent<V, A> {
     type StaticInput = (
-        &'static mut MaybeUninit<KVStoreDriver<'static, V>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<KVStoreDriver<'static, V, VirtualMuxAlarm<'static, A>>>,
         &'static mut MaybeUninit<[u8; 64]>,
         &'static mut MaybeUninit<[u8; 256]>,
     );
-    type Output = &'static KVStoreDriver<'static, V>;
+    type Output = &'static KVStoreDriver<'static, V, VirtualMuxAlarm<'static, A>>;
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
 
-        let key_buffer = static_buffer.1.write([0; 64]);
-        let value_buffer = static_buffer.2.write([0; 256]);
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
 
-        let driver = static_buffer.0.write(KVStoreDriver::new(
+        let key_buffer = static_buffer.2.write([0; 64]);
+        let value_buffer = static_buffer.3.write([0; 256]);
+
+        let driver = static_buffer.1.write(KVStoreDriver::new(
             self.kv,
+            alarm,
             key_buffer,
             value_buffer,
             self.board_kernel.create_grant(self.driver_num, &grant_cap),
         ));
         self.kv.set_client(driver);
+        alarm.set_alarm_client(driver);
         driver
     }
 }
@@ -199,6 +232,109 @@ impl<V: hil::kv::KV<'static> + 'static> Component for KVStorePermissionsComponen
     }
 }
 
+/////////////////////
+// KV Store Encryption
+/////////////////////
+
+#[macro_export]
+macro_rules! kv_store_encryption_component_static {
+    ($V:ty, $A:ty, $R:ty $(,)?) => {{
+        let aes_ccm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, $A>
+        );
+        // Room for the CCM blocks of the largest key and value the KV driver
+        // passes down, and for the record with the key in front of it.
+        let crypt_buf = kernel::static_buf!([u8; 512]);
+        let record = kernel::static_buf!([u8; 384]);
+        let kv_store = kernel::static_buf!(
+            capsules_extra::kv_store_encryption::KVStoreEncryption<
+                'static,
+                $V,
+                capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, $A>,
+                $R,
+            >
+        );
+
+        (kv_store, aes_ccm, crypt_buf, record)
+    };};
+}
+
+pub type KVStoreEncryptionComponentType<V, A, R> =
+    capsules_extra::kv_store_encryption::KVStoreEncryption<
+        'static,
+        V,
+        VirtualAES128CCM<'static, A>,
+        R,
+    >;
+
+pub struct KVStoreEncryptionComponent<
+    V: hil::kv::KV<'static> + 'static,
+    A: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB + 'static,
+    R: hil::rng::Rng<'static> + 'static,
+> {
+    kv: &'static V,
+    aes_mux: &'static MuxAES128CCM<'static, A>,
+    rng: &'static R,
+    key: [u8; AES128_KEY_SIZE],
+}
+
+impl<
+        V: hil::kv::KV<'static> + 'static,
+        A: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB + 'static,
+        R: hil::rng::Rng<'static> + 'static,
+    > KVStoreEncryptionComponent<V, A, R>
+{
+    /// `key` is the per-device key the values are encrypted with.
+    pub fn new(
+        kv: &'static V,
+        aes_mux: &'static MuxAES128CCM<'static, A>,
+        rng: &'static R,
+        key: [u8; AES128_KEY_SIZE],
+    ) -> Self {
+        Self {
+            kv,
+            aes_mux,
+            rng,
+            key,
+        }
+    }
+}
+
+impl<
+        V: hil::kv::KV<'static> + 'static,
+        A: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB + 'static,
+        R: hil::rng::Rng<'static> + 'static,
+    > Component for KVStoreEncryptionComponent<V, A, R>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<KVStoreEncryption<'static, V, VirtualAES128CCM<'static, A>, R>>,
+        &'static mut MaybeUninit<VirtualAES128CCM<'static, A>>,
+        &'static mut MaybeUninit<[u8; 512]>,
+        &'static mut MaybeUninit<[u8; 384]>,
+    );
+    type Output = &'static KVStoreEncryption<'static, V, VirtualAES128CCM<'static, A>, R>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let crypt_buf = static_buffer.2.write([0; 512]);
+        let aes_ccm = static_buffer
+            .1
+            .write(VirtualAES128CCM::new(self.aes_mux, crypt_buf));
+        aes_ccm.setup();
+        let _ = AES128CCM::set_key(aes_ccm, &self.key);
+
+        let record = static_buffer.3.write([0; 384]);
+        let kv_store_encryption = static_buffer
+            .0
+            .write(KVStoreEncryption::new(self.kv, aes_ccm, self.rng, record));
+
+        AES128CCM::set_client(aes_ccm, kv_store_encryption);
+        self.rng.set_client(kv_store_encryption);
+        self.kv.set_client(kv_store_encryption);
+
+        kv_store_encryption
+    }
+}
+
 /////////////////////
 // TicKV KV Store
 /////////////////////
@@ -12,6 +12,10 @@ pub mod alarm;
 pub mod analog_comparator;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_loader;
+pub mod appid_rollback;
+pub mod appid_rsa;
+pub mod appid_signature;
 pub mod ble;
 pub mod bme280;
 pub mod bmm150;
@@ -22,16 +26,20 @@ pub mod can;
 pub mod ccs811;
 pub mod cdc;
 pub mod console;
+pub mod crash_log;
 pub mod crc;
 pub mod ctap;
 pub mod dac;
 pub mod date_time;
 pub mod debug_queue;
 pub mod debug_writer;
+pub mod event_bus;
+pub mod fault_policy;
 pub mod flash;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700;
+pub mod gdb_stub;
 pub mod gpio;
 pub mod hd44780;
 pub mod hmac;
@@ -62,12 +70,18 @@ pub mod nonvolatile_storage;
 pub mod nrf51822;
 pub mod panic_button;
 pub mod pressure;
+pub mod process_checkpoint;
 pub mod process_console;
+pub mod process_console_debug;
 pub mod process_printer;
+pub mod process_watchdog;
 pub mod proximity;
 pub mod pwm;
+pub mod ram_disk;
 pub mod rf233;
 pub mod rng;
+#[cfg(feature = "sandbox")]
+pub mod sandbox;
 pub mod sched;
 pub mod screen;
 pub mod segger_rtt;
@@ -78,6 +92,7 @@ pub mod siphash;
 pub mod sound_pressure;
 pub mod spi;
 pub mod st77xx;
+pub mod syscall_trace;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
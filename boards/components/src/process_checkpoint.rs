@@ -0,0 +1,131 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for saving processes to flash and resuming them after a reboot.
+//!
+//! This creates the kernel's process checkpointer on top of the board's flash
+//! controller and registers it with the kernel, so processes with a matching
+//! snapshot are resumed instead of started when the kernel first runs them.
+//! `region` is flash reserved for snapshots, divided into slots of
+//! `slot_size` bytes. The board must enable the kernel's `process_checkpoint`
+//! feature.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let checkpointer = components::process_checkpoint::ProcessCheckpointComponent::new(
+//!     board_kernel,
+//!     app_flash,
+//!     checkpoint_region,
+//!     0x4000,
+//!     &base_peripherals.nvmc,
+//! )
+//! .finalize(components::process_checkpoint_component_static!(
+//!     nrf52840::nvmc::Nvmc,
+//!     512
+//! ));
+//! ```
+
+use capsules_extra::nonvolatile_to_pages::NonvolatileToPages;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+use kernel::process_checkpoint::ProcessCheckpointer;
+
+#[macro_export]
+macro_rules! process_checkpoint_component_static {
+    ($F:ty, $buffer_size: literal) => {{
+        let buffer = kernel::static_buf!([u8; $buffer_size]);
+        let page_buffer = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let nv_to_page = kernel::static_buf!(
+            capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, $F>
+        );
+        let checkpointer = kernel::static_buf!(kernel::process_checkpoint::ProcessCheckpointer);
+        (buffer, page_buffer, nv_to_page, checkpointer)
+    };};
+}
+
+pub struct ProcessCheckpointComponent<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    const BUF_LEN: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    app_flash: &'static [u8],
+    region: &'static [u8],
+    slot_size: usize,
+    storage: &'static F,
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+        const BUF_LEN: usize,
+    > ProcessCheckpointComponent<F, BUF_LEN>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        app_flash: &'static [u8],
+        region: &'static [u8],
+        slot_size: usize,
+        storage: &'static F,
+    ) -> ProcessCheckpointComponent<F, BUF_LEN> {
+        ProcessCheckpointComponent {
+            board_kernel,
+            app_flash,
+            region,
+            slot_size,
+            storage,
+        }
+    }
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+        const BUF_LEN: usize,
+    > Component for ProcessCheckpointComponent<F, BUF_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<<F as hil::flash::Flash>::Page>,
+        &'static mut MaybeUninit<NonvolatileToPages<'static, F>>,
+        &'static mut MaybeUninit<ProcessCheckpointer>,
+    );
+    type Output = &'static ProcessCheckpointer;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let checkpoint_cap = create_capability!(capabilities::ProcessCheckpointCapability);
+
+        let buffer = static_buffer.0.write([0; BUF_LEN]);
+
+        let flash_pagebuffer = static_buffer
+            .1
+            .write(<F as hil::flash::Flash>::Page::default());
+
+        let nv_to_page = static_buffer
+            .2
+            .write(NonvolatileToPages::new(self.storage, flash_pagebuffer));
+        self.storage.set_client(nv_to_page);
+
+        let checkpointer = static_buffer.3.write(ProcessCheckpointer::new(
+            self.board_kernel,
+            self.app_flash,
+            self.region,
+            self.slot_size,
+            nv_to_page,
+            buffer,
+            &checkpoint_cap,
+        ));
+        nv_to_page.set_client(checkpointer);
+        self.board_kernel
+            .set_process_restore(checkpointer, &checkpoint_cap);
+
+        checkpointer
+    }
+}
@@ -0,0 +1,111 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the process console debugging commands.
+//!
+//! This provides one Component, ProcessConsoleDebugComponent, which adds the
+//! `memory`, `grants`, `peek`, `priority` and `policy` commands to a process
+//! console.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! components::process_console_debug::ProcessConsoleDebugComponent::new(
+//!     board_kernel,
+//!     process_console,
+//! )
+//! .finalize(components::process_console_debug_component_static!());
+//! ```
+
+use capsules_core::process_console::{self, CommandEntry, ProcessConsole};
+use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+use capsules_extra::process_console_debug::{DebugCommand, DebugCommandKind};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! process_console_debug_component_static {
+    () => {{
+        let commands = kernel::static_buf!(
+            [capsules_extra::process_console_debug::DebugCommand<
+                components::process_console_debug::Capability,
+            >; 5]
+        );
+        let entries =
+            kernel::static_buf!([capsules_core::process_console::CommandEntry<'static>; 5]);
+
+        (commands, entries)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+unsafe impl capabilities::ProcessDebugCapability for Capability {}
+
+pub struct ProcessConsoleDebugComponent<
+    const COMMAND_HISTORY_LEN: usize,
+    A: 'static + Alarm<'static>,
+> {
+    board_kernel: &'static kernel::Kernel,
+    console: &'static ProcessConsole<
+        'static,
+        COMMAND_HISTORY_LEN,
+        VirtualMuxAlarm<'static, A>,
+        crate::process_console::Capability,
+    >,
+}
+
+impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>>
+    ProcessConsoleDebugComponent<COMMAND_HISTORY_LEN, A>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        console: &'static ProcessConsole<
+            'static,
+            COMMAND_HISTORY_LEN,
+            VirtualMuxAlarm<'static, A>,
+            crate::process_console::Capability,
+        >,
+    ) -> ProcessConsoleDebugComponent<COMMAND_HISTORY_LEN, A> {
+        ProcessConsoleDebugComponent {
+            board_kernel,
+            console,
+        }
+    }
+}
+
+impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>> Component
+    for ProcessConsoleDebugComponent<COMMAND_HISTORY_LEN, A>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[DebugCommand<Capability>; 5]>,
+        &'static mut MaybeUninit<[process_console::CommandEntry<'static>; 5]>,
+    );
+    type Output = ();
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let kinds = [
+            DebugCommandKind::Memory,
+            DebugCommandKind::Grants,
+            DebugCommandKind::Peek,
+            DebugCommandKind::Priority,
+            DebugCommandKind::Policy,
+        ];
+        let commands: &'static [DebugCommand<Capability>; 5] = static_buffer
+            .0
+            .write(kinds.map(|kind| DebugCommand::new(kind, self.board_kernel, Capability)));
+        let entries: &'static [CommandEntry<'static>; 5] = static_buffer.1.write([
+            CommandEntry::new(kinds[0].name(), kinds[0].usage(), &commands[0]),
+            CommandEntry::new(kinds[1].name(), kinds[1].usage(), &commands[1]),
+            CommandEntry::new(kinds[2].name(), kinds[2].usage(), &commands[2]),
+            CommandEntry::new(kinds[3].name(), kinds[3].usage(), &commands[3]),
+            CommandEntry::new(kinds[4].name(), kinds[4].usage(), &commands[4]),
+        ]);
+        for entry in entries.iter() {
+            self.console.add_command(entry);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for per-process liveness heartbeats.
+//!
+//! This component wraps the board's hardware watchdog in a `GatedWatchDog`
+//! and creates the `ProcessWatchdog` capsule that closes it, or faults the
+//! process, when a process misses its heartbeat. The board must return the
+//! gated watchdog (the second output) from `KernelResources::watchdog()`.
+//! Only processes whose TBF header permits them to register a heartbeat are
+//! monitored.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let (process_watchdog, watchdog) =
+//!     components::process_watchdog::ProcessWatchdogComponent::new(
+//!         board_kernel,
+//!         capsules_extra::process_watchdog::DRIVER_NUM,
+//!         mux_alarm,
+//!         &peripherals.wdt,
+//!         capsules_extra::process_watchdog::MissedHeartbeatAction::ResetBoard,
+//!     )
+//!     .finalize(components::process_watchdog_component_static!(
+//!         sam4l::ast::Ast,
+//!         sam4l::wdt::Wdt
+//!     ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::process_watchdog::{GatedWatchDog, MissedHeartbeatAction, ProcessWatchdog};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+use kernel::platform::watchdog::WatchDog;
+
+#[macro_export]
+macro_rules! process_watchdog_component_static {
+    ($A:ty, $W:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let watchdog =
+            kernel::static_buf!(capsules_extra::process_watchdog::GatedWatchDog<'static, $W>);
+        let process_watchdog = kernel::static_buf!(
+            capsules_extra::process_watchdog::ProcessWatchdog<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $W,
+                components::process_watchdog::Capability,
+            >
+        );
+        (alarm, watchdog, process_watchdog)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct ProcessWatchdogComponent<A: 'static + Alarm<'static>, W: 'static + WatchDog> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    watchdog: &'static W,
+    action: MissedHeartbeatAction,
+}
+
+impl<A: 'static + Alarm<'static>, W: 'static + WatchDog> ProcessWatchdogComponent<A, W> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        watchdog: &'static W,
+        action: MissedHeartbeatAction,
+    ) -> ProcessWatchdogComponent<A, W> {
+        ProcessWatchdogComponent {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            watchdog,
+            action,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, W: 'static + WatchDog> Component
+    for ProcessWatchdogComponent<A, W>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<GatedWatchDog<'static, W>>,
+        &'static mut MaybeUninit<
+            ProcessWatchdog<'static, VirtualMuxAlarm<'static, A>, W, Capability>,
+        >,
+    );
+    type Output = (
+        &'static ProcessWatchdog<'static, VirtualMuxAlarm<'static, A>, W, Capability>,
+        &'static GatedWatchDog<'static, W>,
+    );
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let watchdog = static_buffer.1.write(GatedWatchDog::new(self.watchdog));
+
+        let process_watchdog = static_buffer.2.write(ProcessWatchdog::new(
+            self.board_kernel,
+            alarm,
+            watchdog,
+            self.action,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            Capability,
+        ));
+        alarm.set_alarm_client(process_watchdog);
+
+        (process_watchdog, watchdog)
+    }
+}
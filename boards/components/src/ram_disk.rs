@@ -0,0 +1,56 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a RAM disk providing `BlockStorage`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ram_disk = components::ram_disk::RamDiskComponent::new(512)
+//!     .finalize(components::ram_disk_component_static!(32 * 512));
+//! ```
+
+use capsules_extra::ram_disk::RamDisk;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+
+// Setup static space for the objects. `$size` is the size of the disk in
+// bytes.
+#[macro_export]
+macro_rules! ram_disk_component_static {
+    ($size:expr $(,)?) => {{
+        let storage = kernel::static_buf!([u8; $size]);
+        let ram_disk = kernel::static_buf!(capsules_extra::ram_disk::RamDisk<'static>);
+
+        (storage, ram_disk)
+    };};
+}
+
+pub struct RamDiskComponent<const SIZE: usize> {
+    block_size: usize,
+}
+
+impl<const SIZE: usize> RamDiskComponent<SIZE> {
+    pub fn new(block_size: usize) -> Self {
+        Self { block_size }
+    }
+}
+
+impl<const SIZE: usize> Component for RamDiskComponent<SIZE> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; SIZE]>,
+        &'static mut MaybeUninit<RamDisk<'static>>,
+    );
+    type Output = &'static RamDisk<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let storage = static_buffer.0.write([0; SIZE]);
+        let ram_disk = static_buffer
+            .1
+            .write(RamDisk::new(storage, self.block_size));
+        ram_disk.register();
+        ram_disk
+    }
+}
@@ -0,0 +1,120 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a policy that runs untrusted processes in a sandbox.
+//!
+//! This provides one Component, SandboxPolicyComponent, which creates the
+//! policy and sets it as the sandbox policy of the kernel. It is only
+//! available with the `sandbox` feature of this crate, which enables the
+//! kernel's `sandbox` feature.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static UNTRUSTED: SandboxProfile = SandboxProfile { ... };
+//! static DENY_ALL: SandboxProfile = SandboxProfile {
+//!     drivers: &[],
+//!     max_upcall_queue_depth: Some(0),
+//! };
+//!
+//! components::sandbox::SandboxPolicyComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     &[(0x5678, Some(&UNTRUSTED))],
+//!     &DENY_ALL,
+//! )
+//! .finalize(components::sandbox_policy_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     NUM_PROCS,
+//!     4
+//! ));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time;
+use kernel::sandbox::{SandboxPolicy, SandboxProfile};
+
+#[macro_export]
+macro_rules! sandbox_policy_component_static {
+    ($A:ty, $N:expr, $D:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let policy = kernel::static_buf!(
+            kernel::sandbox::SandboxPolicy<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $N,
+                $D,
+            >
+        );
+
+        (alarm, policy)
+    };};
+}
+
+pub struct SandboxPolicyComponent<
+    A: 'static + time::Alarm<'static>,
+    const NUM_PROCS: usize,
+    const NUM_DRIVERS: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    profiles: &'static [(u32, Option<&'static SandboxProfile>)],
+    default: &'static SandboxProfile,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize, const NUM_DRIVERS: usize>
+    SandboxPolicyComponent<A, NUM_PROCS, NUM_DRIVERS>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        profiles: &'static [(u32, Option<&'static SandboxProfile>)],
+        default: &'static SandboxProfile,
+    ) -> SandboxPolicyComponent<A, NUM_PROCS, NUM_DRIVERS> {
+        SandboxPolicyComponent {
+            board_kernel,
+            alarm_mux,
+            profiles,
+            default,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize, const NUM_DRIVERS: usize> Component
+    for SandboxPolicyComponent<A, NUM_PROCS, NUM_DRIVERS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<
+            SandboxPolicy<'static, VirtualMuxAlarm<'static, A>, NUM_PROCS, NUM_DRIVERS>,
+        >,
+    );
+    type Output =
+        &'static SandboxPolicy<'static, VirtualMuxAlarm<'static, A>, NUM_PROCS, NUM_DRIVERS>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let process_management_capability =
+            create_capability!(capabilities::ProcessManagementCapability);
+
+        let policy_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        policy_alarm.setup();
+
+        let policy = static_buffer.1.write(SandboxPolicy::new(
+            policy_alarm,
+            self.profiles,
+            self.default,
+        ));
+        self.board_kernel
+            .set_sandbox_policy(policy, &process_management_capability);
+
+        policy
+    }
+}
@@ -0,0 +1,105 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a real-time scheduler.
+//!
+//! This provides one Component, RealTimeComponent.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let scheduler = components::sched::real_time::RealTimeComponent::new(
+//!     mux_alarm,
+//!     &*addr_of!(PROCESSES),
+//!     kernel::scheduler::real_time::RealTimePolicy::EarliestDeadlineFirst,
+//! )
+//! .finalize(components::real_time_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     NUM_PROCS
+//! ));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+use kernel::process::ProcessSlot;
+use kernel::scheduler::real_time::{RealTimePolicy, RealTimeProcessNode, RealTimeSched};
+
+#[macro_export]
+macro_rules! real_time_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let real_time_sched = kernel::static_buf!(
+            kernel::scheduler::real_time::RealTimeSched<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let real_time_node = kernel::static_buf!(
+            [core::mem::MaybeUninit<
+                kernel::scheduler::real_time::RealTimeProcessNode<
+                    'static,
+                    <$A as kernel::hil::time::Time>::Ticks,
+                >,
+            >; $N]
+        );
+
+        (alarm, real_time_sched, real_time_node)
+    };};
+}
+
+pub struct RealTimeComponent<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    processes: &'static [ProcessSlot],
+    policy: RealTimePolicy,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> RealTimeComponent<A, NUM_PROCS> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        processes: &'static [ProcessSlot],
+        policy: RealTimePolicy,
+    ) -> RealTimeComponent<A, NUM_PROCS> {
+        RealTimeComponent {
+            alarm_mux,
+            processes,
+            policy,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> Component
+    for RealTimeComponent<A, NUM_PROCS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<RealTimeSched<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[MaybeUninit<RealTimeProcessNode<'static, A::Ticks>>; NUM_PROCS]>,
+    );
+    type Output = &'static RealTimeSched<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let scheduler_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        scheduler_alarm.setup();
+
+        let scheduler: &'static RealTimeSched<'static, VirtualMuxAlarm<'static, A>> = static_buffer
+            .1
+            .write(RealTimeSched::new(scheduler_alarm, self.policy));
+        scheduler_alarm.set_alarm_client(scheduler);
+
+        let nodes = static_buffer
+            .2
+            .write(core::array::from_fn(|_| MaybeUninit::uninit()));
+
+        for (i, node) in nodes.iter_mut().enumerate() {
+            let init_node = node.write(RealTimeProcessNode::new(&self.processes[i]));
+            scheduler.processes.push_tail(init_node);
+        }
+        scheduler
+    }
+}
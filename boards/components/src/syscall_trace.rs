@@ -0,0 +1,126 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for binary syscall tracing drained over a UART.
+//!
+//! This creates the kernel trace buffer, registers it with the kernel, and
+//! drains it over a new device on a UART mux every `interval_ms`
+//! milliseconds. To drain the trace over SEGGER RTT instead, create a UART
+//! mux on top of the RTT channel and pass that mux. The board must enable the
+//! kernel's `syscall_trace` feature for the kernel to record events.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let trace = components::syscall_trace::SyscallTraceComponent::new(
+//!     board_kernel,
+//!     uart_mux,
+//!     mux_alarm,
+//!     100,
+//! )
+//! .finalize(components::syscall_trace_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     128
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
+use capsules_extra::syscall_trace::{SyscallTraceDrain, DEFAULT_BUF_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::{self, Alarm};
+use kernel::hil::uart::Transmit;
+use kernel::syscall_trace::{SyscallTraceBuffer, TraceRecord, RECORD_LEN};
+
+#[macro_export]
+macro_rules! syscall_trace_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let records = kernel::static_buf!([kernel::syscall_trace::TraceRecord; $N]);
+        let trace = kernel::static_buf!(kernel::syscall_trace::SyscallTraceBuffer);
+        let uart =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_uart::UartDevice<'static>);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let buffer = kernel::static_buf!([u8; capsules_extra::syscall_trace::DEFAULT_BUF_LEN]);
+        let drain = kernel::static_buf!(
+            capsules_extra::syscall_trace::SyscallTraceDrain<
+                'static,
+                capsules_core::virtualizers::virtual_uart::UartDevice<'static>,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (records, trace, uart, alarm, buffer, drain)
+    };};
+}
+
+pub type SyscallTraceComponentType<A> =
+    SyscallTraceDrain<'static, UartDevice<'static>, VirtualMuxAlarm<'static, A>>;
+
+pub struct SyscallTraceComponent<A: 'static + time::Alarm<'static>, const N: usize> {
+    board_kernel: &'static kernel::Kernel,
+    uart_mux: &'static MuxUart<'static>,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    interval_ms: u32,
+}
+
+impl<A: 'static + time::Alarm<'static>, const N: usize> SyscallTraceComponent<A, N> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        uart_mux: &'static MuxUart<'static>,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        interval_ms: u32,
+    ) -> Self {
+        Self {
+            board_kernel,
+            uart_mux,
+            alarm_mux,
+            interval_ms,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const N: usize> Component for SyscallTraceComponent<A, N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[TraceRecord; N]>,
+        &'static mut MaybeUninit<SyscallTraceBuffer>,
+        &'static mut MaybeUninit<UartDevice<'static>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; DEFAULT_BUF_LEN]>,
+        &'static mut MaybeUninit<SyscallTraceComponentType<A>>,
+    );
+    type Output = &'static SyscallTraceBuffer;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let process_management_cap = create_capability!(capabilities::ProcessManagementCapability);
+
+        let records = s.0.write([[0; RECORD_LEN]; N]);
+        let trace = s.1.write(SyscallTraceBuffer::new(records));
+        self.board_kernel
+            .set_syscall_tracer(trace, &process_management_cap);
+
+        let uart = s.2.write(UartDevice::new(self.uart_mux, false));
+        uart.setup();
+        let alarm = s.3.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+        let buffer = s.4.write([0; DEFAULT_BUF_LEN]);
+
+        let drain = s.5.write(SyscallTraceDrain::new(
+            trace,
+            uart,
+            alarm,
+            buffer,
+            self.interval_ms,
+        ));
+        uart.set_transmit_client(drain);
+        alarm.set_alarm_client(drain);
+        let _ = drain.start();
+
+        trace
+    }
+}
@@ -14,6 +14,7 @@ use nrf52840::gpio::Pin;
 use nrf52840::uart::{Uarte, UARTE0_BASE};
 
 use crate::CHIP;
+use crate::CRASH_LOG;
 use crate::PROCESSES;
 use crate::PROCESS_PRINTER;
 
@@ -103,6 +104,8 @@ pub unsafe extern "C" fn panic_fmt(pi: &PanicInfo) -> ! {
     let led_kernel_pin = &nrf52840::gpio::GPIOPin::new(Pin::P0_13);
     let led = &mut led::LedLow::new(led_kernel_pin);
     let writer = &mut WRITER;
+    // Persist the panic before printing it, as printing does not return.
+    CRASH_LOG.map(|crash_log| debug::panic_record_crash(crash_log, pi, &CHIP));
     debug::panic(
         &mut [led],
         writer,
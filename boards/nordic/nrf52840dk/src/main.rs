@@ -145,11 +145,17 @@ const FAULT_RESPONSE: kernel::process::PanicFaultPolicy = kernel::process::Panic
 // Number of concurrent processes this platform supports.
 const NUM_PROCS: usize = 8;
 
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>> = None;
 static mut PROCESS_PRINTER: Option<&'static kernel::process::ProcessPrinterText> = None;
+static mut CRASH_LOG: Option<&'static CrashLog> = None;
+
+// One flash page in the kernel's storage region holds the crash log.
+mod crash_log_storage {
+    kernel::storage_volume!(CRASH_LOG_STORAGE, 4);
+}
 
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
@@ -179,7 +185,15 @@ type TicKVKVStore = components::kv::TicKVKVStoreComponentType<
 >;
 type KVStorePermissions = components::kv::KVStorePermissionsComponentType<TicKVKVStore>;
 type VirtualKVPermissions = components::kv::VirtualKVPermissionsComponentType<KVStorePermissions>;
-type KVDriver = components::kv::KVDriverComponentType<VirtualKVPermissions>;
+type KVDriver =
+    components::kv::KVDriverComponentType<VirtualKVPermissions, nrf52840::rtc::Rtc<'static>>;
+
+// Crash log
+type NvmcUser =
+    capsules_core::virtualizers::virtual_flash::FlashUser<'static, nrf52840::nvmc::Nvmc>;
+const NVMC_PAGE_SIZE: usize =
+    core::mem::size_of::<<nrf52840::nvmc::Nvmc as kernel::hil::flash::Flash>::Page>();
+type CrashLog = capsules_extra::crash_log::FlashCrashLog<'static, NvmcUser>;
 
 // Temperature
 type TemperatureDriver =
@@ -233,6 +247,8 @@ pub struct Platform {
         >,
     >,
     kv_driver: &'static KVDriver,
+    app_loader: &'static capsules_extra::app_loader::AppLoader,
+    crash_log: &'static CrashLog,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
     life: &'static capsules_core::life::LifeDriver,
@@ -261,6 +277,8 @@ impl SyscallDriverLookup for Platform {
             capsules_core::spi_controller::DRIVER_NUM => f(Some(self.spi_controller)),
             capsules_extra::net::thread::driver::DRIVER_NUM => f(Some(self.thread_driver)),
             capsules_extra::kv_driver::DRIVER_NUM => f(Some(self.kv_driver)),
+            capsules_extra::app_loader::DRIVER_NUM => f(Some(self.app_loader)),
+            capsules_extra::crash_log::DRIVER_NUM => f(Some(self.crash_log)),
             capsules_core::life::DRIVER_NUM => f(Some(self.life)),
             _ => f(None),
         }
@@ -532,6 +550,10 @@ pub unsafe fn main() {
     .finalize(components::process_console_component_static!(
         nrf52840::rtc::Rtc<'static>
     ));
+    // Let the process console inspect process memory and change the priority
+    // and fault policy of processes.
+    components::process_console_debug::ProcessConsoleDebugComponent::new(board_kernel, pconsole)
+        .finalize(components::process_console_debug_component_static!());
 
     // Setup the serial console for userspace.
     let console = components::console::ConsoleComponent::new(
@@ -787,11 +809,13 @@ pub unsafe fn main() {
     // Userspace driver for KV.
     let kv_driver = components::kv::KVDriverComponent::new(
         virtual_kv_driver,
+        mux_alarm,
         board_kernel,
         capsules_extra::kv_driver::DRIVER_NUM,
     )
     .finalize(components::kv_driver_component_static!(
-        VirtualKVPermissions
+        VirtualKVPermissions,
+        nrf52840::rtc::Rtc<'static>
     ));
 
     //--------------------------------------------------------------------------
@@ -894,6 +918,70 @@ pub unsafe fn main() {
     // keyboard_hid.enable();
     // keyboard_hid.attach();
 
+    //--------------------------------------------------------------------------
+    // INTERNAL FLASH
+    //--------------------------------------------------------------------------
+
+    // The app loader and the crash log share the internal flash.
+    let mux_nvmc = components::flash::FlashMuxComponent::new(&base_peripherals.nvmc).finalize(
+        components::flash_mux_component_static!(nrf52840::nvmc::Nvmc),
+    );
+    let app_loader_nvmc = components::flash::FlashUserComponent::new(mux_nvmc).finalize(
+        components::flash_user_component_static!(nrf52840::nvmc::Nvmc),
+    );
+    let crash_log_nvmc = components::flash::FlashUserComponent::new(mux_nvmc).finalize(
+        components::flash_user_component_static!(nrf52840::nvmc::Nvmc),
+    );
+
+    //--------------------------------------------------------------------------
+    // CRASH LOG
+    //--------------------------------------------------------------------------
+
+    // Keep the record of the last kernel panic or process fault across
+    // reboots. The panic handler records panics through `CRASH_LOG`.
+    let crash_log = components::crash_log::CrashLogComponent::new(
+        board_kernel,
+        capsules_extra::crash_log::DRIVER_NUM,
+        crash_log_nvmc,
+        crash_log_storage::CRASH_LOG_STORAGE.as_ptr() as usize / NVMC_PAGE_SIZE,
+    )
+    .finalize(components::crash_log_component_static!(NvmcUser));
+    CRASH_LOG = Some(crash_log);
+
+    let crash_command = static_init!(
+        capsules_extra::crash_log::CrashCommand<'static>,
+        capsules_extra::crash_log::CrashCommand::new(crash_log)
+    );
+    let crash_entry = static_init!(
+        capsules_core::process_console::CommandEntry<'static>,
+        capsules_core::process_console::CommandEntry::new("crash", "crash", crash_command)
+    );
+    pconsole.add_command(crash_entry);
+
+    //--------------------------------------------------------------------------
+    // DYNAMIC APP LOADING
+    //--------------------------------------------------------------------------
+
+    // Processes can be installed into the unused part of app flash while the
+    // kernel is running. The loader gets the process memory that is left over
+    // once the processes in flash have been loaded below.
+    let (app_loader, dynamic_loader) = components::app_loader::AppLoaderComponent::new(
+        board_kernel,
+        capsules_extra::app_loader::DRIVER_NUM,
+        chip,
+        &FAULT_RESPONSE,
+        core::slice::from_raw_parts(
+            &_sapps as *const u8,
+            &_eapps as *const u8 as usize - &_sapps as *const u8 as usize,
+        ),
+        app_loader_nvmc,
+    )
+    .finalize(components::app_loader_component_static!(
+        nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>,
+        NvmcUser,
+        512
+    ));
+
     //--------------------------------------------------------------------------
     // PLATFORM SETUP, SCHEDULER, AND START KERNEL LOOP
     //--------------------------------------------------------------------------
@@ -925,6 +1013,8 @@ pub unsafe fn main() {
         i2c_master_slave,
         spi_controller,
         kv_driver,
+        app_loader,
+        crash_log,
         scheduler,
         systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
     };
@@ -953,8 +1043,9 @@ pub unsafe fn main() {
         static _eappmem: u8;
     }
 
-    kernel::process::load_processes(
+    match kernel::process::load_and_check_processes_with_remainder(
         board_kernel,
+        &platform,
         chip,
         core::slice::from_raw_parts(
             &_sapps as *const u8,
@@ -964,14 +1055,20 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_management_capability,
-    )
-    .unwrap_or_else(|err| {
-        debug!("Error loading processes!");
-        debug!("{:?}", err);
-    });
+    ) {
+        Ok(remaining_app_memory) => {
+            // The loader has no memory yet, so it always keeps this block.
+            let _ = dynamic_loader
+                .add_free_memory(remaining_app_memory, &process_management_capability);
+        }
+        Err(err) => {
+            debug!("Error loading processes!");
+            debug!("{:?}", err);
+        }
+    }
 
     board_kernel.kernel_loop(&platform, chip, Some(&platform.ipc), &main_loop_capability);
 }
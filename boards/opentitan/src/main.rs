@@ -83,7 +83,8 @@ const NUM_PROCS: usize = 4;
 //
 // Actual memory for holding the active process structures. Need an empty list
 // at least.
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; 4] = [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 // Test access to the peripherals
 #[cfg(test)]
@@ -207,6 +208,7 @@ struct EarlGrey {
                 >,
             >,
         >,
+        VirtualMuxAlarm<'static, earlgrey::timer::RvTimer<'static, ChipConfig>>,
     >,
     syscall_filter: &'static TbfHeaderFilterDefaultAllow,
     scheduler: &'static PrioritySched,
@@ -636,6 +638,7 @@ unsafe fn setup() -> (
 
     let kv_driver = components::kv::KVDriverComponent::new(
         virtual_kv_driver,
+        mux_alarm,
         board_kernel,
         capsules_extra::kv_driver::DRIVER_NUM,
     )
@@ -653,7 +656,8 @@ unsafe fn setup() -> (
                     capsules_extra::tickv::TicKVKeyType,
                 >,
             >,
-        >
+        >,
+        earlgrey::timer::RvTimer<ChipConfig>,
     ));
 
     let mux_otbn = crate::otbn::AccelMuxComponent::new(&peripherals.otbn)
@@ -873,7 +877,7 @@ unsafe fn setup() -> (
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_mgmt_cap,
     )
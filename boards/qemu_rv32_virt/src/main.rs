@@ -28,8 +28,8 @@ pub const NUM_PROCS: usize = 4;
 
 // Actual memory for holding the active process structures. Need an empty list
 // at least.
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 // Reference to the chip for panic dumps.
 static mut CHIP: Option<&'static QemuRv32VirtChip<QemuRv32VirtDefaultPeripherals>> = None;
@@ -67,6 +67,8 @@ struct QemuRv32VirtPlatform {
         VirtualMuxAlarm<'static, qemu_rv32_virt_chip::chip::QemuRv32VirtClint<'static>>,
     >,
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
+    ipc_mailbox: kernel::ipc_mailbox::MailboxIpc<NUM_PROCS, 2, 64>,
+    event_bus: &'static capsules_extra::event_bus::EventBus,
     scheduler: &'static CooperativeSched<'static>,
     scheduler_timer: &'static VirtualSchedulerTimer<
         VirtualMuxAlarm<'static, qemu_rv32_virt_chip::chip::QemuRv32VirtClint<'static>>,
@@ -92,6 +94,8 @@ impl SyscallDriverLookup for QemuRv32VirtPlatform {
                 }
             }
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            kernel::ipc_mailbox::DRIVER_NUM => f(Some(&self.ipc_mailbox)),
+            capsules_extra::event_bus::DRIVER_NUM => f(Some(self.event_bus)),
             _ => f(None),
         }
     }
@@ -221,7 +225,7 @@ pub unsafe fn main() {
     // Collect supported VirtIO peripheral indicies and initialize them if they
     // are found. If there are two instances of a supported peripheral, the one
     // on a higher-indexed VirtIO transport is used.
-    let (mut virtio_net_idx, mut virtio_rng_idx) = (None, None);
+    let (mut virtio_net_idx, mut virtio_rng_idx, mut virtio_console_idx) = (None, None, None);
     for (i, virtio_device) in peripherals.virtio_mmio.iter().enumerate() {
         use qemu_rv32_virt_chip::virtio::devices::VirtIODeviceType;
         match virtio_device.query() {
@@ -231,6 +235,9 @@ pub unsafe fn main() {
             Some(VirtIODeviceType::EntropySource) => {
                 virtio_rng_idx = Some(i);
             }
+            Some(VirtIODeviceType::Console) => {
+                virtio_console_idx = Some(i);
+            }
             _ => (),
         }
     }
@@ -383,6 +390,65 @@ pub unsafe fn main() {
         None
     };
 
+    // If there is a VirtIO Console present, use it as the UART of the GDB
+    // stub, so that GDB does not share the UART with the consoles.
+    let virtio_console: Option<
+        &'static qemu_rv32_virt_chip::virtio::devices::virtio_console::VirtIOConsole<'static>,
+    > = if let Some(console_idx) = virtio_console_idx {
+        use qemu_rv32_virt_chip::virtio::devices::virtio_console::VirtIOConsole;
+        use qemu_rv32_virt_chip::virtio::queues::split_queue::{
+            SplitVirtqueue, VirtqueueAvailableRing, VirtqueueDescriptors, VirtqueueUsedRing,
+        };
+        use qemu_rv32_virt_chip::virtio::queues::Virtqueue;
+        use qemu_rv32_virt_chip::virtio::transports::VirtIOTransport;
+
+        // A VirtIO Console requires 2 Virtqueues for its first port:
+        // - a RX Virtqueue where the device places incoming bytes
+        // - a TX Virtqueue with buffers of outgoing bytes
+
+        // RX Virtqueue
+        let rx_descriptors =
+            static_init!(VirtqueueDescriptors<1>, VirtqueueDescriptors::default(),);
+        let rx_available_ring =
+            static_init!(VirtqueueAvailableRing<1>, VirtqueueAvailableRing::default(),);
+        let rx_used_ring = static_init!(VirtqueueUsedRing<1>, VirtqueueUsedRing::default(),);
+        let rx_queue = static_init!(
+            SplitVirtqueue<1>,
+            SplitVirtqueue::new(rx_descriptors, rx_available_ring, rx_used_ring),
+        );
+        rx_queue.set_transport(&peripherals.virtio_mmio[console_idx]);
+
+        // TX Virtqueue
+        let tx_descriptors =
+            static_init!(VirtqueueDescriptors<1>, VirtqueueDescriptors::default(),);
+        let tx_available_ring =
+            static_init!(VirtqueueAvailableRing<1>, VirtqueueAvailableRing::default(),);
+        let tx_used_ring = static_init!(VirtqueueUsedRing<1>, VirtqueueUsedRing::default(),);
+        let tx_queue = static_init!(
+            SplitVirtqueue<1>,
+            SplitVirtqueue::new(tx_descriptors, tx_available_ring, tx_used_ring),
+        );
+        tx_queue.set_transport(&peripherals.virtio_mmio[console_idx]);
+
+        let rx_staging = static_init!([u8; 64], [0; 64]);
+        let virtio_console = static_init!(
+            VirtIOConsole<'static>,
+            VirtIOConsole::new(rx_queue, tx_queue, rx_staging),
+        );
+        rx_queue.set_client(virtio_console);
+        tx_queue.set_client(virtio_console);
+
+        let mmio_queues = static_init!([&'static dyn Virtqueue; 2], [rx_queue, tx_queue]);
+        peripherals.virtio_mmio[console_idx]
+            .initialize(virtio_console, mmio_queues)
+            .unwrap();
+
+        Some(virtio_console as &'static VirtIOConsole)
+    } else {
+        // No VirtIO Console discovered
+        None
+    };
+
     // ---------- INITIALIZE CHIP, ENABLE INTERRUPTS ---------
 
     let chip = static_init!(
@@ -418,6 +484,10 @@ pub unsafe fn main() {
     .finalize(components::process_console_component_static!(
         qemu_rv32_virt_chip::chip::QemuRv32VirtClint
     ));
+    // Let the process console inspect process memory and change the priority
+    // and fault policy of processes.
+    components::process_console_debug::ProcessConsoleDebugComponent::new(board_kernel, pconsole)
+        .finalize(components::process_console_debug_component_static!());
 
     // Setup the console.
     let console = components::console::ConsoleComponent::new(
@@ -437,6 +507,27 @@ pub unsafe fn main() {
     )
     .finalize(components::low_level_debug_component_static!());
 
+    // GDB stub for debugging processes, on the VirtIO Console if there is
+    // one.
+    let gdb_stub = virtio_console.map(|virtio_console| {
+        components::gdb_stub::GdbStubComponent::new(
+            board_kernel,
+            virtio_console,
+            mux_alarm,
+            capsules_extra::gdb_stub::RISCV_BREAKPOINTS,
+        )
+        .finalize(components::gdb_stub_component_static!(
+            qemu_rv32_virt_chip::chip::QemuRv32VirtClint
+        ))
+    });
+
+    let event_bus = components::event_bus::EventBusComponent::new(
+        board_kernel,
+        capsules_extra::event_bus::DRIVER_NUM,
+        NUM_PROCS,
+    )
+    .finalize(components::event_bus_component_static!());
+
     let scheduler = components::sched::cooperative::CooperativeComponent::new(&PROCESSES)
         .finalize(components::cooperative_component_static!(NUM_PROCS));
 
@@ -452,6 +543,7 @@ pub unsafe fn main() {
         console,
         alarm,
         lldb,
+        event_bus,
         scheduler,
         scheduler_timer,
         virtio_rng: virtio_rng_driver,
@@ -460,10 +552,24 @@ pub unsafe fn main() {
             kernel::ipc::DRIVER_NUM,
             &memory_allocation_cap,
         ),
+        ipc_mailbox: kernel::ipc_mailbox::MailboxIpc::new(
+            board_kernel,
+            kernel::ipc_mailbox::DRIVER_NUM,
+            &memory_allocation_cap,
+        ),
     };
 
+    // Let IPC services restrict their clients with the IPC Clients TBF
+    // header.
+    let ipc_policy = static_init!(kernel::ipc::TbfIPCPolicy, kernel::ipc::TbfIPCPolicy);
+    platform.ipc.set_policy(ipc_policy);
+    platform.ipc_mailbox.set_policy(ipc_policy);
+
     // Start the process console:
     let _ = platform.pconsole.start();
+    if let Some(gdb_stub) = gdb_stub {
+        let _ = gdb_stub.start();
+    }
 
     debug!("QEMU RISC-V 32-bit \"virt\" machine, initialization complete.");
     debug!("Entering main loop.");
@@ -493,7 +599,7 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_mgmt_cap,
     )
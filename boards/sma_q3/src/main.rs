@@ -60,8 +60,8 @@ const FAULT_RESPONSE: kernel::process::PanicFaultPolicy = kernel::process::Panic
 // Number of concurrent processes this platform supports.
 const NUM_PROCS: usize = 8;
 
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 // Static reference to chip for panic dumps
 static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>> = None;
@@ -481,7 +481,7 @@ pub unsafe fn main() {
                     &mut _sappmem as *mut u8,
                     &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
                 ),
-                &mut PROCESSES,
+                &PROCESSES,
                 &FAULT_RESPONSE,
                 &process_management_capability,
             )
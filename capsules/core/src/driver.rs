@@ -26,6 +26,10 @@ pub enum NUM {
 
     // Kernel
     Ipc                   = 0x10000,
+    AppLoader             = 0x10001,
+    IpcMailbox            = 0x10002,
+    CrashLog              = 0x10003,
+    ProcessWatchdog       = 0x10004,
 
     // HW Buses
     Spi                   = 0x20001,
@@ -56,6 +60,7 @@ pub enum NUM {
     NvmStorage            = 0x50001,
     SdCard                = 0x50002,
     Kv                    = 0x50003,
+    FatFs                 = 0x50004,
 
     // Sensors
     Temperature           = 0x60000,
@@ -92,5 +97,6 @@ pub enum NUM {
     KeyboardHid           = 0x90005,
     DateTime              = 0x90007,
     Life                  = 0x90008,
+    EventBus              = 0x90009,
 }
 }
@@ -11,7 +11,9 @@ use core::cmp;
 use core::fmt;
 use core::fmt::write;
 use core::str;
+use core::str::SplitWhitespace;
 use kernel::capabilities::ProcessManagementCapability;
+use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::hil::time::ConvertTicks;
 use kernel::utilities::cells::MapCell;
 use kernel::utilities::cells::TakeCell;
@@ -40,11 +42,6 @@ pub const COMMAND_BUF_LEN: usize = 32;
 /// Default size for the history command.
 pub const DEFAULT_COMMAND_HISTORY_LEN: usize = 10;
 
-/// List of valid commands for printing help. Consolidated as these are
-/// displayed in a few different cases.
-const VALID_COMMANDS_STR: &[u8] =
-    b"help status list stop start fault boot terminate process kernel reset panic console-start console-stop\r\n";
-
 /// Escape character for ANSI escape sequences.
 const ESC: u8 = b'\x1B';
 
@@ -207,6 +204,54 @@ pub struct KernelAddresses {
     pub bss_end: *const u8,
 }
 
+/// A command that boards and capsules can add to the process console.
+pub trait ConsoleCommand {
+    /// Run the command. `args` holds the words typed after the command name.
+    /// Whatever the command writes to `out` is printed on the console, up to
+    /// `WRITE_BUF_LEN` bytes.
+    fn execute(&self, args: SplitWhitespace, out: &mut dyn fmt::Write);
+}
+
+/// An entry in the table of commands added to the process console with
+/// `ProcessConsole::add_command()`.
+pub struct CommandEntry<'a> {
+    name: &'static str,
+    usage: &'static str,
+    command: &'a dyn ConsoleCommand,
+    next: ListLink<'a, CommandEntry<'a>>,
+}
+
+impl<'a> CommandEntry<'a> {
+    /// `name` is the word that runs the command, and `usage` is printed by
+    /// `help <name>`, for example `"led <index> <on|off>"`.
+    pub fn new(
+        name: &'static str,
+        usage: &'static str,
+        command: &'a dyn ConsoleCommand,
+    ) -> CommandEntry<'a> {
+        CommandEntry {
+            name,
+            usage,
+            command,
+            next: ListLink::empty(),
+        }
+    }
+}
+
+impl<'a> ListNode<'a, CommandEntry<'a>> for CommandEntry<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, CommandEntry<'a>> {
+        &self.next
+    }
+}
+
+/// A command built into the process console. `P` is the process console
+/// type, whose method runs the command.
+struct BuiltinCommand<P> {
+    name: &'static str,
+    usage: &'static str,
+    execute: fn(&P, SplitWhitespace),
+}
+
 /// Track the operational state of the process console.
 #[derive(Clone, Copy, PartialEq)]
 enum ProcessConsoleState {
@@ -269,6 +314,9 @@ pub struct ProcessConsole<
     /// Function used to reset the device in bootloader mode
     reset_function: Option<fn() -> !>,
 
+    /// Commands added by the board or by capsules.
+    commands: List<'a, CommandEntry<'a>>,
+
     /// This capsule needs to use potentially dangerous APIs related to
     /// processes, and requires a capability to access those APIs.
     capability: C,
@@ -433,10 +481,16 @@ impl ConsoleWriter {
 }
 impl fmt::Write for ConsoleWriter {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        let curr = (s).as_bytes().len();
-        self.buf[self.size..self.size + curr].copy_from_slice((s).as_bytes());
+        // Drop whatever does not fit, so that commands added by boards and
+        // capsules cannot overflow the buffer.
+        let curr = cmp::min(s.len(), self.buf.len() - self.size);
+        self.buf[self.size..self.size + curr].copy_from_slice(&s.as_bytes()[..curr]);
         self.size += curr;
-        Ok(())
+        if curr < s.len() {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
     }
 }
 
@@ -489,10 +543,395 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
             kernel: kernel,
             kernel_addresses: kernel_addresses,
             reset_function: reset_function,
+            commands: List::new(),
             capability: capability,
         }
     }
 
+    /// Add a command to the console. Built-in commands take precedence over
+    /// added commands with the same name.
+    pub fn add_command(&self, entry: &'a CommandEntry<'a>) {
+        self.commands.push_tail(entry);
+    }
+
+    /// Commands built into the console, in the order `help` lists them.
+    const BUILTIN_COMMANDS: [BuiltinCommand<Self>; 14] = [
+        BuiltinCommand {
+            name: "help",
+            usage: "help [command]",
+            execute: Self::command_help,
+        },
+        BuiltinCommand {
+            name: "status",
+            usage: "status",
+            execute: Self::command_status,
+        },
+        BuiltinCommand {
+            name: "list",
+            usage: "list",
+            execute: Self::command_list,
+        },
+        BuiltinCommand {
+            name: "stop",
+            usage: "stop <process>",
+            execute: Self::command_stop,
+        },
+        BuiltinCommand {
+            name: "start",
+            usage: "start <process>",
+            execute: Self::command_start,
+        },
+        BuiltinCommand {
+            name: "fault",
+            usage: "fault <process>",
+            execute: Self::command_fault,
+        },
+        BuiltinCommand {
+            name: "boot",
+            usage: "boot <process>",
+            execute: Self::command_boot,
+        },
+        BuiltinCommand {
+            name: "terminate",
+            usage: "terminate <process>",
+            execute: Self::command_terminate,
+        },
+        BuiltinCommand {
+            name: "process",
+            usage: "process <process>",
+            execute: Self::command_process,
+        },
+        BuiltinCommand {
+            name: "kernel",
+            usage: "kernel",
+            execute: Self::command_kernel,
+        },
+        BuiltinCommand {
+            name: "reset",
+            usage: "reset",
+            execute: Self::command_reset,
+        },
+        BuiltinCommand {
+            name: "panic",
+            usage: "panic",
+            execute: Self::command_panic,
+        },
+        BuiltinCommand {
+            name: "console-start",
+            usage: "console-start",
+            execute: Self::command_console_start,
+        },
+        BuiltinCommand {
+            name: "console-stop",
+            usage: "console-stop",
+            execute: Self::command_console_stop,
+        },
+    ];
+
+    /// Print the names of all built-in and added commands.
+    fn print_valid_commands(&self) {
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(&mut console_writer, format_args!("Valid commands are:"));
+        for builtin in Self::BUILTIN_COMMANDS.iter() {
+            let _ = write(&mut console_writer, format_args!(" {}", builtin.name));
+        }
+        for entry in self.commands.iter() {
+            let _ = write(&mut console_writer, format_args!(" {}", entry.name));
+        }
+        let _ = write(&mut console_writer, format_args!("\r\n"));
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+
+    /// Print `text` followed by the usage of the command named `name`.
+    fn print_usage(&self, text: &str, name: &str) {
+        let usage = Self::BUILTIN_COMMANDS
+            .iter()
+            .find(|builtin| builtin.name == name)
+            .map(|builtin| builtin.usage)
+            .or_else(|| {
+                self.commands
+                    .iter()
+                    .find(|entry| entry.name == name)
+                    .map(|entry| entry.usage)
+            });
+        let mut console_writer = ConsoleWriter::new();
+        match usage {
+            Some(usage) => {
+                let _ = write(
+                    &mut console_writer,
+                    format_args!("{}Usage: {}\r\n", text, usage),
+                );
+            }
+            None => {
+                let _ = write(
+                    &mut console_writer,
+                    format_args!("Unknown command: {}\r\n", name),
+                );
+            }
+        }
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+
+    fn command_help(&self, mut args: SplitWhitespace) {
+        match args.next() {
+            Some(name) => self.print_usage("", name),
+            None => {
+                let _ = self.write_bytes(b"Welcome to the process console.\r\n");
+                self.print_valid_commands();
+            }
+        }
+    }
+
+    fn command_console_start(&self, _args: SplitWhitespace) {
+        self.mode.set(ProcessConsoleState::Active);
+    }
+
+    fn command_console_stop(&self, _args: SplitWhitespace) {
+        let _ = self.write_bytes(b"Disabling the process console.\r\n");
+        let _ = self.write_bytes(b"Run console-start to reactivate.\r\n");
+        self.mode.set(ProcessConsoleState::Hibernating);
+    }
+
+    fn command_start(&self, mut args: SplitWhitespace) {
+        let argument = args.next();
+        argument.map(|name| {
+            self.kernel
+                .process_each_capability(&self.capability, |proc| {
+                    let proc_name = proc.get_process_name();
+                    if proc_name == name {
+                        proc.resume();
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("Process {} resumed.\r\n", name),
+                        );
+
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    }
+                });
+        });
+    }
+
+    fn command_stop(&self, mut args: SplitWhitespace) {
+        let argument = args.next();
+        argument.map(|name| {
+            self.kernel
+                .process_each_capability(&self.capability, |proc| {
+                    let proc_name = proc.get_process_name();
+                    if proc_name == name {
+                        proc.stop();
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("Process {} stopped\r\n", proc_name),
+                        );
+
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    }
+                });
+        });
+    }
+
+    fn command_fault(&self, mut args: SplitWhitespace) {
+        let argument = args.next();
+        argument.map(|name| {
+            self.kernel
+                .process_each_capability(&self.capability, |proc| {
+                    let proc_name = proc.get_process_name();
+                    if proc_name == name {
+                        proc.set_fault_state();
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("Process {} now faulted\r\n", proc_name),
+                        );
+
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    }
+                });
+        });
+    }
+
+    fn command_terminate(&self, mut args: SplitWhitespace) {
+        let argument = args.next();
+        argument.map(|name| {
+            self.kernel
+                .process_each_capability(&self.capability, |proc| {
+                    let proc_name = proc.get_process_name();
+                    if proc_name == name {
+                        proc.terminate(None);
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("Process {} terminated\n", proc_name),
+                        );
+
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    }
+                });
+        });
+    }
+
+    fn command_boot(&self, mut args: SplitWhitespace) {
+        let argument = args.next();
+        argument.map(|name| {
+            self.kernel
+                .process_each_capability(&self.capability, |proc| {
+                    let proc_name = proc.get_process_name();
+                    if proc_name == name && proc.get_state() == State::Terminated {
+                        proc.try_restart(None);
+                    }
+                });
+        });
+    }
+
+    fn command_list(&self, _args: SplitWhitespace) {
+        let _ = self.write_bytes(b" PID    ShortID    Name                Quanta    CPU ms  ");
+        let _ = self.write_bytes(b"Syscalls  Restarts  Grants  State\r\n");
+
+        // Count the number of current processes.
+        let mut count = 0;
+        self.kernel.process_each_capability(&self.capability, |_| {
+            count += 1;
+        });
+
+        if count > 0 {
+            // Start the state machine to print each separately.
+            self.write_state(WriterState::List {
+                index: -1,
+                total: count,
+            });
+        }
+    }
+
+    fn command_status(&self, _args: SplitWhitespace) {
+        let info: KernelInfo = KernelInfo::new(self.kernel);
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                "Total processes: {}\r\n",
+                info.number_loaded_processes(&self.capability)
+            ),
+        );
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        console_writer.clear();
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                "Active processes: {}\r\n",
+                info.number_active_processes(&self.capability)
+            ),
+        );
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        console_writer.clear();
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                "Timeslice expirations: {}\r\n",
+                info.timeslice_expirations(&self.capability)
+            ),
+        );
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        console_writer.clear();
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                "Deadline misses: {}\r\n",
+                info.deadline_misses(&self.capability)
+            ),
+        );
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        console_writer.clear();
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                "Sandbox violations: {}\r\n",
+                info.sandbox_violations(&self.capability)
+            ),
+        );
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        console_writer.clear();
+        let (run_us, syscall_us) = info.cpu_time_us(&self.capability);
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                "Process CPU time: {} ms ({} ms in syscalls)\r\n",
+                (run_us + syscall_us) / 1000,
+                syscall_us / 1000
+            ),
+        );
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+
+    fn command_process(&self, mut args: SplitWhitespace) {
+        let argument = args.next();
+        argument.map(|name| {
+            // If two processes have the same name, only
+            // print the first one we find.
+            let mut found = false;
+            self.kernel
+                .process_each_capability(&self.capability, |proc| {
+                    if found {
+                        return;
+                    }
+                    let proc_name = proc.get_process_name();
+                    if proc_name == name {
+                        let mut console_writer = ConsoleWriter::new();
+                        let mut context: Option<ProcessPrinterContext> = None;
+                        context =
+                            self.process_printer
+                                .print_overview(proc, &mut console_writer, context);
+
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+
+                        if context.is_some() {
+                            self.writer_state.replace(WriterState::ProcessPrint {
+                                process_id: proc.processid(),
+                                context: context,
+                            });
+                        }
+
+                        found = true;
+                    }
+                });
+        });
+    }
+
+    fn command_kernel(&self, _args: SplitWhitespace) {
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                "Kernel version: {}.{} (build {})\r\n",
+                kernel::KERNEL_MAJOR_VERSION,
+                kernel::KERNEL_MINOR_VERSION,
+                option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown")
+            ),
+        );
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        console_writer.clear();
+
+        // Prints kernel memory by moving the writer to the
+        // start state.
+        self.writer_state.replace(WriterState::KernelStart);
+    }
+
+    fn command_reset(&self, _args: SplitWhitespace) {
+        self.reset_function.map_or_else(
+            || {
+                let _ = self.write_bytes(b"Reset function is not implemented");
+            },
+            |f| {
+                f();
+            },
+        );
+    }
+
+    fn command_panic(&self, _args: SplitWhitespace) {
+        panic!("Process Console forced a kernel panic.");
+    }
+
     /// Start the process console listening for user commands.
     pub fn start(&self) -> Result<(), ErrorCode> {
         if self.mode.get() == ProcessConsoleState::Off {
@@ -541,8 +980,7 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
         let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
 
         let _ = self.write_bytes(b"Welcome to the process console.\r\n");
-        let _ = self.write_bytes(b"Valid commands are: ");
-        let _ = self.write_bytes(VALID_COMMANDS_STR);
+        self.print_valid_commands();
         self.prompt();
     }
 
@@ -723,6 +1161,8 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
 
                             let (grants_used, grants_total) =
                                 info.number_app_grant_uses(process_id, &self.capability);
+                            let cpu_time_us = info.app_run_time_us(process_id, &self.capability)
+                                + info.app_syscall_time_us(process_id, &self.capability);
                             let mut console_writer = ConsoleWriter::new();
 
                             // Display process id.
@@ -744,9 +1184,10 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                             let _ = write(
                                 &mut console_writer,
                                 format_args!(
-                                    "{:<20}{:6}{:10}{:10}  {:2}/{:2}   {:?}\r\n",
+                                    "{:<20}{:6}{:10}{:10}{:10}  {:2}/{:2}   {:?}\r\n",
                                     pname,
                                     process.debug_timeslice_expiration_count(),
+                                    cpu_time_us / 1000,
                                     process.debug_syscall_count(),
                                     process.get_restart_count(),
                                     grants_used,
@@ -798,232 +1239,24 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                             }
                         }
 
-                        if clean_str.starts_with("console-start") {
+                        let mut words = clean_str.split_whitespace();
+                        let name = words.next().unwrap_or("");
+                        if name == "console-start" {
                             self.mode.set(ProcessConsoleState::Active);
                         } else if self.mode.get() == ProcessConsoleState::Hibernating {
                             // Ignore all commands in hibernating mode. We put
                             // this case early so we ensure we get stuck here
                             // even if the user typed a valid command.
-                        } else if clean_str.starts_with("help") {
-                            let _ = self.write_bytes(b"Welcome to the process console.\r\n");
-                            let _ = self.write_bytes(b"Valid commands are: ");
-                            let _ = self.write_bytes(VALID_COMMANDS_STR);
-                        } else if clean_str.starts_with("console-stop") {
-                            let _ = self.write_bytes(b"Disabling the process console.\r\n");
-                            let _ = self.write_bytes(b"Run console-start to reactivate.\r\n");
-                            self.mode.set(ProcessConsoleState::Hibernating);
-                        } else if clean_str.starts_with("start") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.resume();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!("Process {} resumed.\r\n", name),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("stop") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.stop();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!("Process {} stopped\r\n", proc_name),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("fault") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.set_fault_state();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!(
-                                                    "Process {} now faulted\r\n",
-                                                    proc_name
-                                                ),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("terminate") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.terminate(None);
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!("Process {} terminated\n", proc_name),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("boot") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name
-                                            && proc.get_state() == State::Terminated
-                                        {
-                                            proc.try_restart(None);
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("list") {
-                            let _ = self
-                                .write_bytes(b" PID    ShortID    Name                Quanta  ");
-                            let _ = self.write_bytes(b"Syscalls  Restarts  Grants  State\r\n");
-
-                            // Count the number of current processes.
-                            let mut count = 0;
-                            self.kernel.process_each_capability(&self.capability, |_| {
-                                count += 1;
-                            });
-
-                            if count > 0 {
-                                // Start the state machine to print each separately.
-                                self.write_state(WriterState::List {
-                                    index: -1,
-                                    total: count,
-                                });
-                            }
-                        } else if clean_str.starts_with("status") {
-                            let info: KernelInfo = KernelInfo::new(self.kernel);
-                            let mut console_writer = ConsoleWriter::new();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
-                                    "Total processes: {}\r\n",
-                                    info.number_loaded_processes(&self.capability)
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
-                                    "Active processes: {}\r\n",
-                                    info.number_active_processes(&self.capability)
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
-                                    "Timeslice expirations: {}\r\n",
-                                    info.timeslice_expirations(&self.capability)
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                        } else if clean_str.starts_with("process") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                // If two processes have the same name, only
-                                // print the first one we find.
-                                let mut found = false;
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        if found {
-                                            return;
-                                        }
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let mut context: Option<ProcessPrinterContext> = None;
-                                            context = self.process_printer.print_overview(
-                                                proc,
-                                                &mut console_writer,
-                                                context,
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-
-                                            if context.is_some() {
-                                                self.writer_state.replace(
-                                                    WriterState::ProcessPrint {
-                                                        process_id: proc.processid(),
-                                                        context: context,
-                                                    },
-                                                );
-                                            }
-
-                                            found = true;
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("kernel") {
+                        } else if let Some(builtin) =
+                            Self::BUILTIN_COMMANDS.iter().find(|c| c.name == name)
+                        {
+                            (builtin.execute)(self, words);
+                        } else if let Some(entry) = self.commands.iter().find(|e| e.name == name) {
                             let mut console_writer = ConsoleWriter::new();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
-                                    "Kernel version: {}.{} (build {})\r\n",
-                                    kernel::KERNEL_MAJOR_VERSION,
-                                    kernel::KERNEL_MINOR_VERSION,
-                                    option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown")
-                                ),
-                            );
+                            entry.command.execute(words, &mut console_writer);
                             let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
-
-                            // Prints kernel memory by moving the writer to the
-                            // start state.
-                            self.writer_state.replace(WriterState::KernelStart);
-                        } else if clean_str.starts_with("reset") {
-                            self.reset_function.map_or_else(
-                                || {
-                                    let _ = self.write_bytes(b"Reset function is not implemented");
-                                },
-                                |f| {
-                                    f();
-                                },
-                            );
-                        } else if clean_str.starts_with("panic") {
-                            panic!("Process Console forced a kernel panic.");
                         } else {
-                            let _ = self.write_bytes(b"Valid commands are: ");
-                            let _ = self.write_bytes(VALID_COMMANDS_STR);
+                            self.print_valid_commands();
                         }
                     }
                     Err(_e) => {
@@ -1064,6 +1297,11 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
     }
 
     fn write_byte(&self, byte: u8) -> Result<(), ErrorCode> {
+        // A hibernating console stays silent, so that it does not echo input
+        // meant for something else sharing the UART, such as a GDB stub.
+        if self.mode.get() == ProcessConsoleState::Hibernating {
+            return Ok(());
+        }
         if self.tx_in_progress.get() {
             self.queue_buffer.map(|buf| {
                 buf[self.queue_size.get()] = byte;
@@ -1081,6 +1319,9 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
     }
 
     fn write_bytes(&self, bytes: &[u8]) -> Result<(), ErrorCode> {
+        if self.mode.get() == ProcessConsoleState::Hibernating {
+            return Ok(());
+        }
         if self.tx_in_progress.get() {
             self.queue_buffer.map(|buf| {
                 let size = self.queue_size.get();
@@ -0,0 +1,265 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Install new applications while the kernel is running.
+//!
+//! This capsule gives userspace access to the kernel's dynamic process
+//! loader. An application sends a complete TBF object in chunks; the kernel
+//! writes it into free app flash and then creates and runs a process for it
+//! without a reboot. Only one application can load a new application at a
+//! time.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! # use kernel::static_init;
+//!
+//! let app_loader_buffer = static_init!([u8; 512], [0; 512]);
+//! let app_loader = static_init!(
+//!     capsules_extra::app_loader::AppLoader,
+//!     capsules_extra::app_loader::AppLoader::new(
+//!         dynamic_process_loader,
+//!         board_kernel.create_grant(capsules_extra::app_loader::DRIVER_NUM, &grant_cap),
+//!         app_loader_buffer));
+//! dynamic_process_loader.set_client(app_loader);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! - Stability: 1 - Unstable
+//!
+//! The application first calls command 1 with the total length of the TBF
+//! object. Once the `setup_done` upcall arrives, it writes the object with
+//! command 2, one chunk at a time from the read-only allow buffer, waiting
+//! for `write_done` after each chunk. Finally command 3 creates the process.
+//! Command 4 aborts a load that was set up but not completed.
+
+use core::cmp;
+
+use kernel::dynamic_process_loading::{DynamicProcessLoading, DynamicProcessLoadingClient};
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::process::ProcessLoadError;
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AppLoader as usize;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Setup done callback.
+    pub const SETUP_DONE: usize = 0;
+    /// Write done callback.
+    pub const WRITE_DONE: usize = 1;
+    /// Abort done callback.
+    pub const ABORT_DONE: usize = 2;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 3;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Setup a buffer with the next chunk of the TBF object.
+    pub const WRITE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct AppLoader {
+    // The kernel's dynamic process loader.
+    loader: &'static dyn DynamicProcessLoading,
+    // Per-app state.
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<0>,
+    >,
+    // The process currently loading a new application.
+    current_process: OptionalCell<ProcessId>,
+    // Internal buffer for copying chunks from userspace.
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl AppLoader {
+    pub fn new(
+        loader: &'static dyn DynamicProcessLoading,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<0>,
+        >,
+        buffer: &'static mut [u8],
+    ) -> AppLoader {
+        AppLoader {
+            loader,
+            apps: grant,
+            current_process: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+        }
+    }
+
+    /// Make `processid` the process loading a new application, unless another
+    /// process that still exists already is.
+    fn claim(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        match self.current_process.get() {
+            Some(owner) if owner == processid => Ok(()),
+            Some(owner) if self.apps.enter(owner, |_, _| {}).is_ok() => Err(ErrorCode::BUSY),
+            _ => {
+                self.current_process.set(processid);
+                Ok(())
+            }
+        }
+    }
+
+    fn is_owner(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if self.current_process.contains(&processid) {
+            Ok(())
+        } else {
+            Err(ErrorCode::RESERVE)
+        }
+    }
+
+    fn write(&self, offset: usize, length: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |_app, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::WRITE)
+                    .and_then(|write| {
+                        write.enter(|app_buffer| {
+                            self.buffer.take().map_or(Err(ErrorCode::BUSY), |buffer| {
+                                let length =
+                                    cmp::min(length, cmp::min(buffer.len(), app_buffer.len()));
+                                app_buffer[..length].copy_to_slice(&mut buffer[..length]);
+                                self.loader.write(buffer, offset, length)
+                            })
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    fn schedule_upcall(&self, upcall_num: usize, result: Result<(), ErrorCode>, length: usize) {
+        self.current_process.map(|processid| {
+            let _ = self.apps.enter(processid, |_app, kernel_data| {
+                kernel_data
+                    .schedule_upcall(upcall_num, (into_statuscode(result), length, 0))
+                    .ok();
+            });
+        });
+    }
+}
+
+impl DynamicProcessLoadingClient for AppLoader {
+    fn setup_done(&self, result: Result<(), ErrorCode>) {
+        self.schedule_upcall(upcall::SETUP_DONE, result, 0);
+    }
+
+    fn write_done(&self, result: Result<(), ErrorCode>, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        self.schedule_upcall(upcall::WRITE_DONE, result, length);
+    }
+
+    fn abort_done(&self, result: Result<(), ErrorCode>) {
+        self.schedule_upcall(upcall::ABORT_DONE, result, 0);
+        self.current_process.clear();
+    }
+}
+
+impl SyscallDriver for AppLoader {
+    /// Load a new application.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Reserve flash for a TBF object of `arg1` bytes. Issues the
+    ///        `setup_done` upcall when the flash can be written.
+    /// - `2`: Write up to `arg2` bytes from the allow buffer at offset `arg1`
+    ///        into the TBF object. Issues the `write_done` upcall with the
+    ///        number of bytes written.
+    /// - `3`: Create a process from the written TBF object. The process runs
+    ///        once its credentials are approved.
+    /// - `4`: Abort the current load. Issues the `abort_done` upcall.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let res = self.claim(processid).and_then(|()| {
+                    self.loader.setup(arg1).map_err(|e| {
+                        // If the loader is busy with a load left behind by a
+                        // process that no longer exists, keep the claim so
+                        // that this process can abort it.
+                        if e != ErrorCode::BUSY {
+                            self.current_process.clear();
+                        }
+                        e
+                    })
+                });
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            2 => {
+                let res = self
+                    .is_owner(processid)
+                    .and_then(|()| self.write(arg1, arg2, processid));
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            3 => {
+                let res = self.is_owner(processid).and_then(|()| {
+                    self.loader.load().map_err(|e| match e {
+                        ProcessLoadError::NotEnoughMemory => ErrorCode::NOMEM,
+                        ProcessLoadError::InternalError => ErrorCode::FAIL,
+                        _ => ErrorCode::INVAL,
+                    })
+                });
+                match res {
+                    Ok(()) => {
+                        self.current_process.clear();
+                        CommandReturn::success()
+                    }
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            4 => {
+                let res = self.is_owner(processid).and_then(|()| self.loader.abort());
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,375 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Persistent crash log stored in a reserved flash page.
+//!
+//! This capsule keeps the most recent [`CrashRecord`] in one flash page. When
+//! a process faults, the kernel passes a record of the fault to this capsule,
+//! and when the kernel panics for another reason, the board's panic handler
+//! passes a record of the panic through
+//! [`kernel::debug::panic_record_crash`]. The capsule erases the page and
+//! writes the record; a record that arrives while it is still writing the
+//! previous one is dropped. After the board reboots, [`FlashCrashLog::load`]
+//! reads the page back, and the record is available to the process console
+//! through [`CrashCommand`] and to userspace through the syscall interface
+//! below.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let page = static_init!(
+//!     <F as kernel::hil::flash::Flash>::Page,
+//!     Default::default()
+//! );
+//! let crash_log = static_init!(
+//!     capsules_extra::crash_log::FlashCrashLog<'static, F>,
+//!     capsules_extra::crash_log::FlashCrashLog::new(
+//!         flash,
+//!         CRASH_LOG_PAGE,
+//!         page,
+//!         board_kernel.create_grant(capsules_extra::crash_log::DRIVER_NUM, &grant_cap),
+//!     )
+//! );
+//! flash.set_client(crash_log);
+//! crash_log.load();
+//! board_kernel.set_crash_recorder(crash_log, &process_management_capability);
+//!
+//! // Optionally, add the `crash` command to the process console:
+//! let command = static_init!(CrashCommand<'static>, CrashCommand::new(crash_log));
+//! let entry = static_init!(
+//!     CommandEntry<'static>,
+//!     CommandEntry::new("crash", "crash", command)
+//! );
+//! process_console.add_command(entry);
+//!
+//! // In the panic handler:
+//! kernel::debug::panic_record_crash(crash_log, pi, &CHIP);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Copy the most recent crash record, encoded as described in
+//!   [`kernel::crash_log`], into the allowed buffer. Returns the record
+//!   length, `FAIL` if no crash is recorded, or `SIZE` if the buffer is too
+//!   small.
+//! - `2`: Erase the crash record. Returns `BUSY` if the flash page is in use.
+//!   The upcall is scheduled when the record has been erased.
+//!
+//! ### Subscribe
+//!
+//! - `0`: Record erased. The first argument is 0 on success or an error code.
+//!
+//! ### Allow
+//!
+//! - Read-write `0`: Buffer the crash record is copied into.
+
+use core::cell::Cell;
+use core::fmt;
+use core::str::SplitWhitespace;
+
+use capsules_core::process_console::ConsoleCommand;
+use kernel::crash_log::{CrashReader, CrashRecord, CrashRecorder, RECORD_LEN};
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::CrashLog as usize;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer the crash record is copied into.
+    pub const RECORD: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for upcalls
+mod upcall {
+    /// The crash record was erased.
+    pub const CLEAR_DONE: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Reading the record at boot.
+    Loading,
+    /// Erasing the page before writing a new record.
+    RecordErase,
+    /// Writing a new record.
+    RecordWrite,
+    /// Erasing the record on request of a process.
+    Clearing,
+}
+
+pub struct FlashCrashLog<'a, F: hil::flash::Flash + 'static> {
+    flash: &'a F,
+    page_number: usize,
+    buffer: TakeCell<'static, F::Page>,
+    state: Cell<State>,
+    /// The most recent crash record, if one was found in flash.
+    last_crash: OptionalCell<CrashRecord>,
+    apps: Grant<
+        (),
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<0>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// The process that requested the record be erased.
+    clearing_process: OptionalCell<ProcessId>,
+}
+
+impl<'a, F: hil::flash::Flash> FlashCrashLog<'a, F> {
+    /// `page_number` must be reserved for the crash log, and its page must
+    /// be at least `RECORD_LEN` bytes long.
+    pub fn new(
+        flash: &'a F,
+        page_number: usize,
+        buffer: &'static mut F::Page,
+        grant: Grant<
+            (),
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<0>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> FlashCrashLog<'a, F> {
+        FlashCrashLog {
+            flash,
+            page_number,
+            buffer: TakeCell::new(buffer),
+            state: Cell::new(State::Idle),
+            last_crash: OptionalCell::empty(),
+            apps: grant,
+            clearing_process: OptionalCell::empty(),
+        }
+    }
+
+    /// Read the crash record from flash. Call once at boot.
+    pub fn load(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        match self.flash.read_page(self.page_number, buffer) {
+            Ok(()) => {
+                self.state.set(State::Loading);
+                Ok(())
+            }
+            Err((e, buffer)) => {
+                self.buffer.replace(buffer);
+                Err(e)
+            }
+        }
+    }
+
+    fn clear(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.flash.erase_page(self.page_number)?;
+        self.state.set(State::Clearing);
+        self.clearing_process.set(processid);
+        Ok(())
+    }
+
+    fn copy_record(&self, processid: ProcessId) -> Result<u32, ErrorCode> {
+        let record = self.last_crash.get().ok_or(ErrorCode::FAIL)?;
+        let mut encoded = [0; RECORD_LEN];
+        record.encode(&mut encoded)?;
+        self.apps.enter(processid, |_, kernel_data| {
+            kernel_data
+                .get_readwrite_processbuffer(rw_allow::RECORD)?
+                .mut_enter(|buf| {
+                    let dest = buf.get(0..RECORD_LEN).ok_or(ErrorCode::SIZE)?;
+                    dest.copy_from_slice(&encoded);
+                    Ok(RECORD_LEN as u32)
+                })?
+        })?
+    }
+}
+
+impl<F: hil::flash::Flash> CrashRecorder for FlashCrashLog<'_, F> {
+    fn record_crash(&self, mut record: CrashRecord) {
+        // The record is lost if a flash operation is in progress, for example
+        // while the record of an earlier fault is still being written.
+        if self.state.get() != State::Idle {
+            return;
+        }
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        let count = self.last_crash.map_or(0, |last| last.count());
+        record.set_count(count.saturating_add(1));
+        if record.encode(buffer.as_mut()).is_err() {
+            self.buffer.replace(buffer);
+            return;
+        }
+        self.buffer.replace(buffer);
+
+        if self.flash.erase_page(self.page_number).is_ok() {
+            self.state.set(State::RecordErase);
+            // Faults that do not panic are reported right away, and later
+            // records count this one.
+            self.last_crash.set(record);
+        }
+    }
+
+    fn is_busy(&self) -> bool {
+        matches!(self.state.get(), State::RecordErase | State::RecordWrite)
+    }
+}
+
+impl<F: hil::flash::Flash> CrashReader for FlashCrashLog<'_, F> {
+    fn last_crash(&self) -> Option<CrashRecord> {
+        self.last_crash.get()
+    }
+}
+
+/// Process console command that prints the record of the last kernel panic.
+pub struct CrashCommand<'a> {
+    reader: &'a dyn CrashReader,
+}
+
+impl<'a> CrashCommand<'a> {
+    pub fn new(reader: &'a dyn CrashReader) -> CrashCommand<'a> {
+        CrashCommand { reader }
+    }
+}
+
+impl ConsoleCommand for CrashCommand<'_> {
+    fn execute(&self, _args: SplitWhitespace, out: &mut dyn fmt::Write) {
+        let record = match self.reader.last_crash() {
+            Some(record) => record,
+            None => {
+                let _ = write!(out, "No crash recorded.\r\n");
+                return;
+            }
+        };
+        let _ = write!(out, "Crash {}: {}\r\n", record.count(), record.message());
+        if let Some(name) = record.process_name() {
+            let _ = write!(out, "Process: {}", name);
+            if let Some(registers) = record.registers() {
+                let _ = write!(
+                    out,
+                    "  PC: {:#010x}  LR: {:#010x}  SP: {:#010x}",
+                    registers.pc, registers.lr, registers.sp
+                );
+            }
+            let _ = write!(out, "\r\n");
+        }
+        for (i, line) in record.stack().chunks(32).enumerate() {
+            let _ = write!(out, "{}", if i == 0 { "Stack:" } else { "      " });
+            for word in line.chunks(4) {
+                let mut bytes = [0; 4];
+                bytes[..word.len()].copy_from_slice(word);
+                let _ = write!(out, " {:08x}", u32::from_le_bytes(bytes));
+            }
+            let _ = write!(out, "\r\n");
+        }
+    }
+}
+
+impl<F: hil::flash::Flash> hil::flash::Client<F> for FlashCrashLog<'_, F> {
+    fn read_complete(&self, buffer: &'static mut F::Page, result: Result<(), hil::flash::Error>) {
+        if result.is_ok() {
+            self.last_crash.insert(CrashRecord::decode(buffer.as_mut()));
+        }
+        self.buffer.replace(buffer);
+        self.state.set(State::Idle);
+    }
+
+    fn write_complete(&self, buffer: &'static mut F::Page, _result: Result<(), hil::flash::Error>) {
+        self.buffer.replace(buffer);
+        self.state.set(State::Idle);
+    }
+
+    fn erase_complete(&self, result: Result<(), hil::flash::Error>) {
+        match self.state.get() {
+            State::RecordErase => {
+                let written = result.is_ok()
+                    && self.buffer.take().map_or(false, |buffer| {
+                        match self.flash.write_page(self.page_number, buffer) {
+                            Ok(()) => true,
+                            Err((_, buffer)) => {
+                                self.buffer.replace(buffer);
+                                false
+                            }
+                        }
+                    });
+                self.state.set(if written {
+                    State::RecordWrite
+                } else {
+                    State::Idle
+                });
+            }
+            State::Clearing => {
+                self.state.set(State::Idle);
+                let status = match result {
+                    Ok(()) => {
+                        self.last_crash.clear();
+                        0
+                    }
+                    Err(_) => usize::from(ErrorCode::FAIL),
+                };
+                self.clearing_process.take().map(|processid| {
+                    let _ = self.apps.enter(processid, |_, kernel_data| {
+                        kernel_data
+                            .schedule_upcall(upcall::CLEAR_DONE, (status, 0, 0))
+                            .ok();
+                    });
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<F: hil::flash::Flash> SyscallDriver for FlashCrashLog<'_, F> {
+    /// Read and erase the crash record.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Copy the crash record into the allowed buffer.
+    /// - `2`: Erase the crash record.
+    fn command(
+        &self,
+        command_num: usize,
+        _data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => match self.copy_record(processid) {
+                Ok(len) => CommandReturn::success_u32(len),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            2 => self.clear(processid).into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,397 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Topic-based publish/subscribe event bus between processes.
+//!
+//! Processes subscribe to numeric topic IDs and publish small payloads to a
+//! topic. When an event is published, the capsule copies the payload out of
+//! the publisher's read-only buffer and into the read-write buffer of every
+//! other process subscribed to that topic, then schedules an upcall for each
+//! of them. The publisher never receives its own events.
+//!
+//! Each process's subscription table lives in its grant and holds at most
+//! [`MAX_SUBSCRIPTIONS`] topics. Fan-out is bounded as well: at most
+//! `max_fanout` processes (configured when the capsule is created) can be
+//! subscribed to any single topic, so the cost of a publish is bounded
+//! regardless of how many processes are running.
+//!
+//! Payloads are limited to [`MAX_PAYLOAD_LEN`] bytes. If a subscriber's
+//! buffer is shorter than the payload, the payload is truncated; the upcall
+//! always reports the full published length so the subscriber can detect
+//! this. A subscriber's buffer is overwritten by each delivered event, so a
+//! subscriber that has not yet handled the previous event will lose it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let event_bus = static_init!(
+//!     capsules_extra::event_bus::EventBus,
+//!     capsules_extra::event_bus::EventBus::new(
+//!         board_kernel.create_grant(capsules_extra::event_bus::DRIVER_NUM, &grant_cap),
+//!         4,
+//!     )
+//! );
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Subscribe to the topic in `data1`. Returns `ALREADY` if the process
+//!   is already subscribed, `NOMEM` if its subscription table is full, and
+//!   `BUSY` if the topic already has the maximum number of subscribers.
+//! - `2`: Unsubscribe from the topic in `data1`. Returns `INVAL` if the
+//!   process is not subscribed to the topic.
+//! - `3`: Publish the first `data2` bytes of the allowed read-only buffer to
+//!   the topic in `data1`. Returns `SIZE` if the length exceeds the payload
+//!   limit or the allowed buffer, otherwise the number of subscribers the
+//!   event was delivered to.
+//!
+//! ### Subscribe
+//!
+//! - `0`: Event delivered. The upcall arguments are the topic, the length of
+//!   the published payload, and the ID of the publishing process.
+//!
+//! ### Allow
+//!
+//! - Read-only `0`: Payload to publish.
+//! - Read-write `0`: Buffer events are delivered into.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::{
+    ReadableProcessBuffer, ReadableProcessSlice, WriteableProcessBuffer, WriteableProcessSlice,
+};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::EventBus as usize;
+
+/// Maximum number of topics a single process can be subscribed to.
+pub const MAX_SUBSCRIPTIONS: usize = 8;
+
+/// Maximum length of a published payload, in bytes.
+pub const MAX_PAYLOAD_LEN: usize = 32;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Payload to publish.
+    pub const PAYLOAD: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer that delivered events are copied into.
+    pub const EVENT: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for upcalls
+mod upcall {
+    /// An event was delivered on a subscribed topic.
+    pub const EVENT: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Per-process subscription table.
+#[derive(Default)]
+pub struct App {
+    topics: [Option<usize>; MAX_SUBSCRIPTIONS],
+}
+
+impl App {
+    fn is_subscribed(&self, topic: usize) -> bool {
+        self.topics.contains(&Some(topic))
+    }
+
+    /// Add `topic` to the table, given how many processes are already
+    /// subscribed to it.
+    fn subscribe(
+        &mut self,
+        topic: usize,
+        subscribers: usize,
+        max_fanout: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.is_subscribed(topic) {
+            return Err(ErrorCode::ALREADY);
+        }
+        if subscribers >= max_fanout {
+            return Err(ErrorCode::BUSY);
+        }
+        let slot = self
+            .topics
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        *slot = Some(topic);
+        Ok(())
+    }
+
+    fn unsubscribe(&mut self, topic: usize) -> Result<(), ErrorCode> {
+        let slot = self
+            .topics
+            .iter_mut()
+            .find(|slot| **slot == Some(topic))
+            .ok_or(ErrorCode::INVAL)?;
+        *slot = None;
+        Ok(())
+    }
+
+    /// Whether an event on `topic` is delivered to this process. Publishers
+    /// never receive their own events.
+    fn receives(&self, topic: usize, is_publisher: bool) -> bool {
+        !is_publisher && self.is_subscribed(topic)
+    }
+}
+
+/// Copy the first `len` bytes of the publisher's buffer into `payload`.
+fn read_payload(
+    len: usize,
+    buf: &ReadableProcessSlice,
+    payload: &mut [u8; MAX_PAYLOAD_LEN],
+) -> Result<(), ErrorCode> {
+    if len > MAX_PAYLOAD_LEN {
+        return Err(ErrorCode::SIZE);
+    }
+    let src = buf.get(0..len).ok_or(ErrorCode::SIZE)?;
+    src.copy_to_slice(&mut payload[..len]);
+    Ok(())
+}
+
+/// Copy `payload` into a subscriber's buffer, truncating it if the buffer is
+/// shorter.
+fn deliver(payload: &[u8], buf: &WriteableProcessSlice) {
+    let copy_len = core::cmp::min(payload.len(), buf.len());
+    if let Some(dest) = buf.get(0..copy_len) {
+        dest.copy_from_slice(&payload[..copy_len]);
+    }
+}
+
+pub struct EventBus {
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// Maximum number of processes that can subscribe to a single topic.
+    max_fanout: usize,
+}
+
+impl EventBus {
+    pub fn new(
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        max_fanout: usize,
+    ) -> EventBus {
+        EventBus {
+            apps: grant,
+            max_fanout,
+        }
+    }
+
+    /// Count the processes currently subscribed to `topic`.
+    fn subscriber_count(&self, topic: usize) -> usize {
+        let count = Cell::new(0);
+        self.apps.each(|_, app, _| {
+            if app.is_subscribed(topic) {
+                count.set(count.get() + 1);
+            }
+        });
+        count.get()
+    }
+
+    fn subscribe(&self, topic: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        // Count existing subscribers before entering this process's grant, as
+        // the grant cannot be iterated over while one of its entries is
+        // entered.
+        let subscribers = self.subscriber_count(topic);
+        self.apps.enter(processid, |app, _| {
+            app.subscribe(topic, subscribers, self.max_fanout)
+        })?
+    }
+
+    fn unsubscribe(&self, topic: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| app.unsubscribe(topic))?
+    }
+
+    /// Publish `len` bytes of `processid`'s payload buffer to `topic`,
+    /// returning the number of processes the event was delivered to.
+    fn publish(&self, topic: usize, len: usize, processid: ProcessId) -> Result<u32, ErrorCode> {
+        // Copy the payload into the kernel first so that the publisher's
+        // grant is not entered while delivering to subscribers.
+        let mut payload = [0; MAX_PAYLOAD_LEN];
+        self.apps.enter(processid, |_, kernel_data| {
+            kernel_data
+                .get_readonly_processbuffer(ro_allow::PAYLOAD)?
+                .enter(|buf| read_payload(len, buf, &mut payload))?
+        })??;
+
+        let delivered = Cell::new(0);
+        self.apps.each(|subscriber, app, kernel_data| {
+            if !app.receives(topic, subscriber == processid) {
+                return;
+            }
+            let _ = kernel_data
+                .get_readwrite_processbuffer(rw_allow::EVENT)
+                .and_then(|event| event.mut_enter(|buf| deliver(&payload[..len], buf)));
+            if kernel_data
+                .schedule_upcall(upcall::EVENT, (topic, len, processid.id()))
+                .is_ok()
+            {
+                delivered.set(delivered.get() + 1);
+            }
+        });
+
+        Ok(delivered.get())
+    }
+}
+
+impl SyscallDriver for EventBus {
+    /// Subscribe to, unsubscribe from, and publish on event bus topics.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Subscribe to topic `data1`.
+    /// - `2`: Unsubscribe from topic `data1`.
+    /// - `3`: Publish `data2` bytes of the allowed payload to topic `data1`.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.subscribe(data1, processid).into(),
+
+            2 => self.unsubscribe(data1, processid).into(),
+
+            3 => match self.publish(data1, data2, processid) {
+                Ok(delivered) => CommandReturn::success_u32(delivered),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscribed(topics: &[usize]) -> App {
+        let mut app = App::default();
+        for &topic in topics {
+            assert_eq!(app.subscribe(topic, 0, 4), Ok(()));
+        }
+        app
+    }
+
+    #[test]
+    fn subscription_table_is_limited() {
+        let topics: [usize; MAX_SUBSCRIPTIONS] = core::array::from_fn(|i| 100 + i);
+        let mut app = subscribed(&topics);
+        assert_eq!(app.subscribe(7, 0, 4), Err(ErrorCode::NOMEM));
+        // Unsubscribing frees a slot for another topic.
+        assert_eq!(app.unsubscribe(103), Ok(()));
+        assert_eq!(app.subscribe(7, 0, 4), Ok(()));
+        assert!(app.is_subscribed(7));
+        assert!(!app.is_subscribed(103));
+    }
+
+    #[test]
+    fn fanout_is_limited() {
+        let mut app = App::default();
+        assert_eq!(app.subscribe(7, 4, 4), Err(ErrorCode::BUSY));
+        assert!(!app.is_subscribed(7));
+        assert_eq!(app.subscribe(7, 3, 4), Ok(()));
+    }
+
+    #[test]
+    fn subscribing_twice_is_rejected() {
+        let mut app = subscribed(&[7]);
+        assert_eq!(app.subscribe(7, 0, 4), Err(ErrorCode::ALREADY));
+        // Being subscribed already takes precedence over a full topic.
+        assert_eq!(app.subscribe(7, 4, 4), Err(ErrorCode::ALREADY));
+        assert_eq!(app.topics.iter().flatten().count(), 1);
+    }
+
+    #[test]
+    fn unsubscribing_unknown_topic_is_rejected() {
+        let mut app = subscribed(&[7]);
+        assert_eq!(app.unsubscribe(8), Err(ErrorCode::INVAL));
+        assert_eq!(app.unsubscribe(7), Ok(()));
+        assert_eq!(app.unsubscribe(7), Err(ErrorCode::INVAL));
+    }
+
+    #[test]
+    fn oversized_payload_is_rejected() {
+        let mut publisher = [0x5a; MAX_PAYLOAD_LEN + 8];
+        let mut payload = [0; MAX_PAYLOAD_LEN];
+        assert_eq!(
+            read_payload(
+                MAX_PAYLOAD_LEN + 1,
+                (&mut publisher[..]).into(),
+                &mut payload
+            ),
+            Err(ErrorCode::SIZE)
+        );
+        // Nor can a publisher publish more than it allowed.
+        assert_eq!(
+            read_payload(5, (&mut publisher[..4]).into(), &mut payload),
+            Err(ErrorCode::SIZE)
+        );
+        assert_eq!(
+            read_payload(MAX_PAYLOAD_LEN, (&mut publisher[..]).into(), &mut payload),
+            Ok(())
+        );
+        assert_eq!(payload, [0x5a; MAX_PAYLOAD_LEN]);
+    }
+
+    #[test]
+    fn publisher_does_not_receive_its_own_events() {
+        let app = subscribed(&[7]);
+        assert!(app.receives(7, false));
+        assert!(!app.receives(7, true));
+        assert!(!app.receives(8, false));
+    }
+
+    #[test]
+    fn payload_is_truncated_to_short_buffers() {
+        let payload = [1, 2, 3, 4, 5, 6];
+        let mut short = [0; 4];
+        deliver(&payload, (&mut short[..]).into());
+        assert_eq!(short, [1, 2, 3, 4]);
+
+        let mut long = [0; 8];
+        deliver(&payload, (&mut long[..]).into());
+        assert_eq!(long, [1, 2, 3, 4, 5, 6, 0, 0]);
+    }
+}
@@ -0,0 +1,585 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Userspace driver for a FAT16 or FAT32 filesystem on an SD card.
+//!
+//! Files of a process are kept in the directory of its storage write
+//! identifier (see [`volume`](super::volume)), so each process has its own
+//! directory and needs a write identifier in its TBF header to create files.
+//! A process can also open or list the files of another storage identifier
+//! if its storage permissions allow reading (or, to write, modifying) that
+//! identifier.
+//!
+//! The driver replaces [`SDCardDriver`](crate::sdcard::SDCardDriver) as the
+//! client of the SD card, so a board can only use one of them.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let fatfs_buffer = static_init!([u8; 512], [0; 512]);
+//! let fatfs = static_init!(
+//!     capsules_extra::fatfs::driver::FatFsDriver<
+//!         'static,
+//!         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     >,
+//!     capsules_extra::fatfs::driver::FatFsDriver::new(
+//!         sdcard,
+//!         fatfs_buffer,
+//!         board_kernel.create_grant(
+//!             capsules_extra::fatfs::driver::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! sdcard.set_client(fatfs);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Mount the filesystem, initializing the SD card first if needed.
+//!   The upcall returns the FAT width in bits (16 or 32) and the cluster
+//!   size in bytes.
+//! - `2`: Open the file named in read-only allow `0` (`NAME.EXT`). `data1`
+//!   is the mode: `0` to read, `1` to read and write, `2` to read and write,
+//!   creating the file if needed. `data2` is the storage identifier of the
+//!   directory, or `0` for the process' own. The upcall returns the file
+//!   handle and the file size.
+//! - `3`: Read up to `data2` bytes from file handle `data1` into read-write
+//!   allow `0`. The upcall returns the number of bytes read and the new
+//!   position.
+//! - `4`: Write `data2` bytes from read-only allow `1` to file handle
+//!   `data1`. The upcall returns the number of bytes written and the new
+//!   position.
+//! - `5`: Set the position of file handle `data1` to `data2`, which cannot
+//!   be past the end of the file.
+//! - `6`: Close file handle `data1`.
+//! - `7`: Copy the name of file number `data2` in the directory of storage
+//!   identifier `data1` (`0` for the process' own) into read-write allow
+//!   `0`. The upcall returns the length of the name and the size of the
+//!   file, or `NOSUPPORT` if there are fewer files.
+//! - `8`: Return the size and position of file handle `data1`.
+//!
+//! Commands 1, 2, 3, 4 and 7 complete with upcall `0`, whose first argument
+//! is the status code. Each process can have one such operation pending.
+//!
+//! ### Errors
+//!
+//! - `NOSUPPORT`: the file does not exist, or the card has no supported
+//!   volume.
+//! - `FAIL`: the storage permissions do not allow the access, or the volume
+//!   is corrupted.
+//! - `RESERVE`: the filesystem is not mounted.
+//! - `NOMEM`: the volume or directory is full, or too many files are open.
+
+use core::cmp;
+
+use capsules_core::driver;
+use kernel::errorcode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::processbuffer::{ReadableProcessBuffer, ReadableProcessSlice};
+use kernel::processbuffer::{WriteableProcessBuffer, WriteableProcessSlice};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use super::volume::{FileBuffer, NoData, OpenMode, Request, Volume, MAX_OPEN_FILES, SECTOR_SIZE};
+use crate::sdcard::{SDCard, SDCardClient};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::FatFs as usize;
+
+/// Longest file name in the `NAME.EXT` form.
+const MAX_NAME_LEN: usize = 12;
+
+/// IDs for read-only allow buffers.
+mod ro_allow {
+    /// File name to open.
+    pub const NAME: usize = 0;
+    /// Data to write.
+    pub const WRITE: usize = 1;
+    /// The number of RO allow buffers the kernel stores for this grant.
+    pub const COUNT: u8 = 2;
+}
+
+/// IDs for read-write allow buffers.
+mod rw_allow {
+    /// Data read, or a file name listed.
+    pub const READ: usize = 0;
+    /// The number of RW allow buffers the kernel stores for this grant.
+    pub const COUNT: u8 = 1;
+}
+
+/// IDs for upcalls.
+mod upcalls {
+    /// An operation completed.
+    pub const DONE: usize = 0;
+    /// The number of upcalls the kernel stores for this grant.
+    pub const COUNT: u8 = 1;
+}
+
+impl FileBuffer for WriteableProcessSlice {
+    fn copy_in(&self, offset: usize, data: &[u8]) {
+        let len = cmp::min(data.len(), self.len().saturating_sub(offset));
+        if let Some(slice) = self.get(offset..offset + len) {
+            slice.copy_from_slice(&data[..len]);
+        }
+    }
+
+    fn copy_out(&self, offset: usize, data: &mut [u8]) {
+        let len = cmp::min(data.len(), self.len().saturating_sub(offset));
+        if let Some(slice) = self.get(offset..offset + len) {
+            slice.copy_to_slice(&mut data[..len]);
+        }
+    }
+}
+
+impl FileBuffer for ReadableProcessSlice {
+    fn copy_in(&self, _offset: usize, _data: &[u8]) {}
+
+    fn copy_out(&self, offset: usize, data: &mut [u8]) {
+        let len = cmp::min(data.len(), self.len().saturating_sub(offset));
+        if let Some(slice) = self.get(offset..offset + len) {
+            slice.copy_to_slice(&mut data[..len]);
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum UserSpaceOp {
+    Mount,
+    Open { mode: OpenMode, storage_id: u32 },
+    Read { handle: usize, len: usize },
+    Write { handle: usize, len: usize },
+    List { storage_id: u32, index: usize },
+}
+
+/// Contents of the grant for each app.
+#[derive(Default)]
+pub struct App {
+    op: OptionalCell<UserSpaceOp>,
+}
+
+pub struct FatFsDriver<'a, A: hil::time::Alarm<'a>> {
+    sdcard: &'a SDCard<'a, A>,
+    volume: MapCell<Volume>,
+    /// Sector buffer, passed to the SD card for every transfer.
+    buffer: TakeCell<'static, [u8]>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcalls::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// App whose operation is running.
+    processid: OptionalCell<ProcessId>,
+    /// The operation that is running.
+    current_op: OptionalCell<UserSpaceOp>,
+    /// Which process opened each file handle.
+    owners: [OptionalCell<ProcessId>; MAX_OPEN_FILES],
+}
+
+impl<'a, A: hil::time::Alarm<'a>> FatFsDriver<'a, A> {
+    pub fn new(
+        sdcard: &'a SDCard<'a, A>,
+        buffer: &'static mut [u8; SECTOR_SIZE],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcalls::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> FatFsDriver<'a, A> {
+        FatFsDriver {
+            sdcard,
+            volume: MapCell::new(Volume::new()),
+            buffer: TakeCell::new(buffer),
+            apps: grant,
+            processid: OptionalCell::empty(),
+            current_op: OptionalCell::empty(),
+            owners: Default::default(),
+        }
+    }
+
+    /// Resolve the storage identifier `requested` by `processid` to the
+    /// identifier of the directory to access, checking the permissions of
+    /// the process.
+    fn storage_id(
+        &self,
+        processid: ProcessId,
+        requested: usize,
+        write: bool,
+    ) -> Result<u32, ErrorCode> {
+        let permissions = processid
+            .get_storage_permissions()
+            .ok_or(ErrorCode::INVAL)?;
+        let own_id = permissions.get_write_id();
+        let storage_id = match requested {
+            0 => own_id.ok_or(ErrorCode::INVAL)?,
+            id => id as u32,
+        };
+        let allowed = own_id == Some(storage_id)
+            || match write {
+                true => permissions.check_write_permission(storage_id),
+                false => permissions.check_read_permission(storage_id),
+            };
+        match allowed {
+            true => Ok(storage_id),
+            false => Err(ErrorCode::FAIL),
+        }
+    }
+
+    /// Close files whose processes no longer exist.
+    fn close_stale_files(&self) {
+        for (handle, owner) in self.owners.iter().enumerate() {
+            let stale = owner.map_or(false, |processid| {
+                self.apps.enter(processid, |_, _| ()).is_err()
+            });
+            if stale {
+                owner.clear();
+                self.volume.map(|volume| volume.close(handle));
+            }
+        }
+    }
+
+    fn is_owner(&self, handle: usize, processid: ProcessId) -> bool {
+        self.owners
+            .get(handle)
+            .map_or(false, |owner| owner.contains(&processid))
+    }
+
+    /// Start the operation of the current app.
+    fn run(&self) -> Result<(), ErrorCode> {
+        let processid = self.processid.get().ok_or(ErrorCode::RESERVE)?;
+        let request = self
+            .apps
+            .enter(processid, |app, kernel_data| {
+                let op = app.op.get().ok_or(ErrorCode::RESERVE)?;
+                if !self.sdcard.is_installed() {
+                    return Err(ErrorCode::UNINSTALLED);
+                }
+                if op == UserSpaceOp::Mount && !self.sdcard.is_initialized() {
+                    // Mount once the card is initialized.
+                    self.current_op.set(op);
+                    return self.sdcard.initialize().map(|()| None);
+                }
+                if let UserSpaceOp::Open { .. } = op {
+                    self.close_stale_files();
+                }
+
+                let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+                let request = self
+                    .volume
+                    .map_or(Request::Done(Err(ErrorCode::FAIL)), |volume| match op {
+                        UserSpaceOp::Mount => volume.mount(buffer),
+                        UserSpaceOp::Open { mode, storage_id } => kernel_data
+                            .get_readonly_processbuffer(ro_allow::NAME)
+                            .and_then(|name| {
+                                name.enter(|name| {
+                                    let mut copy = [0; MAX_NAME_LEN];
+                                    if name.len() > MAX_NAME_LEN {
+                                        return Request::Done(Err(ErrorCode::INVAL));
+                                    }
+                                    name.copy_to_slice(&mut copy[..name.len()]);
+                                    volume.open(&copy[..name.len()], storage_id, mode, buffer)
+                                })
+                            })
+                            .unwrap_or(Request::Done(Err(ErrorCode::RESERVE))),
+                        UserSpaceOp::Read { handle, len } => kernel_data
+                            .get_readwrite_processbuffer(rw_allow::READ)
+                            .and_then(|data| {
+                                data.mut_enter(|data| {
+                                    volume.read(handle, cmp::min(len, data.len()), buffer, data)
+                                })
+                            })
+                            .unwrap_or(Request::Done(Err(ErrorCode::RESERVE))),
+                        UserSpaceOp::Write { handle, len } => kernel_data
+                            .get_readonly_processbuffer(ro_allow::WRITE)
+                            .and_then(|data| {
+                                data.enter(|data| {
+                                    volume.write(handle, cmp::min(len, data.len()), buffer, data)
+                                })
+                            })
+                            .unwrap_or(Request::Done(Err(ErrorCode::RESERVE))),
+                        UserSpaceOp::List { storage_id, index } => kernel_data
+                            .get_readwrite_processbuffer(rw_allow::READ)
+                            .and_then(|data| {
+                                data.mut_enter(|data| volume.list(storage_id, index, buffer, data))
+                            })
+                            .unwrap_or(Request::Done(Err(ErrorCode::RESERVE))),
+                    });
+                self.current_op.set(op);
+                Ok(Some((request, buffer)))
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+
+        if let Some((request, buffer)) = request {
+            self.issue(request, buffer);
+        }
+        Ok(())
+    }
+
+    /// Continue the running operation after a sector transfer.
+    fn transfer_done(&self, result: Result<(), ErrorCode>, buffer: &'static mut [u8]) {
+        let request = self
+            .volume
+            .map_or(Request::Done(Err(ErrorCode::FAIL)), |volume| {
+                let writing = volume.is_writing();
+                let request = self.processid.and_then(|processid| {
+                    self.apps
+                        .enter(processid, |_, kernel_data| match writing {
+                            true => kernel_data
+                                .get_readonly_processbuffer(ro_allow::WRITE)
+                                .and_then(|data| {
+                                    data.enter(|data| volume.io_done(result, buffer, data))
+                                })
+                                .ok(),
+                            false => kernel_data
+                                .get_readwrite_processbuffer(rw_allow::READ)
+                                .and_then(|data| {
+                                    data.mut_enter(|data| volume.io_done(result, buffer, data))
+                                })
+                                .ok(),
+                        })
+                        .ok()
+                        .flatten()
+                });
+                // The process is gone, so give up on its operation.
+                request.unwrap_or_else(|| volume.io_done(Err(ErrorCode::CANCEL), buffer, &NoData))
+            });
+        self.issue(request, buffer);
+    }
+
+    /// Pass a request of the volume to the SD card, or complete the
+    /// operation.
+    fn issue(&self, request: Request, buffer: &'static mut [u8]) {
+        // The SD card drops the buffer if it cannot start a transfer, so
+        // check that it can first.
+        let ready = self.sdcard.is_installed() && self.sdcard.is_initialized();
+        let result = match request {
+            Request::Read(lba) if ready => self.sdcard.read_blocks(buffer, lba, 1),
+            Request::Write(lba) if ready => self.sdcard.write_blocks(buffer, lba, 1),
+            Request::Read(_) | Request::Write(_) => {
+                self.buffer.replace(buffer);
+                self.volume.map(|volume| volume.unmount());
+                Err(ErrorCode::RESERVE)
+            }
+            Request::Done(result) => {
+                self.buffer.replace(buffer);
+                self.complete(result);
+                return;
+            }
+        };
+        if let Err(e) = result {
+            self.volume.map(|volume| volume.unmount());
+            self.complete(Err(e));
+        }
+    }
+
+    /// Finish the running operation and start the next queued one.
+    fn complete(&self, result: Result<(usize, usize), ErrorCode>) {
+        let op = self.current_op.take();
+        if let Some(processid) = self.processid.take() {
+            if let (Some(UserSpaceOp::Open { .. }), Ok((handle, _))) = (op, result) {
+                self.owners[handle].set(processid);
+            }
+            let _ = self.apps.enter(processid, |app, kernel_data| {
+                app.op.clear();
+                let (status, value0, value1) = match result {
+                    Ok((value0, value1)) => (0, value0, value1),
+                    Err(e) => (errorcode::into_statuscode(Err(e)), 0, 0),
+                };
+                kernel_data
+                    .schedule_upcall(upcalls::DONE, (status, value0, value1))
+                    .ok();
+            });
+        }
+        self.check_queue();
+    }
+
+    fn check_queue(&self) {
+        // If an app is already running let it complete.
+        if self.processid.is_some() {
+            return;
+        }
+
+        for appiter in self.apps.iter() {
+            let processid = appiter.processid();
+            let has_pending_op = appiter.enter(|app, _| app.op.is_some());
+            if has_pending_op {
+                self.processid.set(processid);
+                match self.run() {
+                    Ok(()) => break,
+                    Err(e) => {
+                        self.processid.clear();
+                        self.current_op.clear();
+                        let _ = self.apps.enter(processid, |app, kernel_data| {
+                            app.op.clear();
+                            kernel_data
+                                .schedule_upcall(
+                                    upcalls::DONE,
+                                    (errorcode::into_statuscode(Err(e)), 0, 0),
+                                )
+                                .ok();
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, A: hil::time::Alarm<'a>> SDCardClient for FatFsDriver<'a, A> {
+    fn card_detection_changed(&self, _installed: bool) {
+        // Whatever card is in the slot now has to be mounted again.
+        self.volume.map(|volume| volume.unmount());
+        self.owners.iter().for_each(|owner| owner.clear());
+    }
+
+    fn init_done(&self, _block_size: u32, _total_size: u64) {
+        if self.current_op.contains(&UserSpaceOp::Mount) {
+            if let Err(e) = self.run() {
+                self.complete(Err(e));
+            }
+        }
+    }
+
+    fn read_done(&self, data: &'static mut [u8], _len: usize) {
+        self.transfer_done(Ok(()), data);
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8]) {
+        self.transfer_done(Ok(()), buffer);
+    }
+
+    fn error(&self, _error: u32) {
+        match self.sdcard.take_failed_buffer() {
+            Some(buffer) => self.transfer_done(Err(ErrorCode::FAIL), buffer),
+            // Initialization failed, no transfer was running.
+            None => {
+                if self.processid.is_some() {
+                    self.complete(Err(ErrorCode::FAIL));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for FatFsDriver<'a, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let op = match command_num {
+            0 => return CommandReturn::success(),
+
+            1 => Ok(UserSpaceOp::Mount),
+
+            2 => {
+                let mode = match data1 {
+                    0 => Ok(OpenMode::Read),
+                    1 => Ok(OpenMode::Write),
+                    2 => Ok(OpenMode::Create),
+                    _ => Err(ErrorCode::INVAL),
+                };
+                mode.and_then(|mode| {
+                    self.storage_id(processid, data2, mode != OpenMode::Read)
+                        .map(|storage_id| UserSpaceOp::Open { mode, storage_id })
+                })
+            }
+
+            3 | 4 if !self.is_owner(data1, processid) => Err(ErrorCode::INVAL),
+            3 => Ok(UserSpaceOp::Read {
+                handle: data1,
+                len: data2,
+            }),
+            4 => Ok(UserSpaceOp::Write {
+                handle: data1,
+                len: data2,
+            }),
+
+            5 | 6 | 8 if !self.is_owner(data1, processid) => {
+                return CommandReturn::failure(ErrorCode::INVAL);
+            }
+            5 => {
+                let position = u32::try_from(data2).map_err(|_| ErrorCode::INVAL);
+                return CommandReturn::from(self.volume.map_or(Err(ErrorCode::FAIL), |volume| {
+                    position.and_then(|position| volume.seek(data1, position))
+                }));
+            }
+            6 => {
+                let result = self
+                    .volume
+                    .map_or(Err(ErrorCode::FAIL), |volume| volume.close(data1));
+                if result.is_ok() {
+                    self.owners[data1].clear();
+                }
+                return CommandReturn::from(result);
+            }
+            8 => {
+                return match self
+                    .volume
+                    .map_or(Err(ErrorCode::FAIL), |volume| volume.file_info(data1))
+                {
+                    Ok((size, position)) => CommandReturn::success_u32_u32(size, position),
+                    Err(e) => CommandReturn::failure(e),
+                };
+            }
+
+            7 => self
+                .storage_id(processid, data1, false)
+                .map(|storage_id| UserSpaceOp::List {
+                    storage_id,
+                    index: data2,
+                }),
+
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+        let op = match op {
+            Ok(op) => op,
+            Err(e) => return CommandReturn::failure(e),
+        };
+
+        let queued = self
+            .apps
+            .enter(processid, |app, _| {
+                if app.op.is_some() {
+                    // Only one operation per app can be pending.
+                    Err(ErrorCode::BUSY)
+                } else {
+                    app.op.set(op);
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        if let Err(e) = queued {
+            return CommandReturn::failure(e);
+        }
+
+        if self.processid.is_none() {
+            // Nothing is using the filesystem, so start this operation now.
+            self.processid.set(processid);
+            if let Err(e) = self.run() {
+                self.processid.clear();
+                self.current_op.clear();
+                let _ = self.apps.enter(processid, |app, _| app.op.clear());
+                self.check_queue();
+                return CommandReturn::failure(e);
+            }
+        }
+        CommandReturn::success()
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
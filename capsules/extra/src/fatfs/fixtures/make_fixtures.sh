@@ -0,0 +1,53 @@
+#!/usr/bin/env bash
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2023.
+
+# Creates the FAT images the fatfs tests read, with the tools a PC would use:
+# mkfs.fat (dosfstools) formats them and mtools writes the files, including
+# files with long names. The images are stored sparse, as the LBA (u32, little
+# endian) of each sector that is not all zeros followed by its 512 bytes.
+#
+# Requires mkfs.fat, mtools and python3. Run from this directory.
+
+set -e
+
+WORK=$(mktemp -d)
+trap 'rm -rf "$WORK"' EXIT
+
+# Fixed volume serial numbers and timestamps, so the images only change
+# when the tools do.
+export SOURCE_DATE_EPOCH=1696161600
+export MTOOLS_SKIP_CHECK=1
+
+printf 'Hello from a PC!\n' > "$WORK/hello"
+printf 'This file has a long name.\n' > "$WORK/long"
+python3 -c 'import sys; sys.stdout.buffer.write(bytes((i * 7 + i // 256) & 0xFF for i in range(1500)))' > "$WORK/data"
+touch -d "@$SOURCE_DATE_EPOCH" "$WORK/hello" "$WORK/long" "$WORK/data"
+
+# FAT16, 4 MiB, one sector per cluster.
+mkfs.fat -F 16 -s 1 -n TOCK -i 1234ABCD -C "$WORK/fat16.img" 4096
+mmd -i "$WORK/fat16.img" ::0000002A
+mcopy -m -i "$WORK/fat16.img" "$WORK/hello" ::0000002A/HELLO.TXT
+mcopy -m -i "$WORK/fat16.img" "$WORK/long" "::0000002A/A long file name.txt"
+mcopy -m -i "$WORK/fat16.img" "$WORK/data" "::0000002A/Multi cluster data.bin"
+
+# FAT32, 34000 KiB, one sector per cluster. The app directory is created after
+# a file with a long name in the root directory.
+mkfs.fat -F 32 -s 1 -n TOCK -i 1234ABCD -C "$WORK/fat32.img" 34000
+mcopy -m -i "$WORK/fat32.img" "$WORK/long" "::Notes from the PC.txt"
+mmd -i "$WORK/fat32.img" ::0000002A
+mcopy -m -i "$WORK/fat32.img" "$WORK/data" "::0000002A/Multi cluster data.bin"
+
+for fs in fat16 fat32; do
+    python3 - "$WORK/$fs.img" "$fs.sparse" <<'PY'
+import struct, sys
+image = open(sys.argv[1], "rb").read()
+with open(sys.argv[2], "wb") as out:
+    for lba in range(len(image) // 512):
+        sector = image[lba * 512:(lba + 1) * 512]
+        if any(sector):
+            out.write(struct.pack("<I", lba) + sector)
+PY
+done
@@ -0,0 +1,36 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! FAT16 and FAT32 filesystem on an SD card.
+//!
+//! Apps that log to an SD card can use this filesystem instead of raw block
+//! reads and writes, so the card can also be read on a PC.
+//!
+//! ```text
+//! +===============+
+//! ||  Userspace  ||
+//! +===============+
+//!
+//! -----Syscall Interface-----
+//!
+//! +-------------------------+
+//! |  FatFsDriver (driver)   |     +--------------------------+
+//! |                         | --- |  Volume (volume)         |
+//! +-------------------------+     |  FAT logic, no I/O       |
+//!                                 +--------------------------+
+//!    SDCard read_blocks / write_blocks
+//!
+//! +-------------------------+
+//! |  SDCard                 |
+//! +-------------------------+
+//! ```
+//!
+//! The FAT logic in [`volume`] does not access the SD card itself, so it is
+//! tested on the host against disk images.
+
+pub mod driver;
+pub mod volume;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,484 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Tests of the FAT logic against disk images in memory.
+//!
+//! Most images are built by the tests. The images in `fixtures/` have the
+//! layout mkfs.fat and mtools give a volume on a PC, including files with long
+//! names; `fixtures/make_fixtures.sh` regenerates them with those tools.
+
+extern crate std;
+
+use std::cell::RefCell;
+use std::format;
+use std::string::String;
+use std::vec;
+use std::vec::Vec;
+
+use kernel::ErrorCode;
+
+use super::volume::{FatType, FileBuffer, OpenMode, Request, Volume, SECTOR_SIZE};
+
+const STORAGE_ID: u32 = 0x2A;
+
+/// A disk image that services the sector requests of a volume.
+struct Disk {
+    image: Vec<u8>,
+    volume: Volume,
+    buf: [u8; SECTOR_SIZE],
+}
+
+struct Data(RefCell<Vec<u8>>);
+
+impl Data {
+    fn new(data: &[u8]) -> Data {
+        Data(RefCell::new(data.to_vec()))
+    }
+
+    fn zeroed(len: usize) -> Data {
+        Data(RefCell::new(vec![0; len]))
+    }
+
+    fn get(&self, len: usize) -> Vec<u8> {
+        self.0.borrow()[..len].to_vec()
+    }
+}
+
+impl FileBuffer for Data {
+    fn copy_in(&self, offset: usize, data: &[u8]) {
+        self.0.borrow_mut()[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    fn copy_out(&self, offset: usize, data: &mut [u8]) {
+        data.copy_from_slice(&self.0.borrow()[offset..offset + data.len()]);
+    }
+}
+
+impl Disk {
+    fn new(image: Vec<u8>) -> Disk {
+        Disk {
+            image,
+            volume: Volume::new(),
+            buf: [0; SECTOR_SIZE],
+        }
+    }
+
+    fn run(&mut self, mut request: Request, data: &Data) -> Result<(usize, usize), ErrorCode> {
+        loop {
+            let sector = match request {
+                Request::Read(lba) => lba as usize * SECTOR_SIZE,
+                Request::Write(lba) => lba as usize * SECTOR_SIZE,
+                Request::Done(result) => return result,
+            };
+            let sector = &mut self.image[sector..sector + SECTOR_SIZE];
+            match request {
+                Request::Read(_) => self.buf.copy_from_slice(sector),
+                _ => sector.copy_from_slice(&self.buf),
+            }
+            request = self.volume.io_done(Ok(()), &mut self.buf, data);
+        }
+    }
+
+    fn mount(&mut self) -> Result<(usize, usize), ErrorCode> {
+        let request = self.volume.mount(&mut self.buf);
+        self.run(request, &Data::zeroed(0))
+    }
+
+    fn open(&mut self, name: &str, mode: OpenMode) -> Result<(usize, usize), ErrorCode> {
+        let request = self
+            .volume
+            .open(name.as_bytes(), STORAGE_ID, mode, &mut self.buf);
+        self.run(request, &Data::zeroed(0))
+    }
+
+    fn write(&mut self, handle: usize, data: &[u8]) -> Result<(usize, usize), ErrorCode> {
+        let data = Data::new(data);
+        let len = data.0.borrow().len();
+        let request = self.volume.write(handle, len, &mut self.buf, &data);
+        self.run(request, &data)
+    }
+
+    fn read(&mut self, handle: usize, len: usize) -> Result<Vec<u8>, ErrorCode> {
+        let data = Data::zeroed(len);
+        let request = self.volume.read(handle, len, &mut self.buf, &data);
+        let (read, _) = self.run(request, &data)?;
+        Ok(data.get(read))
+    }
+
+    fn list(&mut self, index: usize) -> Result<(String, usize), ErrorCode> {
+        let data = Data::zeroed(12);
+        let request = self.volume.list(STORAGE_ID, index, &mut self.buf, &data);
+        let (len, size) = self.run(request, &data)?;
+        Ok((String::from_utf8(data.get(len)).unwrap(), size))
+    }
+
+    fn list_all(&mut self) -> Vec<(String, usize)> {
+        (0..)
+            .map_while(|index| match self.list(index) {
+                Ok(file) => Some(file),
+                Err(ErrorCode::NOSUPPORT) => None,
+                Err(e) => panic!("list failed: {:?}", e),
+            })
+            .collect()
+    }
+
+    /// Remount from the image, so nothing cached in the volume is used.
+    fn remount(&mut self) {
+        self.volume.unmount();
+        self.mount().unwrap();
+    }
+}
+
+fn set_u16(image: &mut [u8], offset: usize, value: u16) {
+    image[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn set_u32(image: &mut [u8], offset: usize, value: u32) {
+    image[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Boot sector fields shared by FAT16 and FAT32.
+fn boot_sector(image: &mut [u8], base: usize, total: u32, reserved: u16, root_entries: u16) {
+    let boot = &mut image[base..base + SECTOR_SIZE];
+    boot[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    boot[3..11].copy_from_slice(b"MSWIN4.1");
+    set_u16(boot, 11, SECTOR_SIZE as u16);
+    boot[13] = 1;
+    set_u16(boot, 14, reserved);
+    boot[16] = 2;
+    set_u16(boot, 17, root_entries);
+    set_u32(boot, 32, total);
+    boot[21] = 0xF8;
+    boot[510] = 0x55;
+    boot[511] = 0xAA;
+}
+
+/// A FAT16 volume of `sectors` sectors with one sector per cluster.
+fn format_fat16(sectors: u32) -> Vec<u8> {
+    let mut image = vec![0; sectors as usize * SECTOR_SIZE];
+    let fat_size = (sectors * 2 + SECTOR_SIZE as u32 - 1) / SECTOR_SIZE as u32;
+    boot_sector(&mut image, 0, sectors, 4, 512);
+    set_u16(&mut image, 22, fat_size as u16);
+    for fat in 0..2 {
+        let start = (4 + fat * fat_size) as usize * SECTOR_SIZE;
+        set_u16(&mut image, start, 0xFFF8);
+        set_u16(&mut image, start + 2, 0xFFFF);
+    }
+    image
+}
+
+/// A FAT32 volume of `sectors` sectors with one sector per cluster,
+/// optionally in an MBR partition starting at LBA 63.
+fn format_fat32(sectors: u32, partitioned: bool) -> Vec<u8> {
+    let base = if partitioned { 63 } else { 0 };
+    let mut image = vec![0; (base + sectors) as usize * SECTOR_SIZE];
+    if partitioned {
+        let entry = 446;
+        image[entry + 4] = 0x0C;
+        set_u32(&mut image, entry + 8, base);
+        set_u32(&mut image, entry + 12, sectors);
+        image[510] = 0x55;
+        image[511] = 0xAA;
+    }
+
+    let offset = base as usize * SECTOR_SIZE;
+    let fat_size = (sectors * 4 + SECTOR_SIZE as u32 - 1) / SECTOR_SIZE as u32;
+    boot_sector(&mut image, offset, sectors, 32, 0);
+    set_u32(&mut image, offset + 36, fat_size);
+    // Root directory in cluster 2.
+    set_u32(&mut image, offset + 44, 2);
+    for fat in 0..2 {
+        let start = offset + (32 + fat * fat_size) as usize * SECTOR_SIZE;
+        set_u32(&mut image, start, 0x0FFF_FFF8);
+        set_u32(&mut image, start + 4, 0x0FFF_FFFF);
+        set_u32(&mut image, start + 8, 0x0FFF_FFFF);
+    }
+    image
+}
+
+/// Sector offsets of the FATs and of the root directory of a FAT16 image
+/// made by `format_fat16`.
+fn fat16_layout(sectors: u32) -> (usize, usize, usize) {
+    let fat_size = (sectors as usize * 2 + SECTOR_SIZE - 1) / SECTOR_SIZE;
+    (4, 4 + fat_size, 4 + 2 * fat_size)
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+/// Expand an image made by `fixtures/make_fixtures.sh` to `sectors` sectors.
+fn fixture(sparse: &[u8], sectors: usize) -> Vec<u8> {
+    let mut image = vec![0; sectors * SECTOR_SIZE];
+    for record in sparse.chunks(4 + SECTOR_SIZE) {
+        let lba = u32::from_le_bytes([record[0], record[1], record[2], record[3]]) as usize;
+        image[lba * SECTOR_SIZE..][..SECTOR_SIZE].copy_from_slice(&record[4..]);
+    }
+    image
+}
+
+#[test]
+fn fat16_create_write_read_list() {
+    let mut disk = Disk::new(format_fat16(8192));
+    assert_eq!(disk.mount(), Ok((16, 512)));
+    assert_eq!(disk.volume.fat_type(), Some(FatType::Fat16));
+
+    let (handle, size) = disk.open("log.txt", OpenMode::Create).unwrap();
+    assert_eq!(size, 0);
+    assert_eq!(disk.write(handle, b"hello, world"), Ok((12, 12)));
+    disk.volume.close(handle).unwrap();
+
+    disk.remount();
+    assert_eq!(disk.list_all(), [("LOG.TXT".into(), 12)]);
+    let (handle, size) = disk.open("LOG.TXT", OpenMode::Read).unwrap();
+    assert_eq!(size, 12);
+    assert_eq!(disk.read(handle, 100).unwrap(), b"hello, world");
+    assert_eq!(disk.read(handle, 100).unwrap(), b"");
+    assert_eq!(disk.write(handle, b"x"), Err(ErrorCode::INVAL));
+}
+
+#[test]
+fn fat32_partition_multi_cluster_seek() {
+    let mut disk = Disk::new(format_fat32(70000, true));
+    assert_eq!(disk.mount(), Ok((32, 512)));
+    assert_eq!(disk.volume.fat_type(), Some(FatType::Fat32));
+
+    let contents = pattern(3000);
+    let (handle, _) = disk.open("DATA.BIN", OpenMode::Create).unwrap();
+    assert_eq!(disk.write(handle, &contents), Ok((3000, 3000)));
+
+    // Overwrite across a cluster boundary.
+    disk.volume.seek(handle, 500).unwrap();
+    assert_eq!(disk.write(handle, &[0xAA; 20]), Ok((20, 520)));
+    assert_eq!(disk.volume.file_info(handle), Ok((3000, 520)));
+    assert_eq!(disk.volume.seek(handle, 3001), Err(ErrorCode::INVAL));
+    disk.volume.close(handle).unwrap();
+
+    disk.remount();
+    let mut expected = contents;
+    expected[500..520].fill(0xAA);
+    let (handle, size) = disk.open("data.bin", OpenMode::Write).unwrap();
+    assert_eq!(size, 3000);
+    assert_eq!(disk.read(handle, 1000).unwrap(), &expected[..1000]);
+    assert_eq!(disk.read(handle, 4000).unwrap(), &expected[1000..]);
+    disk.volume.seek(handle, 2990).unwrap();
+    assert_eq!(disk.read(handle, 100).unwrap(), &expected[2990..]);
+}
+
+#[test]
+fn fat_copies_and_chain() {
+    let sectors = 8192;
+    let mut disk = Disk::new(format_fat16(sectors));
+    disk.mount().unwrap();
+    let (handle, _) = disk.open("CHAIN", OpenMode::Create).unwrap();
+    disk.write(handle, &pattern(5 * 512 + 1)).unwrap();
+
+    let (fat1, fat2, _) = fat16_layout(sectors);
+    let fat_size = (fat2 - fat1) * SECTOR_SIZE;
+    let image = &disk.image;
+    let first = &image[fat1 * SECTOR_SIZE..][..fat_size];
+    assert_eq!(first, &image[fat2 * SECTOR_SIZE..][..fat_size]);
+
+    // Cluster 2 is the app directory, the file follows it.
+    let entry = |cluster: usize| u16::from_le_bytes([first[cluster * 2], first[cluster * 2 + 1]]);
+    assert_eq!(entry(2), 0xFFFF);
+    let mut cluster = 3;
+    let mut length = 1;
+    while entry(cluster) != 0xFFFF {
+        assert_eq!(entry(cluster) as usize, cluster + 1);
+        cluster = entry(cluster) as usize;
+        length += 1;
+    }
+    assert_eq!(length, 6);
+}
+
+#[test]
+fn skips_long_names_and_deleted_entries() {
+    let sectors = 8192;
+    let mut image = format_fat16(sectors);
+    let (fat1, fat2, root) = fat16_layout(sectors);
+    let data = root + 32;
+
+    let entry = |name: &[u8; 11], attr: u8, cluster: u16, size: u32| {
+        let mut entry = [0; 32];
+        entry[..11].copy_from_slice(name);
+        entry[11] = attr;
+        entry[26..28].copy_from_slice(&cluster.to_le_bytes());
+        entry[28..32].copy_from_slice(&size.to_le_bytes());
+        entry
+    };
+    let mut long_name = [0xFF; 32];
+    long_name[0] = 0x41;
+    long_name[11] = 0x0F;
+    long_name[12] = 0;
+
+    // Directory 0000002A in cluster 2 and README.TXT in cluster 3, as a PC
+    // would write them.
+    let root_entries = [
+        entry(b"SYSTEM~1   ", 0x16, 0, 0),
+        entry(b"0000002A   ", 0x10, 2, 0),
+    ];
+    let mut deleted = entry(b"OLD     TXT", 0x20, 0, 10);
+    deleted[0] = 0xE5;
+    let dir_entries = [
+        entry(b".          ", 0x10, 2, 0),
+        entry(b"..         ", 0x10, 0, 0),
+        deleted,
+        long_name,
+        entry(b"README  TXT", 0x20, 3, 5),
+    ];
+    for (i, e) in root_entries.iter().enumerate() {
+        image[root * SECTOR_SIZE + i * 32..][..32].copy_from_slice(e);
+    }
+    for (i, e) in dir_entries.iter().enumerate() {
+        image[data * SECTOR_SIZE + i * 32..][..32].copy_from_slice(e);
+    }
+    image[(data + 1) * SECTOR_SIZE..][..5].copy_from_slice(b"hello");
+    for fat in [fat1, fat2] {
+        set_u16(&mut image, fat * SECTOR_SIZE + 4, 0xFFFF);
+        set_u16(&mut image, fat * SECTOR_SIZE + 6, 0xFFFF);
+    }
+
+    let mut disk = Disk::new(image);
+    disk.mount().unwrap();
+    assert_eq!(disk.list_all(), [("README.TXT".into(), 5)]);
+    let (handle, _) = disk.open("readme.txt", OpenMode::Read).unwrap();
+    assert_eq!(disk.read(handle, 5).unwrap(), b"hello");
+
+    // New files reuse the slot of the deleted entry.
+    let (handle, _) = disk.open("NEW.TXT", OpenMode::Create).unwrap();
+    disk.write(handle, b"new").unwrap();
+    disk.remount();
+    assert_eq!(
+        disk.list_all(),
+        [("NEW.TXT".into(), 3), ("README.TXT".into(), 5)]
+    );
+}
+
+#[test]
+fn directory_grows() {
+    let mut disk = Disk::new(format_fat16(8192));
+    disk.mount().unwrap();
+    // 16 entries fit in a cluster, so this needs three clusters.
+    for i in 0..40 {
+        let name = format!("F{}.DAT", i);
+        let (handle, _) = disk.open(&name, OpenMode::Create).unwrap();
+        disk.write(handle, &[i as u8; 3]).unwrap();
+        disk.volume.close(handle).unwrap();
+    }
+
+    disk.remount();
+    let files = disk.list_all();
+    assert_eq!(files.len(), 40);
+    for (i, (name, size)) in files.iter().enumerate() {
+        assert_eq!(*name, format!("F{}.DAT", i));
+        assert_eq!(*size, 3);
+    }
+    let (handle, _) = disk.open("F39.DAT", OpenMode::Read).unwrap();
+    assert_eq!(disk.read(handle, 3).unwrap(), [39; 3]);
+}
+
+#[test]
+fn errors() {
+    let mut disk = Disk::new(format_fat16(8192));
+    assert_eq!(
+        disk.open("A.TXT", OpenMode::Create),
+        Err(ErrorCode::RESERVE)
+    );
+    disk.mount().unwrap();
+    assert_eq!(
+        disk.open("A.TXT", OpenMode::Read),
+        Err(ErrorCode::NOSUPPORT)
+    );
+    assert_eq!(
+        disk.open("TOOLONGNAME", OpenMode::Create),
+        Err(ErrorCode::INVAL)
+    );
+    assert_eq!(disk.open("A.TEXT", OpenMode::Create), Err(ErrorCode::INVAL));
+    assert_eq!(disk.list(0), Err(ErrorCode::NOSUPPORT));
+
+    let (handle, _) = disk.open("A.TXT", OpenMode::Create).unwrap();
+    assert_eq!(disk.open("A.TXT", OpenMode::Read), Err(ErrorCode::BUSY));
+    disk.volume.close(handle).unwrap();
+    assert_eq!(disk.volume.close(handle), Err(ErrorCode::INVAL));
+
+    // Too few clusters for FAT16.
+    let mut disk = Disk::new(format_fat16(2048));
+    assert_eq!(disk.mount(), Err(ErrorCode::NOSUPPORT));
+}
+
+#[test]
+fn reads_and_writes_pc_formatted_fat16() {
+    let mut disk = Disk::new(fixture(include_bytes!("fixtures/fat16.sparse"), 8192));
+    assert_eq!(disk.mount(), Ok((16, 512)));
+    assert_eq!(disk.volume.fat_type(), Some(FatType::Fat16));
+
+    // Files with long names are listed and opened by their short names.
+    assert_eq!(
+        disk.list_all(),
+        [
+            ("HELLO.TXT".into(), 17),
+            ("ALONGF~1.TXT".into(), 27),
+            ("MULTIC~1.BIN".into(), 1500)
+        ]
+    );
+    let (handle, _) = disk.open("HELLO.TXT", OpenMode::Read).unwrap();
+    assert_eq!(disk.read(handle, 100).unwrap(), b"Hello from a PC!\n");
+    let (handle, _) = disk.open("alongf~1.txt", OpenMode::Read).unwrap();
+    assert_eq!(
+        disk.read(handle, 100).unwrap(),
+        b"This file has a long name.\n"
+    );
+    let (handle, _) = disk.open("MULTIC~1.BIN", OpenMode::Read).unwrap();
+    assert_eq!(disk.read(handle, 2000).unwrap(), pattern(1500));
+
+    // The app directory is cluster 2, the first sector after the root
+    // directory. A new file goes after the nine entries mtools wrote and
+    // leaves the long name entries alone.
+    let directory = (1 + 2 * 32 + 32) * SECTOR_SIZE;
+    let entries = disk.image[directory..][..9 * 32].to_vec();
+    let (handle, _) = disk.open("NEW.TXT", OpenMode::Create).unwrap();
+    disk.write(handle, &pattern(700)).unwrap();
+    disk.volume.close(handle).unwrap();
+    assert_eq!(disk.image[directory..][..9 * 32], entries);
+    assert_eq!(&disk.image[directory + 9 * 32..][..11], b"NEW     TXT");
+
+    disk.remount();
+    assert_eq!(disk.list_all().len(), 4);
+    let (handle, _) = disk.open("NEW.TXT", OpenMode::Read).unwrap();
+    assert_eq!(disk.read(handle, 1000).unwrap(), pattern(700));
+    let (handle, _) = disk.open("MULTIC~1.BIN", OpenMode::Read).unwrap();
+    assert_eq!(disk.read(handle, 2000).unwrap(), pattern(1500));
+}
+
+#[test]
+fn reads_and_writes_pc_formatted_fat32() {
+    let mut disk = Disk::new(fixture(include_bytes!("fixtures/fat32.sparse"), 68000));
+    assert_eq!(disk.mount(), Ok((32, 512)));
+    assert_eq!(disk.volume.fat_type(), Some(FatType::Fat32));
+
+    // The root directory holds the volume label and a file with a long name
+    // before the app directory.
+    assert_eq!(disk.list_all(), [("MULTIC~1.BIN".into(), 1500)]);
+    let (handle, _) = disk.open("MULTIC~1.BIN", OpenMode::Read).unwrap();
+    assert_eq!(disk.read(handle, 2000).unwrap(), pattern(1500));
+    disk.volume.close(handle).unwrap();
+
+    // The root directory is cluster 2, the first sector after the two FATs
+    // of 523 sectors each.
+    let root = (32 + 2 * 523) * SECTOR_SIZE;
+    let root_entries = disk.image[root..][..SECTOR_SIZE].to_vec();
+    let (handle, _) = disk.open("LOG.TXT", OpenMode::Create).unwrap();
+    disk.write(handle, b"written by Tock").unwrap();
+    disk.volume.close(handle).unwrap();
+    assert_eq!(disk.image[root..][..SECTOR_SIZE], root_entries);
+
+    disk.remount();
+    assert_eq!(
+        disk.list_all(),
+        [("MULTIC~1.BIN".into(), 1500), ("LOG.TXT".into(), 15)]
+    );
+    let (handle, _) = disk.open("LOG.TXT", OpenMode::Read).unwrap();
+    assert_eq!(disk.read(handle, 100).unwrap(), b"written by Tock");
+}
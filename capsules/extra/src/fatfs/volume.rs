@@ -0,0 +1,1469 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! FAT16 and FAT32 volume logic, independent of the block device.
+//!
+//! [`Volume`] never accesses the block device itself. Starting an operation
+//! or finishing a sector transfer returns a [`Request`]: either a sector the
+//! caller must read into or write from the sector buffer before calling
+//! [`Volume::io_done`], or the result of the operation. This lets the same
+//! code run on top of the asynchronous SD card driver and against disk images
+//! in host tests.
+//!
+//! Each operation only holds one sector in memory, so every step of an
+//! operation works on the sector in the buffer and keeps whatever it needs
+//! from it in the operation state before requesting the next sector.
+//!
+//! Files are stored in one directory per storage identifier, which is a
+//! subdirectory of the root directory named after the identifier as eight
+//! hex digits (for example `0000002A`). Only short (8.3) file names are
+//! supported; long file name entries written by other systems are skipped.
+//! The FAT32 FSInfo free cluster count is not updated, which is allowed as it
+//! is only a hint.
+
+use core::cmp;
+
+use kernel::ErrorCode;
+
+/// Size of a sector. Only volumes with 512 byte sectors are supported.
+pub const SECTOR_SIZE: usize = 512;
+
+/// How many files can be open at the same time.
+pub const MAX_OPEN_FILES: usize = 4;
+
+const DIR_ENTRY_SIZE: usize = 32;
+const DIR_ENTRIES_PER_SECTOR: usize = SECTOR_SIZE / DIR_ENTRY_SIZE;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+/// Entry name byte marking a deleted entry.
+const ENTRY_DELETED: u8 = 0xE5;
+/// Entry name byte marking the end of the directory.
+const ENTRY_END: u8 = 0x00;
+
+/// 1980-01-01, the earliest date FAT can store. Used for all timestamps as
+/// the kernel has no notion of the wall clock time.
+const FAT_DATE: u16 = (1 << 5) | 1;
+
+/// MBR partition types of FAT16 and FAT32 partitions.
+const FAT_PARTITION_TYPES: [u8; 5] = [0x04, 0x06, 0x0B, 0x0C, 0x0E];
+
+/// What the caller of a [`Volume`] must do next.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Request {
+    /// Read the sector at this LBA into the sector buffer, then call
+    /// [`Volume::io_done`].
+    Read(u32),
+    /// Write the sector buffer to the sector at this LBA, then call
+    /// [`Volume::io_done`].
+    Write(u32),
+    /// The operation finished with two result values, or failed.
+    Done(Result<(usize, usize), ErrorCode>),
+}
+
+/// The type of a mounted volume.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FatType {
+    Fat16,
+    Fat32,
+}
+
+/// How to open a file.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OpenMode {
+    /// Open an existing file for reading.
+    Read,
+    /// Open an existing file for reading and writing.
+    Write,
+    /// Open a file for reading and writing, creating it (and its directory)
+    /// if it does not exist.
+    Create,
+}
+
+/// Memory that file data is read into and written from.
+///
+/// The methods take `&self` so process buffers can be used directly.
+/// Implementations ignore the parts of a copy that are out of bounds.
+pub trait FileBuffer {
+    /// Copy `data` into the buffer, starting at `offset`.
+    fn copy_in(&self, offset: usize, data: &[u8]);
+
+    /// Fill `data` from the buffer, starting at `offset`.
+    fn copy_out(&self, offset: usize, data: &mut [u8]);
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u32 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]]) as u32
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+fn write_u16(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+}
+
+fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Convert `name` in the `NAME.EXT` form to a padded, upper case 8.3 name.
+pub fn short_name(name: &[u8]) -> Result<[u8; 11], ErrorCode> {
+    let (base, extension) = match name.iter().position(|&c| c == b'.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, &[][..]),
+    };
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+        return Err(ErrorCode::INVAL);
+    }
+
+    let mut short = [b' '; 11];
+    let chars = base.iter().zip(0..8).chain(extension.iter().zip(8..11));
+    for (&c, i) in chars {
+        short[i] = match c {
+            b'a'..=b'z' => c.to_ascii_uppercase(),
+            b'A'..=b'Z' | b'0'..=b'9' => c,
+            b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'(' | b')' | b'-' | b'@' | b'^' | b'_'
+            | b'`' | b'{' | b'}' | b'~' => c,
+            _ => return Err(ErrorCode::INVAL),
+        };
+    }
+    Ok(short)
+}
+
+/// Convert a padded 8.3 name to the `NAME.EXT` form. Returns the buffer and
+/// the length of the name in it.
+fn display_name(short: &[u8]) -> ([u8; 12], usize) {
+    let mut name = [0; 12];
+    let mut len = 0;
+    for &c in short[..8].iter().filter(|&&c| c != b' ') {
+        name[len] = c;
+        len += 1;
+    }
+    if short[8] != b' ' {
+        name[len] = b'.';
+        len += 1;
+        for &c in short[8..11].iter().filter(|&&c| c != b' ') {
+            name[len] = c;
+            len += 1;
+        }
+    }
+    (name, len)
+}
+
+/// The name of the directory holding the files of `storage_id`.
+fn directory_name(storage_id: u32) -> [u8; 11] {
+    let mut name = [b' '; 11];
+    for (i, c) in name[..8].iter_mut().enumerate() {
+        let digit = (storage_id >> (28 - 4 * i)) & 0xF;
+        *c = b"0123456789ABCDEF"[digit as usize];
+    }
+    name
+}
+
+/// A new directory entry named `name`.
+fn new_entry(name: &[u8; 11], attributes: u8, cluster: u32) -> [u8; DIR_ENTRY_SIZE] {
+    let mut entry = [0; DIR_ENTRY_SIZE];
+    entry[..11].copy_from_slice(name);
+    entry[11] = attributes;
+    // Creation, last access and last write dates.
+    for offset in [16, 18, 24] {
+        write_u16(&mut entry, offset, FAT_DATE as u32);
+    }
+    set_entry_cluster(&mut entry, cluster);
+    entry
+}
+
+fn entry_cluster(entry: &[u8]) -> u32 {
+    (read_u16(entry, 20) << 16) | read_u16(entry, 26)
+}
+
+fn set_entry_cluster(entry: &mut [u8], cluster: u32) {
+    write_u16(entry, 20, cluster >> 16);
+    write_u16(entry, 26, cluster);
+}
+
+/// Layout of a mounted volume. All sector numbers are absolute LBAs.
+#[derive(Copy, Clone, Debug)]
+struct Geometry {
+    fat_type: FatType,
+    sectors_per_cluster: u32,
+    fat_start: u32,
+    fat_size: u32,
+    num_fats: u32,
+    /// First sector of the FAT16 root directory.
+    root_dir_start: u32,
+    /// Number of sectors of the FAT16 root directory.
+    root_dir_sectors: u32,
+    /// First cluster of the FAT32 root directory.
+    root_cluster: u32,
+    /// First sector of cluster 2.
+    data_start: u32,
+    cluster_count: u32,
+}
+
+impl Geometry {
+    /// Parse the boot sector of a volume starting at `base`.
+    fn parse(sector: &[u8], base: u32) -> Result<Geometry, ErrorCode> {
+        if sector[510..512] != [0x55, 0xAA] || (sector[0] != 0xEB && sector[0] != 0xE9) {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+
+        let bytes_per_sector = read_u16(sector, 11);
+        let sectors_per_cluster = sector[13] as u32;
+        let reserved_sectors = read_u16(sector, 14);
+        let num_fats = sector[16] as u32;
+        let root_entries = read_u16(sector, 17);
+        let total_sectors = match read_u16(sector, 19) {
+            0 => read_u32(sector, 32),
+            total => total,
+        };
+        let fat_size = match read_u16(sector, 22) {
+            0 => read_u32(sector, 36),
+            size => size,
+        };
+        if bytes_per_sector as usize != SECTOR_SIZE
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || num_fats == 0
+            || fat_size == 0
+        {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+
+        let root_dir_sectors =
+            (root_entries * DIR_ENTRY_SIZE as u32 + SECTOR_SIZE as u32 - 1) / SECTOR_SIZE as u32;
+        let data_offset = num_fats
+            .checked_mul(fat_size)
+            .and_then(|fats| fats.checked_add(reserved_sectors + root_dir_sectors))
+            .ok_or(ErrorCode::NOSUPPORT)?;
+        let cluster_count = total_sectors
+            .checked_sub(data_offset)
+            .ok_or(ErrorCode::NOSUPPORT)?
+            / sectors_per_cluster;
+
+        // The FAT type is determined by the number of clusters only. FAT12
+        // volumes are not supported.
+        let fat_type = match cluster_count {
+            0..=4084 => return Err(ErrorCode::NOSUPPORT),
+            4085..=65524 if root_entries != 0 => FatType::Fat16,
+            65525.. if root_entries == 0 => FatType::Fat32,
+            _ => return Err(ErrorCode::NOSUPPORT),
+        };
+
+        let geometry = Geometry {
+            fat_type,
+            sectors_per_cluster,
+            fat_start: base + reserved_sectors,
+            fat_size,
+            num_fats,
+            root_dir_start: base + reserved_sectors + num_fats * fat_size,
+            root_dir_sectors,
+            root_cluster: read_u32(sector, 44),
+            data_start: base + data_offset,
+            cluster_count,
+        };
+
+        let fat_entries = fat_size as u64 * SECTOR_SIZE as u64 / geometry.fat_entry_size() as u64;
+        if fat_entries < cluster_count as u64 + 2
+            || (fat_type == FatType::Fat32 && !geometry.is_cluster(geometry.root_cluster))
+        {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        Ok(geometry)
+    }
+
+    fn fat_entry_size(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+
+    fn cluster_bytes(&self) -> u32 {
+        self.sectors_per_cluster * SECTOR_SIZE as u32
+    }
+
+    fn cluster_lba(&self, cluster: u32) -> u32 {
+        self.data_start + (cluster - 2) * self.sectors_per_cluster
+    }
+
+    /// Returns `true` if `cluster` is a data cluster of the volume.
+    fn is_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.cluster_count
+    }
+
+    /// Returns `true` if the FAT entry `value` marks the end of a chain.
+    fn is_end_of_chain(&self, value: u32) -> bool {
+        match self.fat_type {
+            FatType::Fat16 => value >= 0xFFF8,
+            FatType::Fat32 => value >= 0x0FFF_FFF8,
+        }
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    /// The sector of the first FAT holding the entry of `cluster`, and the
+    /// offset of the entry in the sector.
+    fn fat_location(&self, cluster: u32) -> (u32, usize) {
+        let offset = cluster * self.fat_entry_size();
+        (
+            self.fat_start + offset / SECTOR_SIZE as u32,
+            offset as usize % SECTOR_SIZE,
+        )
+    }
+
+    fn fat_entry(&self, sector: &[u8], offset: usize) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => read_u16(sector, offset),
+            FatType::Fat32 => read_u32(sector, offset) & 0x0FFF_FFFF,
+        }
+    }
+
+    fn set_fat_entry(&self, sector: &mut [u8], offset: usize, value: u32) {
+        match self.fat_type {
+            FatType::Fat16 => write_u16(sector, offset, value),
+            // The upper four bits of FAT32 entries are reserved and must be
+            // preserved.
+            FatType::Fat32 => {
+                let reserved = read_u32(sector, offset) & 0xF000_0000;
+                write_u32(sector, offset, reserved | (value & 0x0FFF_FFFF));
+            }
+        }
+    }
+
+    fn root_dir(&self) -> DirPosition {
+        match self.fat_type {
+            FatType::Fat16 => DirPosition::Fixed(0),
+            FatType::Fat32 => DirPosition::Chain {
+                cluster: self.root_cluster,
+                sector: 0,
+            },
+        }
+    }
+
+    fn dir_lba(&self, position: DirPosition) -> u32 {
+        match position {
+            DirPosition::Fixed(sector) => self.root_dir_start + sector,
+            DirPosition::Chain { cluster, sector } => self.cluster_lba(cluster) + sector,
+        }
+    }
+}
+
+/// A position in a directory.
+#[derive(Copy, Clone, Debug)]
+enum DirPosition {
+    /// A sector of the fixed size FAT16 root directory.
+    Fixed(u32),
+    /// A sector of a cluster of a directory stored in clusters.
+    Chain { cluster: u32, sector: u32 },
+}
+
+/// What a directory scan is looking for.
+#[derive(Copy, Clone, Debug)]
+enum Target {
+    /// The entry with this 8.3 name.
+    Name([u8; 11]),
+    /// The nth file entry.
+    Index(usize),
+}
+
+/// Where a new entry can be added to a directory.
+#[derive(Copy, Clone, Debug)]
+struct FreeSlot {
+    /// The first unused entry found, as sector and offset.
+    entry: Option<(u32, usize)>,
+    /// The last cluster of the directory, if it is stored in clusters and can
+    /// be extended.
+    last_cluster: Option<u32>,
+}
+
+enum ScanResult {
+    /// The entry was found at this sector and offset. The sector is in the
+    /// sector buffer.
+    Found(u32, usize),
+    NotFound(FreeSlot),
+}
+
+/// State of a search through a directory.
+#[derive(Copy, Clone, Debug)]
+struct DirScan {
+    target: Target,
+    position: DirPosition,
+    files_seen: usize,
+    free: Option<(u32, usize)>,
+}
+
+impl DirScan {
+    fn new(position: DirPosition, target: Target) -> DirScan {
+        DirScan {
+            target,
+            position,
+            files_seen: 0,
+            free: None,
+        }
+    }
+}
+
+/// State of allocating a cluster and appending it to a chain.
+#[derive(Copy, Clone, Debug)]
+struct Alloc {
+    /// The cluster the new cluster is linked from, if any.
+    previous: Option<u32>,
+    step: AllocStep,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum AllocStep {
+    /// Check whether `cluster` is free.
+    Search { cluster: u32, checked: u32 },
+    /// Write the FAT sector marking `cluster` as allocated to FAT `copy`.
+    Mark { cluster: u32, copy: u32 },
+    /// Write the FAT sector linking to `cluster` to FAT `copy`.
+    Link { cluster: u32, copy: u32 },
+}
+
+impl Alloc {
+    fn new(previous: Option<u32>, start: u32) -> Alloc {
+        Alloc {
+            previous,
+            step: AllocStep::Search {
+                cluster: start,
+                checked: 0,
+            },
+        }
+    }
+}
+
+/// State of adding an entry to a directory.
+#[derive(Copy, Clone, Debug)]
+struct Insert {
+    entry: [u8; DIR_ENTRY_SIZE],
+    step: InsertStep,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum InsertStep {
+    /// The directory is full and is extended with a new cluster.
+    Extend(Alloc),
+    /// Zero the sectors of the new directory cluster, last sector first.
+    Zero { cluster: u32, sectors: u32 },
+    /// Write the entry to this sector and offset.
+    Write(u32, usize),
+    /// The entry was written to this sector and offset.
+    Written(u32, usize),
+}
+
+#[derive(Copy, Clone, Debug)]
+struct OpenFile {
+    /// Sector and offset of the directory entry of the file.
+    entry: (u32, usize),
+    first_cluster: u32,
+    size: u32,
+    position: u32,
+    writable: bool,
+    /// The size or first cluster changed and the directory entry must be
+    /// updated.
+    dirty: bool,
+    /// The most recently used cluster of the file, and its index in the
+    /// chain. Saves following the chain from the start on every access.
+    cluster: u32,
+    cluster_index: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum MountStep {
+    /// Read the first sector of the disk, which is either a boot sector or
+    /// an MBR.
+    FirstSector,
+    /// Read the boot sector of the partition starting at this LBA.
+    Partition(u32),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum OpenStep {
+    FindDir(DirScan),
+    /// Allocate the cluster of the missing directory.
+    CreateDir(FreeSlot, Alloc),
+    /// Zero the sectors of the directory cluster, last sector first, adding
+    /// the `.` and `..` entries to the first sector.
+    ZeroDir {
+        slot: FreeSlot,
+        cluster: u32,
+        sectors: u32,
+    },
+    InsertDir(u32, Insert),
+    FindFile(DirScan),
+    InsertFile(Insert),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum WriteStep {
+    Data,
+    Alloc(Alloc),
+    /// Update the size and first cluster in the directory entry.
+    UpdateEntry,
+    Finished,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum ListStep {
+    /// Find the directory, then the file at the index.
+    FindDir(DirScan, usize),
+    FindEntry(DirScan),
+}
+
+/// The arguments of an open operation.
+#[derive(Copy, Clone, Debug)]
+struct OpenRequest {
+    name: [u8; 11],
+    directory: [u8; 11],
+    mode: OpenMode,
+    handle: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Operation {
+    Mount(MountStep),
+    Open(OpenRequest, OpenStep),
+    Read {
+        handle: usize,
+        file: OpenFile,
+        remaining: usize,
+        done: usize,
+    },
+    Write {
+        handle: usize,
+        file: OpenFile,
+        remaining: usize,
+        done: usize,
+        step: WriteStep,
+    },
+    List(ListStep),
+}
+
+/// A FAT16 or FAT32 volume and its open files.
+pub struct Volume {
+    geometry: Option<Geometry>,
+    files: [Option<OpenFile>; MAX_OPEN_FILES],
+    operation: Option<Operation>,
+    /// The sector currently held in the sector buffer.
+    loaded: Option<u32>,
+    /// The sector being transferred.
+    transfer: Option<u32>,
+    /// Where to start looking for a free cluster.
+    next_free: u32,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Volume {
+    pub const fn new() -> Volume {
+        Volume {
+            geometry: None,
+            files: [None; MAX_OPEN_FILES],
+            operation: None,
+            loaded: None,
+            transfer: None,
+            next_free: 2,
+        }
+    }
+
+    /// The type of the mounted volume, if any.
+    pub fn fat_type(&self) -> Option<FatType> {
+        self.geometry.map(|geometry| geometry.fat_type)
+    }
+
+    /// Returns `true` if an operation is waiting for a sector transfer.
+    pub fn is_busy(&self) -> bool {
+        self.operation.is_some()
+    }
+
+    /// Returns `true` if the operation in progress writes to a file, so the
+    /// file buffer is only read from.
+    pub fn is_writing(&self) -> bool {
+        matches!(self.operation, Some(Operation::Write { .. }))
+    }
+
+    /// Forget the mounted volume and close all files. Any operation in
+    /// progress is abandoned.
+    pub fn unmount(&mut self) {
+        *self = Volume::new();
+    }
+
+    /// Mount the volume on a disk, which either starts with a FAT boot
+    /// sector or has an MBR with a FAT16 or FAT32 partition.
+    ///
+    /// Completes with the FAT width in bits (16 or 32) and the size of a
+    /// cluster in bytes. Fails with `NOSUPPORT` if no supported volume is
+    /// found.
+    pub fn mount(&mut self, buf: &mut [u8]) -> Request {
+        if self.operation.is_some() {
+            return Request::Done(Err(ErrorCode::BUSY));
+        }
+        self.unmount();
+        self.start(Operation::Mount(MountStep::FirstSector), buf, &NoData)
+    }
+
+    /// Open the file `name` in the directory of `storage_id`.
+    ///
+    /// Completes with the handle of the file and its size. Fails with
+    /// `NOSUPPORT` if the file does not exist and `mode` is not `Create`, and
+    /// with `BUSY` if the file is already open and either handle allows
+    /// writing.
+    pub fn open(
+        &mut self,
+        name: &[u8],
+        storage_id: u32,
+        mode: OpenMode,
+        buf: &mut [u8],
+    ) -> Request {
+        let name = match short_name(name) {
+            Ok(name) => name,
+            Err(e) => return Request::Done(Err(e)),
+        };
+        let Some(geometry) = self.geometry else {
+            return Request::Done(Err(ErrorCode::RESERVE));
+        };
+        let Some(handle) = self.files.iter().position(|file| file.is_none()) else {
+            return Request::Done(Err(ErrorCode::NOMEM));
+        };
+        let directory = directory_name(storage_id);
+        let request = OpenRequest {
+            name,
+            directory,
+            mode,
+            handle,
+        };
+        let scan = DirScan::new(geometry.root_dir(), Target::Name(directory));
+        self.start(
+            Operation::Open(request, OpenStep::FindDir(scan)),
+            buf,
+            &NoData,
+        )
+    }
+
+    /// Read up to `len` bytes from the position of the file into `data`.
+    ///
+    /// Completes with the number of bytes read, which is less than `len` at
+    /// the end of the file, and the new position.
+    pub fn read<B: FileBuffer + ?Sized>(
+        &mut self,
+        handle: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &B,
+    ) -> Request {
+        match self.file(handle) {
+            Ok(file) => self.start(
+                Operation::Read {
+                    handle,
+                    file,
+                    remaining: len,
+                    done: 0,
+                },
+                buf,
+                data,
+            ),
+            Err(e) => Request::Done(Err(e)),
+        }
+    }
+
+    /// Write `len` bytes from `data` at the position of the file, extending
+    /// the file if needed.
+    ///
+    /// Completes with the number of bytes written and the new position.
+    /// Fails with `NOMEM` if the volume is full.
+    pub fn write<B: FileBuffer + ?Sized>(
+        &mut self,
+        handle: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &B,
+    ) -> Request {
+        match self.file(handle) {
+            Ok(file) if !file.writable => Request::Done(Err(ErrorCode::INVAL)),
+            Ok(file) => {
+                // Files cannot be larger than 4 GiB.
+                let len = cmp::min(len, (u32::MAX - file.position) as usize);
+                self.start(
+                    Operation::Write {
+                        handle,
+                        file,
+                        remaining: len,
+                        done: 0,
+                        step: WriteStep::Data,
+                    },
+                    buf,
+                    data,
+                )
+            }
+            Err(e) => Request::Done(Err(e)),
+        }
+    }
+
+    /// Copy the name of the `index`th file in the directory of `storage_id`
+    /// into `data`, in the `NAME.EXT` form.
+    ///
+    /// Completes with the length of the name and the size of the file. Fails
+    /// with `NOSUPPORT` if there are fewer files.
+    pub fn list<B: FileBuffer + ?Sized>(
+        &mut self,
+        storage_id: u32,
+        index: usize,
+        buf: &mut [u8],
+        data: &B,
+    ) -> Request {
+        let Some(geometry) = self.geometry else {
+            return Request::Done(Err(ErrorCode::RESERVE));
+        };
+        let scan = DirScan::new(
+            geometry.root_dir(),
+            Target::Name(directory_name(storage_id)),
+        );
+        self.start(Operation::List(ListStep::FindDir(scan, index)), buf, data)
+    }
+
+    /// Set the position of a file. The position cannot be past the end of
+    /// the file.
+    pub fn seek(&mut self, handle: usize, position: u32) -> Result<(), ErrorCode> {
+        let file = self.file_mut(handle)?;
+        if position > file.size {
+            return Err(ErrorCode::INVAL);
+        }
+        file.position = position;
+        Ok(())
+    }
+
+    /// Returns the size and position of a file.
+    pub fn file_info(&self, handle: usize) -> Result<(u32, u32), ErrorCode> {
+        self.file(handle).map(|file| (file.size, file.position))
+    }
+
+    /// Close a file. Writes update the directory entry when they complete,
+    /// so this does not access the disk.
+    pub fn close(&mut self, handle: usize) -> Result<(), ErrorCode> {
+        self.file_mut(handle)?;
+        self.files[handle] = None;
+        Ok(())
+    }
+
+    /// Continue the operation in progress after the requested sector
+    /// transfer completed with `result`.
+    pub fn io_done<B: FileBuffer + ?Sized>(
+        &mut self,
+        result: Result<(), ErrorCode>,
+        buf: &mut [u8],
+        data: &B,
+    ) -> Request {
+        let transferred = self.transfer.take();
+        if let Err(e) = result {
+            self.loaded = None;
+            self.operation = None;
+            return Request::Done(Err(e));
+        }
+        self.loaded = transferred;
+        self.step(buf, data)
+    }
+
+    fn file(&self, handle: usize) -> Result<OpenFile, ErrorCode> {
+        if self.op_handle() == Some(handle) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.files
+            .get(handle)
+            .copied()
+            .flatten()
+            .ok_or(ErrorCode::INVAL)
+    }
+
+    fn file_mut(&mut self, handle: usize) -> Result<&mut OpenFile, ErrorCode> {
+        if self.op_handle() == Some(handle) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.files
+            .get_mut(handle)
+            .and_then(|file| file.as_mut())
+            .ok_or(ErrorCode::INVAL)
+    }
+
+    /// The file used by the operation in progress.
+    fn op_handle(&self) -> Option<usize> {
+        match self.operation {
+            Some(Operation::Read { handle, .. }) | Some(Operation::Write { handle, .. }) => {
+                Some(handle)
+            }
+            _ => None,
+        }
+    }
+
+    fn start<B: FileBuffer + ?Sized>(
+        &mut self,
+        operation: Operation,
+        buf: &mut [u8],
+        data: &B,
+    ) -> Request {
+        if self.operation.is_some() {
+            return Request::Done(Err(ErrorCode::BUSY));
+        }
+        self.operation = Some(operation);
+        self.step(buf, data)
+    }
+
+    /// Run the operation in progress until it needs a sector transfer or
+    /// finishes.
+    fn step<B: FileBuffer + ?Sized>(&mut self, buf: &mut [u8], data: &B) -> Request {
+        let Some(mut operation) = self.operation.take() else {
+            return Request::Done(Err(ErrorCode::FAIL));
+        };
+
+        let result = match (&mut operation, self.geometry) {
+            (Operation::Mount(step), _) => self.run_mount(step, buf),
+            (Operation::Open(request, step), Some(geometry)) => {
+                self.run_open(&geometry, request, step, buf)
+            }
+            (
+                Operation::Read {
+                    handle,
+                    file,
+                    remaining,
+                    done,
+                },
+                Some(geometry),
+            ) => {
+                let result = self.run_read(&geometry, file, remaining, done, buf, data);
+                if result.is_ok() {
+                    self.files[*handle] = Some(*file);
+                }
+                result
+            }
+            (
+                Operation::Write {
+                    handle,
+                    file,
+                    remaining,
+                    done,
+                    step,
+                },
+                Some(geometry),
+            ) => {
+                let result = self.run_write(&geometry, file, remaining, done, step, buf, data);
+                if result.is_ok() {
+                    self.files[*handle] = Some(*file);
+                }
+                result
+            }
+            (Operation::List(step), Some(geometry)) => self.run_list(&geometry, step, buf, data),
+            (_, None) => Err(Request::Done(Err(ErrorCode::RESERVE))),
+        };
+
+        match result {
+            Ok(values) => Request::Done(Ok(values)),
+            Err(Request::Done(result)) => Request::Done(result),
+            Err(request) => {
+                self.operation = Some(operation);
+                self.loaded = None;
+                self.transfer = match request {
+                    Request::Read(lba) | Request::Write(lba) => Some(lba),
+                    Request::Done(_) => None,
+                };
+                request
+            }
+        }
+    }
+
+    /// Returns `Ok` if sector `lba` is in the sector buffer, or the request
+    /// to read it.
+    fn need(&self, lba: u32) -> Result<(), Request> {
+        if self.loaded == Some(lba) {
+            Ok(())
+        } else {
+            Err(Request::Read(lba))
+        }
+    }
+
+    /// Returns the FAT entry of `cluster`.
+    fn fat_entry(&self, geometry: &Geometry, cluster: u32, buf: &[u8]) -> Result<u32, Request> {
+        let (lba, offset) = geometry.fat_location(cluster);
+        self.need(lba)?;
+        Ok(geometry.fat_entry(buf, offset))
+    }
+
+    /// Follow the cluster chain of `file` to the cluster holding its
+    /// position. Returns `false` if the chain ends before that cluster.
+    fn walk(&self, geometry: &Geometry, file: &mut OpenFile, buf: &[u8]) -> Result<bool, Request> {
+        if file.first_cluster == 0 {
+            return Ok(false);
+        }
+        let index = file.position / geometry.cluster_bytes();
+        if index < file.cluster_index {
+            file.cluster = file.first_cluster;
+            file.cluster_index = 0;
+        }
+        while file.cluster_index < index {
+            let next = self.fat_entry(geometry, file.cluster, buf)?;
+            if geometry.is_end_of_chain(next) {
+                return Ok(false);
+            }
+            if !geometry.is_cluster(next) {
+                return Err(Request::Done(Err(ErrorCode::FAIL)));
+            }
+            file.cluster = next;
+            file.cluster_index += 1;
+        }
+        Ok(true)
+    }
+
+    /// Search a directory.
+    fn scan(
+        &self,
+        geometry: &Geometry,
+        scan: &mut DirScan,
+        buf: &[u8],
+    ) -> Result<ScanResult, Request> {
+        loop {
+            match scan.position {
+                DirPosition::Fixed(sector) if sector >= geometry.root_dir_sectors => {
+                    return Ok(ScanResult::NotFound(FreeSlot {
+                        entry: scan.free,
+                        last_cluster: None,
+                    }));
+                }
+                DirPosition::Chain { cluster, sector }
+                    if sector >= geometry.sectors_per_cluster =>
+                {
+                    let next = self.fat_entry(geometry, cluster, buf)?;
+                    if geometry.is_end_of_chain(next) {
+                        return Ok(ScanResult::NotFound(FreeSlot {
+                            entry: scan.free,
+                            last_cluster: Some(cluster),
+                        }));
+                    }
+                    if !geometry.is_cluster(next) {
+                        return Err(Request::Done(Err(ErrorCode::FAIL)));
+                    }
+                    scan.position = DirPosition::Chain {
+                        cluster: next,
+                        sector: 0,
+                    };
+                    continue;
+                }
+                _ => {}
+            }
+
+            let lba = geometry.dir_lba(scan.position);
+            self.need(lba)?;
+            for offset in (0..DIR_ENTRIES_PER_SECTOR).map(|i| i * DIR_ENTRY_SIZE) {
+                let entry = &buf[offset..offset + DIR_ENTRY_SIZE];
+                let attributes = entry[11];
+                match entry[0] {
+                    ENTRY_END => {
+                        return Ok(ScanResult::NotFound(FreeSlot {
+                            entry: scan.free.or(Some((lba, offset))),
+                            // Entries after the end marker are free, so the
+                            // directory never has to be extended.
+                            last_cluster: None,
+                        }));
+                    }
+                    ENTRY_DELETED => {
+                        scan.free.get_or_insert((lba, offset));
+                    }
+                    _ if attributes & ATTR_LONG_NAME == ATTR_LONG_NAME
+                        || attributes & ATTR_VOLUME_ID != 0 => {}
+                    _ => match scan.target {
+                        Target::Name(name) if entry[..11] == name => {
+                            return Ok(ScanResult::Found(lba, offset));
+                        }
+                        Target::Index(index) if attributes & ATTR_DIRECTORY == 0 => {
+                            if scan.files_seen == index {
+                                return Ok(ScanResult::Found(lba, offset));
+                            }
+                            scan.files_seen += 1;
+                        }
+                        _ => {}
+                    },
+                }
+            }
+
+            scan.position = match scan.position {
+                DirPosition::Fixed(sector) => DirPosition::Fixed(sector + 1),
+                DirPosition::Chain { cluster, sector } => DirPosition::Chain {
+                    cluster,
+                    sector: sector + 1,
+                },
+            };
+        }
+    }
+
+    /// Allocate a cluster, marking it as the end of a chain and linking it
+    /// from the previous cluster. The new cluster is marked before it is
+    /// linked so an interrupted allocation at most leaks the cluster.
+    fn alloc(
+        &mut self,
+        geometry: &Geometry,
+        alloc: &mut Alloc,
+        buf: &mut [u8],
+    ) -> Result<u32, Request> {
+        loop {
+            match alloc.step {
+                AllocStep::Search { cluster, checked } => {
+                    if checked >= geometry.cluster_count {
+                        return Err(Request::Done(Err(ErrorCode::NOMEM)));
+                    }
+                    let (lba, offset) = geometry.fat_location(cluster);
+                    self.need(lba)?;
+                    let next = if geometry.is_cluster(cluster + 1) {
+                        cluster + 1
+                    } else {
+                        2
+                    };
+                    if geometry.fat_entry(buf, offset) == 0 {
+                        geometry.set_fat_entry(buf, offset, geometry.end_of_chain());
+                        self.next_free = next;
+                        alloc.step = AllocStep::Mark { cluster, copy: 1 };
+                        return Err(Request::Write(lba));
+                    }
+                    alloc.step = AllocStep::Search {
+                        cluster: next,
+                        checked: checked + 1,
+                    };
+                }
+                AllocStep::Mark { cluster, copy } | AllocStep::Link { cluster, copy }
+                    if copy < geometry.num_fats =>
+                {
+                    // The sector buffer still holds the FAT sector written to
+                    // the previous copy.
+                    let updated = match alloc.step {
+                        AllocStep::Mark { .. } => cluster,
+                        _ => alloc.previous.unwrap_or(cluster),
+                    };
+                    let (lba, _) = geometry.fat_location(updated);
+                    alloc.step = match alloc.step {
+                        AllocStep::Mark { .. } => AllocStep::Mark {
+                            cluster,
+                            copy: copy + 1,
+                        },
+                        _ => AllocStep::Link {
+                            cluster,
+                            copy: copy + 1,
+                        },
+                    };
+                    return Err(Request::Write(lba + copy * geometry.fat_size));
+                }
+                AllocStep::Mark { cluster, .. } => match alloc.previous {
+                    Some(previous) => {
+                        let (lba, offset) = geometry.fat_location(previous);
+                        self.need(lba)?;
+                        geometry.set_fat_entry(buf, offset, cluster);
+                        alloc.step = AllocStep::Link { cluster, copy: 1 };
+                        return Err(Request::Write(lba));
+                    }
+                    None => return Ok(cluster),
+                },
+                AllocStep::Link { cluster, .. } => return Ok(cluster),
+            }
+        }
+    }
+
+    /// Add an entry to a directory, extending the directory if it is full.
+    /// Returns the sector and offset of the new entry.
+    fn insert(
+        &mut self,
+        geometry: &Geometry,
+        insert: &mut Insert,
+        buf: &mut [u8],
+    ) -> Result<(u32, usize), Request> {
+        loop {
+            match insert.step {
+                InsertStep::Extend(ref mut alloc) => {
+                    let cluster = self.alloc(geometry, alloc, buf)?;
+                    insert.step = InsertStep::Zero {
+                        cluster,
+                        sectors: geometry.sectors_per_cluster,
+                    };
+                }
+                InsertStep::Zero {
+                    cluster,
+                    sectors: 0,
+                } => {
+                    insert.step = InsertStep::Write(geometry.cluster_lba(cluster), 0);
+                }
+                InsertStep::Zero { cluster, sectors } => {
+                    buf[..SECTOR_SIZE].fill(0);
+                    insert.step = InsertStep::Zero {
+                        cluster,
+                        sectors: sectors - 1,
+                    };
+                    return Err(Request::Write(geometry.cluster_lba(cluster) + sectors - 1));
+                }
+                InsertStep::Write(lba, offset) => {
+                    self.need(lba)?;
+                    buf[offset..offset + DIR_ENTRY_SIZE].copy_from_slice(&insert.entry);
+                    insert.step = InsertStep::Written(lba, offset);
+                    return Err(Request::Write(lba));
+                }
+                InsertStep::Written(lba, offset) => return Ok((lba, offset)),
+            }
+        }
+    }
+
+    fn new_insert(&self, entry: [u8; DIR_ENTRY_SIZE], slot: FreeSlot) -> Result<Insert, Request> {
+        let step = match slot {
+            FreeSlot {
+                entry: Some((lba, offset)),
+                ..
+            } => InsertStep::Write(lba, offset),
+            FreeSlot {
+                last_cluster: Some(cluster),
+                ..
+            } => InsertStep::Extend(Alloc::new(Some(cluster), self.next_free)),
+            // The FAT16 root directory has a fixed size.
+            _ => return Err(Request::Done(Err(ErrorCode::NOMEM))),
+        };
+        Ok(Insert { entry, step })
+    }
+
+    fn run_mount(&mut self, step: &mut MountStep, buf: &[u8]) -> Result<(usize, usize), Request> {
+        let geometry = match *step {
+            MountStep::FirstSector => {
+                self.need(0)?;
+                match Geometry::parse(buf, 0) {
+                    Ok(geometry) => geometry,
+                    Err(_) => {
+                        // Not a boot sector, look for a FAT partition in the
+                        // MBR partition table.
+                        let partition =
+                            (0..4)
+                                .map(|i| &buf[446 + 16 * i..462 + 16 * i])
+                                .find(|entry| {
+                                    FAT_PARTITION_TYPES.contains(&entry[4])
+                                        && read_u32(entry, 8) != 0
+                                });
+                        match partition {
+                            Some(entry) if buf[510..512] == [0x55, 0xAA] => {
+                                *step = MountStep::Partition(read_u32(entry, 8));
+                                return Err(Request::Read(read_u32(entry, 8)));
+                            }
+                            _ => return Err(Request::Done(Err(ErrorCode::NOSUPPORT))),
+                        }
+                    }
+                }
+            }
+            MountStep::Partition(lba) => {
+                self.need(lba)?;
+                Geometry::parse(buf, lba).map_err(|e| Request::Done(Err(e)))?
+            }
+        };
+
+        self.geometry = Some(geometry);
+        self.next_free = 2;
+        let bits = match geometry.fat_type {
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        };
+        Ok((bits, geometry.cluster_bytes() as usize))
+    }
+
+    fn run_open(
+        &mut self,
+        geometry: &Geometry,
+        request: &OpenRequest,
+        step: &mut OpenStep,
+        buf: &mut [u8],
+    ) -> Result<(usize, usize), Request> {
+        let OpenRequest {
+            name,
+            directory,
+            mode,
+            handle,
+        } = request;
+        let fail = |e| Err(Request::Done(Err(e)));
+
+        loop {
+            match step {
+                OpenStep::FindDir(scan) => match self.scan(geometry, scan, buf)? {
+                    ScanResult::Found(_, offset) => {
+                        let entry = &buf[offset..offset + DIR_ENTRY_SIZE];
+                        let cluster = entry_cluster(entry);
+                        if entry[11] & ATTR_DIRECTORY == 0 || !geometry.is_cluster(cluster) {
+                            return fail(ErrorCode::FAIL);
+                        }
+                        *step = OpenStep::FindFile(DirScan::new(
+                            DirPosition::Chain { cluster, sector: 0 },
+                            Target::Name(*name),
+                        ));
+                    }
+                    ScanResult::NotFound(slot) if *mode == OpenMode::Create => {
+                        *step = OpenStep::CreateDir(slot, Alloc::new(None, self.next_free));
+                    }
+                    ScanResult::NotFound(_) => return fail(ErrorCode::NOSUPPORT),
+                },
+                OpenStep::CreateDir(slot, alloc) => {
+                    let cluster = self.alloc(geometry, alloc, buf)?;
+                    *step = OpenStep::ZeroDir {
+                        slot: *slot,
+                        cluster,
+                        sectors: geometry.sectors_per_cluster,
+                    };
+                }
+                OpenStep::ZeroDir {
+                    slot,
+                    cluster,
+                    sectors: 0,
+                } => {
+                    let entry = new_entry(directory, ATTR_DIRECTORY, *cluster);
+                    *step = OpenStep::InsertDir(*cluster, self.new_insert(entry, *slot)?);
+                }
+                OpenStep::ZeroDir {
+                    cluster, sectors, ..
+                } => {
+                    buf[..SECTOR_SIZE].fill(0);
+                    let lba = geometry.cluster_lba(*cluster) + *sectors - 1;
+                    if *sectors == 1 {
+                        // The parent is the root directory, which is
+                        // referred to as cluster 0.
+                        let dot = new_entry(b".          ", ATTR_DIRECTORY, *cluster);
+                        let dot_dot = new_entry(b"..         ", ATTR_DIRECTORY, 0);
+                        buf[..DIR_ENTRY_SIZE].copy_from_slice(&dot);
+                        buf[DIR_ENTRY_SIZE..2 * DIR_ENTRY_SIZE].copy_from_slice(&dot_dot);
+                    }
+                    *sectors -= 1;
+                    return Err(Request::Write(lba));
+                }
+                OpenStep::InsertDir(cluster, insert) => {
+                    self.insert(geometry, insert, buf)?;
+                    *step = OpenStep::FindFile(DirScan::new(
+                        DirPosition::Chain {
+                            cluster: *cluster,
+                            sector: 0,
+                        },
+                        Target::Name(*name),
+                    ));
+                }
+                OpenStep::FindFile(scan) => match self.scan(geometry, scan, buf)? {
+                    ScanResult::Found(lba, offset) => {
+                        let entry = &buf[offset..offset + DIR_ENTRY_SIZE];
+                        if entry[11] & ATTR_DIRECTORY != 0 {
+                            return fail(ErrorCode::INVAL);
+                        }
+                        let writable = *mode != OpenMode::Read;
+                        if writable && entry[11] & ATTR_READ_ONLY != 0 {
+                            return fail(ErrorCode::INVAL);
+                        }
+                        let conflict =
+                            self.files.iter().flatten().any(|file| {
+                                file.entry == (lba, offset) && (writable || file.writable)
+                            });
+                        if conflict {
+                            return fail(ErrorCode::BUSY);
+                        }
+                        let first_cluster = entry_cluster(entry);
+                        let size = read_u32(entry, 28);
+                        self.files[*handle] = Some(OpenFile {
+                            entry: (lba, offset),
+                            first_cluster,
+                            size,
+                            position: 0,
+                            writable,
+                            dirty: false,
+                            cluster: first_cluster,
+                            cluster_index: 0,
+                        });
+                        return Ok((*handle, size as usize));
+                    }
+                    ScanResult::NotFound(slot) if *mode == OpenMode::Create => {
+                        let entry = new_entry(name, ATTR_ARCHIVE, 0);
+                        *step = OpenStep::InsertFile(self.new_insert(entry, slot)?);
+                    }
+                    ScanResult::NotFound(_) => return fail(ErrorCode::NOSUPPORT),
+                },
+                OpenStep::InsertFile(insert) => {
+                    let entry = self.insert(geometry, insert, buf)?;
+                    self.files[*handle] = Some(OpenFile {
+                        entry,
+                        first_cluster: 0,
+                        size: 0,
+                        position: 0,
+                        writable: true,
+                        dirty: false,
+                        cluster: 0,
+                        cluster_index: 0,
+                    });
+                    return Ok((*handle, 0));
+                }
+            }
+        }
+    }
+
+    fn run_read<B: FileBuffer + ?Sized>(
+        &self,
+        geometry: &Geometry,
+        file: &mut OpenFile,
+        remaining: &mut usize,
+        done: &mut usize,
+        buf: &[u8],
+        data: &B,
+    ) -> Result<(usize, usize), Request> {
+        while *remaining > 0 && file.position < file.size {
+            if !self.walk(geometry, file, buf)? {
+                // The chain is shorter than the file size.
+                return Err(Request::Done(Err(ErrorCode::FAIL)));
+            }
+            let offset = file.position % geometry.cluster_bytes();
+            let lba = geometry.cluster_lba(file.cluster) + offset / SECTOR_SIZE as u32;
+            let in_sector = offset as usize % SECTOR_SIZE;
+            let len = cmp::min(
+                cmp::min(SECTOR_SIZE - in_sector, *remaining),
+                (file.size - file.position) as usize,
+            );
+            self.need(lba)?;
+            data.copy_in(*done, &buf[in_sector..in_sector + len]);
+            file.position += len as u32;
+            *done += len;
+            *remaining -= len;
+        }
+        Ok((*done, file.position as usize))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn run_write<B: FileBuffer + ?Sized>(
+        &mut self,
+        geometry: &Geometry,
+        file: &mut OpenFile,
+        remaining: &mut usize,
+        done: &mut usize,
+        step: &mut WriteStep,
+        buf: &mut [u8],
+        data: &B,
+    ) -> Result<(usize, usize), Request> {
+        loop {
+            match step {
+                WriteStep::Data if *remaining == 0 => {
+                    *step = match file.dirty {
+                        true => WriteStep::UpdateEntry,
+                        false => WriteStep::Finished,
+                    };
+                }
+                WriteStep::Data => {
+                    if !self.walk(geometry, file, buf)? {
+                        let previous = (file.first_cluster != 0).then_some(file.cluster);
+                        *step = WriteStep::Alloc(Alloc::new(previous, self.next_free));
+                        continue;
+                    }
+                    let offset = file.position % geometry.cluster_bytes();
+                    let lba = geometry.cluster_lba(file.cluster) + offset / SECTOR_SIZE as u32;
+                    let in_sector = offset as usize % SECTOR_SIZE;
+                    let len = cmp::min(SECTOR_SIZE - in_sector, *remaining);
+
+                    // Keep the rest of a partially written sector if it holds
+                    // file data.
+                    let sector_start = file.position - in_sector as u32;
+                    if len < SECTOR_SIZE && sector_start < file.size {
+                        self.need(lba)?;
+                    } else {
+                        buf[..SECTOR_SIZE].fill(0);
+                    }
+                    data.copy_out(*done, &mut buf[in_sector..in_sector + len]);
+
+                    file.position += len as u32;
+                    *done += len;
+                    *remaining -= len;
+                    if file.position > file.size {
+                        file.size = file.position;
+                        file.dirty = true;
+                    }
+                    return Err(Request::Write(lba));
+                }
+                WriteStep::Alloc(alloc) => {
+                    let cluster = self.alloc(geometry, alloc, buf)?;
+                    if file.first_cluster == 0 {
+                        file.first_cluster = cluster;
+                        file.cluster_index = 0;
+                        file.dirty = true;
+                    } else {
+                        file.cluster_index += 1;
+                    }
+                    file.cluster = cluster;
+                    *step = WriteStep::Data;
+                }
+                WriteStep::UpdateEntry => {
+                    let (lba, offset) = file.entry;
+                    self.need(lba)?;
+                    let entry = &mut buf[offset..offset + DIR_ENTRY_SIZE];
+                    set_entry_cluster(entry, file.first_cluster);
+                    write_u32(entry, 28, file.size);
+                    write_u16(entry, 24, FAT_DATE as u32);
+                    file.dirty = false;
+                    *step = WriteStep::Finished;
+                    return Err(Request::Write(lba));
+                }
+                WriteStep::Finished => return Ok((*done, file.position as usize)),
+            }
+        }
+    }
+
+    fn run_list<B: FileBuffer + ?Sized>(
+        &self,
+        geometry: &Geometry,
+        step: &mut ListStep,
+        buf: &[u8],
+        data: &B,
+    ) -> Result<(usize, usize), Request> {
+        let not_found = Err(Request::Done(Err(ErrorCode::NOSUPPORT)));
+        loop {
+            match step {
+                ListStep::FindDir(scan, index) => match self.scan(geometry, scan, buf)? {
+                    ScanResult::Found(_, offset) => {
+                        let entry = &buf[offset..offset + DIR_ENTRY_SIZE];
+                        let cluster = entry_cluster(entry);
+                        if entry[11] & ATTR_DIRECTORY == 0 || !geometry.is_cluster(cluster) {
+                            return Err(Request::Done(Err(ErrorCode::FAIL)));
+                        }
+                        *step = ListStep::FindEntry(DirScan::new(
+                            DirPosition::Chain { cluster, sector: 0 },
+                            Target::Index(*index),
+                        ));
+                    }
+                    ScanResult::NotFound(_) => return not_found,
+                },
+                ListStep::FindEntry(scan) => match self.scan(geometry, scan, buf)? {
+                    ScanResult::Found(_, offset) => {
+                        let entry = &buf[offset..offset + DIR_ENTRY_SIZE];
+                        let (name, len) = display_name(&entry[..11]);
+                        data.copy_in(0, &name[..len]);
+                        return Ok((len, read_u32(entry, 28) as usize));
+                    }
+                    ScanResult::NotFound(_) => return not_found,
+                },
+            }
+        }
+    }
+}
+
+/// A file buffer for operations that do not transfer file data.
+pub struct NoData;
+
+impl FileBuffer for NoData {
+    fn copy_in(&self, _offset: usize, _data: &[u8]) {}
+    fn copy_out(&self, _offset: usize, _data: &mut [u8]) {}
+}
@@ -0,0 +1,953 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! GDB remote serial protocol stub for debugging a userspace process.
+//!
+//! The stub speaks the GDB remote serial protocol (RSP) over a UART and
+//! debugs one process at a time. The UART must be dedicated to the stub: a
+//! console sharing it would echo and interpret GDB's packets.
+//! GDB attaches to a process with `attach <pid>` in extended-remote mode; the
+//! process IDs are listed by `monitor ps`. Once attached, the process is
+//! stopped and GDB can:
+//!
+//! - Read and write its registers, as the process left them when it last
+//!   returned to the kernel.
+//! - Read its flash and the RAM it can access, and write that RAM. Its grant
+//!   region and kernel memory are out of reach.
+//! - Set software breakpoints in code that runs from RAM. The stub writes
+//!   the breakpoint instruction the board configured, which traps into the
+//!   kernel. While a debugger is attached, a fault stops the process instead
+//!   of invoking the kernel's fault policy, so the process stops at the
+//!   breakpoint. Breakpoints in flash are refused.
+//! - Single-step the process. A step runs the process until it next returns
+//!   to the kernel, for a syscall, an interrupt or a fault, and then stops
+//!   it. This is coarser than an instruction step.
+//! - Continue the process, and interrupt it with Ctrl-C.
+//!
+//! The stub polls the process with an alarm to notice when it stops.
+//!
+//! ```text
+//! (gdb) target extended-remote /dev/ttyUSB0
+//! (gdb) monitor ps
+//! (gdb) attach 2
+//! (gdb) add-symbol-file app.elf -o <flash address of the app>
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let gdb_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, Rtc>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! gdb_alarm.setup();
+//! let gdb_stub = static_init!(
+//!     capsules_extra::gdb_stub::GdbStub<'static, VirtualMuxAlarm<'static, Rtc>, Capability>,
+//!     capsules_extra::gdb_stub::GdbStub::new(
+//!         gdb_uart,
+//!         gdb_alarm,
+//!         board_kernel,
+//!         capsules_extra::gdb_stub::RISCV_BREAKPOINTS,
+//!         rx_buffer,
+//!         packet_buffer,
+//!         tx_buffer,
+//!         capability,
+//!     )
+//! );
+//! gdb_uart.set_transmit_client(gdb_stub);
+//! gdb_uart.set_receive_client(gdb_stub);
+//! gdb_alarm.set_alarm_client(gdb_stub);
+//! gdb_stub.start();
+//! ```
+
+use core::cell::Cell;
+use core::fmt::{self, Write};
+
+use kernel::capabilities::{ProcessDebugCapability, ProcessManagementCapability};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::hil::uart;
+use kernel::process::{Process, State};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Length of the buffer received packets are stored in. GDB is told it may
+/// send packets of up to this many bytes.
+pub const PACKET_BUF_LEN: usize = 256;
+/// Length of the buffer replies are assembled in.
+pub const TX_BUF_LEN: usize = 512;
+/// Length of the receive buffer. The stub receives one byte at a time.
+pub const RX_BUF_LEN: usize = 1;
+
+/// How often to check whether a running process stopped, in milliseconds.
+pub const POLL_INTERVAL_MS: u32 = 20;
+/// Upper bound on the number of registers of any architecture.
+const MAX_REGISTERS: usize = 64;
+/// How many software breakpoints can be set at once.
+pub const MAX_BREAKPOINTS: usize = 8;
+
+/// Breakpoint instructions for RISC-V, by breakpoint kind: `c.ebreak` for
+/// kind 2 and `ebreak` for kind 4.
+pub const RISCV_BREAKPOINTS: &[(usize, &[u8])] =
+    &[(2, &[0x02, 0x90]), (4, &[0x73, 0x00, 0x10, 0x00])];
+/// Breakpoint instructions for ARMv6-M and ARMv7-M, by breakpoint kind: the
+/// Thumb `bkpt` instruction for kind 2.
+pub const THUMB_BREAKPOINTS: &[(usize, &[u8])] = &[(2, &[0x00, 0xbe])];
+
+/// Signal reported when the process stopped for any reason but an interrupt.
+const SIGTRAP: u8 = 5;
+/// Signal reported when GDB interrupted the process.
+const SIGINT: u8 = 2;
+/// Byte GDB sends to interrupt a running process.
+const INTERRUPT: u8 = 0x03;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Value of an ASCII hex digit.
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Parse a non-empty hex number.
+fn parse_hex(s: &[u8]) -> Option<usize> {
+    if s.is_empty() {
+        return None;
+    }
+    s.iter().try_fold(0usize, |value, &c| {
+        value.checked_mul(16)?.checked_add(hex_value(c)? as usize)
+    })
+}
+
+/// Decode pairs of hex digits into `out`. Returns the number of bytes
+/// decoded, or `None` if `hex` is not valid or does not fit.
+fn decode_hex(hex: &[u8], out: &mut [u8]) -> Option<usize> {
+    if hex.len() % 2 != 0 || hex.len() / 2 > out.len() {
+        return None;
+    }
+    for (byte, pair) in out.iter_mut().zip(hex.chunks(2)) {
+        *byte = hex_value(pair[0])? << 4 | hex_value(pair[1])?;
+    }
+    Some(hex.len() / 2)
+}
+
+/// Split `s` at the first `separator`.
+fn split_at_byte(s: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let index = s.iter().position(|&c| c == separator)?;
+    Some((&s[..index], &s[index + 1..]))
+}
+
+/// Parse the `address,length` arguments of memory and breakpoint packets.
+fn parse_address_length(s: &[u8]) -> Option<(usize, usize)> {
+    let (address, length) = split_at_byte(s, b',')?;
+    Some((parse_hex(address)?, parse_hex(length)?))
+}
+
+/// Sum of the bytes of a packet, which is its checksum.
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum: u8, &c| sum.wrapping_add(c))
+}
+
+/// Assembles a reply packet, optionally preceded by an acknowledgement.
+/// Data that does not fit is dropped.
+struct PacketWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+    checksum: u8,
+}
+
+impl<'b> PacketWriter<'b> {
+    fn new(buf: &'b mut [u8], ack: bool) -> PacketWriter<'b> {
+        let mut len = 0;
+        if ack {
+            buf[len] = b'+';
+            len += 1;
+        }
+        buf[len] = b'$';
+        PacketWriter {
+            buf,
+            len: len + 1,
+            checksum: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        // Leave room for the checksum.
+        if self.len + 3 < self.buf.len() {
+            self.buf[self.len] = byte;
+            self.len += 1;
+            self.checksum = self.checksum.wrapping_add(byte);
+        }
+    }
+
+    fn push_str(&mut self, s: &[u8]) {
+        s.iter().for_each(|&c| self.push(c));
+    }
+
+    fn push_hex(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.push(HEX_DIGITS[(byte >> 4) as usize]);
+            self.push(HEX_DIGITS[(byte & 0xf) as usize]);
+        }
+    }
+
+    /// How many more data bytes, encoded as hex, fit in the packet.
+    fn hex_capacity(&self) -> usize {
+        self.buf.len().saturating_sub(self.len + 3) / 2
+    }
+
+    /// Terminate the packet and return its length.
+    fn finish(self) -> usize {
+        let checksum = self.checksum;
+        self.buf[self.len] = b'#';
+        self.buf[self.len + 1] = HEX_DIGITS[(checksum >> 4) as usize];
+        self.buf[self.len + 2] = HEX_DIGITS[(checksum & 0xf) as usize];
+        self.len + 3
+    }
+}
+
+impl Write for PacketWriter<'_> {
+    /// Text written with `write!` is hex encoded, as in console output.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_hex(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// A reply to a packet.
+enum Reply {
+    /// Send the packet assembled in the writer.
+    Packet,
+    /// Only acknowledge the packet. The process was resumed, and a stop
+    /// reply follows when it stops.
+    Resumed,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RxState {
+    /// Waiting for the start of a packet.
+    Idle,
+    /// Receiving packet data.
+    Data,
+    /// Receiving the first checksum digit.
+    Checksum,
+    /// Receiving the second checksum digit, after the first one.
+    Checksum2(u8),
+}
+
+/// A software breakpoint and the memory it replaced.
+#[derive(Clone, Copy)]
+struct Breakpoint {
+    address: usize,
+    len: usize,
+    original: [u8; 4],
+}
+
+pub struct GdbStub<'a, A: time::Alarm<'a>, C: ProcessManagementCapability + ProcessDebugCapability>
+{
+    uart: &'a dyn uart::UartData<'a>,
+    alarm: &'a A,
+    kernel: &'static Kernel,
+    breakpoint_instructions: &'static [(usize, &'static [u8])],
+    rx_buffer: TakeCell<'static, [u8]>,
+    packet: TakeCell<'static, [u8]>,
+    packet_len: Cell<usize>,
+    rx_state: Cell<RxState>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    /// Length of the last packet sent, to send it again if GDB asks.
+    tx_len: Cell<usize>,
+    /// The process GDB is attached to.
+    attached: OptionalCell<ProcessId>,
+    /// Whether the attached process was resumed and GDB waits for it to stop.
+    running: Cell<bool>,
+    /// Signal to report when the running process stops.
+    stop_signal: Cell<u8>,
+    breakpoints: [Cell<Option<Breakpoint>>; MAX_BREAKPOINTS],
+    capability: C,
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability + ProcessDebugCapability>
+    GdbStub<'a, A, C>
+{
+    /// `breakpoint_instructions` lists the instruction that traps into the
+    /// kernel for each breakpoint kind GDB uses on this architecture, for
+    /// example `RISCV_BREAKPOINTS`.
+    pub fn new(
+        uart: &'a dyn uart::UartData<'a>,
+        alarm: &'a A,
+        kernel: &'static Kernel,
+        breakpoint_instructions: &'static [(usize, &'static [u8])],
+        rx_buffer: &'static mut [u8],
+        packet: &'static mut [u8],
+        tx_buffer: &'static mut [u8],
+        capability: C,
+    ) -> GdbStub<'a, A, C> {
+        GdbStub {
+            uart,
+            alarm,
+            kernel,
+            breakpoint_instructions,
+            rx_buffer: TakeCell::new(rx_buffer),
+            packet: TakeCell::new(packet),
+            packet_len: Cell::new(0),
+            rx_state: Cell::new(RxState::Idle),
+            tx_buffer: TakeCell::new(tx_buffer),
+            tx_len: Cell::new(0),
+            attached: OptionalCell::empty(),
+            running: Cell::new(false),
+            stop_signal: Cell::new(SIGTRAP),
+            breakpoints: Default::default(),
+            capability,
+        }
+    }
+
+    /// Start receiving packets from GDB.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        let buffer = self.rx_buffer.take().ok_or(ErrorCode::ALREADY)?;
+        self.uart.receive_buffer(buffer, 1).map_err(|(e, buffer)| {
+            self.rx_buffer.replace(buffer);
+            e
+        })
+    }
+
+    /// Run `closure` on the attached process, if it still exists.
+    fn with_attached<F, R>(&self, closure: F) -> Option<R>
+    where
+        F: FnOnce(&dyn Process) -> R,
+    {
+        self.attached.and_then(|processid| {
+            self.kernel.process_map_or_external(
+                None,
+                processid,
+                |process| Some(closure(process)),
+                &self.capability,
+            )
+        })
+    }
+
+    /// Send a packet assembled by `fill` if the UART is free.
+    fn send(&self, ack: bool, fill: impl FnOnce(&mut PacketWriter)) -> bool {
+        self.tx_buffer.take().map_or(false, |buffer| {
+            let mut writer = PacketWriter::new(buffer, ack);
+            fill(&mut writer);
+            let len = writer.finish();
+            self.tx_len.set(len);
+            self.transmit(buffer, len);
+            true
+        })
+    }
+
+    fn transmit(&self, buffer: &'static mut [u8], len: usize) {
+        if let Err((_, buffer)) = self.uart.transmit_buffer(buffer, len) {
+            self.tx_buffer.replace(buffer);
+        }
+    }
+
+    /// Send the last packet again, without its acknowledgement.
+    fn retransmit(&self) {
+        self.tx_buffer.take().map(|buffer| {
+            let mut len = self.tx_len.get();
+            if len > 0 && buffer[0] == b'+' {
+                buffer.copy_within(1..len, 0);
+                len -= 1;
+                self.tx_len.set(len);
+            }
+            if len > 0 {
+                self.transmit(buffer, len);
+            } else {
+                self.tx_buffer.replace(buffer);
+            }
+        });
+    }
+
+    /// Reject a packet with a bad checksum.
+    fn nack(&self) {
+        self.tx_buffer.take().map(|buffer| {
+            buffer[0] = b'-';
+            self.transmit(buffer, 1);
+        });
+    }
+
+    /// Report to GDB if the running process stopped or no longer exists.
+    fn check_stopped(&self) {
+        if !self.running.get() {
+            return;
+        }
+        let state = self.with_attached(|process| process.get_state());
+        let stopped = matches!(
+            state,
+            Some(State::StoppedRunning | State::StoppedYielded | State::StoppedYieldedFor(_))
+        );
+        let exited = match state {
+            None | Some(State::Terminated | State::Faulted) => true,
+            _ => false,
+        };
+        if stopped {
+            let signal = self.stop_signal.get();
+            if self.send(false, |reply| {
+                reply.push(b'S');
+                reply.push_hex(&[signal]);
+            }) {
+                self.running.set(false);
+                return;
+            }
+        } else if exited && self.send(false, |reply| reply.push_str(b"W00")) {
+            self.detach_exited();
+            return;
+        }
+        // Check again later, also if the UART was busy.
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(POLL_INTERVAL_MS));
+    }
+
+    /// Forget the attached process after it exited or restarted. Its memory
+    /// no longer holds the breakpoints.
+    fn detach_exited(&self) {
+        self.with_attached(|process| process.set_debugger_attached(false));
+        self.breakpoints.iter().for_each(|bp| bp.set(None));
+        self.attached.clear();
+        self.running.set(false);
+    }
+
+    /// Remove all breakpoints and let the attached process run freely.
+    fn detach(&self) {
+        self.with_attached(|process| {
+            for bp in self.breakpoints.iter() {
+                if let Some(breakpoint) = bp.take() {
+                    process.debug_write_memory(
+                        breakpoint.address,
+                        &breakpoint.original[..breakpoint.len],
+                        &self.capability,
+                    );
+                }
+            }
+            process.set_debugger_attached(false);
+            process.resume();
+        });
+        self.detach_exited();
+    }
+
+    fn attach(&self, pid: usize) -> Result<(), ErrorCode> {
+        if self.attached.is_some() {
+            self.detach();
+        }
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.processid().id() == pid {
+                    found = Some(process.processid());
+                }
+            });
+        let processid = found.ok_or(ErrorCode::INVAL)?;
+        self.attached.set(processid);
+        let stopped = self
+            .with_attached(|process| {
+                process.stop();
+                match process.get_state() {
+                    State::StoppedRunning | State::StoppedYielded | State::StoppedYieldedFor(_) => {
+                        process.set_debugger_attached(true);
+                        true
+                    }
+                    _ => false,
+                }
+            })
+            .unwrap_or(false);
+        if stopped {
+            Ok(())
+        } else {
+            self.attached.clear();
+            Err(ErrorCode::OFF)
+        }
+    }
+
+    fn attach_by_name(&self, name: &str) -> Result<usize, ErrorCode> {
+        let mut pid = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if pid.is_none() && process.get_process_name() == name {
+                    pid = Some(process.processid().id());
+                }
+            });
+        let pid = pid.ok_or(ErrorCode::INVAL)?;
+        self.attach(pid).map(|()| pid)
+    }
+
+    fn resume(&self, step: bool, address: &[u8]) {
+        self.with_attached(|process| {
+            if let Some(pc) = parse_hex(address) {
+                // The program counter follows the general purpose registers.
+                let pc_index = (0..MAX_REGISTERS)
+                    .take_while(|&i| process.get_debug_register(i).is_some())
+                    .last()
+                    .unwrap_or(0);
+                let _ = process.set_debug_register(pc_index, pc, &self.capability);
+            }
+            if step {
+                process.debug_single_step();
+            } else {
+                process.resume();
+            }
+        });
+        self.stop_signal.set(SIGTRAP);
+        self.running.set(true);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(POLL_INTERVAL_MS));
+    }
+
+    fn interrupt(&self) {
+        if self.running.get() {
+            self.with_attached(|process| process.stop());
+            self.stop_signal.set(SIGINT);
+            self.check_stopped();
+        }
+    }
+
+    fn read_registers(&self, reply: &mut PacketWriter) -> Result<(), ErrorCode> {
+        self.with_attached(|process| {
+            for index in 0..MAX_REGISTERS {
+                match process.get_debug_register(index) {
+                    Some(value) => reply.push_hex(&value.to_le_bytes()),
+                    None => break,
+                }
+            }
+        })
+        .ok_or(ErrorCode::OFF)
+    }
+
+    fn write_registers(&self, hex: &[u8]) -> Result<(), ErrorCode> {
+        const SIZE: usize = core::mem::size_of::<usize>();
+        self.with_attached(|process| {
+            for (index, register) in hex.chunks(2 * SIZE).enumerate() {
+                let mut bytes = [0; SIZE];
+                if decode_hex(register, &mut bytes) != Some(SIZE) {
+                    return Err(ErrorCode::INVAL);
+                }
+                process.set_debug_register(index, usize::from_le_bytes(bytes), &self.capability)?;
+            }
+            Ok(())
+        })
+        .unwrap_or(Err(ErrorCode::OFF))
+    }
+
+    fn read_register(&self, args: &[u8], reply: &mut PacketWriter) -> Result<(), ErrorCode> {
+        let index = parse_hex(args).ok_or(ErrorCode::INVAL)?;
+        let value = self
+            .with_attached(|process| process.get_debug_register(index))
+            .ok_or(ErrorCode::OFF)?
+            .ok_or(ErrorCode::INVAL)?;
+        reply.push_hex(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn write_register(&self, args: &[u8]) -> Result<(), ErrorCode> {
+        let (index, value) = split_at_byte(args, b'=').ok_or(ErrorCode::INVAL)?;
+        let index = parse_hex(index).ok_or(ErrorCode::INVAL)?;
+        let mut bytes = [0; core::mem::size_of::<usize>()];
+        if decode_hex(value, &mut bytes) != Some(bytes.len()) {
+            return Err(ErrorCode::INVAL);
+        }
+        self.with_attached(|process| {
+            process.set_debug_register(index, usize::from_le_bytes(bytes), &self.capability)
+        })
+        .unwrap_or(Err(ErrorCode::OFF))
+    }
+
+    fn read_memory(&self, args: &[u8], reply: &mut PacketWriter) -> Result<(), ErrorCode> {
+        let (address, length) = parse_address_length(args).ok_or(ErrorCode::INVAL)?;
+        let length = core::cmp::min(length, reply.hex_capacity());
+        self.with_attached(|process| {
+            let mut chunk = [0; 32];
+            let mut offset = 0;
+            while offset < length {
+                let len = core::cmp::min(chunk.len(), length - offset);
+                let read = process.debug_read_memory(
+                    address + offset,
+                    &mut chunk[..len],
+                    &self.capability,
+                );
+                if read == 0 {
+                    break;
+                }
+                reply.push_hex(&chunk[..read]);
+                offset += read;
+            }
+            if offset == 0 && length > 0 {
+                Err(ErrorCode::INVAL)
+            } else {
+                Ok(())
+            }
+        })
+        .unwrap_or(Err(ErrorCode::OFF))
+    }
+
+    fn write_memory(&self, args: &[u8]) -> Result<(), ErrorCode> {
+        let (range, data) = split_at_byte(args, b':').ok_or(ErrorCode::INVAL)?;
+        let (address, length) = parse_address_length(range).ok_or(ErrorCode::INVAL)?;
+        if data.len() != 2 * length {
+            return Err(ErrorCode::INVAL);
+        }
+        self.with_attached(|process| {
+            let mut chunk = [0; 32];
+            for (i, hex) in data.chunks(2 * chunk.len()).enumerate() {
+                let len = decode_hex(hex, &mut chunk).ok_or(ErrorCode::INVAL)?;
+                let offset = i * chunk.len();
+                if process.debug_write_memory(address + offset, &chunk[..len], &self.capability)
+                    != len
+                {
+                    return Err(ErrorCode::INVAL);
+                }
+            }
+            Ok(())
+        })
+        .unwrap_or(Err(ErrorCode::OFF))
+    }
+
+    fn insert_breakpoint(&self, args: &[u8]) -> Result<(), ErrorCode> {
+        let (address, kind) = parse_address_length(args).ok_or(ErrorCode::INVAL)?;
+        let instruction = self
+            .breakpoint_instructions
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, instruction)| *instruction)
+            .ok_or(ErrorCode::NOSUPPORT)?;
+        if self
+            .breakpoints
+            .iter()
+            .any(|bp| bp.get().map_or(false, |b| b.address == address))
+        {
+            return Ok(());
+        }
+        let slot = self
+            .breakpoints
+            .iter()
+            .find(|bp| bp.get().is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        let len = instruction.len();
+        let mut breakpoint = Breakpoint {
+            address,
+            len,
+            original: [0; 4],
+        };
+        self.with_attached(|process| {
+            if process.debug_read_memory(address, &mut breakpoint.original[..len], &self.capability)
+                != len
+                || process.debug_write_memory(address, instruction, &self.capability) != len
+            {
+                // The code is not in RAM.
+                return Err(ErrorCode::INVAL);
+            }
+            slot.set(Some(breakpoint));
+            Ok(())
+        })
+        .unwrap_or(Err(ErrorCode::OFF))
+    }
+
+    fn remove_breakpoint(&self, args: &[u8]) -> Result<(), ErrorCode> {
+        let (address, _) = parse_address_length(args).ok_or(ErrorCode::INVAL)?;
+        let slot = self
+            .breakpoints
+            .iter()
+            .find(|bp| bp.get().map_or(false, |b| b.address == address))
+            .ok_or(ErrorCode::INVAL)?;
+        if let Some(breakpoint) = slot.take() {
+            self.with_attached(|process| {
+                process.debug_write_memory(
+                    address,
+                    &breakpoint.original[..breakpoint.len],
+                    &self.capability,
+                )
+            });
+        }
+        Ok(())
+    }
+
+    /// Run a `monitor` command and write its hex encoded output.
+    fn monitor(&self, hex: &[u8], reply: &mut PacketWriter) {
+        let mut command = [0; 64];
+        let command = decode_hex(hex, &mut command)
+            .and_then(|len| core::str::from_utf8(&command[..len]).ok())
+            .unwrap_or("");
+        let mut args = command.split_whitespace();
+        match args.next() {
+            Some("ps") => {
+                let _ = writeln!(reply, "PID  Name                State");
+                self.kernel
+                    .process_each_capability(&self.capability, |process| {
+                        let _ = writeln!(
+                            reply,
+                            "{:<4} {:<19} {:?}",
+                            process.processid().id(),
+                            process.get_process_name(),
+                            process.get_state()
+                        );
+                    });
+            }
+            Some("attach") => match args.next().map(|name| self.attach_by_name(name)) {
+                Some(Ok(pid)) => {
+                    let _ = writeln!(reply, "Attached to process {}.", pid);
+                }
+                _ => {
+                    let _ = writeln!(reply, "No such process.");
+                }
+            },
+            _ => {
+                let _ = writeln!(reply, "Commands: ps, attach <name>");
+            }
+        }
+    }
+
+    fn handle_packet(&self, packet: &[u8], reply: &mut PacketWriter) -> Reply {
+        let (&command, args) = match packet.split_first() {
+            Some(split) => split,
+            None => return Reply::Packet,
+        };
+        let result = match command {
+            b'?' => {
+                if self.attached.is_some() {
+                    reply.push_str(b"S05");
+                } else {
+                    reply.push_str(b"W00");
+                }
+                return Reply::Packet;
+            }
+            b'q' => {
+                if args.starts_with(b"Supported") {
+                    reply.push_str(b"PacketSize=");
+                    for shift in (0..4).rev() {
+                        reply.push(HEX_DIGITS[(PACKET_BUF_LEN >> (shift * 4)) & 0xf]);
+                    }
+                } else if args == b"Attached" {
+                    reply.push_str(b"1");
+                } else if let Some(hex) = args.strip_prefix(b"Rcmd,") {
+                    self.monitor(hex, reply);
+                }
+                return Reply::Packet;
+            }
+            b'v' => {
+                if let Some(pid) = args.strip_prefix(b"Attach;") {
+                    match parse_hex(pid)
+                        .ok_or(ErrorCode::INVAL)
+                        .and_then(|pid| self.attach(pid))
+                    {
+                        Ok(()) => reply.push_str(b"S05"),
+                        Err(_) => reply.push_str(b"E01"),
+                    }
+                }
+                return Reply::Packet;
+            }
+            b'g' => self.read_registers(reply),
+            b'G' => self.write_registers(args),
+            b'p' => self.read_register(args, reply),
+            b'P' => self.write_register(args),
+            b'm' => self.read_memory(args, reply),
+            b'M' => self.write_memory(args),
+            b'Z' | b'z' => match args.split_first() {
+                Some((b'0', args)) => {
+                    let args = args.strip_prefix(b",").unwrap_or(args);
+                    if command == b'Z' {
+                        self.insert_breakpoint(args)
+                    } else {
+                        self.remove_breakpoint(args)
+                    }
+                }
+                // Other breakpoint and watchpoint types are not supported.
+                _ => return Reply::Packet,
+            },
+            b'c' | b's' => {
+                if self.attached.is_none() {
+                    Err(ErrorCode::OFF)
+                } else {
+                    self.resume(command == b's', args);
+                    return Reply::Resumed;
+                }
+            }
+            b'D' => {
+                self.detach();
+                Ok(())
+            }
+            b'k' => {
+                self.detach();
+                return Reply::Resumed;
+            }
+            _ => return Reply::Packet,
+        };
+        match result {
+            Ok(()) => {
+                if matches!(command, b'G' | b'P' | b'M' | b'Z' | b'z' | b'D') {
+                    reply.push_str(b"OK");
+                }
+            }
+            Err(_) => reply.push_str(b"E01"),
+        }
+        Reply::Packet
+    }
+
+    /// Process a complete packet and acknowledge it.
+    fn packet_received(&self) {
+        self.packet.take().map(|packet| {
+            let len = self.packet_len.get();
+            // If a stop reply is being sent, the packet is dropped. GDB sends
+            // it again because it was not acknowledged.
+            self.tx_buffer.take().map(|buffer| {
+                let mut writer = PacketWriter::new(buffer, true);
+                let reply = self.handle_packet(&packet[..len], &mut writer);
+                let len = match reply {
+                    Reply::Packet => writer.finish(),
+                    // Only send the acknowledgement.
+                    Reply::Resumed => 1,
+                };
+                self.tx_len.set(len);
+                self.transmit(buffer, len);
+            });
+            self.packet.replace(packet);
+        });
+    }
+
+    fn receive_byte(&self, byte: u8) {
+        match self.rx_state.get() {
+            RxState::Idle => match byte {
+                b'$' => {
+                    self.packet_len.set(0);
+                    self.rx_state.set(RxState::Data);
+                }
+                INTERRUPT => self.interrupt(),
+                b'-' => self.retransmit(),
+                _ => {}
+            },
+            RxState::Data => match byte {
+                b'#' => self.rx_state.set(RxState::Checksum),
+                b'$' => self.packet_len.set(0),
+                _ => {
+                    let len = self.packet_len.get();
+                    self.packet.map(|packet| {
+                        if len < packet.len() {
+                            packet[len] = byte;
+                        }
+                    });
+                    self.packet_len.set(len + 1);
+                }
+            },
+            RxState::Checksum => match hex_value(byte) {
+                Some(high) => self.rx_state.set(RxState::Checksum2(high)),
+                None => {
+                    self.rx_state.set(RxState::Idle);
+                    self.nack();
+                }
+            },
+            RxState::Checksum2(high) => {
+                self.rx_state.set(RxState::Idle);
+                let len = self.packet_len.get();
+                let valid = hex_value(byte).map_or(false, |low| {
+                    self.packet.map_or(false, |packet| {
+                        len <= packet.len() && checksum(&packet[..len]) == high << 4 | low
+                    })
+                });
+                if valid {
+                    self.packet_received();
+                } else {
+                    self.nack();
+                }
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability + ProcessDebugCapability>
+    time::AlarmClient for GdbStub<'a, A, C>
+{
+    fn alarm(&self) {
+        self.check_stopped();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability + ProcessDebugCapability>
+    uart::TransmitClient for GdbStub<'a, A, C>
+{
+    fn transmitted_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        _tx_len: usize,
+        _rcode: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(buffer);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability + ProcessDebugCapability>
+    uart::ReceiveClient for GdbStub<'a, A, C>
+{
+    fn received_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        rx_len: usize,
+        _rcode: Result<(), ErrorCode>,
+        error: uart::Error,
+    ) {
+        if error == uart::Error::None && rx_len > 0 {
+            self.receive_byte(buffer[0]);
+        }
+        if let Err((_, buffer)) = self.uart.receive_buffer(buffer, 1) {
+            self.rx_buffer.replace(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_numbers() {
+        assert_eq!(parse_hex(b"0"), Some(0));
+        assert_eq!(parse_hex(b"80000a3F"), Some(0x8000_0a3f));
+        assert_eq!(parse_hex(b""), None);
+        assert_eq!(parse_hex(b"12g"), None);
+        assert_eq!(
+            parse_address_length(b"20001000,40"),
+            Some((0x2000_1000, 0x40))
+        );
+        assert_eq!(parse_address_length(b"20001000"), None);
+    }
+
+    #[test]
+    fn decodes_hex_bytes() {
+        let mut out = [0; 4];
+        assert_eq!(decode_hex(b"0290aB", &mut out), Some(3));
+        assert_eq!(&out[..3], &[0x02, 0x90, 0xab]);
+        assert_eq!(decode_hex(b"029", &mut out), None);
+        assert_eq!(decode_hex(b"0011223344", &mut out), None);
+    }
+
+    #[test]
+    fn writes_packets_with_checksum() {
+        let mut buf = [0; 16];
+        let mut writer = PacketWriter::new(&mut buf, true);
+        writer.push_str(b"OK");
+        let len = writer.finish();
+        assert_eq!(&buf[..len], b"+$OK#9a");
+
+        let mut writer = PacketWriter::new(&mut buf, false);
+        writer.push_hex(&[0x12, 0xab]);
+        let len = writer.finish();
+        assert_eq!(&buf[..len], b"$12ab#26");
+        assert_eq!(checksum(b"12ab"), 0x26);
+    }
+
+    #[test]
+    fn truncates_long_packets() {
+        let mut buf = [0; 8];
+        let mut writer = PacketWriter::new(&mut buf, false);
+        assert_eq!(writer.hex_capacity(), 2);
+        writer.push_str(b"abcdefgh");
+        let len = writer.finish();
+        assert_eq!(len, 8);
+        assert_eq!(&buf[..5], b"$abcd");
+    }
+}
@@ -6,7 +6,9 @@
 //!
 //! Provides userspace access to key-value store. Access is restricted based on
 //! `StoragePermissions` so processes must have the required permissions in
-//! their TBF headers to use this interface.
+//! their TBF headers to use this interface. A get of a value that fails
+//! authentication completes with `NOACK` ([`kv::AUTHENTICATION_FAILED`]) for
+//! processes that may read the key, and like a missing key for all others.
 //!
 //! ```
 //! +===============+
@@ -39,16 +41,33 @@
 //!
 //!    hil::flash
 //! ```
+//!
+//! Transactions
+//! ------------
+//!
+//! Only one app can have a transaction open at a time, other apps that try to
+//! begin one get `BUSY` and have to retry later. So that an app cannot hold
+//! the transaction forever, it is aborted when another app tries to begin one
+//! and the app that opened it has exited or has not issued an operation for
+//! [`TRANSACTION_TIMEOUT_MS`]. Each operation of the app re-arms an alarm for
+//! the timeout, and the transaction expires when the alarm fires. Staging,
+//! committing or aborting an aborted transaction fails with `INVAL`.
 
 use capsules_core::driver;
 /// Syscall driver number.
 pub const DRIVER_NUM: usize = driver::NUM::Kv as usize;
 
+/// How long an app can leave its transaction idle before another app that
+/// wants to begin a transaction can abort it.
+pub const TRANSACTION_TIMEOUT_MS: u32 = 1000;
+
+use core::cell::Cell;
 use core::cmp;
 use kernel::errorcode;
 use kernel::grant::Grant;
 use kernel::grant::{AllowRoCount, AllowRwCount, UpcallCount};
 use kernel::hil::kv;
+use kernel::hil::time::{self, Alarm, ConvertTicks};
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
@@ -88,6 +107,21 @@ enum UserSpaceOp {
     Delete,
     Add,
     Update,
+    StageSet,
+    StageDelete,
+    Begin,
+    Commit,
+    Abort,
+}
+
+impl UserSpaceOp {
+    /// Whether the operation acts on the key in the `KEY` allow buffer.
+    fn uses_key(self) -> bool {
+        !matches!(
+            self,
+            UserSpaceOp::Begin | UserSpaceOp::Commit | UserSpaceOp::Abort
+        )
+    }
 }
 
 /// Contents of the grant for each app.
@@ -97,9 +131,11 @@ pub struct App {
 }
 
 /// Capsule that provides userspace access to a key-value store.
-pub struct KVStoreDriver<'a, V: kv::KVPermissions<'a>> {
+pub struct KVStoreDriver<'a, V: kv::KVPermissions<'a>, A: Alarm<'a>> {
     /// Underlying k-v store implementation.
     kv: &'a V,
+    /// Alarm for the transaction timeout.
+    alarm: &'a A,
     /// Grant storage for each app.
     apps: Grant<
         App,
@@ -109,15 +145,21 @@ pub struct KVStoreDriver<'a, V: kv::KVPermissions<'a>> {
     >,
     /// App that is actively using the k-v store.
     processid: OptionalCell<ProcessId>,
+    /// App that has the open transaction.
+    transaction: OptionalCell<ProcessId>,
+    /// Whether the app with the open transaction has not issued an operation
+    /// for `TRANSACTION_TIMEOUT_MS`.
+    transaction_expired: Cell<bool>,
     /// Key buffer.
     key_buffer: TakeCell<'static, [u8]>,
     /// Value buffer.
     value_buffer: TakeCell<'static, [u8]>,
 }
 
-impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
+impl<'a, V: kv::KVPermissions<'a>, A: Alarm<'a>> KVStoreDriver<'a, V, A> {
     pub fn new(
         kv: &'a V,
+        alarm: &'a A,
         key_buffer: &'static mut [u8],
         value_buffer: &'static mut [u8],
         grant: Grant<
@@ -126,22 +168,70 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
             AllowRoCount<{ ro_allow::COUNT }>,
             AllowRwCount<{ rw_allow::COUNT }>,
         >,
-    ) -> KVStoreDriver<'a, V> {
+    ) -> KVStoreDriver<'a, V, A> {
         KVStoreDriver {
             kv,
+            alarm,
             apps: grant,
             processid: OptionalCell::empty(),
+            transaction: OptionalCell::empty(),
+            transaction_expired: Cell::new(false),
             key_buffer: TakeCell::new(key_buffer),
             value_buffer: TakeCell::new(value_buffer),
         }
     }
 
+    /// Whether the transaction of `owner` can still make progress, that is
+    /// the process is alive and has a queued operation or issued one within
+    /// the last `TRANSACTION_TIMEOUT_MS`.
+    fn transaction_alive(&self, owner: ProcessId) -> bool {
+        self.apps
+            .enter(owner, |app, _| {
+                app.op.is_some() || !self.transaction_expired.get()
+            })
+            .unwrap_or(false)
+    }
+
+    /// Restart the timeout of the open transaction.
+    fn transaction_touched(&self) {
+        self.transaction_expired.set(false);
+        self.alarm.set_alarm(
+            self.alarm.now(),
+            self.alarm.ticks_from_ms(TRANSACTION_TIMEOUT_MS),
+        );
+    }
+
+    /// Begin a transaction for `processid`. If the process that has the open
+    /// transaction has exited or left it idle for too long, its transaction
+    /// is aborted first and `transaction_complete()` then begins the new one.
+    fn begin_transaction(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let perms = processid
+            .get_storage_permissions()
+            .ok_or(ErrorCode::INVAL)?;
+
+        match self.transaction.get() {
+            Some(owner) if owner == processid => Err(ErrorCode::ALREADY),
+            Some(owner) if self.transaction_alive(owner) => Err(ErrorCode::BUSY),
+            Some(_) => self.kv.abort_transaction(),
+            None => {
+                self.kv.begin_transaction(perms)?;
+                self.transaction.set(processid);
+                self.transaction_touched();
+                Ok(())
+            }
+        }
+    }
+
     fn run(&self) -> Result<(), ErrorCode> {
         self.processid.map_or(Err(ErrorCode::RESERVE), |processid| {
+            if self.transaction.contains(&processid) {
+                self.transaction_touched();
+            }
+
             self.apps
                 .enter(processid, |app, kernel_data| {
-                    let key_len = if app.op.is_some() {
-                        // For all operations we need to copy in the key.
+                    let key_len = if app.op.get().map_or(false, UserSpaceOp::uses_key) {
+                        // For all key operations we need to copy in the key.
                         kernel_data
                             .get_readonly_processbuffer(ro_allow::KEY)
                             .and_then(|buffer| {
@@ -190,7 +280,16 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
                         }
                         Some(UserSpaceOp::Set)
                         | Some(UserSpaceOp::Add)
-                        | Some(UserSpaceOp::Update) => {
+                        | Some(UserSpaceOp::Update)
+                        | Some(UserSpaceOp::StageSet) => {
+                            // Only the app that began the transaction can
+                            // stage operations in it.
+                            if app.op.contains(&UserSpaceOp::StageSet)
+                                && !self.transaction.contains(&processid)
+                            {
+                                return Err(ErrorCode::INVAL);
+                            }
+
                             let value_len = kernel_data
                                 .get_readonly_processbuffer(ro_allow::VALUE)
                                 .and_then(|buffer| {
@@ -236,6 +335,9 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
                                         Some(UserSpaceOp::Update) => {
                                             self.kv.update(key, value, perms)
                                         }
+                                        Some(UserSpaceOp::StageSet) => {
+                                            self.kv.stage_set(key, value, perms)
+                                        }
                                         _ => Ok(()),
                                     } {
                                         self.key_buffer.replace(key_ret.take());
@@ -248,7 +350,13 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
                                 return e;
                             }
                         }
-                        Some(UserSpaceOp::Delete) => {
+                        Some(UserSpaceOp::Delete) | Some(UserSpaceOp::StageDelete) => {
+                            if app.op.contains(&UserSpaceOp::StageDelete)
+                                && !self.transaction.contains(&processid)
+                            {
+                                return Err(ErrorCode::INVAL);
+                            }
+
                             if let Some(e) = self.key_buffer.take().map(|key_buf| {
                                 let perms = processid
                                     .get_storage_permissions()
@@ -257,7 +365,12 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
                                 let mut key = SubSliceMut::new(key_buf);
                                 key.slice(..key_len);
 
-                                if let Err((key_ret, e)) = self.kv.delete(key, perms) {
+                                let ret = if app.op.contains(&UserSpaceOp::Delete) {
+                                    self.kv.delete(key, perms)
+                                } else {
+                                    self.kv.stage_delete(key, perms)
+                                };
+                                if let Err((key_ret, e)) = ret {
                                     self.key_buffer.replace(key_ret.take());
                                     return Err(e);
                                 }
@@ -266,7 +379,19 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
                                 return e;
                             }
                         }
-                        _ => {}
+                        Some(UserSpaceOp::Begin) => self.begin_transaction(processid)?,
+                        Some(UserSpaceOp::Commit) | Some(UserSpaceOp::Abort) => {
+                            if !self.transaction.contains(&processid) {
+                                return Err(ErrorCode::INVAL);
+                            }
+
+                            if app.op.contains(&UserSpaceOp::Commit) {
+                                self.kv.commit_transaction()?;
+                            } else {
+                                self.kv.abort_transaction()?;
+                            }
+                        }
+                        None => {}
                     }
 
                     Ok(())
@@ -303,7 +428,7 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
     }
 }
 
-impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for KVStoreDriver<'a, V> {
+impl<'a, V: kv::KVPermissions<'a>, A: Alarm<'a>> kv::KVClient for KVStoreDriver<'a, V, A> {
     fn get_complete(
         &self,
         result: Result<(), ErrorCode>,
@@ -377,7 +502,7 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for KVStoreDriver<'a, V> {
         // Signal the upcall and clear the requested op.
         self.processid.map(move |id| {
             self.apps.enter(id, move |app, upcalls| {
-                if app.op.contains(&UserSpaceOp::Set) {
+                if app.op.contains(&UserSpaceOp::Set) || app.op.contains(&UserSpaceOp::StageSet) {
                     app.op.clear();
                     upcalls
                         .schedule_upcall(upcalls::VALUE, (errorcode::into_statuscode(result), 0, 0))
@@ -451,7 +576,9 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for KVStoreDriver<'a, V> {
 
         self.processid.map(move |id| {
             self.apps.enter(id, move |app, upcalls| {
-                if app.op.contains(&UserSpaceOp::Delete) {
+                if app.op.contains(&UserSpaceOp::Delete)
+                    || app.op.contains(&UserSpaceOp::StageDelete)
+                {
                     app.op.clear();
                     upcalls
                         .schedule_upcall(upcalls::VALUE, (errorcode::into_statuscode(result), 0, 0))
@@ -465,9 +592,67 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for KVStoreDriver<'a, V> {
         self.processid.clear();
         self.check_queue();
     }
+
+    fn transaction_complete(&self, result: Result<(), ErrorCode>) {
+        let finished = self.processid.map_or(true, |id| {
+            self.apps
+                .enter(id, |app, upcalls| {
+                    let result = match app.op.get() {
+                        Some(UserSpaceOp::Begin) if !self.transaction.contains(&id) => {
+                            // This aborted the transaction of an app that
+                            // exited or left it idle, now begin the one that
+                            // was requested.
+                            self.transaction.clear();
+                            match result.and_then(|()| self.begin_transaction(id)) {
+                                Ok(()) => return false,
+                                Err(e) => Err(e),
+                            }
+                        }
+                        Some(UserSpaceOp::Begin) => {
+                            if result.is_err() {
+                                self.transaction.clear();
+                            }
+                            result
+                        }
+                        Some(UserSpaceOp::Commit) | Some(UserSpaceOp::Abort) => {
+                            // A failed commit leaves the transaction open so
+                            // that the app can still abort it.
+                            if result.is_ok() {
+                                self.transaction.clear();
+                            }
+                            result
+                        }
+                        _ => return true,
+                    };
+
+                    app.op.clear();
+                    upcalls
+                        .schedule_upcall(upcalls::VALUE, (errorcode::into_statuscode(result), 0, 0))
+                        .ok();
+                    true
+                })
+                .unwrap_or(true)
+        });
+
+        // We have completed the operation so see if there is a queued operation
+        // to run next.
+        if finished {
+            self.processid.clear();
+            self.check_queue();
+        }
+    }
+}
+
+impl<'a, V: kv::KVPermissions<'a>, A: Alarm<'a>> time::AlarmClient for KVStoreDriver<'a, V, A> {
+    fn alarm(&self) {
+        // The next app that tries to begin a transaction can abort this one.
+        if self.transaction.is_some() {
+            self.transaction_expired.set(true);
+        }
+    }
 }
 
-impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
+impl<'a, V: kv::KVPermissions<'a>, A: Alarm<'a>> SyscallDriver for KVStoreDriver<'a, V, A> {
     fn command(
         &self,
         command_num: usize,
@@ -479,8 +664,9 @@ impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
             // check if present
             0 => CommandReturn::success(),
 
-            // get, set, delete, add, update
-            1 | 2 | 3 | 4 | 5 => {
+            // get, set, delete, add, update, begin transaction, stage set,
+            // stage delete, commit transaction, abort transaction
+            1..=10 => {
                 if self.processid.is_none() {
                     // Nothing is using the KV store, so we can handle this
                     // request.
@@ -491,6 +677,11 @@ impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
                         3 => app.op.set(UserSpaceOp::Delete),
                         4 => app.op.set(UserSpaceOp::Add),
                         5 => app.op.set(UserSpaceOp::Update),
+                        6 => app.op.set(UserSpaceOp::Begin),
+                        7 => app.op.set(UserSpaceOp::StageSet),
+                        8 => app.op.set(UserSpaceOp::StageDelete),
+                        9 => app.op.set(UserSpaceOp::Commit),
+                        10 => app.op.set(UserSpaceOp::Abort),
                         _ => {}
                     });
                     let ret = self.run();
@@ -520,6 +711,11 @@ impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
                                     3 => app.op.set(UserSpaceOp::Delete),
                                     4 => app.op.set(UserSpaceOp::Add),
                                     5 => app.op.set(UserSpaceOp::Update),
+                                    6 => app.op.set(UserSpaceOp::Begin),
+                                    7 => app.op.set(UserSpaceOp::StageSet),
+                                    8 => app.op.set(UserSpaceOp::StageDelete),
+                                    9 => app.op.set(UserSpaceOp::Commit),
+                                    10 => app.op.set(UserSpaceOp::Abort),
                                     _ => {}
                                 }
                                 CommandReturn::success()
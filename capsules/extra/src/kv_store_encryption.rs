@@ -0,0 +1,962 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Tock Key-Value store capsule which encrypts values at rest.
+//!
+//! This capsule sits between the permissions layer and the K-V store and
+//! authenticates and encrypts each value with AES-128-CCM under a per-device
+//! key, so that reading the flash does not reveal the stored values.
+//!
+//! ```
+//! +-----------------------+
+//! |  K-V store Permissions|
+//! +-----------------------+
+//!
+//!    hil::kv::KV
+//!
+//! +-----------------------+
+//! | Encryption (this file)|
+//! +-----------------------+
+//!
+//!    hil::kv::KV
+//!
+//! +-----------------------+
+//! |  K-V store            |
+//! +-----------------------+
+//! ```
+//!
+//! Each value is stored as a record:
+//!
+//! ```text
+//! +---------+------------+------------+-------------------+-----------+
+//! | version | length (2) | nonce (13) | value (length)    | MIC (16)  |
+//! +---------+------------+------------+-------------------+-----------+
+//! ```
+//!
+//! The first `kv_store_permissions::HEADER_LENGTH` bytes of the value are the
+//! permissions header, which holds the `write_id` of the owner. The header is
+//! stored in the clear, the rest of the value is encrypted. The associated
+//! data of the MIC is the key, the record header and the permissions header,
+//! so a record which is modified, moved to another key or given another owner
+//! fails authentication. `get` reports `kv::AUTHENTICATION_FAILED` for such a
+//! record, with just the permissions header in the value so that the
+//! permissions layer can check whether the caller may learn about it.
+//!
+//! The nonce is read from the random number generator for every value that
+//! is written.
+
+use crate::kv_store_permissions::HEADER_LENGTH;
+use core::cell::Cell;
+use kernel::hil::kv;
+use kernel::hil::rng;
+use kernel::hil::symmetric_encryption::{CCMClient, AES128CCM, CCM_NONCE_LENGTH};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Current version of the record format.
+const RECORD_VERSION: u8 = 0;
+/// Length of the version, value length and nonce in front of the value.
+const RECORD_HEADER_LENGTH: usize = 3 + CCM_NONCE_LENGTH;
+/// Length of the message integrity code after the value.
+pub const MIC_LENGTH: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operation {
+    Get,
+    Set,
+    Add,
+    Update,
+    StageSet,
+    Delete,
+    StageDelete,
+    Transaction,
+}
+
+/// Key-Value store layer which encrypts values at rest.
+///
+/// Implements `KV` on top of `KV`.
+pub struct KVStoreEncryption<'a, K: kv::KV<'a>, A: AES128CCM<'a>, R: rng::Rng<'a>> {
+    kv: &'a K,
+    aes: &'a A,
+    rng: &'a R,
+
+    client: OptionalCell<&'a dyn kv::KVClient>,
+    operation: OptionalCell<Operation>,
+
+    /// Buffer the record is built in and read into. While the record is
+    /// encrypted or decrypted the key is in front of it.
+    record: TakeCell<'static, [u8]>,
+    /// Length of the key in front of the record.
+    key_length: Cell<usize>,
+    /// Length of the value in the record.
+    value_length: Cell<usize>,
+
+    key: MapCell<SubSliceMut<'static, u8>>,
+    value: MapCell<SubSliceMut<'static, u8>>,
+}
+
+impl<'a, K: kv::KV<'a>, A: AES128CCM<'a>, R: rng::Rng<'a>> KVStoreEncryption<'a, K, A, R> {
+    /// Create the encryption layer. The CCM implementation must already have
+    /// the device key set. `record` limits the size of the key and value
+    /// that can be stored, the record and the key have to fit in it.
+    pub fn new(
+        kv: &'a K,
+        aes: &'a A,
+        rng: &'a R,
+        record: &'static mut [u8],
+    ) -> KVStoreEncryption<'a, K, A, R> {
+        Self {
+            kv,
+            aes,
+            rng,
+            client: OptionalCell::empty(),
+            operation: OptionalCell::empty(),
+            record: TakeCell::new(record),
+            key_length: Cell::new(0),
+            value_length: Cell::new(0),
+            key: MapCell::empty(),
+            value: MapCell::empty(),
+        }
+    }
+
+    fn insert(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+        operation: Operation,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        if self.operation.is_some() {
+            return Err((key, value, ErrorCode::BUSY));
+        }
+
+        // The permissions header is stored in the clear, so it has to be
+        // there, and the key and record have to fit in the record buffer.
+        let record_length = key.len() + RECORD_HEADER_LENGTH + value.len() + MIC_LENGTH;
+        if value.len() < HEADER_LENGTH
+            || value.len() > u16::MAX as usize
+            || self.record.map_or(0, |record| record.len()) < record_length
+        {
+            return Err((key, value, ErrorCode::SIZE));
+        }
+
+        self.operation.set(operation);
+        self.key.replace(key);
+        self.value.replace(value);
+
+        // Get a fresh nonce for the record first.
+        self.rng.get().map_err(|e| {
+            self.operation.clear();
+            (self.key.take().unwrap(), self.value.take().unwrap(), e)
+        })
+    }
+
+    fn remove(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        operation: Operation,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        if self.operation.is_some() {
+            return Err((key, ErrorCode::BUSY));
+        }
+
+        self.operation.set(operation);
+        let ret = if operation == Operation::Delete {
+            self.kv.delete(key)
+        } else {
+            self.kv.stage_delete(key)
+        };
+        ret.map_err(|e| {
+            self.operation.clear();
+            e
+        })
+    }
+
+    fn transaction(&self, start: impl FnOnce() -> Result<(), ErrorCode>) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.operation.set(Operation::Transaction);
+        start().map_err(|e| {
+            self.operation.clear();
+            e
+        })
+    }
+
+    /// Offset of the encrypted part of the value while the key is in front
+    /// of the record.
+    fn message_offset(&self) -> usize {
+        self.key_length.get() + RECORD_HEADER_LENGTH + HEADER_LENGTH
+    }
+
+    /// Build the record behind the key and start encrypting it.
+    fn encrypt(&self, nonce: [u8; CCM_NONCE_LENGTH]) -> Result<(), ErrorCode> {
+        let record = self.record.take().ok_or(ErrorCode::FAIL)?;
+
+        let lengths = self
+            .key
+            .map(|key| {
+                self.value.map(|value| {
+                    let key_length = key.len();
+                    let value_length = value.len();
+                    record[..key_length].copy_from_slice(key.as_slice());
+
+                    let header = &mut record[key_length..key_length + RECORD_HEADER_LENGTH];
+                    header[0] = RECORD_VERSION;
+                    header[1..3].copy_from_slice(&(value_length as u16).to_le_bytes());
+                    header[3..].copy_from_slice(&nonce);
+
+                    let value_offset = key_length + RECORD_HEADER_LENGTH;
+                    record[value_offset..value_offset + value_length]
+                        .copy_from_slice(value.as_slice());
+                    (key_length, value_length)
+                })
+            })
+            .flatten();
+        let (key_length, value_length) = match lengths {
+            Some(lengths) => lengths,
+            None => {
+                self.record.replace(record);
+                return Err(ErrorCode::FAIL);
+            }
+        };
+        self.key_length.set(key_length);
+        self.value_length.set(value_length);
+
+        self.crypt(record, nonce, true)
+    }
+
+    /// Move the record read from the store behind the key and start
+    /// decrypting it.
+    fn decrypt(&self, record: &'static mut [u8]) -> Result<(), ErrorCode> {
+        let key_length = self.key.map_or(0, |key| key.len());
+        let value_length = u16::from_le_bytes([record[1], record[2]]) as usize;
+        let record_length = RECORD_HEADER_LENGTH + value_length + MIC_LENGTH;
+
+        // A record which doesn't parse has been tampered with as well.
+        if record[0] != RECORD_VERSION
+            || value_length < HEADER_LENGTH
+            || key_length + record_length > record.len()
+        {
+            self.copy_header(
+                record
+                    .get(RECORD_HEADER_LENGTH..RECORD_HEADER_LENGTH + HEADER_LENGTH)
+                    .unwrap_or(&[0; HEADER_LENGTH]),
+            );
+            self.record.replace(record);
+            return Err(kv::AUTHENTICATION_FAILED);
+        }
+
+        let mut nonce = [0; CCM_NONCE_LENGTH];
+        nonce.copy_from_slice(&record[3..RECORD_HEADER_LENGTH]);
+
+        record.copy_within(..record_length, key_length);
+        self.key
+            .map(|key| record[..key_length].copy_from_slice(key.as_slice()));
+        self.key_length.set(key_length);
+        self.value_length.set(value_length);
+
+        self.crypt(record, nonce, false)
+    }
+
+    fn crypt(
+        &self,
+        record: &'static mut [u8],
+        nonce: [u8; CCM_NONCE_LENGTH],
+        encrypting: bool,
+    ) -> Result<(), ErrorCode> {
+        if let Err(e) = self.aes.set_nonce(&nonce) {
+            self.record.replace(record);
+            return Err(e);
+        }
+
+        let message_offset = self.message_offset();
+        self.aes
+            .crypt(
+                record,
+                0,
+                message_offset,
+                self.value_length.get() - HEADER_LENGTH,
+                MIC_LENGTH,
+                true,
+                encrypting,
+            )
+            .map_err(|(e, record)| {
+                self.record.replace(record);
+                e
+            })
+    }
+
+    /// Hand the encrypted record to the store.
+    fn store(&self, op: Operation, record: &'static mut [u8]) -> Result<(), ErrorCode> {
+        // The store writes the record from the start of the buffer, so move
+        // it in front of the key.
+        let key_length = self.key_length.get();
+        let record_length = RECORD_HEADER_LENGTH + self.value_length.get() + MIC_LENGTH;
+        record.copy_within(key_length..key_length + record_length, 0);
+
+        let mut value = SubSliceMut::new(record);
+        value.slice(..record_length);
+
+        let key = match self.key.take() {
+            Some(key) => key,
+            None => {
+                self.record.replace(value.take());
+                return Err(ErrorCode::FAIL);
+            }
+        };
+
+        let ret = match op {
+            Operation::Set => self.kv.set(key, value),
+            Operation::Add => self.kv.add(key, value),
+            Operation::Update => self.kv.update(key, value),
+            _ => self.kv.stage_set(key, value),
+        };
+        ret.map_err(|(key, value, e)| {
+            self.key.replace(key);
+            self.record.replace(value.take());
+            e
+        })
+    }
+
+    /// Return the caller's buffers for a set, add, update or staged set.
+    fn insert_complete(&self, op: Operation, result: Result<(), ErrorCode>) {
+        self.operation.clear();
+        self.key.take().map(|key| {
+            self.value.take().map(|value| {
+                self.client.map(move |cb| match op {
+                    Operation::Add => cb.add_complete(result, key, value),
+                    Operation::Update => cb.update_complete(result, key, value),
+                    _ => cb.set_complete(result, key, value),
+                });
+            });
+        });
+    }
+
+    /// Leave only the unauthenticated permissions `header` in the value of a
+    /// record which failed authentication.
+    fn copy_header(&self, header: &[u8]) {
+        self.value.map(|value| {
+            let length = core::cmp::min(header.len(), value.len());
+            value.as_slice()[..length].copy_from_slice(&header[..length]);
+            value.slice(..length);
+        });
+    }
+
+    fn get_done(&self, result: Result<(), ErrorCode>) {
+        self.operation.clear();
+        self.key.take().map(|key| {
+            self.value.take().map(|value| {
+                self.client.map(move |cb| {
+                    cb.get_complete(result, key, value);
+                });
+            });
+        });
+    }
+}
+
+impl<'a, K: kv::KV<'a>, A: AES128CCM<'a>, R: rng::Rng<'a>> kv::KV<'a>
+    for KVStoreEncryption<'a, K, A, R>
+{
+    fn set_client(&self, client: &'a dyn kv::KVClient) {
+        self.client.set(client);
+    }
+
+    fn get(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        if self.operation.is_some() {
+            return Err((key, value, ErrorCode::BUSY));
+        }
+
+        let record = match self.record.take() {
+            Some(record) => record,
+            None => return Err((key, value, ErrorCode::FAIL)),
+        };
+
+        self.operation.set(Operation::Get);
+
+        match self.kv.get(key, SubSliceMut::new(record)) {
+            Ok(()) => {
+                self.value.replace(value);
+                Ok(())
+            }
+            Err((key, record, e)) => {
+                self.record.replace(record.take());
+                self.operation.clear();
+                Err((key, value, e))
+            }
+        }
+    }
+
+    fn set(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        self.insert(key, value, Operation::Set)
+    }
+
+    fn add(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        self.insert(key, value, Operation::Add)
+    }
+
+    fn update(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        self.insert(key, value, Operation::Update)
+    }
+
+    fn delete(
+        &self,
+        key: SubSliceMut<'static, u8>,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        self.remove(key, Operation::Delete)
+    }
+
+    fn begin_transaction(&self) -> Result<(), ErrorCode> {
+        self.transaction(|| self.kv.begin_transaction())
+    }
+
+    fn stage_set(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        self.insert(key, value, Operation::StageSet)
+    }
+
+    fn stage_delete(
+        &self,
+        key: SubSliceMut<'static, u8>,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        self.remove(key, Operation::StageDelete)
+    }
+
+    fn commit_transaction(&self) -> Result<(), ErrorCode> {
+        self.transaction(|| self.kv.commit_transaction())
+    }
+
+    fn abort_transaction(&self) -> Result<(), ErrorCode> {
+        self.transaction(|| self.kv.abort_transaction())
+    }
+}
+
+impl<'a, K: kv::KV<'a>, A: AES128CCM<'a>, R: rng::Rng<'a>> rng::Client
+    for KVStoreEncryption<'a, K, A, R>
+{
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> rng::Continue {
+        let op = match self.operation.get() {
+            Some(op) => op,
+            None => return rng::Continue::Done,
+        };
+
+        let mut words = [0u32; (CCM_NONCE_LENGTH + 3) / 4];
+        for word in words.iter_mut() {
+            match randomness.next() {
+                Some(random) => *word = random,
+                None if error.is_ok() => return rng::Continue::More,
+                None => break,
+            }
+        }
+
+        let ret = error.and_then(|()| {
+            let mut nonce = [0; CCM_NONCE_LENGTH];
+            for (bytes, word) in nonce.chunks_mut(4).zip(words.iter()) {
+                bytes.copy_from_slice(&word.to_le_bytes()[..bytes.len()]);
+            }
+            self.encrypt(nonce)
+        });
+        if let Err(e) = ret {
+            self.insert_complete(op, Err(e));
+        }
+
+        rng::Continue::Done
+    }
+}
+
+impl<'a, K: kv::KV<'a>, A: AES128CCM<'a>, R: rng::Rng<'a>> CCMClient
+    for KVStoreEncryption<'a, K, A, R>
+{
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
+        self.operation.map(|op| match op {
+            Operation::Get => {
+                let value_length = self.value_length.get();
+                let value_offset = self.key_length.get() + RECORD_HEADER_LENGTH;
+
+                let result = match res {
+                    Ok(()) if tag_is_valid => self.value.map_or(Err(ErrorCode::FAIL), |value| {
+                        let copy_length = core::cmp::min(value_length, value.len());
+                        value.as_slice()[..copy_length]
+                            .copy_from_slice(&buf[value_offset..value_offset + copy_length]);
+                        value.slice(..copy_length);
+                        if copy_length < value_length {
+                            Err(ErrorCode::SIZE)
+                        } else {
+                            Ok(())
+                        }
+                    }),
+                    // The record was modified, moved to another key or given
+                    // another owner.
+                    Ok(()) => {
+                        self.copy_header(&buf[value_offset..value_offset + HEADER_LENGTH]);
+                        Err(kv::AUTHENTICATION_FAILED)
+                    }
+                    Err(_) => Err(ErrorCode::FAIL),
+                };
+
+                // Don't leave the plaintext in the record buffer.
+                buf.iter_mut().for_each(|b| *b = 0);
+                self.record.replace(buf);
+                self.get_done(result);
+            }
+            Operation::Set | Operation::Add | Operation::Update | Operation::StageSet => {
+                let ret = res.and_then(|()| self.store(op, buf));
+                if let Err(e) = ret {
+                    self.insert_complete(op, Err(e));
+                }
+            }
+            Operation::Delete | Operation::StageDelete | Operation::Transaction => {
+                self.record.replace(buf);
+            }
+        });
+    }
+}
+
+impl<'a, K: kv::KV<'a>, A: AES128CCM<'a>, R: rng::Rng<'a>> kv::KVClient
+    for KVStoreEncryption<'a, K, A, R>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        record: SubSliceMut<'static, u8>,
+    ) {
+        self.key.replace(key);
+        let record = record.take();
+
+        match result {
+            Ok(()) => {
+                if let Err(e) = self.decrypt(record) {
+                    self.get_done(Err(e));
+                }
+            }
+            Err(e) => {
+                self.record.replace(record);
+                // A record that doesn't fit in the buffer can't be
+                // authenticated.
+                self.get_done(Err(match e {
+                    ErrorCode::SIZE => ErrorCode::FAIL,
+                    e => e,
+                }));
+            }
+        }
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        record: SubSliceMut<'static, u8>,
+    ) {
+        self.key.replace(key);
+        self.record.replace(record.take());
+        self.operation.map(|op| self.insert_complete(op, result));
+    }
+
+    fn add_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        record: SubSliceMut<'static, u8>,
+    ) {
+        self.key.replace(key);
+        self.record.replace(record.take());
+        self.insert_complete(Operation::Add, result);
+    }
+
+    fn update_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        record: SubSliceMut<'static, u8>,
+    ) {
+        self.key.replace(key);
+        self.record.replace(record.take());
+        self.insert_complete(Operation::Update, result);
+    }
+
+    fn delete_complete(&self, result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.operation.clear();
+        self.client.map(move |cb| {
+            cb.delete_complete(result, key);
+        });
+    }
+
+    fn transaction_complete(&self, result: Result<(), ErrorCode>) {
+        self.operation.clear();
+        self.client.map(move |cb| {
+            cb.transaction_complete(result);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! The store, the cipher and the random number generator complete their
+    //! operations when pumped, like hardware interrupts would. The cipher is a
+    //! stand-in for AES-CCM: it is not secure, but it only accepts a record
+    //! that has the same key, nonce, associated data and message as when it
+    //! was encrypted, which is what the layer relies on.
+
+    extern crate std;
+
+    use super::*;
+    use crate::test::kv_fixture::{self, leak, Buffers, KVRecorder, Pump, RamKV};
+    use kernel::hil::kv::KV;
+    use std::vec;
+    use std::vec::Vec;
+
+    const KEY: [u8; 16] = *b"device-key-0123!";
+
+    /// Stand-in for AES-CCM which completes the pending operation when pumped.
+    #[derive(Default)]
+    struct SimCCM {
+        key: Cell<[u8; 16]>,
+        nonce: Cell<[u8; CCM_NONCE_LENGTH]>,
+        pending: Cell<Option<(usize, usize, usize, usize, bool)>>,
+        buf: OptionalCell<&'static mut [u8]>,
+        client: OptionalCell<&'static dyn CCMClient>,
+    }
+
+    impl SimCCM {
+        fn keystream(&self, i: usize) -> u8 {
+            self.key.get()[i % 16] ^ self.nonce.get()[i % CCM_NONCE_LENGTH] ^ (i as u8)
+        }
+
+        fn mic(&self, aad: &[u8], message: &[u8]) -> [u8; MIC_LENGTH] {
+            let mut state: u64 = 0xcbf29ce484222325;
+            let key = self.key.get();
+            let nonce = self.nonce.get();
+            for (i, b) in key
+                .iter()
+                .chain(nonce.iter())
+                .chain(core::iter::once(&(aad.len() as u8)))
+                .chain(aad)
+                .chain(message)
+                .enumerate()
+            {
+                state = (state ^ *b as u64 ^ (i as u64) << 8).wrapping_mul(0x100000001b3);
+            }
+            let mut mic = [0; MIC_LENGTH];
+            mic[..8].copy_from_slice(&state.to_le_bytes());
+            mic[8..].copy_from_slice(&state.wrapping_mul(0x100000001b3).to_be_bytes());
+            mic
+        }
+    }
+
+    impl Pump for SimCCM {
+        fn pump(&self) -> bool {
+            let (a_off, m_off, m_len, mic_len, encrypting) = match self.pending.take() {
+                Some(pending) => pending,
+                None => return false,
+            };
+            let buf = self.buf.take().unwrap();
+            let mic_off = m_off + m_len;
+
+            let valid = if encrypting {
+                let mic = self.mic(&buf[a_off..m_off], &buf[m_off..mic_off]);
+                buf[mic_off..mic_off + mic_len].copy_from_slice(&mic[..mic_len]);
+                for i in 0..m_len {
+                    buf[m_off + i] ^= self.keystream(i);
+                }
+                true
+            } else {
+                for i in 0..m_len {
+                    buf[m_off + i] ^= self.keystream(i);
+                }
+                let mic = self.mic(&buf[a_off..m_off], &buf[m_off..mic_off]);
+                buf[mic_off..mic_off + mic_len] == mic[..mic_len]
+            };
+            self.client
+                .map(|client| client.crypt_done(buf, Ok(()), valid));
+            true
+        }
+    }
+
+    impl AES128CCM<'static> for SimCCM {
+        fn set_client(&self, client: &'static dyn CCMClient) {
+            self.client.set(client);
+        }
+
+        fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+            self.key.set(key.try_into().map_err(|_| ErrorCode::INVAL)?);
+            Ok(())
+        }
+
+        fn set_nonce(&self, nonce: &[u8]) -> Result<(), ErrorCode> {
+            self.nonce
+                .set(nonce.try_into().map_err(|_| ErrorCode::INVAL)?);
+            Ok(())
+        }
+
+        fn crypt(
+            &self,
+            buf: &'static mut [u8],
+            a_off: usize,
+            m_off: usize,
+            m_len: usize,
+            mic_len: usize,
+            _confidential: bool,
+            encrypting: bool,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            if m_off + m_len + mic_len > buf.len() {
+                return Err((ErrorCode::INVAL, buf));
+            }
+            self.buf.set(buf);
+            self.pending
+                .set(Some((a_off, m_off, m_len, mic_len, encrypting)));
+            Ok(())
+        }
+    }
+
+    /// Counter which completes the pending request when pumped.
+    #[derive(Default)]
+    struct SimRng {
+        next: Cell<u32>,
+        pending: Cell<bool>,
+        client: OptionalCell<&'static dyn rng::Client>,
+    }
+
+    impl Pump for SimRng {
+        fn pump(&self) -> bool {
+            if !self.pending.take() {
+                return false;
+            }
+            let mut randomness = (0..).map(|_| {
+                self.next.set(self.next.get().wrapping_add(0x9e3779b9));
+                self.next.get()
+            });
+            self.client
+                .map(|client| client.randomness_available(&mut randomness, Ok(())));
+            true
+        }
+    }
+
+    impl rng::Rng<'static> for SimRng {
+        fn get(&self) -> Result<(), ErrorCode> {
+            self.pending.set(true);
+            Ok(())
+        }
+
+        fn cancel(&self) -> Result<(), ErrorCode> {
+            self.pending.set(false);
+            Ok(())
+        }
+
+        fn set_client(&'static self, client: &'static dyn rng::Client) {
+            self.client.set(client);
+        }
+    }
+
+    struct Harness {
+        kv: &'static RamKV,
+        ccm: &'static SimCCM,
+        rng: &'static SimRng,
+        store: &'static KVStoreEncryption<'static, RamKV, SimCCM, SimRng>,
+        client: &'static KVRecorder,
+    }
+
+    impl Harness {
+        fn new() -> Harness {
+            let kv = leak(RamKV::default());
+            let ccm = leak(SimCCM::default());
+            let rng = leak(SimRng::default());
+            let client = leak(KVRecorder::default());
+            ccm.set_key(&KEY).unwrap();
+            let store = leak(KVStoreEncryption::new(kv, ccm, rng, leak([0; 128])));
+            kv.set_client(store);
+            ccm.set_client(store);
+            rng::Rng::set_client(rng, store);
+            store.set_client(client);
+            Harness {
+                kv,
+                ccm,
+                rng,
+                store,
+                client,
+            }
+        }
+
+        fn run(&self) -> (Result<(), ErrorCode>, Buffers) {
+            kv_fixture::run(&[self.rng, self.ccm, self.kv]);
+            self.client.take().expect("no callback")
+        }
+
+        /// Store `data` behind a permissions header with the given owner.
+        fn set(&self, key: &[u8], owner: u8, data: &[u8]) -> Result<(), ErrorCode> {
+            let mut value = vec![0; HEADER_LENGTH];
+            value[HEADER_LENGTH - 4] = owner;
+            value.extend_from_slice(data);
+            self.store
+                .set(
+                    SubSliceMut::new(leak(key.to_vec()).as_mut_slice()),
+                    SubSliceMut::new(leak(value).as_mut_slice()),
+                )
+                .unwrap();
+            self.run().0
+        }
+
+        /// Read the value of `key` into a buffer of `length` bytes.
+        fn get(&self, key: &[u8], length: usize) -> (Result<(), ErrorCode>, Vec<u8>) {
+            self.store
+                .get(
+                    SubSliceMut::new(leak(key.to_vec()).as_mut_slice()),
+                    SubSliceMut::new(leak(vec![0; length]).as_mut_slice()),
+                )
+                .unwrap();
+            let (result, (_key, value)) = self.run();
+            (result, value.unwrap().as_slice().to_vec())
+        }
+
+        fn stored(&self, key: &[u8]) -> Vec<u8> {
+            self.kv.values.borrow()[key].clone()
+        }
+
+        fn tamper(&self, key: &[u8], f: impl FnOnce(&mut Vec<u8>)) {
+            f(self.kv.values.borrow_mut().get_mut(key).unwrap());
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let harness = Harness::new();
+        assert_eq!(harness.set(b"wifi", 7, b"hunter2-password"), Ok(()));
+
+        // Only the permissions header is stored in the clear.
+        let stored = harness.stored(b"wifi");
+        assert_eq!(stored.len(), 16 + HEADER_LENGTH + 16 + MIC_LENGTH);
+        assert_eq!(stored[16 + HEADER_LENGTH - 4], 7);
+        assert!(!stored.windows(7).any(|window| window == b"hunter2"));
+
+        let (result, value) = harness.get(b"wifi", 64);
+        assert_eq!(result, Ok(()));
+        assert_eq!(&value[HEADER_LENGTH..], b"hunter2-password");
+        assert_eq!(value[HEADER_LENGTH - 4], 7);
+
+        // Every write uses a fresh nonce.
+        assert_eq!(harness.set(b"wifi", 7, b"hunter2-password"), Ok(()));
+        assert_ne!(harness.stored(b"wifi"), stored);
+    }
+
+    #[test]
+    fn short_buffer() {
+        let harness = Harness::new();
+        assert_eq!(harness.set(b"token", 1, b"0123456789"), Ok(()));
+
+        // Like the permissions layer reading just the header.
+        let (result, value) = harness.get(b"token", HEADER_LENGTH);
+        assert_eq!(result, Err(ErrorCode::SIZE));
+        assert_eq!(value[HEADER_LENGTH - 4], 1);
+
+        let (result, _) = harness.get(b"missing", 64);
+        assert_eq!(result, Err(ErrorCode::NOSUPPORT));
+    }
+
+    #[test]
+    fn tampered_records() {
+        let harness = Harness::new();
+        assert_eq!(harness.set(b"token", 1, b"0123456789"), Ok(()));
+        let stored = harness.stored(b"token");
+
+        // Modified ciphertext.
+        harness.tamper(b"token", |record| record[16 + HEADER_LENGTH] ^= 1);
+        let (result, value) = harness.get(b"token", 64);
+        assert_eq!(result, Err(kv::AUTHENTICATION_FAILED));
+        // Only the permissions header is returned, for the permissions layer
+        // to decide whether the caller may learn about the record.
+        assert_eq!(value.len(), HEADER_LENGTH);
+        assert_eq!(value[HEADER_LENGTH - 4], 1);
+
+        // Another owner.
+        harness.tamper(b"token", |record| {
+            *record = stored.clone();
+            record[16 + HEADER_LENGTH - 4] = 2;
+        });
+        assert_eq!(harness.get(b"token", 64).0, Err(kv::AUTHENTICATION_FAILED));
+
+        // Moved to another key.
+        harness
+            .kv
+            .values
+            .borrow_mut()
+            .insert(b"other".to_vec(), stored.clone());
+        assert_eq!(harness.get(b"other", 64).0, Err(kv::AUTHENTICATION_FAILED));
+
+        // Truncated.
+        harness.tamper(b"token", |record| {
+            *record = stored.clone();
+            record[1] = 0xff;
+        });
+        assert_eq!(harness.get(b"token", 64).0, Err(kv::AUTHENTICATION_FAILED));
+
+        harness.tamper(b"token", |record| *record = stored.clone());
+        assert_eq!(harness.get(b"token", 64).0, Ok(()));
+    }
+}
@@ -40,6 +40,9 @@ enum Operation {
     Add,
     Update,
     Delete,
+    StageSet,
+    StageDelete,
+    Transaction,
 }
 
 /// Current version of the Tock K-V header.
@@ -143,7 +146,7 @@ impl<'a, K: kv::KV<'a>> KVStorePermissions<'a, K> {
         self.operation.set(operation);
 
         match operation {
-            Operation::Set | Operation::Update => {
+            Operation::Set | Operation::Update | Operation::StageSet => {
                 self.valid_ids.set(permissions);
 
                 // We first read the key to see if we are allowed to overwrite it.
@@ -179,6 +182,44 @@ impl<'a, K: kv::KV<'a>> KVStorePermissions<'a, K> {
             _ => Err((key, value, ErrorCode::FAIL)),
         }
     }
+
+    fn remove(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+        operation: Operation,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        if self.operation.is_some() {
+            return Err((key, ErrorCode::BUSY));
+        }
+
+        self.operation.set(operation);
+        self.valid_ids.set(permissions);
+
+        match self.header_value.take() {
+            Some(header_value) => match self.kv.get(key, SubSliceMut::new(header_value)) {
+                Ok(()) => Ok(()),
+                Err((key, hvalue, e)) => {
+                    self.header_value.replace(hvalue.take());
+                    self.operation.clear();
+                    Err((key, e))
+                }
+            },
+            None => Err((key, ErrorCode::FAIL)),
+        }
+    }
+
+    fn transaction(&self, start: impl FnOnce() -> Result<(), ErrorCode>) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.operation.set(Operation::Transaction);
+        start().map_err(|e| {
+            self.operation.clear();
+            e
+        })
+    }
 }
 
 impl<'a, K: kv::KV<'a>> kv::KVPermissions<'a> for KVStorePermissions<'a, K> {
@@ -268,24 +309,49 @@ impl<'a, K: kv::KV<'a>> kv::KVPermissions<'a> for KVStorePermissions<'a, K> {
         key: SubSliceMut<'static, u8>,
         permissions: StoragePermissions,
     ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
-        if self.operation.is_some() {
-            return Err((key, ErrorCode::BUSY));
+        self.remove(key, permissions, Operation::Delete)
+    }
+
+    fn begin_transaction(&self, permissions: StoragePermissions) -> Result<(), ErrorCode> {
+        // Only callers that could write the staged keys can open a
+        // transaction.
+        if permissions.get_write_id().is_none() {
+            return Err(ErrorCode::INVAL);
         }
 
-        self.operation.set(Operation::Delete);
-        self.valid_ids.set(permissions);
+        self.transaction(|| self.kv.begin_transaction())
+    }
 
-        match self.header_value.take() {
-            Some(header_value) => match self.kv.get(key, SubSliceMut::new(header_value)) {
-                Ok(()) => Ok(()),
-                Err((key, hvalue, e)) => {
-                    self.header_value.replace(hvalue.take());
-                    self.operation.clear();
-                    Err((key, e))
-                }
-            },
-            None => Err((key, ErrorCode::FAIL)),
-        }
+    fn stage_set(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        self.insert(key, value, permissions, Operation::StageSet)
+    }
+
+    fn stage_delete(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        self.remove(key, permissions, Operation::StageDelete)
+    }
+
+    fn commit_transaction(&self) -> Result<(), ErrorCode> {
+        self.transaction(|| self.kv.commit_transaction())
+    }
+
+    fn abort_transaction(&self) -> Result<(), ErrorCode> {
+        self.transaction(|| self.kv.abort_transaction())
     }
 
     fn header_size(&self) -> usize {
@@ -302,7 +368,7 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
     ) {
         self.operation.map(|op| {
             match op {
-                Operation::Set => {
+                Operation::Set | Operation::StageSet => {
                     // Need to determine if we have permission to set this key.
                     let mut access_allowed = false;
 
@@ -322,9 +388,13 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
                     self.header_value.replace(value.take());
 
                     if access_allowed {
-                        self.value
-                            .take()
-                            .map(|set_value| match self.kv.set(key, set_value) {
+                        self.value.take().map(|set_value| {
+                            let ret = if op == Operation::Set {
+                                self.kv.set(key, set_value)
+                            } else {
+                                self.kv.stage_set(key, set_value)
+                            };
+                            match ret {
                                 Ok(()) => {}
 
                                 Err((key, set_value, e)) => {
@@ -333,7 +403,8 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
                                         cb.set_complete(Err(e), key, set_value);
                                     });
                                 }
-                            });
+                            }
+                        });
                     } else {
                         self.operation.clear();
                         self.value.take().map(|set_value| {
@@ -381,7 +452,7 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
                         });
                     }
                 }
-                Operation::Delete => {
+                Operation::Delete | Operation::StageDelete => {
                     // Before we delete an object we retrieve the header to
                     // ensure that we have permissions to access it. In that
                     // case we don't need to supply a buffer long enough to
@@ -402,7 +473,12 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
                     self.header_value.replace(value.take());
 
                     if access_allowed {
-                        match self.kv.delete(key) {
+                        let ret = if op == Operation::Delete {
+                            self.kv.delete(key)
+                        } else {
+                            self.kv.stage_delete(key)
+                        };
+                        match ret {
                             Ok(()) => {}
 
                             Err((key, e)) => {
@@ -423,8 +499,9 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
                     self.operation.clear();
 
                     let mut read_allowed = false;
+                    let authentic = result != Err(kv::AUTHENTICATION_FAILED);
 
-                    if result.is_ok() || result.err() == Some(ErrorCode::SIZE) {
+                    if result.is_ok() || result.err() == Some(ErrorCode::SIZE) || !authentic {
                         let header = KeyHeader::new_from_buf(value.as_slice());
 
                         if header.version == HEADER_VERSION {
@@ -440,13 +517,16 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
                         }
                     }
 
-                    if !read_allowed {
-                        // Access denied or the header is invalid, zero the buffer.
+                    if !read_allowed || !authentic {
+                        // Access denied, the header is invalid or the value
+                        // can't be trusted, zero the buffer.
                         value.as_slice().iter_mut().for_each(|m| *m = 0)
                     }
 
                     self.client.map(move |cb| {
                         if read_allowed {
+                            // Only callers that can read the key learn that
+                            // its value failed authentication.
                             cb.get_complete(result, key, value);
                         } else {
                             // The operation failed or the caller doesn't
@@ -503,4 +583,11 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
             cb.delete_complete(result, key);
         });
     }
+
+    fn transaction_complete(&self, result: Result<(), ErrorCode>) {
+        self.operation.clear();
+        self.client.map(move |cb| {
+            cb.transaction_complete(result);
+        });
+    }
 }
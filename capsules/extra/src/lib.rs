@@ -18,6 +18,7 @@ pub mod analog_comparator;
 pub mod analog_sensor;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_loader;
 pub mod at24c_eeprom;
 pub mod ble_advertising_driver;
 pub mod bme280;
@@ -28,13 +29,17 @@ pub mod buzzer_driver;
 pub mod buzzer_pwm;
 pub mod can;
 pub mod ccs811;
+pub mod crash_log;
 pub mod crc;
 pub mod dac;
 pub mod date_time;
 pub mod debug_process_restart;
+pub mod event_bus;
+pub mod fatfs;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700cq;
+pub mod gdb_stub;
 pub mod gpio_async;
 pub mod hd44780;
 pub mod hmac;
@@ -45,6 +50,7 @@ pub mod humidity;
 pub mod ieee802154;
 pub mod isl29035;
 pub mod kv_driver;
+pub mod kv_store_encryption;
 pub mod kv_store_permissions;
 pub mod l3gd20;
 pub mod led_matrix;
@@ -68,9 +74,12 @@ pub mod nrf51822_serialization;
 pub mod panic_button;
 pub mod pca9544a;
 pub mod pressure;
+pub mod process_console_debug;
+pub mod process_watchdog;
 pub mod proximity;
 pub mod public_key_crypto;
 pub mod pwm;
+pub mod ram_disk;
 pub mod read_only_state;
 pub mod rf233;
 pub mod rf233_const;
@@ -86,6 +95,7 @@ pub mod sip_hash;
 pub mod sound_pressure;
 pub mod st77xx;
 pub mod symmetric_encryption;
+pub mod syscall_trace;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
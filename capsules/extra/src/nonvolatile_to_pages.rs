@@ -11,8 +11,12 @@
 //! This module is designed to be used on top of any flash storage and below any
 //! user of `NonvolatileStorage`. This module handles different sized pages.
 //!
+//! It also provides `BlockStorage` with one block per flash page, once the
+//! number of pages is set with `set_page_count()`.
+//!
 //! ```plain
 //! hil::nonvolatile_storage::NonvolatileStorage
+//!   hil::block_storage::BlockStorage
 //!                ┌─────────────┐
 //!                │             │
 //!                │ This module │
@@ -53,6 +57,7 @@ enum State {
     Idle,
     Read,
     Write,
+    Erase,
 }
 
 pub struct NonvolatileToPages<'a, F: hil::flash::Flash + 'static> {
@@ -76,10 +81,21 @@ pub struct NonvolatileToPages<'a, F: hil::flash::Flash + 'static> {
     remaining_length: Cell<usize>,
     /// Where we are in the user buffer.
     buffer_index: Cell<usize>,
+    /// Callback to the user of the `BlockStorage` interface.
+    block_client: OptionalCell<&'a dyn hil::block_storage::BlockStorageClient>,
+    /// Whether the current operation was started through `BlockStorage`.
+    block_op: Cell<bool>,
+    /// Whether a flash operation of the current operation failed.
+    failed: Cell<bool>,
+    /// Size of a flash page.
+    page_size: usize,
+    /// Number of flash pages, or 0 if not known.
+    page_count: Cell<usize>,
 }
 
 impl<'a, F: hil::flash::Flash> NonvolatileToPages<'a, F> {
     pub fn new(driver: &'a F, buffer: &'static mut F::Page) -> NonvolatileToPages<'a, F> {
+        let page_size = buffer.as_mut().len();
         NonvolatileToPages {
             driver: driver,
             client: OptionalCell::empty(),
@@ -90,8 +106,140 @@ impl<'a, F: hil::flash::Flash> NonvolatileToPages<'a, F> {
             length: Cell::new(0),
             remaining_length: Cell::new(0),
             buffer_index: Cell::new(0),
+            block_client: OptionalCell::empty(),
+            block_op: Cell::new(false),
+            failed: Cell::new(false),
+            page_size,
+            page_count: Cell::new(0),
+        }
+    }
+
+    /// Set the number of pages of the flash, which enables the
+    /// `BlockStorage` interface.
+    pub fn set_page_count(&self, pages: usize) {
+        self.page_count.set(pages);
+    }
+
+    fn block_result(&self) -> Result<(), ErrorCode> {
+        match self.failed.get() {
+            true => Err(ErrorCode::FAIL),
+            false => Ok(()),
+        }
+    }
+
+    fn read_done(&self, buffer: &'static mut [u8]) {
+        if self.block_op.replace(false) {
+            self.block_client
+                .map(move |client| client.read_complete(buffer, self.block_result()));
+        } else {
+            self.client
+                .map(move |client| client.read_done(buffer, self.length.get()));
+        }
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8]) {
+        if self.block_op.replace(false) {
+            self.block_client
+                .map(move |client| client.write_complete(buffer, self.block_result()));
+        } else {
+            self.client
+                .map(move |client| client.write_done(buffer, self.length.get()));
+        }
+    }
+
+    /// Check that a `BlockStorage` operation on `count` blocks starting at
+    /// `block` can start now.
+    fn check_block_op(&self, block: usize, count: usize) -> Result<(), ErrorCode> {
+        let geometry = hil::block_storage::BlockStorage::geometry(self)?;
+        if self.state.get() != State::Idle || self.pagebuffer.is_none() {
+            Err(ErrorCode::BUSY)
+        } else if count == 0 || !geometry.contains(block, count) {
+            Err(ErrorCode::INVAL)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Start a block read or write with the `NonvolatileStorage` logic.
+    fn start_block_transfer(
+        &self,
+        buffer: &'static mut [u8],
+        block: usize,
+        count: usize,
+        write: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check_block_op(block, count) {
+            return Err((e, buffer));
+        }
+        let length = match count.checked_mul(self.page_size) {
+            Some(length) if length <= buffer.len() => length,
+            _ => return Err((ErrorCode::SIZE, buffer)),
+        };
+
+        self.block_op.set(true);
+        self.failed.set(false);
+        let address = block * self.page_size;
+        let result = match write {
+            true => {
+                hil::nonvolatile_storage::NonvolatileStorage::write(self, buffer, address, length)
+            }
+            false => {
+                hil::nonvolatile_storage::NonvolatileStorage::read(self, buffer, address, length)
+            }
+        };
+        // The checks above ensure the only possible failure is the flash
+        // refusing the first page, after the buffer was stored.
+        result.map_err(|e| {
+            self.state.set(State::Idle);
+            self.block_op.set(false);
+            (e, self.buffer.take().unwrap_or(&mut []))
+        })
+    }
+}
+
+impl<'a, F: hil::flash::Flash> hil::block_storage::BlockStorage<'a> for NonvolatileToPages<'a, F> {
+    fn set_client(&self, client: &'a dyn hil::block_storage::BlockStorageClient) {
+        self.block_client.set(client);
+    }
+
+    fn geometry(&self) -> Result<hil::block_storage::Geometry, ErrorCode> {
+        match self.page_count.get() {
+            0 => Err(ErrorCode::RESERVE),
+            pages => Ok(hil::block_storage::Geometry {
+                block_size: self.page_size,
+                block_count: pages,
+            }),
         }
     }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        block: usize,
+        count: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_block_transfer(buffer, block, count, false)
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        block: usize,
+        count: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_block_transfer(buffer, block, count, true)
+    }
+
+    fn erase(&self, block: usize, count: usize) -> Result<(), ErrorCode> {
+        self.check_block_op(block, count)?;
+        self.driver.erase_page(block)?;
+        self.state.set(State::Erase);
+        self.block_op.set(true);
+        self.failed.set(false);
+        self.address.set(block);
+        self.remaining_length.set(count - 1);
+        Ok(())
+    }
 }
 
 impl<'a, F: hil::flash::Flash> hil::nonvolatile_storage::NonvolatileStorage<'a>
@@ -195,8 +343,11 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
     fn read_complete(
         &self,
         pagebuffer: &'static mut F::Page,
-        _result: Result<(), hil::flash::Error>,
+        result: Result<(), hil::flash::Error>,
     ) {
+        if result.is_err() {
+            self.failed.set(true);
+        }
         match self.state.get() {
             State::Read => {
                 // OK we got a page from flash. Copy what we actually want from it
@@ -220,8 +371,7 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
                         // Nothing more to do. Put things back and issue callback.
                         self.pagebuffer.replace(pagebuffer);
                         self.state.set(State::Idle);
-                        self.client
-                            .map(move |client| client.read_done(buffer, self.length.get()));
+                        self.read_done(buffer);
                     } else {
                         // More to do!
                         self.buffer.replace(buffer);
@@ -274,8 +424,11 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
     fn write_complete(
         &self,
         pagebuffer: &'static mut F::Page,
-        _result: Result<(), hil::flash::Error>,
+        result: Result<(), hil::flash::Error>,
     ) {
+        if result.is_err() {
+            self.failed.set(true);
+        }
         // After a write we could be done, need to do another write, or need to
         // do a read.
         self.buffer.take().map(move |buffer| {
@@ -285,8 +438,7 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
                 // Done!
                 self.pagebuffer.replace(pagebuffer);
                 self.state.set(State::Idle);
-                self.client
-                    .map(move |client| client.write_done(buffer, self.length.get()));
+                self.write_done(buffer);
             } else if self.remaining_length.get() >= page_size {
                 // Write an entire page!
                 let buffer_index = self.buffer_index.get();
@@ -316,5 +468,28 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
         });
     }
 
-    fn erase_complete(&self, _result: Result<(), hil::flash::Error>) {}
+    fn erase_complete(&self, result: Result<(), hil::flash::Error>) {
+        if self.state.get() != State::Erase {
+            return;
+        }
+        if result.is_err() {
+            self.failed.set(true);
+        }
+
+        let remaining = self.remaining_length.get();
+        if remaining > 0 && !self.failed.get() {
+            // Erase the next page.
+            self.remaining_length.set(remaining - 1);
+            self.address.add(1);
+            if self.driver.erase_page(self.address.get()).is_ok() {
+                return;
+            }
+            self.failed.set(true);
+        }
+
+        self.state.set(State::Idle);
+        self.block_op.set(false);
+        self.block_client
+            .map(|client| client.erase_complete(self.block_result()));
+    }
 }
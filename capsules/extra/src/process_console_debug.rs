@@ -0,0 +1,356 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Process console commands for debugging processes.
+//!
+//! These commands inspect the memory of a process and change how the kernel
+//! schedules it and handles its faults:
+//!
+//! - `memory <process>` prints the flash, RAM and grant regions of the
+//!   process.
+//! - `grants <process>` lists the grants the process has allocated, with the
+//!   driver number and size of each.
+//! - `peek <process> <address|flash|ram|stack> [length]` prints a hex dump of
+//!   up to 64 bytes of the process's flash or of the RAM the process can
+//!   access. The grant region and kernel memory cannot be read.
+//! - `priority <process> [<priority>|default]` prints or changes the
+//!   scheduling priority of the process.
+//! - `policy <process> <panic|restart|stop>` changes the fault policy of the
+//!   process.
+//!
+//! The commands are not built into the process console so that boards that do
+//! not need them do not pay for them in flash, and because most of them require
+//! the `ProcessDebugCapability`. A board that wants them creates a
+//! [`DebugCommand`] for each and adds it to the console with
+//! `ProcessConsole::add_command()`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let memory = static_init!(
+//!     DebugCommand<Capability>,
+//!     DebugCommand::new(DebugCommandKind::Memory, board_kernel, Capability)
+//! );
+//! let entry = static_init!(
+//!     CommandEntry<'static>,
+//!     CommandEntry::new(memory.kind().name(), memory.kind().usage(), memory)
+//! );
+//! process_console.add_command(entry);
+//! ```
+
+use core::fmt;
+use core::str::SplitWhitespace;
+
+use capsules_core::process_console::ConsoleCommand;
+use kernel::capabilities::{ProcessDebugCapability, ProcessManagementCapability};
+use kernel::introspection::KernelInfo;
+use kernel::process::{
+    PanicFaultPolicy, Process, ProcessFaultPolicy, RestartFaultPolicy, StopFaultPolicy,
+};
+use kernel::Kernel;
+
+/// Maximum number of bytes a single `peek` prints, as four lines of 16 bytes.
+/// The dump must fit in the console's write buffer.
+pub const MAX_PEEK_LEN: usize = 64;
+
+/// The debug commands a [`DebugCommand`] can run.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DebugCommandKind {
+    Memory,
+    Grants,
+    Peek,
+    Priority,
+    Policy,
+}
+
+impl DebugCommandKind {
+    /// The word that runs the command.
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugCommandKind::Memory => "memory",
+            DebugCommandKind::Grants => "grants",
+            DebugCommandKind::Peek => "peek",
+            DebugCommandKind::Priority => "priority",
+            DebugCommandKind::Policy => "policy",
+        }
+    }
+
+    /// The usage `help` prints for the command.
+    pub fn usage(self) -> &'static str {
+        match self {
+            DebugCommandKind::Memory => "memory <process>",
+            DebugCommandKind::Grants => "grants <process>",
+            DebugCommandKind::Peek => "peek <process> <address|flash|ram|stack> [length]",
+            DebugCommandKind::Priority => "priority <process> [<priority>|default]",
+            DebugCommandKind::Policy => "policy <process> <panic|restart|stop>",
+        }
+    }
+}
+
+/// Parse a decimal number, or a hexadecimal number prefixed with `0x`.
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Write `data`, read from `address`, as lines of 16 bytes in hex followed by
+/// the printable characters.
+fn write_dump(out: &mut dyn fmt::Write, address: usize, data: &[u8]) {
+    for (i, line) in data.chunks(16).enumerate() {
+        let _ = write!(out, "{:#010x}:", address + 16 * i);
+        for byte in line.iter() {
+            let _ = write!(out, " {:02x}", byte);
+        }
+        for _ in line.len()..16 {
+            let _ = write!(out, "   ");
+        }
+        let _ = write!(out, "  |");
+        for byte in line.iter() {
+            let c = if byte.is_ascii_graphic() || *byte == b' ' {
+                *byte as char
+            } else {
+                '.'
+            };
+            let _ = write!(out, "{}", c);
+        }
+        let _ = write!(out, "|\r\n");
+    }
+}
+
+/// A process console command for debugging processes. `C` must allow
+/// looking up processes and debugging them.
+pub struct DebugCommand<C: ProcessManagementCapability + ProcessDebugCapability> {
+    kind: DebugCommandKind,
+    kernel: &'static Kernel,
+    capability: C,
+}
+
+impl<C: ProcessManagementCapability + ProcessDebugCapability> DebugCommand<C> {
+    pub fn new(kind: DebugCommandKind, kernel: &'static Kernel, capability: C) -> Self {
+        DebugCommand {
+            kind,
+            kernel,
+            capability,
+        }
+    }
+
+    /// The command this runs.
+    pub fn kind(&self) -> DebugCommandKind {
+        self.kind
+    }
+
+    fn print_usage(&self, text: &str, out: &mut dyn fmt::Write) {
+        let _ = write!(out, "{}Usage: {}\r\n", text, self.kind.usage());
+    }
+
+    /// Call `f` with the first process named `name`. Prints an error if there
+    /// is no such process.
+    fn with_process<F: FnOnce(&dyn Process, &mut dyn fmt::Write)>(
+        &self,
+        name: &str,
+        out: &mut dyn fmt::Write,
+        f: F,
+    ) {
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if found.is_none() && process.get_process_name() == name {
+                    found = Some(process.processid());
+                }
+            });
+        let ran = found.map_or(false, |processid| {
+            self.kernel.process_map_or_external(
+                false,
+                processid,
+                |process| {
+                    f(process, &mut *out);
+                    true
+                },
+                &self.capability,
+            )
+        });
+        if !ran {
+            let _ = write!(out, "No process named {}\r\n", name);
+        }
+    }
+
+    fn memory(&self, mut args: SplitWhitespace, out: &mut dyn fmt::Write) {
+        let name = match args.next() {
+            Some(name) => name,
+            None => return self.print_usage("", out),
+        };
+        self.with_process(name, out, |process, out| {
+            let addresses = process.get_addresses();
+            let _ = write!(out, " Region   Start       End         Size\r\n");
+            let regions = [
+                ("flash", addresses.flash_start, addresses.flash_end),
+                ("ram", addresses.sram_start, addresses.sram_end),
+                (" app", addresses.sram_start, addresses.sram_app_brk),
+                (" grant", addresses.sram_grant_start, addresses.sram_end),
+            ];
+            for (region, start, end) in regions.iter() {
+                let _ = write!(
+                    out,
+                    " {:<8} {:#010x}  {:#010x}  {}\r\n",
+                    region,
+                    start,
+                    end,
+                    end - start
+                );
+            }
+        });
+    }
+
+    fn grants(&self, mut args: SplitWhitespace, out: &mut dyn fmt::Write) {
+        let name = match args.next() {
+            Some(name) => name,
+            None => return self.print_usage("", out),
+        };
+        self.with_process(name, out, |process, out| {
+            let info = KernelInfo::new(self.kernel);
+            let _ = write!(out, " Driver     Bytes\r\n");
+            let mut total = 0;
+            info.app_grant_allocations(process.processid(), &self.capability, |driver, size| {
+                let _ = write!(out, " {:#07x}  {:6}\r\n", driver, size);
+                total += size;
+            });
+            let (used, _) = info.number_app_grant_uses(process.processid(), &self.capability);
+            let _ = write!(out, " {} grants, {} bytes\r\n", used, total);
+        });
+    }
+
+    fn peek(&self, mut args: SplitWhitespace, out: &mut dyn fmt::Write) {
+        let (name, location) = match (args.next(), args.next()) {
+            (Some(name), Some(location)) => (name, location),
+            _ => return self.print_usage("", out),
+        };
+        let length = match args.next().map(parse_number) {
+            None => MAX_PEEK_LEN,
+            Some(Some(length)) => core::cmp::min(length, MAX_PEEK_LEN),
+            Some(None) => return self.print_usage("Invalid length. ", out),
+        };
+        self.with_process(name, out, |process, out| {
+            let addresses = process.get_addresses();
+            let address = match location {
+                "flash" => Some(addresses.flash_start),
+                "ram" => Some(addresses.sram_start),
+                "stack" => process.get_stored_registers().map(|registers| registers.sp),
+                _ => parse_number(location),
+            };
+            let mut data = [0; MAX_PEEK_LEN];
+            let read = address.map_or(0, |address| {
+                process.debug_read_memory(address, &mut data[..length], &self.capability)
+            });
+            match address {
+                Some(address) if read > 0 => write_dump(out, address, &data[..read]),
+                _ => {
+                    let _ = write!(out, "Address is not in the process's memory.\r\n");
+                }
+            }
+        });
+    }
+
+    fn priority(&self, mut args: SplitWhitespace, out: &mut dyn fmt::Write) {
+        let name = match args.next() {
+            Some(name) => name,
+            None => return self.print_usage("", out),
+        };
+        let priority = match args.next() {
+            None => None,
+            Some("default") => Some(None),
+            Some(value) => match parse_number(value) {
+                Some(priority) => Some(Some(priority as u32)),
+                None => return self.print_usage("Invalid priority. ", out),
+            },
+        };
+        self.with_process(name, out, |process, out| {
+            if let Some(priority) = priority {
+                process.set_priority(priority, &self.capability);
+            }
+            match process.get_priority() {
+                Some(priority) => {
+                    let _ = write!(out, "Process {} priority: {}\r\n", name, priority);
+                }
+                None => {
+                    let _ = write!(out, "Process {} priority: default\r\n", name);
+                }
+            }
+        });
+    }
+
+    fn policy(&self, mut args: SplitWhitespace, out: &mut dyn fmt::Write) {
+        let (name, policy) = match (args.next(), args.next()) {
+            (Some(name), Some(policy)) => (name, policy),
+            _ => return self.print_usage("", out),
+        };
+        let fault_policy: &'static dyn ProcessFaultPolicy = match policy {
+            "panic" => &PanicFaultPolicy {},
+            "restart" => &RestartFaultPolicy {},
+            "stop" => &StopFaultPolicy {},
+            _ => return self.print_usage("Invalid policy. ", out),
+        };
+        self.with_process(name, out, |process, out| {
+            process.set_fault_policy(fault_policy, &self.capability);
+            let _ = write!(out, "Process {} fault policy: {}\r\n", name, policy);
+        });
+    }
+}
+
+impl<C: ProcessManagementCapability + ProcessDebugCapability> ConsoleCommand for DebugCommand<C> {
+    fn execute(&self, args: SplitWhitespace, out: &mut dyn fmt::Write) {
+        match self.kind {
+            DebugCommandKind::Memory => self.memory(args, out),
+            DebugCommandKind::Grants => self.grants(args, out),
+            DebugCommandKind::Peek => self.peek(args, out),
+            DebugCommandKind::Priority => self.priority(args, out),
+            DebugCommandKind::Policy => self.policy(args, out),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::String;
+
+    #[test]
+    fn parse_number_accepts_decimal_and_hex() {
+        assert_eq!(parse_number("42"), Some(42));
+        assert_eq!(parse_number("0x2a"), Some(42));
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("ram"), None);
+    }
+
+    #[test]
+    fn dump_prints_hex_and_printable_characters() {
+        let mut out = String::new();
+        let mut data = [0u8; 18];
+        data[..4].copy_from_slice(b"Tock");
+        write_dump(&mut out, 0x2000_0000, &data);
+        let lines: std::vec::Vec<&str> = out.split("\r\n").collect();
+        assert_eq!(
+            lines[0],
+            "0x20000000: 54 6f 63 6b 00 00 00 00 00 00 00 00 00 00 00 00  |Tock............|"
+        );
+        assert_eq!(
+            lines[1],
+            "0x20000010: 00 00                                            |..|"
+        );
+        assert_eq!(lines[2], "");
+    }
+
+    #[test]
+    fn largest_peek_fits_in_the_console_buffer() {
+        let mut out = String::new();
+        write_dump(&mut out, usize::MAX - MAX_PEEK_LEN, &[0; MAX_PEEK_LEN]);
+        assert!(out.len() <= capsules_core::process_console::WRITE_BUF_LEN);
+    }
+}
@@ -0,0 +1,492 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Per-process liveness heartbeats backed by the hardware watchdog.
+//!
+//! The kernel tickles the board's [`WatchDog`] on every pass through its
+//! main loop, so the watchdog only catches a hung kernel. A process stuck in
+//! an infinite loop, or blocked forever in `yield`, goes unnoticed as long as
+//! the kernel itself keeps running.
+//!
+//! This capsule lets critical processes register a heartbeat interval. Once
+//! registered, a process must check in at least once per interval. If it
+//! misses its deadline, the capsule applies the action the board configured:
+//!
+//! - [`MissedHeartbeatAction::RestartProcess`] faults the process, so that
+//!   the kernel's fault policy decides what happens to it (for example a
+//!   restart).
+//! - [`MissedHeartbeatAction::ResetBoard`] stops feeding the hardware
+//!   watchdog, which then resets the board.
+//!
+//! The hardware watchdog is fed through [`GatedWatchDog`], which the board
+//! returns from `KernelResources::watchdog()` in place of the chip's
+//! watchdog. It forwards the kernel's calls until the capsule closes it.
+//!
+//! Because a process that misses its heartbeat can reset the board, only
+//! processes whose TBF header explicitly permits command `1` of this driver
+//! may register one. Unlike the kernel's `TbfHeaderFilterDefaultAllow`, a
+//! process without a permissions header is not allowed to register.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let gated_watchdog = static_init!(
+//!     capsules_extra::process_watchdog::GatedWatchDog<'static, Wdt>,
+//!     capsules_extra::process_watchdog::GatedWatchDog::new(&peripherals.wdt)
+//! );
+//! let process_watchdog = static_init!(
+//!     capsules_extra::process_watchdog::ProcessWatchdog<
+//!         'static,
+//!         VirtualMuxAlarm<'static, Rtc>,
+//!         Wdt,
+//!         Capability,
+//!     >,
+//!     capsules_extra::process_watchdog::ProcessWatchdog::new(
+//!         board_kernel,
+//!         virtual_alarm,
+//!         gated_watchdog,
+//!         MissedHeartbeatAction::ResetBoard,
+//!         board_kernel.create_grant(capsules_extra::process_watchdog::DRIVER_NUM, &grant_cap),
+//!         capability,
+//!     )
+//! );
+//! virtual_alarm.set_alarm_client(process_watchdog);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Register a heartbeat. The first argument is the interval in
+//!   milliseconds, which must be nonzero. Registering again changes the
+//!   interval. The first deadline is one interval from now. Returns
+//!   `NOSUPPORT` unless the process's TBF header grants it this command (see
+//!   below).
+//! - `2`: Check in. The next deadline is one interval from now. Returns
+//!   `INVAL` if the process has not registered a heartbeat.
+//! - `3`: Unregister the heartbeat. Returns `INVAL` if the process has not
+//!   registered one.
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::debug;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::platform::watchdog::WatchDog;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+use tock_tbf::types::CommandPermissions;
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::ProcessWatchdog as usize;
+
+/// What to do when a process misses its heartbeat deadline.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MissedHeartbeatAction {
+    /// Fault the process, leaving its fate to the kernel's fault policy.
+    RestartProcess,
+    /// Stop feeding the hardware watchdog so that it resets the board.
+    ResetBoard,
+}
+
+/// A registered heartbeat: the interval, and when the process last checked
+/// in.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Heartbeat<T: Ticks> {
+    interval: T,
+    last_check_in: T,
+}
+
+impl<T: Ticks> Heartbeat<T> {
+    /// Time left before the deadline, or `None` if it has passed.
+    fn remaining(&self, now: T) -> Option<T> {
+        let elapsed = now.wrapping_sub(self.last_check_in);
+        if elapsed >= self.interval {
+            None
+        } else {
+            Some(self.interval.wrapping_sub(elapsed))
+        }
+    }
+}
+
+pub struct App<T: Ticks> {
+    heartbeat: Option<Heartbeat<T>>,
+}
+
+impl<T: Ticks> Default for App<T> {
+    fn default() -> App<T> {
+        App { heartbeat: None }
+    }
+}
+
+/// Forwards the kernel's watchdog calls to the hardware watchdog until it is
+/// closed, after which the hardware watchdog is left to expire.
+pub struct GatedWatchDog<'a, W: WatchDog> {
+    watchdog: &'a W,
+    open: Cell<bool>,
+}
+
+impl<'a, W: WatchDog> GatedWatchDog<'a, W> {
+    pub fn new(watchdog: &'a W) -> GatedWatchDog<'a, W> {
+        GatedWatchDog {
+            watchdog,
+            open: Cell::new(true),
+        }
+    }
+
+    /// Stop feeding the hardware watchdog. This cannot be undone.
+    pub fn close(&self) {
+        self.open.set(false);
+    }
+
+    /// Whether the hardware watchdog is still being fed.
+    pub fn is_open(&self) -> bool {
+        self.open.get()
+    }
+}
+
+impl<W: WatchDog> WatchDog for GatedWatchDog<'_, W> {
+    fn setup(&self) {
+        self.watchdog.setup();
+    }
+
+    fn tickle(&self) {
+        if self.open.get() {
+            self.watchdog.tickle();
+        }
+    }
+
+    // Once closed, the watchdog must keep running while the kernel sleeps,
+    // or an idle board would never reset.
+    fn suspend(&self) {
+        if self.open.get() {
+            self.watchdog.suspend();
+        }
+    }
+
+    fn resume(&self) {
+        if self.open.get() {
+            self.watchdog.resume();
+        }
+    }
+}
+
+pub struct ProcessWatchdog<'a, A: time::Alarm<'a>, W: WatchDog, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    watchdog: &'a GatedWatchDog<'a, W>,
+    action: MissedHeartbeatAction,
+    apps: Grant<App<A::Ticks>, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    capability: C,
+}
+
+impl<'a, A: time::Alarm<'a>, W: WatchDog, C: ProcessManagementCapability>
+    ProcessWatchdog<'a, A, W, C>
+{
+    pub fn new(
+        kernel: &'static Kernel,
+        alarm: &'a A,
+        watchdog: &'a GatedWatchDog<'a, W>,
+        action: MissedHeartbeatAction,
+        grant: Grant<App<A::Ticks>, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+        capability: C,
+    ) -> ProcessWatchdog<'a, A, W, C> {
+        ProcessWatchdog {
+            kernel,
+            alarm,
+            watchdog,
+            action,
+            apps: grant,
+            capability,
+        }
+    }
+
+    /// Whether the TBF header of the process explicitly permits it to
+    /// register a heartbeat.
+    fn may_register(&self, processid: ProcessId) -> bool {
+        self.kernel.process_map_or_external(
+            false,
+            processid,
+            |process| match process.get_command_permissions(DRIVER_NUM, 0) {
+                CommandPermissions::Mask(allowed) => allowed & (1 << 1) != 0,
+                CommandPermissions::NoPermsAtAll | CommandPermissions::NoPermsThisDriver => false,
+            },
+            &self.capability,
+        )
+    }
+
+    fn register(&self, interval_ms: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        if !self.may_register(processid) {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        if interval_ms == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        let interval = self.alarm.ticks_from_ms(interval_ms as u32);
+        let now = self.alarm.now();
+        self.apps.enter(processid, |app, _| {
+            app.heartbeat = Some(Heartbeat {
+                interval,
+                last_check_in: now,
+            });
+        })?;
+        self.schedule();
+        Ok(())
+    }
+
+    fn check_in(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let now = self.alarm.now();
+        self.apps.enter(processid, |app, _| {
+            app.heartbeat
+                .as_mut()
+                .map(|heartbeat| heartbeat.last_check_in = now)
+                .ok_or(ErrorCode::INVAL)
+        })??;
+        self.schedule();
+        Ok(())
+    }
+
+    fn unregister(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.apps.enter(processid, |app, _| {
+            app.heartbeat.take().map(|_| ()).ok_or(ErrorCode::INVAL)
+        })??;
+        self.schedule();
+        Ok(())
+    }
+
+    /// Set the alarm for the earliest deadline, or disarm it if no process
+    /// has a heartbeat.
+    fn schedule(&self) {
+        let now = self.alarm.now();
+        let mut earliest: Option<A::Ticks> = None;
+        self.apps.each(|_, app, _| {
+            if let Some(heartbeat) = app.heartbeat {
+                // A deadline that has passed is handled as soon as possible.
+                let remaining = heartbeat.remaining(now).unwrap_or(0.into());
+                if earliest.map_or(true, |earliest| remaining < earliest) {
+                    earliest = Some(remaining);
+                }
+            }
+        });
+        match earliest {
+            Some(dt) => self.alarm.set_alarm(now, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    fn heartbeat_missed(&self, processid: ProcessId) {
+        match self.action {
+            MissedHeartbeatAction::RestartProcess => {
+                debug!("{:?} missed its heartbeat, faulting it", processid);
+                self.kernel.process_map_or_external(
+                    (),
+                    processid,
+                    |process| process.set_fault_state(),
+                    &self.capability,
+                );
+            }
+            MissedHeartbeatAction::ResetBoard => {
+                if self.watchdog.is_open() {
+                    debug!("{:?} missed its heartbeat, resetting", processid);
+                    self.watchdog.close();
+                }
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, W: WatchDog, C: ProcessManagementCapability> time::AlarmClient
+    for ProcessWatchdog<'a, A, W, C>
+{
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        // Faulting a process may free its grant, so the missed heartbeats are
+        // handled one at a time outside of `each`. Each one is unregistered
+        // so that it is only handled once.
+        loop {
+            let mut missed = None;
+            self.apps.each(|processid, app, _| {
+                if missed.is_none()
+                    && app
+                        .heartbeat
+                        .map_or(false, |heartbeat| heartbeat.remaining(now).is_none())
+                {
+                    app.heartbeat = None;
+                    missed = Some(processid);
+                }
+            });
+            match missed {
+                Some(processid) => self.heartbeat_missed(processid),
+                None => break,
+            }
+        }
+        self.schedule();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, W: WatchDog, C: ProcessManagementCapability> SyscallDriver
+    for ProcessWatchdog<'a, A, W, C>
+{
+    /// Register, check in to and unregister a heartbeat.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Register a heartbeat with an interval of `data1` milliseconds.
+    ///   Only processes whose TBF header permits this command may register.
+    /// - `2`: Check in.
+    /// - `3`: Unregister the heartbeat.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.register(data1, processid).into(),
+
+            2 => self.check_in(processid).into(),
+
+            3 => self.unregister(processid).into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel::hil::time::Ticks32;
+
+    /// A software model of a hardware watchdog: it expires if it runs for
+    /// `timeout` time units without being tickled.
+    struct SimulatedWatchDog {
+        timeout: u32,
+        since_tickle: Cell<u32>,
+        running: Cell<bool>,
+        expired: Cell<bool>,
+    }
+
+    impl SimulatedWatchDog {
+        fn new(timeout: u32) -> SimulatedWatchDog {
+            SimulatedWatchDog {
+                timeout,
+                since_tickle: Cell::new(0),
+                running: Cell::new(false),
+                expired: Cell::new(false),
+            }
+        }
+
+        fn advance(&self, time: u32) {
+            if self.running.get() {
+                self.since_tickle.set(self.since_tickle.get() + time);
+                if self.since_tickle.get() >= self.timeout {
+                    self.expired.set(true);
+                }
+            }
+        }
+    }
+
+    impl WatchDog for SimulatedWatchDog {
+        fn setup(&self) {
+            self.running.set(true);
+            self.since_tickle.set(0);
+        }
+
+        fn tickle(&self) {
+            self.running.set(true);
+            self.since_tickle.set(0);
+        }
+
+        fn suspend(&self) {
+            self.running.set(false);
+        }
+    }
+
+    /// Make the kernel loop's watchdog calls for `loops` iterations,
+    /// sleeping in every other one.
+    fn run_kernel_loop(watchdog: &dyn WatchDog, hardware: &SimulatedWatchDog, loops: u32) {
+        for i in 0..loops {
+            watchdog.tickle();
+            hardware.advance(5);
+            if i % 2 == 0 {
+                watchdog.suspend();
+                hardware.advance(100);
+                watchdog.resume();
+            }
+        }
+    }
+
+    #[test]
+    fn open_gate_feeds_watchdog() {
+        let hardware = SimulatedWatchDog::new(10);
+        let gate = GatedWatchDog::new(&hardware);
+        gate.setup();
+        run_kernel_loop(&gate, &hardware, 100);
+        assert!(!hardware.expired.get());
+    }
+
+    #[test]
+    fn closed_gate_lets_watchdog_expire() {
+        let hardware = SimulatedWatchDog::new(10);
+        let gate = GatedWatchDog::new(&hardware);
+        gate.setup();
+        run_kernel_loop(&gate, &hardware, 10);
+        gate.close();
+        assert!(!gate.is_open());
+        run_kernel_loop(&gate, &hardware, 1);
+        assert!(hardware.expired.get());
+    }
+
+    #[test]
+    fn closed_gate_keeps_watchdog_running_while_asleep() {
+        let hardware = SimulatedWatchDog::new(50);
+        let gate = GatedWatchDog::new(&hardware);
+        gate.setup();
+        gate.close();
+        gate.suspend();
+        hardware.advance(60);
+        assert!(hardware.expired.get());
+    }
+
+    #[test]
+    fn heartbeat_deadline() {
+        let heartbeat = Heartbeat {
+            interval: Ticks32::from(100),
+            last_check_in: Ticks32::from(1000),
+        };
+        assert_eq!(heartbeat.remaining(1000.into()), Some(100.into()));
+        assert_eq!(heartbeat.remaining(1099.into()), Some(1.into()));
+        assert_eq!(heartbeat.remaining(1100.into()), None);
+        assert_eq!(heartbeat.remaining(5000.into()), None);
+    }
+
+    #[test]
+    fn heartbeat_deadline_across_wraparound() {
+        let heartbeat = Heartbeat {
+            interval: Ticks32::from(100),
+            last_check_in: Ticks32::from(u32::MAX - 10),
+        };
+        assert_eq!(heartbeat.remaining(u32::MAX.into()), Some(90.into()));
+        assert_eq!(heartbeat.remaining(88.into()), Some(1.into()));
+        assert_eq!(heartbeat.remaining(89.into()), None);
+    }
+}
@@ -5,3 +5,4 @@
 //! Provides capsules for asymmetric encryption
 
 pub mod rsa_keys;
+pub mod rsa_math;
@@ -0,0 +1,910 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Software implementation of RSA modular exponentiation.
+//!
+//! This implements the `RsaCryptoBase` HIL for boards without an RSA
+//! accelerator. It uses Montgomery multiplication over 32-bit limbs.
+//!
+//! A modular exponentiation with a 4096-bit modulus takes far too long to run
+//! in one go, so the operation is split into small steps and one step runs
+//! per deferred call. Interrupts and other deferred calls are handled
+//! between steps.
+//!
+//! All buffers hold big-endian numbers. The modulus must be odd, its most
+//! significant byte must not be zero, and its length must be a multiple of 4
+//! bytes up to 512 bytes (4096 bits). The message must be smaller than the
+//! modulus.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! # use kernel::static_init;
+//!
+//! let rsa = static_init!(
+//!     capsules_extra::public_key_crypto::rsa_math::RsaMathSoftware<'static>,
+//!     capsules_extra::public_key_crypto::rsa_math::RsaMathSoftware::new()
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(rsa);
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::public_key_crypto::rsa_math::{Client, RsaCryptoBase};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Largest supported modulus, in 32-bit limbs.
+const MAX_LIMBS: usize = 128;
+
+/// Number of doublings done per step when converting the message into
+/// Montgomery form. This costs about as much as one Montgomery
+/// multiplication.
+const DOUBLINGS_PER_STEP: usize = 256;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Converting the message into Montgomery form; the number of doublings
+    /// still to do.
+    ToMontgomery(usize),
+    /// Exponentiating; the index of the next exponent bit, counted from the
+    /// most significant bit.
+    Exponent(usize),
+}
+
+/// Working storage for the big numbers, as little-endian limbs.
+struct Workspace {
+    modulus: [u32; MAX_LIMBS],
+    base: [u32; MAX_LIMBS],
+    accumulator: [u32; MAX_LIMBS],
+    product: [u32; MAX_LIMBS + 2],
+}
+
+pub struct RsaMathSoftware<'a> {
+    client: OptionalCell<&'a dyn Client<'a>>,
+    deferred_call: DeferredCall,
+    state: Cell<State>,
+
+    // Number of limbs in the current operation.
+    limbs: Cell<usize>,
+    // -modulus^-1 mod 2^32
+    modulus_inverse: Cell<u32>,
+    workspace: MapCell<Workspace>,
+
+    message: TakeCell<'static, [u8]>,
+    modulus: OptionalCell<&'static [u8]>,
+    exponent: OptionalCell<&'static [u8]>,
+    result: TakeCell<'static, [u8]>,
+}
+
+impl<'a> RsaMathSoftware<'a> {
+    pub fn new() -> Self {
+        Self {
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+            state: Cell::new(State::Idle),
+            limbs: Cell::new(0),
+            modulus_inverse: Cell::new(0),
+            workspace: MapCell::new(Workspace {
+                modulus: [0; MAX_LIMBS],
+                base: [0; MAX_LIMBS],
+                accumulator: [0; MAX_LIMBS],
+                product: [0; MAX_LIMBS + 2],
+            }),
+            message: TakeCell::empty(),
+            modulus: OptionalCell::empty(),
+            exponent: OptionalCell::empty(),
+            result: TakeCell::empty(),
+        }
+    }
+
+    /// The exponent bytes used by the current operation.
+    fn exponent_bytes(&self) -> &'static [u8] {
+        let op_len = self.limbs.get() * 4;
+        self.exponent.map_or(&[], |exponent| {
+            &exponent[..cmp::min(exponent.len(), op_len)]
+        })
+    }
+
+    /// Run one step of the current operation. Returns true once the result
+    /// has been written.
+    fn step(&self) -> bool {
+        let limbs = self.limbs.get();
+        let modulus_inverse = self.modulus_inverse.get();
+        let exponent = self.exponent_bytes();
+
+        match self.state.get() {
+            State::Idle => true,
+            State::ToMontgomery(remaining) => {
+                let doublings = cmp::min(remaining, DOUBLINGS_PER_STEP);
+                self.workspace.map(|ws| {
+                    for _ in 0..doublings {
+                        double_mod(&mut ws.base[..limbs], &ws.modulus[..limbs]);
+                    }
+                });
+                if remaining > doublings {
+                    self.state.set(State::ToMontgomery(remaining - doublings));
+                } else {
+                    // Exponentiation starts at the most significant set bit
+                    // of the exponent, for which the accumulator is the base.
+                    let top = first_set_bit(exponent).unwrap_or(0);
+                    self.workspace.map(|ws| {
+                        ws.accumulator[..limbs].copy_from_slice(&ws.base[..limbs]);
+                    });
+                    self.state.set(State::Exponent(top + 1));
+                }
+                false
+            }
+            State::Exponent(bit) if bit < exponent.len() * 8 => {
+                self.workspace.map(|ws| {
+                    let n = &ws.modulus[..limbs];
+                    let product = &mut ws.product[..limbs + 2];
+
+                    mont_mul(
+                        product,
+                        &ws.accumulator,
+                        &ws.accumulator,
+                        n,
+                        modulus_inverse,
+                    );
+                    ws.accumulator[..limbs].copy_from_slice(&product[..limbs]);
+
+                    if exponent[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                        mont_mul(product, &ws.accumulator, &ws.base, n, modulus_inverse);
+                        ws.accumulator[..limbs].copy_from_slice(&product[..limbs]);
+                    }
+                });
+                self.state.set(State::Exponent(bit + 1));
+                false
+            }
+            State::Exponent(_) => {
+                // Multiplying by 1 leaves Montgomery form.
+                self.workspace.map(|ws| {
+                    ws.base[..limbs].fill(0);
+                    ws.base[0] = 1;
+                    let product = &mut ws.product[..limbs + 2];
+                    mont_mul(
+                        product,
+                        &ws.accumulator,
+                        &ws.base,
+                        &ws.modulus[..limbs],
+                        modulus_inverse,
+                    );
+                    self.result.map(|result| {
+                        to_bytes(&product[..limbs], &mut result[..limbs * 4]);
+                    });
+                });
+                self.state.set(State::Idle);
+                true
+            }
+        }
+    }
+}
+
+impl<'a> DeferredCallClient for RsaMathSoftware<'a> {
+    fn handle_deferred_call(&self) {
+        if !self.step() {
+            self.deferred_call.set();
+            return;
+        }
+
+        if let (Some(message), Some(modulus), Some(exponent), Some(result)) = (
+            self.message.take(),
+            self.modulus.take(),
+            self.exponent.take(),
+            self.result.take(),
+        ) {
+            self.client.map(|client| {
+                client.mod_exponent_done(Ok(true), message, modulus, exponent, result)
+            });
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a> RsaCryptoBase<'a> for RsaMathSoftware<'a> {
+    fn set_client(&'a self, client: &'a dyn Client<'a>) {
+        self.client.set(client);
+    }
+
+    fn clear_data(&self) {
+        self.workspace.map(|ws| {
+            ws.modulus.fill(0);
+            ws.base.fill(0);
+            ws.accumulator.fill(0);
+            ws.product.fill(0);
+        });
+    }
+
+    fn mod_exponent(
+        &self,
+        message: &'static mut [u8],
+        modulus: &'static [u8],
+        exponent: &'static [u8],
+        result: &'static mut [u8],
+    ) -> Result<
+        (),
+        (
+            ErrorCode,
+            &'static mut [u8],
+            &'static [u8],
+            &'static [u8],
+            &'static mut [u8],
+        ),
+    > {
+        let op_len = modulus.len();
+        let limbs = op_len / 4;
+
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, message, modulus, exponent, result));
+        }
+        if result.len() < op_len {
+            return Err((ErrorCode::SIZE, message, modulus, exponent, result));
+        }
+        if op_len == 0
+            || op_len % 4 != 0
+            || limbs > MAX_LIMBS
+            || modulus[0] == 0
+            || modulus[op_len - 1] & 1 == 0
+            || message.len() < op_len
+        {
+            return Err((ErrorCode::INVAL, message, modulus, exponent, result));
+        }
+
+        let valid = self.workspace.map_or(false, |ws| {
+            from_bytes(modulus, &mut ws.modulus[..limbs]);
+            from_bytes(&message[..op_len], &mut ws.base[..limbs]);
+            less_than(&ws.base[..limbs], &ws.modulus[..limbs])
+        });
+        if !valid {
+            return Err((ErrorCode::INVAL, message, modulus, exponent, result));
+        }
+
+        self.limbs.set(limbs);
+        self.modulus_inverse
+            .set(inverse(from_be(&modulus[op_len - 4..])).wrapping_neg());
+        self.message.replace(message);
+        self.modulus.set(modulus);
+        self.exponent.set(exponent);
+        self.result.replace(result);
+
+        if first_set_bit(self.exponent_bytes()).is_some() {
+            // Multiplying the message by 2^(32 * limbs) moves it into
+            // Montgomery form.
+            self.state.set(State::ToMontgomery(32 * limbs));
+        } else {
+            // x^0 = 1
+            self.result.map(|result| {
+                result[..op_len].fill(0);
+                result[op_len - 1] = 1;
+            });
+            self.state.set(State::Idle);
+        }
+        self.deferred_call.set();
+
+        Ok(())
+    }
+}
+
+/// Index of the most significant set bit of the big-endian `bytes`.
+fn first_set_bit(bytes: &[u8]) -> Option<usize> {
+    bytes
+        .iter()
+        .position(|b| *b != 0)
+        .map(|i| i * 8 + bytes[i].leading_zeros() as usize)
+}
+
+fn from_be(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Convert big-endian `bytes` into little-endian `limbs`.
+fn from_bytes(bytes: &[u8], limbs: &mut [u32]) {
+    for (limb, chunk) in limbs.iter_mut().zip(bytes.rchunks(4)) {
+        *limb = from_be(chunk);
+    }
+}
+
+/// Convert little-endian `limbs` into big-endian `bytes`.
+fn to_bytes(limbs: &[u32], bytes: &mut [u8]) {
+    for (limb, chunk) in limbs.iter().zip(bytes.rchunks_mut(4)) {
+        chunk.copy_from_slice(&limb.to_be_bytes());
+    }
+}
+
+/// The inverse of the odd `x` modulo 2^32, by Newton's method.
+fn inverse(x: u32) -> u32 {
+    // Each iteration doubles the number of correct low bits.
+    let mut inv: u32 = 1;
+    for _ in 0..5 {
+        inv = inv.wrapping_mul(2u32.wrapping_sub(x.wrapping_mul(inv)));
+    }
+    inv
+}
+
+fn less_than(a: &[u32], b: &[u32]) -> bool {
+    for (x, y) in a.iter().rev().zip(b.iter().rev()) {
+        if x != y {
+            return x < y;
+        }
+    }
+    false
+}
+
+/// `a -= b`, ignoring the final borrow.
+fn subtract(a: &mut [u32], b: &[u32]) {
+    let mut borrow = false;
+    for (x, y) in a.iter_mut().zip(b.iter()) {
+        let (d1, b1) = x.overflowing_sub(*y);
+        let (d2, b2) = d1.overflowing_sub(borrow as u32);
+        *x = d2;
+        borrow = b1 || b2;
+    }
+}
+
+/// `x = 2 * x mod n`, for `x < n`.
+fn double_mod(x: &mut [u32], n: &[u32]) {
+    let mut carry = 0;
+    for limb in x.iter_mut() {
+        let next = *limb >> 31;
+        *limb = (*limb << 1) | carry;
+        carry = next;
+    }
+    if carry != 0 || !less_than(x, n) {
+        subtract(x, n);
+    }
+}
+
+/// Montgomery multiplication: `product = a * b / 2^(32 * limbs) mod n`, where
+/// `limbs` is the number of limbs of `n`. `product` must have `limbs + 2`
+/// limbs and the result is left in its first `limbs` limbs.
+fn mont_mul(product: &mut [u32], a: &[u32], b: &[u32], n: &[u32], n_inverse: u32) {
+    let limbs = n.len();
+    product.fill(0);
+
+    for i in 0..limbs {
+        // product += a * b[i]
+        let mut carry: u64 = 0;
+        for j in 0..limbs {
+            let sum = product[j] as u64 + a[j] as u64 * b[i] as u64 + carry;
+            product[j] = sum as u32;
+            carry = sum >> 32;
+        }
+        let sum = product[limbs] as u64 + carry;
+        product[limbs] = sum as u32;
+        product[limbs + 1] = (sum >> 32) as u32;
+
+        // product = (product + factor * n) / 2^32, with factor chosen so that the
+        // division is exact.
+        let factor = product[0].wrapping_mul(n_inverse);
+        let sum = product[0] as u64 + factor as u64 * n[0] as u64;
+        let mut carry = sum >> 32;
+        for j in 1..limbs {
+            let sum = product[j] as u64 + factor as u64 * n[j] as u64 + carry;
+            product[j - 1] = sum as u32;
+            carry = sum >> 32;
+        }
+        let sum = product[limbs] as u64 + carry;
+        product[limbs - 1] = sum as u32;
+        product[limbs] = product[limbs + 1] + (sum >> 32) as u32;
+    }
+
+    if product[limbs] != 0 || !less_than(&product[..limbs], n) {
+        subtract(&mut product[..limbs], n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::sha256::Sha256Software;
+    use kernel::hil::digest::DigestDataHash;
+    use kernel::process_checker::rsa::{AppCheckerRsa, RsaPadding, RsaPublicKey};
+    use kernel::process_checker::{AppCredentialsChecker, CheckResult};
+    use std::sync::Mutex;
+    use tock_tbf::types::TbfFooterV2Credentials;
+
+    // Deferred calls are global state, so only one test may use them at a
+    // time.
+    static DEFERRED_CALLS: Mutex<()> = Mutex::new(());
+
+    // The vectors below were generated with OpenSSL (through Python's
+    // `cryptography` package) and signed `BINARY` with SHA-256.
+    static BINARY: &[u8] = b"Tock application binary used to test RSA application credentials.";
+    static TAMPERED_BINARY: &[u8] =
+        b"Tock application binary used to test RSA application credentials!";
+    static PUBLIC_EXPONENT: [u8; 3] = [0x01, 0x00, 0x01];
+
+    static MODEXP_MESSAGE: [u8; 128] = [
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+        0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e,
+        0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2d,
+        0x2e, 0x2f, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c,
+        0x3d, 0x3e, 0x3f, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x4b,
+        0x4c, 0x4d, 0x4e, 0x4f, 0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a,
+        0x5b, 0x5c, 0x5d, 0x5e, 0x5f, 0x60, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+        0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f, 0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78,
+        0x79, 0x7a, 0x7b, 0x7c, 0x7d, 0x7e, 0x7f, 0x80,
+    ];
+
+    static MODEXP_MODULUS: [u8; 128] = [
+        0xc3, 0xdd, 0x25, 0xfc, 0xd3, 0x73, 0x05, 0xbc, 0xe0, 0xcc, 0x16, 0x89, 0xa8, 0x84, 0x43,
+        0x0a, 0x23, 0x26, 0x6c, 0xc2, 0x4b, 0x00, 0xef, 0x91, 0xdb, 0xdf, 0x3c, 0xbf, 0xc0, 0xac,
+        0x91, 0xa0, 0xde, 0xc5, 0xed, 0x54, 0x46, 0x01, 0x40, 0x58, 0x7b, 0x73, 0x7b, 0x4e, 0x8d,
+        0x36, 0xd5, 0xf7, 0x7b, 0xd2, 0x70, 0x1a, 0x00, 0x3f, 0x54, 0xbe, 0x04, 0x3f, 0x60, 0x24,
+        0xb3, 0x99, 0x54, 0xfc, 0x46, 0xef, 0x1f, 0x32, 0x29, 0xb0, 0x4d, 0xcf, 0xd3, 0x37, 0x00,
+        0xff, 0x15, 0x83, 0x9e, 0xfe, 0x40, 0xff, 0xe9, 0x2e, 0x56, 0xb7, 0x44, 0x3c, 0xc5, 0xbd,
+        0xf1, 0x9a, 0x35, 0xff, 0xa2, 0x79, 0xca, 0xeb, 0x3d, 0x7d, 0x35, 0x51, 0xa2, 0x55, 0x77,
+        0x6b, 0x1b, 0xd4, 0x39, 0x1e, 0xd9, 0x27, 0xeb, 0xa7, 0x3e, 0xad, 0x07, 0x52, 0xcb, 0x26,
+        0xff, 0x83, 0x90, 0x26, 0x68, 0x00, 0xe0, 0x9b,
+    ];
+
+    static MODEXP_EXPONENT: [u8; 128] = [
+        0x81, 0x7f, 0xbf, 0x75, 0x19, 0x4d, 0x2c, 0x97, 0x04, 0x3e, 0x8c, 0x0c, 0x3a, 0x3a, 0x30,
+        0xa5, 0x0c, 0x36, 0x84, 0xa4, 0x35, 0xb9, 0x09, 0xac, 0xf3, 0xa5, 0xf6, 0xe1, 0x85, 0x7f,
+        0xd3, 0xcd, 0x7c, 0x5d, 0x7b, 0x1c, 0x44, 0x28, 0xe1, 0xa5, 0x5f, 0x02, 0x2c, 0x9d, 0xf4,
+        0x2a, 0x34, 0x70, 0x2c, 0x13, 0x28, 0x01, 0x58, 0xf6, 0x86, 0x8e, 0x17, 0xfa, 0xdb, 0x13,
+        0x89, 0x17, 0xb1, 0x22, 0xed, 0xc7, 0x21, 0xdb, 0xa1, 0xbb, 0x4b, 0xe2, 0x41, 0x78, 0x7e,
+        0x6c, 0x5e, 0xe9, 0x85, 0x4f, 0xff, 0xbf, 0x84, 0xc7, 0x05, 0x7f, 0x53, 0x75, 0xf2, 0x07,
+        0xe1, 0xf6, 0x08, 0x85, 0x27, 0xdd, 0x32, 0xfb, 0xca, 0xda, 0xd9, 0x65, 0x0d, 0x0e, 0x46,
+        0x73, 0x5a, 0x3f, 0xa0, 0xcb, 0x07, 0x75, 0x9c, 0x0c, 0x38, 0x32, 0x0c, 0xb8, 0xde, 0xf1,
+        0x98, 0x05, 0xd8, 0x7e, 0x81, 0x35, 0x56, 0x79,
+    ];
+
+    static MODEXP_RESULT: [u8; 128] = [
+        0xa1, 0x13, 0xce, 0x2f, 0xa7, 0x12, 0x3d, 0x53, 0x09, 0xf4, 0x2b, 0x53, 0x17, 0x52, 0xc3,
+        0x05, 0x52, 0xca, 0x9b, 0xca, 0xb8, 0x07, 0x15, 0xe7, 0x66, 0x72, 0xb3, 0x98, 0xe2, 0x91,
+        0xf2, 0x73, 0xcd, 0xe1, 0xcb, 0x1b, 0xdd, 0x77, 0xdb, 0x3d, 0x7b, 0x3a, 0x64, 0x89, 0x9f,
+        0x27, 0xd9, 0x55, 0x4a, 0x80, 0xe9, 0x34, 0x59, 0xfc, 0x09, 0x39, 0xc5, 0x92, 0xb2, 0xe3,
+        0x29, 0x32, 0x29, 0x6a, 0x6c, 0x7b, 0x9a, 0xfa, 0x23, 0x5e, 0xd5, 0x86, 0x24, 0x72, 0xf7,
+        0x59, 0xd2, 0x67, 0x84, 0xa1, 0x16, 0x63, 0x6d, 0xfe, 0x8c, 0xfd, 0x72, 0x48, 0x11, 0x18,
+        0x4c, 0xef, 0xbe, 0xeb, 0xc4, 0x6a, 0xae, 0xb8, 0x4e, 0x10, 0x33, 0xf4, 0xc2, 0xbb, 0x34,
+        0x46, 0x94, 0x0d, 0x0f, 0xaf, 0xd3, 0x3b, 0x65, 0xbc, 0x9e, 0x7d, 0x5b, 0x3f, 0xf2, 0x5a,
+        0x9e, 0x98, 0x28, 0x17, 0x5d, 0x0b, 0x74, 0x3e,
+    ];
+
+    static RSA3072_MODULUS: [u8; 384] = [
+        0xed, 0x8c, 0xf8, 0xb2, 0x80, 0x9b, 0x0a, 0x8f, 0x76, 0xca, 0xc9, 0xaa, 0x13, 0xae, 0x44,
+        0x15, 0xd8, 0x04, 0xe5, 0x39, 0x18, 0xfb, 0x7f, 0x52, 0x76, 0x4f, 0xdc, 0xa2, 0xeb, 0xaa,
+        0x1e, 0x33, 0x79, 0x68, 0x6b, 0x76, 0x73, 0x4f, 0x7c, 0xd6, 0x19, 0xac, 0x1a, 0x67, 0x2d,
+        0x64, 0x0b, 0x2a, 0x1c, 0xf9, 0x90, 0x07, 0x41, 0x62, 0x77, 0x5d, 0xcf, 0x8a, 0xb6, 0x6b,
+        0x80, 0x13, 0xe5, 0x94, 0x6d, 0x5e, 0xaa, 0xba, 0xd6, 0x32, 0x17, 0x0e, 0xf0, 0xcf, 0xe9,
+        0x44, 0x3b, 0x7d, 0xc9, 0x02, 0x41, 0xb8, 0x8c, 0x21, 0x27, 0xae, 0x20, 0x53, 0x06, 0x03,
+        0x21, 0x97, 0x8a, 0x89, 0x10, 0x6a, 0xe2, 0x89, 0x0a, 0x78, 0x45, 0x9c, 0xad, 0x0b, 0x3d,
+        0xcb, 0x44, 0xcf, 0xb7, 0xa4, 0x1d, 0x6a, 0xcb, 0xd2, 0x3d, 0x48, 0x32, 0x22, 0x36, 0x99,
+        0xa0, 0x04, 0xf3, 0xca, 0x6b, 0x66, 0xfa, 0xd0, 0xab, 0x19, 0x92, 0xf7, 0xf5, 0xa4, 0x8f,
+        0x97, 0x81, 0x5d, 0x9f, 0xd9, 0x20, 0xe8, 0xad, 0x29, 0xd2, 0xe3, 0xd4, 0x9c, 0x98, 0xe3,
+        0x5e, 0xa5, 0x5d, 0x08, 0x98, 0x8f, 0x6b, 0x27, 0xc0, 0x38, 0x66, 0xcb, 0x64, 0x00, 0x3f,
+        0xdc, 0x63, 0x48, 0x70, 0x43, 0x47, 0x61, 0x28, 0x6d, 0xe8, 0xbd, 0x28, 0xbe, 0x99, 0x71,
+        0xcb, 0xcb, 0xfd, 0x5f, 0x50, 0x6c, 0x25, 0x40, 0xf0, 0x4b, 0x47, 0x49, 0x20, 0xab, 0x17,
+        0x8a, 0x34, 0x45, 0x2b, 0x98, 0x6c, 0xbe, 0x19, 0x19, 0x53, 0x3d, 0x06, 0x86, 0x53, 0xe3,
+        0xa1, 0xdb, 0x86, 0xd6, 0x55, 0xc8, 0x95, 0x80, 0x59, 0x82, 0x99, 0xc2, 0xb7, 0x3c, 0x16,
+        0xba, 0xf3, 0xb9, 0x74, 0x3f, 0xee, 0x48, 0x40, 0xe6, 0x0d, 0x88, 0x10, 0xbd, 0xa8, 0xba,
+        0x9e, 0x64, 0x0d, 0xce, 0x1f, 0xb2, 0xf6, 0x45, 0x75, 0x56, 0x8b, 0x5d, 0x4c, 0xb8, 0x0c,
+        0x1a, 0x38, 0xbc, 0xd4, 0x64, 0x94, 0x0d, 0xcf, 0x66, 0x63, 0x4e, 0xa1, 0xc2, 0xfd, 0x24,
+        0xd2, 0x13, 0xbb, 0x20, 0x3f, 0xca, 0xd5, 0xc1, 0x2e, 0x67, 0xf4, 0xd6, 0x1e, 0xe2, 0x19,
+        0x58, 0x74, 0x60, 0x3f, 0x4b, 0xeb, 0xeb, 0x08, 0x5e, 0x7e, 0x9d, 0x4b, 0xa0, 0xb4, 0xfe,
+        0x4d, 0x52, 0x55, 0x6e, 0x4d, 0x55, 0x59, 0xdc, 0x19, 0x1d, 0xe4, 0x57, 0xad, 0x89, 0x6d,
+        0x11, 0xa8, 0x88, 0x35, 0x99, 0xc7, 0x7f, 0xbd, 0xc5, 0x74, 0x2c, 0x20, 0x64, 0xbf, 0xc8,
+        0xdb, 0xb7, 0x75, 0x01, 0x6d, 0xe8, 0xc6, 0xf0, 0x24, 0x8b, 0x3e, 0xea, 0x89, 0x1a, 0xde,
+        0x96, 0x7a, 0x9f, 0x87, 0x41, 0xd5, 0xb0, 0xe2, 0x55, 0xd7, 0x84, 0xdd, 0x29, 0xe7, 0x73,
+        0x48, 0x80, 0xe4, 0xc3, 0x7f, 0x1f, 0xb6, 0x54, 0x93, 0x9b, 0xfc, 0x1f, 0x39, 0xe5, 0xcb,
+        0x29, 0x14, 0x18, 0x8d, 0x25, 0x28, 0xa1, 0x24, 0x79,
+    ];
+
+    static RSA3072_PKCS1V15_CREDENTIALS: [u8; 772] = [
+        0x01, 0x00, 0x00, 0x00, 0xed, 0x8c, 0xf8, 0xb2, 0x80, 0x9b, 0x0a, 0x8f, 0x76, 0xca, 0xc9,
+        0xaa, 0x13, 0xae, 0x44, 0x15, 0xd8, 0x04, 0xe5, 0x39, 0x18, 0xfb, 0x7f, 0x52, 0x76, 0x4f,
+        0xdc, 0xa2, 0xeb, 0xaa, 0x1e, 0x33, 0x79, 0x68, 0x6b, 0x76, 0x73, 0x4f, 0x7c, 0xd6, 0x19,
+        0xac, 0x1a, 0x67, 0x2d, 0x64, 0x0b, 0x2a, 0x1c, 0xf9, 0x90, 0x07, 0x41, 0x62, 0x77, 0x5d,
+        0xcf, 0x8a, 0xb6, 0x6b, 0x80, 0x13, 0xe5, 0x94, 0x6d, 0x5e, 0xaa, 0xba, 0xd6, 0x32, 0x17,
+        0x0e, 0xf0, 0xcf, 0xe9, 0x44, 0x3b, 0x7d, 0xc9, 0x02, 0x41, 0xb8, 0x8c, 0x21, 0x27, 0xae,
+        0x20, 0x53, 0x06, 0x03, 0x21, 0x97, 0x8a, 0x89, 0x10, 0x6a, 0xe2, 0x89, 0x0a, 0x78, 0x45,
+        0x9c, 0xad, 0x0b, 0x3d, 0xcb, 0x44, 0xcf, 0xb7, 0xa4, 0x1d, 0x6a, 0xcb, 0xd2, 0x3d, 0x48,
+        0x32, 0x22, 0x36, 0x99, 0xa0, 0x04, 0xf3, 0xca, 0x6b, 0x66, 0xfa, 0xd0, 0xab, 0x19, 0x92,
+        0xf7, 0xf5, 0xa4, 0x8f, 0x97, 0x81, 0x5d, 0x9f, 0xd9, 0x20, 0xe8, 0xad, 0x29, 0xd2, 0xe3,
+        0xd4, 0x9c, 0x98, 0xe3, 0x5e, 0xa5, 0x5d, 0x08, 0x98, 0x8f, 0x6b, 0x27, 0xc0, 0x38, 0x66,
+        0xcb, 0x64, 0x00, 0x3f, 0xdc, 0x63, 0x48, 0x70, 0x43, 0x47, 0x61, 0x28, 0x6d, 0xe8, 0xbd,
+        0x28, 0xbe, 0x99, 0x71, 0xcb, 0xcb, 0xfd, 0x5f, 0x50, 0x6c, 0x25, 0x40, 0xf0, 0x4b, 0x47,
+        0x49, 0x20, 0xab, 0x17, 0x8a, 0x34, 0x45, 0x2b, 0x98, 0x6c, 0xbe, 0x19, 0x19, 0x53, 0x3d,
+        0x06, 0x86, 0x53, 0xe3, 0xa1, 0xdb, 0x86, 0xd6, 0x55, 0xc8, 0x95, 0x80, 0x59, 0x82, 0x99,
+        0xc2, 0xb7, 0x3c, 0x16, 0xba, 0xf3, 0xb9, 0x74, 0x3f, 0xee, 0x48, 0x40, 0xe6, 0x0d, 0x88,
+        0x10, 0xbd, 0xa8, 0xba, 0x9e, 0x64, 0x0d, 0xce, 0x1f, 0xb2, 0xf6, 0x45, 0x75, 0x56, 0x8b,
+        0x5d, 0x4c, 0xb8, 0x0c, 0x1a, 0x38, 0xbc, 0xd4, 0x64, 0x94, 0x0d, 0xcf, 0x66, 0x63, 0x4e,
+        0xa1, 0xc2, 0xfd, 0x24, 0xd2, 0x13, 0xbb, 0x20, 0x3f, 0xca, 0xd5, 0xc1, 0x2e, 0x67, 0xf4,
+        0xd6, 0x1e, 0xe2, 0x19, 0x58, 0x74, 0x60, 0x3f, 0x4b, 0xeb, 0xeb, 0x08, 0x5e, 0x7e, 0x9d,
+        0x4b, 0xa0, 0xb4, 0xfe, 0x4d, 0x52, 0x55, 0x6e, 0x4d, 0x55, 0x59, 0xdc, 0x19, 0x1d, 0xe4,
+        0x57, 0xad, 0x89, 0x6d, 0x11, 0xa8, 0x88, 0x35, 0x99, 0xc7, 0x7f, 0xbd, 0xc5, 0x74, 0x2c,
+        0x20, 0x64, 0xbf, 0xc8, 0xdb, 0xb7, 0x75, 0x01, 0x6d, 0xe8, 0xc6, 0xf0, 0x24, 0x8b, 0x3e,
+        0xea, 0x89, 0x1a, 0xde, 0x96, 0x7a, 0x9f, 0x87, 0x41, 0xd5, 0xb0, 0xe2, 0x55, 0xd7, 0x84,
+        0xdd, 0x29, 0xe7, 0x73, 0x48, 0x80, 0xe4, 0xc3, 0x7f, 0x1f, 0xb6, 0x54, 0x93, 0x9b, 0xfc,
+        0x1f, 0x39, 0xe5, 0xcb, 0x29, 0x14, 0x18, 0x8d, 0x25, 0x28, 0xa1, 0x24, 0x79, 0x83, 0x5a,
+        0x78, 0xcf, 0xdd, 0x6d, 0xa1, 0x09, 0xcd, 0x8e, 0x8a, 0x88, 0xc1, 0xa1, 0x83, 0x4a, 0x27,
+        0xf0, 0x6a, 0xbd, 0x90, 0x28, 0xf1, 0x72, 0xf2, 0xb2, 0x7e, 0xeb, 0x24, 0xdc, 0xfa, 0xfa,
+        0x70, 0x1a, 0x60, 0xc0, 0x4b, 0x17, 0xb6, 0x87, 0x3e, 0x76, 0xed, 0x21, 0xfc, 0x0b, 0x6e,
+        0x0a, 0x1b, 0x9b, 0xd7, 0xe9, 0x94, 0x6f, 0x1a, 0xc9, 0x4b, 0x13, 0x58, 0xf7, 0x3a, 0x45,
+        0xcf, 0xbb, 0x2c, 0x24, 0xc6, 0x93, 0x73, 0xc2, 0x09, 0x87, 0xfa, 0x92, 0xc3, 0x80, 0xbe,
+        0xf5, 0x7e, 0x33, 0x44, 0xb6, 0x5c, 0xab, 0x20, 0x8e, 0xda, 0x3e, 0x0a, 0x87, 0x7c, 0x2b,
+        0xae, 0xe0, 0x80, 0xc7, 0x46, 0xa8, 0x1c, 0x2b, 0x1e, 0xf2, 0x56, 0xa1, 0x80, 0x68, 0x91,
+        0x98, 0xf6, 0xfb, 0x2a, 0x95, 0xcf, 0x5d, 0xb3, 0x66, 0xa2, 0x74, 0xb9, 0x62, 0x7f, 0x94,
+        0xae, 0x1e, 0x9f, 0xf6, 0x0a, 0x07, 0xf9, 0x03, 0x60, 0x22, 0x87, 0xce, 0xe1, 0xf1, 0x5a,
+        0xf7, 0xf8, 0x70, 0xf7, 0x45, 0x09, 0x19, 0x58, 0x51, 0xf4, 0xec, 0xbe, 0xbd, 0xf2, 0xba,
+        0x5e, 0x30, 0x1c, 0xf1, 0x85, 0x14, 0x30, 0xe4, 0x44, 0xf9, 0x49, 0x9c, 0x38, 0x29, 0x5b,
+        0x76, 0x3e, 0x5c, 0xbb, 0xd1, 0x17, 0x05, 0x13, 0xc6, 0xf5, 0x62, 0xe4, 0xec, 0xd6, 0xfc,
+        0xff, 0x8a, 0x90, 0xf1, 0xff, 0x7a, 0x6f, 0x13, 0xcf, 0x65, 0x03, 0x07, 0xc0, 0xc2, 0x5b,
+        0x68, 0x45, 0x85, 0xdb, 0xea, 0x24, 0x97, 0x2e, 0xaa, 0x79, 0xaa, 0xfc, 0x8d, 0xa0, 0x05,
+        0x44, 0x28, 0xd1, 0xec, 0x17, 0x88, 0x91, 0x92, 0x0e, 0xf6, 0xc1, 0x26, 0x61, 0x85, 0x72,
+        0xe2, 0x91, 0x36, 0x52, 0xbb, 0xdb, 0x03, 0x81, 0xc5, 0x0f, 0x61, 0x4f, 0x2b, 0xd3, 0x64,
+        0x30, 0x47, 0x28, 0xe7, 0xbe, 0x99, 0x22, 0x5f, 0xf9, 0xc9, 0x8c, 0x12, 0x7e, 0xf6, 0x9a,
+        0x74, 0x87, 0xaf, 0x40, 0xd7, 0x69, 0x20, 0xca, 0x5e, 0x74, 0x25, 0x0f, 0x42, 0xca, 0x6a,
+        0x0b, 0x44, 0xdc, 0xd7, 0x0f, 0x1c, 0x41, 0x07, 0xd7, 0x20, 0x45, 0x90, 0x70, 0x62, 0x20,
+        0x7b, 0xa2, 0xb5, 0x13, 0xf8, 0x2a, 0x6e, 0x22, 0x23, 0xad, 0x46, 0xb7, 0x97, 0xc8, 0x15,
+        0xd2, 0x17, 0x5a, 0x54, 0xbe, 0xed, 0xb6, 0x44, 0x91, 0xf3, 0xeb, 0x95, 0xff, 0x52, 0x65,
+        0x4e, 0x4a, 0x64, 0x4f, 0x6c, 0x48, 0xd6, 0xdf, 0x32, 0x4d, 0xdb, 0xd5, 0x12, 0x42, 0xb7,
+        0x4b, 0x0b, 0x7d, 0xa6, 0xa6, 0x9b, 0x2a, 0xb0, 0x1f, 0x12, 0x32, 0x11, 0xbf, 0x65, 0xcb,
+        0x18, 0x6a, 0x21, 0x41, 0x5f, 0x5f, 0x56, 0xed, 0xbc, 0xb5, 0x80, 0x73, 0x26, 0x0b, 0x84,
+        0x23, 0xd3, 0xaf, 0xc3, 0x07, 0x11, 0x7f, 0x6d, 0x6f, 0xbc, 0xbd, 0x4c, 0x05, 0x9b, 0x98,
+        0x4f, 0xd7, 0x2a, 0xe2, 0x93, 0x3b, 0x8e,
+    ];
+
+    static RSA3072_PSS_CREDENTIALS: [u8; 772] = [
+        0x01, 0x00, 0x00, 0x00, 0xed, 0x8c, 0xf8, 0xb2, 0x80, 0x9b, 0x0a, 0x8f, 0x76, 0xca, 0xc9,
+        0xaa, 0x13, 0xae, 0x44, 0x15, 0xd8, 0x04, 0xe5, 0x39, 0x18, 0xfb, 0x7f, 0x52, 0x76, 0x4f,
+        0xdc, 0xa2, 0xeb, 0xaa, 0x1e, 0x33, 0x79, 0x68, 0x6b, 0x76, 0x73, 0x4f, 0x7c, 0xd6, 0x19,
+        0xac, 0x1a, 0x67, 0x2d, 0x64, 0x0b, 0x2a, 0x1c, 0xf9, 0x90, 0x07, 0x41, 0x62, 0x77, 0x5d,
+        0xcf, 0x8a, 0xb6, 0x6b, 0x80, 0x13, 0xe5, 0x94, 0x6d, 0x5e, 0xaa, 0xba, 0xd6, 0x32, 0x17,
+        0x0e, 0xf0, 0xcf, 0xe9, 0x44, 0x3b, 0x7d, 0xc9, 0x02, 0x41, 0xb8, 0x8c, 0x21, 0x27, 0xae,
+        0x20, 0x53, 0x06, 0x03, 0x21, 0x97, 0x8a, 0x89, 0x10, 0x6a, 0xe2, 0x89, 0x0a, 0x78, 0x45,
+        0x9c, 0xad, 0x0b, 0x3d, 0xcb, 0x44, 0xcf, 0xb7, 0xa4, 0x1d, 0x6a, 0xcb, 0xd2, 0x3d, 0x48,
+        0x32, 0x22, 0x36, 0x99, 0xa0, 0x04, 0xf3, 0xca, 0x6b, 0x66, 0xfa, 0xd0, 0xab, 0x19, 0x92,
+        0xf7, 0xf5, 0xa4, 0x8f, 0x97, 0x81, 0x5d, 0x9f, 0xd9, 0x20, 0xe8, 0xad, 0x29, 0xd2, 0xe3,
+        0xd4, 0x9c, 0x98, 0xe3, 0x5e, 0xa5, 0x5d, 0x08, 0x98, 0x8f, 0x6b, 0x27, 0xc0, 0x38, 0x66,
+        0xcb, 0x64, 0x00, 0x3f, 0xdc, 0x63, 0x48, 0x70, 0x43, 0x47, 0x61, 0x28, 0x6d, 0xe8, 0xbd,
+        0x28, 0xbe, 0x99, 0x71, 0xcb, 0xcb, 0xfd, 0x5f, 0x50, 0x6c, 0x25, 0x40, 0xf0, 0x4b, 0x47,
+        0x49, 0x20, 0xab, 0x17, 0x8a, 0x34, 0x45, 0x2b, 0x98, 0x6c, 0xbe, 0x19, 0x19, 0x53, 0x3d,
+        0x06, 0x86, 0x53, 0xe3, 0xa1, 0xdb, 0x86, 0xd6, 0x55, 0xc8, 0x95, 0x80, 0x59, 0x82, 0x99,
+        0xc2, 0xb7, 0x3c, 0x16, 0xba, 0xf3, 0xb9, 0x74, 0x3f, 0xee, 0x48, 0x40, 0xe6, 0x0d, 0x88,
+        0x10, 0xbd, 0xa8, 0xba, 0x9e, 0x64, 0x0d, 0xce, 0x1f, 0xb2, 0xf6, 0x45, 0x75, 0x56, 0x8b,
+        0x5d, 0x4c, 0xb8, 0x0c, 0x1a, 0x38, 0xbc, 0xd4, 0x64, 0x94, 0x0d, 0xcf, 0x66, 0x63, 0x4e,
+        0xa1, 0xc2, 0xfd, 0x24, 0xd2, 0x13, 0xbb, 0x20, 0x3f, 0xca, 0xd5, 0xc1, 0x2e, 0x67, 0xf4,
+        0xd6, 0x1e, 0xe2, 0x19, 0x58, 0x74, 0x60, 0x3f, 0x4b, 0xeb, 0xeb, 0x08, 0x5e, 0x7e, 0x9d,
+        0x4b, 0xa0, 0xb4, 0xfe, 0x4d, 0x52, 0x55, 0x6e, 0x4d, 0x55, 0x59, 0xdc, 0x19, 0x1d, 0xe4,
+        0x57, 0xad, 0x89, 0x6d, 0x11, 0xa8, 0x88, 0x35, 0x99, 0xc7, 0x7f, 0xbd, 0xc5, 0x74, 0x2c,
+        0x20, 0x64, 0xbf, 0xc8, 0xdb, 0xb7, 0x75, 0x01, 0x6d, 0xe8, 0xc6, 0xf0, 0x24, 0x8b, 0x3e,
+        0xea, 0x89, 0x1a, 0xde, 0x96, 0x7a, 0x9f, 0x87, 0x41, 0xd5, 0xb0, 0xe2, 0x55, 0xd7, 0x84,
+        0xdd, 0x29, 0xe7, 0x73, 0x48, 0x80, 0xe4, 0xc3, 0x7f, 0x1f, 0xb6, 0x54, 0x93, 0x9b, 0xfc,
+        0x1f, 0x39, 0xe5, 0xcb, 0x29, 0x14, 0x18, 0x8d, 0x25, 0x28, 0xa1, 0x24, 0x79, 0x86, 0xe3,
+        0x31, 0x4c, 0x45, 0x53, 0x20, 0x1b, 0x9f, 0xa8, 0x22, 0xa7, 0x8d, 0x1e, 0xe3, 0x93, 0x48,
+        0x28, 0x34, 0xec, 0x36, 0xa3, 0x4b, 0xde, 0xaf, 0x90, 0x0f, 0x9f, 0x66, 0x6a, 0xf2, 0x53,
+        0xd3, 0x25, 0x9b, 0x1c, 0x55, 0x13, 0x93, 0xd6, 0x41, 0xac, 0x56, 0x34, 0x83, 0x88, 0xc2,
+        0xb7, 0xc6, 0x2a, 0x83, 0xaf, 0x52, 0x55, 0x18, 0x54, 0x4a, 0xcb, 0x72, 0x6d, 0x23, 0x9d,
+        0x97, 0xb5, 0x31, 0x54, 0x14, 0x88, 0xfd, 0x26, 0x14, 0x5d, 0x26, 0xc3, 0x96, 0xd9, 0x34,
+        0x9b, 0x98, 0x93, 0x7d, 0x88, 0xc5, 0xe4, 0xe6, 0xcd, 0xe0, 0x8d, 0xa6, 0x62, 0x88, 0xff,
+        0x52, 0x43, 0x22, 0xa2, 0x2a, 0x06, 0x3a, 0xc6, 0x2d, 0x57, 0x4d, 0x39, 0xad, 0x56, 0xae,
+        0x61, 0x35, 0xe0, 0xe8, 0x33, 0x5b, 0x8c, 0xa4, 0x31, 0x2b, 0x9e, 0x2a, 0x2c, 0x49, 0xed,
+        0x12, 0xa7, 0x65, 0x6a, 0xb1, 0xd9, 0x37, 0x61, 0xd4, 0x20, 0x7c, 0x41, 0x4c, 0xd5, 0x52,
+        0x97, 0xf8, 0xcb, 0xef, 0x5d, 0xe6, 0x92, 0x43, 0xed, 0x68, 0x02, 0x89, 0x85, 0xf0, 0xf9,
+        0xcd, 0x2d, 0xba, 0x4a, 0x82, 0xb0, 0xf8, 0x7f, 0x0d, 0x04, 0xf1, 0xce, 0xc7, 0x25, 0x33,
+        0xb0, 0x6d, 0xad, 0x69, 0xed, 0x11, 0x64, 0xcf, 0xf8, 0x54, 0x1b, 0x9a, 0xe9, 0xd5, 0x41,
+        0xce, 0xa6, 0xe6, 0xea, 0x24, 0x0b, 0xab, 0xcf, 0x63, 0x58, 0x8d, 0xc1, 0x12, 0xa5, 0x55,
+        0x7e, 0x1e, 0xcc, 0x22, 0x9b, 0x3e, 0xc0, 0x69, 0xa9, 0xd7, 0xd0, 0xef, 0x5f, 0x15, 0x14,
+        0xe3, 0x10, 0x03, 0x3a, 0x2c, 0xdc, 0x9a, 0x93, 0xa1, 0x07, 0xce, 0x1b, 0x84, 0x26, 0x80,
+        0x21, 0x37, 0x57, 0x25, 0x61, 0x5f, 0x10, 0x98, 0x3d, 0xe9, 0x15, 0xe0, 0xfc, 0x48, 0xf4,
+        0x9d, 0xcd, 0xd6, 0xae, 0x66, 0xe0, 0xbe, 0xb6, 0x50, 0x88, 0xe2, 0xc0, 0x8c, 0xf6, 0x77,
+        0x8e, 0x31, 0x8e, 0x1f, 0xa7, 0xa3, 0xa7, 0x84, 0x76, 0xcf, 0xeb, 0x82, 0xbc, 0x4e, 0x57,
+        0x6e, 0x28, 0xbc, 0x66, 0x46, 0x5e, 0x9a, 0xa8, 0x4c, 0xcc, 0xa8, 0x0f, 0xd5, 0x14, 0x9c,
+        0x97, 0xa7, 0x87, 0x5d, 0x12, 0x0a, 0xa4, 0x32, 0x0f, 0x54, 0x3d, 0x36, 0xb3, 0x77, 0x71,
+        0x1a, 0x05, 0xad, 0x44, 0x60, 0x5d, 0x00, 0x06, 0xf7, 0x87, 0x98, 0xec, 0x90, 0x7c, 0xc6,
+        0x2c, 0x88, 0x58, 0x9d, 0x90, 0x27, 0x16, 0xa4, 0x9f, 0xd9, 0x22, 0x9f, 0x29, 0x0f, 0x35,
+        0x97, 0x85, 0x79, 0x1b, 0x36, 0x43, 0xa1, 0xfa, 0x0f, 0xc5, 0xe4, 0xb1, 0x63, 0xee, 0x68,
+        0xb5, 0xc6, 0xf9, 0x71, 0xf3, 0x85, 0xde, 0xc1, 0xd9, 0x1b, 0xd6, 0x26, 0xf7, 0x44, 0x7e,
+        0x5b, 0x59, 0xa7, 0xe5, 0xc9, 0x70, 0x5b, 0x8c, 0x58, 0x36, 0xcf, 0x9e, 0x56, 0x0c, 0x93,
+        0xa3, 0xef, 0xed, 0x81, 0x1b, 0x0a, 0xa0,
+    ];
+
+    static RSA4096_MODULUS: [u8; 512] = [
+        0xcd, 0xca, 0x7f, 0xab, 0x76, 0x36, 0xc7, 0xca, 0x54, 0xf7, 0x93, 0x17, 0x99, 0x5f, 0x0c,
+        0x95, 0xf9, 0x92, 0x24, 0x0a, 0xde, 0x08, 0x76, 0x0e, 0x2b, 0x4f, 0x10, 0xc0, 0x8f, 0x9e,
+        0x42, 0x81, 0xa8, 0xf0, 0x0b, 0x8d, 0xfe, 0xbf, 0x1d, 0x76, 0xc7, 0x08, 0x32, 0x52, 0x03,
+        0xf1, 0x48, 0x6f, 0x20, 0x34, 0xe0, 0x14, 0xe9, 0xe5, 0xd4, 0xfe, 0xe0, 0xb5, 0x88, 0x2a,
+        0xf1, 0xdb, 0x5c, 0xe3, 0x2f, 0x04, 0xee, 0x2e, 0xd5, 0xa5, 0x2a, 0xca, 0xfc, 0x9e, 0x5c,
+        0xb1, 0xa0, 0xf0, 0x23, 0x26, 0x34, 0xcb, 0x03, 0xd1, 0x76, 0x1e, 0xaa, 0x98, 0x4e, 0xf2,
+        0xaf, 0x47, 0x59, 0x22, 0xff, 0xf1, 0x07, 0x4a, 0x2e, 0xe5, 0x1e, 0x6d, 0x6b, 0xe6, 0xf1,
+        0xee, 0x4a, 0x17, 0xcc, 0xc0, 0xc9, 0x91, 0xaf, 0xad, 0x7f, 0x6b, 0x34, 0xa6, 0x45, 0x4c,
+        0x5a, 0xd7, 0x01, 0x56, 0x31, 0x70, 0x12, 0xe8, 0x9c, 0xb1, 0x39, 0x11, 0x52, 0x04, 0x6b,
+        0xa0, 0x54, 0xeb, 0x64, 0xfd, 0x98, 0x80, 0xf4, 0x77, 0x9a, 0xfe, 0x01, 0x00, 0xf3, 0x37,
+        0xff, 0x66, 0xa7, 0x23, 0x16, 0xe5, 0xa5, 0x36, 0x74, 0xc1, 0xf8, 0x63, 0x07, 0xee, 0xea,
+        0x3e, 0x37, 0x26, 0x1a, 0xb3, 0x61, 0x81, 0x42, 0x93, 0x0a, 0xbc, 0x2f, 0x39, 0xef, 0xe0,
+        0xe0, 0xc9, 0x9c, 0xb8, 0x20, 0x04, 0x0e, 0x7a, 0xaf, 0xd8, 0x31, 0x49, 0x09, 0xc9, 0xfd,
+        0x0f, 0xd5, 0xa9, 0xfe, 0x4c, 0xe2, 0x78, 0x71, 0xbc, 0x6f, 0x62, 0x04, 0x9e, 0x1f, 0x8f,
+        0x65, 0xdc, 0x5e, 0xde, 0xf5, 0x14, 0xd4, 0xdb, 0x81, 0x7b, 0x1c, 0x23, 0x77, 0x50, 0x70,
+        0xaa, 0x3b, 0x24, 0x9d, 0xf4, 0xf3, 0x40, 0x9f, 0x45, 0x11, 0xed, 0x4a, 0x00, 0x4e, 0xe0,
+        0x8d, 0x68, 0xe5, 0xcc, 0xbe, 0xd5, 0x49, 0x64, 0x0c, 0x29, 0x15, 0x5b, 0x51, 0x52, 0xbf,
+        0xe9, 0x42, 0xc0, 0xab, 0x5a, 0x46, 0xfb, 0x1d, 0x67, 0x69, 0x2f, 0xd6, 0x5e, 0x7c, 0x55,
+        0xdb, 0xd2, 0x3f, 0x94, 0x53, 0xa4, 0x31, 0x80, 0x4f, 0x2a, 0xa4, 0x78, 0xed, 0xd2, 0x45,
+        0xc7, 0x9a, 0x17, 0x3c, 0x9b, 0xfe, 0x78, 0x9f, 0x29, 0xef, 0x96, 0x1a, 0x62, 0x4b, 0x87,
+        0x45, 0x78, 0xc6, 0xba, 0xaf, 0x4b, 0x15, 0x26, 0x56, 0x9b, 0xaa, 0x14, 0xac, 0x84, 0x94,
+        0x71, 0xad, 0x63, 0xd6, 0x57, 0xd6, 0x37, 0x94, 0xdc, 0x0f, 0x1b, 0xb5, 0xb1, 0xf3, 0x1c,
+        0x63, 0x5d, 0x86, 0x96, 0xee, 0xcc, 0xff, 0xa5, 0x82, 0xbe, 0xe3, 0x55, 0xad, 0x38, 0x7f,
+        0xe4, 0xdb, 0xc4, 0x04, 0xf9, 0xd3, 0xfd, 0x92, 0x02, 0x70, 0x08, 0x78, 0x56, 0xc4, 0xa1,
+        0x5e, 0x52, 0xa2, 0xf6, 0x98, 0xb6, 0xb1, 0x6e, 0x4e, 0xbf, 0x73, 0x2e, 0x1e, 0x2c, 0x79,
+        0xf3, 0xf6, 0x5c, 0x79, 0xeb, 0x77, 0xb0, 0xa8, 0x5c, 0xc2, 0x3d, 0x93, 0xc2, 0xc2, 0x66,
+        0x68, 0x4d, 0x23, 0xe4, 0x8d, 0x8f, 0x19, 0x12, 0x37, 0x26, 0x98, 0x30, 0x68, 0xef, 0x9b,
+        0x61, 0xa6, 0x01, 0x3d, 0x83, 0xec, 0xa3, 0x16, 0x5e, 0xb1, 0xcd, 0xe7, 0x3c, 0xf3, 0xf0,
+        0xfd, 0xd7, 0xd4, 0x22, 0x71, 0x61, 0x3f, 0x56, 0xcf, 0x2c, 0x9e, 0xb1, 0x9d, 0xb1, 0x89,
+        0xee, 0xe5, 0x20, 0x14, 0x63, 0xfb, 0x51, 0x52, 0x52, 0xe4, 0x67, 0xce, 0xc3, 0x2e, 0x32,
+        0xd5, 0x56, 0x48, 0x08, 0x03, 0x46, 0x3e, 0xe1, 0xa7, 0xe6, 0x3d, 0xc9, 0x98, 0x13, 0x15,
+        0x2e, 0x58, 0x5b, 0x96, 0x6a, 0x0a, 0xb2, 0x4b, 0xfa, 0xd4, 0xbd, 0xcb, 0x6f, 0xc7, 0x83,
+        0xeb, 0xe6, 0xec, 0x51, 0x25, 0x27, 0x6a, 0x93, 0xac, 0xdd, 0x57, 0x5d, 0xa9, 0xea, 0xda,
+        0xb0, 0x51, 0x74, 0x61, 0x0b, 0x0f, 0xc5, 0xcb, 0xaa, 0x01, 0xfc, 0x21, 0x37, 0x9e, 0x3c,
+        0x61, 0x6f,
+    ];
+
+    static RSA4096_PKCS1V15_CREDENTIALS: [u8; 1028] = [
+        0x02, 0x00, 0x00, 0x00, 0xcd, 0xca, 0x7f, 0xab, 0x76, 0x36, 0xc7, 0xca, 0x54, 0xf7, 0x93,
+        0x17, 0x99, 0x5f, 0x0c, 0x95, 0xf9, 0x92, 0x24, 0x0a, 0xde, 0x08, 0x76, 0x0e, 0x2b, 0x4f,
+        0x10, 0xc0, 0x8f, 0x9e, 0x42, 0x81, 0xa8, 0xf0, 0x0b, 0x8d, 0xfe, 0xbf, 0x1d, 0x76, 0xc7,
+        0x08, 0x32, 0x52, 0x03, 0xf1, 0x48, 0x6f, 0x20, 0x34, 0xe0, 0x14, 0xe9, 0xe5, 0xd4, 0xfe,
+        0xe0, 0xb5, 0x88, 0x2a, 0xf1, 0xdb, 0x5c, 0xe3, 0x2f, 0x04, 0xee, 0x2e, 0xd5, 0xa5, 0x2a,
+        0xca, 0xfc, 0x9e, 0x5c, 0xb1, 0xa0, 0xf0, 0x23, 0x26, 0x34, 0xcb, 0x03, 0xd1, 0x76, 0x1e,
+        0xaa, 0x98, 0x4e, 0xf2, 0xaf, 0x47, 0x59, 0x22, 0xff, 0xf1, 0x07, 0x4a, 0x2e, 0xe5, 0x1e,
+        0x6d, 0x6b, 0xe6, 0xf1, 0xee, 0x4a, 0x17, 0xcc, 0xc0, 0xc9, 0x91, 0xaf, 0xad, 0x7f, 0x6b,
+        0x34, 0xa6, 0x45, 0x4c, 0x5a, 0xd7, 0x01, 0x56, 0x31, 0x70, 0x12, 0xe8, 0x9c, 0xb1, 0x39,
+        0x11, 0x52, 0x04, 0x6b, 0xa0, 0x54, 0xeb, 0x64, 0xfd, 0x98, 0x80, 0xf4, 0x77, 0x9a, 0xfe,
+        0x01, 0x00, 0xf3, 0x37, 0xff, 0x66, 0xa7, 0x23, 0x16, 0xe5, 0xa5, 0x36, 0x74, 0xc1, 0xf8,
+        0x63, 0x07, 0xee, 0xea, 0x3e, 0x37, 0x26, 0x1a, 0xb3, 0x61, 0x81, 0x42, 0x93, 0x0a, 0xbc,
+        0x2f, 0x39, 0xef, 0xe0, 0xe0, 0xc9, 0x9c, 0xb8, 0x20, 0x04, 0x0e, 0x7a, 0xaf, 0xd8, 0x31,
+        0x49, 0x09, 0xc9, 0xfd, 0x0f, 0xd5, 0xa9, 0xfe, 0x4c, 0xe2, 0x78, 0x71, 0xbc, 0x6f, 0x62,
+        0x04, 0x9e, 0x1f, 0x8f, 0x65, 0xdc, 0x5e, 0xde, 0xf5, 0x14, 0xd4, 0xdb, 0x81, 0x7b, 0x1c,
+        0x23, 0x77, 0x50, 0x70, 0xaa, 0x3b, 0x24, 0x9d, 0xf4, 0xf3, 0x40, 0x9f, 0x45, 0x11, 0xed,
+        0x4a, 0x00, 0x4e, 0xe0, 0x8d, 0x68, 0xe5, 0xcc, 0xbe, 0xd5, 0x49, 0x64, 0x0c, 0x29, 0x15,
+        0x5b, 0x51, 0x52, 0xbf, 0xe9, 0x42, 0xc0, 0xab, 0x5a, 0x46, 0xfb, 0x1d, 0x67, 0x69, 0x2f,
+        0xd6, 0x5e, 0x7c, 0x55, 0xdb, 0xd2, 0x3f, 0x94, 0x53, 0xa4, 0x31, 0x80, 0x4f, 0x2a, 0xa4,
+        0x78, 0xed, 0xd2, 0x45, 0xc7, 0x9a, 0x17, 0x3c, 0x9b, 0xfe, 0x78, 0x9f, 0x29, 0xef, 0x96,
+        0x1a, 0x62, 0x4b, 0x87, 0x45, 0x78, 0xc6, 0xba, 0xaf, 0x4b, 0x15, 0x26, 0x56, 0x9b, 0xaa,
+        0x14, 0xac, 0x84, 0x94, 0x71, 0xad, 0x63, 0xd6, 0x57, 0xd6, 0x37, 0x94, 0xdc, 0x0f, 0x1b,
+        0xb5, 0xb1, 0xf3, 0x1c, 0x63, 0x5d, 0x86, 0x96, 0xee, 0xcc, 0xff, 0xa5, 0x82, 0xbe, 0xe3,
+        0x55, 0xad, 0x38, 0x7f, 0xe4, 0xdb, 0xc4, 0x04, 0xf9, 0xd3, 0xfd, 0x92, 0x02, 0x70, 0x08,
+        0x78, 0x56, 0xc4, 0xa1, 0x5e, 0x52, 0xa2, 0xf6, 0x98, 0xb6, 0xb1, 0x6e, 0x4e, 0xbf, 0x73,
+        0x2e, 0x1e, 0x2c, 0x79, 0xf3, 0xf6, 0x5c, 0x79, 0xeb, 0x77, 0xb0, 0xa8, 0x5c, 0xc2, 0x3d,
+        0x93, 0xc2, 0xc2, 0x66, 0x68, 0x4d, 0x23, 0xe4, 0x8d, 0x8f, 0x19, 0x12, 0x37, 0x26, 0x98,
+        0x30, 0x68, 0xef, 0x9b, 0x61, 0xa6, 0x01, 0x3d, 0x83, 0xec, 0xa3, 0x16, 0x5e, 0xb1, 0xcd,
+        0xe7, 0x3c, 0xf3, 0xf0, 0xfd, 0xd7, 0xd4, 0x22, 0x71, 0x61, 0x3f, 0x56, 0xcf, 0x2c, 0x9e,
+        0xb1, 0x9d, 0xb1, 0x89, 0xee, 0xe5, 0x20, 0x14, 0x63, 0xfb, 0x51, 0x52, 0x52, 0xe4, 0x67,
+        0xce, 0xc3, 0x2e, 0x32, 0xd5, 0x56, 0x48, 0x08, 0x03, 0x46, 0x3e, 0xe1, 0xa7, 0xe6, 0x3d,
+        0xc9, 0x98, 0x13, 0x15, 0x2e, 0x58, 0x5b, 0x96, 0x6a, 0x0a, 0xb2, 0x4b, 0xfa, 0xd4, 0xbd,
+        0xcb, 0x6f, 0xc7, 0x83, 0xeb, 0xe6, 0xec, 0x51, 0x25, 0x27, 0x6a, 0x93, 0xac, 0xdd, 0x57,
+        0x5d, 0xa9, 0xea, 0xda, 0xb0, 0x51, 0x74, 0x61, 0x0b, 0x0f, 0xc5, 0xcb, 0xaa, 0x01, 0xfc,
+        0x21, 0x37, 0x9e, 0x3c, 0x61, 0x6f, 0x9a, 0x4c, 0x1d, 0x42, 0xb6, 0x81, 0x91, 0x0d, 0xa8,
+        0x37, 0x67, 0x94, 0x4b, 0x30, 0x21, 0x0f, 0xe7, 0x30, 0x30, 0xea, 0x22, 0xa4, 0xef, 0x0c,
+        0x37, 0x77, 0x05, 0xca, 0x3c, 0xe6, 0xce, 0x28, 0x52, 0x5d, 0x5b, 0x40, 0x53, 0xd0, 0x81,
+        0x1f, 0x0d, 0x40, 0x4f, 0xb5, 0x84, 0xd5, 0xa5, 0x97, 0x62, 0xea, 0x30, 0x25, 0xf5, 0x26,
+        0x39, 0x75, 0xc0, 0x63, 0x49, 0x0b, 0xd7, 0xd7, 0xf6, 0xca, 0xa5, 0xc8, 0x53, 0xad, 0x5f,
+        0x3f, 0x96, 0x5d, 0xc7, 0x6c, 0x71, 0x16, 0xe1, 0xd5, 0x1c, 0x47, 0xcc, 0x95, 0xcb, 0xbc,
+        0x5a, 0xc3, 0xe8, 0x36, 0x84, 0x7f, 0xfa, 0xdc, 0x0e, 0x11, 0x14, 0x8d, 0xda, 0x48, 0x53,
+        0xed, 0xf3, 0xff, 0xce, 0xd5, 0x1c, 0x6f, 0x57, 0x17, 0xdd, 0x1c, 0x3f, 0x2f, 0x21, 0x83,
+        0xd4, 0x47, 0x39, 0x05, 0x28, 0x81, 0x3e, 0x61, 0x83, 0x44, 0x87, 0x71, 0xca, 0x08, 0xe7,
+        0x06, 0xf0, 0xfc, 0x47, 0xd3, 0xf2, 0x7b, 0x11, 0x5b, 0x41, 0x91, 0x95, 0x26, 0xec, 0xe6,
+        0x01, 0x21, 0x92, 0x84, 0x87, 0xa2, 0x35, 0xc1, 0x49, 0x59, 0x46, 0x88, 0x7c, 0x6d, 0x27,
+        0xf6, 0xf9, 0x29, 0x51, 0x47, 0x81, 0x3d, 0x6e, 0x2a, 0x66, 0xa1, 0xaa, 0x38, 0x51, 0xcd,
+        0x89, 0x4b, 0xf1, 0x85, 0xd4, 0xab, 0xed, 0x3f, 0x32, 0x86, 0xc6, 0x0d, 0xcc, 0x20, 0x43,
+        0x4a, 0x9a, 0xce, 0x0a, 0x0e, 0xe6, 0xe5, 0x16, 0xae, 0x0f, 0xa0, 0xf9, 0xd9, 0xb0, 0x46,
+        0x6f, 0x0f, 0x14, 0x05, 0xa8, 0x91, 0x05, 0xd7, 0x1e, 0x39, 0x30, 0x16, 0x57, 0x3b, 0x33,
+        0xbe, 0x30, 0x26, 0xc6, 0x42, 0xe6, 0x48, 0xe2, 0xc8, 0xe5, 0xe5, 0x33, 0x89, 0xc2, 0xde,
+        0x2a, 0xf9, 0xa9, 0x60, 0x0c, 0xdb, 0xf5, 0x22, 0xc3, 0x42, 0xe1, 0xe7, 0x78, 0x44, 0xa7,
+        0x0c, 0x88, 0xff, 0x80, 0xf7, 0x49, 0x8b, 0x80, 0x42, 0x2c, 0xb7, 0x9b, 0x66, 0x85, 0xa5,
+        0xbe, 0xc3, 0xf5, 0xc5, 0x7d, 0x13, 0xdd, 0xe8, 0xc9, 0x8d, 0x08, 0xf5, 0xbf, 0x45, 0xa3,
+        0x8b, 0x11, 0x35, 0x0e, 0xfe, 0x4f, 0x77, 0xab, 0xc8, 0x3c, 0xba, 0x67, 0x06, 0x9d, 0xab,
+        0x54, 0x5e, 0xa6, 0xa2, 0x41, 0x67, 0x57, 0x1c, 0x9d, 0x1f, 0x3a, 0xe3, 0xcc, 0xd0, 0xef,
+        0x19, 0x89, 0x1a, 0xd0, 0xe0, 0x02, 0x90, 0x03, 0xb6, 0x57, 0x33, 0x9c, 0xfb, 0xe9, 0x3d,
+        0x70, 0xb5, 0xb7, 0xf1, 0x6e, 0x19, 0x51, 0x54, 0x4b, 0x02, 0xb5, 0xad, 0x75, 0x44, 0x13,
+        0x26, 0xa0, 0x49, 0x3c, 0xdf, 0x70, 0xb3, 0xa5, 0x8b, 0xc1, 0xfe, 0x61, 0x0c, 0xee, 0xaa,
+        0xc2, 0xc4, 0x30, 0xa1, 0xe6, 0x6a, 0xad, 0xcc, 0x43, 0xf6, 0x0f, 0x57, 0xe5, 0x89, 0xaf,
+        0xbc, 0x4f, 0xa2, 0xc2, 0x23, 0x10, 0x84, 0x0d, 0x97, 0x04, 0x41, 0x01, 0xb9, 0x48, 0xcc,
+        0x97, 0x0a, 0x09, 0x6d, 0x3b, 0x6e, 0x76, 0x9a, 0xfe, 0x15, 0xfa, 0x9c, 0xba, 0x10, 0xcb,
+        0x72, 0xab, 0x5d, 0xed, 0x94, 0xfa, 0x55, 0x5c, 0xfa, 0xe5, 0x34, 0x15, 0xc9, 0xd8, 0x81,
+        0xf7, 0xa5, 0x9b, 0x67, 0x0d, 0xfa, 0x3d, 0x45, 0xb7, 0xf3, 0x2c, 0x41, 0x92, 0x6d, 0xa3,
+        0x49, 0x8d, 0xde, 0x64, 0x9a, 0x3b, 0x15, 0x67, 0x37, 0xc8, 0x53, 0x43, 0xc8, 0x53, 0xa6,
+        0x7e, 0x27, 0x20, 0x74, 0xeb, 0xfa, 0x6f, 0x1c, 0xd1, 0x9d, 0xee, 0x1d, 0xcc, 0xb3, 0x19,
+        0xbf, 0x62, 0xbe, 0x8f, 0x4f, 0x10, 0xb2, 0x6f, 0x0a, 0xde, 0xe1, 0x2d, 0xfa, 0xf7, 0x7c,
+        0x40, 0xe0, 0x41, 0xeb, 0xaf, 0x0b, 0x3c, 0xd1, 0xdb, 0x4b, 0xd5, 0xb8, 0x72, 0x40, 0x15,
+        0xfb, 0x20, 0xe0, 0xee, 0x2d, 0x01, 0xc0, 0x4f, 0x74, 0xeb, 0x0e, 0x48, 0x02, 0x97, 0xb5,
+        0x1d, 0x24, 0x12, 0x24, 0x56, 0x51, 0xb7, 0x57,
+    ];
+
+    struct ModExpClient {
+        done: Cell<bool>,
+        result: TakeCell<'static, [u8]>,
+    }
+
+    impl<'a> Client<'a> for ModExpClient {
+        fn mod_exponent_done(
+            &'a self,
+            status: Result<bool, ErrorCode>,
+            _message: &'static mut [u8],
+            _modulus: &'static [u8],
+            _exponent: &'static [u8],
+            result: &'static mut [u8],
+        ) {
+            assert_eq!(status, Ok(true));
+            self.result.replace(result);
+            self.done.set(true);
+        }
+    }
+
+    struct CheckClient {
+        result: Cell<Option<Result<CheckResult, ErrorCode>>>,
+    }
+
+    impl kernel::process_checker::Client<'static> for CheckClient {
+        fn check_done(
+            &self,
+            result: Result<CheckResult, ErrorCode>,
+            _credentials: TbfFooterV2Credentials,
+            _binary: &'static [u8],
+        ) {
+            self.result.set(Some(result));
+        }
+    }
+
+    fn leak<T>(value: T) -> &'static mut T {
+        std::boxed::Box::leak(std::boxed::Box::new(value))
+    }
+
+    /// Run deferred calls until none are pending.
+    fn run_deferred_calls() {
+        while DeferredCall::has_tasks() {
+            DeferredCall::service_next_pending();
+        }
+    }
+
+    #[test]
+    fn test_mod_exponent() {
+        let _lock = DEFERRED_CALLS.lock();
+
+        let rsa = leak(RsaMathSoftware::new());
+        rsa.register();
+        let client = leak(ModExpClient {
+            done: Cell::new(false),
+            result: TakeCell::empty(),
+        });
+        rsa.set_client(client);
+
+        let message = leak(MODEXP_MESSAGE);
+        let result = leak([0; 128]);
+        assert!(rsa
+            .mod_exponent(message, &MODEXP_MODULUS, &MODEXP_EXPONENT, result)
+            .is_ok());
+        run_deferred_calls();
+
+        assert!(client.done.get());
+        client
+            .result
+            .map(|result| assert_eq!(result[..], MODEXP_RESULT[..]));
+
+        // A message that is not smaller than the modulus is rejected.
+        let message = leak(MODEXP_MODULUS);
+        let result = client.result.take().unwrap();
+        assert!(matches!(
+            rsa.mod_exponent(message, &MODEXP_MODULUS, &MODEXP_EXPONENT, result),
+            Err((ErrorCode::INVAL, _, _, _, _))
+        ));
+    }
+
+    #[test]
+    fn test_app_checker_rsa() {
+        let _lock = DEFERRED_CALLS.lock();
+
+        static KEYS: [RsaPublicKey; 2] = [
+            RsaPublicKey {
+                modulus: &RSA3072_MODULUS,
+                exponent: &PUBLIC_EXPONENT,
+            },
+            RsaPublicKey {
+                modulus: &RSA4096_MODULUS,
+                exponent: &PUBLIC_EXPONENT,
+            },
+        ];
+
+        let sha = leak(Sha256Software::new());
+        sha.register();
+        let rsa = leak(RsaMathSoftware::new());
+        rsa.register();
+        let client = leak(CheckClient {
+            result: Cell::new(None),
+        });
+
+        let pkcs1v15 = leak(AppCheckerRsa::new(
+            sha,
+            rsa,
+            RsaPadding::Pkcs1v15,
+            &KEYS,
+            leak([0; 32]),
+            leak([0; 512]),
+            leak([0; 512]),
+        ));
+        pkcs1v15.set_client(client);
+        let pss = leak(AppCheckerRsa::new(
+            sha,
+            rsa,
+            RsaPadding::Pss,
+            &KEYS,
+            leak([0; 32]),
+            leak([0; 512]),
+            leak([0; 512]),
+        ));
+        pss.set_client(client);
+
+        let check = |checker: &'static AppCheckerRsa<_, _, 32>,
+                     credentials: &'static [u8],
+                     binary: &'static [u8]| {
+            DigestDataHash::set_client(sha, checker);
+            rsa.set_client(checker);
+            let credentials = TbfFooterV2Credentials::try_from(credentials).unwrap();
+            assert!(checker.check_credentials(credentials, binary).is_ok());
+            run_deferred_calls();
+            client.result.take()
+        };
+
+        assert!(matches!(
+            check(pkcs1v15, &RSA3072_PKCS1V15_CREDENTIALS, BINARY),
+            Some(Ok(CheckResult::Accept))
+        ));
+        assert!(matches!(
+            check(pkcs1v15, &RSA4096_PKCS1V15_CREDENTIALS, BINARY),
+            Some(Ok(CheckResult::Accept))
+        ));
+        assert!(matches!(
+            check(pss, &RSA3072_PSS_CREDENTIALS, BINARY),
+            Some(Ok(CheckResult::Accept))
+        ));
+
+        // Modified binaries and signatures in the wrong scheme are rejected.
+        assert!(matches!(
+            check(pkcs1v15, &RSA3072_PKCS1V15_CREDENTIALS, TAMPERED_BINARY),
+            Some(Ok(CheckResult::Reject))
+        ));
+        assert!(matches!(
+            check(pss, &RSA3072_PSS_CREDENTIALS, TAMPERED_BINARY),
+            Some(Ok(CheckResult::Reject))
+        ));
+        assert!(matches!(
+            check(pkcs1v15, &RSA3072_PSS_CREDENTIALS, BINARY),
+            Some(Ok(CheckResult::Reject))
+        ));
+        assert!(matches!(
+            check(pss, &RSA3072_PKCS1V15_CREDENTIALS, BINARY),
+            Some(Ok(CheckResult::Reject))
+        ));
+
+        // Keys that are not trusted are not supported.
+        static UNTRUSTED: [RsaPublicKey; 1] = [RsaPublicKey {
+            modulus: &RSA4096_MODULUS,
+            exponent: &PUBLIC_EXPONENT,
+        }];
+        let untrusted = leak(AppCheckerRsa::new(
+            sha,
+            rsa,
+            RsaPadding::Pkcs1v15,
+            &UNTRUSTED,
+            leak([0; 32]),
+            leak([0; 512]),
+            leak([0; 512]),
+        ));
+        let credentials = TbfFooterV2Credentials::try_from(&RSA3072_PKCS1V15_CREDENTIALS[..]);
+        assert!(matches!(
+            untrusted.check_credentials(credentials.unwrap(), BINARY),
+            Err((ErrorCode::NOSUPPORT, _, _))
+        ));
+    }
+}
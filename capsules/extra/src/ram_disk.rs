@@ -0,0 +1,263 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Block storage in RAM.
+//!
+//! Provides `BlockStorage` on top of a buffer in memory. Operations complete
+//! in a deferred call. The contents are lost on reset, so this is useful for
+//! temporary files and for testing users of `BlockStorage`, including on the
+//! host.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let ram_disk_storage = static_init!([u8; 32 * 512], [0; 32 * 512]);
+//! let ram_disk = static_init!(
+//!     capsules_extra::ram_disk::RamDisk<'static>,
+//!     capsules_extra::ram_disk::RamDisk::new(ram_disk_storage, 512)
+//! );
+//! ram_disk.register();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::block_storage::{BlockStorage, BlockStorageClient, Geometry};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// The operation waiting for its completion callback.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operation {
+    Read,
+    Write,
+    Erase,
+}
+
+pub struct RamDisk<'a> {
+    storage: TakeCell<'static, [u8]>,
+    geometry: Geometry,
+    client: OptionalCell<&'a dyn BlockStorageClient>,
+    /// The buffer of the pending read or write.
+    buffer: TakeCell<'static, [u8]>,
+    operation: Cell<Option<Operation>>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> RamDisk<'a> {
+    /// Create a RAM disk with blocks of `block_size` bytes. Any part of
+    /// `storage` after the last whole block is unused.
+    pub fn new(storage: &'static mut [u8], block_size: usize) -> RamDisk<'a> {
+        let geometry = Geometry {
+            block_size,
+            block_count: storage.len().checked_div(block_size).unwrap_or(0),
+        };
+        RamDisk {
+            storage: TakeCell::new(storage),
+            geometry,
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            operation: Cell::new(None),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// The byte range of `count` blocks starting at `block`, if they are on
+    /// the disk and no other operation is pending.
+    fn range(&self, block: usize, count: usize) -> Result<(usize, usize), ErrorCode> {
+        if self.operation.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if count == 0 || !self.geometry.contains(block, count) {
+            return Err(ErrorCode::INVAL);
+        }
+        let start = block * self.geometry.block_size;
+        Ok((start, start + count * self.geometry.block_size))
+    }
+
+    fn start(&self, operation: Operation) {
+        self.operation.set(Some(operation));
+        self.deferred_call.set();
+    }
+}
+
+impl<'a> BlockStorage<'a> for RamDisk<'a> {
+    fn set_client(&self, client: &'a dyn BlockStorageClient) {
+        self.client.set(client);
+    }
+
+    fn geometry(&self) -> Result<Geometry, ErrorCode> {
+        Ok(self.geometry)
+    }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        block: usize,
+        count: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let (start, end) = match self.range(block, count) {
+            Ok(range) => range,
+            Err(e) => return Err((e, buffer)),
+        };
+        if buffer.len() < end - start {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        self.storage
+            .map(|storage| buffer[..end - start].copy_from_slice(&storage[start..end]));
+        self.buffer.replace(buffer);
+        self.start(Operation::Read);
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        block: usize,
+        count: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let (start, end) = match self.range(block, count) {
+            Ok(range) => range,
+            Err(e) => return Err((e, buffer)),
+        };
+        if buffer.len() < end - start {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        self.storage
+            .map(|storage| storage[start..end].copy_from_slice(&buffer[..end - start]));
+        self.buffer.replace(buffer);
+        self.start(Operation::Write);
+        Ok(())
+    }
+
+    fn erase(&self, block: usize, count: usize) -> Result<(), ErrorCode> {
+        let (start, end) = self.range(block, count)?;
+        self.storage.map(|storage| storage[start..end].fill(0xFF));
+        self.start(Operation::Erase);
+        Ok(())
+    }
+}
+
+impl<'a> DeferredCallClient for RamDisk<'a> {
+    fn handle_deferred_call(&self) {
+        match self.operation.take() {
+            Some(Operation::Read) => {
+                self.buffer.take().map(|buffer| {
+                    self.client
+                        .map(move |client| client.read_complete(buffer, Ok(())));
+                });
+            }
+            Some(Operation::Write) => {
+                self.buffer.take().map(|buffer| {
+                    self.client
+                        .map(move |client| client.write_complete(buffer, Ok(())));
+                });
+            }
+            Some(Operation::Erase) => {
+                self.client.map(|client| client.erase_complete(Ok(())));
+            }
+            None => {}
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::boxed::Box;
+    use std::vec;
+
+    struct Client {
+        buffer: TakeCell<'static, [u8]>,
+        erased: Cell<bool>,
+    }
+
+    impl Client {
+        fn new() -> Client {
+            Client {
+                buffer: TakeCell::empty(),
+                erased: Cell::new(false),
+            }
+        }
+    }
+
+    impl BlockStorageClient for Client {
+        fn read_complete(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+            assert_eq!(result, Ok(()));
+            self.buffer.replace(buffer);
+        }
+
+        fn write_complete(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+            assert_eq!(result, Ok(()));
+            self.buffer.replace(buffer);
+        }
+
+        fn erase_complete(&self, result: Result<(), ErrorCode>) {
+            assert_eq!(result, Ok(()));
+            self.erased.set(true);
+        }
+    }
+
+    fn leak(len: usize) -> &'static mut [u8] {
+        Box::leak(vec![0; len].into_boxed_slice())
+    }
+
+    #[test]
+    fn read_write_erase() {
+        let client = Client::new();
+        let disk = RamDisk::new(leak(4 * 64 + 10), 64);
+        disk.set_client(&client);
+        assert_eq!(
+            disk.geometry(),
+            Ok(Geometry {
+                block_size: 64,
+                block_count: 4
+            })
+        );
+
+        let buffer = leak(128);
+        buffer.fill(0xA5);
+        disk.write(buffer, 2, 2).unwrap();
+        assert!(client.buffer.is_none());
+        disk.handle_deferred_call();
+
+        let buffer = client.buffer.take().unwrap();
+        disk.read(buffer, 1, 2).unwrap();
+        disk.handle_deferred_call();
+        let buffer = client.buffer.take().unwrap();
+        assert!(buffer[..64].iter().all(|&b| b == 0));
+        assert!(buffer[64..].iter().all(|&b| b == 0xA5));
+
+        disk.erase(3, 1).unwrap();
+        assert_eq!(disk.erase(0, 1), Err(ErrorCode::BUSY));
+        disk.handle_deferred_call();
+        assert!(client.erased.get());
+        disk.read(buffer, 3, 1).unwrap();
+        disk.handle_deferred_call();
+        assert!(client.buffer.take().unwrap()[..64]
+            .iter()
+            .all(|&b| b == 0xFF));
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let disk = RamDisk::new(leak(4 * 64), 64);
+        let (e, buffer) = disk.read(leak(64), 4, 1).unwrap_err();
+        assert_eq!(e, ErrorCode::INVAL);
+        let (e, buffer) = disk.write(buffer, 3, 0).unwrap_err();
+        assert_eq!(e, ErrorCode::INVAL);
+        let (e, _) = disk.read(buffer, 0, 2).unwrap_err();
+        assert_eq!(e, ErrorCode::SIZE);
+        assert_eq!(disk.erase(2, 3), Err(ErrorCode::INVAL));
+    }
+}
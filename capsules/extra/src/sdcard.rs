@@ -131,6 +131,10 @@ pub struct SDCard<'a, A: hil::time::Alarm<'a>> {
     client: OptionalCell<&'a dyn SDCardClient>,
     client_buffer: TakeCell<'static, [u8]>,
     client_offset: Cell<usize>,
+
+    block_client: OptionalCell<&'a dyn hil::block_storage::BlockStorageClient>,
+    block_op: Cell<BlockOp>,
+    block_count: Cell<usize>,
 }
 
 /// SD card command codes
@@ -219,6 +223,20 @@ enum SdCardError {
     TimeoutFailure = -10005,
 }
 
+/// Operation started through the `BlockStorage` interface
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BlockOp {
+    None,
+    Read,
+    /// Writing block `sector`, with `remaining` blocks left including it.
+    /// The card only supports single block writes, so multiple blocks are
+    /// written one at a time.
+    Write {
+        sector: u32,
+        remaining: usize,
+    },
+}
+
 /// SD card types, determined during initialization
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum SDCardType {
@@ -292,6 +310,9 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
             client: OptionalCell::empty(),
             client_buffer: TakeCell::empty(),
             client_offset: Cell::new(0),
+            block_client: OptionalCell::empty(),
+            block_op: Cell::new(BlockOp::None),
+            block_count: Cell::new(0),
         }
     }
 
@@ -507,9 +528,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -577,9 +596,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -606,9 +623,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -673,9 +688,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -711,9 +724,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -734,9 +745,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -784,6 +793,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     // initialization complete
                     self.state.set(SpiState::Idle);
                     self.is_initialized.set(true);
+                    self.block_count.set((total_size / 512) as usize);
 
                     // perform callback
                     self.client.map(move |client| {
@@ -796,9 +806,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -823,9 +831,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
-                    });
+                    self.report_error(SdCardError::ReadFailure);
                 }
             }
 
@@ -853,37 +859,38 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
-                    });
+                    self.report_error(SdCardError::ReadFailure);
                 }
             }
 
             SpiState::ReadBlockComplete => {
+                // read finished
+                self.state.set(SpiState::Idle);
+                let client_buffer = self.client_buffer.take();
+
+                // copy data to user buffer before giving back the SPI
+                // buffers, so the client can start another read in the
+                // callback
+                let completed = client_buffer.map(|buffer| {
+                    // Limit to minimum length between buffer, read_buffer,
+                    // and 512 (block size)
+                    for (client_byte, &read_byte) in
+                        buffer.iter_mut().zip(read_buffer.iter()).take(512)
+                    {
+                        *client_byte = read_byte;
+                    }
+                    let read_len = cmp::min(read_buffer.len(), cmp::min(buffer.len(), 512));
+                    (buffer, read_len)
+                });
+
                 // replace buffers
                 self.txbuffer.replace(write_buffer);
                 self.rxbuffer.replace(read_buffer);
 
-                // read finished, perform callback
-                self.state.set(SpiState::Idle);
-                self.rxbuffer.map(|read_buffer| {
-                    self.client_buffer.take().map(move |buffer| {
-                        // copy data to user buffer
-                        // Limit to minimum length between buffer, read_buffer,
-                        // and 512 (block size)
-                        for (client_byte, &read_byte) in
-                            buffer.iter_mut().zip(read_buffer.iter()).take(512)
-                        {
-                            *client_byte = read_byte;
-                        }
-
-                        // callback
-                        let read_len = cmp::min(read_buffer.len(), cmp::min(buffer.len(), 512));
-                        self.client.map(move |client| {
-                            client.read_done(buffer, read_len);
-                        });
-                    });
-                });
+                // perform callback
+                if let Some((buffer, read_len)) = completed {
+                    self.read_complete(buffer, read_len);
+                }
             }
 
             SpiState::WaitReadBlocks { count } => {
@@ -911,9 +918,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
-                    });
+                    self.report_error(SdCardError::ReadFailure);
                 }
             }
 
@@ -962,9 +967,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
 
                     // read finished, perform callback
                     self.client_buffer.take().map(move |buffer| {
-                        self.client.map(move |client| {
-                            client.read_done(buffer, self.client_offset.get());
-                        });
+                        self.read_complete(buffer, self.client_offset.get());
                     });
                 } else {
                     // error, send callback and quit
@@ -973,9 +976,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
-                    });
+                    self.report_error(SdCardError::ReadFailure);
                 }
             }
 
@@ -985,18 +986,25 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
 
                 if r1 == SUCCESS_STATUS {
                     if count <= 1 {
+                        let offset = self.client_offset.get();
                         let bytes_written = self.client_buffer.map_or(0, |buffer| {
                             // copy over data from client buffer
                             // Limit to minimum length between write_buffer,
                             // buffer, and 512 (block size)
-                            for (write_byte, &client_byte) in
-                                write_buffer.iter_mut().skip(1).zip(buffer.iter()).take(512)
+                            for (write_byte, &client_byte) in write_buffer
+                                .iter_mut()
+                                .skip(1)
+                                .zip(buffer.iter().skip(offset))
+                                .take(512)
                             {
                                 *write_byte = client_byte;
                             }
 
                             // calculate number of bytes written
-                            cmp::min(write_buffer.len(), cmp::min(buffer.len(), 512))
+                            cmp::min(
+                                write_buffer.len(),
+                                cmp::min(buffer.len().saturating_sub(offset), 512),
+                            )
                         });
 
                         // set a known value for remaining bytes
@@ -1026,9 +1034,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                         self.state.set(SpiState::Idle);
                         self.alarm_state.set(AlarmState::Idle);
                         self.alarm_count.set(0);
-                        self.client.map(move |client| {
-                            client.error(SdCardError::WriteFailure as u32);
-                        });
+                        self.report_error(SdCardError::WriteFailure);
                     }
                 } else {
                     // error, send callback and quit
@@ -1037,9 +1043,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::WriteFailure as u32);
-                    });
+                    self.report_error(SdCardError::WriteFailure);
                 }
             }
 
@@ -1061,9 +1065,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::WriteFailure as u32);
-                    });
+                    self.report_error(SdCardError::WriteFailure);
                 }
             }
 
@@ -1078,9 +1080,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_count.set(0);
                     self.client_buffer.take().map(move |buffer| {
-                        self.client.map(move |client| {
-                            client.write_done(buffer);
-                        });
+                        self.write_complete(buffer);
                     });
                 } else {
                     // replace buffers
@@ -1113,9 +1113,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
             self.state.set(SpiState::Idle);
             self.alarm_state.set(AlarmState::Idle);
             self.alarm_count.set(0);
-            self.client.map(move |client| {
-                client.error(SdCardError::TimeoutFailure as u32);
-            });
+            self.report_error(SdCardError::TimeoutFailure);
         } else {
             self.alarm_count.set(repeats + 1);
         }
@@ -1238,6 +1236,113 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         }
     }
 
+    /// Pass a completed read to the client that started it.
+    fn read_complete(&self, buffer: &'static mut [u8], len: usize) {
+        match self.block_op.replace(BlockOp::None) {
+            BlockOp::None => self.client.map(move |client| {
+                client.read_done(buffer, len);
+            }),
+            _ => self.block_client.map(move |client| {
+                client.read_complete(buffer, Ok(()));
+            }),
+        };
+    }
+
+    /// Pass a completed write to the client that started it, or write the
+    /// next block of a `BlockStorage` write.
+    fn write_complete(&self, buffer: &'static mut [u8]) {
+        match self.block_op.get() {
+            BlockOp::None => {
+                self.client.map(move |client| {
+                    client.write_done(buffer);
+                });
+            }
+            BlockOp::Write { sector, remaining } if remaining > 1 => {
+                self.block_op.set(BlockOp::Write {
+                    sector: sector + 1,
+                    remaining: remaining - 1,
+                });
+                self.start_write_block(buffer, sector + 1, self.client_offset.get() + 512);
+            }
+            _ => {
+                self.block_op.set(BlockOp::None);
+                self.block_client.map(move |client| {
+                    client.write_complete(buffer, Ok(()));
+                });
+            }
+        }
+    }
+
+    /// Report a failed transaction to the client that started it.
+    fn report_error(&self, error: SdCardError) {
+        match self.block_op.replace(BlockOp::None) {
+            BlockOp::None => {
+                self.client.map(move |client| {
+                    client.error(error as u32);
+                });
+            }
+            BlockOp::Read => {
+                self.client_buffer.take().map(|buffer| {
+                    self.block_client.map(move |client| {
+                        client.read_complete(buffer, Err(ErrorCode::FAIL));
+                    })
+                });
+            }
+            BlockOp::Write { .. } => {
+                self.client_buffer.take().map(|buffer| {
+                    self.block_client.map(move |client| {
+                        client.write_complete(buffer, Err(ErrorCode::FAIL));
+                    })
+                });
+            }
+        }
+    }
+
+    /// Returns `true` if no transaction is in progress.
+    fn is_idle(&self) -> bool {
+        self.state.get() == SpiState::Idle
+            && self.alarm_state.get() == AlarmState::Idle
+            && self.block_op.get() == BlockOp::None
+            && self.txbuffer.is_some()
+            && self.rxbuffer.is_some()
+    }
+
+    /// Check that a `BlockStorage` transfer of `count` blocks starting at
+    /// `block` using `buffer` can start now.
+    fn check_block_op(&self, buffer: &[u8], block: usize, count: usize) -> Result<(), ErrorCode> {
+        let geometry = hil::block_storage::BlockStorage::geometry(self)?;
+        if !self.is_idle() {
+            Err(ErrorCode::BUSY)
+        } else if count == 0 || !geometry.contains(block, count) || u32::try_from(block).is_err() {
+            Err(ErrorCode::INVAL)
+        } else if geometry.bytes(count).map_or(true, |len| buffer.len() < len) {
+            Err(ErrorCode::SIZE)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Write the block at `offset` in `buffer` to `sector`.
+    fn start_write_block(&self, buffer: &'static mut [u8], sector: u32, offset: usize) {
+        self.txbuffer.take().map(|txbuffer| {
+            self.rxbuffer.take().map(move |rxbuffer| {
+                // save the user buffer for later
+                self.client_buffer.replace(buffer);
+                self.client_offset.set(offset);
+
+                // convert block address to byte address for non-block
+                //  access cards
+                let mut address = sector;
+                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
+                    address *= 512;
+                }
+
+                self.state.set(SpiState::StartWriteBlocks { count: 1 });
+                self.send_command(SDCmd::CMD24_WriteSingle, address, txbuffer, rxbuffer, 10);
+            })
+        });
+    }
+
     pub fn set_client<C: SDCardClient>(&self, client: &'static C) {
         self.client.set(client);
     }
@@ -1254,6 +1359,12 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         self.is_initialized.get()
     }
 
+    /// Returns the buffer passed to `read_blocks` or `write_blocks` after
+    /// the transfer failed and the client received an `error` callback.
+    pub fn take_failed_buffer(&self) -> Option<&'static mut [u8]> {
+        self.client_buffer.take()
+    }
+
     /// watches SD card detect pin for changes, sends callback on change
     pub fn detect_changes(&self) {
         self.detect_pin.get().map(|pin| {
@@ -1355,41 +1466,17 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         // only if initialized and installed
         if self.is_installed() {
             if self.is_initialized() {
-                self.txbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                        self.rxbuffer
-                            .take()
-                            .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                                // save the user buffer for later
-                                self.client_buffer.replace(buffer);
-                                self.client_offset.set(0);
-
-                                // convert block address to byte address for non-block
-                                //  access cards
-                                let mut address = sector;
-                                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
-                                    address *= 512;
-                                }
-
-                                self.state.set(SpiState::StartWriteBlocks { count: count });
-                                if count == 1 {
-                                    self.send_command(
-                                        SDCmd::CMD24_WriteSingle,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
+                if count != 1 {
+                    // can't write multiple blocks yet
+                    Err(ErrorCode::NOSUPPORT)
+                } else if self.txbuffer.is_none() || self.rxbuffer.is_none() {
+                    Err(ErrorCode::NOMEM)
+                } else {
+                    self.start_write_block(buffer, sector, 0);
 
-                                    // command started successfully
-                                    Ok(())
-                                } else {
-                                    // can't write multiple blocks yet
-                                    Err(ErrorCode::NOSUPPORT)
-                                }
-                            })
-                    })
+                    // command started successfully
+                    Ok(())
+                }
             } else {
                 // sd card not initialized
                 Err(ErrorCode::RESERVE)
@@ -1402,6 +1489,64 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
 }
 
 /// Handle callbacks from the SPI peripheral
+/// Block storage on the SD card. The card must be initialized with
+/// `initialize()` first; until then `geometry()` returns `RESERVE`. Erasing
+/// is not supported, blocks are overwritten in place.
+impl<'a, A: hil::time::Alarm<'a>> hil::block_storage::BlockStorage<'a> for SDCard<'a, A> {
+    fn set_client(&self, client: &'a dyn hil::block_storage::BlockStorageClient) {
+        self.block_client.set(client);
+    }
+
+    fn geometry(&self) -> Result<hil::block_storage::Geometry, ErrorCode> {
+        if !self.is_installed() {
+            Err(ErrorCode::UNINSTALLED)
+        } else if !self.is_initialized() {
+            Err(ErrorCode::RESERVE)
+        } else {
+            Ok(hil::block_storage::Geometry {
+                block_size: 512,
+                block_count: self.block_count.get(),
+            })
+        }
+    }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        block: usize,
+        count: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check_block_op(buffer, block, count) {
+            return Err((e, buffer));
+        }
+        self.block_op.set(BlockOp::Read);
+        // The checks above ensure this starts.
+        let _ = self.read_blocks(buffer, block as u32, count as u32);
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        block: usize,
+        count: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check_block_op(buffer, block, count) {
+            return Err((e, buffer));
+        }
+        self.block_op.set(BlockOp::Write {
+            sector: block as u32,
+            remaining: count,
+        });
+        self.start_write_block(buffer, block as u32, 0);
+        Ok(())
+    }
+
+    fn erase(&self, _block: usize, _count: usize) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
+
 impl<'a, A: hil::time::Alarm<'a>> hil::spi::SpiMasterClient for SDCard<'a, A> {
     fn read_write_done(
         &self,
@@ -1433,9 +1578,7 @@ impl<'a, A: hil::time::Alarm<'a>> hil::gpio::Client for SDCard<'a, A> {
             //  send an error callback
             self.state.set(SpiState::Idle);
             self.alarm_state.set(AlarmState::Idle);
-            self.client.map(move |client| {
-                client.error(SdCardError::CardStateChanged as u32);
-            });
+            self.report_error(SdCardError::CardStateChanged);
         }
 
         // either the card is new or gone, in either case it isn't initialized
@@ -52,6 +52,8 @@ pub struct Sha256Software<'a> {
     state: Cell<State>,
 
     client: OptionalCell<&'a dyn Client<SHA_256_OUTPUT_LEN_BYTES>>,
+    // Client set through `DigestDataHash`, used when `client` is not set.
+    data_hash_client: OptionalCell<&'a dyn ClientDataHash<SHA_256_OUTPUT_LEN_BYTES>>,
     input_data: OptionalCell<SubSliceMutImmut<'static, u8>>,
     data_buffer: MapCell<[u8; SHA_BLOCK_LEN_BYTES]>,
     buffered_length: Cell<usize>,
@@ -69,6 +71,7 @@ impl<'a> Sha256Software<'a> {
         let s = Self {
             state: Cell::new(State::Idle),
             client: OptionalCell::empty(),
+            data_hash_client: OptionalCell::empty(),
             input_data: OptionalCell::empty(),
             data_buffer: MapCell::new([0; SHA_BLOCK_LEN_BYTES]),
             buffered_length: Cell::new(0),
@@ -90,6 +93,37 @@ impl<'a> Sha256Software<'a> {
         }
     }
 
+    // Return `data` to whichever client was set.
+    fn data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMutImmut<'static, u8>) {
+        match data {
+            SubSliceMutImmut::Mutable(buffer) => match self.client.get() {
+                Some(client) => client.add_mut_data_done(result, buffer),
+                None => {
+                    self.data_hash_client
+                        .map(|client| client.add_mut_data_done(result, buffer));
+                }
+            },
+            SubSliceMutImmut::Immutable(buffer) => match self.client.get() {
+                Some(client) => client.add_data_done(result, buffer),
+                None => {
+                    self.data_hash_client
+                        .map(|client| client.add_data_done(result, buffer));
+                }
+            },
+        }
+    }
+
+    // Return `digest` to whichever client was set.
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 32]) {
+        match self.client.get() {
+            Some(client) => client.hash_done(result, digest),
+            None => {
+                self.data_hash_client
+                    .map(|client| client.hash_done(result, digest));
+            }
+        }
+    }
+
     fn initialize(&self) {
         let new_state = match self.state.get() {
             State::Idle => State::Idle,
@@ -260,7 +294,10 @@ impl<'a> Sha256Software<'a> {
             let mut s1 = self.right_rotate(message_schedule[i - 2], 17);
             s1 ^= self.right_rotate(message_schedule[i - 2], 19);
             s1 ^= message_schedule[i - 2] >> 10;
-            message_schedule[i] = message_schedule[i - 16] + s0 + message_schedule[i - 7] + s1;
+            message_schedule[i] = message_schedule[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(message_schedule[i - 7])
+                .wrapping_add(s1);
         }
 
         // Compression
@@ -271,12 +308,16 @@ impl<'a> Sha256Software<'a> {
                 ^ self.right_rotate(hashes[4], 25);
             let ch = (hashes[4] & hashes[5]) ^ ((!hashes[4]) & hashes[6]);
             let constant = ROUND_CONSTANTS[i];
-            let temp1 = hashes[7] + s1 + ch + constant + message_schedule[i];
+            let temp1 = hashes[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(constant)
+                .wrapping_add(message_schedule[i]);
             let s0 = self.right_rotate(hashes[0], 2)
                 ^ self.right_rotate(hashes[0], 13)
                 ^ self.right_rotate(hashes[0], 22);
             let maj = (hashes[0] & hashes[1]) ^ (hashes[0] & hashes[2]) ^ (hashes[1] & hashes[2]);
-            let temp2 = s0 + maj;
+            let temp2 = s0.wrapping_add(maj);
 
             hashes[7] = hashes[6];
             hashes[6] = hashes[5];
@@ -387,6 +428,7 @@ impl<'a> DigestVerify<'a, 32> for Sha256Software<'a> {
 
 impl<'a> Digest<'a, 32> for Sha256Software<'a> {
     fn set_client(&'a self, client: &'a dyn Client<32>) {
+        self.data_hash_client.clear();
         self.client.set(client);
     }
 }
@@ -423,44 +465,20 @@ impl<'a> DeferredCallClient for Sha256Software<'a> {
                 // Data already computed in method call
                 let data = self.input_data.take().unwrap();
                 self.state.set(State::Idle);
-                match data {
-                    SubSliceMutImmut::Mutable(buffer) => {
-                        self.client.map(|client| {
-                            client.add_mut_data_done(Ok(()), buffer);
-                        });
-                    }
-                    SubSliceMutImmut::Immutable(buffer) => {
-                        self.client.map(|client| {
-                            client.add_data_done(Ok(()), buffer);
-                        });
-                    }
-                }
+                self.data_done(Ok(()), data);
             }
             State::Hash => {
                 // Hash already copied in method call.
                 let output = self.output_data.replace(None).unwrap();
                 self.state.set(State::Idle);
                 self.clear_data();
-                self.client.map(|c| {
-                    c.hash_done(Ok(()), output);
-                });
+                self.hash_done(Ok(()), output);
             }
             State::CancelData => {
                 self.state.set(State::Idle);
                 self.clear_data();
                 let data = self.input_data.take().unwrap();
-                match data {
-                    SubSliceMutImmut::Mutable(buffer) => {
-                        self.client.map(|client| {
-                            client.add_mut_data_done(Err(ErrorCode::CANCEL), buffer);
-                        });
-                    }
-                    SubSliceMutImmut::Immutable(buffer) => {
-                        self.client.map(|client| {
-                            client.add_data_done(Err(ErrorCode::CANCEL), buffer);
-                        });
-                    }
-                }
+                self.data_done(Err(ErrorCode::CANCEL), data);
             }
             State::CancelVerify => {
                 self.state.set(State::Idle);
@@ -474,9 +492,7 @@ impl<'a> DeferredCallClient for Sha256Software<'a> {
                 self.state.set(State::Idle);
                 self.clear_data();
                 let output = self.output_data.replace(None).unwrap();
-                self.client.map(|client| {
-                    client.hash_done(Err(ErrorCode::CANCEL), output);
-                });
+                self.hash_done(Err(ErrorCode::CANCEL), output);
             }
         }
     }
@@ -494,8 +510,9 @@ impl Sha256 for Sha256Software<'_> {
 }
 
 impl<'a> DigestDataHash<'a, 32> for Sha256Software<'a> {
-    fn set_client(&'a self, _client: &'a dyn ClientDataHash<32>) {
-        unimplemented!()
+    fn set_client(&'a self, client: &'a dyn ClientDataHash<32>) {
+        self.client.clear();
+        self.data_hash_client.set(client);
     }
 }
 
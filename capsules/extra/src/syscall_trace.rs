@@ -0,0 +1,184 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Drains the kernel's binary syscall trace over a UART.
+//!
+//! The kernel's [`SyscallTraceBuffer`] collects compact records of system
+//! calls, return values, context switches and upcalls. This capsule
+//! periodically takes records from that buffer and sends them over any
+//! [`uart::Transmit`] implementation, such as a virtual UART device shared
+//! with the console or [`SeggerRtt`](crate::segger_rtt::SeggerRtt).
+//!
+//! So that the trace can share a channel with other text output, each record
+//! is sent as its own line: the prefix `#T `, the record as hexadecimal, and
+//! a newline. The `tools/decode_syscall_trace.py` script picks these lines
+//! out of a captured log and turns them into a readable timeline.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let records = static_init!(
+//!     [kernel::syscall_trace::TraceRecord; 128],
+//!     [[0; kernel::syscall_trace::RECORD_LEN]; 128]
+//! );
+//! let trace = static_init!(
+//!     kernel::syscall_trace::SyscallTraceBuffer,
+//!     kernel::syscall_trace::SyscallTraceBuffer::new(records)
+//! );
+//! board_kernel.set_syscall_tracer(trace, &process_management_capability);
+//!
+//! let drain = static_init!(
+//!     capsules_extra::syscall_trace::SyscallTraceDrain<'static, UartDevice, VirtualMuxAlarm>,
+//!     capsules_extra::syscall_trace::SyscallTraceDrain::new(
+//!         trace, uart_device, alarm, tx_buffer, 100
+//!     )
+//! );
+//! uart_device.set_transmit_client(drain);
+//! alarm.set_alarm_client(drain);
+//! drain.start();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::hil::uart;
+use kernel::syscall_trace::{SyscallTraceBuffer, RECORD_LEN};
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+/// Prefix of each line carrying a trace record.
+pub const LINE_PREFIX: &[u8] = b"#T ";
+
+/// Length of one encoded line: the prefix, two hex digits per byte, and a
+/// newline.
+pub const LINE_LEN: usize = LINE_PREFIX.len() + 2 * RECORD_LEN + 1;
+
+/// Suggested transmit buffer length, holding eight records.
+pub const DEFAULT_BUF_LEN: usize = 8 * LINE_LEN;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+pub struct SyscallTraceDrain<'a, U: uart::Transmit<'a>, A: time::Alarm<'a>> {
+    trace: &'a SyscallTraceBuffer,
+    uart: &'a U,
+    alarm: &'a A,
+    tx_buffer: TakeCell<'static, [u8]>,
+    /// How often to check the trace buffer for new records.
+    interval_ms: u32,
+    running: Cell<bool>,
+}
+
+impl<'a, U: uart::Transmit<'a>, A: time::Alarm<'a>> SyscallTraceDrain<'a, U, A> {
+    /// `tx_buffer` must be able to hold at least one line of `LINE_LEN`
+    /// bytes.
+    pub fn new(
+        trace: &'a SyscallTraceBuffer,
+        uart: &'a U,
+        alarm: &'a A,
+        tx_buffer: &'static mut [u8],
+        interval_ms: u32,
+    ) -> SyscallTraceDrain<'a, U, A> {
+        SyscallTraceDrain {
+            trace,
+            uart,
+            alarm,
+            tx_buffer: TakeCell::new(tx_buffer),
+            interval_ms,
+            running: Cell::new(false),
+        }
+    }
+
+    /// Start draining the trace buffer.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.running.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.tx_buffer.map_or(0, |buffer| buffer.len()) < LINE_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        self.running.set(true);
+        self.schedule();
+        Ok(())
+    }
+
+    /// Stop draining the trace buffer. Records keep accumulating in the
+    /// kernel until the buffer is full.
+    pub fn stop(&self) {
+        self.running.set(false);
+        let _ = self.alarm.disarm();
+    }
+
+    fn schedule(&self) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.interval_ms));
+    }
+
+    /// Send as many pending records as fit in the transmit buffer. Returns
+    /// `false` if there was nothing to send.
+    fn send(&self) -> bool {
+        if self.trace.is_empty() {
+            return false;
+        }
+        self.tx_buffer.take().map_or(false, |buffer| {
+            let mut len = 0;
+            for line in buffer.chunks_exact_mut(LINE_LEN) {
+                match self.trace.dequeue() {
+                    Some(record) => {
+                        encode_line(&record, line);
+                        len += LINE_LEN;
+                    }
+                    None => break,
+                }
+            }
+            match self.uart.transmit_buffer(buffer, len) {
+                Ok(()) => true,
+                Err((_, buffer)) => {
+                    self.tx_buffer.replace(buffer);
+                    false
+                }
+            }
+        })
+    }
+}
+
+/// Write `record` as a `#T ` line into `line`, which is `LINE_LEN` long.
+fn encode_line(record: &[u8; RECORD_LEN], line: &mut [u8]) {
+    let (prefix, rest) = line.split_at_mut(LINE_PREFIX.len());
+    prefix.copy_from_slice(LINE_PREFIX);
+    for (byte, hex) in record.iter().zip(rest.chunks_exact_mut(2)) {
+        hex[0] = HEX_DIGITS[(byte >> 4) as usize];
+        hex[1] = HEX_DIGITS[(byte & 0xf) as usize];
+    }
+    rest[2 * RECORD_LEN] = b'\n';
+}
+
+impl<'a, U: uart::Transmit<'a>, A: time::Alarm<'a>> time::AlarmClient
+    for SyscallTraceDrain<'a, U, A>
+{
+    fn alarm(&self) {
+        if self.running.get() && !self.send() {
+            self.schedule();
+        }
+    }
+}
+
+impl<'a, U: uart::Transmit<'a>, A: time::Alarm<'a>> uart::TransmitClient
+    for SyscallTraceDrain<'a, U, A>
+{
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        _rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(tx_buffer);
+        // Keep sending while records are pending, then go back to polling.
+        if self.running.get() && !self.send() {
+            self.schedule();
+        }
+    }
+}
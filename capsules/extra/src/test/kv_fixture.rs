@@ -0,0 +1,470 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Simulated devices for testing the KV stack on the host.
+//!
+//! Each device accepts one operation at a time and completes it when pumped,
+//! like a hardware interrupt would. [`run`] pumps a set of devices until none
+//! of them has an operation pending.
+
+extern crate std;
+
+use core::cell::{Cell, RefCell};
+use std::boxed::Box;
+use std::collections::HashMap;
+use std::vec;
+use std::vec::Vec;
+
+use kernel::hil::flash::{self, Flash, HasClient};
+use kernel::hil::hasher::{self, Hasher};
+use kernel::hil::kv::{KVClient, KV};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use kernel::ErrorCode;
+
+/// Move `value` to the heap for the rest of the test, to get the `'static`
+/// references the capsules need.
+pub(crate) fn leak<T>(value: T) -> &'static mut T {
+    Box::leak(Box::new(value))
+}
+
+/// A simulated device.
+pub(crate) trait Pump {
+    /// Complete the pending operation, if there is one. Returns whether there
+    /// was one.
+    fn pump(&self) -> bool;
+}
+
+/// Pump `devices` until none of them has an operation pending.
+pub(crate) fn run(devices: &[&dyn Pump]) {
+    while devices.iter().any(|device| device.pump()) {}
+}
+
+pub(crate) const PAGE_SIZE: usize = 512;
+
+pub(crate) struct Page(pub(crate) [u8; PAGE_SIZE]);
+
+impl Default for Page {
+    fn default() -> Self {
+        Page([0; PAGE_SIZE])
+    }
+}
+
+impl AsMut<[u8]> for Page {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FlashOp {
+    Read(usize),
+    Write(usize),
+    Erase,
+}
+
+/// Flash in RAM which counts how often each page was erased.
+pub(crate) struct SimFlash {
+    pages: RefCell<Vec<[u8; PAGE_SIZE]>>,
+    pub(crate) erases: RefCell<Vec<usize>>,
+    pending: Cell<Option<FlashOp>>,
+    buffer: TakeCell<'static, Page>,
+    client: OptionalCell<&'static dyn flash::Client<SimFlash>>,
+}
+
+impl SimFlash {
+    pub(crate) fn new(pages: usize) -> SimFlash {
+        SimFlash {
+            pages: RefCell::new(vec![[0xFF; PAGE_SIZE]; pages]),
+            erases: RefCell::new(vec![0; pages]),
+            pending: Cell::new(None),
+            buffer: TakeCell::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn start(
+        &self,
+        op: FlashOp,
+        buf: &'static mut Page,
+    ) -> Result<(), (ErrorCode, &'static mut Page)> {
+        if self.pending.get().is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        self.buffer.replace(buf);
+        self.pending.set(Some(op));
+        Ok(())
+    }
+}
+
+impl Pump for SimFlash {
+    fn pump(&self) -> bool {
+        match self.pending.take() {
+            Some(FlashOp::Read(page)) => {
+                let buf = self.buffer.take().unwrap();
+                buf.0.copy_from_slice(&self.pages.borrow()[page]);
+                self.client.map(|client| client.read_complete(buf, Ok(())));
+            }
+            Some(FlashOp::Write(page)) => {
+                let buf = self.buffer.take().unwrap();
+                // Without an erase, writes can only clear bits.
+                for (dst, src) in self.pages.borrow_mut()[page].iter_mut().zip(buf.0) {
+                    *dst &= src;
+                }
+                self.client.map(|client| client.write_complete(buf, Ok(())));
+            }
+            Some(FlashOp::Erase) => {
+                self.client.map(|client| client.erase_complete(Ok(())));
+            }
+            None => return false,
+        }
+        true
+    }
+}
+
+impl Flash for SimFlash {
+    type Page = Page;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Page,
+    ) -> Result<(), (ErrorCode, &'static mut Page)> {
+        self.start(FlashOp::Read(page_number), buf)
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Page,
+    ) -> Result<(), (ErrorCode, &'static mut Page)> {
+        self.start(FlashOp::Write(page_number), buf)
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        if self.pending.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.pages.borrow_mut()[page_number] = [0xFF; PAGE_SIZE];
+        self.erases.borrow_mut()[page_number] += 1;
+        self.pending.set(Some(FlashOp::Erase));
+        Ok(())
+    }
+}
+
+impl<C: flash::Client<SimFlash>> HasClient<'static, C> for SimFlash {
+    fn set_client(&'static self, client: &'static C) {
+        self.client.set(client);
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// FNV-1a hasher.
+pub(crate) struct SimHasher {
+    state: Cell<u64>,
+    data: MapCell<SubSliceMut<'static, u8>>,
+    hash: TakeCell<'static, [u8; 8]>,
+    client: OptionalCell<&'static dyn hasher::Client<8>>,
+}
+
+impl SimHasher {
+    pub(crate) fn new() -> SimHasher {
+        SimHasher {
+            state: Cell::new(FNV_OFFSET),
+            data: MapCell::empty(),
+            hash: TakeCell::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+}
+
+impl Pump for SimHasher {
+    fn pump(&self) -> bool {
+        if let Some(mut data) = self.data.take() {
+            let state = data.as_slice().iter().fold(self.state.get(), |state, &b| {
+                (state ^ b as u64).wrapping_mul(FNV_PRIME)
+            });
+            self.state.set(state);
+            self.client
+                .map(|client| client.add_mut_data_done(Ok(()), data));
+        } else if let Some(hash) = self.hash.take() {
+            *hash = self.state.get().to_be_bytes();
+            self.client.map(|client| client.hash_done(Ok(()), hash));
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+impl Hasher<'static, 8> for SimHasher {
+    fn set_client(&'static self, client: &'static dyn hasher::Client<8>) {
+        self.client.set(client);
+    }
+
+    fn add_data(
+        &self,
+        data: SubSlice<'static, u8>,
+    ) -> Result<usize, (ErrorCode, SubSlice<'static, u8>)> {
+        Err((ErrorCode::NOSUPPORT, data))
+    }
+
+    fn add_mut_data(
+        &self,
+        data: SubSliceMut<'static, u8>,
+    ) -> Result<usize, (ErrorCode, SubSliceMut<'static, u8>)> {
+        let len = data.len();
+        self.data.replace(data);
+        Ok(len)
+    }
+
+    fn run(
+        &'static self,
+        hash: &'static mut [u8; 8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 8])> {
+        self.hash.replace(hash);
+        Ok(())
+    }
+
+    fn clear_data(&self) {
+        self.state.set(FNV_OFFSET);
+    }
+}
+
+/// The key and value buffers of a KV operation. Deletes have no value.
+pub(crate) type Buffers = (SubSliceMut<'static, u8>, Option<SubSliceMut<'static, u8>>);
+
+#[derive(Clone, Copy)]
+enum KVOp {
+    Get,
+    Set,
+    Delete,
+}
+
+/// KV store in RAM. Only gets, sets and deletes are supported.
+#[derive(Default)]
+pub(crate) struct RamKV {
+    pub(crate) values: RefCell<HashMap<Vec<u8>, Vec<u8>>>,
+    pending: RefCell<Option<(KVOp, Buffers)>>,
+    client: OptionalCell<&'static dyn KVClient>,
+}
+
+impl Pump for RamKV {
+    fn pump(&self) -> bool {
+        let (op, (mut key, value)) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return false,
+        };
+        self.client.map(|client| match op {
+            KVOp::Set => {
+                let mut value = value.unwrap();
+                self.values
+                    .borrow_mut()
+                    .insert(key.as_slice().to_vec(), value.as_slice().to_vec());
+                client.set_complete(Ok(()), key, value);
+            }
+            KVOp::Get => {
+                let mut value = value.unwrap();
+                let result = match self.values.borrow().get(key.as_slice()) {
+                    Some(stored) if stored.len() <= value.len() => {
+                        value.as_slice()[..stored.len()].copy_from_slice(stored);
+                        Ok(())
+                    }
+                    Some(stored) => {
+                        let len = value.len();
+                        value.as_slice().copy_from_slice(&stored[..len]);
+                        Err(ErrorCode::SIZE)
+                    }
+                    None => Err(ErrorCode::NOSUPPORT),
+                };
+                client.get_complete(result, key, value);
+            }
+            KVOp::Delete => {
+                let result = match self.values.borrow_mut().remove(key.as_slice()) {
+                    Some(_) => Ok(()),
+                    None => Err(ErrorCode::NOSUPPORT),
+                };
+                client.delete_complete(result, key);
+            }
+        });
+        true
+    }
+}
+
+impl RamKV {
+    fn start(&self, op: KVOp, buffers: Buffers) -> Result<(), Buffers> {
+        if self.pending.borrow().is_some() {
+            return Err(buffers);
+        }
+        self.pending.replace(Some((op, buffers)));
+        Ok(())
+    }
+}
+
+impl KV<'static> for RamKV {
+    fn set_client(&self, client: &'static dyn KVClient) {
+        self.client.set(client);
+    }
+
+    fn get(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        self.start(KVOp::Get, (key, Some(value)))
+            .map_err(|(key, value)| (key, value.unwrap(), ErrorCode::BUSY))
+    }
+
+    fn set(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        self.start(KVOp::Set, (key, Some(value)))
+            .map_err(|(key, value)| (key, value.unwrap(), ErrorCode::BUSY))
+    }
+
+    fn add(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        Err((key, value, ErrorCode::NOSUPPORT))
+    }
+
+    fn update(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        Err((key, value, ErrorCode::NOSUPPORT))
+    }
+
+    fn delete(
+        &self,
+        key: SubSliceMut<'static, u8>,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        self.start(KVOp::Delete, (key, None))
+            .map_err(|(key, _)| (key, ErrorCode::BUSY))
+    }
+
+    fn begin_transaction(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn stage_set(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        Err((key, value, ErrorCode::NOSUPPORT))
+    }
+
+    fn stage_delete(
+        &self,
+        key: SubSliceMut<'static, u8>,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        Err((key, ErrorCode::NOSUPPORT))
+    }
+
+    fn commit_transaction(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn abort_transaction(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
+
+/// KV client which keeps the result and buffers of the last operation.
+#[derive(Default)]
+pub(crate) struct KVRecorder {
+    done: RefCell<Option<(Result<(), ErrorCode>, Buffers)>>,
+}
+
+impl KVRecorder {
+    /// The result and buffers of the last operation, if it completed.
+    pub(crate) fn take(&self) -> Option<(Result<(), ErrorCode>, Buffers)> {
+        self.done.take()
+    }
+}
+
+impl KVClient for KVRecorder {
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.done.replace(Some((result, (key, Some(value)))));
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.done.replace(Some((result, (key, Some(value)))));
+    }
+
+    fn add_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.done.replace(Some((result, (key, Some(value)))));
+    }
+
+    fn update_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.done.replace(Some((result, (key, Some(value)))));
+    }
+
+    fn delete_complete(&self, result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.done.replace(Some((result, (key, None))));
+    }
+}
@@ -27,10 +27,22 @@
 //! Unable to find key: [18, 52, 86, 120, 154, 188, 222, 240]
 //! Let's start a garbage collection
 //! Finished garbage collection
+//! Found key: [123, 201, 247, 255, 79, 118, 242, 68] with 0 byte value in region 4
+//! Found 1 keys
+//! 16 regions: 1 keys, 15 live, 0 reclaimable and 8158 free bytes, erased at most 1 times
+//! Let's defragment the most worn region
+//! Moved the keys out of region 4
+//! Found key: [123, 201, 247, 255, 79, 118, 242, 68] with 0 byte value in region 5
+//! Found 1 keys
 //! ---Finished TicKV Tests---
 //! ```
+//!
+//! The region statistics depend on the size of the flash and on what else is
+//! stored in it. The value buffer is also used to move values when
+//! defragmenting, if it is smaller than a stored value that step is skipped.
+//! The unit tests in `tickv.rs` run the same sequence on a simulated flash.
 
-use crate::tickv::{KVSystem, KVSystemClient, KeyType};
+use crate::tickv::{KVSystem, KVSystemClient, KeyCursor, KeyInfo, KeyType, RegionStats};
 use core::cell::Cell;
 use core::marker::PhantomData;
 use kernel::debug;
@@ -38,21 +50,34 @@ use kernel::utilities::cells::{MapCell, TakeCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
 use kernel::ErrorCode;
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum CurrentState {
     Normal,
     ExpectGetValueFail,
+    CheckDefragment,
+    Finished,
 }
 
-pub struct KVSystemTest<'a, S: KVSystem<'static>, T: KeyType> {
+pub struct KVSystemTest<'a, S: KVSystem<'static>, T: KeyType + 'static> {
     kv_system: &'a S,
     phantom: PhantomData<&'a T>,
     value: MapCell<SubSliceMut<'static, u8>>,
     ret_buffer: TakeCell<'static, [u8]>,
     state: Cell<CurrentState>,
+    /// The key buffer while it isn't used by an operation.
+    key: TakeCell<'static, T>,
+    /// The number of keys found while enumerating.
+    keys_found: Cell<usize>,
+    /// The number of keys found before defragmenting.
+    keys_before: Cell<usize>,
+    /// The region whose statistics are being read.
+    region: Cell<usize>,
+    /// The statistics of the regions read so far added together, except for
+    /// the erase count which is the highest of them.
+    totals: Cell<RegionStats>,
 }
 
-impl<'a, S: KVSystem<'static>, T: KeyType> KVSystemTest<'a, S, T> {
+impl<'a, S: KVSystem<'static>, T: KeyType + 'static> KVSystemTest<'a, S, T> {
     pub fn new(
         kv_system: &'a S,
         value: SubSliceMut<'static, u8>,
@@ -66,11 +91,30 @@ impl<'a, S: KVSystem<'static>, T: KeyType> KVSystemTest<'a, S, T> {
             value: MapCell::new(value),
             ret_buffer: TakeCell::new(static_buf),
             state: Cell::new(CurrentState::Normal),
+            key: TakeCell::empty(),
+            keys_found: Cell::new(0),
+            keys_before: Cell::new(0),
+            region: Cell::new(0),
+            totals: Cell::new(RegionStats::default()),
         }
     }
 }
 
-impl<'a, S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> KVSystemClient<T>
+impl<'a, S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug + 'static>
+    KVSystemTest<'a, S, T>
+{
+    fn enumerate_keys(&self, key: &'static mut T) {
+        self.keys_found.set(0);
+        self.kv_system.next_key(KeyCursor::default(), key).unwrap();
+    }
+
+    fn finish(&self) {
+        debug!("---Finished TicKV Tests---");
+        self.state.set(CurrentState::Finished);
+    }
+}
+
+impl<'a, S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug + 'static> KVSystemClient<T>
     for KVSystemTest<'a, S, T>
 {
     fn generate_key_complete(
@@ -102,6 +146,7 @@ impl<'a, S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> KVSystemCli
         match result {
             Ok(()) => {
                 debug!("Key: {:?} with value {:?} was added", key, value);
+                self.value.replace(value);
                 debug!("Now retrieving the key");
                 self.kv_system
                     .get_value(key, SubSliceMut::new(self.ret_buffer.take().unwrap()))
@@ -130,6 +175,7 @@ impl<'a, S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> KVSystemCli
                     // We expected this failure
                     debug!("Unable to find key: {:?}", key);
                     self.state.set(CurrentState::Normal);
+                    self.key.replace(key);
 
                     debug!("Let's start a garbage collection");
                     self.kv_system.garbage_collect().unwrap();
@@ -161,11 +207,105 @@ impl<'a, S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> KVSystemCli
         match result {
             Ok(()) => {
                 debug!("Finished garbage collection");
-                debug!("---Finished TicKV Tests---");
+                self.enumerate_keys(self.key.take().unwrap());
             }
             Err(e) => {
                 panic!("Error running garbage collection: {:?}", e);
             }
         }
     }
+
+    fn next_key_complete(&self, result: Result<Option<KeyInfo>, ErrorCode>, key: &'static mut T) {
+        match result {
+            Ok(Some(info)) => {
+                debug!(
+                    "Found key: {:?} with {} byte value in region {}",
+                    key, info.value_length, info.region
+                );
+                self.keys_found.set(self.keys_found.get() + 1);
+                self.kv_system.next_key(info.next, key).unwrap();
+            }
+            Ok(None) => {
+                debug!("Found {} keys", self.keys_found.get());
+                if self.state.get() == CurrentState::CheckDefragment {
+                    assert_eq!(self.keys_found.get(), self.keys_before.get());
+                    self.key.replace(key);
+                    self.finish();
+                } else {
+                    self.keys_before.set(self.keys_found.get());
+                    self.key.replace(key);
+                    self.region.set(0);
+                    self.totals.set(RegionStats::default());
+                    self.kv_system.region_stats(0).unwrap();
+                }
+            }
+            Err(e) => {
+                panic!("Error finding keys: {:?}", e);
+            }
+        }
+    }
+
+    fn region_stats_complete(&self, result: Result<RegionStats, ErrorCode>) {
+        match result {
+            Ok(stats) => {
+                let totals = self.totals.get();
+                self.totals.set(RegionStats {
+                    erase_count: totals.erase_count.max(stats.erase_count),
+                    live_keys: totals.live_keys + stats.live_keys,
+                    live_bytes: totals.live_bytes + stats.live_bytes,
+                    reclaimable_bytes: totals.reclaimable_bytes + stats.reclaimable_bytes,
+                    free_bytes: totals.free_bytes + stats.free_bytes,
+                });
+
+                let region = self.region.get() + 1;
+                if region < self.kv_system.region_count() {
+                    self.region.set(region);
+                    self.kv_system.region_stats(region).unwrap();
+                    return;
+                }
+
+                let totals = self.totals.get();
+                debug!(
+                    "{} regions: {} keys, {} live, {} reclaimable and {} free bytes, erased at most {} times",
+                    region,
+                    totals.live_keys,
+                    totals.live_bytes,
+                    totals.reclaimable_bytes,
+                    totals.free_bytes,
+                    totals.erase_count
+                );
+                assert_eq!(totals.live_keys, self.keys_before.get());
+
+                debug!("Let's defragment the most worn region");
+                self.kv_system
+                    .defragment(self.value.take().unwrap())
+                    .unwrap();
+            }
+            Err(e) => {
+                panic!("Error reading region statistics: {:?}", e);
+            }
+        }
+    }
+
+    fn defragment_complete(
+        &self,
+        result: Result<usize, ErrorCode>,
+        buffer: SubSliceMut<'static, u8>,
+    ) {
+        self.value.replace(buffer);
+        match result {
+            Ok(region) => {
+                debug!("Moved the keys out of region {}", region);
+                self.state.set(CurrentState::CheckDefragment);
+                self.enumerate_keys(self.key.take().unwrap());
+            }
+            Err(ErrorCode::SIZE) => {
+                debug!("The value buffer is too small to move the keys");
+                self.finish();
+            }
+            Err(e) => {
+                panic!("Error defragmenting: {:?}", e);
+            }
+        }
+    }
 }
@@ -7,6 +7,8 @@ pub mod aes_ccm;
 pub mod aes_gcm;
 pub mod crc;
 pub mod hmac_sha256;
+#[cfg(test)]
+pub(crate) mod kv_fixture;
 pub mod kv_system;
 pub mod sha256;
 pub mod siphash24;
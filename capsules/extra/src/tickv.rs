@@ -39,8 +39,11 @@ use kernel::hil::hasher::{self, Hasher};
 use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
 use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
 use kernel::ErrorCode;
+use tickv::async_ops::Report;
 use tickv::{self, AsyncTicKV};
 
+pub use tickv::{KeyCursor, KeyInfo, RegionStats};
+
 /// The type of keys, this should define the output size of the digest
 /// operations.
 pub trait KeyType: Eq + Copy + Clone + Sized + AsRef<[u8]> + AsMut<[u8]> {}
@@ -95,6 +98,72 @@ pub trait KVSystemClient<K: KeyType> {
     ///
     /// - `result`: Nothing on success, 'ErrorCode' on error
     fn garbage_collect_complete(&self, result: Result<(), ErrorCode>);
+
+    /// This callback is called when the next_key operation completes.
+    ///
+    /// Clients that don't enumerate keys don't need to implement this.
+    ///
+    /// - `result`: The key that was found or `None` if there are no more
+    ///             keys on success, 'ErrorCode' on error
+    /// - `key`: The key buffer, containing the hashed key if one was found
+    fn next_key_complete(&self, _result: Result<Option<KeyInfo>, ErrorCode>, _key: &'static mut K) {
+    }
+
+    /// This callback is called when the region_stats operation completes.
+    ///
+    /// Clients that don't read region statistics don't need to implement
+    /// this.
+    ///
+    /// - `result`: The statistics of the region on success, 'ErrorCode' on
+    ///             error
+    fn region_stats_complete(&self, _result: Result<RegionStats, ErrorCode>) {}
+
+    /// This callback is called when the defragment operation completes.
+    ///
+    /// Clients that don't defragment the store don't need to implement
+    /// this.
+    ///
+    /// - `result`: The region that was erased on success, 'ErrorCode' on
+    ///             error
+    /// - `buffer`: The buffer used to move values
+    fn defragment_complete(
+        &self,
+        _result: Result<usize, ErrorCode>,
+        _buffer: SubSliceMut<'static, u8>,
+    ) {
+    }
+
+    /// This callback is called when the stage_key operation completes.
+    ///
+    /// Clients that don't use transactions don't need to implement this.
+    ///
+    /// - `result`: Nothing on success, 'ErrorCode' on error
+    /// - `key`: The key buffer
+    /// - `value`: The value buffer
+    fn stage_key_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _key: &'static mut K,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+    }
+
+    /// This callback is called when the stage_invalidate operation
+    /// completes.
+    ///
+    /// Clients that don't use transactions don't need to implement this.
+    ///
+    /// - `result`: Nothing on success, 'ErrorCode' on error
+    /// - `key`: The key buffer
+    fn stage_invalidate_complete(&self, _result: Result<(), ErrorCode>, _key: &'static mut K) {}
+
+    /// This callback is called when the begin_transaction,
+    /// commit_transaction or abort_transaction operation completes.
+    ///
+    /// Clients that don't use transactions don't need to implement this.
+    ///
+    /// - `result`: Nothing on success, 'ErrorCode' on error
+    fn transaction_complete(&self, _result: Result<(), ErrorCode>) {}
 }
 
 pub trait KVSystem<'a> {
@@ -193,6 +262,145 @@ pub trait KVSystem<'a> {
     /// - `INVAL`: An invalid parameter was passed.
     /// - `NODEVICE`: No KV store was setup.
     fn garbage_collect(&self) -> Result<(), ErrorCode>;
+
+    /// Returns the number of regions in the KV store. Regions are numbered
+    /// from zero.
+    fn region_count(&self) -> usize;
+
+    /// Find the next valid key in the KV store.
+    ///
+    /// Start with `KeyCursor::default()` and continue with the `next` cursor
+    /// of each key found, until no key is found.
+    ///
+    /// - `cursor`: The position to start looking from.
+    /// - `key`: A buffer to store the hashed key to.
+    ///
+    /// On success nothing will be returned.
+    /// On error the key and a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    /// - `BUSY`: An operation is already in progress
+    /// - `FAIL`: The operation could not be started
+    fn next_key(
+        &self,
+        cursor: KeyCursor,
+        key: &'static mut Self::K,
+    ) -> Result<(), (&'static mut Self::K, ErrorCode)>;
+
+    /// Read the usage and wear of a region, including how many times it has
+    /// been erased and how much space could be reclaimed.
+    ///
+    /// - `region`: The region number, less than `region_count()`.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    /// - `BUSY`: An operation is already in progress
+    /// - `INVAL`: The region doesn't exist
+    /// - `FAIL`: The operation could not be started
+    fn region_stats(&self, region: usize) -> Result<(), ErrorCode>;
+
+    /// Move the keys out of the most worn region and erase it, reclaiming
+    /// the space used by invalid objects in that region.
+    ///
+    /// - `buffer`: A buffer to move values through. It must be large enough
+    ///             for the largest value in the region.
+    ///
+    /// On success nothing will be returned.
+    /// On error the buffer and a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    /// - `BUSY`: An operation is already in progress
+    /// - `FAIL`: The operation could not be started
+    ///
+    /// The callback reports `SIZE` if the buffer is too small for a value,
+    /// `NOMEM` if there is no space to move a key to and `ALREADY` if there
+    /// are no keys to move.
+    fn defragment(
+        &self,
+        buffer: SubSliceMut<'static, u8>,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)>;
+
+    /// Begin a transaction.
+    ///
+    /// The keys staged with `stage_key()` and `stage_invalidate()` take
+    /// effect together when the transaction is committed. If power is lost
+    /// before that none of them do. Only one transaction can be open at a
+    /// time.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    /// - `BUSY`: An operation is already in progress
+    /// - `ALREADY`: A transaction is already open
+    /// - `FAIL`: The operation could not be started
+    fn begin_transaction(&self) -> Result<(), ErrorCode>;
+
+    /// Stage the key/value pair in the open transaction. When the
+    /// transaction is committed it replaces the value stored for the key.
+    ///
+    /// - `key`: A hashed key.
+    /// - `value`: A buffer containing the data to be stored to flash.
+    ///
+    /// On success nothing will be returned.
+    /// On error the key, value and a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    /// - `BUSY`: An operation is already in progress
+    /// - `INVAL`: No transaction is open
+    /// - `SIZE`: The value is too large
+    ///
+    /// The callback reports `NOSUPPORT` if the key has already been staged
+    /// and `NOMEM` if there is no more space.
+    fn stage_key(
+        &self,
+        key: &'static mut Self::K,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<(), (&'static mut Self::K, SubSliceMut<'static, u8>, ErrorCode)>;
+
+    /// Stage the invalidation of the key in the open transaction.
+    ///
+    /// - `key`: A hashed key.
+    ///
+    /// On success nothing will be returned.
+    /// On error the key and a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    /// - `BUSY`: An operation is already in progress
+    /// - `INVAL`: No transaction is open
+    ///
+    /// The callback reports `NOSUPPORT` if the key has already been staged
+    /// and `NOMEM` if there is no more space.
+    fn stage_invalidate(
+        &self,
+        key: &'static mut Self::K,
+    ) -> Result<(), (&'static mut Self::K, ErrorCode)>;
+
+    /// Commit the open transaction.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    /// - `BUSY`: An operation is already in progress
+    /// - `INVAL`: No transaction is open
+    /// - `FAIL`: The operation could not be started
+    ///
+    /// If the callback reports an error the transaction is still open.
+    fn commit_transaction(&self) -> Result<(), ErrorCode>;
+
+    /// Discard the keys staged in the open transaction.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    /// - `BUSY`: An operation is already in progress
+    /// - `INVAL`: No transaction is open
+    /// - `FAIL`: The operation could not be started
+    fn abort_transaction(&self) -> Result<(), ErrorCode>;
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -203,6 +411,26 @@ enum Operation {
     AppendKey,
     InvalidateKey,
     GarbageCollect,
+    NextKey,
+    RegionStats,
+    Defragment,
+    StageKey,
+    StageInvalidate,
+    Transaction,
+}
+
+/// Convert the error from starting a transaction operation.
+fn transaction_error(e: tickv::error_codes::ErrorCode) -> ErrorCode {
+    match e {
+        tickv::error_codes::ErrorCode::TransactionInProgress => ErrorCode::ALREADY,
+        tickv::error_codes::ErrorCode::NoTransaction => ErrorCode::INVAL,
+        tickv::error_codes::ErrorCode::KeyAlreadyExists => ErrorCode::NOSUPPORT,
+        tickv::error_codes::ErrorCode::RegionFull | tickv::error_codes::ErrorCode::FlashFull => {
+            ErrorCode::NOMEM
+        }
+        tickv::error_codes::ErrorCode::ObjectTooLarge => ErrorCode::SIZE,
+        _ => ErrorCode::FAIL,
+    }
 }
 
 /// Wrapper object that provides the flash interface TicKV expects using the
@@ -277,6 +505,10 @@ impl<'a, F: Flash, const PAGE_SIZE: usize> tickv::flash_controller::FlashControl
     }
 
     fn erase_region(&self, region_number: usize) -> Result<(), tickv::error_codes::ErrorCode> {
+        // TicKV writes the wear record of the region next, so the page
+        // written must start out erased as well.
+        self.flash_read_buffer
+            .map(|buf| buf.as_mut().iter_mut().for_each(|b| *b = 0xFF));
         let _ = self.flash.erase_page(self.region_offset + region_number);
 
         Err(tickv::error_codes::ErrorCode::EraseNotReady(region_number))
@@ -339,6 +571,126 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> TicKVSystem<'a, F,
         self.operation.set(Operation::Init);
     }
 
+    /// Continue the TicKV operation after a flash operation has completed.
+    fn continue_operation(&self) -> Result<tickv::success_codes::SuccessCode, tickv::ErrorCode> {
+        let (ret, tickv_buf, tickv_buf_len) = self.tickv.continue_operation();
+
+        // If we got the buffer back from TicKV then store it.
+        tickv_buf.map(|buf| {
+            let mut val_buf = SubSliceMut::new(buf);
+            if tickv_buf_len > 0 {
+                // Length of zero means nothing was inserted into the buffer so
+                // no need to slice it.
+                val_buf.slice(0..tickv_buf_len);
+            }
+            self.value_buffer.replace(val_buf);
+        });
+
+        ret
+    }
+
+    /// Start beginning, committing or discarding a transaction with `op`.
+    fn start_transaction(
+        &self,
+        op: impl FnOnce() -> Result<tickv::success_codes::SuccessCode, tickv::error_codes::ErrorCode>,
+    ) -> Result<(), ErrorCode> {
+        match self.operation.get() {
+            Operation::None => {
+                op().map_err(transaction_error)?;
+                self.operation.set(Operation::Transaction);
+                Ok(())
+            }
+            _ => {
+                // An operation or initialisation is already in process.
+                Err(ErrorCode::BUSY)
+            }
+        }
+    }
+
+    /// Report the result of a `next_key`, `region_stats`, `defragment` or
+    /// transaction operation, unless TicKV is still waiting for the flash.
+    fn report_complete(&self, ret: Result<tickv::success_codes::SuccessCode, tickv::ErrorCode>) {
+        let error = match ret {
+            Err(tickv::error_codes::ErrorCode::ReadNotReady(_))
+            | Err(tickv::error_codes::ErrorCode::WriteNotReady(_))
+            | Err(tickv::error_codes::ErrorCode::EraseNotReady(_))
+            | Ok(tickv::success_codes::SuccessCode::Queued) => return,
+            Ok(_) => None,
+            Err(e) => Some(match e {
+                tickv::error_codes::ErrorCode::BufferTooSmall(_) => ErrorCode::SIZE,
+                tickv::error_codes::ErrorCode::RegionFull
+                | tickv::error_codes::ErrorCode::FlashFull => ErrorCode::NOMEM,
+                tickv::error_codes::ErrorCode::KeyNotFound => ErrorCode::ALREADY,
+                _ => ErrorCode::FAIL,
+            }),
+        };
+        let report = self.tickv.take_report();
+
+        match self.operation.replace(Operation::None) {
+            Operation::NextKey => {
+                let key = self.key_buffer.take().unwrap();
+                let result = match (error, report) {
+                    (None, Some(Report::NextKey(info))) => Ok(info),
+                    (error, _) => Err(error.unwrap_or(ErrorCode::FAIL)),
+                };
+                if let Ok(Some(info)) = result {
+                    *key = info.hashed_key.to_be_bytes();
+                }
+                self.client.map(move |cb| {
+                    cb.next_key_complete(result, key);
+                });
+            }
+            Operation::RegionStats => {
+                let result = match (error, report) {
+                    (None, Some(Report::RegionStats(stats))) => Ok(stats),
+                    (error, _) => Err(error.unwrap_or(ErrorCode::FAIL)),
+                };
+                self.client.map(move |cb| {
+                    cb.region_stats_complete(result);
+                });
+            }
+            Operation::Defragment => {
+                let result = match (error, report) {
+                    (None, Some(Report::Defragment(region))) => Ok(region),
+                    (error, _) => Err(error.unwrap_or(ErrorCode::FAIL)),
+                };
+                let buffer = self.value_buffer.take().unwrap();
+                self.client.map(move |cb| {
+                    cb.defragment_complete(result, buffer);
+                });
+            }
+            Operation::Transaction => {
+                let result = error.map_or(Ok(()), Err);
+                self.client.map(move |cb| {
+                    cb.transaction_complete(result);
+                });
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Report the result of staging a key that has failed.
+    fn stage_failed(&self, e: tickv::error_codes::ErrorCode) {
+        let result = Err(transaction_error(e));
+        match self.operation.replace(Operation::None) {
+            Operation::StageKey => {
+                self.client.map(|cb| {
+                    cb.stage_key_complete(
+                        result,
+                        self.key_buffer.take().unwrap(),
+                        self.value_buffer.take().unwrap(),
+                    );
+                });
+            }
+            Operation::StageInvalidate => {
+                self.client.map(|cb| {
+                    cb.stage_invalidate_complete(result, self.key_buffer.take().unwrap());
+                });
+            }
+            _ => unreachable!(),
+        }
+    }
+
     fn complete_init(&self) {
         self.operation.set(Operation::None);
         match self.next_operation.get() {
@@ -387,6 +739,12 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> TicKVSystem<'a, F,
                 }
                 _ => {}
             },
+            Operation::NextKey
+            | Operation::RegionStats
+            | Operation::Defragment
+            | Operation::StageKey
+            | Operation::StageInvalidate
+            | Operation::Transaction => {}
         }
         self.next_operation.set(Operation::None);
     }
@@ -421,18 +779,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
             .controller
             .flash_read_buffer
             .replace(pagebuffer);
-        let (ret, tickv_buf, tickv_buf_len) = self.tickv.continue_operation();
-
-        // If we got the buffer back from TicKV then store it.
-        tickv_buf.map(|buf| {
-            let mut val_buf = SubSliceMut::new(buf);
-            if tickv_buf_len > 0 {
-                // Length of zero means nothing was inserted into the buffer so
-                // no need to slice it.
-                val_buf.slice(0..tickv_buf_len);
-            }
-            self.value_buffer.replace(val_buf);
-        });
+        let ret = self.continue_operation();
 
         match self.operation.get() {
             Operation::Init => match ret {
@@ -555,6 +902,17 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                 }
                 _ => {}
             },
+            Operation::NextKey
+            | Operation::RegionStats
+            | Operation::Defragment
+            | Operation::Transaction => {
+                self.report_complete(ret);
+            }
+            Operation::StageKey | Operation::StageInvalidate => match ret {
+                // Wait for the flash write, or the next flash read.
+                Ok(_) | Err(tickv::error_codes::ErrorCode::ReadNotReady(_)) => {}
+                Err(e) => self.stage_failed(e),
+            },
             _ => unreachable!(),
         }
     }
@@ -568,7 +926,15 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
 
         match self.operation.get() {
             Operation::Init => {
-                self.complete_init();
+                // Recovering an interrupted transaction can continue after
+                // the write.
+                match self.continue_operation() {
+                    Ok(tickv::success_codes::SuccessCode::Complete)
+                    | Ok(tickv::success_codes::SuccessCode::Written) => {
+                        self.complete_init();
+                    }
+                    _ => {}
+                }
             }
             Operation::AppendKey => {
                 self.operation.set(Operation::None);
@@ -586,23 +952,45 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                     cb.invalidate_key_complete(Ok(()), self.key_buffer.take().unwrap());
                 });
             }
+            Operation::GarbageCollect => {
+                // The wear record of an erased region has been written.
+                match self.continue_operation() {
+                    Ok(tickv::success_codes::SuccessCode::Complete)
+                    | Ok(tickv::success_codes::SuccessCode::Written) => {
+                        self.operation.set(Operation::None);
+                        self.client.map(|cb| {
+                            cb.garbage_collect_complete(Ok(()));
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            Operation::Defragment | Operation::Transaction => {
+                let ret = self.continue_operation();
+                self.report_complete(ret);
+            }
+            Operation::StageKey => {
+                self.operation.set(Operation::None);
+                self.client.map(|cb| {
+                    cb.stage_key_complete(
+                        Ok(()),
+                        self.key_buffer.take().unwrap(),
+                        self.value_buffer.take().unwrap(),
+                    );
+                });
+            }
+            Operation::StageInvalidate => {
+                self.operation.set(Operation::None);
+                self.client.map(|cb| {
+                    cb.stage_invalidate_complete(Ok(()), self.key_buffer.take().unwrap());
+                });
+            }
             _ => unreachable!(),
         }
     }
 
     fn erase_complete(&self, _result: Result<(), flash::Error>) {
-        let (ret, tickv_buf, tickv_buf_len) = self.tickv.continue_operation();
-
-        // If we got the buffer back from TicKV then store it.
-        tickv_buf.map(|buf| {
-            let mut val_buf = SubSliceMut::new(buf);
-            if tickv_buf_len > 0 {
-                // Length of zero means nothing was inserted into the buffer so
-                // no need to slice it.
-                val_buf.slice(0..tickv_buf_len);
-            }
-            self.value_buffer.replace(val_buf);
-        });
+        let ret = self.continue_operation();
 
         match self.operation.get() {
             Operation::Init => match ret {
@@ -622,6 +1010,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                 }
                 _ => {}
             },
+            Operation::Defragment => self.report_complete(ret),
             _ => unreachable!(),
         }
     }
@@ -757,6 +1146,67 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
         }
     }
 
+    fn region_count(&self) -> usize {
+        self.tickv.tickv.region_count()
+    }
+
+    fn next_key(
+        &self,
+        cursor: KeyCursor,
+        key: &'static mut Self::K,
+    ) -> Result<(), (&'static mut Self::K, ErrorCode)> {
+        match self.operation.get() {
+            Operation::None => match self.tickv.next_key(cursor) {
+                Ok(_ret) => {
+                    self.operation.set(Operation::NextKey);
+                    self.key_buffer.replace(key);
+                    Ok(())
+                }
+                Err(_e) => Err((key, ErrorCode::FAIL)),
+            },
+            _ => {
+                // An operation or initialisation is already in process.
+                Err((key, ErrorCode::BUSY))
+            }
+        }
+    }
+
+    fn region_stats(&self, region: usize) -> Result<(), ErrorCode> {
+        if region >= self.region_count() {
+            return Err(ErrorCode::INVAL);
+        }
+        match self.operation.get() {
+            Operation::None => {
+                self.tickv.region_stats(region).or(Err(ErrorCode::FAIL))?;
+                self.operation.set(Operation::RegionStats);
+                Ok(())
+            }
+            _ => {
+                // An operation or initialisation is already in process.
+                Err(ErrorCode::BUSY)
+            }
+        }
+    }
+
+    fn defragment(
+        &self,
+        buffer: SubSliceMut<'static, u8>,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        match self.operation.get() {
+            Operation::None => match self.tickv.defragment(buffer.take()) {
+                Ok(_ret) => {
+                    self.operation.set(Operation::Defragment);
+                    Ok(())
+                }
+                Err((buf, _e)) => Err((SubSliceMut::new(buf), ErrorCode::FAIL)),
+            },
+            _ => {
+                // An operation or initialisation is already in process.
+                Err((buffer, ErrorCode::BUSY))
+            }
+        }
+    }
+
     fn garbage_collect(&self) -> Result<(), ErrorCode> {
         match self.operation.get() {
             Operation::None => {
@@ -778,4 +1228,341 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
             }
         }
     }
+
+    fn begin_transaction(&self) -> Result<(), ErrorCode> {
+        self.start_transaction(|| self.tickv.begin_transaction())
+    }
+
+    fn stage_key(
+        &self,
+        key: &'static mut Self::K,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<(), (&'static mut [u8; 8], SubSliceMut<'static, u8>, ErrorCode)> {
+        match self.operation.get() {
+            Operation::None => {
+                let length = value.len();
+                match self
+                    .tickv
+                    .stage_key(u64::from_be_bytes(*key), value.take(), length)
+                {
+                    Ok(_ret) => {
+                        self.operation.set(Operation::StageKey);
+                        self.key_buffer.replace(key);
+                        Ok(())
+                    }
+                    Err((buf, e)) => Err((key, SubSliceMut::new(buf), transaction_error(e))),
+                }
+            }
+            _ => {
+                // An operation or initialisation is already in process.
+                Err((key, value, ErrorCode::BUSY))
+            }
+        }
+    }
+
+    fn stage_invalidate(
+        &self,
+        key: &'static mut Self::K,
+    ) -> Result<(), (&'static mut Self::K, ErrorCode)> {
+        match self.operation.get() {
+            Operation::None => match self.tickv.stage_invalidate(u64::from_be_bytes(*key)) {
+                Ok(_ret) => {
+                    self.operation.set(Operation::StageInvalidate);
+                    self.key_buffer.replace(key);
+                    Ok(())
+                }
+                Err(e) => Err((key, transaction_error(e))),
+            },
+            _ => {
+                // An operation or initialisation is already in process.
+                Err((key, ErrorCode::BUSY))
+            }
+        }
+    }
+
+    fn commit_transaction(&self) -> Result<(), ErrorCode> {
+        self.start_transaction(|| self.tickv.commit_transaction())
+    }
+
+    fn abort_transaction(&self) -> Result<(), ErrorCode> {
+        self.start_transaction(|| self.tickv.abort_transaction())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test::kv_fixture::{self, leak, Page, SimFlash, SimHasher, PAGE_SIZE};
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    const PAGES: usize = 16;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Done(Result<(), ErrorCode>),
+        Key(Result<Option<KeyInfo>, ErrorCode>),
+        Stats(Result<RegionStats, ErrorCode>),
+        Defragmented(Result<usize, ErrorCode>),
+    }
+
+    /// Client which keeps the result and buffers of the last operation.
+    struct Recorder {
+        event: RefCell<Option<Event>>,
+        key: TakeCell<'static, [u8; 8]>,
+        value: MapCell<SubSliceMut<'static, u8>>,
+    }
+
+    impl Recorder {
+        fn done(&self, event: Event, key: Option<&'static mut [u8; 8]>) {
+            assert!(self.event.replace(Some(event)).is_none());
+            key.map(|key| self.key.replace(key));
+        }
+    }
+
+    impl KVSystemClient<[u8; 8]> for Recorder {
+        fn generate_key_complete(
+            &self,
+            result: Result<(), ErrorCode>,
+            _unhashed_key: SubSliceMut<'static, u8>,
+            key_buf: &'static mut [u8; 8],
+        ) {
+            self.done(Event::Done(result), Some(key_buf));
+        }
+
+        fn append_key_complete(
+            &self,
+            result: Result<(), ErrorCode>,
+            key: &'static mut [u8; 8],
+            value: SubSliceMut<'static, u8>,
+        ) {
+            self.value.replace(value);
+            self.done(Event::Done(result), Some(key));
+        }
+
+        fn get_value_complete(
+            &self,
+            result: Result<(), ErrorCode>,
+            key: &'static mut [u8; 8],
+            ret_buf: SubSliceMut<'static, u8>,
+        ) {
+            self.value.replace(ret_buf);
+            self.done(Event::Done(result), Some(key));
+        }
+
+        fn invalidate_key_complete(
+            &self,
+            result: Result<(), ErrorCode>,
+            key: &'static mut [u8; 8],
+        ) {
+            self.done(Event::Done(result), Some(key));
+        }
+
+        fn garbage_collect_complete(&self, result: Result<(), ErrorCode>) {
+            self.done(Event::Done(result), None);
+        }
+
+        fn next_key_complete(
+            &self,
+            result: Result<Option<KeyInfo>, ErrorCode>,
+            key: &'static mut [u8; 8],
+        ) {
+            self.done(Event::Key(result), Some(key));
+        }
+
+        fn region_stats_complete(&self, result: Result<RegionStats, ErrorCode>) {
+            self.done(Event::Stats(result), None);
+        }
+
+        fn defragment_complete(
+            &self,
+            result: Result<usize, ErrorCode>,
+            buffer: SubSliceMut<'static, u8>,
+        ) {
+            self.value.replace(buffer);
+            self.done(Event::Defragmented(result), None);
+        }
+    }
+
+    struct Harness {
+        flash: &'static SimFlash,
+        hasher: &'static SimHasher,
+        kv: &'static TicKVSystem<'static, SimFlash, SimHasher, PAGE_SIZE>,
+        client: &'static Recorder,
+    }
+
+    impl Harness {
+        fn new() -> Harness {
+            let flash = leak(SimFlash::new(PAGES));
+            let hasher = leak(SimHasher::new());
+            let kv = leak(TicKVSystem::new(
+                flash,
+                hasher,
+                leak([0; PAGE_SIZE]),
+                leak(Page::default()),
+                0,
+                PAGE_SIZE * PAGES,
+            ));
+            let client = leak(Recorder {
+                event: RefCell::new(None),
+                key: TakeCell::empty(),
+                value: MapCell::empty(),
+            });
+            flash::HasClient::set_client(flash, kv);
+            hasher.set_client(kv);
+            kv.set_client(client);
+            let harness = Harness {
+                flash,
+                hasher,
+                kv,
+                client,
+            };
+            kv.initialise();
+            kv_fixture::run(&[flash, hasher]);
+            harness
+        }
+
+        fn run(&self) -> Event {
+            kv_fixture::run(&[self.flash, self.hasher]);
+            self.client.event.take().expect("no callback")
+        }
+
+        fn key(&self) -> &'static mut [u8; 8] {
+            self.client.key.take().unwrap()
+        }
+
+        fn value(&self) -> Vec<u8> {
+            self.client.value.take().unwrap().as_slice().to_vec()
+        }
+
+        fn generate_key(&self, name: &[u8]) -> &'static mut [u8; 8] {
+            let name = SubSliceMut::new(leak(name.to_vec()).as_mut_slice());
+            self.kv.generate_key(name, leak([0; 8])).unwrap();
+            assert_eq!(self.run(), Event::Done(Ok(())));
+            self.key()
+        }
+
+        fn append(&self, key: &'static mut [u8; 8], value: &[u8]) -> Event {
+            let value = SubSliceMut::new(leak(value.to_vec()).as_mut_slice());
+            self.kv.append_key(key, value).unwrap();
+            self.run()
+        }
+
+        fn get(&self, key: &'static mut [u8; 8], length: usize) -> (Event, Vec<u8>) {
+            let buffer = SubSliceMut::new(leak(std::vec![0; length]).as_mut_slice());
+            self.kv.get_value(key, buffer).unwrap();
+            (self.run(), self.value())
+        }
+
+        /// All keys found by enumerating the store.
+        fn keys(&self) -> Vec<KeyInfo> {
+            let mut keys = Vec::new();
+            let mut cursor = KeyCursor::default();
+            loop {
+                self.kv.next_key(cursor, leak([0; 8])).unwrap();
+                match self.run() {
+                    Event::Key(Ok(Some(info))) => {
+                        assert_eq!(u64::from_be_bytes(*self.key()), info.hashed_key);
+                        cursor = info.next;
+                        keys.push(info);
+                    }
+                    Event::Key(Ok(None)) => return keys,
+                    event => panic!("unexpected {:?}", event),
+                }
+            }
+        }
+
+        fn stats(&self) -> Vec<RegionStats> {
+            (0..self.kv.region_count())
+                .map(|region| {
+                    self.kv.region_stats(region).unwrap();
+                    match self.run() {
+                        Event::Stats(Ok(stats)) => stats,
+                        event => panic!("unexpected {:?}", event),
+                    }
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn append_get_and_invalidate() {
+        let harness = Harness::new();
+        let key = harness.generate_key(b"tickv-test");
+        let hashed_key = *key;
+        assert_eq!(
+            harness.append(key, &[0x10, 0x20, 0x30]),
+            Event::Done(Ok(()))
+        );
+        harness.value();
+
+        let (event, value) = harness.get(leak(hashed_key), 4);
+        assert_eq!(event, Event::Done(Ok(())));
+        assert_eq!(value[..3], [0x10, 0x20, 0x30]);
+
+        assert_eq!(
+            harness.append(leak(hashed_key), &[1]),
+            Event::Done(Err(ErrorCode::NOSUPPORT))
+        );
+        assert!(harness.keys().iter().any(|info| info.hashed_key
+            == u64::from_be_bytes(hashed_key)
+            && info.value_length == 3));
+
+        harness.kv.invalidate_key(leak(hashed_key)).unwrap();
+        assert_eq!(harness.run(), Event::Done(Ok(())));
+        let (event, _) = harness.get(leak(hashed_key), 4);
+        assert!(matches!(event, Event::Done(Err(_))));
+        assert!(harness
+            .keys()
+            .iter()
+            .all(|info| info.hashed_key != u64::from_be_bytes(hashed_key)));
+    }
+
+    #[test]
+    fn stats_garbage_collection_and_defragmentation() {
+        let harness = Harness::new();
+        let key = harness.generate_key(b"tickv-test");
+        assert_eq!(
+            harness.append(key, &[0x10, 0x20, 0x30]),
+            Event::Done(Ok(()))
+        );
+        harness.kv.invalidate_key(harness.key()).unwrap();
+        assert_eq!(harness.run(), Event::Done(Ok(())));
+
+        harness.kv.garbage_collect().unwrap();
+        assert_eq!(harness.run(), Event::Done(Ok(())));
+
+        let keys = harness.keys();
+        let stats = harness.stats();
+        assert_eq!(stats.len(), PAGES);
+        assert_eq!(
+            stats.iter().map(|stats| stats.live_keys).sum::<usize>(),
+            keys.len()
+        );
+        assert!(stats
+            .iter()
+            .all(
+                |stats| stats.live_bytes + stats.reclaimable_bytes + stats.free_bytes <= PAGE_SIZE
+            ));
+
+        harness
+            .kv
+            .defragment(SubSliceMut::new(leak([0; 3]).as_mut_slice()))
+            .unwrap();
+        assert_eq!(harness.run(), Event::Defragmented(Ok(4)));
+        let moved = harness.keys();
+        assert_eq!(moved.len(), keys.len());
+        assert!(keys.iter().zip(moved.iter()).all(|(key, moved)| {
+            key.hashed_key == moved.hashed_key && key.value_length == moved.value_length
+        }));
+
+        // Initialising the empty flash erases every region, then the garbage
+        // collection reclaims the removed key's region and the defragmentation
+        // erases the region it moved the main key out of.
+        let erases = harness.flash.erases.borrow();
+        assert_eq!(erases.iter().sum::<usize>(), PAGES + 2);
+        assert_eq!(erases[4], 2);
+    }
 }
@@ -40,6 +40,9 @@ enum Operation {
     Add,
     Update,
     Delete,
+    StageSet,
+    StageDelete,
+    Transaction,
 }
 
 /// `TicKVKVStore` implements the KV interface using the TicKV KVSystem
@@ -101,6 +104,42 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> TicKVKVStore<'a, K, T> {
             None => Err((key, value, ErrorCode::FAIL)),
         }
     }
+
+    fn remove(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        operation: Operation,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        if self.operation.is_some() {
+            return Err((key, ErrorCode::BUSY));
+        }
+
+        self.operation.set(operation);
+
+        match self.hashed_key.take() {
+            Some(hashed_key) => match self.kv.generate_key(key, hashed_key) {
+                Ok(()) => Ok(()),
+                Err((unhashed_key, hashed_key, _e)) => {
+                    self.hashed_key.replace(hashed_key);
+                    self.operation.clear();
+                    Err((unhashed_key, ErrorCode::FAIL))
+                }
+            },
+            None => Err((key, ErrorCode::FAIL)),
+        }
+    }
+
+    fn transaction(&self, start: impl FnOnce() -> Result<(), ErrorCode>) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.operation.set(Operation::Transaction);
+        start().map_err(|e| {
+            self.operation.clear();
+            e
+        })
+    }
 }
 
 impl<'a, K: KVSystem<'a, K = T>, T: KeyType> kv::KV<'a> for TicKVKVStore<'a, K, T> {
@@ -191,23 +230,41 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> kv::KV<'a> for TicKVKVStore<'a, K,
         &self,
         key: SubSliceMut<'static, u8>,
     ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
-        if self.operation.is_some() {
-            return Err((key, ErrorCode::BUSY));
-        }
+        self.remove(key, Operation::Delete)
+    }
 
-        self.operation.set(Operation::Delete);
+    fn begin_transaction(&self) -> Result<(), ErrorCode> {
+        self.transaction(|| self.kv.begin_transaction())
+    }
 
-        match self.hashed_key.take() {
-            Some(hashed_key) => match self.kv.generate_key(key, hashed_key) {
-                Ok(()) => Ok(()),
-                Err((unhashed_key, hashed_key, _e)) => {
-                    self.hashed_key.replace(hashed_key);
-                    self.operation.clear();
-                    Err((unhashed_key, ErrorCode::FAIL))
-                }
-            },
-            None => Err((key, ErrorCode::FAIL)),
-        }
+    fn stage_set(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        self.insert(key, value, Operation::StageSet)
+    }
+
+    fn stage_delete(
+        &self,
+        key: SubSliceMut<'static, u8>,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        self.remove(key, Operation::StageDelete)
+    }
+
+    fn commit_transaction(&self) -> Result<(), ErrorCode> {
+        self.transaction(|| self.kv.commit_transaction())
+    }
+
+    fn abort_transaction(&self) -> Result<(), ErrorCode> {
+        self.transaction(|| self.kv.abort_transaction())
     }
 }
 
@@ -255,11 +312,19 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
                             });
                         });
                     }
-                    Operation::Delete => {
+                    Operation::StageSet => {
+                        self.value.take().map(|value| {
+                            self.client.map(move |cb| {
+                                cb.set_complete(Err(ErrorCode::FAIL), unhashed_key, value);
+                            });
+                        });
+                    }
+                    Operation::Delete | Operation::StageDelete => {
                         self.client.map(move |cb| {
                             cb.delete_complete(Err(ErrorCode::FAIL), unhashed_key);
                         });
                     }
+                    Operation::Transaction => {}
                 }
             } else {
                 match op {
@@ -350,6 +415,40 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
                             }
                         };
                     }
+                    Operation::StageSet => {
+                        self.value.take().map(|value| {
+                            // Staging replaces any existing value when the
+                            // transaction is committed, so there is no need
+                            // to invalidate the key first.
+                            match self.kv.stage_key(hashed_key, value) {
+                                Ok(()) => {
+                                    self.unhashed_key.replace(unhashed_key);
+                                }
+                                Err((key, value, e)) => {
+                                    self.hashed_key.replace(key);
+                                    self.operation.clear();
+                                    self.client.map(move |cb| {
+                                        cb.set_complete(Err(e), unhashed_key, value);
+                                    });
+                                }
+                            }
+                        });
+                    }
+                    Operation::StageDelete => {
+                        match self.kv.stage_invalidate(hashed_key) {
+                            Ok(()) => {
+                                self.unhashed_key.replace(unhashed_key);
+                            }
+                            Err((key, e)) => {
+                                self.hashed_key.replace(key);
+                                self.operation.clear();
+                                self.client.map(move |cb| {
+                                    cb.delete_complete(Err(e), unhashed_key);
+                                });
+                            }
+                        };
+                    }
+                    Operation::Transaction => {}
                 }
             }
         });
@@ -364,7 +463,11 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
         self.hashed_key.replace(key);
 
         self.operation.map(|op| match op {
-            Operation::Get | Operation::Delete => {}
+            Operation::Get
+            | Operation::Delete
+            | Operation::StageSet
+            | Operation::StageDelete
+            | Operation::Transaction => {}
             Operation::Set => {
                 match result {
                     Err(ErrorCode::NOSUPPORT) => {
@@ -477,7 +580,11 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
         self.hashed_key.replace(key);
 
         self.operation.map(|op| match op {
-            Operation::Get | Operation::Add => {}
+            Operation::Get
+            | Operation::Add
+            | Operation::StageSet
+            | Operation::StageDelete
+            | Operation::Transaction => {}
             Operation::Set => {
                 // Now that we have deleted the existing key-value we can store
                 // our new key and value.
@@ -568,4 +675,45 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
     }
 
     fn garbage_collect_complete(&self, _result: Result<(), ErrorCode>) {}
+
+    fn stage_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut T,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.hashed_key.replace(key);
+        self.operation.clear();
+
+        self.unhashed_key.take().map(|unhashed_key| {
+            self.client.map(move |cb| {
+                cb.set_complete(
+                    result.map_err(|e| match e {
+                        ErrorCode::NOSUPPORT => ErrorCode::NOSUPPORT,
+                        ErrorCode::NOMEM => ErrorCode::NOMEM,
+                        ErrorCode::SIZE => ErrorCode::SIZE,
+                        _ => ErrorCode::FAIL,
+                    }),
+                    unhashed_key,
+                    value,
+                );
+            });
+        });
+    }
+
+    fn stage_invalidate_complete(&self, result: Result<(), ErrorCode>, key: &'static mut T) {
+        self.hashed_key.replace(key);
+        self.operation.clear();
+
+        self.unhashed_key.take().map(|unhashed_key| {
+            self.client.map(move |cb| {
+                cb.delete_complete(result, unhashed_key);
+            });
+        });
+    }
+
+    fn transaction_complete(&self, result: Result<(), ErrorCode>) {
+        self.operation.clear();
+        self.client.map(|cb| cb.transaction_complete(result));
+    }
 }
@@ -48,6 +48,11 @@ enum Operation {
     Delete,
     Add,
     Update,
+    StageSet,
+    StageDelete,
+    Begin,
+    Commit,
+    Abort,
 }
 
 pub struct VirtualKVPermissions<'a, V: kv::KVPermissions<'a>> {
@@ -124,6 +129,45 @@ impl<'a, V: kv::KVPermissions<'a>> VirtualKVPermissions<'a, V> {
             .do_next_op(false)
             .map_err(|e| (self.key.take().unwrap(), self.value.take().unwrap(), e))
     }
+
+    fn remove(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+        operation: Operation,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        if self.operation.is_some() {
+            return Err((key, ErrorCode::BUSY));
+        }
+
+        self.operation.set(operation);
+        self.valid_ids.set(permissions);
+        self.key.replace(key);
+
+        self.mux_kv
+            .do_next_op(false)
+            .map_err(|e| (self.key.take().unwrap(), e))
+    }
+
+    /// Whether this user has the open transaction.
+    fn owns_transaction(&self) -> bool {
+        self.mux_kv
+            .transaction
+            .map_or(false, |owner| core::ptr::eq(owner, self))
+    }
+
+    fn finish_transaction(&self, operation: Operation) -> Result<(), ErrorCode> {
+        if !self.owns_transaction() {
+            return Err(ErrorCode::INVAL);
+        }
+
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.operation.set(operation);
+        self.mux_kv.do_next_op(false)
+    }
 }
 
 impl<'a, V: kv::KVPermissions<'a>> kv::KVPermissions<'a> for VirtualKVPermissions<'a, V> {
@@ -211,17 +255,80 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVPermissions<'a> for VirtualKVPermission
         key: SubSliceMut<'static, u8>,
         permissions: StoragePermissions,
     ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        self.remove(key, permissions, Operation::Delete)
+    }
+
+    fn begin_transaction(&self, permissions: StoragePermissions) -> Result<(), ErrorCode> {
+        if permissions.get_write_id().is_none() {
+            return Err(ErrorCode::INVAL);
+        }
+
         if self.operation.is_some() {
-            return Err((key, ErrorCode::BUSY));
+            return Err(ErrorCode::BUSY);
         }
 
-        self.operation.set(Operation::Delete);
+        // Only one transaction can be open in the underlying store, so
+        // another user has to wait until the open one is finished.
+        if self.owns_transaction() {
+            return Err(ErrorCode::ALREADY);
+        } else if self.mux_kv.transaction.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.operation.set(Operation::Begin);
         self.valid_ids.set(permissions);
-        self.key.replace(key);
+        // Record the owner with the list's reference to this user.
+        self.mux_kv.transaction.insert(
+            self.mux_kv
+                .users
+                .iter()
+                .find(|node| core::ptr::eq(*node, self)),
+        );
+
+        self.mux_kv.do_next_op(false).map_err(|e| {
+            self.mux_kv.transaction.clear();
+            e
+        })
+    }
 
-        self.mux_kv
-            .do_next_op(false)
-            .map_err(|e| (self.key.take().unwrap(), e))
+    fn stage_set(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        if !self.owns_transaction() {
+            return Err((key, value, ErrorCode::INVAL));
+        }
+
+        self.insert(key, value, permissions, Operation::StageSet)
+    }
+
+    fn stage_delete(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        if !self.owns_transaction() {
+            return Err((key, ErrorCode::INVAL));
+        }
+
+        self.remove(key, permissions, Operation::StageDelete)
+    }
+
+    fn commit_transaction(&self) -> Result<(), ErrorCode> {
+        self.finish_transaction(Operation::Commit)
+    }
+
+    fn abort_transaction(&self) -> Result<(), ErrorCode> {
+        self.finish_transaction(Operation::Abort)
     }
 
     fn header_size(&self) -> usize {
@@ -233,6 +340,8 @@ pub struct MuxKVPermissions<'a, V: kv::KVPermissions<'a>> {
     kv: &'a V,
     users: List<'a, VirtualKVPermissions<'a, V>>,
     inflight: OptionalCell<&'a VirtualKVPermissions<'a, V>>,
+    /// The user which has the open transaction, if any.
+    transaction: OptionalCell<&'a VirtualKVPermissions<'a, V>>,
 }
 
 impl<'a, V: kv::KVPermissions<'a>> MuxKVPermissions<'a, V> {
@@ -241,16 +350,62 @@ impl<'a, V: kv::KVPermissions<'a>> MuxKVPermissions<'a, V> {
             kv,
             inflight: OptionalCell::empty(),
             users: List::new(),
+            transaction: OptionalCell::empty(),
+        }
+    }
+
+    fn do_transaction_op(
+        &self,
+        node: &'a VirtualKVPermissions<'a, V>,
+        op: Operation,
+        async_op: bool,
+    ) -> Result<(), ErrorCode> {
+        let ret = match op {
+            Operation::Begin => node.valid_ids.map_or(Err(ErrorCode::FAIL), |perms| {
+                self.kv.begin_transaction(perms)
+            }),
+            Operation::Commit => self.kv.commit_transaction(),
+            _ => self.kv.abort_transaction(),
+        };
+
+        match ret {
+            Ok(()) => {
+                self.inflight.set(node);
+                Ok(())
+            }
+            Err(e) => {
+                node.operation.clear();
+                if op == Operation::Begin {
+                    self.transaction.clear();
+                }
+                if async_op {
+                    node.client.map(move |cb| {
+                        cb.transaction_complete(Err(e));
+                    });
+                    Ok(())
+                } else {
+                    Err(e)
+                }
+            }
         }
     }
 
     fn do_next_op(&self, async_op: bool) -> Result<(), ErrorCode> {
+        // Transaction operations don't take a key, so they can't rely on the
+        // key being taken to tell that they are in flight.
+        if self.inflight.is_some() {
+            return Ok(());
+        }
+
         // Find a virtual device which has pending work.
         let mnode = self.users.iter().find(|node| node.operation.is_some());
 
         mnode.map_or(Ok(()), |node| {
-            node.operation.map_or(Ok(()), |op| {
-                node.key.take().map_or(Ok(()), |key| match op {
+            node.operation.map_or(Ok(()), |op| match op {
+                Operation::Begin | Operation::Commit | Operation::Abort => {
+                    self.do_transaction_op(node, op, async_op)
+                }
+                _ => node.key.take().map_or(Ok(()), |key| match op {
                     Operation::Get => node.value.take().map_or(Ok(()), |value| {
                         node.valid_ids.map_or(Ok(()), |perms| {
                             match self.kv.get(key, value, perms) {
@@ -274,29 +429,36 @@ impl<'a, V: kv::KVPermissions<'a>> MuxKVPermissions<'a, V> {
                             }
                         })
                     }),
-                    Operation::Set => node.value.take().map_or(Ok(()), |value| {
-                        node.valid_ids.map_or(Ok(()), |perms| {
-                            match self.kv.set(key, value, perms) {
-                                Ok(()) => {
-                                    self.inflight.set(node);
-                                    Ok(())
-                                }
-                                Err((key, value, e)) => {
-                                    node.operation.clear();
-                                    if async_op {
-                                        node.client.map(move |cb| {
-                                            cb.set_complete(Err(e), key, value);
-                                        });
+                    Operation::Set | Operation::StageSet => {
+                        node.value.take().map_or(Ok(()), |value| {
+                            node.valid_ids.map_or(Ok(()), |perms| {
+                                let ret = if op == Operation::Set {
+                                    self.kv.set(key, value, perms)
+                                } else {
+                                    self.kv.stage_set(key, value, perms)
+                                };
+                                match ret {
+                                    Ok(()) => {
+                                        self.inflight.set(node);
                                         Ok(())
-                                    } else {
-                                        node.key.replace(key);
-                                        node.value.replace(value);
-                                        Err(e)
+                                    }
+                                    Err((key, value, e)) => {
+                                        node.operation.clear();
+                                        if async_op {
+                                            node.client.map(move |cb| {
+                                                cb.set_complete(Err(e), key, value);
+                                            });
+                                            Ok(())
+                                        } else {
+                                            node.key.replace(key);
+                                            node.value.replace(value);
+                                            Err(e)
+                                        }
                                     }
                                 }
-                            }
+                            })
                         })
-                    }),
+                    }
                     Operation::Add => node.value.take().map_or(Ok(()), |value| {
                         node.valid_ids.map_or(Ok(()), |perms| {
                             match self.kv.add(key, value, perms) {
@@ -343,9 +505,14 @@ impl<'a, V: kv::KVPermissions<'a>> MuxKVPermissions<'a, V> {
                             }
                         })
                     }),
-                    Operation::Delete => {
-                        node.valid_ids
-                            .map_or(Ok(()), |perms| match self.kv.delete(key, perms) {
+                    Operation::Delete | Operation::StageDelete => {
+                        node.valid_ids.map_or(Ok(()), |perms| {
+                            let ret = if op == Operation::Delete {
+                                self.kv.delete(key, perms)
+                            } else {
+                                self.kv.stage_delete(key, perms)
+                            };
+                            match ret {
                                 Ok(()) => {
                                     self.inflight.set(node);
                                     Ok(())
@@ -362,9 +529,12 @@ impl<'a, V: kv::KVPermissions<'a>> MuxKVPermissions<'a, V> {
                                         Err(e)
                                     }
                                 }
-                            })
+                            }
+                        })
                     }
-                })
+                    // Handled above, these operations don't have a key.
+                    Operation::Begin | Operation::Commit | Operation::Abort => Ok(()),
+                }),
             })
         })
     }
@@ -445,4 +615,21 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for MuxKVPermissions<'a, V> {
 
         let _ = self.do_next_op(true);
     }
+
+    fn transaction_complete(&self, result: Result<(), ErrorCode>) {
+        self.inflight.take().map(|node| {
+            // The transaction stays open if committing it failed, so that
+            // the owner can still abort it.
+            node.operation.take().map(|op| match op {
+                Operation::Begin if result.is_err() => self.transaction.clear(),
+                Operation::Commit | Operation::Abort if result.is_ok() => self.transaction.clear(),
+                _ => {}
+            });
+            node.client.map(move |cb| {
+                cb.transaction_complete(result);
+            });
+        });
+
+        let _ = self.do_next_op(true);
+    }
 }
@@ -0,0 +1,338 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Tests that drive the `FlashCrashLog` capsule against a simulated flash.
+//!
+//! Creating the capsule's grant requires a capability, which needs `unsafe`
+//! that `capsules-extra` forbids, so these tests live outside of the crate.
+//! No process ever enters the grant. Crash records are built from real
+//! panics, caught by a panic hook.
+
+use std::cell::{Cell, RefCell};
+use std::panic;
+use std::sync::Once;
+
+use capsules_extra::crash_log::{FlashCrashLog, DRIVER_NUM};
+use kernel::capabilities::MemoryAllocationCapability;
+use kernel::crash_log::{CrashReader, CrashRecord, CrashRecorder, RECORD_LEN};
+use kernel::hil::flash::{self, Flash, HasClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, Kernel};
+
+struct MemoryAllocationCap;
+unsafe impl MemoryAllocationCapability for MemoryAllocationCap {}
+
+const PAGE_SIZE: usize = 256;
+const CRASH_LOG_PAGE: usize = 1;
+
+struct Page([u8; PAGE_SIZE]);
+
+impl Default for Page {
+    fn default() -> Self {
+        Page([0; PAGE_SIZE])
+    }
+}
+
+impl AsMut<[u8]> for Page {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Read(usize),
+    Write(usize),
+    Erase(usize),
+}
+
+/// Flash in RAM that accepts one operation at a time and completes it when
+/// the test calls `complete()`, like a hardware interrupt would.
+struct SimFlash {
+    pages: RefCell<Vec<[u8; PAGE_SIZE]>>,
+    /// Every operation that was started, in order.
+    ops: RefCell<Vec<Op>>,
+    pending: Cell<Option<Op>>,
+    buffer: TakeCell<'static, Page>,
+    client: OptionalCell<&'static dyn flash::Client<SimFlash>>,
+}
+
+impl SimFlash {
+    fn new(pages: usize) -> SimFlash {
+        SimFlash {
+            pages: RefCell::new(vec![[0xFF; PAGE_SIZE]; pages]),
+            ops: RefCell::new(Vec::new()),
+            pending: Cell::new(None),
+            buffer: TakeCell::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn start(&self, op: Op) -> Result<(), ErrorCode> {
+        if self.pending.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.ops.borrow_mut().push(op);
+        self.pending.set(Some(op));
+        Ok(())
+    }
+
+    /// Complete the pending operation. Returns the operation, if there was
+    /// one.
+    fn complete(&self) -> Option<Op> {
+        let op = self.pending.take()?;
+        let client = self.client.get().expect("flash client not set");
+        match op {
+            Op::Read(page) => {
+                let buffer = self.buffer.take().unwrap();
+                buffer.0 = self.pages.borrow()[page];
+                client.read_complete(buffer, Ok(()));
+            }
+            Op::Write(page) => {
+                let buffer = self.buffer.take().unwrap();
+                self.pages.borrow_mut()[page] = buffer.0;
+                client.write_complete(buffer, Ok(()));
+            }
+            Op::Erase(page) => {
+                self.pages.borrow_mut()[page] = [0xFF; PAGE_SIZE];
+                client.erase_complete(Ok(()));
+            }
+        }
+        Some(op)
+    }
+}
+
+impl Flash for SimFlash {
+    type Page = Page;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Page,
+    ) -> Result<(), (ErrorCode, &'static mut Page)> {
+        match self.start(Op::Read(page_number)) {
+            Ok(()) => {
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Page,
+    ) -> Result<(), (ErrorCode, &'static mut Page)> {
+        match self.start(Op::Write(page_number)) {
+            Ok(()) => {
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        self.start(Op::Erase(page_number))
+    }
+}
+
+impl HasClient<'static, FlashCrashLog<'static, SimFlash>> for SimFlash {
+    fn set_client(&'static self, client: &'static FlashCrashLog<'static, SimFlash>) {
+        self.client.set(client);
+    }
+}
+
+fn leak<T>(value: T) -> &'static mut T {
+    Box::leak(Box::new(value))
+}
+
+thread_local! {
+    static CAUGHT: RefCell<Option<CrashRecord>> = RefCell::new(None);
+    static CATCHING: Cell<bool> = Cell::new(false);
+}
+
+/// Panic with `message` and return the record the kernel would build from
+/// the panic.
+fn panic_record(message: &'static str) -> CrashRecord {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(|catching| catching.get()) {
+                CAUGHT.with(|caught| *caught.borrow_mut() = Some(CrashRecord::from_panic(info)));
+            } else {
+                default_hook(info);
+            }
+        }));
+    });
+
+    CATCHING.with(|catching| catching.set(true));
+    let _ = panic::catch_unwind(|| panic!("{}", message));
+    CATCHING.with(|catching| catching.set(false));
+    CAUGHT.with(|caught| caught.borrow_mut().take()).unwrap()
+}
+
+struct Harness {
+    flash: &'static SimFlash,
+    crash_log: &'static FlashCrashLog<'static, SimFlash>,
+}
+
+impl Harness {
+    /// A crash log whose flash page holds `contents`. The log has not been
+    /// loaded yet.
+    fn new(contents: [u8; PAGE_SIZE]) -> Harness {
+        let kernel: &'static Kernel = leak(Kernel::new(&[]));
+        let flash: &'static SimFlash = leak(SimFlash::new(CRASH_LOG_PAGE + 1));
+        flash.pages.borrow_mut()[CRASH_LOG_PAGE] = contents;
+        let crash_log: &'static FlashCrashLog<'static, SimFlash> = leak(FlashCrashLog::new(
+            flash,
+            CRASH_LOG_PAGE,
+            leak(Page::default()),
+            kernel.create_grant(DRIVER_NUM, &MemoryAllocationCap),
+        ));
+        flash.set_client(crash_log);
+        Harness { flash, crash_log }
+    }
+
+    /// A loaded crash log whose flash page is erased.
+    fn loaded() -> Harness {
+        let harness = Harness::new([0xFF; PAGE_SIZE]);
+        harness.crash_log.load().unwrap();
+        harness.run();
+        harness.flash.ops.borrow_mut().clear();
+        harness
+    }
+
+    /// Complete flash operations until none is pending.
+    fn run(&self) {
+        while self.flash.complete().is_some() {}
+    }
+
+    fn ops(&self) -> Vec<Op> {
+        self.flash.ops.borrow().clone()
+    }
+
+    fn stored(&self) -> Option<CrashRecord> {
+        CrashRecord::decode(&self.flash.pages.borrow()[CRASH_LOG_PAGE])
+    }
+}
+
+fn encoded(record: &CrashRecord) -> [u8; PAGE_SIZE] {
+    let mut page = [0xFF; PAGE_SIZE];
+    record.encode(&mut page).unwrap();
+    page
+}
+
+#[test]
+fn record_erases_then_writes_the_page() {
+    let harness = Harness::loaded();
+
+    harness.crash_log.record_crash(panic_record("first"));
+    assert!(harness.crash_log.is_busy());
+    assert_eq!(harness.ops(), [Op::Erase(CRASH_LOG_PAGE)]);
+    assert!(harness.stored().is_none());
+
+    assert_eq!(harness.flash.complete(), Some(Op::Erase(CRASH_LOG_PAGE)));
+    assert!(harness.crash_log.is_busy());
+    assert_eq!(
+        harness.ops(),
+        [Op::Erase(CRASH_LOG_PAGE), Op::Write(CRASH_LOG_PAGE)]
+    );
+
+    assert_eq!(harness.flash.complete(), Some(Op::Write(CRASH_LOG_PAGE)));
+    assert!(!harness.crash_log.is_busy());
+    let stored = harness.stored().unwrap();
+    assert_eq!(stored.count(), 1);
+    assert!(stored.message().contains("first"));
+    assert_eq!(harness.crash_log.last_crash().unwrap().count(), 1);
+}
+
+#[test]
+fn later_records_count_earlier_ones() {
+    let harness = Harness::loaded();
+
+    harness.crash_log.record_crash(panic_record("first"));
+    harness.run();
+    harness.crash_log.record_crash(panic_record("second"));
+    harness.run();
+
+    let stored = harness.stored().unwrap();
+    assert_eq!(stored.count(), 2);
+    assert!(stored.message().contains("second"));
+}
+
+#[test]
+fn record_is_dropped_while_busy() {
+    let harness = Harness::loaded();
+    harness.crash_log.record_crash(panic_record("first"));
+
+    // While the page is being erased.
+    harness.crash_log.record_crash(panic_record("during erase"));
+    assert_eq!(harness.ops(), [Op::Erase(CRASH_LOG_PAGE)]);
+
+    // While the record is being written.
+    harness.flash.complete();
+    harness.crash_log.record_crash(panic_record("during write"));
+    harness.run();
+
+    assert_eq!(
+        harness.ops(),
+        [Op::Erase(CRASH_LOG_PAGE), Op::Write(CRASH_LOG_PAGE)]
+    );
+    let stored = harness.stored().unwrap();
+    assert_eq!(stored.count(), 1);
+    assert!(stored.message().contains("first"));
+    assert_eq!(harness.crash_log.last_crash().unwrap().count(), 1);
+}
+
+#[test]
+fn record_is_dropped_while_loading() {
+    let harness = Harness::new([0xFF; PAGE_SIZE]);
+    harness.crash_log.load().unwrap();
+
+    harness.crash_log.record_crash(panic_record("too early"));
+    assert!(!harness.crash_log.is_busy());
+    harness.run();
+
+    assert_eq!(harness.ops(), [Op::Read(CRASH_LOG_PAGE)]);
+    assert!(harness.crash_log.last_crash().is_none());
+}
+
+#[test]
+fn load_decodes_the_stored_record() {
+    let mut record = panic_record("before reboot");
+    record.set_count(7);
+    let harness = Harness::new(encoded(&record));
+    assert!(harness.crash_log.last_crash().is_none());
+
+    harness.crash_log.load().unwrap();
+    assert_eq!(harness.crash_log.load(), Err(ErrorCode::BUSY));
+    harness.run();
+
+    let loaded = harness.crash_log.last_crash().unwrap();
+    assert_eq!(loaded.count(), 7);
+    assert_eq!(loaded.message(), record.message());
+
+    // The next record continues the count.
+    harness.crash_log.record_crash(panic_record("after reboot"));
+    harness.run();
+    assert_eq!(harness.stored().unwrap().count(), 8);
+}
+
+#[test]
+fn load_ignores_erased_and_corrupt_pages() {
+    let harness = Harness::new([0xFF; PAGE_SIZE]);
+    harness.crash_log.load().unwrap();
+    harness.run();
+    assert!(harness.crash_log.last_crash().is_none());
+
+    let mut corrupt = encoded(&panic_record("corrupt"));
+    corrupt[RECORD_LEN - 1] ^= 0x01;
+    let harness = Harness::new(corrupt);
+    harness.crash_log.load().unwrap();
+    harness.run();
+    assert!(harness.crash_log.last_crash().is_none());
+}
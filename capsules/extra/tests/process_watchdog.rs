@@ -0,0 +1,626 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Tests that drive the `ProcessWatchdog` capsule against a kernel with real
+//! processes and grants.
+//!
+//! Creating the capabilities, the kernel and its processes requires `unsafe`,
+//! which `capsules-extra` forbids, so these tests live outside of the crate.
+//! The processes are loaded from TBF headers built by the tests and never
+//! run: the chip's userspace boundary does nothing, and the tests call the
+//! capsule's syscall and alarm handlers directly.
+
+use std::cell::Cell;
+use std::fmt::Write;
+use std::sync::{Mutex, Once};
+
+use capsules_extra::process_watchdog::{
+    GatedWatchDog, MissedHeartbeatAction, ProcessWatchdog, DRIVER_NUM,
+};
+use kernel::capabilities::{
+    MemoryAllocationCapability, ProcessInitCapability, ProcessManagementCapability,
+};
+use kernel::collections::ring_buffer::RingBuffer;
+use kernel::debug::{DebugWriter, DebugWriterWrapper};
+use kernel::hil::time::{self, AlarmClient, Ticks};
+use kernel::hil::uart;
+use kernel::platform::chip::Chip;
+use kernel::platform::watchdog::WatchDog;
+use kernel::process::{self, ProcessSlot, State, StopFaultPolicy};
+use kernel::syscall::{
+    ContextSwitchReason, ProcessRegisters, SyscallDriver, SyscallReturn, UserspaceKernelBoundary,
+};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+struct MemoryAllocationCap;
+unsafe impl MemoryAllocationCapability for MemoryAllocationCap {}
+
+struct ProcessManagementCap;
+unsafe impl ProcessManagementCapability for ProcessManagementCap {}
+
+struct ProcessInitCap;
+unsafe impl ProcessInitCapability for ProcessInitCap {}
+
+/// A userspace boundary for processes that never run.
+struct NoUserspace;
+
+impl UserspaceKernelBoundary for NoUserspace {
+    type StoredState = ();
+
+    fn initial_process_app_brk_size(&self) -> usize {
+        0
+    }
+
+    unsafe fn initialize_process(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut (),
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    unsafe fn set_syscall_return_value(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut (),
+        _return_value: SyscallReturn,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    unsafe fn set_process_function(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut (),
+        _upcall: process::FunctionCall,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    unsafe fn switch_to_process(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut (),
+    ) -> (ContextSwitchReason, Option<*const u8>) {
+        unreachable!("processes in these tests never run")
+    }
+
+    unsafe fn print_context(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &(),
+        _writer: &mut dyn Write,
+    ) {
+    }
+
+    unsafe fn get_registers(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &(),
+    ) -> Option<ProcessRegisters> {
+        None
+    }
+
+    unsafe fn get_debug_register(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &(),
+        _index: usize,
+    ) -> Option<usize> {
+        None
+    }
+
+    unsafe fn set_debug_register(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut (),
+        _index: usize,
+        _value: usize,
+    ) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn store_context(&self, _state: &(), _out: &mut [u8]) -> Result<usize, ErrorCode> {
+        Ok(0)
+    }
+
+    fn load_context(&self, _state: &mut (), _input: &[u8]) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
+
+struct TestChip {
+    userspace: NoUserspace,
+}
+
+impl Chip for TestChip {
+    type MPU = ();
+    type UserspaceKernelBoundary = NoUserspace;
+
+    fn service_pending_interrupts(&self) {}
+
+    fn has_pending_interrupts(&self) -> bool {
+        false
+    }
+
+    fn mpu(&self) -> &() {
+        &()
+    }
+
+    fn userspace_kernel_boundary(&self) -> &NoUserspace {
+        &self.userspace
+    }
+
+    fn sleep(&self) {}
+
+    unsafe fn atomic<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        f()
+    }
+
+    unsafe fn print_state(&self, _writer: &mut dyn Write) {}
+}
+
+/// A UART that accepts one buffer and never finishes sending it, so that
+/// `debug!()` has somewhere to go.
+struct NullUart;
+
+impl<'a> uart::Transmit<'a> for NullUart {
+    fn set_transmit_client(&self, _client: &'a dyn uart::TransmitClient) {}
+
+    fn transmit_buffer(
+        &self,
+        _tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        Ok(())
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
+
+/// An alarm whose clock only moves when a test advances it.
+#[derive(Default)]
+struct MockAlarm {
+    now: Cell<u32>,
+    expiry: Cell<Option<u32>>,
+}
+
+impl MockAlarm {
+    fn advance(&self, ms: u32) {
+        self.now.set(self.now.get().wrapping_add(ms));
+    }
+
+    /// Milliseconds from now until the alarm fires, if it is armed.
+    fn remaining(&self) -> Option<u32> {
+        self.expiry
+            .get()
+            .map(|expiry| expiry.wrapping_sub(self.now.get()))
+    }
+
+    /// Whether the alarm is armed and its expiry has passed.
+    fn expired(&self) -> bool {
+        self.expiry.get().map_or(false, |expiry| {
+            self.now.get().wrapping_sub(expiry) <= u32::MAX / 2
+        })
+    }
+}
+
+impl time::Time for MockAlarm {
+    type Frequency = time::Freq1KHz;
+    type Ticks = time::Ticks32;
+
+    fn now(&self) -> Self::Ticks {
+        self.now.get().into()
+    }
+}
+
+impl<'a> time::Alarm<'a> for MockAlarm {
+    fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+    fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
+        self.expiry
+            .set(Some(reference.into_u32().wrapping_add(dt.into_u32())));
+    }
+
+    fn get_alarm(&self) -> Self::Ticks {
+        self.expiry.get().unwrap_or(0).into()
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.expiry.set(None);
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.expiry.get().is_some()
+    }
+
+    fn minimum_dt(&self) -> Self::Ticks {
+        1.into()
+    }
+}
+
+/// A software model of a hardware watchdog: it expires if it runs for
+/// `timeout` milliseconds without being tickled.
+struct SimulatedWatchDog {
+    timeout: u32,
+    since_tickle: Cell<u32>,
+    running: Cell<bool>,
+    expired: Cell<bool>,
+}
+
+impl SimulatedWatchDog {
+    fn new(timeout: u32) -> SimulatedWatchDog {
+        SimulatedWatchDog {
+            timeout,
+            since_tickle: Cell::new(0),
+            running: Cell::new(false),
+            expired: Cell::new(false),
+        }
+    }
+
+    fn advance(&self, ms: u32) {
+        if self.running.get() {
+            self.since_tickle.set(self.since_tickle.get() + ms);
+            if self.since_tickle.get() >= self.timeout {
+                self.expired.set(true);
+            }
+        }
+    }
+}
+
+impl WatchDog for SimulatedWatchDog {
+    fn setup(&self) {
+        self.running.set(true);
+        self.since_tickle.set(0);
+    }
+
+    fn tickle(&self) {
+        self.running.set(true);
+        self.since_tickle.set(0);
+    }
+
+    fn suspend(&self) {
+        self.running.set(false);
+    }
+}
+
+/// The package name and the command permissions for the watchdog driver of
+/// one test process. `None` leaves out the permissions TLV.
+type App = (&'static str, Option<u64>);
+
+/// A process that may register a heartbeat.
+const CRITICAL: Option<u64> = Some(0b1111);
+
+/// Build a TBF object with a main TLV, a package name, the kernel version
+/// and, optionally, a permissions TLV for the watchdog driver.
+fn tbf(name: &str, permissions: Option<u64>) -> Vec<u8> {
+    fn tlv(header: &mut Vec<u8>, tlv_type: u16, value: &[u8]) {
+        header.extend_from_slice(&tlv_type.to_le_bytes());
+        header.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        header.extend_from_slice(value);
+        header.resize((header.len() + 3) & !3, 0);
+    }
+
+    let mut header = vec![0; 16];
+    // Main: init function offset, protected trailer size and enough RAM for
+    // the grants.
+    let mut main = vec![0; 8];
+    main.extend_from_slice(&1024u32.to_le_bytes());
+    tlv(&mut header, 1, &main);
+    tlv(&mut header, 3, name.as_bytes());
+    let mut version = kernel::KERNEL_MAJOR_VERSION.to_le_bytes().to_vec();
+    version.extend_from_slice(&kernel::KERNEL_MINOR_VERSION.to_le_bytes());
+    tlv(&mut header, 8, &version);
+    if let Some(allowed_commands) = permissions {
+        let mut value = 1u16.to_le_bytes().to_vec();
+        value.extend_from_slice(&(DRIVER_NUM as u32).to_le_bytes());
+        value.extend_from_slice(&0u32.to_le_bytes());
+        value.extend_from_slice(&allowed_commands.to_le_bytes());
+        tlv(&mut header, 6, &value);
+    }
+
+    let header_size = header.len();
+    let total_size = header_size + 64;
+    header[0..2].copy_from_slice(&2u16.to_le_bytes());
+    header[2..4].copy_from_slice(&(header_size as u16).to_le_bytes());
+    header[4..8].copy_from_slice(&(total_size as u32).to_le_bytes());
+    // Enabled.
+    header[8..12].copy_from_slice(&1u32.to_le_bytes());
+    let checksum = header
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .fold(0, |checksum, word| checksum ^ word);
+    header[12..16].copy_from_slice(&checksum.to_le_bytes());
+
+    header.resize(total_size, 0);
+    header
+}
+
+#[repr(align(8))]
+struct AppMemory([u8; 16384]);
+
+/// `debug!()` writes to a global writer, so the tests take turns.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn set_debug_writer() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let writer = Box::leak(Box::new(DebugWriter::new(
+            Box::leak(Box::new(NullUart)),
+            Box::leak(Box::new([0; 64])),
+            Box::leak(Box::new(RingBuffer::new(Box::leak(Box::new([0; 256]))))),
+        )));
+        unsafe {
+            kernel::debug::set_debug_writer_wrapper(Box::leak(Box::new(DebugWriterWrapper::new(
+                writer,
+            ))));
+        }
+    });
+}
+
+type TestWatchdog = ProcessWatchdog<'static, MockAlarm, SimulatedWatchDog, ProcessManagementCap>;
+
+struct Harness {
+    processes: &'static [ProcessSlot],
+    alarm: &'static MockAlarm,
+    hardware: &'static SimulatedWatchDog,
+    gate: &'static GatedWatchDog<'static, SimulatedWatchDog>,
+    watchdog: &'static TestWatchdog,
+}
+
+impl Harness {
+    fn new(apps: &[App], action: MissedHeartbeatAction) -> Harness {
+        set_debug_writer();
+
+        let processes: &'static [ProcessSlot] =
+            Box::leak((0..apps.len()).map(|_| ProcessSlot::EMPTY).collect());
+        let kernel: &'static Kernel = Box::leak(Box::new(Kernel::new(processes)));
+        let alarm: &'static MockAlarm = Box::leak(Box::default());
+        let hardware = Box::leak(Box::new(SimulatedWatchDog::new(50)));
+        let gate = Box::leak(Box::new(GatedWatchDog::new(&*hardware)));
+        let watchdog = Box::leak(Box::new(ProcessWatchdog::new(
+            kernel,
+            &*alarm,
+            &*gate,
+            action,
+            kernel.create_grant(DRIVER_NUM, &MemoryAllocationCap),
+            ProcessManagementCap,
+        )));
+
+        let mut flash: Vec<u8> = apps
+            .iter()
+            .flat_map(|(name, permissions)| tbf(name, *permissions))
+            .collect();
+        flash.resize(flash.len() + 8, 0);
+        let chip = Box::leak(Box::new(TestChip {
+            userspace: NoUserspace,
+        }));
+        process::load_processes(
+            kernel,
+            chip,
+            flash.leak(),
+            &mut Box::leak(Box::new(AppMemory([0; 16384]))).0,
+            processes,
+            &StopFaultPolicy {},
+            &ProcessManagementCap,
+        )
+        .unwrap();
+        // Start the processes as the kernel loop would, so that their grants
+        // can be entered.
+        for slot in processes {
+            assert_eq!(
+                slot.get().unwrap().enqueue_init_task(&ProcessInitCap),
+                Ok(())
+            );
+        }
+
+        gate.setup();
+        Harness {
+            processes,
+            alarm,
+            hardware,
+            gate,
+            watchdog,
+        }
+    }
+
+    fn processid(&self, index: usize) -> ProcessId {
+        self.processes[index].get().unwrap().processid()
+    }
+
+    fn command(&self, index: usize, command_num: usize, data: usize) -> Result<(), ErrorCode> {
+        let result = self
+            .watchdog
+            .command(command_num, data, 0, self.processid(index));
+        match result.get_failure() {
+            Some(error) => Err(error),
+            None => {
+                assert!(result.is_success());
+                Ok(())
+            }
+        }
+    }
+
+    fn register(&self, index: usize, interval_ms: usize) -> Result<(), ErrorCode> {
+        self.command(index, 1, interval_ms)
+    }
+
+    fn check_in(&self, index: usize) -> Result<(), ErrorCode> {
+        self.command(index, 2, 0)
+    }
+
+    fn unregister(&self, index: usize) -> Result<(), ErrorCode> {
+        self.command(index, 3, 0)
+    }
+
+    fn faulted(&self, index: usize) -> bool {
+        self.processes[index].get().unwrap().get_state() == State::Faulted
+    }
+
+    /// Let `ms` milliseconds pass in steps of at most 5 ms. In each step the
+    /// kernel loop tickles the watchdog, and the alarm fires once it expires.
+    fn run(&self, ms: u32) {
+        let mut left = ms;
+        while left > 0 {
+            let step = left.min(5);
+            self.gate.tickle();
+            self.alarm.advance(step);
+            self.hardware.advance(step);
+            left -= step;
+            if self.alarm.expired() {
+                self.alarm.expiry.set(None);
+                self.watchdog.alarm();
+            }
+        }
+    }
+}
+
+#[test]
+fn only_permitted_processes_may_register() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let harness = Harness::new(
+        &[
+            ("critical", CRITICAL),
+            ("unlisted", None),
+            ("other", Some(0b1101)),
+        ],
+        MissedHeartbeatAction::ResetBoard,
+    );
+
+    assert_eq!(harness.register(0, 100), Ok(()));
+    // Without a permissions TLV, and with one that leaves out command 1.
+    assert_eq!(harness.register(1, 100), Err(ErrorCode::NOSUPPORT));
+    assert_eq!(harness.register(2, 100), Err(ErrorCode::NOSUPPORT));
+    assert_eq!(harness.check_in(1), Err(ErrorCode::INVAL));
+    assert_eq!(harness.unregister(2), Err(ErrorCode::INVAL));
+}
+
+#[test]
+fn check_in_moves_the_deadline() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let harness = Harness::new(&[("critical", CRITICAL)], MissedHeartbeatAction::ResetBoard);
+
+    assert_eq!(harness.check_in(0), Err(ErrorCode::INVAL));
+    assert_eq!(harness.register(0, 0), Err(ErrorCode::INVAL));
+    assert_eq!(harness.alarm.remaining(), None);
+
+    assert_eq!(harness.register(0, 100), Ok(()));
+    assert_eq!(harness.alarm.remaining(), Some(100));
+    for _ in 0..10 {
+        harness.run(80);
+        assert_eq!(harness.check_in(0), Ok(()));
+        assert_eq!(harness.alarm.remaining(), Some(100));
+    }
+    assert!(harness.gate.is_open());
+    assert!(!harness.hardware.expired.get());
+
+    assert_eq!(harness.unregister(0), Ok(()));
+    assert_eq!(harness.alarm.remaining(), None);
+    harness.run(500);
+    assert!(harness.gate.is_open());
+    assert_eq!(harness.unregister(0), Err(ErrorCode::INVAL));
+}
+
+#[test]
+fn alarm_is_set_for_the_earliest_deadline() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let harness = Harness::new(
+        &[("slow", CRITICAL), ("fast", CRITICAL)],
+        MissedHeartbeatAction::RestartProcess,
+    );
+
+    assert_eq!(harness.register(0, 300), Ok(()));
+    assert_eq!(harness.alarm.remaining(), Some(300));
+    harness.run(50);
+    assert_eq!(harness.register(1, 100), Ok(()));
+    assert_eq!(harness.alarm.remaining(), Some(100));
+    assert_eq!(harness.unregister(1), Ok(()));
+    assert_eq!(harness.alarm.remaining(), Some(250));
+}
+
+#[test]
+fn missed_heartbeat_faults_the_process() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let harness = Harness::new(
+        &[("late", CRITICAL), ("punctual", CRITICAL)],
+        MissedHeartbeatAction::RestartProcess,
+    );
+
+    assert_eq!(harness.register(0, 100), Ok(()));
+    assert_eq!(harness.register(1, 100), Ok(()));
+    for _ in 0..3 {
+        harness.run(60);
+        assert_eq!(harness.check_in(1), Ok(()));
+    }
+
+    assert!(harness.faulted(0));
+    assert!(!harness.faulted(1));
+    // The faulted process is no longer monitored, the other one still is.
+    assert_eq!(harness.alarm.remaining(), Some(100));
+    // Faulting a process leaves the hardware watchdog alone.
+    assert!(harness.gate.is_open());
+    assert!(!harness.hardware.expired.get());
+}
+
+#[test]
+fn every_missed_heartbeat_is_handled_when_the_alarm_fires() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let harness = Harness::new(
+        &[
+            ("first", CRITICAL),
+            ("second", CRITICAL),
+            ("third", CRITICAL),
+        ],
+        MissedHeartbeatAction::RestartProcess,
+    );
+
+    assert_eq!(harness.register(0, 100), Ok(()));
+    assert_eq!(harness.register(1, 100), Ok(()));
+    assert_eq!(harness.register(2, 400), Ok(()));
+    // Both deadlines pass before the alarm gets to fire.
+    harness.alarm.advance(150);
+    harness.watchdog.alarm();
+
+    assert!(harness.faulted(0));
+    assert!(harness.faulted(1));
+    assert!(!harness.faulted(2));
+    assert_eq!(harness.alarm.remaining(), Some(250));
+}
+
+#[test]
+fn missed_heartbeat_resets_the_board() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let harness = Harness::new(&[("critical", CRITICAL)], MissedHeartbeatAction::ResetBoard);
+
+    assert_eq!(harness.register(0, 100), Ok(()));
+    harness.run(95);
+    assert!(harness.gate.is_open());
+    assert!(!harness.hardware.expired.get());
+
+    // The kernel keeps tickling the watchdog, but once the deadline passes
+    // the ticks no longer reach the hardware.
+    harness.run(10);
+    assert!(!harness.gate.is_open());
+    assert!(!harness.faulted(0));
+    harness.run(50);
+    assert!(harness.hardware.expired.get());
+}
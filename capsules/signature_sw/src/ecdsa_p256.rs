@@ -0,0 +1,215 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Software implementation of ECDSA signature verification over the NIST
+//! P-256 curve.
+//!
+//! Public keys are SEC1 encoded points, either uncompressed (65 bytes) or
+//! compressed (33 bytes). Signatures are the 32-byte big-endian `r` value
+//! followed by the 32-byte big-endian `s` value, and are verified over a
+//! SHA-256 hash.
+//!
+//! The verification is computed synchronously in `verify()`; the result is
+//! delivered from a deferred call.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let verifier = static_init!(
+//!     capsules_signature_sw::ecdsa_p256::EcdsaP256SignatureVerifier<'static>,
+//!     capsules_signature_sw::ecdsa_p256::EcdsaP256SignatureVerifier::new()
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(verifier);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::public_key_crypto::signature::{ClientVerify, SignatureVerify};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+
+pub struct EcdsaP256SignatureVerifier<'a> {
+    verified: Cell<bool>,
+    client: OptionalCell<&'a dyn ClientVerify<32, 64>>,
+    hash: TakeCell<'static, [u8; 32]>,
+    signature: TakeCell<'static, [u8; 64]>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> EcdsaP256SignatureVerifier<'a> {
+    pub fn new() -> EcdsaP256SignatureVerifier<'a> {
+        EcdsaP256SignatureVerifier {
+            verified: Cell::new(false),
+            client: OptionalCell::empty(),
+            hash: TakeCell::empty(),
+            signature: TakeCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+}
+
+impl<'a> SignatureVerify<'a, 32, 64> for EcdsaP256SignatureVerifier<'a> {
+    fn set_verify_client(&self, client: &'a dyn ClientVerify<32, 64>) {
+        self.client.replace(client);
+    }
+
+    fn verify(
+        &self,
+        public_key: &'static [u8],
+        hash: &'static mut [u8; 32],
+        signature: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 32], &'static mut [u8; 64])> {
+        if self.hash.is_some() {
+            return Err((ErrorCode::BUSY, hash, signature));
+        }
+        let key = match VerifyingKey::from_sec1_bytes(public_key) {
+            Ok(key) => key,
+            Err(_) => return Err((ErrorCode::INVAL, hash, signature)),
+        };
+
+        // A malformed signature is not valid for any hash.
+        let verified = Signature::from_slice(&signature[..])
+            .map_or(false, |sig| key.verify_prehash(&hash[..], &sig).is_ok());
+        self.verified.set(verified);
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl<'a> DeferredCallClient for EcdsaP256SignatureVerifier<'a> {
+    fn handle_deferred_call(&self) {
+        if let (Some(hash), Some(signature)) = (self.hash.take(), self.signature.take()) {
+            self.client
+                .map(|client| client.verification_done(Ok(self.verified.get()), hash, signature));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::boxed::Box;
+
+    struct Client(Cell<Option<Result<bool, ErrorCode>>>);
+
+    impl ClientVerify<32, 64> for Client {
+        fn verification_done(
+            &self,
+            result: Result<bool, ErrorCode>,
+            _hash: &'static mut [u8; 32],
+            _signature: &'static mut [u8; 64],
+        ) {
+            self.0.set(Some(result));
+        }
+    }
+
+    /// Verify `signature` over `hash` and return the result passed to the
+    /// client.
+    fn verify(
+        public_key: &'static [u8],
+        hash: [u8; 32],
+        signature: [u8; 64],
+    ) -> Result<bool, ErrorCode> {
+        let client = Box::leak(Box::new(Client(Cell::new(None))));
+        let verifier = Box::leak(Box::new(EcdsaP256SignatureVerifier::new()));
+        verifier.set_verify_client(client);
+        verifier
+            .verify(
+                public_key,
+                Box::leak(Box::new(hash)),
+                Box::leak(Box::new(signature)),
+            )
+            .map_err(|(e, _, _)| e)?;
+        verifier.handle_deferred_call();
+        client.0.get().unwrap()
+    }
+
+    /// RFC 6979 appendix A.2.5, uncompressed public key.
+    const PUBLIC_KEY: [u8; 65] = [
+        0x04, 0x60, 0xfe, 0xd4, 0xba, 0x25, 0x5a, 0x9d, 0x31, 0xc9, 0x61, 0xeb, 0x74, 0xc6, 0x35,
+        0x6d, 0x68, 0xc0, 0x49, 0xb8, 0x92, 0x3b, 0x61, 0xfa, 0x6c, 0xe6, 0x69, 0x62, 0x2e, 0x60,
+        0xf2, 0x9f, 0xb6, 0x79, 0x03, 0xfe, 0x10, 0x08, 0xb8, 0xbc, 0x99, 0xa4, 0x1a, 0xe9, 0xe9,
+        0x56, 0x28, 0xbc, 0x64, 0xf2, 0xf1, 0xb2, 0x0c, 0x2d, 0x7e, 0x9f, 0x51, 0x77, 0xa3, 0xc2,
+        0x94, 0xd4, 0x46, 0x22, 0x99,
+    ];
+
+    /// SHA-256("sample").
+    const HASH: [u8; 32] = [
+        0xaf, 0x2b, 0xdb, 0xe1, 0xaa, 0x9b, 0x6e, 0xc1, 0xe2, 0xad, 0xe1, 0xd6, 0x94, 0xf4, 0x1f,
+        0xc7, 0x1a, 0x83, 0x1d, 0x02, 0x68, 0xe9, 0x89, 0x15, 0x62, 0x11, 0x3d, 0x8a, 0x62, 0xad,
+        0xd1, 0xbf,
+    ];
+
+    /// RFC 6979 appendix A.2.5, signature of "sample" with SHA-256.
+    const SIGNATURE: [u8; 64] = [
+        0xef, 0xd4, 0x8b, 0x2a, 0xac, 0xb6, 0xa8, 0xfd, 0x11, 0x40, 0xdd, 0x9c, 0xd4, 0x5e, 0x81,
+        0xd6, 0x9d, 0x2c, 0x87, 0x7b, 0x56, 0xaa, 0xf9, 0x91, 0xc3, 0x4d, 0x0e, 0xa8, 0x4e, 0xaf,
+        0x37, 0x16, 0xf7, 0xcb, 0x1c, 0x94, 0x2d, 0x65, 0x7c, 0x41, 0xd4, 0x36, 0xc7, 0xa1, 0xb6,
+        0xe2, 0x9f, 0x65, 0xf3, 0xe9, 0x00, 0xdb, 0xb9, 0xaf, 0xf4, 0x06, 0x4d, 0xc4, 0xab, 0x2f,
+        0x84, 0x3a, 0xcd, 0xa8,
+    ];
+
+    #[test]
+    fn rfc6979_sample() {
+        assert_eq!(verify(&PUBLIC_KEY, HASH, SIGNATURE), Ok(true));
+    }
+
+    #[test]
+    fn compressed_key() {
+        static COMPRESSED: [u8; 33] = {
+            let mut key = [0; 33];
+            // The Y coordinate of the key is odd.
+            key[0] = 0x03;
+            let mut i = 0;
+            while i < 32 {
+                key[i + 1] = PUBLIC_KEY[i + 1];
+                i += 1;
+            }
+            key
+        };
+        assert_eq!(verify(&COMPRESSED, HASH, SIGNATURE), Ok(true));
+    }
+
+    #[test]
+    fn tampered_signature() {
+        let mut signature = SIGNATURE;
+        signature[40] ^= 0x01;
+        assert_eq!(verify(&PUBLIC_KEY, HASH, signature), Ok(false));
+    }
+
+    #[test]
+    fn tampered_hash() {
+        let mut hash = HASH;
+        hash[0] ^= 0x01;
+        assert_eq!(verify(&PUBLIC_KEY, hash, SIGNATURE), Ok(false));
+    }
+
+    #[test]
+    fn malformed_signature() {
+        // r = 0 is outside the valid range.
+        let mut signature = SIGNATURE;
+        signature[..32].fill(0);
+        assert_eq!(verify(&PUBLIC_KEY, HASH, signature), Ok(false));
+    }
+
+    #[test]
+    fn invalid_key() {
+        assert_eq!(
+            verify(&PUBLIC_KEY[..64], HASH, SIGNATURE),
+            Err(ErrorCode::INVAL)
+        );
+    }
+}
@@ -0,0 +1,208 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Software implementation of Ed25519 signature verification.
+//!
+//! Public keys are 32 bytes and signatures 64 bytes as defined in RFC 8032.
+//! Ed25519 credentials in TBF footers sign the SHA-256 hash of the
+//! application binary: the signature is the PureEdDSA Ed25519 signature
+//! (RFC 8032, section 5.1) with the 32-byte hash passed to `verify()` as the
+//! message, i.e. `Ed25519-Sign(key, SHA-256(binary))`. This is not
+//! Ed25519ph, which hashes the message with SHA-512 and signs it with a
+//! domain separation prefix, so Ed25519ph signatures do not verify.
+//!
+//! The verification is computed synchronously in `verify()`; the result is
+//! delivered from a deferred call.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let verifier = static_init!(
+//!     capsules_signature_sw::ed25519::Ed25519SignatureVerifier<'static>,
+//!     capsules_signature_sw::ed25519::Ed25519SignatureVerifier::new()
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(verifier);
+//! ```
+
+use core::cell::Cell;
+
+use ed25519_compact::{PublicKey, Signature};
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::public_key_crypto::signature::{ClientVerify, SignatureVerify};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub struct Ed25519SignatureVerifier<'a> {
+    verified: Cell<bool>,
+    client: OptionalCell<&'a dyn ClientVerify<32, 64>>,
+    hash: TakeCell<'static, [u8; 32]>,
+    signature: TakeCell<'static, [u8; 64]>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> Ed25519SignatureVerifier<'a> {
+    pub fn new() -> Ed25519SignatureVerifier<'a> {
+        Ed25519SignatureVerifier {
+            verified: Cell::new(false),
+            client: OptionalCell::empty(),
+            hash: TakeCell::empty(),
+            signature: TakeCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+}
+
+impl<'a> SignatureVerify<'a, 32, 64> for Ed25519SignatureVerifier<'a> {
+    fn set_verify_client(&self, client: &'a dyn ClientVerify<32, 64>) {
+        self.client.replace(client);
+    }
+
+    fn verify(
+        &self,
+        public_key: &'static [u8],
+        hash: &'static mut [u8; 32],
+        signature: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 32], &'static mut [u8; 64])> {
+        if self.hash.is_some() {
+            return Err((ErrorCode::BUSY, hash, signature));
+        }
+        let key = match PublicKey::from_slice(public_key) {
+            Ok(key) => key,
+            Err(_) => return Err((ErrorCode::INVAL, hash, signature)),
+        };
+
+        let verified = key.verify(&hash[..], &Signature::new(*signature)).is_ok();
+        self.verified.set(verified);
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl<'a> DeferredCallClient for Ed25519SignatureVerifier<'a> {
+    fn handle_deferred_call(&self) {
+        if let (Some(hash), Some(signature)) = (self.hash.take(), self.signature.take()) {
+            self.client
+                .map(|client| client.verification_done(Ok(self.verified.get()), hash, signature));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::boxed::Box;
+
+    struct Client(Cell<Option<Result<bool, ErrorCode>>>);
+
+    impl ClientVerify<32, 64> for Client {
+        fn verification_done(
+            &self,
+            result: Result<bool, ErrorCode>,
+            _hash: &'static mut [u8; 32],
+            _signature: &'static mut [u8; 64],
+        ) {
+            self.0.set(Some(result));
+        }
+    }
+
+    /// Verify `signature` over `hash` and return the result passed to the
+    /// client.
+    fn verify(
+        public_key: &'static [u8],
+        hash: [u8; 32],
+        signature: [u8; 64],
+    ) -> Result<bool, ErrorCode> {
+        let client = Box::leak(Box::new(Client(Cell::new(None))));
+        let verifier = Box::leak(Box::new(Ed25519SignatureVerifier::new()));
+        verifier.set_verify_client(client);
+        verifier
+            .verify(
+                public_key,
+                Box::leak(Box::new(hash)),
+                Box::leak(Box::new(signature)),
+            )
+            .map_err(|(e, _, _)| e)?;
+        verifier.handle_deferred_call();
+        client.0.get().unwrap()
+    }
+
+    /// RFC 8032 section 7.1, TEST 1.
+    const PUBLIC_KEY: [u8; 32] = [
+        0xd7, 0x5a, 0x98, 0x01, 0x82, 0xb1, 0x0a, 0xb7, 0xd5, 0x4b, 0xfe, 0xd3, 0xc9, 0x64, 0x07,
+        0x3a, 0x0e, 0xe1, 0x72, 0xf3, 0xda, 0xa6, 0x23, 0x25, 0xaf, 0x02, 0x1a, 0x68, 0xf7, 0x07,
+        0x51, 0x1a,
+    ];
+
+    /// SHA-256("abc").
+    const HASH: [u8; 32] = [
+        0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22,
+        0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00,
+        0x15, 0xad,
+    ];
+
+    /// Ed25519 signature of `HASH` as the message with the TEST 1 key.
+    const SIGNATURE: [u8; 64] = [
+        0x09, 0x6f, 0x55, 0x69, 0xd8, 0x07, 0xee, 0x8a, 0xc7, 0xb1, 0x91, 0x3d, 0xa7, 0x0c, 0xf0,
+        0xaa, 0xb3, 0x35, 0xc2, 0x58, 0xf4, 0xb9, 0x4c, 0x8f, 0x21, 0x0d, 0xd1, 0x41, 0xe9, 0x74,
+        0x39, 0x27, 0xc8, 0xd1, 0xa6, 0xb3, 0x78, 0x87, 0x2a, 0x72, 0xc9, 0x44, 0x6c, 0x1f, 0x75,
+        0xe6, 0xdc, 0x7b, 0x2d, 0xef, 0x98, 0xbd, 0x0c, 0x21, 0x4b, 0xe6, 0x70, 0x6d, 0x48, 0x79,
+        0x1f, 0x57, 0x68, 0x0a,
+    ];
+
+    #[test]
+    fn rfc8032_test_2() {
+        // Pins the algorithm to PureEdDSA as used by `verify()`.
+        const PUBLIC_KEY: [u8; 32] = [
+            0x3d, 0x40, 0x17, 0xc3, 0xe8, 0x43, 0x89, 0x5a, 0x92, 0xb7, 0x0a, 0xa7, 0x4d, 0x1b,
+            0x7e, 0xbc, 0x9c, 0x98, 0x2c, 0xcf, 0x2e, 0xc4, 0x96, 0x8c, 0xc0, 0xcd, 0x55, 0xf1,
+            0x2a, 0xf4, 0x66, 0x0c,
+        ];
+        const SIGNATURE: [u8; 64] = [
+            0x92, 0xa0, 0x09, 0xa9, 0xf0, 0xd4, 0xca, 0xb8, 0x72, 0x0e, 0x82, 0x0b, 0x5f, 0x64,
+            0x25, 0x40, 0xa2, 0xb2, 0x7b, 0x54, 0x16, 0x50, 0x3f, 0x8f, 0xb3, 0x76, 0x22, 0x23,
+            0xeb, 0xdb, 0x69, 0xda, 0x08, 0x5a, 0xc1, 0xe4, 0x3e, 0x15, 0x99, 0x6e, 0x45, 0x8f,
+            0x36, 0x13, 0xd0, 0xf1, 0x1d, 0x8c, 0x38, 0x7b, 0x2e, 0xae, 0xb4, 0x30, 0x2a, 0xee,
+            0xb0, 0x0d, 0x29, 0x16, 0x12, 0xbb, 0x0c, 0x00,
+        ];
+        let key = PublicKey::from_slice(&PUBLIC_KEY).unwrap();
+        assert!(key.verify([0x72], &Signature::new(SIGNATURE)).is_ok());
+        assert!(key.verify([0x73], &Signature::new(SIGNATURE)).is_err());
+    }
+
+    #[test]
+    fn signed_hash() {
+        assert_eq!(verify(&PUBLIC_KEY, HASH, SIGNATURE), Ok(true));
+    }
+
+    #[test]
+    fn tampered_signature() {
+        let mut signature = SIGNATURE;
+        signature[10] ^= 0x01;
+        assert_eq!(verify(&PUBLIC_KEY, HASH, signature), Ok(false));
+    }
+
+    #[test]
+    fn tampered_hash() {
+        let mut hash = HASH;
+        hash[31] ^= 0x80;
+        assert_eq!(verify(&PUBLIC_KEY, hash, SIGNATURE), Ok(false));
+    }
+
+    #[test]
+    fn invalid_key() {
+        assert_eq!(
+            verify(&PUBLIC_KEY[..31], HASH, SIGNATURE),
+            Err(ErrorCode::INVAL)
+        );
+    }
+}
@@ -0,0 +1,9 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+#![forbid(unsafe_code)]
+#![no_std]
+
+pub mod ecdsa_p256;
+pub mod ed25519;
@@ -4,6 +4,7 @@
 
 use kernel::ErrorCode;
 
+pub mod virtio_console;
 pub mod virtio_net;
 pub mod virtio_rng;
 
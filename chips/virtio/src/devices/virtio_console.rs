@@ -0,0 +1,229 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! VirtIO console device driver.
+//!
+//! Exposes the first port of a VirtIO console as a UART, for example to give
+//! a serial link its own QEMU character device instead of sharing the board's
+//! UART. The multiport feature is not negotiated, so the device only has the
+//! receive queue 0 and the transmit queue 1.
+//!
+//! The device can return fewer bytes than a receive asks for. The driver
+//! keeps offering the rest of the receive to the device until `rx_len` bytes
+//! arrived, so it never holds more bytes than the client asked for.
+
+use core::cell::Cell;
+
+use kernel::hil::uart;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use super::super::devices::{VirtIODeviceDriver, VirtIODeviceType};
+use super::super::queues::split_queue::{SplitVirtqueue, SplitVirtqueueClient, VirtqueueBuffer};
+
+pub struct VirtIOConsole<'a> {
+    rxqueue: &'a SplitVirtqueue<'static, 'static, 1>,
+    txqueue: &'a SplitVirtqueue<'static, 'static, 1>,
+    /// Buffer the device writes received bytes into. It is `None` while the
+    /// device holds it.
+    rx_staging: TakeCell<'static, [u8]>,
+    /// The client's receive buffer, the number of bytes it asked for and the
+    /// number of bytes received so far.
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    rx_received: Cell<usize>,
+    rx_aborted: Cell<bool>,
+    /// Length of the transmission the device holds, if any.
+    tx_len: OptionalCell<usize>,
+    rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+}
+
+impl<'a> VirtIOConsole<'a> {
+    /// `rx_staging` limits how many bytes the device returns at once.
+    pub fn new(
+        rxqueue: &'a SplitVirtqueue<'static, 'static, 1>,
+        txqueue: &'a SplitVirtqueue<'static, 'static, 1>,
+        rx_staging: &'static mut [u8],
+    ) -> VirtIOConsole<'a> {
+        rxqueue.enable_used_callbacks();
+        txqueue.enable_used_callbacks();
+
+        VirtIOConsole {
+            rxqueue,
+            txqueue,
+            rx_staging: TakeCell::new(rx_staging),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_received: Cell::new(0),
+            rx_aborted: Cell::new(false),
+            tx_len: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            tx_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Offer the staging buffer to the device for the rest of the current
+    /// receive.
+    fn provide_rx(&self) -> Result<(), ErrorCode> {
+        let staging = self.rx_staging.take().ok_or(ErrorCode::BUSY)?;
+        let remaining = self.rx_len.get() - self.rx_received.get();
+        let len = core::cmp::min(staging.len(), remaining);
+        let mut buffer_chain = [Some(VirtqueueBuffer {
+            buf: staging,
+            len,
+            device_writeable: true,
+        })];
+        self.rxqueue
+            .provide_buffer_chain(&mut buffer_chain)
+            .map_err(|e| {
+                self.rx_staging.replace(buffer_chain[0].take().unwrap().buf);
+                e
+            })
+    }
+
+    fn receive_done(&self, rval: Result<(), ErrorCode>) {
+        self.rx_aborted.set(false);
+        if let Some(buffer) = self.rx_buffer.take() {
+            let received = self.rx_received.get();
+            self.rx_client.map(move |client| {
+                client.received_buffer(buffer, received, rval, uart::Error::None)
+            });
+        }
+    }
+}
+
+impl<'a> uart::Transmit<'a> for VirtIOConsole<'a> {
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if tx_len == 0 || tx_len > tx_buffer.len() {
+            return Err((ErrorCode::SIZE, tx_buffer));
+        }
+        if self.tx_len.is_some() {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+        let mut buffer_chain = [Some(VirtqueueBuffer {
+            buf: tx_buffer,
+            len: tx_len,
+            device_writeable: false,
+        })];
+        self.txqueue
+            .provide_buffer_chain(&mut buffer_chain)
+            .map_err(|e| (e, buffer_chain[0].take().unwrap().buf))?;
+        self.tx_len.set(tx_len);
+        Ok(())
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        // A buffer handed to the device cannot be taken back; the callback
+        // follows once the device has sent it.
+        if self.tx_len.is_some() {
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> uart::Receive<'a> for VirtIOConsole<'a> {
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if rx_len == 0 || rx_len > rx_buffer.len() {
+            return Err((ErrorCode::SIZE, rx_buffer));
+        }
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+        self.rx_len.set(rx_len);
+        self.rx_received.set(0);
+        self.rx_buffer.replace(rx_buffer);
+        self.provide_rx()
+            .map_err(|e| (e, self.rx_buffer.take().unwrap()))
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        // The device keeps the staging buffer until the next bytes arrive,
+        // so the receive ends with a `CANCEL` callback at that point.
+        if self.rx_buffer.is_some() {
+            self.rx_aborted.set(true);
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> SplitVirtqueueClient<'static> for VirtIOConsole<'a> {
+    fn buffer_chain_ready(
+        &self,
+        queue_number: u32,
+        buffer_chain: &mut [Option<VirtqueueBuffer<'static>>],
+        bytes_used: usize,
+    ) {
+        if queue_number == self.rxqueue.queue_number().unwrap() {
+            let staging = buffer_chain[0].take().expect("No rx buffer").buf;
+            let received = self.rx_received.get();
+            let copied = self.rx_buffer.map_or(0, |buffer| {
+                let len = core::cmp::min(bytes_used, self.rx_len.get() - received);
+                buffer[received..received + len].copy_from_slice(&staging[..len]);
+                len
+            });
+            self.rx_staging.replace(staging);
+            self.rx_received.set(received + copied);
+
+            if self.rx_aborted.get() {
+                self.receive_done(Err(ErrorCode::CANCEL));
+            } else if self.rx_buffer.is_some() {
+                if self.rx_received.get() == self.rx_len.get() {
+                    self.receive_done(Ok(()));
+                } else if let Err(e) = self.provide_rx() {
+                    self.receive_done(Err(e));
+                }
+            }
+        } else if queue_number == self.txqueue.queue_number().unwrap() {
+            // The device does not report how many bytes it read from a
+            // buffer it cannot write, so `bytes_used` is not the length.
+            let tx_buffer = buffer_chain[0].take().expect("No tx buffer").buf;
+            let tx_len = self.tx_len.take().unwrap_or(0);
+            self.tx_client
+                .map(move |client| client.transmitted_buffer(tx_buffer, tx_len, Ok(())));
+        } else {
+            panic!("Callback from unknown queue");
+        }
+    }
+}
+
+impl<'a> VirtIODeviceDriver for VirtIOConsole<'a> {
+    fn negotiate_features(&self, _offered_features: u64) -> Option<u64> {
+        // None of the console features (size, multiport, emergency write)
+        // are needed for a single port.
+        Some(0)
+    }
+
+    fn device_type(&self) -> VirtIODeviceType {
+        VirtIODeviceType::Console
+    }
+}
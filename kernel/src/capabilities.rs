@@ -59,6 +59,27 @@ pub unsafe trait ProcessManagementCapability {}
 /// credentials of a process, indicating they have permission to be run.
 pub unsafe trait ProcessApprovalCapability {}
 
+/// The `ProcessDebugCapability` allows the holder to inspect and modify a
+/// running process for debugging: reading and writing its memory and
+/// registers, and changing its scheduling priority and fault policy.
+pub unsafe trait ProcessDebugCapability {}
+
+/// The `ProcessUninstallCapability` allows the holder to permanently remove a
+/// process binary from flash and reclaim the resources of its process. This
+/// is separate from `ProcessManagementCapability` because an uninstalled
+/// process does not come back after a reboot.
+pub unsafe trait ProcessUninstallCapability {}
+
+/// The `ProcessRollbackResetCapability` allows the holder to reset the
+/// highest version of an application that rollback protection has recorded,
+/// which lets older versions of that application run again.
+pub unsafe trait ProcessRollbackResetCapability {}
+
+/// The `ProcessCheckpointCapability` allows the holder to save the state of a
+/// stopped process to flash and to resume processes from those snapshots
+/// after a reboot instead of starting them from their init function.
+pub unsafe trait ProcessCheckpointCapability {}
+
 /// The `ProcessInitCapability` allows the holder to start a process
 /// to run by pushing an init function stack frame. This is controlled
 /// and separate from `ProcessManagementCapability` because the process
@@ -31,4 +31,10 @@ pub trait Queue<T> {
     fn retain<F>(&mut self, f: F)
     where
         F: FnMut(&T) -> bool;
+
+    /// Removes the first element that satisfies the predicate and returns
+    /// it. The order of the remaining elements is preserved.
+    fn remove_first_matching<F>(&mut self, f: F) -> Option<T>
+    where
+        F: Fn(&T) -> bool;
 }
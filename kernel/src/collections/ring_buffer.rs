@@ -37,7 +37,7 @@ impl<'a, T: Copy> RingBuffer<'a, T> {
     /// - `(Some(left), Some(right))` if the head is after the tail. In that case, the logical
     /// contents of the buffer is `[left, right].concat()` (although physically the "left" slice is
     /// stored after the "right" slice).
-    pub fn as_slices(&'a self) -> (Option<&'a [T]>, Option<&'a [T]>) {
+    pub fn as_slices(&self) -> (Option<&[T]>, Option<&[T]>) {
         if self.head < self.tail {
             (Some(&self.ring[self.head..self.tail]), None)
         } else if self.head > self.tail {
@@ -140,6 +140,31 @@ impl<T: Copy> queue::Queue<T> for RingBuffer<'_, T> {
 
         self.tail = dst;
     }
+
+    fn remove_first_matching<F>(&mut self, f: F) -> Option<T>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let len = self.ring.len();
+        let mut slot = self.head;
+        while slot != self.tail {
+            if f(&self.ring[slot]) {
+                let val = self.ring[slot];
+                // Shift the following elements forward by one to close the
+                // gap.
+                let mut next = (slot + 1) % len;
+                while next != self.tail {
+                    self.ring[slot] = self.ring[next];
+                    slot = next;
+                    next = (next + 1) % len;
+                }
+                self.tail = slot;
+                return Some(val);
+            }
+            slot = (slot + 1) % len;
+        }
+        None
+    }
 }
 
 #[cfg(test)]
@@ -284,4 +309,44 @@ mod test {
         assert_eq!(buf.dequeue(), Some(9));
         assert_eq!(buf.dequeue(), None);
     }
+
+    #[test]
+    fn test_remove_first_matching() {
+        const LEN: usize = 10;
+        let mut ring = [0; LEN];
+        let mut buf = RingBuffer::new(&mut ring);
+
+        move_head(&mut buf, LEN - 2);
+        enqueue_iota(&mut buf, LEN);
+
+        // Only the first match is removed, and the others keep their order.
+        assert_eq!(buf.remove_first_matching(|x| x % 3 == 0), Some(3));
+        assert_eq!(buf.len(), LEN - 2);
+        assert_eq!(buf.remove_first_matching(|x| *x == 1), Some(1));
+        assert_eq!(buf.remove_first_matching(|x| *x == 9), Some(9));
+        assert_eq!(buf.remove_first_matching(|x| *x == 9), None);
+        assert_eq!(buf.len(), LEN - 4);
+
+        // Elements can be enqueued into the space that was freed.
+        assert!(buf.enqueue(10));
+        assert!(buf.enqueue(11));
+        assert!(buf.enqueue(12));
+        assert!(buf.is_full());
+
+        for i in [2, 4, 5, 6, 7, 8, 10, 11, 12] {
+            assert_eq!(buf.dequeue(), Some(i));
+        }
+        assert_eq!(buf.dequeue(), None);
+    }
+
+    #[test]
+    fn test_remove_first_matching_empty() {
+        let mut ring = [0; 4];
+        let mut buf = RingBuffer::new(&mut ring);
+
+        assert_eq!(buf.remove_first_matching(|_| true), None);
+        assert!(buf.enqueue(1));
+        assert_eq!(buf.remove_first_matching(|_| true), Some(1));
+        assert!(!buf.has_elements());
+    }
 }
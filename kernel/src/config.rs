@@ -41,6 +41,10 @@ pub(crate) struct Config {
     /// If enabled, the kernel will print a message in the debug output for each
     /// system call and upcall, with details including the application ID, and
     /// system call or upcall parameters.
+    ///
+    /// Printing every system call is slow and changes the timing of the
+    /// system. For a compact binary trace that can be enabled at runtime, see
+    /// [`crate::syscall_trace`].
     pub(crate) trace_syscalls: bool,
 
     /// Whether the kernel should show debugging output when loading processes.
@@ -80,6 +84,29 @@ pub(crate) struct Config {
     // credentials checking, e.g., whether elf2tab and tockloader are generating
     // properly formatted footers.
     pub(crate) debug_process_credentials: bool,
+
+    /// Whether the kernel enforces sandbox profiles.
+    ///
+    /// If disabled, `Kernel::set_sandbox_policy()` does not exist, so a board
+    /// that sets a policy fails to build instead of running its processes
+    /// unsandboxed.
+    // The sandbox check runs on every system call and every queued upcall, so
+    // boards that do not sandbox processes should not pay for it.
+    pub(crate) sandbox: bool,
+
+    /// Whether the kernel reports events to the binary syscall tracer.
+    ///
+    /// If disabled, a tracer set with `Kernel::set_syscall_tracer()` never
+    /// receives events. This is unrelated to `trace_syscalls`, which prints
+    /// every system call to the debug output.
+    pub(crate) syscall_trace: bool,
+
+    /// Whether the kernel can checkpoint processes and resume them from a
+    /// snapshot after a reboot.
+    ///
+    /// If disabled, a mechanism set with `Kernel::set_process_restore()` is
+    /// never called and processes cannot be checkpointed.
+    pub(crate) process_checkpoint: bool,
 }
 
 /// A unique instance of `Config` where compile-time configuration options are
@@ -92,4 +119,7 @@ pub(crate) const CONFIG: Config = Config {
     debug_load_processes: cfg!(feature = "debug_load_processes"),
     debug_panics: !cfg!(feature = "no_debug_panics"),
     debug_process_credentials: cfg!(feature = "debug_process_credentials"),
+    sandbox: cfg!(feature = "sandbox"),
+    syscall_trace: cfg!(feature = "syscall_trace"),
+    process_checkpoint: cfg!(feature = "process_checkpoint"),
 };
@@ -0,0 +1,427 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Records of kernel panics and process faults that survive a reboot.
+//!
+//! Panic output normally only goes to a UART, so a board that panics in the
+//! field loses it when it resets. A [`CrashRecord`] captures the essentials
+//! of a panic or fault in a compact, fixed-size form: a message, the faulting
+//! process (if any) with its PC, LR and SP, and a snippet of its stack.
+//!
+//! A board registers a [`CrashRecorder`] with
+//! [`Kernel::set_crash_recorder`](crate::Kernel::set_crash_recorder), which
+//! persists a record (for example to a reserved flash page) whenever a
+//! process faults, before the fault policy restarts or stops the process or
+//! panics. In its panic handler, the board passes the recorder to
+//! [`debug::panic_record_crash`](crate::debug::panic_record_crash), which
+//! records panics that are not caused by a process fault. After the board
+//! reboots, the recorder makes the most recent record available through
+//! [`CrashReader`], which the process console and a syscall driver use to
+//! report it.
+//!
+//! Encoded record format
+//! ---------------------
+//!
+//! Records are [`RECORD_LEN`] bytes, with multi-byte fields little endian:
+//!
+//! | Offset | Length | Field                                              |
+//! |--------|--------|----------------------------------------------------|
+//! | 0      | 4      | Magic, `TKCR`                                      |
+//! | 4      | 1      | Format version, currently 1                        |
+//! | 5      | 1      | Flags: bit 0 set if the registers are valid        |
+//! | 6      | 2      | Reserved                                           |
+//! | 8      | 4      | Number of crashes recorded, including this one     |
+//! | 12     | 4      | PC of the faulting process                         |
+//! | 16     | 4      | LR of the faulting process                         |
+//! | 20     | 4      | SP of the faulting process                         |
+//! | 24     | 1      | Process name length                                |
+//! | 25     | 1      | Message length                                     |
+//! | 26     | 1      | Stack snippet length                               |
+//! | 27     | 1      | Reserved                                           |
+//! | 28     | 16     | Process name                                       |
+//! | 44     | 128    | Panic message                                      |
+//! | 172    | 64     | Stack snippet, starting at SP                      |
+//! | 236    | 4      | FNV-1a checksum of the preceding bytes             |
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::str;
+
+use crate::process::Process;
+use crate::process_checkpoint::{fnv1a, FNV_OFFSET_BASIS};
+use crate::syscall::ProcessRegisters;
+use crate::ErrorCode;
+
+/// Maximum length of the recorded panic message, in bytes.
+pub const MAX_MESSAGE_LEN: usize = 128;
+/// Maximum length of the recorded process name, in bytes.
+pub const MAX_NAME_LEN: usize = 16;
+/// Maximum length of the recorded stack snippet, in bytes.
+pub const MAX_STACK_LEN: usize = 64;
+
+const MAGIC: [u8; 4] = *b"TKCR";
+const FORMAT_VERSION: u8 = 1;
+const FLAG_REGISTERS: u8 = 1 << 0;
+
+const NAME_OFFSET: usize = 28;
+const MESSAGE_OFFSET: usize = NAME_OFFSET + MAX_NAME_LEN;
+const STACK_OFFSET: usize = MESSAGE_OFFSET + MAX_MESSAGE_LEN;
+const CHECKSUM_OFFSET: usize = STACK_OFFSET + MAX_STACK_LEN;
+
+/// Length of an encoded crash record, in bytes.
+pub const RECORD_LEN: usize = CHECKSUM_OFFSET + 4;
+
+/// A compact description of a kernel panic or process fault.
+#[derive(Copy, Clone)]
+pub struct CrashRecord {
+    count: u32,
+    registers: Option<ProcessRegisters>,
+    process_name: [u8; MAX_NAME_LEN],
+    process_name_len: usize,
+    message: [u8; MAX_MESSAGE_LEN],
+    message_len: usize,
+    stack: [u8; MAX_STACK_LEN],
+    stack_len: usize,
+}
+
+/// Writes formatted text into a fixed buffer, dropping whatever does not
+/// fit.
+struct TruncatingWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for TruncatingWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = core::cmp::min(s.len(), self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// Interpret `bytes` as UTF-8, cutting it off before the first invalid
+/// sequence (such as a character truncated when it was recorded).
+fn utf8_prefix(bytes: &[u8]) -> &str {
+    match str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or(""),
+    }
+}
+
+impl CrashRecord {
+    /// A record with `message` and no faulting process.
+    fn with_message(message: fmt::Arguments) -> CrashRecord {
+        let mut record = CrashRecord {
+            count: 0,
+            registers: None,
+            process_name: [0; MAX_NAME_LEN],
+            process_name_len: 0,
+            message: [0; MAX_MESSAGE_LEN],
+            message_len: 0,
+            stack: [0; MAX_STACK_LEN],
+            stack_len: 0,
+        };
+        let mut writer = TruncatingWriter {
+            buf: &mut record.message,
+            len: 0,
+        };
+        let _ = writer.write_fmt(message);
+        record.message_len = writer.len;
+        record
+    }
+
+    /// Record `name`, `registers` and the stack snippet `stack` copies into
+    /// the buffer it is passed as the faulting process.
+    fn set_process(
+        &mut self,
+        name: &str,
+        registers: Option<ProcessRegisters>,
+        stack: impl FnOnce(&mut [u8]) -> usize,
+    ) {
+        let name = name.as_bytes();
+        let len = core::cmp::min(name.len(), MAX_NAME_LEN);
+        self.process_name[..len].copy_from_slice(&name[..len]);
+        self.process_name_len = len;
+        self.registers = registers;
+        self.stack_len = core::cmp::min(stack(&mut self.stack), MAX_STACK_LEN);
+    }
+
+    /// Describe the kernel panic in `panic_info`.
+    pub fn from_panic(panic_info: &PanicInfo) -> CrashRecord {
+        CrashRecord::with_message(format_args!("{}", panic_info))
+    }
+
+    /// Describe a fault of `process`, which must not have been restarted or
+    /// terminated since it faulted.
+    pub fn from_fault(process: &dyn Process) -> CrashRecord {
+        let mut record = CrashRecord::with_message(format_args!(
+            "Process {} had a fault",
+            process.get_process_name()
+        ));
+        record.set_process(
+            process.get_process_name(),
+            process.get_stored_registers(),
+            |stack| process.get_stack_snippet(stack),
+        );
+        record
+    }
+
+    /// How many crashes have been recorded, including this one.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Set how many crashes have been recorded, including this one.
+    pub fn set_count(&mut self, count: u32) {
+        self.count = count;
+    }
+
+    /// The panic or fault message, possibly truncated.
+    pub fn message(&self) -> &str {
+        utf8_prefix(&self.message[..self.message_len])
+    }
+
+    /// The name of the faulting process, if a process had faulted.
+    pub fn process_name(&self) -> Option<&str> {
+        if self.process_name_len == 0 {
+            None
+        } else {
+            Some(utf8_prefix(&self.process_name[..self.process_name_len]))
+        }
+    }
+
+    /// The PC, LR and SP of the faulting process, if known.
+    pub fn registers(&self) -> Option<ProcessRegisters> {
+        self.registers
+    }
+
+    /// The top of the faulting process's stack, starting at its SP.
+    pub fn stack(&self) -> &[u8] {
+        &self.stack[..self.stack_len]
+    }
+
+    /// Encode the record into the first `RECORD_LEN` bytes of `buf`.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, ErrorCode> {
+        let buf = buf.get_mut(..RECORD_LEN).ok_or(ErrorCode::SIZE)?;
+        buf.fill(0);
+        buf[0..4].copy_from_slice(&MAGIC);
+        buf[4] = FORMAT_VERSION;
+        let registers = self.registers.unwrap_or(ProcessRegisters {
+            pc: 0,
+            lr: 0,
+            sp: 0,
+        });
+        if self.registers.is_some() {
+            buf[5] = FLAG_REGISTERS;
+        }
+        buf[8..12].copy_from_slice(&self.count.to_le_bytes());
+        buf[12..16].copy_from_slice(&(registers.pc as u32).to_le_bytes());
+        buf[16..20].copy_from_slice(&(registers.lr as u32).to_le_bytes());
+        buf[20..24].copy_from_slice(&(registers.sp as u32).to_le_bytes());
+        buf[24] = self.process_name_len as u8;
+        buf[25] = self.message_len as u8;
+        buf[26] = self.stack_len as u8;
+        buf[NAME_OFFSET..MESSAGE_OFFSET].copy_from_slice(&self.process_name);
+        buf[MESSAGE_OFFSET..STACK_OFFSET].copy_from_slice(&self.message);
+        buf[STACK_OFFSET..CHECKSUM_OFFSET].copy_from_slice(&self.stack);
+        let checksum = fnv1a(FNV_OFFSET_BASIS, &buf[..CHECKSUM_OFFSET]);
+        buf[CHECKSUM_OFFSET..RECORD_LEN].copy_from_slice(&checksum.to_le_bytes());
+        Ok(RECORD_LEN)
+    }
+
+    /// Decode a record written by `encode()`. Returns `None` if `buf` does
+    /// not hold a valid record, for example because it is erased flash.
+    pub fn decode(buf: &[u8]) -> Option<CrashRecord> {
+        let buf = buf.get(..RECORD_LEN)?;
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                buf[offset],
+                buf[offset + 1],
+                buf[offset + 2],
+                buf[offset + 3],
+            ])
+        };
+        if buf[0..4] != MAGIC
+            || buf[4] != FORMAT_VERSION
+            || read_u32(CHECKSUM_OFFSET) != fnv1a(FNV_OFFSET_BASIS, &buf[..CHECKSUM_OFFSET])
+        {
+            return None;
+        }
+        let process_name_len = buf[24] as usize;
+        let message_len = buf[25] as usize;
+        let stack_len = buf[26] as usize;
+        if process_name_len > MAX_NAME_LEN
+            || message_len > MAX_MESSAGE_LEN
+            || stack_len > MAX_STACK_LEN
+        {
+            return None;
+        }
+
+        let mut record = CrashRecord {
+            count: read_u32(8),
+            registers: None,
+            process_name: [0; MAX_NAME_LEN],
+            process_name_len,
+            message: [0; MAX_MESSAGE_LEN],
+            message_len,
+            stack: [0; MAX_STACK_LEN],
+            stack_len,
+        };
+        if buf[5] & FLAG_REGISTERS != 0 {
+            record.registers = Some(ProcessRegisters {
+                pc: read_u32(12) as usize,
+                lr: read_u32(16) as usize,
+                sp: read_u32(20) as usize,
+            });
+        }
+        record
+            .process_name
+            .copy_from_slice(&buf[NAME_OFFSET..MESSAGE_OFFSET]);
+        record
+            .message
+            .copy_from_slice(&buf[MESSAGE_OFFSET..STACK_OFFSET]);
+        record
+            .stack
+            .copy_from_slice(&buf[STACK_OFFSET..CHECKSUM_OFFSET]);
+        Some(record)
+    }
+}
+
+/// Persists crash records while the kernel is panicking.
+pub trait CrashRecorder {
+    /// Start persisting `record`. The recorder sets the record's crash count.
+    ///
+    /// This is called from the panic handler, after which the kernel main
+    /// loop never runs again. The recorder must make progress only through
+    /// interrupts and deferred calls, which the panic handler services until
+    /// `is_busy()` returns `false`.
+    fn record_crash(&self, record: CrashRecord);
+
+    /// Whether the recorder is still persisting a record.
+    fn is_busy(&self) -> bool;
+}
+
+/// Provides the most recent crash record after a reboot.
+pub trait CrashReader {
+    /// The most recent crash record, if there is one.
+    fn last_crash(&self) -> Option<CrashRecord>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fault() -> CrashRecord {
+        let mut record = CrashRecord::with_message(format_args!("Process {} had a fault", "blink"));
+        record.set_process(
+            "blink",
+            Some(ProcessRegisters {
+                pc: 0x0004_1234,
+                lr: 0x0004_1001,
+                sp: 0x2000_3f80,
+            }),
+            |stack| {
+                stack[..4].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+                4
+            },
+        );
+        record.set_count(3);
+        record
+    }
+
+    fn encoded(record: &CrashRecord) -> [u8; RECORD_LEN] {
+        let mut buf = [0; RECORD_LEN];
+        assert_eq!(record.encode(&mut buf), Ok(RECORD_LEN));
+        buf
+    }
+
+    #[test]
+    fn fault_round_trips() {
+        let record = CrashRecord::decode(&encoded(&fault())).unwrap();
+        assert_eq!(record.count(), 3);
+        assert_eq!(record.message(), "Process blink had a fault");
+        assert_eq!(record.process_name(), Some("blink"));
+        assert_eq!(
+            record.registers(),
+            Some(ProcessRegisters {
+                pc: 0x0004_1234,
+                lr: 0x0004_1001,
+                sp: 0x2000_3f80,
+            })
+        );
+        assert_eq!(record.stack(), &[0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn panic_without_process_round_trips() {
+        let record = CrashRecord::with_message(format_args!("kernel panic"));
+        let record = CrashRecord::decode(&encoded(&record)).unwrap();
+        assert_eq!(record.message(), "kernel panic");
+        assert_eq!(record.process_name(), None);
+        assert_eq!(record.registers(), None);
+        assert!(record.stack().is_empty());
+    }
+
+    #[test]
+    fn long_fields_are_truncated() {
+        let long = "x".repeat(2 * MAX_MESSAGE_LEN);
+        let mut record = CrashRecord::with_message(format_args!("{}\u{e9}", long));
+        record.set_process("a-very-long-process-name", None, |stack| stack.len() + 10);
+        let record = CrashRecord::decode(&encoded(&record)).unwrap();
+        assert_eq!(record.message().len(), MAX_MESSAGE_LEN);
+        assert_eq!(record.process_name(), Some("a-very-long-proc"));
+        assert_eq!(record.stack().len(), MAX_STACK_LEN);
+    }
+
+    #[test]
+    fn truncated_character_is_dropped() {
+        // The two bytes of the final character do not both fit.
+        let message = "x".repeat(MAX_MESSAGE_LEN - 1);
+        let record = CrashRecord::with_message(format_args!("{}\u{e9}", message));
+        let record = CrashRecord::decode(&encoded(&record)).unwrap();
+        assert_eq!(record.message(), message);
+    }
+
+    #[test]
+    fn corrupted_records_are_rejected() {
+        let buf = encoded(&fault());
+        for offset in [
+            0,
+            4,
+            9,
+            30,
+            MESSAGE_OFFSET + 3,
+            STACK_OFFSET,
+            CHECKSUM_OFFSET,
+        ] {
+            let mut corrupted = buf;
+            corrupted[offset] ^= 0x01;
+            assert!(
+                CrashRecord::decode(&corrupted).is_none(),
+                "offset {}",
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn erased_and_short_buffers_are_rejected() {
+        assert!(CrashRecord::decode(&[0xff; RECORD_LEN]).is_none());
+        assert!(CrashRecord::decode(&encoded(&fault())[..RECORD_LEN - 1]).is_none());
+        assert_eq!(
+            fault().encode(&mut [0; RECORD_LEN - 1]),
+            Err(ErrorCode::SIZE)
+        );
+    }
+
+    #[test]
+    fn out_of_range_lengths_are_rejected() {
+        let mut buf = encoded(&fault());
+        buf[26] = MAX_STACK_LEN as u8 + 1;
+        let checksum = fnv1a(FNV_OFFSET_BASIS, &buf[..CHECKSUM_OFFSET]);
+        buf[CHECKSUM_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
+        assert!(CrashRecord::decode(&buf).is_none());
+    }
+}
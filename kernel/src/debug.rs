@@ -60,10 +60,12 @@ use core::str;
 
 use crate::collections::queue::Queue;
 use crate::collections::ring_buffer::RingBuffer;
+use crate::crash_log::{CrashRecord, CrashRecorder};
+use crate::deferred_call::DeferredCall;
 use crate::hil;
 use crate::platform::chip::Chip;
-use crate::process::Process;
 use crate::process::ProcessPrinter;
+use crate::process::ProcessSlot;
 use crate::processbuffer::ReadableProcessSlice;
 use crate::utilities::binary_write::BinaryToWriteWrapper;
 use crate::utilities::cells::NumericCellExt;
@@ -112,7 +114,7 @@ pub unsafe fn panic_print<W: Write + IoWrite, C: Chip, PP: ProcessPrinter>(
     writer: &mut W,
     panic_info: &PanicInfo,
     nop: &dyn Fn(),
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [ProcessSlot],
     chip: &'static Option<&'static C>,
     process_printer: &'static Option<&'static PP>,
 ) {
@@ -132,7 +134,7 @@ pub unsafe fn panic<L: hil::led::Led, W: Write + IoWrite, C: Chip, PP: ProcessPr
     writer: &mut W,
     panic_info: &PanicInfo,
     nop: &dyn Fn(),
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [ProcessSlot],
     chip: &'static Option<&'static C>,
     process_printer: &'static Option<&'static PP>,
 ) -> ! {
@@ -188,7 +190,7 @@ pub unsafe fn panic_cpu_state<W: Write, C: Chip>(
 ///
 /// **NOTE:** The supplied `writer` must be synchronous.
 pub unsafe fn panic_process_info<PP: ProcessPrinter, W: Write>(
-    procs: &'static [Option<&'static dyn Process>],
+    procs: &'static [ProcessSlot],
     process_printer: &'static Option<&'static PP>,
     writer: &mut W,
 ) {
@@ -196,7 +198,7 @@ pub unsafe fn panic_process_info<PP: ProcessPrinter, W: Write>(
         // print data about each process
         let _ = writer.write_fmt(format_args!("\r\n---| App Status |---\r\n"));
         for idx in 0..procs.len() {
-            procs[idx].map(|process| {
+            procs[idx].get().map(|process| {
                 // Print the memory map and basic process info.
                 //
                 // Because we are using a synchronous printer we do not need to
@@ -209,6 +211,45 @@ pub unsafe fn panic_process_info<PP: ProcessPrinter, W: Write>(
     });
 }
 
+/// Persist a record of the panic with `recorder` so it can be read after the
+/// board reboots.
+///
+/// If the panic was caused by a process fault, the kernel already started
+/// recording the fault with the faulting process's registers and stack when
+/// the recorder is also set with `Kernel::set_crash_recorder()`. The recorder
+/// is busy with that record then, and this only waits for it to complete.
+///
+/// The kernel main loop no longer runs during a panic, so this services the
+/// chip's pending interrupts and deferred calls itself until the recorder has
+/// finished writing the record. It gives up after a bounded number of
+/// iterations so that a stuck storage driver cannot prevent the rest of the
+/// panic output.
+///
+/// Boards should call this before `panic()`, which never returns.
+pub unsafe fn panic_record_crash<C: Chip>(
+    recorder: &dyn CrashRecorder,
+    panic_info: &PanicInfo,
+    chip: &'static Option<&'static C>,
+) {
+    if !recorder.is_busy() {
+        recorder.record_crash(CrashRecord::from_panic(panic_info));
+    }
+
+    chip.map(|c| {
+        for _ in 0..1_000_000 {
+            if !recorder.is_busy() {
+                break;
+            }
+            if c.has_pending_interrupts() {
+                c.service_pending_interrupts();
+            }
+            if DeferredCall::has_tasks() {
+                DeferredCall::service_next_pending();
+            }
+        }
+    });
+}
+
 /// Blinks a recognizable pattern forever.
 ///
 /// If a multi-color LED is used for the panic pattern, it is
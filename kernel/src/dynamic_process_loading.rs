@@ -0,0 +1,873 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Loading new processes while the kernel is running.
+//!
+//! At boot, the kernel walks the linked list of TBF objects in app flash and
+//! creates a process for each of them (see `kernel::process::load_processes`).
+//! The dynamic process loader extends that list at runtime: it reserves a
+//! region of unused app flash for a new TBF object, writes the object into it
+//! chunk by chunk, and then creates a `ProcessStandard` for it. The new process
+//! goes through the same credentials checking as processes loaded at boot, and
+//! all other processes keep running while this happens.
+//!
+//! New TBF objects are placed either into padding left behind by uninstalled
+//! processes or after the last TBF object in app flash. The start of the new
+//! object is aligned to its length rounded up to a power of two so that the
+//! MPU can protect it. Any gaps in front of or behind the new object are
+//! filled with padding TBF headers so that the linked list of TBF objects
+//! stays intact at every step of the load.
+//!
+//! A new TBF object only becomes part of the linked list once its header has
+//! been written. If the board resets while the object is being written, the
+//! kernel finds the partial object at boot. Users of this interface should
+//! call `abort()` if they cannot complete a load so the region is skipped at
+//! boot.
+//!
+//! ```text
+//! setup(length) -> setup_done()
+//! write(buffer, offset, length) -> write_done()   (repeated)
+//! load()
+//! ```
+//!
+//! The loader can also permanently remove a process with `uninstall()`. This
+//! rewrites the header of its TBF object into a padding header, removes the
+//! process from the process array, and returns its memory to the loader so
+//! that both can be used for later loads.
+
+use core::cell::Cell;
+use core::convert::TryInto;
+
+use crate::capabilities::{ProcessManagementCapability, ProcessUninstallCapability};
+use crate::config;
+use crate::create_capability;
+use crate::debug;
+use crate::deferred_call::{DeferredCall, DeferredCallClient};
+use crate::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use crate::kernel::Kernel;
+use crate::platform::chip::Chip;
+use crate::process::{ProcessId, State as ProcessState};
+use crate::process_loading::ProcessLoadError;
+use crate::process_policies::ProcessFaultPolicy;
+use crate::process_standard::ProcessStandard;
+use crate::utilities::cells::{OptionalCell, TakeCell};
+use crate::ErrorCode;
+
+/// Size of a padding TBF header.
+const PADDING_HEADER_LEN: usize = 16;
+
+/// Number of separate blocks of free process memory the loader keeps track
+/// of. A process is only uninstalled if its memory can be merged with an
+/// existing block or fits into an unused entry.
+const MEMORY_BLOCKS: usize = 4;
+
+/// Maximum number of padding headers written while reserving flash.
+const SETUP_PADDING_WRITES: usize = 3;
+
+/// Interface for loading a new process binary into flash and running it.
+pub trait DynamicProcessLoading {
+    fn set_client(&self, client: &'static dyn DynamicProcessLoadingClient);
+
+    /// Reserve a region of app flash for a TBF object of `app_length` bytes.
+    /// On success, `setup_done()` is called once the region is ready to be
+    /// written.
+    fn setup(&self, app_length: usize) -> Result<(), ErrorCode>;
+
+    /// Write `length` bytes from `buffer` into the reserved region at `offset`
+    /// bytes from the start of the TBF object. `write_done()` returns the
+    /// buffer, including when the loader rejects the write. The first write
+    /// must start at offset 0 and include the TBF header lengths, which must
+    /// match the length passed to `setup()`. As with `NonvolatileStorage`, the
+    /// buffer is only lost if this returns an error, which means the
+    /// underlying storage refused the write.
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        offset: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode>;
+
+    /// Create a process from the TBF object written into the reserved region
+    /// and start checking its credentials. The process starts running once
+    /// its credentials are approved.
+    fn load(&self) -> Result<(), ProcessLoadError>;
+
+    /// Give up on the current load. The start of the reserved region is
+    /// overwritten with a padding header so that anything already written is
+    /// skipped at boot. `abort_done()` is called when this is finished.
+    fn abort(&self) -> Result<(), ErrorCode>;
+}
+
+/// Client for asynchronous operations of `DynamicProcessLoading`.
+pub trait DynamicProcessLoadingClient {
+    /// The flash region requested with `setup()` is ready to be written.
+    fn setup_done(&self, result: Result<(), ErrorCode>);
+
+    /// A write requested with `write()` finished.
+    fn write_done(&self, result: Result<(), ErrorCode>, buffer: &'static mut [u8], length: usize);
+
+    /// An abort requested with `abort()` finished.
+    fn abort_done(&self, result: Result<(), ErrorCode>);
+}
+
+/// Interface for permanently removing a process binary from flash.
+pub trait ProcessUninstall {
+    fn set_uninstall_client(&self, client: &'static dyn ProcessUninstallClient);
+
+    /// Remove the process `processid`. The process is terminated and removed
+    /// from the process array immediately, and its memory is reclaimed, so
+    /// this must not be called for the process whose system call the kernel
+    /// is currently handling. `uninstall_done()` is called once its TBF
+    /// object has been turned into padding in flash.
+    ///
+    /// Returns `Err(ErrorCode::NOMEM)` without changing anything if the
+    /// memory of the process could not be reclaimed.
+    fn uninstall(
+        &self,
+        processid: ProcessId,
+        capability: &dyn ProcessUninstallCapability,
+    ) -> Result<(), ErrorCode>;
+}
+
+/// Client for `ProcessUninstall`.
+pub trait ProcessUninstallClient {
+    /// The TBF object of an uninstalled process has been erased.
+    fn uninstall_done(&self, result: Result<(), ErrorCode>);
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    /// No flash region is reserved.
+    Idle,
+    /// Writing the padding headers around a newly reserved region.
+    Setup,
+    /// A region is reserved and ready to be written.
+    Ready,
+    /// Writing app data into the reserved region.
+    AppWrite,
+    /// Writing the padding header which marks an aborted region.
+    Abort,
+    /// Writing the padding header over the TBF object of an uninstalled
+    /// process.
+    Uninstall,
+}
+
+/// A region of app flash reserved for a new TBF object.
+#[derive(Clone, Copy)]
+struct NewApp {
+    /// Offset of the region from the start of app flash.
+    offset: usize,
+    /// Length of the TBF object.
+    length: usize,
+}
+
+/// Find the offset of a TBF object of `app_length` bytes in the free
+/// region `start..end`. The object is aligned to its length rounded up to
+/// a power of two so the MPU can cover it with a single region. Gaps in
+/// front of and behind it must be large enough for a padding header,
+/// except behind an object at the end of the list, where erased flash
+/// ends the list.
+fn place(start: usize, end: usize, app_length: usize, at_end: bool) -> Option<usize> {
+    let alignment = app_length.next_power_of_two();
+    let mut offset = (start + alignment - 1) / alignment * alignment;
+    while offset + app_length <= end {
+        let leading = offset - start;
+        let trailing = end - (offset + app_length);
+        if (leading == 0 || leading >= PADDING_HEADER_LEN)
+            && (at_end || trailing == 0 || trailing >= PADDING_HEADER_LEN)
+        {
+            return Some(offset);
+        }
+        offset += alignment;
+    }
+    None
+}
+
+/// Blocks of process memory that are not used by any process.
+struct FreeMemory {
+    blocks: [TakeCell<'static, [u8]>; MEMORY_BLOCKS],
+}
+
+impl FreeMemory {
+    fn new() -> FreeMemory {
+        FreeMemory {
+            blocks: [
+                TakeCell::empty(),
+                TakeCell::empty(),
+                TakeCell::empty(),
+                TakeCell::empty(),
+            ],
+        }
+    }
+
+    /// Whether the block `start..end` is adjacent to a free block.
+    fn is_adjacent(&self, start: usize, end: usize) -> bool {
+        self.blocks.iter().any(|block| {
+            block.map_or(false, |free| {
+                let free_start = free.as_ptr() as usize;
+                free_start == end || free_start + free.len() == start
+            })
+        })
+    }
+
+    /// Whether `add()` would keep the block `start..end`.
+    fn can_add(&self, start: usize, end: usize) -> bool {
+        self.is_adjacent(start, end) || self.blocks.iter().any(|block| block.is_none())
+    }
+
+    /// The highest end address of a free block that ends at or below
+    /// `address`.
+    fn end_below(&self, address: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .filter_map(|block| {
+                block.map_or(None, |free| Some(free.as_ptr() as usize + free.len()))
+            })
+            .filter(|&end| end <= address)
+            .max()
+    }
+
+    /// Add a block of memory, merging it with adjacent free blocks where
+    /// possible. Returns `Err(ErrorCode::NOMEM)` if the block could not be
+    /// merged and all entries are in use, in which case the memory is not
+    /// used again until reboot.
+    fn add(&self, memory: &'static mut [u8]) -> Result<(), ErrorCode> {
+        let mut memory = memory;
+        for block in self.blocks.iter() {
+            if let Some(free) = block.take() {
+                let free_start = free.as_ptr() as usize;
+                let mem_start = memory.as_ptr() as usize;
+                if mem_start + memory.len() == free_start || free_start + free.len() == mem_start {
+                    let start = core::cmp::min(free_start, mem_start) as *mut u8;
+                    let len = free.len() + memory.len();
+                    // Safety: the two blocks are adjacent, so together they
+                    // form one block, and neither is used by a process.
+                    memory = unsafe { core::slice::from_raw_parts_mut(start, len) };
+                } else {
+                    block.replace(free);
+                }
+            }
+        }
+        match self.blocks.iter().find(|block| block.is_none()) {
+            Some(block) => {
+                block.replace(memory);
+                Ok(())
+            }
+            None => {
+                if config::CONFIG.debug_load_processes {
+                    debug!(
+                        "Dynamic load: dropped free memory {:#010X}-{:#010X}",
+                        memory.as_ptr() as usize,
+                        memory.as_ptr() as usize + memory.len() - 1
+                    );
+                }
+                Err(ErrorCode::NOMEM)
+            }
+        }
+    }
+}
+
+/// Loads processes into free app flash while the kernel is running.
+pub struct DynamicProcessLoader<C: 'static + Chip> {
+    kernel: &'static Kernel,
+    chip: &'static C,
+    fault_policy: &'static dyn ProcessFaultPolicy,
+    app_flash: &'static [u8],
+    app_memory: FreeMemory,
+    storage: &'static dyn NonvolatileStorage<'static>,
+    client: OptionalCell<&'static dyn DynamicProcessLoadingClient>,
+    uninstall_client: OptionalCell<&'static dyn ProcessUninstallClient>,
+    padding_buffer: TakeCell<'static, [u8]>,
+    deferred_call: DeferredCall,
+    state: Cell<State>,
+    new_app: OptionalCell<NewApp>,
+    /// Padding headers (offset, length) still to be written for `setup()`.
+    setup_padding: Cell<[Option<(usize, usize)>; SETUP_PADDING_WRITES]>,
+    /// A rejected write buffer to return from a deferred call.
+    rejected_write: TakeCell<'static, [u8]>,
+}
+
+impl<C: 'static + Chip> DynamicProcessLoader<C> {
+    /// Create a dynamic process loader.
+    ///
+    /// `app_flash` must be the full app flash region. The loader has no
+    /// process memory until the board hands it the memory left over after
+    /// loading processes at boot with `add_free_memory()`. `storage` must address
+    /// flash with absolute addresses and should be used only by this loader.
+    /// `padding_buffer` must be at least 16 bytes long.
+    pub fn new(
+        kernel: &'static Kernel,
+        chip: &'static C,
+        fault_policy: &'static dyn ProcessFaultPolicy,
+        app_flash: &'static [u8],
+        storage: &'static dyn NonvolatileStorage<'static>,
+        padding_buffer: &'static mut [u8],
+        _capability: &dyn ProcessManagementCapability,
+    ) -> DynamicProcessLoader<C> {
+        DynamicProcessLoader {
+            kernel,
+            chip,
+            fault_policy,
+            app_flash,
+            app_memory: FreeMemory::new(),
+            storage,
+            client: OptionalCell::empty(),
+            uninstall_client: OptionalCell::empty(),
+            padding_buffer: TakeCell::new(padding_buffer),
+            deferred_call: DeferredCall::new(),
+            state: Cell::new(State::Idle),
+            new_app: OptionalCell::empty(),
+            setup_padding: Cell::new([None; SETUP_PADDING_WRITES]),
+            rejected_write: TakeCell::empty(),
+        }
+    }
+
+    /// Give the loader process memory that it can create new processes in.
+    /// This is usually the memory left over after loading processes at boot
+    /// (see `load_and_check_processes_with_remainder()`), which is only known
+    /// after all grants, including the one of the syscall driver using this
+    /// loader, have been created.
+    ///
+    /// Returns `Err(ErrorCode::NOMEM)` if the loader cannot keep track of
+    /// another separate block of memory.
+    pub fn add_free_memory(
+        &self,
+        app_memory: &'static mut [u8],
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ErrorCode> {
+        self.app_memory.add(app_memory)
+    }
+
+    /// Find where a TBF object of `app_length` bytes can be placed in app
+    /// flash. Walks the linked list of TBF objects and tries each run of
+    /// consecutive padding objects and finally the free flash after the last
+    /// object. Returns the start and end of the free region that was chosen,
+    /// the offset of the new object within it, and whether the free region is
+    /// erased flash at the end of the list.
+    fn find_free_region(&self, app_length: usize) -> Option<(usize, usize, usize, bool)> {
+        let mut offset = 0;
+        // Start of the current run of padding objects, if any.
+        let mut padding_start = None;
+        while let Some(header) = self
+            .app_flash
+            .get(offset..offset + 8)
+            .and_then(|h| h.try_into().ok())
+        {
+            let (is_padding, entry_length) = match tock_tbf::parse::parse_tbf_header_lengths(header)
+            {
+                Ok((version, header_length, entry_length)) => {
+                    let is_padding = self
+                        .app_flash
+                        .get(offset..offset + header_length as usize)
+                        .map_or(false, |header| {
+                            matches!(
+                                tock_tbf::parse::parse_tbf_header(header, version),
+                                Ok(tock_tbf::types::TbfHeader::Padding(_))
+                            )
+                        });
+                    (is_padding, entry_length as usize)
+                }
+                Err(tock_tbf::types::InitialTbfParseError::InvalidHeader(entry_length)) => {
+                    (false, entry_length as usize)
+                }
+                Err(tock_tbf::types::InitialTbfParseError::UnableToParse) => break,
+            };
+            if entry_length == 0 {
+                break;
+            }
+
+            if is_padding {
+                padding_start.get_or_insert(offset);
+            } else if let Some(start) = padding_start.take() {
+                if let Some(new_offset) = place(start, offset, app_length, false) {
+                    return Some((start, offset, new_offset, false));
+                }
+            }
+            offset += entry_length;
+        }
+
+        // Free flash after the last object. A run of padding objects at the
+        // end of the list becomes part of it, but then the old padding behind
+        // the new object must still be covered.
+        let end = self.app_flash.len();
+        let (start, at_end) = match padding_start {
+            Some(start) => (start, false),
+            None => (offset, true),
+        };
+        place(start, end, app_length, at_end).map(|new_offset| (start, end, new_offset, at_end))
+    }
+
+    /// Write the next padding header queued by `setup()`. Returns `None` if
+    /// there are no more padding headers to write.
+    fn write_next_setup_padding(&self) -> Option<Result<(), ErrorCode>> {
+        let mut queue = self.setup_padding.get();
+        let next = queue.iter_mut().find_map(|entry| entry.take());
+        self.setup_padding.set(queue);
+        next.map(|(offset, length)| self.write_padding(offset, length))
+    }
+
+    /// Write a padding header covering `length` bytes at `offset` in app
+    /// flash.
+    fn write_padding(&self, offset: usize, length: usize) -> Result<(), ErrorCode> {
+        let header = tock_tbf::types::TbfHeaderV2Base::new_padding(length as u32).to_bytes();
+        self.padding_buffer
+            .take()
+            .map_or(Err(ErrorCode::RESERVE), |buffer| {
+                buffer[..PADDING_HEADER_LEN].copy_from_slice(&header);
+                self.storage.write(
+                    buffer,
+                    self.app_flash.as_ptr() as usize + offset,
+                    PADDING_HEADER_LEN,
+                )
+            })
+    }
+
+    /// Check that a write into the new TBF object keeps its header lengths
+    /// intact: the first chunk must hold a version 2 header with the length
+    /// reserved by `setup()`, and no other write may touch those fields.
+    fn check_header_write(buffer: &[u8], offset: usize, length: usize, app_length: usize) -> bool {
+        if offset == 0 {
+            length >= 8
+                && u16::from_le_bytes([buffer[0], buffer[1]]) == 2
+                && u32::from_le_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize
+                    == app_length
+        } else {
+            offset >= 8
+        }
+    }
+}
+
+impl<C: 'static + Chip> DynamicProcessLoading for DynamicProcessLoader<C> {
+    fn set_client(&self, client: &'static dyn DynamicProcessLoadingClient) {
+        self.client.set(client);
+    }
+
+    fn setup(&self, app_length: usize) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if app_length < PADDING_HEADER_LEN {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let (start, end, offset, at_end) =
+            self.find_free_region(app_length).ok_or(ErrorCode::NOMEM)?;
+
+        if config::CONFIG.debug_load_processes {
+            debug!(
+                "Dynamic load: reserved flash={:#010X}-{:#010X}",
+                self.app_flash.as_ptr() as usize + offset,
+                self.app_flash.as_ptr() as usize + offset + app_length - 1
+            );
+        }
+
+        // Prepare the region so that the linked list of TBF objects stays
+        // intact after every single flash write:
+        //
+        // 1. Padding behind the new object, still hidden inside the existing
+        //    padding.
+        // 2. Padding covering the new object and everything behind it, also
+        //    still hidden. The first write of the new object replaces it.
+        // 3. Padding in front of the new object, which links the header
+        //    written in step 2 into the list.
+        //
+        // In erased flash at the end of the list, nothing follows the new
+        // object, so only step 3 is needed.
+        let mut queue = [None; SETUP_PADDING_WRITES];
+        if !at_end {
+            if offset + app_length < end {
+                queue[0] = Some((offset + app_length, end - (offset + app_length)));
+            }
+            queue[1] = Some((offset, end - offset));
+        }
+        if offset > start {
+            queue[2] = Some((start, offset - start));
+        }
+        self.setup_padding.set(queue);
+
+        self.new_app.set(NewApp {
+            offset,
+            length: app_length,
+        });
+        self.state.set(State::Setup);
+        match self.write_next_setup_padding() {
+            None => {
+                // Nothing to write, report completion from a deferred call.
+                self.deferred_call.set();
+                Ok(())
+            }
+            Some(Ok(())) => Ok(()),
+            Some(Err(e)) => {
+                self.new_app.clear();
+                self.state.set(State::Idle);
+                Err(e)
+            }
+        }
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        offset: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Ready {
+            return Err(ErrorCode::BUSY);
+        }
+        let new_app = self.new_app.get().ok_or(ErrorCode::FAIL)?;
+        if length > buffer.len()
+            || offset + length > new_app.length
+            || !Self::check_header_write(buffer, offset, length, new_app.length)
+        {
+            // Return the buffer with an error from a deferred call.
+            self.rejected_write.replace(buffer);
+            self.state.set(State::AppWrite);
+            self.deferred_call.set();
+            return Ok(());
+        }
+
+        self.storage
+            .write(
+                buffer,
+                self.app_flash.as_ptr() as usize + new_app.offset + offset,
+                length,
+            )
+            .map(|()| self.state.set(State::AppWrite))
+    }
+
+    fn load(&self) -> Result<(), ProcessLoadError> {
+        if self.state.get() != State::Ready {
+            return Err(ProcessLoadError::InternalError);
+        }
+        let new_app = self.new_app.get().ok_or(ProcessLoadError::InternalError)?;
+        let app_flash = self
+            .app_flash
+            .get(new_app.offset..new_app.offset + new_app.length)
+            .ok_or(ProcessLoadError::NotEnoughFlash)?;
+
+        // The TBF object must have exactly the length that was reserved for
+        // it, otherwise it would corrupt the linked list of TBF objects.
+        let header: &'static [u8; 8] = app_flash
+            .get(0..8)
+            .and_then(|h| h.try_into().ok())
+            .ok_or(ProcessLoadError::NotEnoughFlash)?;
+        let (version, header_length, entry_length) =
+            tock_tbf::parse::parse_tbf_header_lengths(header)
+                .or(Err(ProcessLoadError::TbfHeaderNotFound))?;
+        if entry_length as usize != new_app.length {
+            return Err(ProcessLoadError::NotEnoughFlash);
+        }
+
+        let index = self
+            .kernel
+            .find_free_process_slot()
+            .ok_or(ProcessLoadError::NotEnoughMemory)?;
+
+        // Try each block of free memory until one is large enough.
+        let mut result = Err(ProcessLoadError::NotEnoughMemory);
+        for block in self.app_memory.blocks.iter() {
+            let memory = match block.take() {
+                Some(memory) => memory,
+                None => continue,
+            };
+            let create_result = unsafe {
+                ProcessStandard::create(
+                    self.kernel,
+                    self.chip,
+                    app_flash,
+                    header_length as usize,
+                    version,
+                    memory,
+                    self.fault_policy,
+                    true,
+                    index,
+                )
+            };
+            match create_result {
+                Ok((process, remaining_memory)) => {
+                    block.replace(remaining_memory);
+                    result = Ok(process);
+                    break;
+                }
+                Err((
+                    err @ (ProcessLoadError::NotEnoughMemory
+                    | ProcessLoadError::MemoryAddressMismatch { .. }),
+                    memory,
+                )) => {
+                    block.replace(memory);
+                    result = Err(err);
+                }
+                Err((err, memory)) => {
+                    block.replace(memory);
+                    return Err(err);
+                }
+            }
+        }
+        let process = result?;
+
+        // The TBF object is now part of app flash, whether or not it is an
+        // enabled app.
+        self.new_app.clear();
+        self.state.set(State::Idle);
+
+        if let Some(process) = process {
+            if config::CONFIG.debug_load_processes {
+                debug!(
+                    "Dynamic load: loaded process[{}] {}",
+                    index,
+                    process.get_process_name()
+                );
+            }
+            let capability = create_capability!(ProcessManagementCapability);
+            self.kernel
+                .add_process(process, &capability)
+                .or(Err(ProcessLoadError::InternalError))?;
+            self.kernel
+                .get_checker()
+                .check_process(index)
+                .or(Err(ProcessLoadError::InternalError))?;
+        }
+        Ok(())
+    }
+
+    fn abort(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Ready {
+            return Err(ErrorCode::BUSY);
+        }
+        let new_app = self.new_app.get().ok_or(ErrorCode::FAIL)?;
+        self.write_padding(new_app.offset, new_app.length)
+            .map(|()| self.state.set(State::Abort))
+    }
+}
+
+impl<C: 'static + Chip> ProcessUninstall for DynamicProcessLoader<C> {
+    fn set_uninstall_client(&self, client: &'static dyn ProcessUninstallClient) {
+        self.uninstall_client.set(client);
+    }
+
+    fn uninstall(
+        &self,
+        processid: ProcessId,
+        _capability: &dyn ProcessUninstallCapability,
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let process = self.kernel.get_process(processid).ok_or(ErrorCode::INVAL)?;
+        // The credentials checker may still be looking at this process.
+        if process.get_state() == ProcessState::CredentialsUnchecked {
+            return Err(ErrorCode::BUSY);
+        }
+
+        // The memory between the end of the next lower process or free block
+        // and the start of this process was alignment padding when this
+        // process was created. Below the lowest process there is no such
+        // bound, so padding in front of it is not reclaimed.
+        let addresses = process.get_addresses();
+        let memory_start = self
+            .kernel
+            .get_process_iter()
+            .filter(|p| p.processid() != processid)
+            .map(|p| p.get_addresses().sram_end)
+            .chain(self.app_memory.end_below(addresses.sram_start))
+            .filter(|&end| end <= addresses.sram_start)
+            .max()
+            .unwrap_or(addresses.sram_start);
+        if !self.app_memory.can_add(memory_start, addresses.sram_end) {
+            return Err(ErrorCode::NOMEM);
+        }
+
+        self.write_padding(
+            addresses.flash_start - self.app_flash.as_ptr() as usize,
+            addresses.flash_end - addresses.flash_start,
+        )?;
+        self.state.set(State::Uninstall);
+
+        if config::CONFIG.debug_load_processes {
+            debug!(
+                "Dynamic load: uninstalled process {}",
+                process.get_process_name()
+            );
+        }
+
+        // Stop the process and remove it from the process array before its
+        // memory is reused. After this, looking up its `ProcessId` fails and
+        // the schedulers no longer see it, so nothing refers to the memory
+        // holding the process anymore.
+        process.terminate(None);
+        let capability = create_capability!(ProcessManagementCapability);
+        self.kernel.remove_process(processid, &capability)?;
+        // Safety: the process was removed from the process array, so its
+        // memory and the padding in front of it are no longer used.
+        let memory = unsafe {
+            core::slice::from_raw_parts_mut(
+                memory_start as *mut u8,
+                addresses.sram_end - memory_start,
+            )
+        };
+        // Checked with `can_add()` above.
+        let _ = self.app_memory.add(memory);
+        Ok(())
+    }
+}
+
+impl<C: 'static + Chip> NonvolatileStorageClient for DynamicProcessLoader<C> {
+    fn read_done(&self, _buffer: &'static mut [u8], _length: usize) {}
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        match self.state.get() {
+            State::Setup => {
+                self.padding_buffer.replace(buffer);
+                match self.write_next_setup_padding() {
+                    Some(Ok(())) => {}
+                    Some(Err(e)) => {
+                        self.new_app.clear();
+                        self.state.set(State::Idle);
+                        self.client.map(|client| client.setup_done(Err(e)));
+                    }
+                    None => {
+                        self.state.set(State::Ready);
+                        self.client.map(|client| client.setup_done(Ok(())));
+                    }
+                }
+            }
+            State::AppWrite => {
+                self.state.set(State::Ready);
+                self.client
+                    .map(move |client| client.write_done(Ok(()), buffer, length));
+            }
+            State::Abort => {
+                self.padding_buffer.replace(buffer);
+                self.new_app.clear();
+                self.state.set(State::Idle);
+                self.client.map(|client| client.abort_done(Ok(())));
+            }
+            State::Uninstall => {
+                self.padding_buffer.replace(buffer);
+                self.state.set(State::Idle);
+                self.uninstall_client
+                    .map(|client| client.uninstall_done(Ok(())));
+            }
+            State::Idle | State::Ready => {}
+        }
+    }
+}
+
+impl<C: 'static + Chip> DeferredCallClient for DynamicProcessLoader<C> {
+    fn handle_deferred_call(&self) {
+        match self.state.get() {
+            State::Setup => {
+                self.state.set(State::Ready);
+                self.client.map(|client| client.setup_done(Ok(())));
+            }
+            State::AppWrite => {
+                self.rejected_write.take().map(|buffer| {
+                    self.state.set(State::Ready);
+                    self.client
+                        .map(move |client| client.write_done(Err(ErrorCode::INVAL), buffer, 0));
+                });
+            }
+            State::Idle | State::Ready | State::Abort | State::Uninstall => {}
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(memory: &[u8]) -> (usize, usize) {
+        let start = memory.as_ptr() as usize;
+        (start, start + memory.len())
+    }
+
+    fn blocks(free: &FreeMemory) -> usize {
+        free.blocks.iter().filter(|block| block.is_some()).count()
+    }
+
+    #[test]
+    fn free_memory_merges_adjacent_blocks() {
+        static mut MEMORY: [u8; 48] = [0; 48];
+        let memory: &'static mut [u8; 48] = unsafe { &mut *core::ptr::addr_of_mut!(MEMORY) };
+        let (start, end) = range(memory);
+        let (low, rest) = memory.split_at_mut(16);
+        let (middle, high) = rest.split_at_mut(16);
+
+        let free = FreeMemory::new();
+        assert_eq!(free.add(middle), Ok(()));
+        assert_eq!(free.add(high), Ok(()));
+        assert_eq!(free.add(low), Ok(()));
+        assert_eq!(blocks(&free), 1);
+        assert_eq!(
+            free.blocks[0].map_or((0, 0), |block| range(block)),
+            (start, end)
+        );
+    }
+
+    #[test]
+    fn free_memory_reports_a_full_table() {
+        static mut MEMORY: [u8; 40] = [0; 40];
+        let memory: &'static mut [u8; 40] = unsafe { &mut *core::ptr::addr_of_mut!(MEMORY) };
+        let (start, _) = range(memory);
+        let mut rest: &'static mut [u8] = memory;
+        let free = FreeMemory::new();
+        // Four separate blocks, each followed by a gap.
+        for _ in 0..MEMORY_BLOCKS {
+            let (block, tail) = rest.split_at_mut(4);
+            let (_gap, tail) = tail.split_at_mut(4);
+            assert_eq!(free.add(block), Ok(()));
+            rest = tail;
+        }
+        assert_eq!(blocks(&free), MEMORY_BLOCKS);
+
+        // A block in a gap merges with its neighbours, a separate one does
+        // not fit.
+        assert!(free.can_add(start + 4, start + 8));
+        assert!(!free.can_add(start + 34, start + 38));
+        let (_gap, separate) = rest.split_at_mut(2);
+        assert_eq!(free.add(separate), Err(ErrorCode::NOMEM));
+    }
+
+    #[test]
+    fn free_memory_end_below() {
+        static mut MEMORY: [u8; 32] = [0; 32];
+        let memory: &'static mut [u8; 32] = unsafe { &mut *core::ptr::addr_of_mut!(MEMORY) };
+        let (start, _) = range(memory);
+        let (low, rest) = memory.split_at_mut(8);
+        let (_used, high) = rest.split_at_mut(8);
+
+        let free = FreeMemory::new();
+        assert_eq!(free.end_below(start + 8), None);
+        free.add(low).unwrap();
+        free.add(high).unwrap();
+        assert_eq!(free.end_below(start + 7), None);
+        assert_eq!(free.end_below(start + 16), Some(start + 8));
+        assert_eq!(free.end_below(start + 32), Some(start + 32));
+    }
+
+    #[test]
+    fn place_aligns_new_objects() {
+        // Aligned to the next power of two of the length.
+        assert_eq!(place(0, 4096, 1000, true), Some(0));
+        assert_eq!(place(16, 4096, 1000, true), Some(1024));
+        assert_eq!(place(1000, 2048, 1024, true), Some(1024));
+        assert_eq!(place(1000, 2047, 1024, true), None);
+    }
+
+    #[test]
+    fn place_leaves_room_for_padding() {
+        // A gap in front of the object must hold a padding header.
+        assert_eq!(place(1020, 4096, 1024, true), Some(2048));
+        // So must a gap behind it, unless the object is at the end of the
+        // list.
+        assert_eq!(place(0, 1032, 1024, false), None);
+        assert_eq!(place(0, 1032, 1024, true), Some(0));
+        assert_eq!(place(0, 1024, 1024, false), Some(0));
+        assert_eq!(place(0, 1040, 1024, false), Some(0));
+    }
+}
@@ -0,0 +1,107 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for storage devices made of fixed-size blocks.
+//!
+//! Block storage is addressed by block number, and every read and write
+//! transfers one or more whole blocks. This is the interface of SD cards and
+//! other disks, and it can be provided on top of flash pages. Filesystems
+//! and logs that use this interface work on any such device, including a
+//! RAM disk on the host.
+//!
+//! ```text
+//! +-----------------------+
+//! |  Filesystem, log, ... |
+//! +-----------------------+
+//!
+//!    hil::block_storage (this file)
+//!
+//! +-----------------------+
+//! |  SD card, flash, RAM  |
+//! +-----------------------+
+//! ```
+//!
+//! Only one operation can be in progress at a time. All operations complete
+//! with a callback to the [`BlockStorageClient`], never from within the call
+//! that started them.
+
+use crate::ErrorCode;
+
+/// The layout of a block storage device.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Geometry {
+    /// Size of a block in bytes.
+    pub block_size: usize,
+    /// Number of blocks on the device.
+    pub block_count: usize,
+}
+
+impl Geometry {
+    /// Returns `true` if `count` blocks starting at `block` are on the
+    /// device.
+    pub fn contains(&self, block: usize, count: usize) -> bool {
+        block
+            .checked_add(count)
+            .map_or(false, |end| end <= self.block_count)
+    }
+
+    /// The number of bytes in `count` blocks.
+    pub fn bytes(&self, count: usize) -> Option<usize> {
+        count.checked_mul(self.block_size)
+    }
+}
+
+/// A device that stores data in fixed-size blocks.
+pub trait BlockStorage<'a> {
+    fn set_client(&self, client: &'a dyn BlockStorageClient);
+
+    /// Returns the layout of the device.
+    ///
+    /// Returns `RESERVE` if the layout is not known yet, for example because
+    /// the device has not been initialized.
+    fn geometry(&self) -> Result<Geometry, ErrorCode>;
+
+    /// Read `count` blocks starting at block `block` into `buffer`.
+    ///
+    /// The buffer must hold at least `count` blocks, otherwise `SIZE` is
+    /// returned. Returns `INVAL` if the blocks are not on the device and
+    /// `BUSY` if another operation is in progress. On error the buffer is
+    /// returned.
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        block: usize,
+        count: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Write `count` blocks from `buffer` starting at block `block`.
+    ///
+    /// Blocks do not need to be erased before they are written. Returns the
+    /// same errors as [`BlockStorage::read`].
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        block: usize,
+        count: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Erase `count` blocks starting at block `block`.
+    ///
+    /// Erased blocks read as `0xFF` until they are written. Devices that
+    /// cannot erase blocks return `NOSUPPORT`; writes to them overwrite
+    /// blocks in place.
+    fn erase(&self, block: usize, count: usize) -> Result<(), ErrorCode>;
+}
+
+/// Client interface for block storage.
+pub trait BlockStorageClient {
+    /// A read finished. On success `buffer` holds the blocks read.
+    fn read_complete(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>);
+
+    /// A write finished.
+    fn write_complete(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>);
+
+    /// An erase finished.
+    fn erase_complete(&self, result: Result<(), ErrorCode>);
+}
@@ -13,6 +13,12 @@
 //!    call requires storage permissions. This permits implementing access
 //!    control permissions with key-value stores in Tock.
 //!
+//! Both levels also support transactions. After `begin_transaction()` any
+//! number of set and delete operations can be staged with `stage_set()` and
+//! `stage_delete()`. None of them are visible until `commit_transaction()`
+//! applies all of them atomically, `abort_transaction()` discards them. Only
+//! one transaction can be open at a time.
+//!
 //! The expected setup inside Tock will look like this:
 //!
 //! ```text
@@ -39,6 +45,11 @@ use crate::storage_permissions::StoragePermissions;
 use crate::utilities::leasable_buffer::SubSliceMut;
 use crate::ErrorCode;
 
+/// Error for a `get` of a stored value which failed authentication. No other
+/// KV operation returns this code, so callers can tell a value that was
+/// modified apart from every other failure.
+pub const AUTHENTICATION_FAILED: ErrorCode = ErrorCode::NOACK;
+
 /// Callback trait for KV stores.
 ///
 /// Implement this trait and use `set_client()` to receive callbacks.
@@ -58,6 +69,9 @@ pub trait KVClient {
     ///   - `NOSUPPORT`: The key could not be found or the caller does not have
     ///     permission to read this key. The data in the `value` buffer is
     ///     meaningless.
+    ///   - `AUTHENTICATION_FAILED`: The stored value failed authentication,
+    ///     it has been modified. Only reported to callers that have permission
+    ///     to read this key. The data in the `value` buffer is meaningless.
     ///   - `FAIL`: An internal error occurred and the operation cannot be
     ///     completed.
     /// - `key`: The key buffer.
@@ -145,6 +159,22 @@ pub trait KVClient {
     ///     completed.
     /// - `key`: The key buffer.
     fn delete_complete(&self, result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>);
+
+    /// This callback is called when beginning, committing or aborting a
+    /// transaction completes.
+    ///
+    /// Staged set and delete operations complete with `set_complete()` and
+    /// `delete_complete()`.
+    ///
+    /// ### Return Values
+    ///
+    /// - `result`: `Ok(())` on success, `Err(ErrorCode)` on error. Valid
+    ///   `ErrorCode`s:
+    ///   - `NOMEM`: The transaction could not be started or committed because
+    ///     the KV store is full.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn transaction_complete(&self, _result: Result<(), ErrorCode>) {}
 }
 
 /// Key-Value interface with permissions.
@@ -319,6 +349,114 @@ pub trait KVPermissions<'a> {
         permissions: StoragePermissions,
     ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)>;
 
+    /// Begin a transaction.
+    ///
+    /// ### Arguments
+    ///
+    /// - `permissions`: The read/write/modify permissions for this access.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A `transaction_complete()` callback will
+    ///   be issued.
+    /// - On error returns:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `ALREADY`: A transaction is already open.
+    ///   - `INVAL`: The caller does not have write permissions.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn begin_transaction(&self, permissions: StoragePermissions) -> Result<(), ErrorCode>;
+
+    /// Stage storing a value based on the given key in the open transaction.
+    /// Like `set()`, the key is added if it does not exist and updated
+    /// otherwise once the transaction is committed.
+    ///
+    /// The `value` buffer must have room for a header.
+    ///
+    /// ### Arguments
+    ///
+    /// - `key`: The key to identify the k-v pair.
+    /// - `value`: The value to store. The provided buffer MUST start
+    ///   `KVPermissions.header_size()` bytes after the beginning of the buffer
+    ///   to enable the implementation to insert a header.
+    /// - `permissions`: The read/write/modify permissions for this access.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A `set_complete()` callback will be
+    ///   issued.
+    /// - On error, returns the buffers and:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `SIZE`: There is insufficient room to include the permission header
+    ///     in the `value` buffer or the key/value is too large to store.
+    ///   - `INVAL`: No transaction is open or the caller does not have write
+    ///     permissions.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn stage_set(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    >;
+
+    /// Stage deleting a key-value object based on the given key in the open
+    /// transaction.
+    ///
+    /// ### Arguments
+    ///
+    /// - `key`: The key to identify the k-v pair.
+    /// - `permissions`: The read/write/modify permissions for this access.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A `delete_complete()` callback will be
+    ///   issued.
+    /// - On error, returns the buffer and:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `INVAL`: No transaction is open or the caller does not have modify
+    ///     permissions.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn stage_delete(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)>;
+
+    /// Atomically apply every operation staged in the open transaction.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A `transaction_complete()` callback will
+    ///   be issued.
+    /// - On error returns:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `INVAL`: No transaction is open.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn commit_transaction(&self) -> Result<(), ErrorCode>;
+
+    /// Discard every operation staged in the open transaction.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A `transaction_complete()` callback will
+    ///   be issued.
+    /// - On error returns:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `INVAL`: No transaction is open.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn abort_transaction(&self) -> Result<(), ErrorCode>;
+
     /// Returns the length of the key-value store's header in bytes.
     ///
     /// Room for this header must be accommodated in a `set`, `add`, or `update`
@@ -336,6 +474,10 @@ pub trait KVPermissions<'a> {
 /// - `add(key, value)`
 /// - `update(key, value)`
 /// - `delete(key)`
+///
+/// and the transaction commands `begin_transaction()`, `stage_set(key,
+/// value)`, `stage_delete(key)`, `commit_transaction()` and
+/// `abort_transaction()`.
 pub trait KV<'a> {
     /// Configure the client for operation callbacks.
     fn set_client(&self, client: &'a dyn KVClient);
@@ -478,4 +620,96 @@ pub trait KV<'a> {
         &self,
         key: SubSliceMut<'static, u8>,
     ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)>;
+
+    /// Begin a transaction.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A `transaction_complete()` callback will
+    ///   be issued.
+    /// - On error returns:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `ALREADY`: A transaction is already open.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn begin_transaction(&self) -> Result<(), ErrorCode>;
+
+    /// Stage storing a value based on the given key in the open transaction.
+    /// Like `set()`, the key is added if it does not exist and updated
+    /// otherwise once the transaction is committed.
+    ///
+    /// ### Arguments
+    ///
+    /// - `key`: The key to identify the k-v pair.
+    /// - `value`: The value to store.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A `set_complete()` callback will be
+    ///   issued.
+    /// - On error, returns the buffers and:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `INVAL`: No transaction is open.
+    ///   - `SIZE`: The key/value is too large to store.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn stage_set(
+        &self,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    >;
+
+    /// Stage deleting a key-value object based on the given key in the open
+    /// transaction.
+    ///
+    /// ### Arguments
+    ///
+    /// - `key`: The key to identify the k-v pair.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A `delete_complete()` callback will be
+    ///   issued.
+    /// - On error, returns the buffer and:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `INVAL`: No transaction is open.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn stage_delete(
+        &self,
+        key: SubSliceMut<'static, u8>,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)>;
+
+    /// Atomically apply every operation staged in the open transaction.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A `transaction_complete()` callback will
+    ///   be issued.
+    /// - On error returns:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `INVAL`: No transaction is open.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn commit_transaction(&self) -> Result<(), ErrorCode>;
+
+    /// Discard every operation staged in the open transaction.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A `transaction_complete()` callback will
+    ///   be issued.
+    /// - On error returns:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `INVAL`: No transaction is open.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn abort_transaction(&self) -> Result<(), ErrorCode>;
 }
@@ -0,0 +1,52 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for verifying digital signatures.
+//!
+//! Signatures are verified over the hash of a message, which the caller
+//! computes beforehand (for example with a `hil::digest` implementation).
+//! `HL` is the length of the hash and `SL` the length of the signature in
+//! bytes, so an implementation supports a single signature algorithm, for
+//! example ECDSA over NIST P-256 with `HL = 32` and `SL = 64`.
+
+use crate::ErrorCode;
+
+/// Upcall from the `SignatureVerify` trait.
+pub trait ClientVerify<const HL: usize, const SL: usize> {
+    /// The `verify()` command has been completed.
+    ///
+    /// `result` is `Ok(true)` if the signature is valid for the hash and key,
+    /// `Ok(false)` if it is not, and `Err()` if the verification could not
+    /// be performed. The `hash` and `signature` buffers passed to `verify()`
+    /// are returned.
+    fn verification_done(
+        &self,
+        result: Result<bool, ErrorCode>,
+        hash: &'static mut [u8; HL],
+        signature: &'static mut [u8; SL],
+    );
+}
+
+/// Verify a signature over a hash with a public key.
+pub trait SignatureVerify<'a, const HL: usize, const SL: usize> {
+    /// Set the client instance which will receive the `verification_done()`
+    /// callback.
+    fn set_verify_client(&self, client: &'a dyn ClientVerify<HL, SL>);
+
+    /// Verify that `signature` is a valid signature of `hash` made with the
+    /// private key matching `public_key`. The format of `public_key` is
+    /// specific to the signature algorithm.
+    ///
+    /// On success, `verification_done()` is called with the result.
+    ///
+    /// The possible ErrorCodes are:
+    ///     - `BUSY`: A verification is already in progress.
+    ///     - `INVAL`: `public_key` is not a valid key for this algorithm.
+    fn verify(
+        &self,
+        public_key: &'static [u8],
+        hash: &'static mut [u8; HL],
+        signature: &'static mut [u8; SL],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; HL], &'static mut [u8; SL])>;
+}
@@ -20,6 +20,8 @@ use crate::capabilities::ProcessManagementCapability;
 use crate::kernel::Kernel;
 use crate::process;
 use crate::process::ProcessId;
+use crate::process_policies::ProcessMemoryQuota;
+use crate::sandbox::SandboxViolation;
 use crate::utilities::cells::NumericCellExt;
 
 /// This struct provides the inspection functions.
@@ -53,6 +55,7 @@ impl KernelInfo {
             .process_each(|process| match process.get_state() {
                 process::State::Running => count.increment(),
                 process::State::Yielded => count.increment(),
+                process::State::YieldedFor(_) => count.increment(),
                 _ => {}
             });
         count.get()
@@ -70,6 +73,7 @@ impl KernelInfo {
             .process_each(|process| match process.get_state() {
                 process::State::Running => {}
                 process::State::Yielded => {}
+                process::State::YieldedFor(_) => {}
                 _ => count.increment(),
             });
         count.get()
@@ -127,6 +131,83 @@ impl KernelInfo {
             .process_map_or(0, app, |process| process.debug_timeslice_expiration_count())
     }
 
+    /// Returns a tuple of (the number of bytes this app has grown its heap by,
+    /// the number of bytes allocated for its grants), both relative to the
+    /// memory layout the app started with.
+    pub fn app_memory_usage(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> (usize, usize) {
+        self.kernel
+            .process_map_or((0, 0), app, |process| process.get_memory_usage())
+    }
+
+    /// Returns the limits on heap growth and grant allocations of this app.
+    pub fn app_memory_quota(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> ProcessMemoryQuota {
+        self.kernel
+            .process_map_or(ProcessMemoryQuota::default(), app, |process| {
+                self.kernel.memory_quota(process)
+            })
+    }
+
+    /// Returns the number of times this app has missed a real-time deadline.
+    pub fn number_app_deadline_misses(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_deadline_miss_count())
+    }
+
+    /// Returns the number of times this app has violated its sandbox profile.
+    pub fn number_app_sandbox_violations(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_sandbox_violation_count())
+    }
+
+    /// Returns the most recent violation of the sandbox profile of this app,
+    /// if any.
+    pub fn app_last_sandbox_violation(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<SandboxViolation> {
+        self.kernel
+            .process_map_or(None, app, |process| process.debug_last_sandbox_violation())
+    }
+
+    /// Returns how many microseconds this app has executed for. Time is only
+    /// counted while the app runs with a timeslice from the scheduler timer.
+    pub fn app_run_time_us(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> u64 {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_run_time_us())
+    }
+
+    /// Returns how many microseconds the kernel has spent handling syscalls of
+    /// this app.
+    pub fn app_syscall_time_us(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> u64 {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_syscall_time_us())
+    }
+
     /// Returns a tuple of the (the number of grants in the grant region this
     /// app has allocated, total number of grants that exist in the system).
     pub fn number_app_grant_uses(
@@ -147,6 +228,24 @@ impl KernelInfo {
         (used, number_of_grants)
     }
 
+    /// Calls `closure` with the driver number and the size in bytes of each
+    /// grant the app has allocated.
+    pub fn app_grant_allocations<F: FnMut(usize, usize)>(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+        mut closure: F,
+    ) {
+        let number_of_grants = self.kernel.get_grant_count_and_finalize();
+        self.kernel.process_map_or((), app, |process| {
+            for grant_num in 0..number_of_grants {
+                if let Some((driver_num, size)) = process.grant_allocation(grant_num) {
+                    closure(driver_num, size);
+                }
+            }
+        });
+    }
+
     /// Returns the total number of times all processes have exceeded
     /// their timeslices.
     pub fn timeslice_expirations(&self, _capability: &dyn ProcessManagementCapability) -> usize {
@@ -156,4 +255,37 @@ impl KernelInfo {
         });
         count.get()
     }
+
+    /// Returns the total number of real-time deadlines all processes have
+    /// missed.
+    pub fn deadline_misses(&self, _capability: &dyn ProcessManagementCapability) -> usize {
+        let count: Cell<usize> = Cell::new(0);
+        self.kernel.process_each(|proc| {
+            count.add(proc.debug_deadline_miss_count());
+        });
+        count.get()
+    }
+
+    /// Returns the total number of times all processes have violated their
+    /// sandbox profiles.
+    pub fn sandbox_violations(&self, _capability: &dyn ProcessManagementCapability) -> usize {
+        let count: Cell<usize> = Cell::new(0);
+        self.kernel.process_each(|proc| {
+            count.add(proc.debug_sandbox_violation_count());
+        });
+        count.get()
+    }
+
+    /// Returns the total number of microseconds all processes have executed
+    /// for, and the total number of microseconds the kernel has spent
+    /// handling their syscalls.
+    pub fn cpu_time_us(&self, _capability: &dyn ProcessManagementCapability) -> (u64, u64) {
+        let run: Cell<u64> = Cell::new(0);
+        let syscall: Cell<u64> = Cell::new(0);
+        self.kernel.process_each(|proc| {
+            run.set(run.get() + proc.debug_run_time_us());
+            syscall.set(syscall.get() + proc.debug_syscall_time_us());
+        });
+        (run.get(), syscall.get())
+    }
 }
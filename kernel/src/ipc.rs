@@ -11,10 +11,12 @@ use crate::capabilities::MemoryAllocationCapability;
 use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use crate::kernel::Kernel;
 use crate::process;
-use crate::process::ProcessId;
+use crate::process::{Process, ProcessId, ShortID};
 use crate::processbuffer::ReadableProcessBuffer;
 use crate::syscall_driver::{CommandReturn, SyscallDriver};
+use crate::utilities::cells::OptionalCell;
 use crate::ErrorCode;
+use tock_tbf::types::NUM_IPC_CLIENTS;
 
 /// Syscall number
 pub const DRIVER_NUM: usize = 0x10000;
@@ -37,6 +39,57 @@ pub enum IPCUpcallType {
     Client,
 }
 
+/// Policy that decides which processes can communicate over IPC.
+///
+/// Without a policy, every process can discover and notify every other
+/// process.
+pub trait IPCPolicy {
+    /// Decide whether `client` can discover `service` by its package name.
+    fn can_discover(&self, client: &dyn Process, service: &dyn Process) -> bool;
+
+    /// Decide whether `client` can notify `service` and `service` can notify
+    /// `client` in return.
+    fn can_notify(&self, client: &dyn Process, service: &dyn Process) -> bool;
+}
+
+/// Lets services restrict their clients with the IPC Clients TBF header.
+///
+/// A service that includes the header can only be used by processes with a
+/// fixed `ShortID` from its list. Services without the header can be used by
+/// every process.
+pub struct TbfIPCPolicy;
+
+impl TbfIPCPolicy {
+    fn allowed(client: &dyn Process, service: &dyn Process) -> bool {
+        Self::in_client_list(client.short_app_id(), service.get_ipc_client_ids())
+    }
+
+    /// Whether a client with `client_id` is in the list of `(length, ids)`
+    /// from the IPC Clients header of a service, if the service has one.
+    fn in_client_list(
+        client_id: ShortID,
+        clients: Option<(usize, [u32; NUM_IPC_CLIENTS])>,
+    ) -> bool {
+        match clients {
+            None => true,
+            Some((length, ids)) => match client_id {
+                ShortID::Fixed(id) => ids.iter().take(length).any(|&i| i == id.get()),
+                ShortID::LocallyUnique => false,
+            },
+        }
+    }
+}
+
+impl IPCPolicy for TbfIPCPolicy {
+    fn can_discover(&self, client: &dyn Process, service: &dyn Process) -> bool {
+        Self::allowed(client, service)
+    }
+
+    fn can_notify(&self, client: &dyn Process, service: &dyn Process) -> bool {
+        Self::allowed(client, service)
+    }
+}
+
 /// State that is stored in each process's grant region to support IPC.
 #[derive(Default)]
 struct IPCData;
@@ -50,6 +103,8 @@ pub struct IPC<const NUM_PROCS: u8> {
         AllowRoCount<{ ro_allow::COUNT }>,
         AllowRwCount<NUM_PROCS>,
     >,
+    /// Decides which processes can communicate.
+    policy: OptionalCell<&'static dyn IPCPolicy>,
 }
 
 impl<const NUM_PROCS: u8> IPC<NUM_PROCS> {
@@ -60,9 +115,28 @@ impl<const NUM_PROCS: u8> IPC<NUM_PROCS> {
     ) -> Self {
         Self {
             data: kernel.create_grant(driver_num, capability),
+            policy: OptionalCell::empty(),
         }
     }
 
+    /// Set the policy that decides which processes can discover and notify
+    /// each other.
+    pub fn set_policy(&self, policy: &'static dyn IPCPolicy) {
+        self.policy.set(policy);
+    }
+
+    /// Ask the policy whether the process `client` can notify the process
+    /// `service` and the other way around.
+    fn can_notify(&self, client: ProcessId, service: ProcessId) -> bool {
+        self.policy.map_or(true, |policy| {
+            self.data.kernel.process_map_or(false, client, |client| {
+                self.data
+                    .kernel
+                    .process_map_or(false, service, |service| policy.can_notify(client, service))
+            })
+        })
+    }
+
     /// Schedule an IPC upcall for a process. This is called by the main
     /// scheduler loop if an IPC task was queued for the process.
     pub(crate) unsafe fn schedule_upcall(
@@ -118,12 +192,15 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
     /// - `0`: Driver existence check, always returns Ok(())
     /// - `1`: Perform discovery on the package name passed to `allow_readonly`. Returns the
     ///        service descriptor if the service is found, otherwise returns an error.
+    ///        Services the IPC policy does not allow this process to use are not found.
     /// - `2`: Notify a service previously discovered to have the service descriptor in
     ///        `target_id`. Returns an error if `target_id` refers to an invalid service or the
     ///        notify fails to enqueue.
     /// - `3`: Notify a client with descriptor `target_id`, typically in response to a previous
     ///        notify from the client. Returns an error if `target_id` refers to an invalid client
     ///        or the notify fails to enqueue.
+    ///
+    /// Notifications the IPC policy does not allow fail with `INVAL`.
     fn command(
         &self,
         command_number: usize,
@@ -151,6 +228,13 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
                                                 && s.iter()
                                                     .zip(slice.iter())
                                                     .all(|(c1, c2)| *c1 == c2.get())
+                                                && self.policy.map_or(true, |policy| {
+                                                    self.data.kernel.process_map_or(
+                                                        false,
+                                                        processid,
+                                                        |client| policy.can_discover(client, p),
+                                                    )
+                                                })
                                             {
                                                 // Return the index of the process which is used for
                                                 // subscribe number
@@ -173,13 +257,14 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
             {
                 let cb_type = IPCUpcallType::Service;
 
-                let other_process =
-                    self.data
-                        .kernel
-                        .process_until(|p| match p.processid().index() {
-                            Some(i) if i == target_id => Some(p.processid()),
-                            _ => None,
-                        });
+                let other_process = self
+                    .data
+                    .kernel
+                    .process_until(|p| match p.processid().index() {
+                        Some(i) if i == target_id => Some(p.processid()),
+                        _ => None,
+                    })
+                    .filter(|&service| self.can_notify(processid, service));
 
                 other_process.map_or(CommandReturn::failure(ErrorCode::INVAL), |otherapp| {
                     self.data.kernel.process_map_or(
@@ -206,13 +291,14 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
             {
                 let cb_type = IPCUpcallType::Client;
 
-                let other_process =
-                    self.data
-                        .kernel
-                        .process_until(|p| match p.processid().index() {
-                            Some(i) if i == target_id => Some(p.processid()),
-                            _ => None,
-                        });
+                let other_process = self
+                    .data
+                    .kernel
+                    .process_until(|p| match p.processid().index() {
+                        Some(i) if i == target_id => Some(p.processid()),
+                        _ => None,
+                    })
+                    .filter(|&client| self.can_notify(client, processid));
 
                 other_process.map_or(CommandReturn::failure(ErrorCode::INVAL), |otherapp| {
                     self.data.kernel.process_map_or(
@@ -242,3 +328,40 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
         self.data.enter(processid, |_, _| {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::num::NonZeroU32;
+
+    fn fixed(id: u32) -> ShortID {
+        ShortID::Fixed(NonZeroU32::new(id).unwrap())
+    }
+
+    #[test]
+    fn service_without_header_allows_everyone() {
+        assert!(TbfIPCPolicy::in_client_list(fixed(7), None));
+        assert!(TbfIPCPolicy::in_client_list(ShortID::LocallyUnique, None));
+    }
+
+    #[test]
+    fn only_listed_clients_are_allowed() {
+        let clients = Some((2, [7, 9, 11, 0, 0, 0, 0, 0]));
+        assert!(TbfIPCPolicy::in_client_list(fixed(7), clients));
+        assert!(TbfIPCPolicy::in_client_list(fixed(9), clients));
+        // Entries past the length of the list do not count.
+        assert!(!TbfIPCPolicy::in_client_list(fixed(11), clients));
+        assert!(!TbfIPCPolicy::in_client_list(fixed(8), clients));
+    }
+
+    #[test]
+    fn clients_without_fixed_id_are_not_allowed() {
+        let clients = Some((1, [7, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(!TbfIPCPolicy::in_client_list(
+            ShortID::LocallyUnique,
+            clients
+        ));
+        // An empty list allows no client at all.
+        assert!(!TbfIPCPolicy::in_client_list(fixed(7), Some((0, [7; 8]))));
+    }
+}
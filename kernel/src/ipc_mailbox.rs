@@ -0,0 +1,706 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Message-passing inter-process communication for Tock.
+//!
+//! Unlike `kernel::ipc`, which lets processes share allowed buffers, this
+//! syscall driver never gives a process access to memory of another process.
+//! The kernel copies each message from the buffer of the sender into the
+//! mailbox of the receiver, which is held in the grant of the receiver.
+//! Messages are at most `MESSAGE_LEN` bytes long.
+//!
+//! A mailbox keeps a separate queue of up to `QUEUE_DEPTH` messages for each
+//! sender, so a process that sends a lot cannot keep others from reaching the
+//! receiver. Sending to a full queue fails with `BUSY` and the sender should
+//! retry once the receiver has made progress.
+//!
+//! Processes address each other with handles, which are returned when
+//! looking up a process by its package name or its `ShortID`. A process can
+//! also send a message as a call, in which case the receiver answers with a
+//! reply that the kernel copies directly into a buffer of the caller. The
+//! caller blocks for the reply with `yield-wait-for` on the reply upcall.
+//!
+//! Mailboxes are cleared when their process restarts. Messages from a sender
+//! that has since terminated or restarted are dropped, so they are never
+//! attributed to whichever process uses the handle next, and a reply is only
+//! accepted from the exact process instance that was called.
+//!
+//! Like `kernel::ipc`, the driver can be given an `IPCPolicy`. The sender of a
+//! message is treated as the client and the receiver as the service, so
+//! processes the policy does not allow are neither found nor reachable.
+
+use crate::capabilities::MemoryAllocationCapability;
+use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use crate::ipc::IPCPolicy;
+use crate::kernel::Kernel;
+use crate::process::{Process, ProcessId, ShortID};
+use crate::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use crate::syscall_driver::{CommandReturn, SyscallDriver};
+use crate::utilities::cells::OptionalCell;
+use crate::ErrorCode;
+
+/// Syscall number
+pub const DRIVER_NUM: usize = 0x10002;
+
+/// Handle passed to receive to take a message from any sender.
+const ANY_SENDER: usize = usize::MAX;
+
+/// Ids for subscribed upcalls
+mod upcall {
+    /// A message arrived in the mailbox.
+    pub(super) const MESSAGE: usize = 0;
+    /// A reply to a call arrived.
+    pub(super) const REPLY: usize = 1;
+    /// The number of upcalls the kernel stores for this grant.
+    pub(super) const COUNT: u8 = 2;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Message to send or reply with.
+    pub(super) const MESSAGE: usize = 0;
+    /// Package name to look up.
+    pub(super) const SEARCH: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant.
+    pub(super) const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer received messages are copied into.
+    pub(super) const RECEIVE: usize = 0;
+    /// Buffer replies are copied into.
+    pub(super) const REPLY: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant.
+    pub(super) const COUNT: u8 = 2;
+}
+
+/// A message waiting in a mailbox.
+#[derive(Clone, Copy)]
+struct Message<const MESSAGE_LEN: usize> {
+    len: usize,
+    data: [u8; MESSAGE_LEN],
+}
+
+impl<const MESSAGE_LEN: usize> Default for Message<MESSAGE_LEN> {
+    fn default() -> Self {
+        Message {
+            len: 0,
+            data: [0; MESSAGE_LEN],
+        }
+    }
+}
+
+/// Messages from one sender, oldest first.
+struct SenderQueue<const QUEUE_DEPTH: usize, const MESSAGE_LEN: usize> {
+    /// The process instance that sent the queued messages.
+    sender: Option<ProcessId>,
+    messages: [Message<MESSAGE_LEN>; QUEUE_DEPTH],
+    head: usize,
+    count: usize,
+}
+
+impl<const QUEUE_DEPTH: usize, const MESSAGE_LEN: usize> Default
+    for SenderQueue<QUEUE_DEPTH, MESSAGE_LEN>
+{
+    fn default() -> Self {
+        SenderQueue {
+            sender: None,
+            messages: [Message::default(); QUEUE_DEPTH],
+            head: 0,
+            count: 0,
+        }
+    }
+}
+
+impl<const QUEUE_DEPTH: usize, const MESSAGE_LEN: usize> SenderQueue<QUEUE_DEPTH, MESSAGE_LEN> {
+    /// Return the slot for the next message, or `None` if the queue is full.
+    /// The message is only added to the queue by `push()`.
+    fn back(&mut self) -> Option<&mut Message<MESSAGE_LEN>> {
+        if self.count == QUEUE_DEPTH {
+            return None;
+        }
+        Some(&mut self.messages[(self.head + self.count) % QUEUE_DEPTH])
+    }
+
+    fn push(&mut self) {
+        if self.count < QUEUE_DEPTH {
+            self.count += 1;
+        }
+    }
+
+    fn front(&self) -> Option<&Message<MESSAGE_LEN>> {
+        (self.count > 0).then(|| &self.messages[self.head])
+    }
+
+    fn pop(&mut self) {
+        if self.count > 0 {
+            self.head = (self.head + 1) % QUEUE_DEPTH;
+            self.count -= 1;
+        }
+    }
+
+    /// Drop all messages, which were sent by a process that is gone.
+    fn clear(&mut self) {
+        self.sender = None;
+        self.head = 0;
+        self.count = 0;
+    }
+}
+
+/// State that is stored in each process's grant region.
+struct Mailbox<const NUM_PROCS: usize, const QUEUE_DEPTH: usize, const MESSAGE_LEN: usize> {
+    /// Queues of messages to this process, indexed by the handle of the
+    /// sender.
+    queues: [SenderQueue<QUEUE_DEPTH, MESSAGE_LEN>; NUM_PROCS],
+    /// The sender whose queue is checked first when receiving from any
+    /// sender, so that all senders are served in turn.
+    next_sender: usize,
+    /// The process this process has called and is waiting for a reply from.
+    awaiting_reply: Option<ProcessId>,
+}
+
+impl<const NUM_PROCS: usize, const QUEUE_DEPTH: usize, const MESSAGE_LEN: usize> Default
+    for Mailbox<NUM_PROCS, QUEUE_DEPTH, MESSAGE_LEN>
+{
+    fn default() -> Self {
+        Mailbox {
+            queues: core::array::from_fn(|_| SenderQueue::default()),
+            next_sender: 0,
+            awaiting_reply: None,
+        }
+    }
+}
+
+impl<const NUM_PROCS: usize, const QUEUE_DEPTH: usize, const MESSAGE_LEN: usize>
+    Mailbox<NUM_PROCS, QUEUE_DEPTH, MESSAGE_LEN>
+{
+    /// Queue a message of `len` bytes from `sender`, whose handle is
+    /// `handle`. `fill` copies the message into the buffer it is passed.
+    /// Returns `BUSY` if the queue of the sender is full.
+    fn deliver(
+        &mut self,
+        sender: ProcessId,
+        handle: usize,
+        len: usize,
+        fill: impl FnOnce(&mut [u8]) -> Result<(), ErrorCode>,
+    ) -> Result<(), ErrorCode> {
+        let queue = self.queues.get_mut(handle).ok_or(ErrorCode::INVAL)?;
+        if queue.sender != Some(sender) {
+            // The messages are from an earlier process with this handle.
+            queue.clear();
+            queue.sender = Some(sender);
+        }
+        let message = queue.back().ok_or(ErrorCode::BUSY)?;
+        fill(&mut message.data[..len])?;
+        message.len = len;
+        queue.push();
+        Ok(())
+    }
+
+    /// Take the oldest message from the sender with handle `sender`, or from
+    /// the next sender in turn if `sender` is `None`. `current` returns the
+    /// live process with a handle, and queues of other processes are dropped.
+    /// `copy` copies the message out; if it fails the message stays queued.
+    /// Returns the handle of the sender and the message length.
+    fn take(
+        &mut self,
+        sender: Option<usize>,
+        current: impl Fn(usize) -> Option<ProcessId>,
+        copy: impl FnOnce(&[u8]) -> Result<(), ErrorCode>,
+    ) -> Result<(usize, usize), ErrorCode> {
+        for (handle, queue) in self.queues.iter_mut().enumerate() {
+            if queue.sender.is_some() && queue.sender != current(handle) {
+                queue.clear();
+            }
+        }
+        let sender = match sender {
+            Some(sender) => sender,
+            None => (0..NUM_PROCS)
+                .map(|i| (self.next_sender + i) % NUM_PROCS)
+                .find(|&s| self.queues[s].front().is_some())
+                .ok_or(ErrorCode::FAIL)?,
+        };
+        let queue = self.queues.get_mut(sender).ok_or(ErrorCode::INVAL)?;
+        let message = queue.front().ok_or(ErrorCode::FAIL)?;
+        let len = message.len;
+        copy(&message.data[..len])?;
+        queue.pop();
+        self.next_sender = (sender + 1) % NUM_PROCS;
+        Ok((sender, len))
+    }
+
+    /// Whether this process is waiting for a reply from `service`.
+    fn expects_reply(&self, service: ProcessId) -> bool {
+        self.awaiting_reply == Some(service)
+    }
+}
+
+/// The message-passing IPC mechanism struct.
+pub struct MailboxIpc<const NUM_PROCS: usize, const QUEUE_DEPTH: usize, const MESSAGE_LEN: usize> {
+    /// The grant regions for each process that hold its mailbox.
+    data: Grant<
+        Mailbox<NUM_PROCS, QUEUE_DEPTH, MESSAGE_LEN>,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// Decides which processes can communicate.
+    policy: OptionalCell<&'static dyn IPCPolicy>,
+}
+
+impl<const NUM_PROCS: usize, const QUEUE_DEPTH: usize, const MESSAGE_LEN: usize>
+    MailboxIpc<NUM_PROCS, QUEUE_DEPTH, MESSAGE_LEN>
+{
+    pub fn new(
+        kernel: &'static Kernel,
+        driver_num: usize,
+        capability: &dyn MemoryAllocationCapability,
+    ) -> Self {
+        Self {
+            data: kernel.create_grant(driver_num, capability),
+            policy: OptionalCell::empty(),
+        }
+    }
+
+    /// Set the policy that decides which processes can look each other up
+    /// and send each other messages. The process sending a message is the
+    /// client and the receiver is the service.
+    pub fn set_policy(&self, policy: &'static dyn IPCPolicy) {
+        self.policy.set(policy);
+    }
+
+    /// Ask the policy whether `client` can look up `service`.
+    fn can_discover(&self, client: ProcessId, service: &dyn Process) -> bool {
+        self.policy.map_or(true, |policy| {
+            self.data
+                .kernel
+                .process_map_or(false, client, |client| policy.can_discover(client, service))
+        })
+    }
+
+    /// Return the process with the handle `handle` if it has not terminated.
+    fn lookup_running(&self, handle: usize) -> Option<ProcessId> {
+        self.data
+            .kernel
+            .process_until(|p| match p.processid().index() {
+                Some(i) if i == handle && handle < NUM_PROCS && p.is_running() => {
+                    Some(p.processid())
+                }
+                _ => None,
+            })
+    }
+
+    /// Return the process with the handle `handle`.
+    fn lookup(&self, handle: usize) -> Option<ProcessId> {
+        self.data
+            .kernel
+            .process_until(|p| match p.processid().index() {
+                Some(i) if i == handle && handle < NUM_PROCS => Some(p.processid()),
+                _ => None,
+            })
+    }
+
+    /// Return the handle of the process with the package name passed to
+    /// `allow_readonly`.
+    fn discover_by_name(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        self.data
+            .enter(processid, |_, kernel_data| {
+                let search = kernel_data.get_readonly_processbuffer(ro_allow::SEARCH)?;
+                search
+                    .enter(|name| {
+                        self.data.kernel.process_until(|p| {
+                            let s = p.get_process_name().as_bytes();
+                            if s.len() == name.len()
+                                && s.iter().zip(name.iter()).all(|(c1, c2)| *c1 == c2.get())
+                                && self.can_discover(processid, p)
+                            {
+                                p.processid().index()
+                            } else {
+                                None
+                            }
+                        })
+                    })?
+                    .ok_or(ErrorCode::NODEVICE)
+            })
+            .unwrap_or(Err(ErrorCode::NOMEM))
+    }
+
+    /// Return the handle of the process with the fixed `ShortID` `short_id`.
+    fn discover_by_short_id(
+        &self,
+        processid: ProcessId,
+        short_id: usize,
+    ) -> Result<usize, ErrorCode> {
+        self.data
+            .kernel
+            .process_until(|p| match p.short_app_id() {
+                ShortID::Fixed(id)
+                    if id.get() as usize == short_id && self.can_discover(processid, p) =>
+                {
+                    p.processid().index()
+                }
+                _ => None,
+            })
+            .ok_or(ErrorCode::NODEVICE)
+    }
+
+    /// Copy `len` bytes of the message buffer of `sender` into the mailbox
+    /// of the process with handle `target`. If `call` is set, the sender
+    /// waits for a reply from the target.
+    fn send(
+        &self,
+        sender: ProcessId,
+        target: usize,
+        len: usize,
+        call: bool,
+    ) -> Result<(), ErrorCode> {
+        let sender_handle = sender.index().ok_or(ErrorCode::FAIL)?;
+        let target_id = self.lookup(target).ok_or(ErrorCode::INVAL)?;
+        if target_id == sender {
+            return Err(ErrorCode::INVAL);
+        }
+        let allowed = self.policy.map_or(true, |policy| {
+            self.data.kernel.process_map_or(false, sender, |client| {
+                self.data
+                    .kernel
+                    .process_map_or(false, target_id, |service| {
+                        policy.can_notify(client, service)
+                    })
+            })
+        });
+        if !allowed {
+            return Err(ErrorCode::INVAL);
+        }
+        if len > MESSAGE_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+
+        self.data
+            .enter(sender, |sender_mailbox, sender_data| {
+                let result = self
+                    .data
+                    .enter(target_id, |target_mailbox, target_data| {
+                        let message = sender_data.get_readonly_processbuffer(ro_allow::MESSAGE)?;
+                        target_mailbox.deliver(sender, sender_handle, len, |buffer| {
+                            message.enter(|data| {
+                                data.get(0..len)
+                                    .ok_or(ErrorCode::SIZE)
+                                    .map(|data| data.copy_to_slice(buffer))
+                            })?
+                        })?;
+                        let _ =
+                            target_data.schedule_upcall(upcall::MESSAGE, (sender_handle, len, 0));
+                        Ok(())
+                    })
+                    .unwrap_or(Err(ErrorCode::NOMEM));
+                if result.is_ok() && call {
+                    sender_mailbox.awaiting_reply = Some(target_id);
+                }
+                result
+            })
+            .unwrap_or(Err(ErrorCode::NOMEM))
+    }
+
+    /// Copy the oldest message from `sender`, or from the next sender in
+    /// turn if `sender` is `ANY_SENDER`, into the receive buffer of
+    /// `receiver`. Returns the handle of the sender and the message length.
+    fn receive(&self, receiver: ProcessId, sender: usize) -> Result<(usize, usize), ErrorCode> {
+        if sender != ANY_SENDER && sender >= NUM_PROCS {
+            return Err(ErrorCode::INVAL);
+        }
+        self.data
+            .enter(receiver, |mailbox, kernel_data| {
+                let buffer = kernel_data.get_readwrite_processbuffer(rw_allow::RECEIVE)?;
+                mailbox.take(
+                    (sender != ANY_SENDER).then_some(sender),
+                    |handle| self.lookup_running(handle),
+                    |message| {
+                        buffer.mut_enter(|data| {
+                            data.get(0..message.len())
+                                .ok_or(ErrorCode::SIZE)
+                                .map(|data| data.copy_from_slice(message))
+                        })?
+                    },
+                )
+            })
+            .unwrap_or(Err(ErrorCode::NOMEM))
+    }
+
+    /// Copy `len` bytes of the message buffer of `service` into the reply
+    /// buffer of the process with handle `client`, which must be waiting for
+    /// a reply from `service`.
+    fn reply(&self, service: ProcessId, client: usize, len: usize) -> Result<(), ErrorCode> {
+        let service_handle = service.index().ok_or(ErrorCode::FAIL)?;
+        let client_id = self.lookup(client).ok_or(ErrorCode::INVAL)?;
+        if client_id == service {
+            return Err(ErrorCode::INVAL);
+        }
+        if len > MESSAGE_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+
+        self.data
+            .enter(service, |_, service_data| {
+                self.data
+                    .enter(client_id, |client_mailbox, client_data| {
+                        if !client_mailbox.expects_reply(service) {
+                            return Err(ErrorCode::INVAL);
+                        }
+                        let message = service_data.get_readonly_processbuffer(ro_allow::MESSAGE)?;
+                        client_data
+                            .get_readwrite_processbuffer(rw_allow::REPLY)?
+                            .mut_enter(|reply| {
+                                message.enter(|message| {
+                                    match (message.get(0..len), reply.get(0..len)) {
+                                        (Some(message), Some(reply)) => {
+                                            for (r, m) in reply.iter().zip(message.iter()) {
+                                                r.set(m.get());
+                                            }
+                                            Ok(())
+                                        }
+                                        _ => Err(ErrorCode::SIZE),
+                                    }
+                                })
+                            })???;
+                        client_mailbox.awaiting_reply = None;
+                        let _ =
+                            client_data.schedule_upcall(upcall::REPLY, (service_handle, len, 0));
+                        Ok(())
+                    })
+                    .unwrap_or(Err(ErrorCode::NOMEM))
+            })
+            .unwrap_or(Err(ErrorCode::NOMEM))
+    }
+}
+
+impl<const NUM_PROCS: usize, const QUEUE_DEPTH: usize, const MESSAGE_LEN: usize> SyscallDriver
+    for MailboxIpc<NUM_PROCS, QUEUE_DEPTH, MESSAGE_LEN>
+{
+    /// Looks up processes and sends, receives and replies to messages.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check, always returns Ok(())
+    /// - `1`: Look up the process with the package name passed to
+    ///        `allow_readonly` 1. Returns its handle.
+    /// - `2`: Look up the process with the fixed `ShortID` `arg1`. Returns
+    ///        its handle.
+    /// - `3`: Send the first `arg2` bytes of the buffer passed to
+    ///        `allow_readonly` 0 to the process with handle `arg1`. Returns
+    ///        `BUSY` if the queue for this process in the receiving mailbox is
+    ///        full.
+    /// - `4`: Like `3`, but the receiver is expected to reply. The reply is
+    ///        copied into the buffer passed to `allow_readwrite` 1 and
+    ///        signaled with upcall 1. A new call replaces an earlier one that
+    ///        is still waiting for its reply.
+    /// - `5`: Copy the oldest message from the process with handle `arg1`,
+    ///        or from any process if `arg1` is `usize::MAX`, into the buffer
+    ///        passed to `allow_readwrite` 0. Returns the handle of the sender
+    ///        and the length of the message, or `FAIL` if there is no
+    ///        message. The message stays queued if the buffer is too short.
+    /// - `6`: Reply with the first `arg2` bytes of the buffer passed to
+    ///        `allow_readonly` 0 to the process with handle `arg1`, which
+    ///        must be waiting for a reply from this process.
+    fn command(
+        &self,
+        command_number: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_number {
+            0 => CommandReturn::success(),
+            1 => self
+                .discover_by_name(processid)
+                .map_or_else(CommandReturn::failure, |handle| {
+                    CommandReturn::success_u32(handle as u32)
+                }),
+            2 => self
+                .discover_by_short_id(processid, arg1)
+                .map_or_else(CommandReturn::failure, |handle| {
+                    CommandReturn::success_u32(handle as u32)
+                }),
+            3 => self.send(processid, arg1, arg2, false).into(),
+            4 => self.send(processid, arg1, arg2, true).into(),
+            5 => self
+                .receive(processid, arg1)
+                .map_or_else(CommandReturn::failure, |(sender, len)| {
+                    CommandReturn::success_u32_u32(sender as u32, len as u32)
+                }),
+            6 => self.reply(processid, arg1, arg2).into(),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), crate::process::Error> {
+        self.data.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::process::ProcessSlot;
+    use std::boxed::Box;
+
+    type TestMailbox = Mailbox<4, 2, 8>;
+
+    fn kernel() -> &'static Kernel {
+        let processes: &'static [ProcessSlot] = &[];
+        Box::leak(Box::new(Kernel::new(processes)))
+    }
+
+    fn send(mailbox: &mut TestMailbox, sender: ProcessId, data: &[u8]) -> Result<(), ErrorCode> {
+        mailbox.deliver(sender, sender.index, data.len(), |buffer| {
+            buffer.copy_from_slice(data);
+            Ok(())
+        })
+    }
+
+    fn receive(
+        mailbox: &mut TestMailbox,
+        sender: Option<usize>,
+        running: &[ProcessId],
+    ) -> Result<(usize, std::vec::Vec<u8>), ErrorCode> {
+        let mut received = std::vec::Vec::new();
+        let (handle, len) = mailbox.take(
+            sender,
+            |handle| running.iter().find(|p| p.index == handle).copied(),
+            |message| {
+                received.extend_from_slice(message);
+                Ok(())
+            },
+        )?;
+        assert_eq!(len, received.len());
+        Ok((handle, received))
+    }
+
+    #[test]
+    fn messages_arrive_in_order() {
+        let kernel = kernel();
+        let a = ProcessId::new(kernel, 10, 0);
+        let mut mailbox = TestMailbox::default();
+        send(&mut mailbox, a, b"one").unwrap();
+        send(&mut mailbox, a, b"two").unwrap();
+        assert_eq!(
+            receive(&mut mailbox, Some(0), &[a]),
+            Ok((0, b"one".to_vec()))
+        );
+        assert_eq!(receive(&mut mailbox, None, &[a]), Ok((0, b"two".to_vec())));
+        assert_eq!(receive(&mut mailbox, None, &[a]), Err(ErrorCode::FAIL));
+    }
+
+    #[test]
+    fn full_queue_is_busy_for_its_sender_only() {
+        let kernel = kernel();
+        let a = ProcessId::new(kernel, 10, 0);
+        let b = ProcessId::new(kernel, 11, 1);
+        let mut mailbox = TestMailbox::default();
+        send(&mut mailbox, a, b"1").unwrap();
+        send(&mut mailbox, a, b"2").unwrap();
+        assert_eq!(send(&mut mailbox, a, b"3"), Err(ErrorCode::BUSY));
+        assert_eq!(send(&mut mailbox, b, b"x"), Ok(()));
+
+        // Receiving makes room again.
+        receive(&mut mailbox, Some(0), &[a, b]).unwrap();
+        assert_eq!(send(&mut mailbox, a, b"3"), Ok(()));
+    }
+
+    #[test]
+    fn failed_copy_keeps_message() {
+        let kernel = kernel();
+        let a = ProcessId::new(kernel, 10, 0);
+        let mut mailbox = TestMailbox::default();
+        send(&mut mailbox, a, b"hello").unwrap();
+        assert_eq!(
+            mailbox.take(None, |_| Some(a), |_| Err(ErrorCode::SIZE)),
+            Err(ErrorCode::SIZE)
+        );
+        assert_eq!(
+            receive(&mut mailbox, None, &[a]),
+            Ok((0, b"hello".to_vec()))
+        );
+    }
+
+    #[test]
+    fn any_sender_is_served_round_robin() {
+        let kernel = kernel();
+        let a = ProcessId::new(kernel, 10, 0);
+        let b = ProcessId::new(kernel, 11, 1);
+        let c = ProcessId::new(kernel, 12, 3);
+        let running = [a, b, c];
+        let mut mailbox = TestMailbox::default();
+        send(&mut mailbox, a, b"a1").unwrap();
+        send(&mut mailbox, a, b"a2").unwrap();
+        send(&mut mailbox, b, b"b1").unwrap();
+        send(&mut mailbox, c, b"c1").unwrap();
+
+        let order: std::vec::Vec<_> = (0..4)
+            .map(|_| receive(&mut mailbox, None, &running).unwrap().1)
+            .collect();
+        assert_eq!(
+            order,
+            [
+                b"a1".to_vec(),
+                b"b1".to_vec(),
+                b"c1".to_vec(),
+                b"a2".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn messages_of_gone_sender_are_dropped() {
+        let kernel = kernel();
+        let a = ProcessId::new(kernel, 10, 0);
+        let restarted = ProcessId::new(kernel, 20, 0);
+        let mut mailbox = TestMailbox::default();
+        send(&mut mailbox, a, b"old").unwrap();
+
+        // The sender restarted, so its message is not attributed to the new
+        // instance.
+        assert_eq!(
+            receive(&mut mailbox, None, &[restarted]),
+            Err(ErrorCode::FAIL)
+        );
+
+        // The sender terminated.
+        send(&mut mailbox, restarted, b"new").unwrap();
+        assert_eq!(receive(&mut mailbox, Some(0), &[]), Err(ErrorCode::FAIL));
+    }
+
+    #[test]
+    fn new_instance_does_not_inherit_queue() {
+        let kernel = kernel();
+        let a = ProcessId::new(kernel, 10, 0);
+        let restarted = ProcessId::new(kernel, 20, 0);
+        let mut mailbox = TestMailbox::default();
+        send(&mut mailbox, a, b"1").unwrap();
+        send(&mut mailbox, a, b"2").unwrap();
+        assert_eq!(send(&mut mailbox, restarted, b"3"), Ok(()));
+        assert_eq!(
+            receive(&mut mailbox, None, &[restarted]),
+            Ok((0, b"3".to_vec()))
+        );
+        assert_eq!(
+            receive(&mut mailbox, None, &[restarted]),
+            Err(ErrorCode::FAIL)
+        );
+    }
+
+    #[test]
+    fn reply_only_from_called_instance() {
+        let kernel = kernel();
+        let service = ProcessId::new(kernel, 10, 1);
+        let restarted = ProcessId::new(kernel, 20, 1);
+        let mut mailbox = TestMailbox::default();
+        assert!(!mailbox.expects_reply(service));
+        mailbox.awaiting_reply = Some(service);
+        assert!(mailbox.expects_reply(service));
+        assert!(!mailbox.expects_reply(restarted));
+    }
+}
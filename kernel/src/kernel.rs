@@ -15,6 +15,7 @@ use core::slice;
 
 use crate::capabilities;
 use crate::config;
+use crate::crash_log::{CrashRecord, CrashRecorder};
 use crate::debug;
 use crate::deferred_call::DeferredCall;
 use crate::errorcode::ErrorCode;
@@ -30,12 +31,16 @@ use crate::platform::scheduler_timer::SchedulerTimer;
 use crate::platform::watchdog::WatchDog;
 use crate::process::{self, Process, ProcessId, ShortID, Task};
 use crate::process_checker::{self, CredentialsCheckingPolicy};
+use crate::process_checkpoint::ProcessRestore;
 use crate::process_loading::ProcessLoadError;
+use crate::process_policies::{ProcessMemoryQuota, ProcessMemoryQuotaPolicy};
+use crate::sandbox::{self, ProcessSandboxPolicy, SandboxProfile, ViolationKind};
 use crate::scheduler::{Scheduler, SchedulingDecision};
 use crate::syscall::SyscallDriver;
 use crate::syscall::{ContextSwitchReason, SyscallReturn};
 use crate::syscall::{Syscall, YieldCall};
 use crate::syscall_driver::CommandReturn;
+use crate::syscall_trace::{SyscallTracer, TraceEvent};
 use crate::upcall::{Upcall, UpcallId};
 use crate::utilities::cells::{NumericCellExt, OptionalCell};
 
@@ -50,7 +55,7 @@ pub(crate) const MIN_QUANTA_THRESHOLD_US: u32 = 500;
 /// Main object for the kernel. Each board will need to create one.
 pub struct Kernel {
     /// This holds a pointer to the static array of Process pointers.
-    processes: &'static [Option<&'static dyn process::Process>],
+    processes: &'static [process::ProcessSlot],
 
     /// A counter which keeps track of how many process identifiers have been
     /// created. This is used to create new unique identifiers for processes.
@@ -70,6 +75,25 @@ pub struct Kernel {
     init_cap: KernelProcessInitCapability,
 
     checker: ProcessCheckerMachine,
+
+    /// How much memory processes can use. Processes are not limited if no
+    /// policy is set.
+    memory_quota_policy: OptionalCell<&'static dyn ProcessMemoryQuotaPolicy>,
+
+    /// Restricts the system calls and upcalls of untrusted processes.
+    /// Processes are not sandboxed if no policy is set.
+    sandbox_policy: OptionalCell<&'static dyn ProcessSandboxPolicy>,
+
+    /// Resumes processes from snapshots saved before a reboot instead of
+    /// starting them from their init function.
+    process_restore: OptionalCell<&'static dyn ProcessRestore>,
+
+    /// Persists a record of every process fault, if set.
+    crash_recorder: OptionalCell<&'static dyn CrashRecorder>,
+
+    /// Receives a record of every system call, context switch and upcall, if
+    /// set.
+    syscall_tracer: OptionalCell<&'static dyn SyscallTracer>,
 }
 
 /// Represents the different outcomes when trying to allocate a grant region
@@ -99,7 +123,7 @@ struct KernelProcessApprovalCapability {}
 unsafe impl capabilities::ProcessApprovalCapability for KernelProcessApprovalCapability {}
 
 impl Kernel {
-    pub fn new(processes: &'static [Option<&'static dyn process::Process>]) -> Kernel {
+    pub fn new(processes: &'static [process::ProcessSlot]) -> Kernel {
         Kernel {
             processes,
             process_identifier_max: Cell::new(0),
@@ -113,6 +137,11 @@ impl Kernel {
                 processes: processes,
                 approve_cap: KernelProcessApprovalCapability {},
             },
+            memory_quota_policy: OptionalCell::empty(),
+            sandbox_policy: OptionalCell::empty(),
+            process_restore: OptionalCell::empty(),
+            crash_recorder: OptionalCell::empty(),
+            syscall_tracer: OptionalCell::empty(),
         }
     }
 
@@ -123,12 +152,12 @@ impl Kernel {
         // However, we are not guaranteed that the app still exists at that
         // index in the processes array. To avoid additional overhead, we do the
         // lookup and check here, rather than calling `.index()`.
-        match self.processes.get(processid.index) {
+        match self.processes.get(processid.index).map(|p| p.get()) {
             Some(Some(process)) => {
                 // Check that the process stored here matches the identifier
                 // in the `processid`.
                 if process.processid() == processid {
-                    Some(*process)
+                    Some(process)
                 } else {
                     None
                 }
@@ -194,9 +223,9 @@ impl Kernel {
         F: FnMut(&dyn process::Process),
     {
         for process in self.processes.iter() {
-            match process {
+            match process.get() {
                 Some(p) => {
-                    closure(*p);
+                    closure(p);
                 }
                 None => {}
             }
@@ -207,13 +236,11 @@ impl Kernel {
     pub(crate) fn get_process_iter(
         &self,
     ) -> core::iter::FilterMap<
-        core::slice::Iter<Option<&dyn process::Process>>,
-        fn(&Option<&'static dyn process::Process>) -> Option<&'static dyn process::Process>,
+        core::slice::Iter<process::ProcessSlot>,
+        fn(&process::ProcessSlot) -> Option<&'static dyn process::Process>,
     > {
-        fn keep_some(
-            &x: &Option<&'static dyn process::Process>,
-        ) -> Option<&'static dyn process::Process> {
-            x
+        fn keep_some(x: &process::ProcessSlot) -> Option<&'static dyn process::Process> {
+            x.get()
         }
         self.processes.iter().filter_map(keep_some)
     }
@@ -232,9 +259,9 @@ impl Kernel {
         F: FnMut(&dyn process::Process),
     {
         for process in self.processes.iter() {
-            match process {
+            match process.get() {
                 Some(p) => {
-                    closure(*p);
+                    closure(p);
                 }
                 None => {}
             }
@@ -249,9 +276,9 @@ impl Kernel {
         F: Fn(&dyn process::Process) -> Option<T>,
     {
         for process in self.processes.iter() {
-            match process {
+            match process.get() {
                 Some(p) => {
-                    let ret = closure(*p);
+                    let ret = closure(p);
                     if ret.is_some() {
                         return ret;
                     }
@@ -270,7 +297,8 @@ impl Kernel {
     /// verify that the referenced app is still at the correct index.
     pub(crate) fn processid_is_valid(&self, processid: &ProcessId) -> bool {
         self.processes.get(processid.index).map_or(false, |p| {
-            p.map_or(false, |process| process.processid().id() == processid.id())
+            p.get()
+                .map_or(false, |process| process.processid().id() == processid.id())
         })
     }
 
@@ -343,6 +371,58 @@ impl Kernel {
         self.process_identifier_max.get_and_increment()
     }
 
+    /// Returns the index of the first slot in the processes array that does
+    /// not hold a process, if there is one. A process created for that index
+    /// can then be added with `add_process()`.
+    pub fn find_free_process_slot(&self) -> Option<usize> {
+        self.processes.iter().position(|p| p.get().is_none())
+    }
+
+    /// Add a process that was created after the kernel started to the
+    /// processes array. The process is placed at the index stored in its
+    /// `ProcessId`, which must be an empty slot.
+    ///
+    /// Only callers with the `ProcessManagementCapability` can add processes,
+    /// as a process in the array is scheduled and can receive upcalls.
+    pub fn add_process(
+        &self,
+        process: &'static dyn process::Process,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) -> Result<(), ErrorCode> {
+        let slot = self
+            .processes
+            .get(process.processid().index)
+            .ok_or(ErrorCode::INVAL)?;
+        if slot.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        slot.set(Some(process));
+        Ok(())
+    }
+
+    /// Remove the process `processid` from the processes array, so that it is
+    /// no longer scheduled and `ProcessId`s referring to it are no longer
+    /// valid. Returns the removed process.
+    ///
+    /// Only callers with the `ProcessManagementCapability` can remove
+    /// processes.
+    pub fn remove_process(
+        &self,
+        processid: ProcessId,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) -> Result<&'static dyn process::Process, ErrorCode> {
+        let slot = self
+            .processes
+            .get(processid.index)
+            .ok_or(ErrorCode::INVAL)?;
+        let process = slot
+            .get()
+            .filter(|p| p.processid() == processid)
+            .ok_or(ErrorCode::INVAL)?;
+        slot.set(None);
+        Ok(process)
+    }
+
     /// Cause all apps to fault.
     ///
     /// This will call `set_fault_state()` on each app, causing the app to enter
@@ -355,7 +435,7 @@ impl Kernel {
     /// apps.
     pub fn hardfault_all_apps<C: capabilities::ProcessManagementCapability>(&self, _c: &C) {
         for p in self.processes.iter() {
-            p.map(|process| {
+            p.get().map(|process| {
                 process.set_fault_state();
             });
         }
@@ -519,6 +599,15 @@ impl Kernel {
         // inform the scheduler.
         let mut return_reason = process::StoppedExecutingReason::NoWorkLeft;
 
+        // To account for the time the process uses, we read the scheduler
+        // timer before and after the process executes and after the kernel
+        // handled a syscall for it. The timer may only be read once after the
+        // timeslice has expired, so remember when it has.
+        let mut timeslice_expired = false;
+        // Remaining time in the timeslice when the kernel started to handle a
+        // syscall of the process.
+        let mut syscall_start_us: Option<u32> = None;
+
         // Since the timeslice counts both the process's execution time and the
         // time spent in the kernel on behalf of the process (setting it up and
         // handling its syscalls), we intend to keep running the process until
@@ -526,7 +615,15 @@ impl Kernel {
         // no longer wants to execute this process or if it exceeds its
         // timeslice.
         loop {
-            let stop_running = match scheduler_timer.get_remaining_us() {
+            let remaining_us = if timeslice_expired {
+                None
+            } else {
+                scheduler_timer.get_remaining_us()
+            };
+            if let Some(start_us) = syscall_start_us.take() {
+                process.debug_syscall_handled(start_us.saturating_sub(remaining_us.unwrap_or(0)));
+            }
+            let stop_running = match remaining_us {
                 Some(us) => us <= MIN_QUANTA_THRESHOLD_US,
                 None => true,
             };
@@ -568,11 +665,26 @@ impl Kernel {
                         .context_switch_hook(process);
                     process.setup_mpu();
                     chip.mpu().enable_app_mpu();
+                    self.trace(process.processid(), TraceEvent::ContextSwitchIn);
                     scheduler_timer.arm();
                     let context_switch_reason = process.switch_to();
                     scheduler_timer.disarm();
                     chip.mpu().disable_app_mpu();
 
+                    let after_us = scheduler_timer.get_remaining_us();
+                    timeslice_expired = after_us.is_none();
+                    let executed_us = remaining_us
+                        .unwrap_or(0)
+                        .saturating_sub(after_us.unwrap_or(0));
+                    process.debug_executed(executed_us);
+                    self.trace(
+                        process.processid(),
+                        TraceEvent::ContextSwitchOut {
+                            reason: context_switch_reason.as_ref(),
+                            executed_us,
+                        },
+                    );
+
                     // Now the process has returned back to the kernel. Check
                     // why and handle the process as appropriate.
                     match context_switch_reason {
@@ -589,12 +701,14 @@ impl Kernel {
                             }
                         }
                         Some(ContextSwitchReason::SyscallFired { syscall }) => {
+                            syscall_start_us = after_us;
                             self.handle_syscall(resources, process, syscall);
                         }
                         Some(ContextSwitchReason::Interrupted) => {
-                            if scheduler_timer.get_remaining_us().is_none() {
+                            if timeslice_expired {
                                 // This interrupt was a timeslice expiration.
                                 process.debug_timeslice_expired();
+                                process.debug_complete_step();
                                 return_reason = process::StoppedExecutingReason::TimesliceExpired;
                                 break;
                             }
@@ -602,7 +716,6 @@ impl Kernel {
                             // to break to handle the interrupt, continue
                             // executing this process, or switch to another
                             // process.
-                            continue;
                         }
                         None => {
                             // Something went wrong when switching to this
@@ -611,6 +724,13 @@ impl Kernel {
                             process.set_fault_state();
                         }
                     }
+
+                    // A process that is being single-stepped by a debugger
+                    // stops after each return to the kernel.
+                    if process.debug_complete_step() {
+                        return_reason = process::StoppedExecutingReason::Stopped;
+                        break;
+                    }
                 }
                 process::State::Yielded => {
                     // If the process is yielded or hasn't been started it is
@@ -655,6 +775,38 @@ impl Kernel {
                         },
                     }
                 }
+                process::State::YieldedFor(upcall_id) => {
+                    // The process waits for one specific upcall. If it has been
+                    // scheduled, remove it from the task queue and hand its
+                    // arguments to the process as the return value of its
+                    // yield call instead of running the upcall function. Any
+                    // other tasks stay queued until the process yields again.
+                    match process.remove_upcall(upcall_id) {
+                        None => break,
+                        Some(Task::FunctionCall(ccb)) => {
+                            if config::CONFIG.trace_syscalls {
+                                debug!(
+                                    "[{:?}] yield-wait-for[{:#x}:{}] = ({:#x}, {:#x}, {:#x})",
+                                    process.processid(),
+                                    upcall_id.driver_num,
+                                    upcall_id.subscribe_num,
+                                    ccb.argument0,
+                                    ccb.argument1,
+                                    ccb.argument2,
+                                );
+                            }
+                            process.set_syscall_return_value(SyscallReturn::YieldWaitFor(
+                                ccb.argument0,
+                                ccb.argument1,
+                                ccb.argument2,
+                            ));
+                        }
+                        Some(Task::IPC(_)) => {
+                            // `remove_upcall()` only returns function calls.
+                            break;
+                        }
+                    }
+                }
                 process::State::CredentialsApproved => {
                     // The process's credentials are approved and it's
                     // potentially runnable, but actually running
@@ -670,6 +822,17 @@ impl Kernel {
                         if config::CONFIG.debug_process_credentials {
                             debug!("Making process {} runnable", process.get_process_name());
                         }
+                        if config::CONFIG.process_checkpoint
+                            && process.get_restart_count() == 0
+                            && self
+                                .process_restore
+                                .map_or(false, |restore| restore.restore(process))
+                        {
+                            // The process continues from where it was saved
+                            // before the reboot. A restarted process always
+                            // starts from its init function.
+                            continue;
+                        }
                         match process.enqueue_init_task(&self.init_cap) {
                             Ok(_) => { /* All is good, do nothing. */ }
                             Err(e) => {
@@ -704,7 +867,7 @@ impl Kernel {
                     return_reason = process::StoppedExecutingReason::Stopped;
                     break;
                 }
-                process::State::StoppedYielded => {
+                process::State::StoppedYielded | process::State::StoppedYieldedFor(_) => {
                     return_reason = process::StoppedExecutingReason::Stopped;
                     break;
                 }
@@ -716,7 +879,9 @@ impl Kernel {
         let time_executed_us = timeslice_us.map_or(None, |timeslice| {
             // Note, we cannot call `.get_remaining_us()` again if it has previously
             // returned `None`, so we _must_ check the return reason first.
-            if return_reason == process::StoppedExecutingReason::TimesliceExpired {
+            if return_reason == process::StoppedExecutingReason::TimesliceExpired
+                || timeslice_expired
+            {
                 // used the whole timeslice
                 Some(timeslice)
             } else {
@@ -749,6 +914,7 @@ impl Kernel {
     ) {
         // Hook for process debugging.
         process.debug_syscall_called(syscall);
+        self.trace(process.processid(), TraceEvent::Syscall(&syscall));
 
         // Enforce platform-specific syscall filtering here.
         //
@@ -764,7 +930,8 @@ impl Kernel {
         match syscall {
             Syscall::Yield {
                 which: _,
-                address: _,
+                param_a: _,
+                param_b: _,
             } => {} // Yield is not filterable.
             Syscall::Exit {
                 which: _,
@@ -791,6 +958,33 @@ impl Kernel {
 
                     return;
                 }
+
+                // Then check the sandbox profile of the process, if it has
+                // one.
+                if let Some(violation) = self
+                    .sandbox_policy()
+                    .and_then(|policy| sandbox::check_syscall(policy, process, &syscall).err())
+                {
+                    process.debug_sandbox_violation(violation);
+                    let response = match violation.kind {
+                        ViolationKind::RateLimit => ErrorCode::BUSY,
+                        _ => ErrorCode::NODEVICE,
+                    };
+                    process
+                        .set_syscall_return_value(SyscallReturn::failure_for(&syscall, response));
+
+                    if config::CONFIG.trace_syscalls {
+                        debug!(
+                            "[{:?}] Sandboxed: {:?} was rejected with {:?} ({:?})",
+                            process.processid(),
+                            syscall,
+                            response,
+                            violation.kind
+                        );
+                    }
+
+                    return;
+                }
             }
         }
 
@@ -809,18 +1003,34 @@ impl Kernel {
                 }
                 process.set_syscall_return_value(rval);
             }
-            Syscall::Yield { which, address } => {
+            Syscall::Yield {
+                which,
+                param_a,
+                param_b,
+            } => {
                 if config::CONFIG.trace_syscalls {
                     debug!("[{:?}] yield. which: {}", process.processid(), which);
                 }
-                if which > (YieldCall::Wait as usize) {
-                    // Only 0 and 1 are valid, so this is not a valid yield
+                if which > (YieldCall::WaitFor as usize) {
+                    // Only 0, 1 and 2 are valid, so this is not a valid yield
                     // system call, Yield does not have a return value because
                     // it can push a function call onto the stack; just return
                     // control to the process.
                     return;
                 }
+                if which == (YieldCall::WaitFor as usize) {
+                    // Wait for one specific upcall. The process continues once
+                    // that upcall is scheduled, with the upcall's arguments as
+                    // the return value, see the `YieldedFor` state below.
+                    let upcall_id = UpcallId {
+                        driver_num: param_a,
+                        subscribe_num: param_b,
+                    };
+                    process.set_yielded_for_state(upcall_id);
+                    return;
+                }
                 if which == (YieldCall::NoWait as usize) {
+                    let address = param_a as *mut u8;
                     let upcall_triggered = process.has_tasks().into();
                     // Set the "did I trigger upcalls" flag.
                     //
@@ -1358,6 +1568,112 @@ impl Kernel {
     pub fn get_checker(&'static self) -> &'static ProcessCheckerMachine {
         &self.checker
     }
+
+    /// Set the policy that limits how much memory processes can use for their
+    /// heap and for grants.
+    ///
+    /// Only callers with the `ProcessManagementCapability` can set the policy.
+    pub fn set_memory_quota_policy(
+        &self,
+        policy: &'static dyn ProcessMemoryQuotaPolicy,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.memory_quota_policy.set(policy);
+    }
+
+    /// Set the policy that assigns sandbox profiles to untrusted processes.
+    /// Only available if the kernel is built with the `sandbox` feature.
+    ///
+    /// Only callers with the `ProcessManagementCapability` can set the policy.
+    #[cfg(feature = "sandbox")]
+    pub fn set_sandbox_policy(
+        &self,
+        policy: &'static dyn ProcessSandboxPolicy,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.sandbox_policy.set(policy);
+    }
+
+    /// Set the mechanism that resumes processes from snapshots when they are
+    /// first started after a reboot. It is only used if the kernel is built
+    /// with the `process_checkpoint` feature.
+    ///
+    /// Only callers with the `ProcessCheckpointCapability` can set it.
+    pub fn set_process_restore(
+        &self,
+        restore: &'static dyn ProcessRestore,
+        _capability: &dyn capabilities::ProcessCheckpointCapability,
+    ) {
+        self.process_restore.set(restore);
+    }
+
+    /// Set the recorder that persists a record of every process fault, so
+    /// that faults handled by restarting or stopping the process can be
+    /// inspected after a reboot as well.
+    ///
+    /// Only callers with the `ProcessManagementCapability` can set it.
+    pub fn set_crash_recorder(
+        &self,
+        recorder: &'static dyn CrashRecorder,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.crash_recorder.set(recorder);
+    }
+
+    /// Record that `process` faulted with the crash recorder, if one is set.
+    pub(crate) fn record_fault(&self, process: &dyn process::Process) {
+        self.crash_recorder
+            .map(|recorder| recorder.record_crash(CrashRecord::from_fault(process)));
+    }
+
+    /// Set the tracer that records system calls, context switches and
+    /// upcalls. The tracer only receives events if the kernel is built with
+    /// the `syscall_trace` feature.
+    ///
+    /// Only callers with the `ProcessManagementCapability` can set it.
+    pub fn set_syscall_tracer(
+        &self,
+        tracer: &'static dyn SyscallTracer,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.syscall_tracer.set(tracer);
+    }
+
+    /// Report `event` of `processid` to the syscall tracer, if one is set.
+    pub(crate) fn trace(&self, processid: ProcessId, event: TraceEvent) {
+        if config::CONFIG.syscall_trace {
+            self.syscall_tracer
+                .map(|tracer| tracer.trace(processid, event));
+        }
+    }
+
+    /// Returns how much memory `process` can use according to the memory
+    /// quota policy.
+    pub(crate) fn memory_quota(&self, process: &dyn process::Process) -> ProcessMemoryQuota {
+        self.memory_quota_policy
+            .map_or(ProcessMemoryQuota::default(), |policy| {
+                policy.quota(process)
+            })
+    }
+
+    /// Returns the sandbox profile `process` runs under, if any.
+    pub(crate) fn sandbox_profile(
+        &self,
+        process: &dyn process::Process,
+    ) -> Option<&'static SandboxProfile> {
+        self.sandbox_policy()
+            .and_then(|policy| policy.profile(process))
+    }
+
+    /// Returns the sandbox policy, if sandboxing is enabled and a policy is
+    /// set.
+    fn sandbox_policy(&self) -> Option<&'static dyn ProcessSandboxPolicy> {
+        if config::CONFIG.sandbox {
+            self.sandbox_policy.get()
+        } else {
+            None
+        }
+    }
 }
 
 /// Iterates across the `processes` array, checking footers and deciding
@@ -1368,7 +1684,7 @@ pub struct ProcessCheckerMachine {
     process: Cell<usize>,
     footer: Cell<usize>,
     policy: OptionalCell<&'static dyn CredentialsCheckingPolicy<'static>>,
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [process::ProcessSlot],
     approve_cap: KernelProcessApprovalCapability,
 }
 
@@ -1396,7 +1712,13 @@ impl ProcessCheckerMachine {
             // index. In case the array has None entries or the
             // process array changes under us, don't actually trust
             // this value.
-            while proc_index < self.processes.len() && self.processes[proc_index].is_none() {
+            // Processes that have already been checked (e.g., because they
+            // were loaded earlier) are also skipped.
+            while proc_index < self.processes.len()
+                && self.processes[proc_index].get().map_or(true, |p| {
+                    p.get_state() != process::State::CredentialsUnchecked
+                })
+            {
                 proc_index += 1;
                 self.process.set(proc_index);
                 self.footer.set(0);
@@ -1409,9 +1731,11 @@ impl ProcessCheckerMachine {
             let footer_index = self.footer.get();
             // Try to check the next footer.
             let check_result = self.policy.map_or(FooterCheckResult::Error, |c| {
-                self.processes[proc_index].map_or(FooterCheckResult::NoProcess, |p| {
-                    check_footer(p, c, footer_index)
-                })
+                self.processes[proc_index]
+                    .get()
+                    .map_or(FooterCheckResult::NoProcess, |p| {
+                        check_footer(p, c, footer_index)
+                    })
             });
 
             if config::CONFIG.debug_process_credentials {
@@ -1431,7 +1755,7 @@ impl ProcessCheckerMachine {
                     // should be allowed to run.
                     self.policy.map(|policy| {
                         let requires = policy.require_credentials();
-                        let _res = self.processes[proc_index].map_or(
+                        let _res = self.processes[proc_index].get().map_or(
                             Err(ProcessLoadError::InternalError),
                             |p| {
                                 if requires {
@@ -1482,6 +1806,39 @@ impl ProcessCheckerMachine {
     pub fn set_policy(&self, policy: &'static dyn CredentialsCheckingPolicy<'static>) {
         self.policy.replace(policy);
     }
+
+    /// Start checking the credentials of the process in slot `index`, which
+    /// was loaded after the kernel finished checking the processes found at
+    /// boot. If no checking policy was ever set, the board loads processes
+    /// without checking credentials, so the process is approved directly.
+    ///
+    /// Returns `Err(ErrorCode::BUSY)` if the machine is still checking
+    /// processes and would not reach `index`.
+    pub(crate) fn check_process(&self, index: usize) -> Result<(), ErrorCode> {
+        if self.policy.is_none() {
+            return self.processes.get(index).and_then(|p| p.get()).map_or(
+                Err(ErrorCode::INVAL),
+                |p| {
+                    p.mark_credentials_pass(None, ShortID::LocallyUnique, &self.approve_cap)
+                        .or(Err(ErrorCode::FAIL))
+                },
+            );
+        }
+
+        if self.process.get() < self.processes.len() {
+            // Still checking; `next()` will get to this process as long as it
+            // has not already moved past it.
+            return if index >= self.process.get() {
+                Ok(())
+            } else {
+                Err(ErrorCode::BUSY)
+            };
+        }
+
+        self.process.set(index);
+        self.footer.set(0);
+        self.next().map(|_| ()).or(Err(ErrorCode::FAIL))
+    }
 }
 
 // Returns whether a footer is being checked or not, and if not, why.
@@ -1607,7 +1964,7 @@ impl process_checker::Client<'static> for ProcessCheckerMachine {
         }
         match result {
             Ok(process_checker::CheckResult::Accept) => {
-                self.processes[self.process.get()].map(|p| {
+                self.processes[self.process.get()].get().map(|p| {
                     let short_id = self.policy.map_or(ShortID::LocallyUnique, |policy| {
                         policy.to_short_id(&credentials)
                     });
@@ -1620,7 +1977,7 @@ impl process_checker::Client<'static> for ProcessCheckerMachine {
                 self.footer.set(self.footer.get() + 1);
             }
             Ok(process_checker::CheckResult::Reject) => {
-                self.processes[self.process.get()].map(|p| {
+                self.processes[self.process.get()].get().map(|p| {
                     p.mark_credentials_fail(&self.approve_cap);
                 });
                 self.process.set(self.process.get() + 1);
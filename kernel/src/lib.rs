@@ -99,20 +99,26 @@ pub const KERNEL_MINOR_VERSION: u16 = 1;
 pub mod capabilities;
 pub mod collections;
 pub mod component;
+pub mod crash_log;
 pub mod debug;
 pub mod deferred_call;
+pub mod dynamic_process_loading;
 pub mod errorcode;
 pub mod grant;
 pub mod hil;
 pub mod introspection;
 pub mod ipc;
+pub mod ipc_mailbox;
 pub mod platform;
 pub mod process;
 pub mod process_checker;
+pub mod process_checkpoint;
 pub mod processbuffer;
+pub mod sandbox;
 pub mod scheduler;
 pub mod storage_permissions;
 pub mod syscall;
+pub mod syscall_trace;
 pub mod upcall;
 pub mod utilities;
 
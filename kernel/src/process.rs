@@ -4,6 +4,7 @@
 
 //! Types for Tock-compatible processes.
 
+use core::cell::Cell;
 use core::fmt;
 use core::fmt::Write;
 use core::num::NonZeroU32;
@@ -16,6 +17,7 @@ use crate::ipc;
 use crate::kernel::Kernel;
 use crate::platform::mpu::{self};
 use crate::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
+use crate::sandbox::SandboxViolation;
 use crate::storage_permissions;
 use crate::syscall::{self, Syscall, SyscallReturn};
 use crate::upcall::UpcallId;
@@ -23,10 +25,14 @@ use tock_tbf::types::{CommandPermissions, TbfFooterV2Credentials};
 
 // Export all process related types via `kernel::process::`.
 pub use crate::process_loading::ProcessLoadError;
-pub use crate::process_loading::{load_and_check_processes, load_processes};
+pub use crate::process_loading::{
+    load_and_check_processes, load_and_check_processes_with_remainder, load_processes,
+};
 pub use crate::process_policies::{
-    PanicFaultPolicy, ProcessFaultPolicy, RestartFaultPolicy, StopFaultPolicy,
-    StopWithDebugFaultPolicy, ThresholdRestartFaultPolicy, ThresholdRestartThenPanicFaultPolicy,
+    BackoffBounds, BackoffRestartFaultPolicy, DefaultMemoryQuotaPolicy, FaultPolicyRequest,
+    PanicFaultPolicy, ProcessFaultPolicy, ProcessMemoryQuota, ProcessMemoryQuotaPolicy,
+    RestartFaultPolicy, StopFaultPolicy, StopWithDebugFaultPolicy, ThresholdRestartFaultPolicy,
+    ThresholdRestartThenPanicFaultPolicy,
 };
 pub use crate::process_printer::{ProcessPrinter, ProcessPrinterContext, ProcessPrinterText};
 pub use crate::process_standard::ProcessStandard;
@@ -284,6 +290,33 @@ impl BinaryVersion {
     }
 }
 
+/// One entry in the kernel's array of processes.
+///
+/// Boards allocate a static array of `ProcessSlot`s and hand it to the kernel,
+/// schedulers and panic handlers, all of which only read the slots. Only the
+/// kernel crate places a process in or removes a process from a slot, so
+/// loading processes after boot does not need a mutable alias of the array.
+pub struct ProcessSlot {
+    proc: Cell<Option<&'static dyn Process>>,
+}
+
+impl ProcessSlot {
+    /// A slot that does not hold a process. Boards use this to initialize
+    /// their processes array.
+    pub const EMPTY: ProcessSlot = ProcessSlot {
+        proc: Cell::new(None),
+    };
+
+    /// Returns the process stored in this slot, if any.
+    pub fn get(&self) -> Option<&'static dyn Process> {
+        self.proc.get()
+    }
+
+    pub(crate) fn set(&self, process: Option<&'static dyn Process>) {
+        self.proc.set(process);
+    }
+}
+
 /// This trait represents a generic process that the Tock scheduler can
 /// schedule.
 pub trait Process {
@@ -371,6 +404,12 @@ pub trait Process {
     /// Remove all scheduled upcalls for a given upcall id from the task queue.
     fn remove_pending_upcalls(&self, upcall_id: UpcallId);
 
+    /// Remove the oldest scheduled upcall for a given upcall id from the task
+    /// queue and return it. All other tasks stay in the queue in order.
+    ///
+    /// Returns `None` if no upcall with this id is scheduled.
+    fn remove_upcall(&self, upcall_id: UpcallId) -> Option<Task>;
+
     /// Returns the current state the process is in. Common states are "running"
     /// or "yielded".
     fn get_state(&self) -> State;
@@ -385,6 +424,13 @@ pub trait Process {
     /// running.
     fn set_yielded_state(&self);
 
+    /// Move this process from the running state to the yielded-for state,
+    /// where it waits for the upcall `upcall_id` only.
+    ///
+    /// This will fail (i.e. not do anything) if the process was not previously
+    /// running.
+    fn set_yielded_for_state(&self, upcall_id: UpcallId);
+
     /// Move this process from running or yielded state into the stopped state.
     ///
     /// This will fail (i.e. not do anything) if the process was not either
@@ -393,8 +439,8 @@ pub trait Process {
 
     /// Move this stopped process back into its original state.
     ///
-    /// This transitions a process from `StoppedRunning` -> `Running` or
-    /// `StoppedYielded` -> `Yielded`.
+    /// This transitions a process from `StoppedRunning` -> `Running`,
+    /// `StoppedYielded` -> `Yielded` or `StoppedYieldedFor` -> `YieldedFor`.
     fn resume(&self);
 
     /// Put this process in the fault state. The kernel will use its process
@@ -402,6 +448,14 @@ pub trait Process {
     /// process.
     fn set_fault_state(&self);
 
+    /// Replace the policy the kernel uses to decide what to do when this
+    /// process faults.
+    fn set_fault_policy(
+        &self,
+        fault_policy: &'static dyn ProcessFaultPolicy,
+        capability: &dyn capabilities::ProcessDebugCapability,
+    );
+
     /// Returns how many times this process has been restarted.
     fn get_restart_count(&self) -> usize;
 
@@ -573,6 +627,52 @@ pub trait Process {
     /// Returns `None` if the process has no storage permissions.
     fn get_storage_permissions(&self) -> Option<storage_permissions::StoragePermissions>;
 
+    /// Get the `ShortID`s of the processes that may use this process as an
+    /// IPC service, as the number of valid ids and the ids.
+    ///
+    /// Returns `None` if the process does not restrict its IPC clients.
+    fn get_ipc_client_ids(&self) -> Option<(usize, [u32; tock_tbf::types::NUM_IPC_CLIENTS])>;
+
+    /// Get the number of bytes the process requests to be allowed to grow its
+    /// heap by and to allocate for grants, as `(heap, grant)`. A value of
+    /// `u32::MAX` does not limit the respective use. Quota policies may lower
+    /// these limits but should not raise them.
+    ///
+    /// Returns `None` if the process does not request a memory quota.
+    fn get_requested_memory_quota(&self) -> Option<(u32, u32)>;
+
+    /// Get the fault behavior the process requests with the Fault Policy TBF
+    /// header. Only `BackoffRestartFaultPolicy` honors the request, within
+    /// the bounds the board sets; other fault policies ignore it.
+    ///
+    /// Returns `None` if the process does not request a fault behavior.
+    fn get_requested_fault_policy(&self) -> Option<FaultPolicyRequest>;
+
+    /// Returns how many bytes the process has grown its heap by and how many
+    /// bytes have been allocated for its grants, as `(heap, grant)`. Both are
+    /// relative to the memory layout the process started with.
+    fn get_memory_usage(&self) -> (usize, usize);
+
+    /// Get the period and the execution budget per period, both in
+    /// microseconds, that the process declared for real-time scheduling.
+    ///
+    /// Returns `None` if the process is not a periodic real-time process.
+    fn get_real_time_parameters(&self) -> Option<(u32, u32)>;
+
+    /// Get the scheduling priority assigned to this process at runtime, where
+    /// lower values are higher priority.
+    ///
+    /// Returns `None` if no priority was assigned, in which case schedulers
+    /// that use priorities fall back to their own default.
+    fn get_priority(&self) -> Option<u32>;
+
+    /// Assign a scheduling priority to this process, or clear it with `None`.
+    fn set_priority(
+        &self,
+        priority: Option<u32>,
+        capability: &dyn capabilities::ProcessDebugCapability,
+    );
+
     // mpu
 
     /// Configure the MPU to use the process's allocated regions.
@@ -693,6 +793,12 @@ pub trait Process {
     /// Useful for debugging/inspecting the system.
     fn grant_allocated_count(&self) -> Option<usize>;
 
+    /// Return the driver number and the size in bytes of the grant
+    /// `grant_num`, if the process is active and the grant is allocated.
+    ///
+    /// Useful for debugging/inspecting the system.
+    fn grant_allocation(&self, grant_num: usize) -> Option<(usize, usize)>;
+
     /// Get the grant number (grant_num) associated with a given driver number
     /// if there is a grant associated with that driver_num.
     fn lookup_grant_from_driver_num(&self, driver_num: usize) -> Result<usize, Error>;
@@ -754,6 +860,114 @@ pub trait Process {
     /// binary representation. Returns `ErrorCode::FAIL` on an internal error.
     fn get_stored_state(&self, out: &mut [u8]) -> Result<usize, ErrorCode>;
 
+    /// Return the program counter, link register and stack pointer the
+    /// process had when it last returned to the kernel, if the architecture
+    /// can determine them.
+    fn get_stored_registers(&self) -> Option<syscall::ProcessRegisters>;
+
+    /// Copy the top of the process's stack, starting at its stored stack
+    /// pointer, into `out`. Returns the number of bytes copied, which is zero
+    /// if the stack pointer is not within the process's memory.
+    fn get_stack_snippet(&self, out: &mut [u8]) -> usize;
+
+    /// Copy the memory at `address` into `out`, for debugging. `address` must
+    /// be within the process's flash or the memory the process can access
+    /// (from the start of its RAM to its app break); the grant region and
+    /// kernel memory cannot be read. Returns the number of bytes copied,
+    /// which is less than `out.len()` if the region ends first and zero if
+    /// `address` is outside the process.
+    fn debug_read_memory(
+        &self,
+        address: usize,
+        out: &mut [u8],
+        capability: &dyn capabilities::ProcessDebugCapability,
+    ) -> usize;
+
+    /// Copy `data` into the process's memory at `address`, for debugging.
+    /// `address` must be within the memory the process can access; flash and
+    /// the grant region cannot be written. Returns the number of bytes
+    /// copied, which is less than `data.len()` if the memory ends first and
+    /// zero if `address` is outside the process's accessible memory.
+    fn debug_write_memory(
+        &self,
+        address: usize,
+        data: &[u8],
+        capability: &dyn capabilities::ProcessDebugCapability,
+    ) -> usize;
+
+    /// Read register `index` of the process as it was when the process last
+    /// returned to the kernel. Registers are numbered as in the register
+    /// layout GDB uses for the architecture. Returns `None` if there is no
+    /// such register or it cannot be read.
+    fn get_debug_register(&self, index: usize) -> Option<usize>;
+
+    /// Set register `index`, numbered as for `get_debug_register()`, to
+    /// `value` for when the process next runs.
+    ///
+    /// Returns `ErrorCode::INVAL` if there is no such register or it cannot
+    /// be written.
+    fn set_debug_register(
+        &self,
+        index: usize,
+        value: usize,
+        capability: &dyn capabilities::ProcessDebugCapability,
+    ) -> Result<(), ErrorCode>;
+
+    /// Mark whether a debugger is attached to the process. While a debugger
+    /// is attached, a fault stops the process so the debugger can inspect it,
+    /// instead of invoking the fault policy. Detaching cancels a pending
+    /// single step.
+    fn set_debugger_attached(&self, attached: bool);
+
+    /// Whether a debugger is attached to the process.
+    fn is_debugger_attached(&self) -> bool;
+
+    /// Resume the process, and stop it again the next time it returns to the
+    /// kernel, for example because it called a syscall, was interrupted or
+    /// faulted.
+    fn debug_single_step(&self);
+
+    /// Called by the kernel after it handled the process returning to the
+    /// kernel. If the process is being single-stepped, it is stopped and this
+    /// returns `true`.
+    fn debug_complete_step(&self) -> bool;
+
+    /// Copy process-accessible memory into `out`, starting `offset` bytes
+    /// after the start of process memory. Returns the number of bytes copied,
+    /// which is less than `out.len()` only at the process's memory break.
+    ///
+    /// Together with `get_stored_state()` this takes a snapshot of the
+    /// process, so it is only possible while the process is stopped. Returns
+    /// `ErrorCode::OFF` if the process is not in one of the stopped states,
+    /// or `ErrorCode::NOSUPPORT` if the kernel is built without the
+    /// `process_checkpoint` feature.
+    fn get_checkpoint_memory(
+        &self,
+        offset: usize,
+        out: &mut [u8],
+        capability: &dyn capabilities::ProcessCheckpointCapability,
+    ) -> Result<usize, ErrorCode>;
+
+    /// Resume the process from a snapshot instead of starting it from its
+    /// init function. `stored_state` must have been written by
+    /// `get_stored_state()` and `memory` by `get_checkpoint_memory()` of this
+    /// process on a previous boot; the length of `memory` sets the new memory
+    /// break. `state` is the state the process was in when the snapshot was
+    /// taken, and the process continues in the matching running or yielded
+    /// state.
+    ///
+    /// Like `enqueue_init_task()`, this is only possible in the
+    /// `CredentialsApproved` state. Grants and pending tasks are not part of
+    /// the snapshot, so the process resumes without any upcalls, allowed
+    /// buffers, or capsule state.
+    fn restore_checkpoint(
+        &self,
+        stored_state: &[u8],
+        memory: &[u8],
+        state: State,
+        capability: &dyn capabilities::ProcessCheckpointCapability,
+    ) -> Result<(), ErrorCode>;
+
     /// Print out the full state of the process: its memory map, its context,
     /// and the state of the memory protection unit (MPU).
     fn print_full_process(&self, writer: &mut dyn Write);
@@ -772,6 +986,43 @@ pub trait Process {
     /// Increment the number of times the process has exceeded its timeslice.
     fn debug_timeslice_expired(&self);
 
+    /// Returns how many microseconds this process has executed for.
+    ///
+    /// The time is measured with the scheduler timer, so it is only counted
+    /// while the process runs with a timeslice.
+    fn debug_run_time_us(&self) -> u64;
+
+    /// Add `us` microseconds to the time this process has executed for.
+    fn debug_executed(&self, us: u32);
+
+    /// Returns how many microseconds the kernel has spent handling syscalls
+    /// of this process.
+    ///
+    /// Like the run time, this is only counted while the process runs with a
+    /// timeslice.
+    fn debug_syscall_time_us(&self) -> u64;
+
+    /// Add `us` microseconds to the time spent handling syscalls of this
+    /// process.
+    fn debug_syscall_handled(&self, us: u32);
+
+    /// Returns how many times this process did not finish the work of a
+    /// real-time period before its deadline.
+    fn debug_deadline_miss_count(&self) -> usize;
+
+    /// Increment the number of deadlines the process has missed.
+    fn debug_deadline_missed(&self);
+
+    /// Returns how many times this process has violated its sandbox profile.
+    fn debug_sandbox_violation_count(&self) -> usize;
+
+    /// Returns the most recent violation of the sandbox profile of this
+    /// process, or `None` if it has not violated its profile.
+    fn debug_last_sandbox_violation(&self) -> Option<SandboxViolation>;
+
+    /// Record that the process violated its sandbox profile.
+    fn debug_sandbox_violation(&self, violation: SandboxViolation);
+
     /// Increment the number of times the process called a syscall and record
     /// the last syscall that was called.
     fn debug_syscall_called(&self, last_syscall: Syscall);
@@ -896,6 +1147,12 @@ pub enum State {
     /// scheduled again.
     Yielded,
 
+    /// Process stopped executing and returned to the kernel because it called
+    /// the `yield-wait-for` syscall. It only resumes once the upcall with the
+    /// given id is scheduled, and then receives the arguments of the upcall as
+    /// the return value of `yield` instead of running the upcall function.
+    YieldedFor(UpcallId),
+
     /// The process is stopped, and its previous state was Running. This is used
     /// if the kernel forcibly stops a process when it is in the `Running`
     /// state. This state indicates to the kernel not to schedule the process,
@@ -907,6 +1164,11 @@ pub enum State {
     /// process needs to be resumed it should be put back in the `Yield` state.
     StoppedYielded,
 
+    /// The process is stopped, and it was stopped while it was waiting for a
+    /// specific upcall. If this process needs to be resumed it should be put
+    /// back in the `YieldedFor` state.
+    StoppedYieldedFor(UpcallId),
+
     /// The process ran, faulted while running, and is no longer runnable. For a
     /// faulted process to be made runnable, it must first be terminated (to
     /// clean up its state).
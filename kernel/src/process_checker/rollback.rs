@@ -0,0 +1,797 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Rollback protection for application credentials checkers. See the
+//! [AppID TRD](../../doc/reference/trd-appid.md).
+//!
+//! `AppUniqueness` and `BinaryVersion` let the kernel run the newest of
+//! several installed versions of an application, but an older version that
+//! is still correctly signed will run as soon as it is the only one
+//! installed. `AppCheckerRollback` wraps another Credentials Checking Policy
+//! and remembers, in a key-value store, the highest binary version it has
+//! accepted for each application. Binaries with a lower version are rejected
+//! even though their credentials are valid.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let rollback = static_init!(
+//!     kernel::process_checker::rollback::AppCheckerRollback<
+//!         AppCheckerSignature<...>,
+//!         VirtualKVPermissions<...>,
+//!     >,
+//!     kernel::process_checker::rollback::AppCheckerRollback::new(
+//!         checker,
+//!         kv_store,
+//!         StoragePermissions::new_kernel_permissions(&storage_cap),
+//!         key_buffer,
+//!         value_buffer,
+//!     )
+//! );
+//! checker.set_client(rollback);
+//! kv_store.set_client(rollback);
+//! ```
+
+use core::cell::Cell;
+use core::num::NonZeroU32;
+
+use crate::capabilities;
+use crate::hil::kv::{KVClient, KVPermissions};
+use crate::process::{Process, ShortID};
+use crate::process_checker::{AppCredentialsChecker, AppUniqueness, CredentialsCheckingPolicy};
+use crate::process_checker::{CheckResult, Client, Compress};
+use crate::storage_permissions::StoragePermissions;
+use crate::utilities::cells::{OptionalCell, TakeCell};
+use crate::utilities::leasable_buffer::SubSliceMut;
+use crate::ErrorCode;
+use tock_tbf::types::TbfFooterV2Credentials;
+
+/// Prefix of the key storing the version of an application with a fixed
+/// `ShortID`. The key continues with the big-endian `ShortID`.
+const SHORT_ID_KEY_PREFIX: &[u8] = b"tock.rollback.id.";
+/// Prefix of the key storing the version of an application without a fixed
+/// `ShortID`. The key continues with the package name.
+const NAME_KEY_PREFIX: &[u8] = b"tock.rollback.name.";
+/// Length of a stored version in bytes.
+const VERSION_LENGTH: usize = 4;
+
+/// How an application is identified in the version store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RollbackId {
+    /// An application with a fixed `ShortID`.
+    ShortId(NonZeroU32),
+    /// An application without a fixed `ShortID`, identified by the package
+    /// name in its TBF header.
+    PackageName(&'static str),
+}
+
+/// Receives the result of resetting the stored version of an application.
+pub trait RollbackResetClient {
+    /// The stored version for `id` was removed. `NOSUPPORT` means that no
+    /// version was stored for `id`.
+    fn reset_done(&self, result: Result<(), ErrorCode>, id: RollbackId);
+}
+
+/// What the checker is waiting for from the key-value store.
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Waiting for the wrapped checker.
+    Check,
+    /// Reading the highest accepted version of the binary being checked.
+    ReadVersion,
+    /// Storing the version of the binary being checked.
+    WriteVersion,
+    /// Removing the stored version for a reset.
+    Reset(RollbackId),
+}
+
+/// A Credentials Checking Policy that accepts what `checker` accepts, unless
+/// a newer version of the same application was accepted before.
+///
+/// An application is identified by its `ShortID` if `checker` assigns it a
+/// fixed one, otherwise by its package name. Binaries that have neither, or
+/// that do not have a binary version, cannot be tracked. Since they could
+/// otherwise be used to run an old version of any application, they are
+/// rejected unless the board allows them with `set_reject_untracked()`. The
+/// highest version is only updated once the new version has been written to
+/// `kv`; if the store fails the check fails too.
+pub struct AppCheckerRollback<
+    C: 'static + CredentialsCheckingPolicy<'static>,
+    KV: 'static + KVPermissions<'static>,
+> {
+    checker: &'static C,
+    kv: &'static KV,
+    permissions: StoragePermissions,
+    state: Cell<State>,
+    reject_untracked: Cell<bool>,
+    version: Cell<u32>,
+    key: TakeCell<'static, [u8]>,
+    value: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'static dyn Client<'static>>,
+    reset_client: OptionalCell<&'static dyn RollbackResetClient>,
+    credentials: OptionalCell<TbfFooterV2Credentials>,
+    binary: OptionalCell<&'static [u8]>,
+}
+
+impl<C: 'static + CredentialsCheckingPolicy<'static>, KV: 'static + KVPermissions<'static>>
+    AppCheckerRollback<C, KV>
+{
+    /// Create a rollback protected policy around `checker`.
+    ///
+    /// `permissions` are used for all accesses to `kv` and should be kernel
+    /// permissions so that applications cannot change the stored versions.
+    /// `key_buffer` must fit the longest package name plus 19 bytes, and
+    /// `value_buffer` must fit `kv.header_size()` plus 4 bytes.
+    pub fn new(
+        checker: &'static C,
+        kv: &'static KV,
+        permissions: StoragePermissions,
+        key_buffer: &'static mut [u8],
+        value_buffer: &'static mut [u8],
+    ) -> Self {
+        Self {
+            checker,
+            kv,
+            permissions,
+            state: Cell::new(State::Idle),
+            reject_untracked: Cell::new(true),
+            version: Cell::new(0),
+            key: TakeCell::new(key_buffer),
+            value: TakeCell::new(value_buffer),
+            client: OptionalCell::empty(),
+            reset_client: OptionalCell::empty(),
+            credentials: OptionalCell::empty(),
+            binary: OptionalCell::empty(),
+        }
+    }
+
+    /// Set the client that receives `reset_done()` callbacks.
+    pub fn set_reset_client(&self, client: &'static dyn RollbackResetClient) {
+        self.reset_client.set(client);
+    }
+
+    /// Set whether binaries that cannot be tracked, because they have no
+    /// binary version or no identifier, are rejected. They are rejected by
+    /// default. Accepting them lets any binary without a version run,
+    /// including an old release of an application, so this requires the same
+    /// capability as resetting the version of an application.
+    pub fn set_reject_untracked(
+        &self,
+        reject: bool,
+        _capability: &dyn capabilities::ProcessRollbackResetCapability,
+    ) {
+        self.reject_untracked.set(reject);
+    }
+
+    /// Forget the highest accepted version of the application `id`, so that
+    /// an older version of it can run again.
+    ///
+    /// This deliberately re-opens the application to downgrades, so it
+    /// requires a capability. `reset_done()` is called once the version is
+    /// removed. Only applications checked after the reset see its effect.
+    ///
+    /// The possible ErrorCodes are:
+    ///     - `BUSY`: A check or reset is in progress.
+    ///     - `SIZE`: The key for `id` does not fit the key buffer.
+    pub fn reset(
+        &self,
+        id: RollbackId,
+        _capability: &dyn capabilities::ProcessRollbackResetCapability,
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let key = self.make_key(id)?;
+        match self.kv.delete(key, self.permissions) {
+            Ok(()) => {
+                self.state.set(State::Reset(id));
+                Ok(())
+            }
+            Err((key, e)) => {
+                self.key.replace(key.take());
+                Err(e)
+            }
+        }
+    }
+
+    /// Write the key identifying `id` into the key buffer.
+    fn make_key(&self, id: RollbackId) -> Result<SubSliceMut<'static, u8>, ErrorCode> {
+        let buffer = self.key.take().ok_or(ErrorCode::BUSY)?;
+        let short_id;
+        let (prefix, suffix) = match id {
+            RollbackId::ShortId(id) => {
+                short_id = id.get().to_be_bytes();
+                (SHORT_ID_KEY_PREFIX, &short_id[..])
+            }
+            RollbackId::PackageName(name) => (NAME_KEY_PREFIX, name.as_bytes()),
+        };
+        let length = prefix.len() + suffix.len();
+        if length > buffer.len() {
+            self.key.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        buffer[..prefix.len()].copy_from_slice(prefix);
+        buffer[prefix.len()..length].copy_from_slice(suffix);
+        let mut key = SubSliceMut::new(buffer);
+        key.slice(..length);
+        Ok(key)
+    }
+
+    /// Find how the binary is identified in the version store and its
+    /// version. Returns `None` if the binary cannot be tracked.
+    fn tracked_version(
+        &self,
+        credentials: &TbfFooterV2Credentials,
+        binary: &'static [u8],
+    ) -> Option<(RollbackId, NonZeroU32)> {
+        let lengths = binary.get(0..8)?.try_into().ok()?;
+        let (tbf_version, header_length, _) =
+            tock_tbf::parse::parse_tbf_header_lengths(lengths).ok()?;
+        let header = binary.get(..header_length as usize)?;
+        let header = tock_tbf::parse::parse_tbf_header(header, tbf_version).ok()?;
+        let version = NonZeroU32::new(header.get_binary_version())?;
+        let id = match self.checker.to_short_id(credentials) {
+            ShortID::Fixed(id) => RollbackId::ShortId(id),
+            ShortID::LocallyUnique => {
+                RollbackId::PackageName(header.get_package_name().filter(|name| !name.is_empty())?)
+            }
+        };
+        Some((id, version))
+    }
+
+    /// Read the stored version of `id` to compare against `version`.
+    fn read_version(&self, id: RollbackId, version: NonZeroU32) -> Result<(), ErrorCode> {
+        let key = self.make_key(id)?;
+        let value = match self.value.take() {
+            Some(value) => SubSliceMut::new(value),
+            None => {
+                self.key.replace(key.take());
+                return Err(ErrorCode::FAIL);
+            }
+        };
+        match self.kv.get(key, value, self.permissions) {
+            Ok(()) => {
+                self.version.set(version.get());
+                self.state.set(State::ReadVersion);
+                Ok(())
+            }
+            Err((key, value, e)) => {
+                self.key.replace(key.take());
+                self.value.replace(value.take());
+                Err(e)
+            }
+        }
+    }
+
+    /// Store the version of the binary being checked under `key`.
+    fn write_version(&self, key: SubSliceMut<'static, u8>) -> Result<(), ErrorCode> {
+        let header_size = self.kv.header_size();
+        let buffer = match self.value.take() {
+            Some(buffer) if buffer.len() >= header_size + VERSION_LENGTH => buffer,
+            Some(buffer) => {
+                self.value.replace(buffer);
+                self.key.replace(key.take());
+                return Err(ErrorCode::SIZE);
+            }
+            None => {
+                self.key.replace(key.take());
+                return Err(ErrorCode::FAIL);
+            }
+        };
+        buffer[header_size..header_size + VERSION_LENGTH]
+            .copy_from_slice(&self.version.get().to_le_bytes());
+        let mut value = SubSliceMut::new(buffer);
+        value.slice(..header_size + VERSION_LENGTH);
+        match self.kv.set(key, value, self.permissions) {
+            Ok(()) => {
+                self.state.set(State::WriteVersion);
+                Ok(())
+            }
+            Err((key, value, e)) => {
+                self.key.replace(key.take());
+                self.value.replace(value.take());
+                Err(e)
+            }
+        }
+    }
+
+    /// Report the result of the current check to the client.
+    fn check_done(&self, result: Result<CheckResult, ErrorCode>) {
+        self.state.set(State::Idle);
+        if let (Some(credentials), Some(binary)) = (self.credentials.take(), self.binary.take()) {
+            self.client
+                .map(|client| client.check_done(result, credentials, binary));
+        }
+    }
+}
+
+impl<C: 'static + CredentialsCheckingPolicy<'static>, KV: 'static + KVPermissions<'static>>
+    AppCredentialsChecker<'static> for AppCheckerRollback<C, KV>
+{
+    fn require_credentials(&self) -> bool {
+        self.checker.require_credentials()
+    }
+
+    fn check_credentials(
+        &self,
+        credentials: TbfFooterV2Credentials,
+        binary: &'static [u8],
+    ) -> Result<(), (ErrorCode, TbfFooterV2Credentials, &'static [u8])> {
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, credentials, binary));
+        }
+        self.checker
+            .check_credentials(credentials, binary)
+            .map(|()| self.state.set(State::Check))
+    }
+
+    fn set_client(&self, client: &'static dyn Client<'static>) {
+        self.client.replace(client);
+    }
+}
+
+impl<C: 'static + CredentialsCheckingPolicy<'static>, KV: 'static + KVPermissions<'static>>
+    Client<'static> for AppCheckerRollback<C, KV>
+{
+    fn check_done(
+        &self,
+        result: Result<CheckResult, ErrorCode>,
+        credentials: TbfFooterV2Credentials,
+        binary: &'static [u8],
+    ) {
+        let tracked = match result {
+            Ok(CheckResult::Accept) => Some(self.tracked_version(&credentials, binary)),
+            _ => None,
+        };
+        self.credentials.set(credentials);
+        self.binary.set(binary);
+        match tracked {
+            Some(Some((id, version))) => {
+                if let Err(e) = self.read_version(id, version) {
+                    self.check_done(Err(e));
+                }
+            }
+            Some(None) if self.reject_untracked.get() => {
+                self.check_done(Ok(CheckResult::Reject));
+            }
+            _ => self.check_done(result),
+        }
+    }
+}
+
+impl<C: 'static + CredentialsCheckingPolicy<'static>, KV: 'static + KVPermissions<'static>> KVClient
+    for AppCheckerRollback<C, KV>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        mut value: SubSliceMut<'static, u8>,
+    ) {
+        let stored = match result {
+            Ok(()) => {
+                let mut stored = [0; VERSION_LENGTH];
+                match value.as_slice().get(..VERSION_LENGTH) {
+                    Some(bytes) => {
+                        stored.copy_from_slice(bytes);
+                        Ok(u32::from_le_bytes(stored))
+                    }
+                    None => Err(ErrorCode::FAIL),
+                }
+            }
+            // No version of this application was accepted before.
+            Err(ErrorCode::NOSUPPORT) => Ok(0),
+            Err(e) => Err(e),
+        };
+        self.value.replace(value.take());
+
+        match stored {
+            Ok(stored) if self.version.get() < stored => {
+                self.key.replace(key.take());
+                self.check_done(Ok(CheckResult::Reject));
+            }
+            Ok(stored) if self.version.get() == stored => {
+                self.key.replace(key.take());
+                self.check_done(Ok(CheckResult::Accept));
+            }
+            Ok(_) => {
+                if let Err(e) = self.write_version(key) {
+                    self.check_done(Err(e));
+                }
+            }
+            Err(e) => {
+                self.key.replace(key.take());
+                self.check_done(Err(e));
+            }
+        }
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key.replace(key.take());
+        self.value.replace(value.take());
+        self.check_done(result.map(|()| CheckResult::Accept));
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key.replace(key.take());
+        self.value.replace(value.take());
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.key.replace(key.take());
+        self.value.replace(value.take());
+    }
+
+    fn delete_complete(&self, result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.key.replace(key.take());
+        if let State::Reset(id) = self.state.get() {
+            self.state.set(State::Idle);
+            self.reset_client
+                .map(|client| client.reset_done(result, id));
+        }
+    }
+}
+
+impl<C: 'static + CredentialsCheckingPolicy<'static>, KV: 'static + KVPermissions<'static>>
+    AppUniqueness for AppCheckerRollback<C, KV>
+{
+    fn different_identifier(&self, process_a: &dyn Process, process_b: &dyn Process) -> bool {
+        self.checker.different_identifier(process_a, process_b)
+    }
+}
+
+impl<C: 'static + CredentialsCheckingPolicy<'static>, KV: 'static + KVPermissions<'static>> Compress
+    for AppCheckerRollback<C, KV>
+{
+    fn to_short_id(&self, credentials: &TbfFooterV2Credentials) -> ShortID {
+        self.checker.to_short_id(credentials)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::process_checker::Client;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    struct KernelStorage;
+    unsafe impl capabilities::KerneluserStorageCapability for KernelStorage {}
+
+    struct ResetCapability;
+    unsafe impl capabilities::ProcessRollbackResetCapability for ResetCapability {}
+
+    #[derive(Clone, Copy)]
+    enum Pending {
+        Get,
+        Set(u32),
+    }
+
+    /// A key-value store holding a single version, which completes
+    /// operations when `pump()` is called.
+    struct VersionStore {
+        version: Cell<Option<u32>>,
+        writes: Cell<usize>,
+        pending: Cell<Option<Pending>>,
+        key: OptionalCell<SubSliceMut<'static, u8>>,
+        value: OptionalCell<SubSliceMut<'static, u8>>,
+        client: OptionalCell<&'static dyn KVClient>,
+    }
+
+    type Result3 = Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    >;
+
+    impl VersionStore {
+        fn pump(&self) -> bool {
+            let (key, mut value) = match (self.key.take(), self.value.take()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => return false,
+            };
+            self.client.map(|client| match self.pending.take() {
+                Some(Pending::Get) => match self.version.get() {
+                    Some(version) => {
+                        value.as_slice()[..VERSION_LENGTH].copy_from_slice(&version.to_le_bytes());
+                        client.get_complete(Ok(()), key, value);
+                    }
+                    None => client.get_complete(Err(ErrorCode::NOSUPPORT), key, value),
+                },
+                Some(Pending::Set(version)) => {
+                    self.version.set(Some(version));
+                    self.writes.set(self.writes.get() + 1);
+                    client.set_complete(Ok(()), key, value);
+                }
+                None => {}
+            });
+            true
+        }
+
+        fn start(
+            &self,
+            op: Pending,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+        ) -> Result3 {
+            self.pending.set(Some(op));
+            self.key.set(key);
+            self.value.set(value);
+            Ok(())
+        }
+    }
+
+    impl KVPermissions<'static> for VersionStore {
+        fn set_client(&self, client: &'static dyn KVClient) {
+            self.client.set(client);
+        }
+
+        fn get(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result3 {
+            self.start(Pending::Get, key, value)
+        }
+
+        fn set(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            mut value: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result3 {
+            let mut version = [0; VERSION_LENGTH];
+            version.copy_from_slice(&value.as_slice()[..VERSION_LENGTH]);
+            self.start(Pending::Set(u32::from_le_bytes(version)), key, value)
+        }
+
+        fn add(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result3 {
+            Err((key, value, ErrorCode::NOSUPPORT))
+        }
+
+        fn update(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result3 {
+            Err((key, value, ErrorCode::NOSUPPORT))
+        }
+
+        fn delete(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+            Err((key, ErrorCode::NOSUPPORT))
+        }
+
+        fn begin_transaction(&self, _permissions: StoragePermissions) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+
+        fn stage_set(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result3 {
+            Err((key, value, ErrorCode::NOSUPPORT))
+        }
+
+        fn stage_delete(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+            Err((key, ErrorCode::NOSUPPORT))
+        }
+
+        fn commit_transaction(&self) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+
+        fn abort_transaction(&self) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+
+        fn header_size(&self) -> usize {
+            0
+        }
+    }
+
+    struct CheckClient(Cell<Option<Result<CheckResult, ErrorCode>>>);
+
+    impl Client<'static> for CheckClient {
+        fn check_done(
+            &self,
+            result: Result<CheckResult, ErrorCode>,
+            _credentials: TbfFooterV2Credentials,
+            _binary: &'static [u8],
+        ) {
+            self.0.set(Some(result));
+        }
+    }
+
+    /// Build a TBF header with an optional binary version and package name.
+    fn tbf(version: Option<u32>, name: &str) -> &'static [u8] {
+        let mut tlvs = Vec::new();
+        if let Some(version) = version {
+            tlvs.extend_from_slice(&9u16.to_le_bytes());
+            tlvs.extend_from_slice(&20u16.to_le_bytes());
+            for word in [0, 0, 0, 0, version] {
+                tlvs.extend_from_slice(&u32::to_le_bytes(word));
+            }
+        }
+        if !name.is_empty() {
+            tlvs.extend_from_slice(&3u16.to_le_bytes());
+            tlvs.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            tlvs.extend_from_slice(name.as_bytes());
+            tlvs.resize((tlvs.len() + 3) / 4 * 4, 0);
+        }
+        let length = 16 + tlvs.len() as u32;
+        let mut header = Vec::new();
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&(length as u16).to_le_bytes());
+        header.extend_from_slice(&length.to_le_bytes());
+        header.extend_from_slice(&1u32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&tlvs);
+        let checksum = header
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(i, _)| *i != 3)
+            .fold(0, |checksum, (_, word)| {
+                checksum ^ u32::from_le_bytes(word.try_into().unwrap())
+            });
+        header[12..16].copy_from_slice(&checksum.to_le_bytes());
+        Box::leak(header.into_boxed_slice())
+    }
+
+    struct Fixture {
+        rollback: &'static AppCheckerRollback<(), VersionStore>,
+        kv: &'static VersionStore,
+        result: &'static CheckClient,
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let kv: &'static VersionStore = Box::leak(Box::new(VersionStore {
+                version: Cell::new(None),
+                writes: Cell::new(0),
+                pending: Cell::new(None),
+                key: OptionalCell::empty(),
+                value: OptionalCell::empty(),
+                client: OptionalCell::empty(),
+            }));
+            let rollback = Box::leak(Box::new(AppCheckerRollback::new(
+                &(),
+                kv,
+                StoragePermissions::new_kernel_permissions(&KernelStorage),
+                Box::leak(Box::new([0; 64])),
+                Box::leak(Box::new([0; 8])),
+            )));
+            let result = Box::leak(Box::new(CheckClient(Cell::new(None))));
+            kv.set_client(rollback);
+            rollback.set_client(result);
+            Fixture {
+                rollback,
+                kv,
+                result,
+            }
+        }
+
+        /// Run the rollback check for `binary` after the wrapped checker
+        /// accepted it.
+        fn check(&self, binary: &'static [u8]) -> Result<CheckResult, ErrorCode> {
+            static RESERVED: [u8; 4] = [0; 4];
+            let credentials = TbfFooterV2Credentials::try_from(&RESERVED[..]).unwrap();
+            Client::check_done(self.rollback, Ok(CheckResult::Accept), credentials, binary);
+            while self.kv.pump() {}
+            self.result.0.take().unwrap()
+        }
+    }
+
+    #[test]
+    fn downgrade_rejected() {
+        let fixture = Fixture::new();
+        assert!(matches!(
+            fixture.check(tbf(Some(2), "app")),
+            Ok(CheckResult::Accept)
+        ));
+        assert!(matches!(
+            fixture.check(tbf(Some(1), "app")),
+            Ok(CheckResult::Reject)
+        ));
+        assert_eq!(fixture.kv.version.get(), Some(2));
+    }
+
+    #[test]
+    fn equal_version_accepted() {
+        let fixture = Fixture::new();
+        assert!(matches!(
+            fixture.check(tbf(Some(2), "app")),
+            Ok(CheckResult::Accept)
+        ));
+        assert!(matches!(
+            fixture.check(tbf(Some(2), "app")),
+            Ok(CheckResult::Accept)
+        ));
+        // The version is only written the first time.
+        assert_eq!(fixture.kv.writes.get(), 1);
+    }
+
+    #[test]
+    fn upgrade_recorded() {
+        let fixture = Fixture::new();
+        assert!(matches!(
+            fixture.check(tbf(Some(1), "app")),
+            Ok(CheckResult::Accept)
+        ));
+        assert!(matches!(
+            fixture.check(tbf(Some(3), "app")),
+            Ok(CheckResult::Accept)
+        ));
+        assert_eq!(fixture.kv.version.get(), Some(3));
+        assert!(matches!(
+            fixture.check(tbf(Some(2), "app")),
+            Ok(CheckResult::Reject)
+        ));
+    }
+
+    #[test]
+    fn untracked_rejected_by_default() {
+        let fixture = Fixture::new();
+        assert!(matches!(
+            fixture.check(tbf(None, "app")),
+            Ok(CheckResult::Reject)
+        ));
+        assert!(matches!(
+            fixture.check(tbf(Some(1), "")),
+            Ok(CheckResult::Reject)
+        ));
+        assert_eq!(fixture.kv.writes.get(), 0);
+
+        fixture
+            .rollback
+            .set_reject_untracked(false, &ResetCapability);
+        assert!(matches!(
+            fixture.check(tbf(None, "app")),
+            Ok(CheckResult::Accept)
+        ));
+        assert!(matches!(
+            fixture.check(tbf(Some(1), "")),
+            Ok(CheckResult::Accept)
+        ));
+        assert_eq!(fixture.kv.writes.get(), 0);
+    }
+}
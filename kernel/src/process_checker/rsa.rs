@@ -0,0 +1,551 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Application credentials checker that verifies RSA signature credentials
+//! from a list of trusted keys. See the
+//! [AppID TRD](../../doc/reference/trd-appid.md).
+
+use core::cell::Cell;
+
+use crate::hil::digest::{ClientData, ClientHash, DigestDataHash};
+use crate::hil::public_key_crypto::rsa_math::{Client as RsaClient, RsaCryptoBase};
+use crate::process::{Process, ShortID};
+use crate::process_checker::{AppCredentialsChecker, AppUniqueness};
+use crate::process_checker::{CheckResult, Client, Compress};
+use crate::utilities::cells::{OptionalCell, TakeCell};
+use crate::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use crate::ErrorCode;
+use tock_tbf::types::TbfFooterV2Credentials;
+use tock_tbf::types::TbfFooterV2CredentialsType;
+
+/// DER encoded `DigestInfo` prefixes for PKCS#1 v1.5 signatures, from RFC
+/// 8017 section 9.2.
+const SHA256_DIGEST_INFO: [u8; 19] = [
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+const SHA384_DIGEST_INFO: [u8; 19] = [
+    0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05,
+    0x00, 0x04, 0x30,
+];
+const SHA512_DIGEST_INFO: [u8; 19] = [
+    0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03, 0x05,
+    0x00, 0x04, 0x40,
+];
+
+/// Number of zero bytes at the start of the PSS message `M'`.
+const PSS_PADDING_LENGTH: usize = 8;
+
+/// The signature scheme used by the RSA credentials.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RsaPadding {
+    /// RSASSA-PKCS1-v1_5.
+    Pkcs1v15,
+    /// RSASSA-PSS with MGF1, using the same hash function for the message and
+    /// the mask, and a salt as long as the hash.
+    Pss,
+}
+
+/// An RSA public key trusted to sign applications.
+pub struct RsaPublicKey {
+    /// The big-endian modulus, 384 bytes for 3072-bit keys and 512 bytes for
+    /// 4096-bit keys.
+    pub modulus: &'static [u8],
+    /// The big-endian public exponent, usually `[0x01, 0x00, 0x01]`.
+    pub exponent: &'static [u8],
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Hashing the binary.
+    HashBinary,
+    /// Running the RSA public key operation on the signature.
+    ModExp,
+    /// Generating the PSS mask; the MGF1 counter of the block being hashed.
+    Mask(u32),
+    /// Hashing the PSS message `M'`.
+    MessageHash,
+}
+
+/// A Credentials Checking Policy that only runs Userspace Binaries with a
+/// valid `Rsa3072Key` or `Rsa4096Key` credential signed by one of a list of
+/// trusted keys.
+///
+/// The binary is hashed with `hasher`, whose hash length `HL` selects the
+/// hash function: 32 for SHA-256, 48 for SHA-384 and 64 for SHA-512. The
+/// signature is then checked with `rsa` according to `padding`. The RSA
+/// operation is asynchronous; with a software implementation such as
+/// `capsules_extra::public_key_crypto::rsa_math::RsaMathSoftware` it is split
+/// over many deferred calls so it does not stall the kernel loop.
+///
+/// Credentials whose key is not trusted are not supported and skipped. As a
+/// key is usually shared by many applications, this checker identifies
+/// applications by their process name.
+///
+/// `message_buffer` and `result_buffer` must be at least as long as the
+/// largest modulus to check; credentials with a longer key are skipped.
+pub struct AppCheckerRsa<
+    R: 'static + RsaCryptoBase<'static>,
+    H: 'static + DigestDataHash<'static, HL>,
+    const HL: usize,
+> {
+    hasher: &'static H,
+    rsa: &'static R,
+    padding: RsaPadding,
+    trusted_keys: &'static [RsaPublicKey],
+    state: Cell<State>,
+    key: OptionalCell<&'static RsaPublicKey>,
+    hash: TakeCell<'static, [u8; HL]>,
+    message: TakeCell<'static, [u8]>,
+    result: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'static dyn Client<'static>>,
+    credentials: OptionalCell<TbfFooterV2Credentials>,
+    binary: OptionalCell<&'static [u8]>,
+}
+
+impl<
+        R: 'static + RsaCryptoBase<'static>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+    > AppCheckerRsa<R, H, HL>
+{
+    pub fn new(
+        hasher: &'static H,
+        rsa: &'static R,
+        padding: RsaPadding,
+        trusted_keys: &'static [RsaPublicKey],
+        hash_buffer: &'static mut [u8; HL],
+        message_buffer: &'static mut [u8],
+        result_buffer: &'static mut [u8],
+    ) -> AppCheckerRsa<R, H, HL> {
+        AppCheckerRsa {
+            hasher,
+            rsa,
+            padding,
+            trusted_keys,
+            state: Cell::new(State::Idle),
+            key: OptionalCell::empty(),
+            hash: TakeCell::new(hash_buffer),
+            message: TakeCell::new(message_buffer),
+            result: TakeCell::new(result_buffer),
+            client: OptionalCell::empty(),
+            credentials: OptionalCell::empty(),
+            binary: OptionalCell::empty(),
+        }
+    }
+
+    fn digest_info(&self) -> Option<&'static [u8]> {
+        match HL {
+            32 => Some(&SHA256_DIGEST_INFO),
+            48 => Some(&SHA384_DIGEST_INFO),
+            64 => Some(&SHA512_DIGEST_INFO),
+            _ => None,
+        }
+    }
+
+    /// Whether the buffers and padding support keys of `key_length` bytes.
+    fn supports(&self, key_length: usize) -> bool {
+        let message_length = self.message.map_or(0, |m| m.len());
+        let result_length = self.result.map_or(0, |r| r.len());
+        let padding_ok = match self.padding {
+            RsaPadding::Pkcs1v15 => self
+                .digest_info()
+                .map_or(false, |info| key_length >= info.len() + HL + 11),
+            RsaPadding::Pss => {
+                key_length >= 2 * HL + 2 && message_length >= HL + 4 + PSS_PADDING_LENGTH + 2 * HL
+            }
+        };
+        padding_ok && message_length >= key_length && result_length >= key_length
+    }
+
+    /// Length of the key of the current check.
+    fn key_length(&self) -> usize {
+        self.key.map_or(0, |key| key.modulus.len())
+    }
+
+    /// Offset and length of the PSS encoded message `EM` within the result
+    /// of the RSA operation, and the number of unused high bits of `EM`.
+    fn pss_layout(&self) -> (usize, usize, usize) {
+        let key_length = self.key_length();
+        let leading_zeros = self
+            .key
+            .map_or(0, |key| key.modulus[0].leading_zeros() as usize);
+        // emBits = modBits - 1
+        let em_bits = 8 * key_length - leading_zeros - 1;
+        let em_length = (em_bits + 7) / 8;
+        (key_length - em_length, em_length, 8 * em_length - em_bits)
+    }
+
+    /// Report the result of the current check to the client.
+    fn check_done(&self, result: Result<CheckResult, ErrorCode>) {
+        self.state.set(State::Idle);
+        self.key.clear();
+        if let (Some(credentials), Some(binary)) = (self.credentials.take(), self.binary.take()) {
+            self.client
+                .map(|client| client.check_done(result, credentials, binary));
+        }
+    }
+
+    /// Start the RSA public key operation on the signature.
+    fn start_mod_exp(&self) {
+        let key = match self.key.get() {
+            Some(key) => key,
+            None => {
+                self.check_done(Err(ErrorCode::FAIL));
+                return;
+            }
+        };
+        let key_length = key.modulus.len();
+
+        // The signature must be smaller than the modulus.
+        let in_range = self
+            .message
+            .map_or(false, |message| message[..key_length] < *key.modulus);
+        if !in_range {
+            self.check_done(Ok(CheckResult::Reject));
+            return;
+        }
+
+        match (self.message.take(), self.result.take()) {
+            (Some(message), Some(result)) => {
+                self.state.set(State::ModExp);
+                if let Err((e, message, _, _, result)) =
+                    self.rsa
+                        .mod_exponent(message, key.modulus, key.exponent, result)
+                {
+                    self.message.replace(message);
+                    self.result.replace(result);
+                    self.check_done(Err(e));
+                }
+            }
+            (message, result) => {
+                message.map(|m| self.message.replace(m));
+                result.map(|r| self.result.replace(r));
+                self.check_done(Err(ErrorCode::FAIL));
+            }
+        }
+    }
+
+    /// Check a PKCS#1 v1.5 encoded message against the hash of the binary.
+    fn check_pkcs1v15(&self) {
+        let key_length = self.key_length();
+        let digest_info = self.digest_info().unwrap_or(&[]);
+        let padding_end = key_length - digest_info.len() - HL - 1;
+
+        let valid = self.result.map_or(false, |em| {
+            self.hash.map_or(false, |hash| {
+                em[0] == 0x00
+                    && em[1] == 0x01
+                    && em[2..padding_end].iter().all(|b| *b == 0xff)
+                    && em[padding_end] == 0x00
+                    && em[padding_end + 1..key_length - HL] == *digest_info
+                    && em[key_length - HL..key_length] == hash[..]
+            })
+        });
+        self.check_done(Ok(if valid {
+            CheckResult::Accept
+        } else {
+            CheckResult::Reject
+        }));
+    }
+
+    /// Check the structure of a PSS encoded message and start unmasking it.
+    ///
+    /// The message buffer, which held the signature, is reused: it holds
+    /// `H || counter` for the mask generation function at its start and then
+    /// `M' = (0x00 * 8) || mHash || salt`.
+    fn start_pss(&self) {
+        let (offset, em_length, zero_bits) = self.pss_layout();
+        let db_length = em_length - HL - 1;
+        let high_mask = (0xff00u16 >> zero_bits) as u8;
+
+        let valid = self.result.map_or(false, |em| {
+            (offset == 0 || em[0] == 0)
+                && em[offset + em_length - 1] == 0xbc
+                && em[offset] & high_mask == 0
+        });
+        if !valid {
+            self.check_done(Ok(CheckResult::Reject));
+            return;
+        }
+
+        self.result.map(|em| {
+            self.message.map(|message| {
+                let h = &em[offset + db_length..offset + db_length + HL];
+                message[..HL].copy_from_slice(h);
+                let m_prime = &mut message[HL + 4..];
+                m_prime[..PSS_PADDING_LENGTH].fill(0);
+                self.hash.map(|hash| {
+                    m_prime[PSS_PADDING_LENGTH..PSS_PADDING_LENGTH + HL].copy_from_slice(hash);
+                });
+            });
+        });
+        self.hash_mask_block(0);
+    }
+
+    /// Hash `H || counter` to generate the next block of the PSS mask.
+    fn hash_mask_block(&self, counter: u32) {
+        match self.message.take() {
+            Some(message) => {
+                message[HL..HL + 4].copy_from_slice(&counter.to_be_bytes());
+                let mut data = SubSliceMut::new(message);
+                data.slice(..HL + 4);
+                self.state.set(State::Mask(counter));
+                self.hash_mut_data(data);
+            }
+            None => self.check_done(Err(ErrorCode::FAIL)),
+        }
+    }
+
+    /// XOR a block of the PSS mask into `maskedDB`. Once the whole data block
+    /// `DB` is unmasked, check it and hash `M'`.
+    fn unmask(&self, counter: u32, mask: &[u8; HL]) {
+        let (offset, em_length, zero_bits) = self.pss_layout();
+        let db_length = em_length - HL - 1;
+        let start = counter as usize * HL;
+        let end = core::cmp::min(start + HL, db_length);
+
+        self.result.map(|em| {
+            let db = &mut em[offset..offset + db_length];
+            for (b, m) in db[start..end].iter_mut().zip(mask.iter()) {
+                *b ^= m;
+            }
+        });
+        if end < db_length {
+            self.hash_mask_block(counter + 1);
+            return;
+        }
+
+        // DB = PS || 0x01 || salt, where PS is all zeros.
+        let salt_start = db_length - HL;
+        let valid = self.result.map_or(false, |em| {
+            let db = &mut em[offset..offset + db_length];
+            db[0] &= 0xff >> zero_bits;
+            db[..salt_start - 1].iter().all(|b| *b == 0) && db[salt_start - 1] == 0x01
+        });
+        if !valid {
+            self.check_done(Ok(CheckResult::Reject));
+            return;
+        }
+
+        match self.message.take() {
+            Some(message) => {
+                let m_prime_start = HL + 4;
+                let salt = m_prime_start + PSS_PADDING_LENGTH + HL;
+                self.result.map(|em| {
+                    message[salt..salt + HL]
+                        .copy_from_slice(&em[offset + salt_start..offset + db_length]);
+                });
+                let mut data = SubSliceMut::new(message);
+                data.slice(m_prime_start..salt + HL);
+                self.state.set(State::MessageHash);
+                self.hash_mut_data(data);
+            }
+            None => self.check_done(Err(ErrorCode::FAIL)),
+        }
+    }
+
+    fn hash_mut_data(&self, data: SubSliceMut<'static, u8>) {
+        self.hasher.clear_data();
+        if let Err((e, data)) = self.hasher.add_mut_data(data) {
+            self.message.replace(data.take());
+            self.check_done(Err(e));
+        }
+    }
+
+    fn run_hash(&self) {
+        match self.hash.take() {
+            Some(hash) => {
+                if let Err((e, hash)) = self.hasher.run(hash) {
+                    self.hash.replace(hash);
+                    self.check_done(Err(e));
+                }
+            }
+            None => self.check_done(Err(ErrorCode::FAIL)),
+        }
+    }
+
+    /// Compare the hash of `M'` with `H` from the encoded message.
+    fn check_pss(&self, digest: &[u8; HL]) {
+        let (offset, em_length, _) = self.pss_layout();
+        let h_start = offset + em_length - HL - 1;
+        let valid = self
+            .result
+            .map_or(false, |em| em[h_start..h_start + HL] == digest[..]);
+        self.check_done(Ok(if valid {
+            CheckResult::Accept
+        } else {
+            CheckResult::Reject
+        }));
+    }
+}
+
+impl<
+        R: 'static + RsaCryptoBase<'static>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+    > AppCredentialsChecker<'static> for AppCheckerRsa<R, H, HL>
+{
+    fn require_credentials(&self) -> bool {
+        true
+    }
+
+    fn check_credentials(
+        &self,
+        credentials: TbfFooterV2Credentials,
+        binary: &'static [u8],
+    ) -> Result<(), (ErrorCode, TbfFooterV2Credentials, &'static [u8])> {
+        let key_length = match credentials.format() {
+            TbfFooterV2CredentialsType::Rsa3072Key => 384,
+            TbfFooterV2CredentialsType::Rsa4096Key => 512,
+            _ => return Err((ErrorCode::NOSUPPORT, credentials, binary)),
+        };
+        if !self.supports(key_length) {
+            return Err((ErrorCode::NOSUPPORT, credentials, binary));
+        }
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, credentials, binary));
+        }
+
+        // The credentials hold the modulus followed by the signature.
+        let data = credentials.data();
+        let (modulus, signature) = data.split_at(key_length);
+        let key = match self.trusted_keys.iter().find(|key| key.modulus == modulus) {
+            Some(key) => key,
+            None => return Err((ErrorCode::NOSUPPORT, credentials, binary)),
+        };
+        self.message.map(|message| {
+            message[..key_length].copy_from_slice(&signature[..key_length]);
+        });
+
+        self.hasher.clear_data();
+        match self.hasher.add_data(SubSlice::new(binary)) {
+            Ok(()) => {
+                self.state.set(State::HashBinary);
+                self.key.set(key);
+                self.credentials.set(credentials);
+                Ok(())
+            }
+            Err((e, b)) => Err((e, credentials, b.take())),
+        }
+    }
+
+    fn set_client(&self, client: &'static dyn Client<'static>) {
+        self.client.replace(client);
+    }
+}
+
+impl<
+        R: 'static + RsaCryptoBase<'static>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+    > ClientData<HL> for AppCheckerRsa<R, H, HL>
+{
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        self.message.replace(data.take());
+        if let Err(e) = result {
+            self.check_done(Err(e));
+            return;
+        }
+        self.run_hash();
+    }
+
+    fn add_data_done(&self, result: Result<(), ErrorCode>, data: SubSlice<'static, u8>) {
+        self.binary.set(data.take());
+        if let Err(e) = result {
+            self.check_done(Err(e));
+            return;
+        }
+        self.run_hash();
+    }
+}
+
+impl<
+        R: 'static + RsaCryptoBase<'static>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+    > ClientHash<HL> for AppCheckerRsa<R, H, HL>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; HL]) {
+        if let Err(e) = result {
+            self.hash.replace(digest);
+            self.check_done(Err(e));
+            return;
+        }
+
+        match self.state.get() {
+            State::HashBinary => {
+                self.hash.replace(digest);
+                self.start_mod_exp();
+            }
+            State::Mask(counter) => {
+                let mask = *digest;
+                self.hash.replace(digest);
+                self.unmask(counter, &mask);
+            }
+            State::MessageHash => {
+                let m_prime_hash = *digest;
+                self.hash.replace(digest);
+                self.check_pss(&m_prime_hash);
+            }
+            State::Idle | State::ModExp => {
+                self.hash.replace(digest);
+            }
+        }
+    }
+}
+
+impl<
+        R: 'static + RsaCryptoBase<'static>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+    > RsaClient<'static> for AppCheckerRsa<R, H, HL>
+{
+    fn mod_exponent_done(
+        &'static self,
+        status: Result<bool, ErrorCode>,
+        message: &'static mut [u8],
+        _modulus: &'static [u8],
+        _exponent: &'static [u8],
+        result: &'static mut [u8],
+    ) {
+        self.message.replace(message);
+        self.result.replace(result);
+
+        match status {
+            Ok(true) => match self.padding {
+                RsaPadding::Pkcs1v15 => self.check_pkcs1v15(),
+                RsaPadding::Pss => self.start_pss(),
+            },
+            Ok(false) => self.check_done(Err(ErrorCode::FAIL)),
+            Err(e) => self.check_done(Err(e)),
+        }
+    }
+}
+
+impl<
+        R: 'static + RsaCryptoBase<'static>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+    > AppUniqueness for AppCheckerRsa<R, H, HL>
+{
+    // Applications signed by the same key are told apart by their name.
+    fn different_identifier(&self, process_a: &dyn Process, process_b: &dyn Process) -> bool {
+        let a = process_a.get_process_name();
+        let b = process_b.get_process_name();
+        !a.eq(b)
+    }
+}
+
+impl<
+        R: 'static + RsaCryptoBase<'static>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+    > Compress for AppCheckerRsa<R, H, HL>
+{
+    fn to_short_id(&self, _credentials: &TbfFooterV2Credentials) -> ShortID {
+        ShortID::LocallyUnique
+    }
+}
@@ -0,0 +1,279 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Application credentials checker that verifies signature credentials
+//! against a list of trusted public keys. See the
+//! [AppID TRD](../../doc/reference/trd-appid.md).
+
+use core::cell::Cell;
+
+use crate::hil::digest::{ClientData, ClientHash, DigestDataHash};
+use crate::hil::public_key_crypto::signature::{ClientVerify, SignatureVerify};
+use crate::process::{Process, ShortID};
+use crate::process_checker::{AppCredentialsChecker, AppUniqueness};
+use crate::process_checker::{CheckResult, Client, Compress};
+use crate::utilities::cells::{OptionalCell, TakeCell};
+use crate::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use crate::ErrorCode;
+use tock_tbf::types::TbfFooterV2Credentials;
+use tock_tbf::types::TbfFooterV2CredentialsType;
+
+/// A Credentials Checking Policy that only runs Userspace Binaries with a
+/// valid signature from one of a list of trusted keys.
+///
+/// The checker handles credentials of a single type, for example
+/// `EcdsaNistP256` or `Ed25519`. It hashes the binary with `hasher` and then
+/// asks `verifier` to check the signature in the credentials against each
+/// trusted key in turn. A binary signed by any trusted key is accepted, a
+/// binary with a signature no trusted key verifies is rejected, and
+/// credentials of other types are not supported and skipped.
+///
+/// A trusted key is usually shared by many applications, so this checker
+/// identifies applications by their process name: only one Userspace Binary
+/// with a particular name runs at any time.
+pub struct AppCheckerSignature<
+    S: 'static + SignatureVerify<'static, HL, SL>,
+    H: 'static + DigestDataHash<'static, HL>,
+    const HL: usize,
+    const SL: usize,
+> {
+    hasher: &'static H,
+    verifier: &'static S,
+    credential_type: TbfFooterV2CredentialsType,
+    trusted_keys: &'static [&'static [u8]],
+    key_index: Cell<usize>,
+    hash: TakeCell<'static, [u8; HL]>,
+    signature: TakeCell<'static, [u8; SL]>,
+    client: OptionalCell<&'static dyn Client<'static>>,
+    credentials: OptionalCell<TbfFooterV2Credentials>,
+    binary: OptionalCell<&'static [u8]>,
+}
+
+impl<
+        S: 'static + SignatureVerify<'static, HL, SL>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > AppCheckerSignature<S, H, HL, SL>
+{
+    /// Create a signature checker for credentials of type `credential_type`.
+    ///
+    /// The format of the keys in `trusted_keys` is defined by `verifier`.
+    pub fn new(
+        hasher: &'static H,
+        verifier: &'static S,
+        credential_type: TbfFooterV2CredentialsType,
+        trusted_keys: &'static [&'static [u8]],
+        hash_buffer: &'static mut [u8; HL],
+        signature_buffer: &'static mut [u8; SL],
+    ) -> AppCheckerSignature<S, H, HL, SL> {
+        AppCheckerSignature {
+            hasher,
+            verifier,
+            credential_type,
+            trusted_keys,
+            key_index: Cell::new(0),
+            hash: TakeCell::new(hash_buffer),
+            signature: TakeCell::new(signature_buffer),
+            client: OptionalCell::empty(),
+            credentials: OptionalCell::empty(),
+            binary: OptionalCell::empty(),
+        }
+    }
+
+    /// Report the result of the current check to the client.
+    fn check_done(&self, result: Result<CheckResult, ErrorCode>) {
+        if let (Some(credentials), Some(binary)) = (self.credentials.take(), self.binary.take()) {
+            self.client
+                .map(|client| client.check_done(result, credentials, binary));
+        }
+    }
+
+    /// Verify the signature with the trusted key at `key_index` or, if that
+    /// key is not usable, the next one that is. Rejects the binary once all
+    /// keys have been tried.
+    fn verify_next_key(&self, hash: &'static mut [u8; HL]) {
+        let mut hash = hash;
+        while let Some(key) = self.trusted_keys.get(self.key_index.get()) {
+            let signature = match self.signature.take() {
+                Some(signature) => signature,
+                None => break,
+            };
+            match self.verifier.verify(key, hash, signature) {
+                Ok(()) => return,
+                Err((ErrorCode::INVAL, h, s)) => {
+                    // The verifier does not understand this key, try the
+                    // next one.
+                    self.signature.replace(s);
+                    hash = h;
+                    self.key_index.set(self.key_index.get() + 1);
+                }
+                Err((e, h, s)) => {
+                    self.signature.replace(s);
+                    self.hash.replace(h);
+                    self.check_done(Err(e));
+                    return;
+                }
+            }
+        }
+        self.hash.replace(hash);
+        self.check_done(Ok(CheckResult::Reject));
+    }
+}
+
+impl<
+        S: 'static + SignatureVerify<'static, HL, SL>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > AppCredentialsChecker<'static> for AppCheckerSignature<S, H, HL, SL>
+{
+    fn require_credentials(&self) -> bool {
+        true
+    }
+
+    fn check_credentials(
+        &self,
+        credentials: TbfFooterV2Credentials,
+        binary: &'static [u8],
+    ) -> Result<(), (ErrorCode, TbfFooterV2Credentials, &'static [u8])> {
+        if credentials.format() != self.credential_type {
+            return Err((ErrorCode::NOSUPPORT, credentials, binary));
+        }
+        if self.credentials.is_some() {
+            return Err((ErrorCode::BUSY, credentials, binary));
+        }
+        let copied = self
+            .signature
+            .map_or(false, |signature| match credentials.data().get(..SL) {
+                Some(data) => {
+                    signature.copy_from_slice(data);
+                    true
+                }
+                None => false,
+            });
+        if !copied {
+            return Err((ErrorCode::INVAL, credentials, binary));
+        }
+
+        self.hasher.clear_data();
+        match self.hasher.add_data(SubSlice::new(binary)) {
+            Ok(()) => {
+                self.credentials.set(credentials);
+                Ok(())
+            }
+            Err((e, b)) => Err((e, credentials, b.take())),
+        }
+    }
+
+    fn set_client(&self, client: &'static dyn Client<'static>) {
+        self.client.replace(client);
+    }
+}
+
+impl<
+        S: 'static + SignatureVerify<'static, HL, SL>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > ClientData<HL> for AppCheckerSignature<S, H, HL, SL>
+{
+    fn add_mut_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSliceMut<'static, u8>) {}
+
+    fn add_data_done(&self, result: Result<(), ErrorCode>, data: SubSlice<'static, u8>) {
+        self.binary.set(data.take());
+        if let Err(e) = result {
+            self.check_done(Err(e));
+            return;
+        }
+        match self.hash.take() {
+            Some(hash) => {
+                if let Err((e, hash)) = self.hasher.run(hash) {
+                    self.hash.replace(hash);
+                    self.check_done(Err(e));
+                }
+            }
+            None => self.check_done(Err(ErrorCode::FAIL)),
+        }
+    }
+}
+
+impl<
+        S: 'static + SignatureVerify<'static, HL, SL>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > ClientHash<HL> for AppCheckerSignature<S, H, HL, SL>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; HL]) {
+        match result {
+            Ok(()) => {
+                self.key_index.set(0);
+                self.verify_next_key(digest);
+            }
+            Err(e) => {
+                self.hash.replace(digest);
+                self.check_done(Err(e));
+            }
+        }
+    }
+}
+
+impl<
+        S: 'static + SignatureVerify<'static, HL, SL>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > ClientVerify<HL, SL> for AppCheckerSignature<S, H, HL, SL>
+{
+    fn verification_done(
+        &self,
+        result: Result<bool, ErrorCode>,
+        hash: &'static mut [u8; HL],
+        signature: &'static mut [u8; SL],
+    ) {
+        self.signature.replace(signature);
+        match result {
+            Ok(true) => {
+                self.hash.replace(hash);
+                self.check_done(Ok(CheckResult::Accept));
+            }
+            Ok(false) => {
+                self.key_index.set(self.key_index.get() + 1);
+                self.verify_next_key(hash);
+            }
+            Err(e) => {
+                self.hash.replace(hash);
+                self.check_done(Err(e));
+            }
+        }
+    }
+}
+
+impl<
+        S: 'static + SignatureVerify<'static, HL, SL>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > AppUniqueness for AppCheckerSignature<S, H, HL, SL>
+{
+    // Applications signed by the same key are told apart by their name.
+    fn different_identifier(&self, process_a: &dyn Process, process_b: &dyn Process) -> bool {
+        let a = process_a.get_process_name();
+        let b = process_b.get_process_name();
+        !a.eq(b)
+    }
+}
+
+impl<
+        S: 'static + SignatureVerify<'static, HL, SL>,
+        H: 'static + DigestDataHash<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > Compress for AppCheckerSignature<S, H, HL, SL>
+{
+    fn to_short_id(&self, _credentials: &TbfFooterV2Credentials) -> ShortID {
+        ShortID::LocallyUnique
+    }
+}
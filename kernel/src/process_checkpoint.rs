@@ -0,0 +1,838 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Saving processes to flash and resuming them after a reboot.
+//!
+//! Boards that fully power down between periods of activity lose the state
+//! of all processes. The checkpointer lets them keep long-running processes
+//! alive across such power cycles: it writes a snapshot of a stopped process
+//! into a region of flash reserved for this, and when the process is started
+//! on the next boot, the kernel resumes it from the snapshot instead of
+//! calling its init function. Checkpointing requires building the kernel with
+//! the `process_checkpoint` feature.
+//!
+//! A snapshot holds the process-accessible memory of the process up to its
+//! memory break, its stored registers, and whether it was running or
+//! yielded. Grants are owned by capsules and are not part of the snapshot,
+//! so a resumed process has no subscribed upcalls, allowed buffers, or
+//! pending tasks. Processes waiting in `yield-wait-for` cannot be saved.
+//!
+//! A snapshot is only used if the TBF object of the process is unchanged,
+//! the kernel version matches, and the process memory starts at the same
+//! address as before, since the saved memory contains absolute pointers.
+//! A snapshot is used at most once: it is only considered when a process is
+//! first started after boot, not when it restarts after a fault, and the
+//! checkpointer invalidates it in flash as soon as it has been looked at, so
+//! a process that faults after being resumed is not resumed into the same
+//! state again on the next boot. Otherwise snapshots stay valid until they
+//! are overwritten by a newer snapshot of the same process or removed with
+//! `discard()`.
+//!
+//! The reserved flash region is divided into at most `MAX_SLOTS` slots of
+//! equal size, each holding at most one snapshot:
+//!
+//! ```text
+//! +----------------------+ slot start
+//! | header (40 bytes)    |
+//! +----------------------+
+//! | stored state         |
+//! +----------------------+
+//! | process memory       |
+//! +----------------------+
+//! ```
+//!
+//! The header is written last and includes a checksum over the rest of the
+//! snapshot, so a snapshot interrupted by a reset is never resumed.
+
+use core::cell::Cell;
+
+use crate::capabilities::ProcessCheckpointCapability;
+use crate::config;
+use crate::debug;
+use crate::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use crate::kernel::Kernel;
+use crate::process::{Process, ProcessId, State as ProcessState};
+use crate::utilities::cells::{OptionalCell, TakeCell};
+use crate::ErrorCode;
+
+/// Marks a slot that holds a snapshot.
+const MAGIC: u32 = u32::from_le_bytes(*b"TKCP");
+
+/// Version of the snapshot layout.
+const FORMAT_VERSION: u32 = 1;
+
+/// Size of the snapshot header.
+const HEADER_LEN: usize = 40;
+
+/// Offsets of the fields of the snapshot header.
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 4;
+const KERNEL_VERSION_OFFSET: usize = 8;
+const FLASH_START_OFFSET: usize = 12;
+const FINGERPRINT_OFFSET: usize = 16;
+const MEMORY_START_OFFSET: usize = 20;
+const STORED_STATE_LEN_OFFSET: usize = 24;
+const MEMORY_LEN_OFFSET: usize = 28;
+const STATE_OFFSET: usize = 32;
+const CHECKSUM_OFFSET: usize = 36;
+
+/// Maximum number of slots in the checkpoint region.
+const MAX_SLOTS: usize = 32;
+
+/// Values of the process state field of the snapshot header.
+const STATE_RUNNING: u32 = 0;
+const STATE_YIELDED: u32 = 1;
+
+/// Interface for saving stopped processes to flash.
+pub trait ProcessCheckpoint {
+    fn set_client(&self, client: &'static dyn ProcessCheckpointClient);
+
+    /// Write a snapshot of the stopped process `processid` to flash. The
+    /// process must stay stopped until `checkpoint_done()` is called.
+    ///
+    /// Returns `ErrorCode::OFF` if the process is not stopped,
+    /// `ErrorCode::INVAL` if it is stopped while waiting for a specific
+    /// upcall, `ErrorCode::SIZE` if the snapshot does not fit into a slot,
+    /// and `ErrorCode::NOMEM` if all slots hold snapshots of other processes.
+    fn checkpoint(
+        &self,
+        processid: ProcessId,
+        capability: &dyn ProcessCheckpointCapability,
+    ) -> Result<(), ErrorCode>;
+
+    /// Remove the snapshot of `processid` so it is started from its init
+    /// function on the next boot. `discard_done()` is called once the
+    /// snapshot is invalidated in flash. Returns `ErrorCode::INVAL` if there
+    /// is no snapshot of this process.
+    fn discard(
+        &self,
+        processid: ProcessId,
+        capability: &dyn ProcessCheckpointCapability,
+    ) -> Result<(), ErrorCode>;
+}
+
+/// Client for asynchronous operations of `ProcessCheckpoint`.
+pub trait ProcessCheckpointClient {
+    /// A snapshot requested with `checkpoint()` has been written. Returns
+    /// `ErrorCode::CANCEL` if the process was resumed before the snapshot was
+    /// complete.
+    fn checkpoint_done(&self, result: Result<(), ErrorCode>);
+
+    /// A snapshot has been removed with `discard()`.
+    fn discard_done(&self, result: Result<(), ErrorCode>);
+}
+
+/// Resumes processes from snapshots when the kernel starts them.
+pub trait ProcessRestore {
+    /// Called when `process` has been approved to run for the first time
+    /// since boot, before the kernel enqueues its init function. Returns
+    /// `true` if the process was resumed from a snapshot, in which case the
+    /// kernel does not start it. Implementations must not resume a process
+    /// from the same snapshot twice.
+    fn restore(&self, process: &dyn Process) -> bool;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Writing the stored state of the process.
+    StoredState,
+    /// Writing the memory of the process.
+    Memory,
+    /// Writing the header which makes the snapshot valid.
+    Header,
+    /// Writing the header which invalidates a snapshot.
+    Discard,
+    /// Writing the header which invalidates a snapshot that has been used.
+    Consume,
+}
+
+/// A snapshot that is being written.
+#[derive(Clone, Copy)]
+struct Snapshot {
+    processid: ProcessId,
+    /// Offset of the slot from the start of the checkpoint region.
+    slot: usize,
+    flash_start: usize,
+    fingerprint: u32,
+    memory_start: usize,
+    stored_state_len: usize,
+    memory_len: usize,
+    /// Number of bytes of process memory written so far.
+    memory_written: usize,
+    state: u32,
+    checksum: u32,
+}
+
+/// Saves stopped processes into a reserved flash region and resumes them on
+/// the next boot.
+pub struct ProcessCheckpointer {
+    kernel: &'static Kernel,
+    app_flash: &'static [u8],
+    region: &'static [u8],
+    slot_size: usize,
+    storage: &'static dyn NonvolatileStorage<'static>,
+    client: OptionalCell<&'static dyn ProcessCheckpointClient>,
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    snapshot: OptionalCell<Snapshot>,
+    /// Bitmask of the slots whose snapshot has been used since boot. These
+    /// slots count as empty.
+    consumed: Cell<u32>,
+    /// Bitmask of the used slots that still need to be invalidated in flash,
+    /// which the checkpointer does whenever it is idle.
+    invalidate: Cell<u32>,
+}
+
+/// Capability the checkpointer uses to snapshot and resume processes, given to
+/// it by holding a `ProcessCheckpointCapability` when it is created.
+struct CheckpointerCapability;
+unsafe impl ProcessCheckpointCapability for CheckpointerCapability {}
+
+impl ProcessCheckpointer {
+    /// Create a checkpointer.
+    ///
+    /// `app_flash` must be the full app flash region. `region` is the flash
+    /// reserved for snapshots, which is divided into slots of `slot_size`
+    /// bytes; it must not overlap app flash or the kernel. `storage` must
+    /// address flash with absolute addresses and should be used only by the
+    /// checkpointer. `buffer` is used to copy snapshots into flash and must
+    /// be at least 256 bytes long.
+    pub fn new(
+        kernel: &'static Kernel,
+        app_flash: &'static [u8],
+        region: &'static [u8],
+        slot_size: usize,
+        storage: &'static dyn NonvolatileStorage<'static>,
+        buffer: &'static mut [u8],
+        _capability: &dyn ProcessCheckpointCapability,
+    ) -> ProcessCheckpointer {
+        ProcessCheckpointer {
+            kernel,
+            app_flash,
+            region,
+            slot_size,
+            storage,
+            client: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+            state: Cell::new(State::Idle),
+            snapshot: OptionalCell::empty(),
+            consumed: Cell::new(0),
+            invalidate: Cell::new(0),
+        }
+    }
+
+    /// Iterate over the offsets of all slots in the checkpoint region.
+    fn slots(&self) -> impl Iterator<Item = usize> {
+        let slot_size = self.slot_size;
+        let count = if slot_size >= HEADER_LEN {
+            core::cmp::min(self.region.len() / slot_size, MAX_SLOTS)
+        } else {
+            0
+        };
+        (0..count).map(move |index| index * slot_size)
+    }
+
+    fn consumed_bit(&self, slot: usize) -> u32 {
+        1 << (slot / self.slot_size)
+    }
+
+    /// Whether the flash header of `slot` marks a snapshot.
+    fn slot_valid(&self, slot: usize) -> bool {
+        self.read_u32(slot + MAGIC_OFFSET) == MAGIC
+            && self.read_u32(slot + VERSION_OFFSET) == FORMAT_VERSION
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&self.region[offset..offset + 4]);
+        u32::from_le_bytes(bytes)
+    }
+
+    /// Return the app flash address of the process saved in `slot`, or
+    /// `None` if the slot does not hold a snapshot.
+    fn slot_owner(&self, slot: usize) -> Option<usize> {
+        if self.slot_valid(slot) && self.consumed.get() & self.consumed_bit(slot) == 0 {
+            Some(self.read_u32(slot + FLASH_START_OFFSET) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Find the slot holding the snapshot of the process whose TBF object
+    /// starts at `flash_start`.
+    fn find_slot(&self, flash_start: usize) -> Option<usize> {
+        self.slots()
+            .find(|&slot| self.slot_owner(slot) == Some(flash_start))
+    }
+
+    /// Return the TBF object of `process`.
+    fn tbf_object(&self, process: &dyn Process) -> Option<&'static [u8]> {
+        let addresses = process.get_addresses();
+        let start = addresses
+            .flash_start
+            .checked_sub(self.app_flash.as_ptr() as usize)?;
+        self.app_flash
+            .get(start..start + (addresses.flash_end - addresses.flash_start))
+    }
+
+    fn kernel_version() -> u32 {
+        crate::KERNEL_MAJOR_VERSION as u32 | (crate::KERNEL_MINOR_VERSION as u32) << 16
+    }
+
+    /// Write the next chunk of the snapshot, or its header once everything
+    /// else has been written.
+    fn write_next(&self, buffer: &'static mut [u8]) -> Result<(), ErrorCode> {
+        let mut snapshot = match self.snapshot.get() {
+            Some(snapshot) => snapshot,
+            None => {
+                self.buffer.replace(buffer);
+                return Err(ErrorCode::FAIL);
+            }
+        };
+
+        if snapshot.memory_written < snapshot.memory_len {
+            let chunk =
+                self.kernel
+                    .process_map_or(Err(ErrorCode::CANCEL), snapshot.processid, |process| {
+                        let remaining = snapshot.memory_len - snapshot.memory_written;
+                        let length = core::cmp::min(buffer.len(), remaining);
+                        process
+                            .get_checkpoint_memory(
+                                snapshot.memory_written,
+                                &mut buffer[..length],
+                                &CheckpointerCapability,
+                            )
+                            .or(Err(ErrorCode::CANCEL))
+                    });
+            let length = match chunk {
+                Ok(length) if length > 0 => length,
+                Ok(_) => {
+                    // The memory break moved, so the process ran again.
+                    self.buffer.replace(buffer);
+                    return Err(ErrorCode::CANCEL);
+                }
+                Err(e) => {
+                    self.buffer.replace(buffer);
+                    return Err(e);
+                }
+            };
+            let address = self.region.as_ptr() as usize
+                + snapshot.slot
+                + HEADER_LEN
+                + snapshot.stored_state_len
+                + snapshot.memory_written;
+            snapshot.checksum = fnv1a(snapshot.checksum, &buffer[..length]);
+            snapshot.memory_written += length;
+            self.snapshot.set(snapshot);
+            self.state.set(State::Memory);
+            return self.storage.write(buffer, address, length);
+        }
+
+        let fields = [
+            (MAGIC_OFFSET, MAGIC),
+            (VERSION_OFFSET, FORMAT_VERSION),
+            (KERNEL_VERSION_OFFSET, Self::kernel_version()),
+            (FLASH_START_OFFSET, snapshot.flash_start as u32),
+            (FINGERPRINT_OFFSET, snapshot.fingerprint),
+            (MEMORY_START_OFFSET, snapshot.memory_start as u32),
+            (STORED_STATE_LEN_OFFSET, snapshot.stored_state_len as u32),
+            (MEMORY_LEN_OFFSET, snapshot.memory_len as u32),
+            (STATE_OFFSET, snapshot.state),
+            (CHECKSUM_OFFSET, snapshot.checksum),
+        ];
+        for (offset, value) in fields {
+            buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        self.state.set(State::Header);
+        self.storage.write(
+            buffer,
+            self.region.as_ptr() as usize + snapshot.slot,
+            HEADER_LEN,
+        )
+    }
+
+    /// Return the stored state, the memory and the state of the process
+    /// saved in `slot` if the snapshot is intact and was taken of the binary
+    /// `tbf` by this kernel with process memory starting at `memory_start`.
+    fn load(
+        &self,
+        slot: usize,
+        tbf: &[u8],
+        memory_start: usize,
+    ) -> Option<(&'static [u8], &'static [u8], ProcessState)> {
+        let stored_state_len = self.read_u32(slot + STORED_STATE_LEN_OFFSET) as usize;
+        let memory_len = self.read_u32(slot + MEMORY_LEN_OFFSET) as usize;
+        let data = self.region.get(
+            slot + HEADER_LEN
+                ..(slot + HEADER_LEN)
+                    .checked_add(stored_state_len)?
+                    .checked_add(memory_len)?,
+        )?;
+        if HEADER_LEN + data.len() > self.slot_size {
+            return None;
+        }
+        let state = match self.read_u32(slot + STATE_OFFSET) {
+            STATE_RUNNING => ProcessState::Running,
+            STATE_YIELDED => ProcessState::Yielded,
+            _ => return None,
+        };
+
+        // Only resume a snapshot taken of this exact binary by this kernel
+        // with the same memory layout.
+        if self.read_u32(slot + KERNEL_VERSION_OFFSET) != Self::kernel_version()
+            || self.read_u32(slot + FINGERPRINT_OFFSET) != fnv1a(FNV_OFFSET_BASIS, tbf)
+            || self.read_u32(slot + MEMORY_START_OFFSET) as usize != memory_start
+            || self.read_u32(slot + CHECKSUM_OFFSET) != fnv1a(FNV_OFFSET_BASIS, data)
+        {
+            return None;
+        }
+        let (stored_state, memory) = data.split_at(stored_state_len);
+        Some((stored_state, memory, state))
+    }
+
+    /// Mark the snapshot in `slot` as used so that it is never resumed
+    /// again, and start invalidating it in flash.
+    fn consume(&self, slot: usize) {
+        self.consumed
+            .set(self.consumed.get() | self.consumed_bit(slot));
+        self.invalidate
+            .set(self.invalidate.get() | self.consumed_bit(slot));
+        self.invalidate_consumed();
+    }
+
+    /// If idle, start invalidating the flash header of a used snapshot.
+    fn invalidate_consumed(&self) {
+        if self.state.get() != State::Idle {
+            return;
+        }
+        let slot = match self
+            .slots()
+            .find(|&slot| self.invalidate.get() & self.consumed_bit(slot) != 0)
+        {
+            Some(slot) => slot,
+            None => return,
+        };
+        if let Some(buffer) = self.buffer.take() {
+            self.invalidate
+                .set(self.invalidate.get() & !self.consumed_bit(slot));
+            buffer[..HEADER_LEN].fill(0);
+            self.state.set(State::Consume);
+            if let Err(_) =
+                self.storage
+                    .write(buffer, self.region.as_ptr() as usize + slot, HEADER_LEN)
+            {
+                // The slot stays marked as used in RAM, so the snapshot is
+                // not resumed again before the next boot.
+                self.state.set(State::Idle);
+            }
+        }
+    }
+
+    /// Finish the current operation and report `result` to the client.
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        let state = self.state.get();
+        self.state.set(State::Idle);
+        if let (State::Header, Some(snapshot)) = (state, self.snapshot.get()) {
+            // The slot holds a new snapshot now.
+            self.consumed
+                .set(self.consumed.get() & !self.consumed_bit(snapshot.slot));
+        }
+        self.snapshot.clear();
+        self.client.map(|client| match state {
+            State::Discard => client.discard_done(result),
+            State::Consume => {}
+            _ => client.checkpoint_done(result),
+        });
+        self.invalidate_consumed();
+    }
+}
+
+impl ProcessCheckpoint for ProcessCheckpointer {
+    fn set_client(&self, client: &'static dyn ProcessCheckpointClient) {
+        self.client.set(client);
+    }
+
+    fn checkpoint(
+        &self,
+        processid: ProcessId,
+        _capability: &dyn ProcessCheckpointCapability,
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::RESERVE)?;
+
+        let snapshot = self
+            .kernel
+            .process_map_or(Err(ErrorCode::INVAL), processid, |process| {
+                let state = match process.get_state() {
+                    ProcessState::StoppedRunning => STATE_RUNNING,
+                    ProcessState::StoppedYielded => STATE_YIELDED,
+                    ProcessState::StoppedYieldedFor(_) => return Err(ErrorCode::INVAL),
+                    _ => return Err(ErrorCode::OFF),
+                };
+                let tbf = self.tbf_object(process).ok_or(ErrorCode::FAIL)?;
+                let stored_state_len = process.get_stored_state(buffer)?;
+                let addresses = process.get_addresses();
+                let memory_len = addresses.sram_app_brk - addresses.sram_start;
+                if HEADER_LEN + stored_state_len + memory_len > self.slot_size {
+                    return Err(ErrorCode::SIZE);
+                }
+
+                // Replace an older snapshot of this process, or use a free slot.
+                let flash_start = tbf.as_ptr() as usize;
+                let slot = self
+                    .find_slot(flash_start)
+                    .or_else(|| self.slots().find(|&slot| self.slot_owner(slot).is_none()))
+                    .ok_or(ErrorCode::NOMEM)?;
+
+                Ok(Snapshot {
+                    processid,
+                    slot,
+                    flash_start,
+                    fingerprint: fnv1a(FNV_OFFSET_BASIS, tbf),
+                    memory_start: addresses.sram_start,
+                    stored_state_len,
+                    memory_len,
+                    memory_written: 0,
+                    state,
+                    checksum: fnv1a(FNV_OFFSET_BASIS, &buffer[..stored_state_len]),
+                })
+            });
+        let snapshot = match snapshot {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                self.buffer.replace(buffer);
+                return Err(e);
+            }
+        };
+
+        if config::CONFIG.debug_load_processes {
+            debug!(
+                "Checkpoint: saving process {:?} to {:#010X}",
+                processid,
+                self.region.as_ptr() as usize + snapshot.slot
+            );
+        }
+
+        // The new snapshot replaces a used one that may not be invalidated
+        // yet.
+        self.invalidate
+            .set(self.invalidate.get() & !self.consumed_bit(snapshot.slot));
+        self.snapshot.set(snapshot);
+        self.state.set(State::StoredState);
+        self.storage
+            .write(
+                buffer,
+                self.region.as_ptr() as usize + snapshot.slot + HEADER_LEN,
+                snapshot.stored_state_len,
+            )
+            .map_err(|e| {
+                self.state.set(State::Idle);
+                self.snapshot.clear();
+                e
+            })
+    }
+
+    fn discard(
+        &self,
+        processid: ProcessId,
+        _capability: &dyn ProcessCheckpointCapability,
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let slot = self
+            .kernel
+            .process_map_or(None, processid, |process| {
+                self.tbf_object(process)
+                    .and_then(|tbf| self.find_slot(tbf.as_ptr() as usize))
+            })
+            .ok_or(ErrorCode::INVAL)?;
+
+        let buffer = self.buffer.take().ok_or(ErrorCode::RESERVE)?;
+        buffer[..HEADER_LEN].fill(0);
+        self.state.set(State::Discard);
+        self.storage
+            .write(buffer, self.region.as_ptr() as usize + slot, HEADER_LEN)
+            .map_err(|e| {
+                self.state.set(State::Idle);
+                e
+            })
+    }
+}
+
+impl ProcessRestore for ProcessCheckpointer {
+    fn restore(&self, process: &dyn Process) -> bool {
+        let tbf = match self.tbf_object(process) {
+            Some(tbf) => tbf,
+            None => return false,
+        };
+        let slot = match self.find_slot(tbf.as_ptr() as usize) {
+            Some(slot) => slot,
+            None => return false,
+        };
+
+        // A snapshot is only ever looked at once, whether it can be resumed
+        // or not.
+        self.consume(slot);
+
+        let (stored_state, memory, state) =
+            match self.load(slot, tbf, process.get_addresses().sram_start) {
+                Some(snapshot) => snapshot,
+                None => {
+                    if config::CONFIG.debug_load_processes {
+                        debug!(
+                            "Checkpoint: snapshot of {} does not match, starting it",
+                            process.get_process_name()
+                        );
+                    }
+                    return false;
+                }
+            };
+
+        match process.restore_checkpoint(stored_state, memory, state, &CheckpointerCapability) {
+            Ok(()) => {
+                if config::CONFIG.debug_load_processes {
+                    debug!("Checkpoint: resumed {}", process.get_process_name());
+                }
+                true
+            }
+            Err(e) => {
+                if config::CONFIG.debug_load_processes {
+                    debug!(
+                        "Checkpoint: could not resume {}: {:?}",
+                        process.get_process_name(),
+                        e
+                    );
+                }
+                false
+            }
+        }
+    }
+}
+
+impl NonvolatileStorageClient for ProcessCheckpointer {
+    fn read_done(&self, _buffer: &'static mut [u8], _length: usize) {}
+
+    fn write_done(&self, buffer: &'static mut [u8], _length: usize) {
+        match self.state.get() {
+            State::StoredState | State::Memory => {
+                if let Err(e) = self.write_next(buffer) {
+                    self.finish(Err(e));
+                }
+            }
+            State::Header | State::Discard | State::Consume => {
+                self.buffer.replace(buffer);
+                self.finish(Ok(()));
+            }
+            State::Idle => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+}
+
+pub(crate) const FNV_OFFSET_BASIS: u32 = 0x811c9dc5;
+
+/// Continue a 32-bit FNV-1a hash from `hash` over `data`.
+pub(crate) fn fnv1a(hash: u32, data: &[u8]) -> u32 {
+    data.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::process::ProcessSlot;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    const SLOT_SIZE: usize = 256;
+    const MEMORY_START: usize = 0x2000_4000;
+
+    /// Storage that records writes instead of changing the region.
+    struct Storage {
+        writes: core::cell::RefCell<Vec<(usize, Vec<u8>)>>,
+        buffer: TakeCell<'static, [u8]>,
+    }
+
+    impl NonvolatileStorage<'static> for Storage {
+        fn set_client(&self, _client: &'static dyn NonvolatileStorageClient) {}
+
+        fn read(&self, _buffer: &'static mut [u8], _: usize, _: usize) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+
+        fn write(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            self.writes
+                .borrow_mut()
+                .push((address, buffer[..length].to_vec()));
+            self.buffer.replace(buffer);
+            Ok(())
+        }
+    }
+
+    /// A slot holding a snapshot of `tbf` with `memory`, or the header of
+    /// such a snapshot changed by `tamper`.
+    fn slot(tbf: &[u8], memory: &[u8], tamper: impl Fn(&mut [u8])) -> Vec<u8> {
+        let stored_state = [0xaa; 16];
+        let mut slot = std::vec![0xff; SLOT_SIZE];
+        slot[HEADER_LEN..HEADER_LEN + stored_state.len()].copy_from_slice(&stored_state);
+        let memory_offset = HEADER_LEN + stored_state.len();
+        slot[memory_offset..memory_offset + memory.len()].copy_from_slice(memory);
+        let fields = [
+            (MAGIC_OFFSET, MAGIC),
+            (VERSION_OFFSET, FORMAT_VERSION),
+            (KERNEL_VERSION_OFFSET, ProcessCheckpointer::kernel_version()),
+            (FLASH_START_OFFSET, tbf.as_ptr() as u32),
+            (FINGERPRINT_OFFSET, fnv1a(FNV_OFFSET_BASIS, tbf)),
+            (MEMORY_START_OFFSET, MEMORY_START as u32),
+            (STORED_STATE_LEN_OFFSET, stored_state.len() as u32),
+            (MEMORY_LEN_OFFSET, memory.len() as u32),
+            (STATE_OFFSET, STATE_YIELDED),
+            (
+                CHECKSUM_OFFSET,
+                fnv1a(
+                    FNV_OFFSET_BASIS,
+                    &slot[HEADER_LEN..memory_offset + memory.len()],
+                ),
+            ),
+        ];
+        for (offset, value) in fields {
+            slot[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        tamper(&mut slot);
+        slot
+    }
+
+    fn set_u32(slot: &mut [u8], offset: usize, value: u32) {
+        slot[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn checkpointer(
+        tbf: &'static [u8],
+        region: Vec<u8>,
+    ) -> (&'static ProcessCheckpointer, &'static Storage) {
+        let processes: &'static [ProcessSlot] = &[];
+        let kernel = Box::leak(Box::new(Kernel::new(processes)));
+        let storage = Box::leak(Box::new(Storage {
+            writes: core::cell::RefCell::new(Vec::new()),
+            buffer: TakeCell::empty(),
+        }));
+        struct Capability;
+        unsafe impl ProcessCheckpointCapability for Capability {}
+        let checkpointer = Box::leak(Box::new(ProcessCheckpointer::new(
+            kernel,
+            tbf,
+            Box::leak(region.into_boxed_slice()),
+            SLOT_SIZE,
+            storage,
+            Box::leak(Box::new([0; 256])),
+            &Capability,
+        )));
+        (checkpointer, storage)
+    }
+
+    /// Snapshot headers store 32-bit addresses.
+    fn flash_start(tbf: &[u8]) -> usize {
+        tbf.as_ptr() as u32 as usize
+    }
+
+    fn tbf() -> &'static [u8] {
+        Box::leak(Box::new([0x5a; 64]))
+    }
+
+    #[test]
+    fn intact_snapshot_is_loaded() {
+        let tbf = tbf();
+        let (checkpointer, _) = checkpointer(tbf, slot(tbf, &[1, 2, 3, 4], |_| {}));
+        let slot = checkpointer.find_slot(flash_start(tbf)).unwrap();
+        let (stored_state, memory, state) = checkpointer.load(slot, tbf, MEMORY_START).unwrap();
+        assert_eq!(stored_state, &[0xaa; 16]);
+        assert_eq!(memory, &[1, 2, 3, 4]);
+        assert_eq!(state, ProcessState::Yielded);
+    }
+
+    #[test]
+    fn corrupted_snapshot_is_rejected() {
+        let tbf = tbf();
+        let (checkpointer, _) = checkpointer(
+            tbf,
+            slot(tbf, &[1, 2, 3, 4], |slot| slot[HEADER_LEN + 17] ^= 1),
+        );
+        assert!(checkpointer.load(0, tbf, MEMORY_START).is_none());
+    }
+
+    #[test]
+    fn snapshot_of_other_kernel_or_binary_is_rejected() {
+        let tbf = tbf();
+        let (other_kernel, _) = checkpointer(
+            tbf,
+            slot(tbf, &[1, 2, 3, 4], |slot| {
+                set_u32(slot, KERNEL_VERSION_OFFSET, 0x0001_0001)
+            }),
+        );
+        assert!(other_kernel.load(0, tbf, MEMORY_START).is_none());
+
+        let (checkpointer, _) = checkpointer(tbf, slot(tbf, &[1, 2, 3, 4], |_| {}));
+        assert!(checkpointer.load(0, &[0x5b; 64], MEMORY_START).is_none());
+        assert!(checkpointer.load(0, tbf, MEMORY_START + 0x400).is_none());
+    }
+
+    #[test]
+    fn snapshot_with_other_format_is_ignored() {
+        let tbf = tbf();
+        let (checkpointer, _) = checkpointer(
+            tbf,
+            slot(tbf, &[1, 2, 3, 4], |slot| {
+                set_u32(slot, VERSION_OFFSET, FORMAT_VERSION + 1)
+            }),
+        );
+        assert_eq!(checkpointer.find_slot(flash_start(tbf)), None);
+    }
+
+    #[test]
+    fn oversized_lengths_are_rejected() {
+        let tbf = tbf();
+        let (checkpointer, _) = checkpointer(
+            tbf,
+            slot(tbf, &[1, 2, 3, 4], |slot| {
+                set_u32(slot, MEMORY_LEN_OFFSET, u32::MAX)
+            }),
+        );
+        assert!(checkpointer.load(0, tbf, MEMORY_START).is_none());
+    }
+
+    #[test]
+    fn snapshot_is_used_once() {
+        let tbf = tbf();
+        let (checkpointer, storage) = checkpointer(tbf, slot(tbf, &[1, 2, 3, 4], |_| {}));
+        let slot = checkpointer.find_slot(flash_start(tbf)).unwrap();
+        checkpointer.consume(slot);
+
+        // The snapshot is gone for the rest of this boot...
+        assert_eq!(checkpointer.find_slot(flash_start(tbf)), None);
+        // ...and its header is erased in flash for the next one.
+        let region = checkpointer.region.as_ptr() as usize;
+        assert_eq!(
+            *storage.writes.borrow(),
+            [(region + slot, std::vec![0; HEADER_LEN])]
+        );
+
+        // Once the write completes, the checkpointer is idle again.
+        checkpointer.write_done(storage.buffer.take().unwrap(), HEADER_LEN);
+        assert_eq!(checkpointer.state.get(), State::Idle);
+        assert_eq!(storage.writes.borrow().len(), 1);
+    }
+}
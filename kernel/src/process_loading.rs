@@ -18,7 +18,7 @@ use crate::debug;
 use crate::kernel::{Kernel, ProcessCheckerMachine};
 use crate::platform::chip::Chip;
 use crate::platform::platform::KernelResources;
-use crate::process::{Process, ShortID};
+use crate::process::{Process, ProcessSlot, ShortID};
 use crate::process_checker::AppCredentialsChecker;
 use crate::process_policies::ProcessFaultPolicy;
 use crate::process_standard::ProcessStandard;
@@ -197,25 +197,46 @@ pub fn load_and_check_processes<KR: KernelResources<C>, C: Chip>(
     chip: &'static C,
     app_flash: &'static [u8],
     app_memory: &'static mut [u8],
-    mut procs: &'static mut [Option<&'static dyn Process>],
+    procs: &'static [ProcessSlot],
     fault_policy: &'static dyn ProcessFaultPolicy,
     _capability_management: &dyn ProcessManagementCapability,
 ) -> Result<(), ProcessLoadError>
 where
     <KR as KernelResources<C>>::CredentialsCheckingPolicy: 'static,
 {
-    load_processes_from_flash(
-        kernel,
-        chip,
-        app_flash,
-        app_memory,
-        &mut procs,
-        fault_policy,
-    )?;
+    load_processes_from_flash(kernel, chip, app_flash, app_memory, procs, fault_policy)?;
     let _res = check_processes(kernel_resources, kernel.get_checker());
     Ok(())
 }
 
+/// Load and check processes exactly like `load_and_check_processes`, but
+/// return the portion of `app_memory` that was not given to any process.
+/// Boards that load additional processes while the kernel is running (see
+/// `kernel::dynamic_process_loading`) pass this memory to the dynamic loader.
+///
+/// This function is made `pub` so that board files can use it, but loading
+/// processes from slices of flash an memory is fundamentally unsafe. Therefore,
+/// we require the `ProcessManagementCapability` to call this function.
+#[inline(always)]
+pub fn load_and_check_processes_with_remainder<KR: KernelResources<C>, C: Chip>(
+    kernel: &'static Kernel,
+    kernel_resources: &KR,
+    chip: &'static C,
+    app_flash: &'static [u8],
+    app_memory: &'static mut [u8],
+    procs: &'static [ProcessSlot],
+    fault_policy: &'static dyn ProcessFaultPolicy,
+    _capability_management: &dyn ProcessManagementCapability,
+) -> Result<&'static mut [u8], ProcessLoadError>
+where
+    <KR as KernelResources<C>>::CredentialsCheckingPolicy: 'static,
+{
+    let remaining_memory =
+        load_processes_from_flash(kernel, chip, app_flash, app_memory, procs, fault_policy)?;
+    let _res = check_processes(kernel_resources, kernel.get_checker());
+    Ok(remaining_memory)
+}
+
 /// Load processes (stored as TBF objects in flash) into runnable
 /// process structures stored in the `procs` array and mark all
 /// successfully loaded processes as runnable. This method does not
@@ -239,25 +260,18 @@ pub fn load_processes<C: Chip>(
     chip: &'static C,
     app_flash: &'static [u8],
     app_memory: &'static mut [u8],
-    mut procs: &'static mut [Option<&'static dyn Process>],
+    procs: &'static [ProcessSlot],
     fault_policy: &'static dyn ProcessFaultPolicy,
     _capability_management: &dyn ProcessManagementCapability,
 ) -> Result<(), ProcessLoadError> {
-    load_processes_from_flash(
-        kernel,
-        chip,
-        app_flash,
-        app_memory,
-        &mut procs,
-        fault_policy,
-    )?;
+    load_processes_from_flash(kernel, chip, app_flash, app_memory, procs, fault_policy)?;
 
     if config::CONFIG.debug_process_credentials {
         debug!("Checking: no checking, load and run all processes");
     }
     let capability = create_capability!(ProcessApprovalCapability);
     for proc in procs.iter() {
-        let res = proc.map(|p| {
+        let res = proc.get().map(|p| {
             p.mark_credentials_pass(None, ShortID::LocallyUnique, &capability)
                 .or(Err(ProcessLoadError::InternalError))?;
             if config::CONFIG.debug_process_credentials {
@@ -287,18 +301,18 @@ pub fn load_processes<C: Chip>(
 /// How process faults are handled by the
 /// kernel must be provided and is assigned to every created process.
 ///
-/// Returns `Ok(())` if process discovery went as expected. Returns a
-/// `ProcessLoadError` if something goes wrong during TBF parsing or process
-/// creation.
+/// Returns `Ok` with the memory not assigned to any process if process
+/// discovery went as expected. Returns a `ProcessLoadError` if something goes
+/// wrong during TBF parsing or process creation.
 #[inline(always)]
 fn load_processes_from_flash<C: Chip>(
     kernel: &'static Kernel,
     chip: &'static C,
     app_flash: &'static [u8],
     app_memory: &'static mut [u8],
-    procs: &mut &'static mut [Option<&'static dyn Process>],
+    procs: &'static [ProcessSlot],
     fault_policy: &'static dyn ProcessFaultPolicy,
-) -> Result<(), ProcessLoadError> {
+) -> Result<&'static mut [u8], ProcessLoadError> {
     if config::CONFIG.debug_load_processes {
         debug!(
             "Loading processes from flash={:#010X}-{:#010X} into sram={:#010X}-{:#010X}",
@@ -331,7 +345,7 @@ fn load_processes_from_flash<C: Chip>(
                     if config::CONFIG.debug_load_processes {
                         proc.map(|p| debug!("Loaded process {}", p.get_process_name()));
                     }
-                    procs[index] = proc;
+                    procs[index].set(proc);
                     index += 1;
                 } else {
                     if config::CONFIG.debug_load_processes {
@@ -339,7 +353,8 @@ fn load_processes_from_flash<C: Chip>(
                     }
                 }
             }
-            Err((_new_flash, _new_mem, err)) => {
+            Err((_new_flash, new_mem, err)) => {
+                remaining_memory = new_mem;
                 if config::CONFIG.debug_load_processes {
                     debug!("No more processes to load: {:?}.", err);
                 }
@@ -348,7 +363,7 @@ fn load_processes_from_flash<C: Chip>(
             }
         }
     }
-    Ok(())
+    Ok(remaining_memory)
 }
 
 /// Use `checker` to transition `procs` from the
@@ -8,8 +8,12 @@
 //! kernel can use when managing processes. For example, these policies control
 //! decisions such as whether a specific process should be restarted.
 
+use core::cell::Cell;
+
+use crate::hil::time::{self, ConvertTicks, Ticks};
+use crate::kernel::Kernel;
 use crate::process;
-use crate::process::Process;
+use crate::process::{Process, ProcessId, State};
 
 /// Generic trait for implementing a policy on what to do when a process faults.
 ///
@@ -109,3 +113,534 @@ impl ProcessFaultPolicy for ThresholdRestartThenPanicFaultPolicy {
         }
     }
 }
+
+/// The fault behavior a process requests with the Fault Policy TBF header.
+///
+/// Only `BackoffRestartFaultPolicy` honors the request. Boards that use any
+/// other fault policy ignore the header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultPolicyRequest {
+    /// Stop the process and no longer schedule it.
+    Stop,
+    /// Restart the process after a delay that doubles with every consecutive
+    /// fault, from `initial_delay_ms` up to `max_delay_ms`. After
+    /// `max_restarts` consecutive restarts the process is stopped instead;
+    /// `None` means there is no limit.
+    RestartWithBackoff {
+        initial_delay_ms: u32,
+        max_delay_ms: u32,
+        max_restarts: Option<u32>,
+    },
+}
+
+/// Bounds a board places on the restart behavior processes can request from
+/// `BackoffRestartFaultPolicy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BackoffBounds {
+    /// The shortest delay before a process is restarted.
+    pub min_delay_ms: u32,
+    /// The longest delay before a process is restarted.
+    pub max_delay_ms: u32,
+    /// The most consecutive restarts of a process, or `None` for no limit.
+    pub max_restarts: Option<u32>,
+}
+
+impl BackoffBounds {
+    /// Limit `request` to these bounds. Stopping a process is always allowed.
+    pub fn apply(&self, request: FaultPolicyRequest) -> FaultPolicyRequest {
+        match request {
+            FaultPolicyRequest::Stop => FaultPolicyRequest::Stop,
+            FaultPolicyRequest::RestartWithBackoff {
+                initial_delay_ms,
+                max_delay_ms,
+                max_restarts,
+            } => {
+                let upper = core::cmp::max(self.min_delay_ms, self.max_delay_ms);
+                let initial_delay_ms = initial_delay_ms.clamp(self.min_delay_ms, upper);
+                FaultPolicyRequest::RestartWithBackoff {
+                    initial_delay_ms,
+                    max_delay_ms: max_delay_ms.clamp(initial_delay_ms, upper),
+                    max_restarts: match (max_restarts, self.max_restarts) {
+                        (Some(requested), Some(bound)) => Some(core::cmp::min(requested, bound)),
+                        (requested, bound) => requested.or(bound),
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Restart state of the process in one slot of the processes array.
+#[derive(Clone, Copy)]
+struct Backoff<T: Ticks> {
+    /// Number of consecutive faults that led to a restart.
+    failures: u32,
+    /// When the process was last restarted by this policy.
+    last_restart: Option<T>,
+    /// The faulted process waiting to be restarted, with the reference time
+    /// and delay of its restart.
+    pending: Option<(ProcessId, T, T)>,
+}
+
+impl<T: Ticks> Backoff<T> {
+    /// Count another fault of the process and return the delay in
+    /// milliseconds before it is restarted, or `None` if it has been
+    /// restarted `max_restarts` times in a row and must stay stopped.
+    /// `healthy` is whether the process ran for at least `max_delay_ms` since
+    /// its last restart, in which case it starts over with `initial_delay_ms`.
+    fn next_delay_ms(
+        &mut self,
+        initial_delay_ms: u32,
+        max_delay_ms: u32,
+        max_restarts: Option<u32>,
+        healthy: bool,
+    ) -> Option<u32> {
+        if healthy {
+            self.failures = 0;
+        }
+        if max_restarts.map_or(false, |max| self.failures >= max) {
+            return None;
+        }
+        let delay = (initial_delay_ms as u64) << core::cmp::min(self.failures, 32);
+        self.failures = self.failures.saturating_add(1);
+        Some(core::cmp::min(delay, max_delay_ms as u64) as u32)
+    }
+}
+
+/// Restart a faulted process after a delay that grows exponentially with the
+/// number of consecutive faults, instead of restarting it immediately.
+///
+/// Each process can request its behavior with the Fault Policy TBF header;
+/// processes without the header use the board's default. This is the only
+/// fault policy that honors the header. Requests are
+/// limited to the board's `BackoffBounds`. While a process waits to be
+/// restarted it is in the `Faulted` state. A process that runs for at least
+/// its maximum delay without faulting starts over with its initial delay.
+///
+/// `NUM_PROCS` must be the length of the kernel's processes array.
+pub struct BackoffRestartFaultPolicy<'a, A: time::Alarm<'a>, const NUM_PROCS: usize> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    default: FaultPolicyRequest,
+    bounds: BackoffBounds,
+    backoffs: [Cell<Backoff<A::Ticks>>; NUM_PROCS],
+}
+
+impl<'a, A: time::Alarm<'a>, const NUM_PROCS: usize> BackoffRestartFaultPolicy<'a, A, NUM_PROCS> {
+    pub fn new(
+        kernel: &'static Kernel,
+        alarm: &'a A,
+        default: FaultPolicyRequest,
+        bounds: BackoffBounds,
+    ) -> BackoffRestartFaultPolicy<'a, A, NUM_PROCS> {
+        BackoffRestartFaultPolicy {
+            kernel,
+            alarm,
+            default,
+            bounds,
+            backoffs: core::array::from_fn(|_| {
+                Cell::new(Backoff {
+                    failures: 0,
+                    last_restart: None,
+                    pending: None,
+                })
+            }),
+        }
+    }
+
+    /// Set the alarm for the earliest pending restart, if there is one.
+    fn arm(&self) {
+        let now = self.alarm.now();
+        let next = self
+            .backoffs
+            .iter()
+            .filter_map(|backoff| backoff.get().pending)
+            .map(|(_, reference, delay)| {
+                let elapsed = now.wrapping_sub(reference);
+                if elapsed.into_u32() >= delay.into_u32() {
+                    A::Ticks::from(0)
+                } else {
+                    delay.wrapping_sub(elapsed)
+                }
+            })
+            .min_by_key(|remaining| remaining.into_u32());
+        match next {
+            Some(remaining) => self.alarm.set_alarm(now, remaining),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const NUM_PROCS: usize> ProcessFaultPolicy
+    for BackoffRestartFaultPolicy<'a, A, NUM_PROCS>
+{
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        let request = self
+            .bounds
+            .apply(process.get_requested_fault_policy().unwrap_or(self.default));
+        let (initial_delay_ms, max_delay_ms, max_restarts) = match request {
+            FaultPolicyRequest::Stop => return process::FaultAction::Stop,
+            FaultPolicyRequest::RestartWithBackoff {
+                initial_delay_ms,
+                max_delay_ms,
+                max_restarts,
+            } => (initial_delay_ms, max_delay_ms, max_restarts),
+        };
+        let processid = process.processid();
+        let backoff = match self.backoffs.get(processid.index) {
+            Some(backoff) => backoff,
+            None => return process::FaultAction::Stop,
+        };
+
+        let now = self.alarm.now();
+        let mut state = backoff.get();
+        let healthy = state.last_restart.map_or(false, |last_restart| {
+            now.wrapping_sub(last_restart).into_u32()
+                >= self.alarm.ticks_from_ms(max_delay_ms).into_u32()
+        });
+        let delay_ms =
+            match state.next_delay_ms(initial_delay_ms, max_delay_ms, max_restarts, healthy) {
+                Some(delay_ms) => delay_ms,
+                None => {
+                    state.pending = None;
+                    backoff.set(state);
+                    return process::FaultAction::Stop;
+                }
+            };
+        state.pending = Some((processid, now, self.alarm.ticks_from_ms(delay_ms)));
+        backoff.set(state);
+        self.arm();
+
+        // The process stays faulted until the alarm restarts it.
+        process::FaultAction::Stop
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const NUM_PROCS: usize> time::AlarmClient
+    for BackoffRestartFaultPolicy<'a, A, NUM_PROCS>
+{
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        for backoff in self.backoffs.iter() {
+            let mut state = backoff.get();
+            if let Some((processid, reference, delay)) = state.pending {
+                if now.wrapping_sub(reference).into_u32() < delay.into_u32() {
+                    continue;
+                }
+                state.pending = None;
+                state.last_restart = Some(now);
+                backoff.set(state);
+                // Someone else may have restarted or removed the process in
+                // the meantime, in which case its identifier changed.
+                self.kernel.process_map_or((), processid, |process| {
+                    if process.get_state() == State::Faulted {
+                        process.try_restart(None);
+                    }
+                });
+            }
+        }
+        self.arm();
+    }
+}
+
+/// Limits on how much memory a process can use beyond the memory it starts
+/// with. `None` means the respective use is not limited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProcessMemoryQuota {
+    /// How many bytes the process can move its memory break beyond its
+    /// initial break with `memop`.
+    pub heap: Option<usize>,
+    /// How many bytes the kernel can allocate for grants of the process.
+    pub grant: Option<usize>,
+}
+
+impl ProcessMemoryQuota {
+    /// Returns the quota that enforces both `self` and `other`, the lower
+    /// limit of the two for every use.
+    pub fn min(self, other: ProcessMemoryQuota) -> ProcessMemoryQuota {
+        let min = |a: Option<usize>, b: Option<usize>| match (a, b) {
+            (Some(a), Some(b)) => Some(core::cmp::min(a, b)),
+            (a, None) => a,
+            (None, b) => b,
+        };
+        ProcessMemoryQuota {
+            heap: min(self.heap, other.heap),
+            grant: min(self.grant, other.grant),
+        }
+    }
+
+    /// Whether the quota allows a process to move its memory break from
+    /// `initial_break` to `new_break`.
+    pub fn allows_heap(&self, initial_break: usize, new_break: usize) -> bool {
+        self.heap.map_or(true, |quota| {
+            new_break.saturating_sub(initial_break) <= quota
+        })
+    }
+
+    /// Whether the quota allows the kernel to move the kernel memory break of
+    /// a process from `initial_break` down to `new_break` for grants.
+    pub fn allows_grant(&self, initial_break: usize, new_break: usize) -> bool {
+        self.grant.map_or(true, |quota| {
+            initial_break.saturating_sub(new_break) <= quota
+        })
+    }
+}
+
+/// Generic trait for implementing a policy on how much memory a process can
+/// use.
+///
+/// The kernel asks the policy whenever a process grows its heap or a grant is
+/// allocated for it, so the quota can depend on the current state of the
+/// process. Allocations that would exceed the quota fail with `NOMEM`.
+pub trait ProcessMemoryQuotaPolicy {
+    /// Decide how much memory `process` can use.
+    fn quota(&self, process: &dyn Process) -> ProcessMemoryQuota;
+}
+
+/// Give every process the same quota. A process can request a lower quota for
+/// itself with a Memory Quota TBF header, but never a higher one: the kernel
+/// enforces the lower of the board quota and the requested quota for each use.
+///
+/// A process cannot use more memory than its RAM block no matter what quota
+/// applies.
+pub struct DefaultMemoryQuotaPolicy {
+    quota: ProcessMemoryQuota,
+}
+
+impl DefaultMemoryQuotaPolicy {
+    pub const fn new(heap: Option<usize>, grant: Option<usize>) -> DefaultMemoryQuotaPolicy {
+        DefaultMemoryQuotaPolicy {
+            quota: ProcessMemoryQuota { heap, grant },
+        }
+    }
+
+    /// Combine the board quota with the `(heap, grant)` quota a process
+    /// requests in its TBF header, where `u32::MAX` means no limit.
+    fn combine(&self, requested: Option<(u32, u32)>) -> ProcessMemoryQuota {
+        match requested {
+            Some((heap, grant)) => {
+                let limit = |size: u32| (size != u32::MAX).then_some(size as usize);
+                self.quota.min(ProcessMemoryQuota {
+                    heap: limit(heap),
+                    grant: limit(grant),
+                })
+            }
+            None => self.quota,
+        }
+    }
+}
+
+impl ProcessMemoryQuotaPolicy for DefaultMemoryQuotaPolicy {
+    fn quota(&self, process: &dyn Process) -> ProcessMemoryQuota {
+        self.combine(process.get_requested_memory_quota())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_cannot_raise_board_quota() {
+        let policy = DefaultMemoryQuotaPolicy::new(Some(1024), Some(512));
+        assert_eq!(
+            policy.combine(Some((u32::MAX, u32::MAX))),
+            ProcessMemoryQuota {
+                heap: Some(1024),
+                grant: Some(512)
+            }
+        );
+        assert_eq!(
+            policy.combine(Some((4096, 4096))),
+            ProcessMemoryQuota {
+                heap: Some(1024),
+                grant: Some(512)
+            }
+        );
+    }
+
+    #[test]
+    fn header_can_lower_board_quota() {
+        let policy = DefaultMemoryQuotaPolicy::new(Some(1024), None);
+        assert_eq!(
+            policy.combine(Some((256, u32::MAX))),
+            ProcessMemoryQuota {
+                heap: Some(256),
+                grant: None
+            }
+        );
+        assert_eq!(
+            policy.combine(Some((u32::MAX, 128))),
+            ProcessMemoryQuota {
+                heap: Some(1024),
+                grant: Some(128)
+            }
+        );
+    }
+
+    #[test]
+    fn board_quota_applies_without_header() {
+        let policy = DefaultMemoryQuotaPolicy::new(Some(1024), Some(512));
+        assert_eq!(
+            policy.combine(None),
+            ProcessMemoryQuota {
+                heap: Some(1024),
+                grant: Some(512)
+            }
+        );
+    }
+
+    #[test]
+    fn heap_quota_limits_break_growth() {
+        let quota = ProcessMemoryQuota {
+            heap: Some(0x100),
+            grant: None,
+        };
+        assert!(quota.allows_heap(0x2000_1000, 0x2000_1100));
+        assert!(!quota.allows_heap(0x2000_1000, 0x2000_1101));
+        // Shrinking below the initial break is always within the quota.
+        assert!(quota.allows_heap(0x2000_1000, 0x2000_0800));
+        assert!(ProcessMemoryQuota::default().allows_heap(0x2000_1000, 0x2000_8000));
+    }
+
+    #[test]
+    fn grant_quota_limits_kernel_break_growth() {
+        let quota = ProcessMemoryQuota {
+            heap: None,
+            grant: Some(0x40),
+        };
+        assert!(quota.allows_grant(0x2000_2000, 0x2000_1fc0));
+        assert!(!quota.allows_grant(0x2000_2000, 0x2000_1fbc));
+        assert!(ProcessMemoryQuota::default().allows_grant(0x2000_2000, 0x2000_1000));
+    }
+
+    const BOUNDS: BackoffBounds = BackoffBounds {
+        min_delay_ms: 100,
+        max_delay_ms: 10_000,
+        max_restarts: Some(5),
+    };
+
+    fn restart(
+        initial_delay_ms: u32,
+        max_delay_ms: u32,
+        max_restarts: Option<u32>,
+    ) -> FaultPolicyRequest {
+        FaultPolicyRequest::RestartWithBackoff {
+            initial_delay_ms,
+            max_delay_ms,
+            max_restarts,
+        }
+    }
+
+    fn backoff() -> Backoff<crate::hil::time::Ticks32> {
+        Backoff {
+            failures: 0,
+            last_restart: None,
+            pending: None,
+        }
+    }
+
+    #[test]
+    fn bounds_clamp_requested_delays() {
+        assert_eq!(
+            BOUNDS.apply(restart(1, 1_000_000, Some(3))),
+            restart(100, 10_000, Some(3))
+        );
+        assert_eq!(
+            BOUNDS.apply(restart(500, 200, Some(3))),
+            restart(500, 500, Some(3))
+        );
+        assert_eq!(
+            BOUNDS.apply(restart(20_000, 30_000, Some(3))),
+            restart(10_000, 10_000, Some(3))
+        );
+        assert_eq!(
+            BOUNDS.apply(FaultPolicyRequest::Stop),
+            FaultPolicyRequest::Stop
+        );
+    }
+
+    #[test]
+    fn bounds_merge_max_restarts() {
+        // The smaller limit wins, and a limit on either side applies.
+        assert_eq!(
+            BOUNDS.apply(restart(100, 100, Some(9))),
+            restart(100, 100, Some(5))
+        );
+        assert_eq!(
+            BOUNDS.apply(restart(100, 100, Some(2))),
+            restart(100, 100, Some(2))
+        );
+        assert_eq!(
+            BOUNDS.apply(restart(100, 100, None)),
+            restart(100, 100, Some(5))
+        );
+        let unlimited = BackoffBounds {
+            max_restarts: None,
+            ..BOUNDS
+        };
+        assert_eq!(
+            unlimited.apply(restart(100, 100, Some(2))),
+            restart(100, 100, Some(2))
+        );
+        assert_eq!(
+            unlimited.apply(restart(100, 100, None)),
+            restart(100, 100, None)
+        );
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_maximum() {
+        let mut state = backoff();
+        let delays: [Option<u32>; 6] =
+            core::array::from_fn(|_| state.next_delay_ms(100, 1000, None, false));
+        assert_eq!(
+            delays,
+            [
+                Some(100),
+                Some(200),
+                Some(400),
+                Some(800),
+                Some(1000),
+                Some(1000)
+            ]
+        );
+    }
+
+    #[test]
+    fn delay_saturates_after_many_faults() {
+        let mut state = backoff();
+        state.failures = u32::MAX - 1;
+        assert_eq!(
+            state.next_delay_ms(u32::MAX, u32::MAX, None, false),
+            Some(u32::MAX)
+        );
+        assert_eq!(
+            state.next_delay_ms(u32::MAX, u32::MAX, None, false),
+            Some(u32::MAX)
+        );
+        assert_eq!(state.failures, u32::MAX);
+    }
+
+    #[test]
+    fn max_restarts_stops_the_process() {
+        let mut state = backoff();
+        assert_eq!(state.next_delay_ms(100, 1000, Some(2), false), Some(100));
+        assert_eq!(state.next_delay_ms(100, 1000, Some(2), false), Some(200));
+        assert_eq!(state.next_delay_ms(100, 1000, Some(2), false), None);
+        assert_eq!(state.next_delay_ms(100, 1000, Some(2), false), None);
+    }
+
+    #[test]
+    fn healthy_run_resets_the_delay() {
+        let mut state = backoff();
+        for _ in 0..3 {
+            state.next_delay_ms(100, 1000, Some(3), false);
+        }
+        assert_eq!(state.next_delay_ms(100, 1000, Some(3), false), None);
+        assert_eq!(state.next_delay_ms(100, 1000, Some(3), true), Some(100));
+        assert_eq!(state.next_delay_ms(100, 1000, Some(3), false), Some(200));
+    }
+}
@@ -25,13 +25,15 @@ use crate::platform::chip::Chip;
 use crate::platform::mpu::{self, MPU};
 use crate::process::BinaryVersion;
 use crate::process::{Error, FunctionCall, FunctionCallSource, Process, State, Task};
-use crate::process::{FaultAction, ProcessCustomGrantIdentifier, ProcessId};
+use crate::process::{FaultAction, FaultPolicyRequest, ProcessCustomGrantIdentifier, ProcessId};
 use crate::process::{ProcessAddresses, ProcessSizes, ShortID};
 use crate::process_loading::ProcessLoadError;
 use crate::process_policies::ProcessFaultPolicy;
 use crate::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
+use crate::sandbox::SandboxViolation;
 use crate::storage_permissions;
 use crate::syscall::{self, Syscall, SyscallReturn, UserspaceKernelBoundary};
+use crate::syscall_trace::TraceEvent;
 use crate::upcall::UpcallId;
 use crate::utilities::cells::{MapCell, NumericCellExt, OptionalCell};
 
@@ -76,6 +78,23 @@ struct ProcessStandardDebug {
     /// How many times this process has been paused because it exceeded its
     /// timeslice.
     timeslice_expiration_count: usize,
+
+    /// How many microseconds the process has executed for, as measured by the
+    /// scheduler timer.
+    run_time_us: u64,
+
+    /// How many microseconds the kernel has spent handling syscalls of the
+    /// process.
+    syscall_time_us: u64,
+
+    /// How many real-time deadlines the process has missed.
+    deadline_miss_count: usize,
+
+    /// How many times the process has violated its sandbox profile.
+    sandbox_violation_count: usize,
+
+    /// What was the most recent sandbox violation.
+    last_sandbox_violation: Option<SandboxViolation>,
 }
 
 /// Entry that is stored in the grant pointer table at the top of process
@@ -97,6 +116,10 @@ struct GrantPointerEntry {
     /// The start of the memory location where the grant has been allocated, or
     /// null if the grant has not been allocated.
     grant_ptr: *mut u8,
+
+    /// The size of the grant allocation in bytes, or 0 if the grant has not
+    /// been allocated.
+    size: usize,
 }
 
 /// A type for userspace processes in Tock.
@@ -170,6 +193,11 @@ pub struct ProcessStandard<'a, C: 'static + Chip> {
     /// Pointer to the end of process RAM that has been sbrk'd to the process.
     app_break: Cell<*const u8>,
 
+    /// The `kernel_memory_break` and `app_break` the process started with.
+    /// Memory quotas limit how far the breaks can move from these.
+    initial_kernel_memory_break: Cell<*const u8>,
+    initial_app_break: Cell<*const u8>,
+
     /// Pointer to high water mark for process buffers shared through `allow`
     allow_high_water_mark: Cell<*const u8>,
 
@@ -205,7 +233,17 @@ pub struct ProcessStandard<'a, C: 'static + Chip> {
     state: Cell<State>,
 
     /// How to respond if this process faults.
-    fault_policy: &'a dyn ProcessFaultPolicy,
+    fault_policy: Cell<&'a dyn ProcessFaultPolicy>,
+
+    /// Scheduling priority assigned at runtime, if any.
+    priority: OptionalCell<u32>,
+
+    /// Whether a debugger is attached to this process.
+    debugger_attached: Cell<bool>,
+
+    /// Whether the process should be stopped the next time it returns to the
+    /// kernel.
+    debug_step: Cell<bool>,
 
     /// Configuration data for the MPU
     mpu_config: MapCell<<<C as Chip>::MPU as MPU>::MpuConfig>,
@@ -266,6 +304,25 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             return Err(ErrorCode::NODEVICE);
         }
 
+        // A sandboxed process may be limited to fewer queued upcalls than fit
+        // in its task queue.
+        let violation = self.kernel.sandbox_profile(self).and_then(|profile| {
+            self.tasks
+                .map(|tasks| {
+                    let (first, second) = tasks.as_slices();
+                    let queued = first.into_iter().chain(second).flatten();
+                    profile.check_task(&task, queued).err()
+                })
+                .flatten()
+        });
+        if let Some(violation) = violation {
+            self.debug_sandbox_violation(violation);
+            self.debug.map(|debug| {
+                debug.dropped_upcall_count += 1;
+            });
+            return Err(ErrorCode::NOMEM);
+        }
+
         let ret = self.tasks.map_or(Err(ErrorCode::FAIL), |tasks| {
             match tasks.enqueue(task) {
                 true => {
@@ -350,9 +407,19 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
     }
 
     fn ready(&self) -> bool {
-        self.tasks.map_or(false, |ring_buf| ring_buf.has_elements())
-            || self.state.get() == State::Running
-            || self.state.get() == State::CredentialsApproved
+        match self.state.get() {
+            // A process waiting for a specific upcall has nothing to do until
+            // that upcall is scheduled, whatever else is in its queue.
+            State::YieldedFor(upcall_id) => self.tasks.map_or(false, |tasks| {
+                let (left, right) = tasks.as_slices();
+                left.into_iter()
+                    .chain(right)
+                    .flatten()
+                    .any(|task| Self::is_upcall(task, upcall_id))
+            }),
+            State::Running | State::CredentialsApproved => true,
+            _ => self.tasks.map_or(false, |ring_buf| ring_buf.has_elements()),
+        }
     }
 
     fn remove_pending_upcalls(&self, upcall_id: UpcallId) {
@@ -380,9 +447,20 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         });
     }
 
+    fn remove_upcall(&self, upcall_id: UpcallId) -> Option<Task> {
+        self.tasks.map_or(None, |tasks| {
+            tasks.remove_first_matching(|task| Self::is_upcall(task, upcall_id))
+        })
+    }
+
     fn is_running(&self) -> bool {
         match self.state.get() {
-            State::Running | State::Yielded | State::StoppedRunning | State::StoppedYielded => true,
+            State::Running
+            | State::Yielded
+            | State::YieldedFor(_)
+            | State::StoppedRunning
+            | State::StoppedYielded
+            | State::StoppedYieldedFor(_) => true,
             _ => false,
         }
     }
@@ -397,10 +475,17 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         }
     }
 
+    fn set_yielded_for_state(&self, upcall_id: UpcallId) {
+        if self.state.get() == State::Running {
+            self.state.set(State::YieldedFor(upcall_id));
+        }
+    }
+
     fn stop(&self) {
         match self.state.get() {
             State::Running => self.state.set(State::StoppedRunning),
             State::Yielded => self.state.set(State::StoppedYielded),
+            State::YieldedFor(upcall_id) => self.state.set(State::StoppedYieldedFor(upcall_id)),
             _ => {} // Do nothing
         }
     }
@@ -409,20 +494,32 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         match self.state.get() {
             State::StoppedRunning => self.state.set(State::Running),
             State::StoppedYielded => self.state.set(State::Yielded),
+            State::StoppedYieldedFor(upcall_id) => self.state.set(State::YieldedFor(upcall_id)),
             _ => {} // Do nothing
         }
     }
 
     fn set_fault_state(&self) {
+        // A debugger inspects faults itself, so just stop the process where it
+        // faulted.
+        if self.debugger_attached.get() {
+            self.debug_step.set(false);
+            self.stop();
+            return;
+        }
+
         // Use the per-process fault policy to determine what action the kernel
         // should take since the process faulted.
-        let action = self.fault_policy.action(self);
+        let action = self.fault_policy.get().action(self);
         let state = self.state.get();
         // Accidentally calling faulted on an unchecked or failed process should
         // not make it eventually runnable.
         if state == State::CredentialsFailed || state == State::CredentialsUnchecked {
             return;
         }
+        // Record the fault while the registers and stack of the process still
+        // show where it faulted.
+        self.kernel.record_fault(self);
         match action {
             FaultAction::Panic => {
                 // process faulted. Panic and print status
@@ -503,6 +600,14 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         self.state.set(State::Terminated);
     }
 
+    fn set_fault_policy(
+        &self,
+        fault_policy: &'static dyn ProcessFaultPolicy,
+        _capability: &dyn capabilities::ProcessDebugCapability,
+    ) {
+        self.fault_policy.set(fault_policy);
+    }
+
     fn get_restart_count(&self) -> usize {
         self.restart_count.get()
     }
@@ -523,6 +628,51 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         self.header.get_command_permissions(driver_num, offset)
     }
 
+    fn get_real_time_parameters(&self) -> Option<(u32, u32)> {
+        self.header.get_real_time_parameters()
+    }
+
+    fn get_priority(&self) -> Option<u32> {
+        self.priority.get()
+    }
+
+    fn set_priority(
+        &self,
+        priority: Option<u32>,
+        _capability: &dyn capabilities::ProcessDebugCapability,
+    ) {
+        self.priority.insert(priority);
+    }
+
+    fn get_ipc_client_ids(&self) -> Option<(usize, [u32; tock_tbf::types::NUM_IPC_CLIENTS])> {
+        self.header.get_ipc_client_ids()
+    }
+
+    fn get_requested_memory_quota(&self) -> Option<(u32, u32)> {
+        self.header.get_memory_quota()
+    }
+
+    fn get_requested_fault_policy(&self) -> Option<FaultPolicyRequest> {
+        self.header.get_fault_policy().map(
+            |(policy, initial_delay_ms, max_delay_ms, max_restarts)| match policy {
+                0 => FaultPolicyRequest::Stop,
+                _ => FaultPolicyRequest::RestartWithBackoff {
+                    initial_delay_ms,
+                    max_delay_ms,
+                    max_restarts: (max_restarts != u32::MAX).then_some(max_restarts),
+                },
+            },
+        )
+    }
+
+    fn get_memory_usage(&self) -> (usize, usize) {
+        let heap =
+            (self.app_break.get() as usize).saturating_sub(self.initial_app_break.get() as usize);
+        let grant = (self.initial_kernel_memory_break.get() as usize)
+            .saturating_sub(self.kernel_memory_break.get() as usize);
+        (heap, grant)
+    }
+
     fn get_storage_permissions(&self) -> Option<storage_permissions::StoragePermissions> {
         let (read_count, read_ids) = self.header.get_storage_read_ids().unwrap_or((0, [0; 8]));
 
@@ -644,6 +794,14 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
                 Err(Error::AddressOutOfBounds)
             } else if new_break > self.kernel_memory_break.get() {
                 Err(Error::OutOfMemory)
+            } else if !self
+                .kernel
+                .memory_quota(self)
+                .allows_heap(self.initial_app_break.get() as usize, new_break as usize)
+            {
+                // Growing the heap this far would exceed the memory quota of
+                // the process.
+                Err(Error::OutOfMemory)
             } else if let Err(_) = self.chip.mpu().update_app_memory_region(
                 new_break,
                 self.kernel_memory_break.get(),
@@ -879,6 +1037,7 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
                         // Actually set the driver num and grant pointer.
                         grant_entry.driver_num = driver_num;
                         grant_entry.grant_ptr = grant_ptr.as_ptr();
+                        grant_entry.size = size;
 
                         // If all of this worked, return true.
                         Ok(())
@@ -1012,6 +1171,22 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         })
     }
 
+    fn grant_allocation(&self, grant_num: usize) -> Option<(usize, usize)> {
+        if !self.is_running() {
+            return None;
+        }
+
+        self.grant_pointers.map_or(None, |grant_pointers| {
+            grant_pointers.get(grant_num).and_then(|grant_entry| {
+                if grant_entry.grant_ptr.is_null() {
+                    None
+                } else {
+                    Some((grant_entry.driver_num, grant_entry.size))
+                }
+            })
+        })
+    }
+
     fn lookup_grant_from_driver_num(&self, driver_num: usize) -> Result<usize, Error> {
         self.grant_pointers
             .map_or(Err(Error::KernelError), |grant_pointers| {
@@ -1045,6 +1220,8 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
     }
 
     fn set_syscall_return_value(&self, return_value: SyscallReturn) {
+        self.kernel
+            .trace(self.processid(), TraceEvent::SyscallReturn(&return_value));
         match self.stored_state.map(|stored_state| unsafe {
             // Actually set the return value for a particular process.
             //
@@ -1062,7 +1239,12 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
                 )
         }) {
             Some(Ok(())) => {
-                // If we get an `Ok` we are all set.
+                // If we get an `Ok` we are all set. A process waiting for an
+                // upcall gets the upcall as the return value of its
+                // `yield-wait-for` call and can run again.
+                if let State::YieldedFor(_) = self.state.get() {
+                    self.state.set(State::Running);
+                }
             }
 
             Some(Err(())) => {
@@ -1184,6 +1366,46 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             .map(|debug| debug.timeslice_expiration_count += 1);
     }
 
+    fn debug_run_time_us(&self) -> u64 {
+        self.debug.map_or(0, |debug| debug.run_time_us)
+    }
+
+    fn debug_executed(&self, us: u32) {
+        self.debug.map(|debug| debug.run_time_us += us as u64);
+    }
+
+    fn debug_syscall_time_us(&self) -> u64 {
+        self.debug.map_or(0, |debug| debug.syscall_time_us)
+    }
+
+    fn debug_syscall_handled(&self, us: u32) {
+        self.debug.map(|debug| debug.syscall_time_us += us as u64);
+    }
+
+    fn debug_deadline_miss_count(&self) -> usize {
+        self.debug.map_or(0, |debug| debug.deadline_miss_count)
+    }
+
+    fn debug_deadline_missed(&self) {
+        self.debug.map(|debug| debug.deadline_miss_count += 1);
+    }
+
+    fn debug_sandbox_violation_count(&self) -> usize {
+        self.debug.map_or(0, |debug| debug.sandbox_violation_count)
+    }
+
+    fn debug_last_sandbox_violation(&self) -> Option<SandboxViolation> {
+        self.debug
+            .map_or(None, |debug| debug.last_sandbox_violation)
+    }
+
+    fn debug_sandbox_violation(&self, violation: SandboxViolation) {
+        self.debug.map(|debug| {
+            debug.sandbox_violation_count += 1;
+            debug.last_sandbox_violation = Some(violation);
+        });
+    }
+
     fn debug_syscall_called(&self, last_syscall: Syscall) {
         self.debug.map(|debug| {
             debug.syscall_count += 1;
@@ -1329,6 +1551,240 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             })
             .unwrap_or(Err(ErrorCode::FAIL))
     }
+
+    fn get_stored_registers(&self) -> Option<syscall::ProcessRegisters> {
+        self.stored_state.and_then(|stored_state| {
+            // We guarantee the memory bounds pointers provided to the UKB are
+            // correct.
+            unsafe {
+                self.chip.userspace_kernel_boundary().get_registers(
+                    self.mem_start(),
+                    self.app_break.get(),
+                    stored_state,
+                )
+            }
+        })
+    }
+
+    fn get_stack_snippet(&self, out: &mut [u8]) -> usize {
+        let sp = match self.get_stored_registers() {
+            Some(registers) => registers.sp,
+            None => return 0,
+        };
+        let mem_start = self.mem_start() as usize;
+        let app_break = self.app_break.get() as usize;
+        if sp < mem_start || sp >= app_break {
+            return 0;
+        }
+        let length = cmp::min(out.len(), app_break - sp);
+        // Safety: `sp..sp + length` is within the memory the process can
+        // access. The memory is copied without creating a reference to it, as
+        // a capsule may hold a reference to part of it.
+        unsafe {
+            ptr::copy_nonoverlapping(sp as *const u8, out.as_mut_ptr(), length);
+        }
+        length
+    }
+
+    fn debug_read_memory(
+        &self,
+        address: usize,
+        out: &mut [u8],
+        _capability: &dyn capabilities::ProcessDebugCapability,
+    ) -> usize {
+        let flash_start = self.flash_start() as usize;
+        if address >= flash_start && address < self.flash_end() as usize {
+            let flash = &self.flash[address - flash_start..];
+            let length = cmp::min(out.len(), flash.len());
+            out[..length].copy_from_slice(&flash[..length]);
+            return length;
+        }
+
+        // Only the memory the process itself can access is readable; the grant
+        // region above the app break holds kernel data structures.
+        let mem_start = self.mem_start() as usize;
+        let app_break = self.app_break.get() as usize;
+        if address < mem_start || address >= app_break {
+            return 0;
+        }
+        let length = cmp::min(out.len(), app_break - address);
+        // Safety: `address..address + length` is within the memory the process
+        // can access. The memory is copied without creating a reference to
+        // it, as a capsule may hold a reference to part of it.
+        unsafe {
+            ptr::copy_nonoverlapping(address as *const u8, out.as_mut_ptr(), length);
+        }
+        length
+    }
+
+    fn debug_write_memory(
+        &self,
+        address: usize,
+        data: &[u8],
+        _capability: &dyn capabilities::ProcessDebugCapability,
+    ) -> usize {
+        let mem_start = self.mem_start() as usize;
+        let app_break = self.app_break.get() as usize;
+        if address < mem_start || address >= app_break {
+            return 0;
+        }
+        let length = cmp::min(data.len(), app_break - address);
+        // Safety: `address..address + length` is within the memory the process
+        // can access. The memory is written without creating a reference to
+        // it, as a capsule may hold a reference to part of it.
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), address as *mut u8, length);
+        }
+        length
+    }
+
+    fn get_debug_register(&self, index: usize) -> Option<usize> {
+        self.stored_state.and_then(|stored_state| {
+            // We guarantee the memory bounds pointers provided to the UKB are
+            // correct.
+            unsafe {
+                self.chip.userspace_kernel_boundary().get_debug_register(
+                    self.mem_start(),
+                    self.app_break.get(),
+                    stored_state,
+                    index,
+                )
+            }
+        })
+    }
+
+    fn set_debug_register(
+        &self,
+        index: usize,
+        value: usize,
+        _capability: &dyn capabilities::ProcessDebugCapability,
+    ) -> Result<(), ErrorCode> {
+        self.stored_state
+            .map(|stored_state| {
+                // We guarantee the memory bounds pointers provided to the UKB
+                // are correct.
+                unsafe {
+                    self.chip.userspace_kernel_boundary().set_debug_register(
+                        self.mem_start(),
+                        self.app_break.get(),
+                        stored_state,
+                        index,
+                        value,
+                    )
+                }
+            })
+            .unwrap_or(Err(ErrorCode::FAIL))
+    }
+
+    fn set_debugger_attached(&self, attached: bool) {
+        self.debugger_attached.set(attached);
+        if !attached {
+            self.debug_step.set(false);
+        }
+    }
+
+    fn is_debugger_attached(&self) -> bool {
+        self.debugger_attached.get()
+    }
+
+    fn debug_single_step(&self) {
+        self.debug_step.set(true);
+        self.resume();
+    }
+
+    fn debug_complete_step(&self) -> bool {
+        if self.debug_step.replace(false) {
+            self.stop();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn get_checkpoint_memory(
+        &self,
+        offset: usize,
+        out: &mut [u8],
+        _capability: &dyn capabilities::ProcessCheckpointCapability,
+    ) -> Result<usize, ErrorCode> {
+        if !config::CONFIG.process_checkpoint {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        match self.state.get() {
+            State::StoppedRunning | State::StoppedYielded | State::StoppedYieldedFor(_) => {}
+            _ => return Err(ErrorCode::OFF),
+        }
+
+        let app_memory_len = self.app_break.get() as usize - self.mem_start() as usize;
+        let length = cmp::min(out.len(), app_memory_len.saturating_sub(offset));
+        if length > 0 {
+            // Safety: `offset..offset + length` is within the memory the
+            // process can access, which belongs to this process and is not
+            // borrowed by the kernel while the process is stopped.
+            let memory = unsafe { slice::from_raw_parts(self.mem_start().add(offset), length) };
+            out[..length].copy_from_slice(memory);
+        }
+        Ok(length)
+    }
+
+    fn restore_checkpoint(
+        &self,
+        stored_state: &[u8],
+        memory: &[u8],
+        state: State,
+        _capability: &dyn capabilities::ProcessCheckpointCapability,
+    ) -> Result<(), ErrorCode> {
+        if !config::CONFIG.process_checkpoint {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        if self.state.get() != State::CredentialsApproved {
+            return Err(ErrorCode::NODEVICE);
+        }
+        let state = match state {
+            State::Running | State::StoppedRunning => State::Running,
+            State::Yielded | State::StoppedYielded => State::Yielded,
+            _ => return Err(ErrorCode::INVAL),
+        };
+
+        let new_break = self.mem_start().wrapping_add(memory.len());
+        if new_break > self.kernel_memory_break.get() {
+            return Err(ErrorCode::NOMEM);
+        }
+
+        // Parse the stored state first so that a snapshot from a different
+        // architecture leaves the process untouched.
+        let mut loaded =
+            <<C as Chip>::UserspaceKernelBoundary as UserspaceKernelBoundary>::StoredState::default(
+            );
+        self.chip
+            .userspace_kernel_boundary()
+            .load_context(&mut loaded, stored_state)?;
+
+        self.mpu_config.map_or(Err(ErrorCode::FAIL), |config| {
+            self.chip
+                .mpu()
+                .update_app_memory_region(
+                    new_break,
+                    self.kernel_memory_break.get(),
+                    mpu::Permissions::ReadWriteOnly,
+                    config,
+                )
+                .or(Err(ErrorCode::NOMEM))
+        })?;
+        self.app_break.set(new_break);
+        self.allow_high_water_mark.set(self.mem_start());
+
+        // Safety: `memory` ends at the new memory break, so it fits into the
+        // memory the process can access, which belongs to this process and
+        // is not borrowed by the kernel before the process has run.
+        unsafe {
+            ptr::copy_nonoverlapping(memory.as_ptr(), self.mem_start() as *mut u8, memory.len());
+        }
+        self.stored_state.map(|stored_state| *stored_state = loaded);
+
+        self.state.set(state);
+        Ok(())
+    }
 }
 
 impl<C: 'static + Chip> ProcessStandard<'_, C> {
@@ -1766,6 +2222,7 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         for grant_entry in grant_pointers.iter_mut() {
             grant_entry.driver_num = 0;
             grant_entry.grant_ptr = ptr::null_mut();
+            grant_entry.size = 0;
         }
 
         // Now that we know we have the space we can setup the memory for the
@@ -1817,6 +2274,8 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         process.header = tbf_header;
         process.kernel_memory_break = Cell::new(kernel_memory_break);
         process.app_break = Cell::new(initial_app_brk);
+        process.initial_kernel_memory_break = Cell::new(kernel_memory_break);
+        process.initial_app_break = Cell::new(initial_app_brk);
         process.grant_pointers = MapCell::new(grant_pointers);
 
         process.credentials = OptionalCell::empty();
@@ -1828,7 +2287,10 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         // Mark this process as unverified/unrunnable: leave it to the loader to
         // verify it.
         process.state = Cell::new(State::CredentialsUnchecked);
-        process.fault_policy = fault_policy;
+        process.fault_policy = Cell::new(fault_policy);
+        process.priority = OptionalCell::empty();
+        process.debugger_attached = Cell::new(false);
+        process.debug_step = Cell::new(false);
         process.restart_count = Cell::new(0);
         process.completion_code = OptionalCell::empty();
 
@@ -1854,6 +2316,11 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             last_syscall: None,
             dropped_upcall_count: 0,
             timeslice_expiration_count: 0,
+            run_time_us: 0,
+            syscall_time_us: 0,
+            deadline_miss_count: 0,
+            sandbox_violation_count: 0,
+            last_sandbox_violation: None,
         });
 
         // Handle any architecture-specific requirements for a new process.
@@ -1917,8 +2384,18 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             debug.last_syscall = None;
             debug.dropped_upcall_count = 0;
             debug.timeslice_expiration_count = 0;
+            debug.run_time_us = 0;
+            debug.syscall_time_us = 0;
+            debug.deadline_miss_count = 0;
+            debug.sandbox_violation_count = 0;
+            debug.last_sandbox_violation = None;
         });
 
+        // A debugger attached to the previous execution is no longer attached,
+        // as it refers to the old process identifier.
+        self.debugger_attached.set(false);
+        self.debug_step.set(false);
+
         // Reset MPU region configuration.
         //
         // TODO: ideally, this would be moved into a helper function used by
@@ -1994,12 +2471,14 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         // memory.
         let app_brk = app_mpu_mem_start.wrapping_add(min_process_memory_size);
         self.app_break.set(app_brk);
+        self.initial_app_break.set(app_brk);
         // kernel_brk is calculated backwards from the end of memory the size of
         // the initial kernel data structures.
         let kernel_brk = app_mpu_mem_start
             .wrapping_add(app_mpu_mem_len)
             .wrapping_sub(initial_kernel_memory_size);
         self.kernel_memory_break.set(kernel_brk);
+        self.initial_kernel_memory_break.set(kernel_brk);
         // High water mark for `allow`ed memory is reset to the start of the
         // process's memory region.
         self.allow_high_water_mark.set(app_mpu_mem_start);
@@ -2040,6 +2519,17 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         }
     }
 
+    /// Checks if `task` is a scheduled upcall with the id `upcall_id`.
+    fn is_upcall(task: &Task, upcall_id: UpcallId) -> bool {
+        match task {
+            Task::FunctionCall(function_call) => match function_call.source {
+                FunctionCallSource::Kernel => false,
+                FunctionCallSource::Driver(id) => id == upcall_id,
+            },
+            Task::IPC(_) => false,
+        }
+    }
+
     /// Checks if the buffer represented by the passed in base pointer and size
     /// is within the RAM bounds currently exposed to the processes (i.e. ending
     /// at `app_break`). If this method returns `true`, the buffer is guaranteed
@@ -2071,6 +2561,7 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             for grant_entry in grant_pointers.iter_mut() {
                 grant_entry.driver_num = 0;
                 grant_entry.grant_ptr = ptr::null_mut();
+                grant_entry.size = 0;
             }
         });
     }
@@ -2105,6 +2596,12 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
 
             // Verify there is space for this allocation
             if new_break < self.app_break.get() {
+                None
+                // Verify the allocation fits in the grant memory quota.
+            } else if !self.kernel.memory_quota(self).allows_grant(
+                self.initial_kernel_memory_break.get() as usize,
+                new_break as usize,
+            ) {
                 None
                 // Verify it didn't wrap around
             } else if new_break > self.kernel_memory_break.get() {
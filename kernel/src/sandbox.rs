@@ -0,0 +1,760 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Sandbox profiles for running untrusted processes.
+//!
+//! The TBF permissions header restricts which commands a process may call, but
+//! it is written by the author of the process and does not cover subscribe and
+//! allow calls, or how often a process may call into a driver. A
+//! [`SandboxProfile`] is instead chosen by the board and restricts, per
+//! driver:
+//!
+//! - which command, subscribe, read-write allow and read-only allow numbers
+//!   the process may use,
+//! - how many system calls the process may make to the driver within a time
+//!   window.
+//!
+//! A profile also limits how many upcalls may be queued for the process at
+//! once, so a process that does not yield cannot make the kernel hold on to
+//! an unbounded amount of work for it. Userspace-readable allows are treated
+//! as read-write allows.
+//!
+//! Sandboxing is enabled by building the kernel with the `sandbox` feature
+//! and passing a [`ProcessSandboxPolicy`] to `Kernel::set_sandbox_policy()`,
+//! which only exists with that feature. The
+//! kernel checks every command, subscribe and allow call of a sandboxed
+//! process against its profile after the platform syscall filter, and only
+//! ever restricts a process further. Yield, memop and exit are never
+//! restricted. System calls to drivers not in the profile or with a number
+//! not in the driver's allow-list fail with `NODEVICE`, and system calls over
+//! the rate limit fail with `BUSY`. Rejected allows and subscribes use their
+//! own failure variants and hand the buffer or upcall back to the process,
+//! as they do for any other error. Upcalls beyond the queue depth are
+//! dropped as if the queue was full.
+//!
+//! Every violation is recorded with the process, and can be read with
+//! [`KernelInfo`](crate::introspection::KernelInfo).
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! static UNTRUSTED: SandboxProfile = SandboxProfile {
+//!     drivers: &[
+//!         DriverSandbox {
+//!             driver_num: capsules_core::console::DRIVER_NUM,
+//!             commands: AllowList::Only(&[0, 1, 2]),
+//!             subscribes: AllowList::Only(&[1, 2]),
+//!             allow_readwrite: AllowList::Only(&[1]),
+//!             allow_readonly: AllowList::Only(&[1]),
+//!             rate_limit: Some(RateLimit { max_syscalls: 50, period_ms: 1000 }),
+//!         },
+//!     ],
+//!     max_upcall_queue_depth: Some(4),
+//! };
+//!
+//! static DENY_ALL: SandboxProfile = SandboxProfile {
+//!     drivers: &[],
+//!     max_upcall_queue_depth: Some(0),
+//! };
+//!
+//! // The process with ShortID 0x1234 is trusted, the one with 0x5678 runs
+//! // under `UNTRUSTED`, and every other process may not use any driver.
+//! let sandbox_policy = static_init!(
+//!     SandboxPolicy<'static, VirtualMuxAlarm<'static, Rtc>, NUM_PROCS, 4>,
+//!     SandboxPolicy::new(
+//!         alarm,
+//!         &[(0x1234, None), (0x5678, Some(&UNTRUSTED))],
+//!         &DENY_ALL,
+//!     )
+//! );
+//! board_kernel.set_sandbox_policy(sandbox_policy, &process_mgmt_cap);
+//! ```
+
+use core::cell::Cell;
+
+use crate::hil::time::{self, ConvertTicks, Ticks};
+use crate::process::{FunctionCall, FunctionCallSource, Process, ProcessId, ShortID, Task};
+use crate::syscall::Syscall;
+
+/// The system call numbers of a driver a sandboxed process may use.
+#[derive(Copy, Clone, Debug)]
+pub enum AllowList {
+    /// Every number is allowed.
+    All,
+    /// Only the listed numbers are allowed.
+    Only(&'static [usize]),
+}
+
+impl AllowList {
+    /// Returns `true` if `number` is allowed.
+    pub fn contains(&self, number: usize) -> bool {
+        match self {
+            AllowList::All => true,
+            AllowList::Only(numbers) => numbers.contains(&number),
+        }
+    }
+}
+
+/// How many system calls a sandboxed process may make to a driver within
+/// `period_ms` milliseconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    pub max_syscalls: u32,
+    pub period_ms: u32,
+}
+
+/// What a sandboxed process may do with one driver.
+#[derive(Copy, Clone, Debug)]
+pub struct DriverSandbox {
+    pub driver_num: usize,
+    pub commands: AllowList,
+    pub subscribes: AllowList,
+    pub allow_readwrite: AllowList,
+    pub allow_readonly: AllowList,
+    /// How often the process may call into the driver, counting all
+    /// commands, subscribes and allows. `None` means no limit.
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// The restrictions applied to a sandboxed process.
+#[derive(Copy, Clone, Debug)]
+pub struct SandboxProfile {
+    /// The drivers the process may use. System calls to any other driver are
+    /// denied.
+    pub drivers: &'static [DriverSandbox],
+    /// How many upcalls from drivers can be queued for the process at once.
+    /// `None` means the process may use its whole task queue. Tasks the
+    /// kernel queues itself, such as the call to the init function of the
+    /// process, and IPC tasks are not limited.
+    pub max_upcall_queue_depth: Option<usize>,
+}
+
+impl SandboxProfile {
+    /// Check `syscall` against the allow-lists of this profile.
+    ///
+    /// On success returns the index of the driver in `drivers` and its rate
+    /// limit, if the system call is to a driver. Yield, memop and exit are
+    /// always allowed and return `Ok(None)`.
+    pub fn check(
+        &self,
+        syscall: &Syscall,
+    ) -> Result<Option<(usize, Option<RateLimit>)>, SandboxViolation> {
+        let (driver_num, number, kind) = match *syscall {
+            Syscall::Command {
+                driver_number,
+                subdriver_number,
+                ..
+            } => (
+                driver_number,
+                subdriver_number,
+                ViolationKind::Command(subdriver_number),
+            ),
+            Syscall::Subscribe {
+                driver_number,
+                subdriver_number,
+                ..
+            } => (
+                driver_number,
+                subdriver_number,
+                ViolationKind::Subscribe(subdriver_number),
+            ),
+            Syscall::ReadWriteAllow {
+                driver_number,
+                subdriver_number,
+                ..
+            }
+            | Syscall::UserspaceReadableAllow {
+                driver_number,
+                subdriver_number,
+                ..
+            } => (
+                driver_number,
+                subdriver_number,
+                ViolationKind::AllowReadWrite(subdriver_number),
+            ),
+            Syscall::ReadOnlyAllow {
+                driver_number,
+                subdriver_number,
+                ..
+            } => (
+                driver_number,
+                subdriver_number,
+                ViolationKind::AllowReadOnly(subdriver_number),
+            ),
+            Syscall::Yield { .. } | Syscall::Memop { .. } | Syscall::Exit { .. } => {
+                return Ok(None)
+            }
+        };
+
+        let violation = |kind| SandboxViolation { driver_num, kind };
+        let (index, driver) = self
+            .drivers
+            .iter()
+            .enumerate()
+            .find(|(_, driver)| driver.driver_num == driver_num)
+            .ok_or(violation(ViolationKind::Driver))?;
+
+        let list = match kind {
+            ViolationKind::Command(_) => driver.commands,
+            ViolationKind::Subscribe(_) => driver.subscribes,
+            ViolationKind::AllowReadWrite(_) => driver.allow_readwrite,
+            _ => driver.allow_readonly,
+        };
+        if list.contains(number) {
+            Ok(Some((index, driver.rate_limit)))
+        } else {
+            Err(violation(kind))
+        }
+    }
+
+    /// Check whether `task` can be queued for a process which already has
+    /// the tasks in `queued`, under `max_upcall_queue_depth`.
+    pub fn check_task<'t>(
+        &self,
+        task: &Task,
+        queued: impl Iterator<Item = &'t Task>,
+    ) -> Result<(), SandboxViolation> {
+        let (Some(max_depth), Some(driver_num)) = (self.max_upcall_queue_depth, driver_of(task))
+        else {
+            return Ok(());
+        };
+        if queued.filter(|task| driver_of(task).is_some()).count() >= max_depth {
+            Err(SandboxViolation {
+                driver_num,
+                kind: ViolationKind::UpcallQueueFull,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns the driver that queued `task`, if it is an upcall from a driver.
+fn driver_of(task: &Task) -> Option<usize> {
+    match task {
+        Task::FunctionCall(FunctionCall {
+            source: FunctionCallSource::Driver(upcall_id),
+            ..
+        }) => Some(upcall_id.driver_num),
+        _ => None,
+    }
+}
+
+/// How a sandboxed process violated its profile.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ViolationKind {
+    /// The driver is not in the profile.
+    Driver,
+    /// The command number is not allowed.
+    Command(usize),
+    /// The subscribe number is not allowed.
+    Subscribe(usize),
+    /// The read-write or userspace-readable allow number is not allowed.
+    AllowReadWrite(usize),
+    /// The read-only allow number is not allowed.
+    AllowReadOnly(usize),
+    /// The process called into the driver too often.
+    RateLimit,
+    /// An upcall from the driver was dropped because the process already had
+    /// as many upcalls queued as its profile allows.
+    UpcallQueueFull,
+}
+
+/// A record of a sandboxed process violating its profile.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SandboxViolation {
+    pub driver_num: usize,
+    pub kind: ViolationKind,
+}
+
+/// Decides which processes are sandboxed and tracks their rate limits.
+pub trait ProcessSandboxPolicy {
+    /// Returns the profile `process` runs under, or `None` if it is not
+    /// sandboxed.
+    fn profile(&self, process: &dyn Process) -> Option<&'static SandboxProfile>;
+
+    /// Count a system call of `process` to the driver at `driver_index` in
+    /// its profile, and return `false` if the call exceeds `limit`.
+    fn within_rate_limit(
+        &self,
+        process: &dyn Process,
+        driver_index: usize,
+        limit: RateLimit,
+    ) -> bool;
+}
+
+/// Check `syscall` of `process` against its profile under `policy`.
+pub(crate) fn check_syscall(
+    policy: &dyn ProcessSandboxPolicy,
+    process: &dyn Process,
+    syscall: &Syscall,
+) -> Result<(), SandboxViolation> {
+    let Some(profile) = policy.profile(process) else {
+        return Ok(());
+    };
+    match profile.check(syscall)? {
+        Some((index, Some(limit))) if !policy.within_rate_limit(process, index, limit) => {
+            Err(SandboxViolation {
+                driver_num: profile.drivers[index].driver_num,
+                kind: ViolationKind::RateLimit,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Rate limit state of one process slot.
+struct SandboxSlot<T: Ticks, const NUM_DRIVERS: usize> {
+    /// The process the counters belong to. The counters are reset when a
+    /// different process (or a restart of the same process) uses the slot.
+    processid: Cell<Option<ProcessId>>,
+    window_start: [Cell<T>; NUM_DRIVERS],
+    count: [Cell<u32>; NUM_DRIVERS],
+}
+
+/// Assign sandbox profiles to processes by their fixed `ShortID`, with a
+/// fixed-window rate limit per driver.
+///
+/// Each entry of the table maps a fixed `ShortID` to the profile the process
+/// runs under, or to `None` if the process is trusted and not sandboxed.
+/// `ShortID`s are assigned by the board's credential checking policy, so a
+/// process cannot choose its entry the way it can choose its name. Processes
+/// not in the table, including processes without a fixed `ShortID`, run
+/// under the default profile, so a board denies by default by choosing a
+/// restrictive default profile. Rate limits are tracked for the first
+/// `NUM_DRIVERS` drivers of each profile; system calls to later drivers with
+/// a rate limit are always denied, so profiles should list rate limited
+/// drivers first.
+pub struct SandboxPolicy<'a, A: time::Time, const NUM_PROCS: usize, const NUM_DRIVERS: usize> {
+    time: &'a A,
+    profiles: &'static [(u32, Option<&'static SandboxProfile>)],
+    default: &'static SandboxProfile,
+    slots: [SandboxSlot<A::Ticks, NUM_DRIVERS>; NUM_PROCS],
+}
+
+impl<'a, A: time::Time, const NUM_PROCS: usize, const NUM_DRIVERS: usize>
+    SandboxPolicy<'a, A, NUM_PROCS, NUM_DRIVERS>
+{
+    pub fn new(
+        time: &'a A,
+        profiles: &'static [(u32, Option<&'static SandboxProfile>)],
+        default: &'static SandboxProfile,
+    ) -> Self {
+        Self {
+            time,
+            profiles,
+            default,
+            slots: core::array::from_fn(|_| SandboxSlot {
+                processid: Cell::new(None),
+                window_start: core::array::from_fn(|_| Cell::new(A::Ticks::from(0))),
+                count: core::array::from_fn(|_| Cell::new(0)),
+            }),
+        }
+    }
+
+    /// Returns the profile of the process with `short_id`.
+    fn lookup(&self, short_id: ShortID) -> Option<&'static SandboxProfile> {
+        let entry = match short_id {
+            ShortID::Fixed(id) => self
+                .profiles
+                .iter()
+                .find(|(profile_id, _)| *profile_id == id.get()),
+            ShortID::LocallyUnique => None,
+        };
+        match entry {
+            Some((_, profile)) => *profile,
+            None => Some(self.default),
+        }
+    }
+}
+
+impl<A: time::Time, const NUM_PROCS: usize, const NUM_DRIVERS: usize> ProcessSandboxPolicy
+    for SandboxPolicy<'_, A, NUM_PROCS, NUM_DRIVERS>
+{
+    fn profile(&self, process: &dyn Process) -> Option<&'static SandboxProfile> {
+        self.lookup(process.short_app_id())
+    }
+
+    fn within_rate_limit(
+        &self,
+        process: &dyn Process,
+        driver_index: usize,
+        limit: RateLimit,
+    ) -> bool {
+        self.count_syscall(process.processid(), driver_index, limit)
+    }
+}
+
+impl<A: time::Time, const NUM_PROCS: usize, const NUM_DRIVERS: usize>
+    SandboxPolicy<'_, A, NUM_PROCS, NUM_DRIVERS>
+{
+    /// Count a system call of `processid` to the driver at `driver_index` in
+    /// its profile, and return `false` if the call exceeds `limit`.
+    fn count_syscall(&self, processid: ProcessId, driver_index: usize, limit: RateLimit) -> bool {
+        let Some(slot) = self.slots.get(processid.index) else {
+            return false;
+        };
+        if driver_index >= NUM_DRIVERS {
+            return false;
+        }
+
+        if slot.processid.get() != Some(processid) {
+            slot.processid.set(Some(processid));
+            slot.count.iter().for_each(|count| count.set(0));
+        }
+
+        let now = self.time.now();
+        let window_start = &slot.window_start[driver_index];
+        let count = &slot.count[driver_index];
+        if count.get() == 0
+            || now.wrapping_sub(window_start.get()) >= self.time.ticks_from_ms(limit.period_ms)
+        {
+            window_start.set(now);
+            count.set(0);
+        }
+
+        if count.get() >= limit.max_syscalls {
+            false
+        } else {
+            count.set(count.get() + 1);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::ipc::IPCUpcallType;
+    use crate::process::ProcessSlot;
+    use crate::syscall::SyscallReturn;
+    use crate::upcall::UpcallId;
+    use crate::{ErrorCode, Kernel};
+    use std::boxed::Box;
+
+    static PROFILE: SandboxProfile = SandboxProfile {
+        drivers: &[
+            DriverSandbox {
+                driver_num: 1,
+                commands: AllowList::Only(&[0, 1]),
+                subscribes: AllowList::All,
+                allow_readwrite: AllowList::Only(&[]),
+                allow_readonly: AllowList::Only(&[1]),
+                rate_limit: None,
+            },
+            DriverSandbox {
+                driver_num: 2,
+                commands: AllowList::All,
+                subscribes: AllowList::Only(&[]),
+                allow_readwrite: AllowList::All,
+                allow_readonly: AllowList::All,
+                rate_limit: Some(RateLimit {
+                    max_syscalls: 10,
+                    period_ms: 100,
+                }),
+            },
+        ],
+        max_upcall_queue_depth: None,
+    };
+
+    fn command(driver_number: usize, subdriver_number: usize) -> Syscall {
+        Syscall::Command {
+            driver_number,
+            subdriver_number,
+            arg0: 0,
+            arg1: 0,
+        }
+    }
+
+    #[test]
+    fn allow_lists() {
+        assert!(matches!(PROFILE.check(&command(1, 1)), Ok(Some((0, None)))));
+        assert_eq!(
+            PROFILE.check(&command(1, 2)),
+            Err(SandboxViolation {
+                driver_num: 1,
+                kind: ViolationKind::Command(2)
+            })
+        );
+        assert_eq!(
+            PROFILE.check(&command(3, 0)),
+            Err(SandboxViolation {
+                driver_num: 3,
+                kind: ViolationKind::Driver
+            })
+        );
+        let allow = Syscall::UserspaceReadableAllow {
+            driver_number: 1,
+            subdriver_number: 1,
+            allow_address: core::ptr::null_mut(),
+            allow_size: 0,
+        };
+        assert_eq!(
+            PROFILE.check(&allow),
+            Err(SandboxViolation {
+                driver_num: 1,
+                kind: ViolationKind::AllowReadWrite(1)
+            })
+        );
+        let subscribe = Syscall::Subscribe {
+            driver_number: 2,
+            subdriver_number: 0,
+            upcall_ptr: core::ptr::null_mut(),
+            appdata: 0,
+        };
+        assert_eq!(
+            PROFILE.check(&subscribe),
+            Err(SandboxViolation {
+                driver_num: 2,
+                kind: ViolationKind::Subscribe(0)
+            })
+        );
+    }
+
+    #[test]
+    fn unrestricted_syscalls() {
+        let memop = Syscall::Memop {
+            operand: 0,
+            arg0: 0,
+        };
+        assert!(matches!(PROFILE.check(&memop), Ok(None)));
+    }
+
+    /// Clock with 1 ms ticks which only advances when told to.
+    #[derive(Default)]
+    struct MockTime {
+        now: Cell<u32>,
+    }
+
+    impl MockTime {
+        fn advance(&self, ms: u32) {
+            self.now.set(self.now.get().wrapping_add(ms));
+        }
+    }
+
+    impl time::Time for MockTime {
+        type Frequency = time::Freq1KHz;
+        type Ticks = time::Ticks32;
+
+        fn now(&self) -> Self::Ticks {
+            self.now.get().into()
+        }
+    }
+
+    static DENY_ALL: SandboxProfile = SandboxProfile {
+        drivers: &[],
+        max_upcall_queue_depth: Some(0),
+    };
+
+    /// ShortID 1 is trusted and ShortID 2 runs under `PROFILE`.
+    static PROFILES: [(u32, Option<&SandboxProfile>); 2] = [(1, None), (2, Some(&PROFILE))];
+
+    fn short_id(id: u32) -> ShortID {
+        ShortID::Fixed(core::num::NonZeroU32::new(id).unwrap())
+    }
+
+    fn is(profile: Option<&'static SandboxProfile>, expected: &'static SandboxProfile) -> bool {
+        profile.map_or(false, |profile| core::ptr::eq(profile, expected))
+    }
+
+    #[test]
+    fn profiles_are_chosen_by_short_id() {
+        let time = MockTime::default();
+        let policy: SandboxPolicy<MockTime, 1, 1> = SandboxPolicy::new(&time, &PROFILES, &DENY_ALL);
+        assert!(policy.lookup(short_id(1)).is_none());
+        assert!(is(policy.lookup(short_id(2)), &PROFILE));
+    }
+
+    #[test]
+    fn unknown_processes_get_the_default_profile() {
+        let time = MockTime::default();
+        let policy: SandboxPolicy<MockTime, 1, 1> = SandboxPolicy::new(&time, &PROFILES, &DENY_ALL);
+        assert!(is(policy.lookup(short_id(3)), &DENY_ALL));
+        assert!(is(policy.lookup(ShortID::LocallyUnique), &DENY_ALL));
+    }
+
+    #[test]
+    fn rejected_allows_and_subscribes_return_their_arguments() {
+        let mut buffer = [0u8; 4];
+        let allow = Syscall::ReadWriteAllow {
+            driver_number: 3,
+            subdriver_number: 0,
+            allow_address: buffer.as_mut_ptr(),
+            allow_size: buffer.len(),
+        };
+        assert!(matches!(
+            SyscallReturn::failure_for(&allow, ErrorCode::NODEVICE),
+            SyscallReturn::AllowReadWriteFailure(ErrorCode::NODEVICE, address, 4)
+                if address == buffer.as_mut_ptr()
+        ));
+        let allow = Syscall::ReadOnlyAllow {
+            driver_number: 3,
+            subdriver_number: 0,
+            allow_address: buffer.as_ptr(),
+            allow_size: buffer.len(),
+        };
+        assert!(matches!(
+            SyscallReturn::failure_for(&allow, ErrorCode::BUSY),
+            SyscallReturn::AllowReadOnlyFailure(ErrorCode::BUSY, address, 4)
+                if address == buffer.as_ptr()
+        ));
+        let allow = Syscall::UserspaceReadableAllow {
+            driver_number: 3,
+            subdriver_number: 0,
+            allow_address: buffer.as_mut_ptr(),
+            allow_size: buffer.len(),
+        };
+        assert!(matches!(
+            SyscallReturn::failure_for(&allow, ErrorCode::NODEVICE),
+            SyscallReturn::UserspaceReadableAllowFailure(ErrorCode::NODEVICE, address, 4)
+                if address == buffer.as_mut_ptr()
+        ));
+        let subscribe = Syscall::Subscribe {
+            driver_number: 3,
+            subdriver_number: 0,
+            upcall_ptr: 0x1000 as *mut (),
+            appdata: 7,
+        };
+        assert!(matches!(
+            SyscallReturn::failure_for(&subscribe, ErrorCode::NODEVICE),
+            SyscallReturn::SubscribeFailure(ErrorCode::NODEVICE, upcall, 7)
+                if upcall == 0x1000 as *const ()
+        ));
+        assert!(matches!(
+            SyscallReturn::failure_for(&command(3, 0), ErrorCode::NODEVICE),
+            SyscallReturn::Failure(ErrorCode::NODEVICE)
+        ));
+    }
+
+    fn kernel() -> &'static Kernel {
+        let processes: &'static [ProcessSlot] = &[];
+        Box::leak(Box::new(Kernel::new(processes)))
+    }
+
+    fn upcall(driver_num: usize) -> Task {
+        task(FunctionCallSource::Driver(UpcallId {
+            driver_num,
+            subscribe_num: 0,
+        }))
+    }
+
+    fn task(source: FunctionCallSource) -> Task {
+        Task::FunctionCall(FunctionCall {
+            source,
+            argument0: 0,
+            argument1: 0,
+            argument2: 0,
+            argument3: 0,
+            pc: 0,
+        })
+    }
+
+    #[test]
+    fn processes_on_a_depth_zero_profile_still_start() {
+        let init = task(FunctionCallSource::Kernel);
+        let ipc = Task::IPC((ProcessId::new(kernel(), 1, 0), IPCUpcallType::Service));
+        assert_eq!(DENY_ALL.check_task(&init, [].iter()), Ok(()));
+        assert_eq!(DENY_ALL.check_task(&ipc, [init].iter()), Ok(()));
+        assert_eq!(
+            DENY_ALL.check_task(&upcall(3), [].iter()),
+            Err(SandboxViolation {
+                driver_num: 3,
+                kind: ViolationKind::UpcallQueueFull
+            })
+        );
+    }
+
+    #[test]
+    fn only_upcalls_from_drivers_count_against_the_queue_depth() {
+        let profile = SandboxProfile {
+            drivers: &[],
+            max_upcall_queue_depth: Some(1),
+        };
+        let init = task(FunctionCallSource::Kernel);
+        let ipc = Task::IPC((ProcessId::new(kernel(), 1, 0), IPCUpcallType::Client));
+        assert_eq!(profile.check_task(&upcall(1), [init, ipc].iter()), Ok(()));
+        assert_eq!(
+            profile.check_task(&upcall(2), [init, upcall(1)].iter()),
+            Err(SandboxViolation {
+                driver_num: 2,
+                kind: ViolationKind::UpcallQueueFull
+            })
+        );
+        assert_eq!(profile.check_task(&init, [upcall(1)].iter()), Ok(()));
+    }
+
+    const LIMIT: RateLimit = RateLimit {
+        max_syscalls: 3,
+        period_ms: 100,
+    };
+
+    #[test]
+    fn rate_limit_cuts_off_and_resets_with_the_window() {
+        let time = MockTime::default();
+        let policy: SandboxPolicy<MockTime, 2, 2> = SandboxPolicy::new(&time, &PROFILES, &DENY_ALL);
+        let processid = ProcessId::new(kernel(), 1, 0);
+        for _ in 0..3 {
+            assert!(policy.count_syscall(processid, 0, LIMIT));
+        }
+        assert!(!policy.count_syscall(processid, 0, LIMIT));
+        // The other driver has its own counter.
+        assert!(policy.count_syscall(processid, 1, LIMIT));
+
+        time.advance(99);
+        assert!(!policy.count_syscall(processid, 0, LIMIT));
+        time.advance(1);
+        for _ in 0..3 {
+            assert!(policy.count_syscall(processid, 0, LIMIT));
+        }
+        assert!(!policy.count_syscall(processid, 0, LIMIT));
+    }
+
+    #[test]
+    fn rate_limit_window_survives_clock_wrap() {
+        let time = MockTime::default();
+        time.advance(u32::MAX - 10);
+        let policy: SandboxPolicy<MockTime, 1, 1> = SandboxPolicy::new(&time, &PROFILES, &DENY_ALL);
+        let processid = ProcessId::new(kernel(), 1, 0);
+        for _ in 0..3 {
+            assert!(policy.count_syscall(processid, 0, LIMIT));
+        }
+        time.advance(50);
+        assert!(!policy.count_syscall(processid, 0, LIMIT));
+        time.advance(50);
+        assert!(policy.count_syscall(processid, 0, LIMIT));
+    }
+
+    #[test]
+    fn rate_limited_drivers_past_num_drivers_are_denied() {
+        let time = MockTime::default();
+        let policy: SandboxPolicy<MockTime, 1, 1> = SandboxPolicy::new(&time, &PROFILES, &DENY_ALL);
+        let processid = ProcessId::new(kernel(), 1, 0);
+        assert!(policy.count_syscall(processid, 0, LIMIT));
+        assert!(!policy.count_syscall(processid, 1, LIMIT));
+        // So are processes past NUM_PROCS.
+        assert!(!policy.count_syscall(ProcessId::new(kernel(), 2, 1), 0, LIMIT));
+    }
+
+    #[test]
+    fn rate_limit_resets_for_a_new_process_in_the_slot() {
+        let time = MockTime::default();
+        let policy: SandboxPolicy<MockTime, 1, 1> = SandboxPolicy::new(&time, &PROFILES, &DENY_ALL);
+        let kernel = kernel();
+        let first = ProcessId::new(kernel, 1, 0);
+        for _ in 0..3 {
+            assert!(policy.count_syscall(first, 0, LIMIT));
+        }
+        assert!(!policy.count_syscall(first, 0, LIMIT));
+
+        // A restart gives the process in slot 0 a new identifier.
+        let restarted = ProcessId::new(kernel, 2, 0);
+        for _ in 0..3 {
+            assert!(policy.count_syscall(restarted, 0, LIMIT));
+        }
+        assert!(!policy.count_syscall(restarted, 0, LIMIT));
+    }
+}
@@ -7,6 +7,7 @@
 pub mod cooperative;
 pub mod mlfq;
 pub mod priority;
+pub mod real_time;
 pub mod round_robin;
 
 use crate::deferred_call::DeferredCall;
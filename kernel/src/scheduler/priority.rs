@@ -9,6 +9,11 @@
 //! point in time. Kernel tasks (bottom half interrupt handling / deferred call
 //! handling) always take priority over userspace processes.
 //!
+//! A process's priority is its index in the `PROCESSES` array, unless a
+//! different priority was assigned at runtime with `Process::set_priority()`
+//! (for example from the process console). Lower values are higher priority,
+//! and processes with equal priority run in array order.
+//!
 //! Notably, there is no need to enforce timeslices, as it is impossible for a
 //! process running to not be the highest priority process at any point while it
 //! is running. The only way for a process to longer be the highest priority is
@@ -17,6 +22,7 @@
 use crate::deferred_call::DeferredCall;
 use crate::kernel::Kernel;
 use crate::platform::chip::Chip;
+use crate::process::Process;
 use crate::process::ProcessId;
 use crate::process::StoppedExecutingReason;
 use crate::scheduler::{Scheduler, SchedulingDecision};
@@ -35,17 +41,26 @@ impl PrioritySched {
             running: OptionalCell::empty(),
         }
     }
+
+    /// The key processes are ordered by, lowest first.
+    fn priority(process: &dyn Process) -> (usize, usize) {
+        let index = process.processid().index;
+        let priority = process
+            .get_priority()
+            .map_or(index, |priority| priority as usize);
+        (priority, index)
+    }
 }
 
 impl<C: Chip> Scheduler<C> for PrioritySched {
     fn next(&self) -> SchedulingDecision {
-        // Iterates in-order through the process array, always running the
-        // first process it finds that is ready to run. This enforces the
-        // priorities of all processes.
+        // Always run the highest priority process that is ready to run. This
+        // enforces the priorities of all processes.
         let next = self
             .kernel
             .get_process_iter()
-            .find(|&proc| proc.ready())
+            .filter(|&proc| proc.ready())
+            .min_by_key(|&proc| Self::priority(proc))
             .map_or(None, |proc| Some(proc.processid()));
         self.running.insert(next);
 
@@ -64,10 +79,13 @@ impl<C: Chip> Scheduler<C> for PrioritySched {
             || self
                 .kernel
                 .get_process_iter()
-                .find(|proc| proc.ready())
+                .filter(|proc| proc.ready())
+                .min_by_key(|&proc| Self::priority(proc))
                 .map_or(false, |ready_proc| {
                     self.running.map_or(false, |running| {
-                        ready_proc.processid().index < running.index
+                        self.kernel.process_map_or(false, running, |running_proc| {
+                            Self::priority(ready_proc) < Self::priority(running_proc)
+                        })
                     })
                 }))
     }
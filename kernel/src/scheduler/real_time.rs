@@ -0,0 +1,443 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Real-time scheduler for periodic processes.
+//!
+//! Processes declare a period and an execution budget per period with the
+//! Real-Time TBF header. At the start of every period a process gets its
+//! budget back, and its deadline is the end of the period. Among the real-time
+//! processes that are ready and have budget left, the scheduler runs either
+//! the one with the earliest deadline (EDF) or the one with the shortest
+//! period (rate-monotonic), depending on the configured policy.
+//!
+//! Budgets are enforced with the scheduler timer: a process never runs for
+//! longer than its remaining budget, or past the start of the next period of
+//! any other real-time process so that the scheduler can re-evaluate its
+//! decision. Processes without the Real-Time header run round-robin whenever
+//! no real-time process can run.
+//!
+//! When a process with the Real-Time header is first scheduled, the scheduler
+//! runs admission control: the process is only admitted if the total
+//! utilization (budget divided by period) of all admitted processes stays
+//! within the bound of the policy, 100% for EDF and the Liu and Layland bound
+//! for rate-monotonic scheduling. Processes that are not admitted are
+//! terminated.
+//!
+//! A process misses a deadline if it still has work to do when its period
+//! ends. Deadline misses are counted per process and reported through
+//! `KernelInfo`.
+
+use core::cell::Cell;
+
+use crate::collections::list::{List, ListLink, ListNode};
+use crate::config;
+use crate::debug;
+use crate::hil::time::{self, ConvertTicks, Ticks};
+use crate::kernel::MIN_QUANTA_THRESHOLD_US;
+use crate::platform::chip::Chip;
+use crate::process::StoppedExecutingReason;
+use crate::process::{ProcessId, ProcessSlot, State};
+use crate::scheduler::{Scheduler, SchedulingDecision};
+use crate::utilities::cells::OptionalCell;
+
+/// How the scheduler picks between real-time processes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RealTimePolicy {
+    /// Run the process whose current period ends first.
+    EarliestDeadlineFirst,
+    /// Run the process with the shortest period.
+    RateMonotonic,
+}
+
+/// Utilization bounds for rate-monotonic scheduling of `n` processes, in
+/// parts per million: `n * (2^(1/n) - 1)`.
+const RATE_MONOTONIC_BOUNDS: [u32; 10] = [
+    1_000_000, 828_427, 779_763, 756_828, 743_492, 734_772, 728_627, 724_062, 720_538, 717_735,
+];
+
+/// Limit of the rate-monotonic utilization bound for many processes, ln(2),
+/// in parts per million.
+const RATE_MONOTONIC_BOUND_LIMIT: u32 = 693_147;
+
+/// Utilization of `budget_us` per `period_us` in parts per million.
+fn utilization(period_us: u32, budget_us: u32) -> u64 {
+    budget_us as u64 * 1_000_000 / period_us as u64
+}
+
+/// Admission control: decide whether a process with `period_us` and
+/// `budget_us` can be scheduled under `policy` in addition to the processes
+/// with the `(period_us, budget_us)` parameters in `admitted`.
+fn admissible(
+    policy: RealTimePolicy,
+    admitted: impl Iterator<Item = (u32, u32)>,
+    period_us: u32,
+    budget_us: u32,
+) -> bool {
+    if budget_us == 0 || budget_us > period_us {
+        return false;
+    }
+    let mut count = 1;
+    let mut total = utilization(period_us, budget_us);
+    for (period_us, budget_us) in admitted {
+        count += 1;
+        total += utilization(period_us, budget_us);
+    }
+    let bound = match policy {
+        RealTimePolicy::EarliestDeadlineFirst => 1_000_000,
+        RealTimePolicy::RateMonotonic => RATE_MONOTONIC_BOUNDS
+            .get(count - 1)
+            .copied()
+            .unwrap_or(RATE_MONOTONIC_BOUND_LIMIT),
+    };
+    total <= bound as u64
+}
+
+/// Rank of a process with `period_us` whose current period ends in
+/// `until_deadline_us` under `policy`. Lower ranks run first.
+fn rank(policy: RealTimePolicy, period_us: u32, until_deadline_us: u32) -> u32 {
+    match policy {
+        RealTimePolicy::EarliestDeadlineFirst => until_deadline_us,
+        RealTimePolicy::RateMonotonic => period_us,
+    }
+}
+
+/// Real-time state of a process instance.
+#[derive(Clone, Copy)]
+struct Job<T: Ticks> {
+    /// Length of a period in microseconds.
+    period_us: u32,
+    /// Execution budget per period in microseconds.
+    budget_us: u32,
+    /// Start of the current period.
+    release: T,
+    /// Execution budget left in the current period.
+    budget_left_us: u32,
+}
+
+/// Nodes store per-process state
+pub struct RealTimeProcessNode<'a, T: Ticks> {
+    proc: &'static ProcessSlot,
+    /// The process instance the state below belongs to. The state is reset
+    /// when a new process is loaded into the slot or the process restarts.
+    processid: OptionalCell<ProcessId>,
+    /// `Some` if the process is an admitted real-time process.
+    job: Cell<Option<Job<T>>>,
+    next: ListLink<'a, RealTimeProcessNode<'a, T>>,
+}
+
+impl<'a, T: Ticks> RealTimeProcessNode<'a, T> {
+    pub fn new(proc: &'static ProcessSlot) -> RealTimeProcessNode<'a, T> {
+        RealTimeProcessNode {
+            proc,
+            processid: OptionalCell::empty(),
+            job: Cell::new(None),
+            next: ListLink::empty(),
+        }
+    }
+
+    fn ready(&self) -> bool {
+        self.proc.get().map_or(false, |proc| proc.ready())
+    }
+}
+
+impl<'a, T: Ticks> ListNode<'a, RealTimeProcessNode<'a, T>> for RealTimeProcessNode<'a, T> {
+    fn next(&'a self) -> &'a ListLink<'a, RealTimeProcessNode<'a, T>> {
+        &self.next
+    }
+}
+
+/// Earliest-deadline-first or rate-monotonic scheduler.
+pub struct RealTimeSched<'a, A: 'static + time::Alarm<'static>> {
+    alarm: &'static A,
+    policy: RealTimePolicy,
+    pub processes: List<'a, RealTimeProcessNode<'a, A::Ticks>>,
+    /// The node of the process that is currently executing.
+    running: OptionalCell<&'a RealTimeProcessNode<'a, A::Ticks>>,
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> RealTimeSched<'a, A> {
+    /// Timeslice for processes without real-time parameters.
+    pub const BEST_EFFORT_TIMESLICE_US: u32 = 10000;
+
+    pub fn new(alarm: &'static A, policy: RealTimePolicy) -> Self {
+        Self {
+            alarm,
+            policy,
+            processes: List::new(),
+            running: OptionalCell::empty(),
+        }
+    }
+
+    /// Decide whether a process with `period_us` and `budget_us` can be
+    /// scheduled in addition to the admitted processes.
+    fn admit(&self, period_us: u32, budget_us: u32) -> bool {
+        self.alarm.ticks_from_us(period_us).into_u32() != 0
+            && admissible(
+                self.policy,
+                self.processes
+                    .iter()
+                    .filter_map(|node| node.job.get())
+                    .map(|job| (job.period_us, job.budget_us)),
+                period_us,
+                budget_us,
+            )
+    }
+
+    /// Bring the state of `node` up to date at time `now`: reset it for a new
+    /// process instance, run admission control, and start the periods that
+    /// began since the scheduler last looked at the process.
+    fn update(&self, node: &RealTimeProcessNode<'a, A::Ticks>, now: A::Ticks) {
+        let proc = match node.proc.get() {
+            Some(proc) => proc,
+            None => {
+                node.processid.clear();
+                node.job.set(None);
+                return;
+            }
+        };
+
+        if !node.processid.contains(&proc.processid()) {
+            // Wait with admission control until the process has been
+            // approved to run.
+            if matches!(
+                proc.get_state(),
+                State::CredentialsUnchecked | State::CredentialsFailed
+            ) {
+                node.job.set(None);
+                return;
+            }
+            node.processid.set(proc.processid());
+            node.job.set(None);
+            if let Some((period_us, budget_us)) = proc.get_real_time_parameters() {
+                if self.admit(period_us, budget_us) {
+                    node.job.set(Some(Job {
+                        period_us,
+                        budget_us,
+                        release: now,
+                        budget_left_us: budget_us,
+                    }));
+                } else {
+                    if config::CONFIG.debug_load_processes {
+                        debug!(
+                            "Process {} not admitted: period {}us, budget {}us",
+                            proc.get_process_name(),
+                            period_us,
+                            budget_us
+                        );
+                    }
+                    proc.terminate(None);
+                }
+            }
+            return;
+        }
+
+        if let Some(mut job) = node.job.get() {
+            let period = self.alarm.ticks_from_us(job.period_us).into_u32();
+            let elapsed = now.wrapping_sub(job.release).into_u32();
+            if elapsed >= period {
+                // The deadline of the current period has passed.
+                if proc.ready() {
+                    proc.debug_deadline_missed();
+                }
+                let periods = elapsed / period;
+                job.release = job
+                    .release
+                    .wrapping_add(A::Ticks::from(periods.wrapping_mul(period)));
+                job.budget_left_us = job.budget_us;
+                node.job.set(Some(job));
+            }
+        }
+    }
+
+    /// Microseconds from `now` until the end of the current period of `job`.
+    fn until_deadline_us(&self, job: &Job<A::Ticks>, now: A::Ticks) -> u32 {
+        let deadline = job
+            .release
+            .wrapping_add(self.alarm.ticks_from_us(job.period_us));
+        self.alarm.ticks_to_us(deadline.wrapping_sub(now))
+    }
+
+    /// Microseconds from `now` until the next period of any real-time process
+    /// other than `except` starts.
+    fn until_next_release_us(
+        &self,
+        except: Option<&RealTimeProcessNode<'a, A::Ticks>>,
+        now: A::Ticks,
+    ) -> Option<u32> {
+        self.processes
+            .iter()
+            .filter(|node| except.map_or(true, |except| !core::ptr::eq(*node, except)))
+            .filter_map(|node| node.job.get())
+            .map(|job| self.until_deadline_us(&job, now))
+            .min()
+    }
+
+    /// Returns the ready real-time process with budget left that the policy
+    /// ranks highest.
+    fn next_real_time(&self, now: A::Ticks) -> Option<&'a RealTimeProcessNode<'a, A::Ticks>> {
+        self.processes
+            .iter()
+            .filter_map(|node| node.job.get().map(|job| (node, job)))
+            .filter(|(node, job)| job.budget_left_us > 0 && node.ready())
+            .min_by_key(|(_, job)| {
+                rank(self.policy, job.period_us, self.until_deadline_us(job, now))
+            })
+            .map(|(node, _)| node)
+    }
+
+    /// Returns the next ready process without real-time parameters and moves
+    /// it to the end of the list, so that these processes run round-robin.
+    fn next_best_effort(&self) -> Option<&'a RealTimeProcessNode<'a, A::Ticks>> {
+        let next = self
+            .processes
+            .iter()
+            .find(|node| node.job.get().is_none() && node.ready())?;
+        // Rotate the list until `next` is at its tail.
+        while let Some(node) = self.processes.pop_head() {
+            self.processes.push_tail(node);
+            if core::ptr::eq(node, next) {
+                break;
+            }
+        }
+        Some(next)
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for RealTimeSched<'a, A> {
+    fn next(&self) -> SchedulingDecision {
+        let now = self.alarm.now();
+        for node in self.processes.iter() {
+            self.update(node, now);
+        }
+
+        let (node, timeslice) = match self.next_real_time(now) {
+            Some(node) => {
+                let budget_left_us = node.job.get().map_or(0, |job| job.budget_left_us);
+                (node, budget_left_us)
+            }
+            None => match self.next_best_effort() {
+                Some(node) => (node, Self::BEST_EFFORT_TIMESLICE_US),
+                None => {
+                    // Processes that are ready but out of budget can run again
+                    // once their next period starts, make sure the chip wakes
+                    // up for it.
+                    let throttled = self
+                        .processes
+                        .iter()
+                        .filter(|node| node.job.get().is_some() && node.ready())
+                        .filter_map(|node| node.job.get())
+                        .map(|job| self.until_deadline_us(&job, now))
+                        .min();
+                    if let Some(us) = throttled {
+                        self.alarm.set_alarm(now, self.alarm.ticks_from_us(us));
+                    }
+                    return SchedulingDecision::TrySleep;
+                }
+            },
+        };
+
+        // Stop the process when another real-time process starts a new period
+        // so that the scheduler can decide again.
+        let timeslice = self
+            .until_next_release_us(Some(node), now)
+            .map_or(timeslice, |us| core::cmp::min(timeslice, us));
+
+        // The kernel does not start a process with a timeslice at or below
+        // `MIN_QUANTA_THRESHOLD_US`, so a short remainder of a budget or of
+        // the time until the next release would make the scheduler pick the
+        // same process again without ever running it. Round it up instead;
+        // the overrun is charged to the budget of the process.
+        let timeslice = core::cmp::max(timeslice, MIN_QUANTA_THRESHOLD_US + 1);
+
+        self.running.set(node);
+        match node.proc.get() {
+            Some(proc) => SchedulingDecision::RunProcess((proc.processid(), Some(timeslice))),
+            None => SchedulingDecision::TrySleep,
+        }
+    }
+
+    fn result(&self, _result: StoppedExecutingReason, execution_time_us: Option<u32>) {
+        if let Some(node) = self.running.take() {
+            if let Some(mut job) = node.job.get() {
+                job.budget_left_us = job
+                    .budget_left_us
+                    .saturating_sub(execution_time_us.unwrap_or(0));
+                node.job.set(Some(job));
+            }
+        }
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> time::AlarmClient for RealTimeSched<'a, A> {
+    fn alarm(&self) {
+        // The alarm only wakes the chip up so that the scheduler runs
+        // processes whose new period started.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDF: RealTimePolicy = RealTimePolicy::EarliestDeadlineFirst;
+    const RM: RealTimePolicy = RealTimePolicy::RateMonotonic;
+
+    /// Index of the `(period_us, until_deadline_us)` entry that `policy`
+    /// selects.
+    fn select(policy: RealTimePolicy, jobs: &[(u32, u32)]) -> Option<usize> {
+        jobs.iter()
+            .enumerate()
+            .min_by_key(|(_, (period_us, until_us))| rank(policy, *period_us, *until_us))
+            .map(|(i, _)| i)
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        assert!(!admissible(EDF, [].into_iter(), 1000, 0));
+        assert!(!admissible(EDF, [].into_iter(), 1000, 1001));
+        assert!(admissible(EDF, [].into_iter(), 1000, 1000));
+    }
+
+    #[test]
+    fn edf_admits_up_to_full_utilization() {
+        let admitted = [(1000, 500), (2000, 500)];
+        // 50% + 25% + 25%
+        assert!(admissible(EDF, admitted.into_iter(), 4000, 1000));
+        // 50% + 25% + 25.025%
+        assert!(!admissible(EDF, admitted.into_iter(), 4000, 1001));
+    }
+
+    #[test]
+    fn rate_monotonic_uses_liu_layland_bound() {
+        // Two processes may use up to 82.8%.
+        assert!(admissible(RM, [(1000, 500)].into_iter(), 1000, 328));
+        assert!(!admissible(RM, [(1000, 500)].into_iter(), 1000, 329));
+        // A set EDF accepts can exceed the bound for rate-monotonic.
+        assert!(admissible(EDF, [(1000, 500)].into_iter(), 1000, 400));
+        assert!(!admissible(RM, [(1000, 500)].into_iter(), 1000, 400));
+    }
+
+    #[test]
+    fn rate_monotonic_bound_approaches_ln2() {
+        let admitted = [(100_000, 6_000); 11];
+        // Twelve processes with 6% each: 72% is above the limit of 69.3%.
+        assert!(!admissible(RM, admitted.into_iter(), 100_000, 6_000));
+        let admitted = [(100_000, 5_700); 11];
+        // Twelve processes with 5.7% each: 68.4%.
+        assert!(admissible(RM, admitted.into_iter(), 100_000, 5_700));
+    }
+
+    #[test]
+    fn edf_selects_earliest_deadline() {
+        // The process with the longer period has the closer deadline.
+        let jobs = [(1000, 800), (5000, 300), (2000, 1500)];
+        assert_eq!(select(EDF, &jobs), Some(1));
+    }
+
+    #[test]
+    fn rate_monotonic_selects_shortest_period() {
+        let jobs = [(2000, 800), (5000, 300), (1000, 900)];
+        assert_eq!(select(RM, &jobs), Some(2));
+    }
+}
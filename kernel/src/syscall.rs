@@ -51,6 +51,7 @@ pub enum SyscallClass {
 pub enum YieldCall {
     NoWait = 0,
     Wait = 1,
+    WaitFor = 2,
 }
 
 // Required as long as no solution to
@@ -78,8 +79,15 @@ impl TryFrom<u8> for SyscallClass {
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Syscall {
     /// Structure representing an invocation of the Yield system call class.
-    /// `which` is the Yield identifier value and `address` is the no wait field.
-    Yield { which: usize, address: *mut u8 },
+    /// `which` is the Yield identifier value. For yield-no-wait, `param_a` is
+    /// the address of the no wait field. For yield-wait-for, `param_a` is the
+    /// driver number and `param_b` the subscribe number of the upcall to wait
+    /// for.
+    Yield {
+        which: usize,
+        param_a: usize,
+        param_b: usize,
+    },
 
     /// Structure representing an invocation of the Subscribe system call
     /// class. `driver_number` is the driver identifier, `subdriver_number`
@@ -168,7 +176,8 @@ impl Syscall {
         match SyscallClass::try_from(syscall_number) {
             Ok(SyscallClass::Yield) => Some(Syscall::Yield {
                 which: r0,
-                address: r1 as *mut u8,
+                param_a: r1,
+                param_b: r2,
             }),
             Ok(SyscallClass::Subscribe) => Some(Syscall::Subscribe {
                 driver_number: r0,
@@ -312,6 +321,10 @@ pub enum SyscallReturn {
     /// Subscribe failure case, returns the passed upcall function
     /// pointer and application data.
     SubscribeFailure(ErrorCode, *const (), usize),
+
+    /// Yield-wait-for return value, returns the three arguments of the
+    /// upcall the process waited for.
+    YieldWaitFor(usize, usize, usize),
 }
 
 impl SyscallReturn {
@@ -325,6 +338,35 @@ impl SyscallReturn {
         res.into_inner()
     }
 
+    /// Returns the failure variant that rejecting `syscall` with `error`
+    /// must return, so that allows and subscribes give their arguments back
+    /// to the process as the ABI requires.
+    pub(crate) fn failure_for(syscall: &Syscall, error: ErrorCode) -> Self {
+        match *syscall {
+            Syscall::Subscribe {
+                upcall_ptr,
+                appdata,
+                ..
+            } => SyscallReturn::SubscribeFailure(error, upcall_ptr as *const (), appdata),
+            Syscall::ReadWriteAllow {
+                allow_address,
+                allow_size,
+                ..
+            } => SyscallReturn::AllowReadWriteFailure(error, allow_address, allow_size),
+            Syscall::UserspaceReadableAllow {
+                allow_address,
+                allow_size,
+                ..
+            } => SyscallReturn::UserspaceReadableAllowFailure(error, allow_address, allow_size),
+            Syscall::ReadOnlyAllow {
+                allow_address,
+                allow_size,
+                ..
+            } => SyscallReturn::AllowReadOnlyFailure(error, allow_address, allow_size),
+            _ => SyscallReturn::Failure(error),
+        }
+    }
+
     /// Returns true if the `SyscallReturn` is any success type.
     pub(crate) fn is_success(&self) -> bool {
         match self {
@@ -338,6 +380,7 @@ impl SyscallReturn {
             SyscallReturn::UserspaceReadableAllowSuccess(_, _) => true,
             SyscallReturn::AllowReadOnlySuccess(_, _) => true,
             SyscallReturn::SubscribeSuccess(_, _) => true,
+            SyscallReturn::YieldWaitFor(_, _, _) => true,
             SyscallReturn::Failure(_) => false,
             SyscallReturn::FailureU32(_, _) => false,
             SyscallReturn::FailureU32U32(_, _, _) => false,
@@ -453,12 +496,29 @@ impl SyscallReturn {
                 *a2 = ptr as u32;
                 *a3 = data as u32;
             }
+            SyscallReturn::YieldWaitFor(data0, data1, data2) => {
+                *a0 = data0 as u32;
+                *a1 = data1 as u32;
+                *a2 = data2 as u32;
+            }
         }
     }
 }
 
 // ---------- USERSPACE KERNEL BOUNDARY ----------
 
+/// Registers identifying where a process was executing when it last returned
+/// to the kernel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProcessRegisters {
+    /// Program counter.
+    pub pc: usize,
+    /// Link register (return address).
+    pub lr: usize,
+    /// Stack pointer.
+    pub sp: usize,
+}
+
 /// `ContentSwitchReason` specifies why the process stopped executing and
 /// execution returned to the kernel.
 #[derive(PartialEq, Copy, Clone)]
@@ -653,7 +713,68 @@ pub trait UserspaceKernelBoundary {
         writer: &mut dyn Write,
     );
 
+    /// Return the program counter, link register and stack pointer of a
+    /// process identified by the stored state for that process, or `None` if
+    /// they cannot be determined.
+    ///
+    /// ### Safety
+    ///
+    /// This function guarantees that it will only read process memory starting
+    /// at `accessible_memory_start` and before `app_brk`. The caller is
+    /// responsible for guaranteeing that those pointers are valid for the
+    /// process.
+    unsafe fn get_registers(
+        &self,
+        accessible_memory_start: *const u8,
+        app_brk: *const u8,
+        state: &Self::StoredState,
+    ) -> Option<ProcessRegisters>;
+
+    /// Read register `index` of the process, numbered as in the register
+    /// layout GDB uses for this architecture. Returns `None` if the
+    /// architecture has no such register or it cannot be read.
+    ///
+    /// ### Safety
+    ///
+    /// This function guarantees that it will only read process memory starting
+    /// at `accessible_memory_start` and before `app_brk`. The caller is
+    /// responsible for guaranteeing that those pointers are valid for the
+    /// process.
+    unsafe fn get_debug_register(
+        &self,
+        accessible_memory_start: *const u8,
+        app_brk: *const u8,
+        state: &Self::StoredState,
+        index: usize,
+    ) -> Option<usize>;
+
+    /// Set register `index` of the process, numbered as for
+    /// `get_debug_register()`, to `value`. The process sees the new value
+    /// when it next runs. Returns `ErrorCode::INVAL` if the architecture has
+    /// no such register or it cannot be written.
+    ///
+    /// ### Safety
+    ///
+    /// This function guarantees that it will only write process memory
+    /// starting at `accessible_memory_start` and before `app_brk`. The caller
+    /// is responsible for guaranteeing that those pointers are valid for the
+    /// process.
+    unsafe fn set_debug_register(
+        &self,
+        accessible_memory_start: *const u8,
+        app_brk: *const u8,
+        state: &mut Self::StoredState,
+        index: usize,
+        value: usize,
+    ) -> Result<(), ErrorCode>;
+
     /// Store architecture specific (e.g. CPU registers or status flags) data
     /// for a process. On success returns the number of elements written to out.
     fn store_context(&self, state: &Self::StoredState, out: &mut [u8]) -> Result<usize, ErrorCode>;
+
+    /// Replace the architecture specific data of a process with data
+    /// previously written by `store_context()`. Returns `ErrorCode::FAIL` if
+    /// `input` was not written by `store_context()` of this architecture, in
+    /// which case `state` is left unchanged.
+    fn load_context(&self, state: &mut Self::StoredState, input: &[u8]) -> Result<(), ErrorCode>;
 }
@@ -106,6 +106,30 @@ impl CommandReturn {
         self.0
     }
 
+    /// Returns true if this `CommandReturn` is of type success.
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self.0,
+            SyscallReturn::Success
+                | SyscallReturn::SuccessU32(_)
+                | SyscallReturn::SuccessU32U32(_, _)
+                | SyscallReturn::SuccessU32U32U32(_, _, _)
+                | SyscallReturn::SuccessU64(_)
+                | SyscallReturn::SuccessU32U64(_, _)
+        )
+    }
+
+    /// Returns the `ErrorCode` if this `CommandReturn` is of type failure.
+    pub fn get_failure(&self) -> Option<ErrorCode> {
+        match self.0 {
+            SyscallReturn::Failure(rc)
+            | SyscallReturn::FailureU32(rc, _)
+            | SyscallReturn::FailureU32U32(rc, _, _)
+            | SyscallReturn::FailureU64(rc, _) => Some(rc),
+            _ => None,
+        }
+    }
+
     /// Command error
     pub fn failure(rc: ErrorCode) -> Self {
         CommandReturn(SyscallReturn::Failure(rc))
@@ -0,0 +1,370 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Binary tracing of system calls, context switches and upcalls.
+//!
+//! The `trace_syscalls` kernel feature prints every system call with
+//! `debug!()`, which is slow and changes the timing of the traced system.
+//! This module instead records each event as a compact fixed-size binary
+//! record in a kernel ring buffer, which a capsule can drain at its own pace
+//! (for example over the console UART or SEGGER RTT). The
+//! `tools/decode_syscall_trace.py` script turns the drained stream back into
+//! a readable timeline.
+//!
+//! Tracing is enabled by building the kernel with the `syscall_trace` feature
+//! and passing a [`SyscallTracer`] to
+//! [`Kernel::set_syscall_tracer`](crate::Kernel::set_syscall_tracer). The
+//! kernel then reports:
+//!
+//! - every system call a process makes, before it is filtered or handled,
+//! - every system call return value set for a process,
+//! - every switch into a process and the reason it returned to the kernel,
+//! - every upcall successfully scheduled for a process.
+//!
+//! Record format
+//! -------------
+//!
+//! Each record is [`RECORD_LEN`] bytes, with all multi-byte fields little
+//! endian:
+//!
+//! ```text
+//! 0       1         2          4            8      12     16     20     24
+//! +-------+---------+----------+------------+------+------+------+------+
+//! | kind  | subtype | sequence | process ID | arg0 | arg1 | arg2 | arg3 |
+//! +-------+---------+----------+------------+------+------+------+------+
+//! ```
+//!
+//! The sequence number increments for every record the buffer accepts or
+//! drops because it is full, so gaps in the sequence show where records
+//! were lost. The meaning of the subtype and the arguments depends on the
+//! kind:
+//!
+//! | Kind                   | Subtype                   | Arguments                             |
+//! |------------------------|---------------------------|---------------------------------------|
+//! | 1 `Syscall`            | system call class         | the four system call registers        |
+//! | 2 `SyscallReturn`      | 0                         | the four encoded return registers     |
+//! | 3 `ContextSwitchIn`    | 0                         | unused                                |
+//! | 4 `ContextSwitchOut`   | [`SwitchOutReason`]       | microseconds executed                 |
+//! | 5 `Upcall`             | subscribe number          | driver number, the three upcall args  |
+
+use core::cell::Cell;
+
+use crate::collections::queue::Queue;
+use crate::collections::ring_buffer::RingBuffer;
+use crate::process::ProcessId;
+use crate::syscall::{ContextSwitchReason, Syscall, SyscallClass, SyscallReturn};
+use crate::upcall::UpcallId;
+use crate::utilities::cells::{MapCell, OptionalCell};
+
+/// Length in bytes of an encoded trace record.
+pub const RECORD_LEN: usize = 24;
+
+/// A single encoded trace record.
+pub type TraceRecord = [u8; RECORD_LEN];
+
+/// The kinds of trace records.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RecordKind {
+    Syscall = 1,
+    SyscallReturn = 2,
+    ContextSwitchIn = 3,
+    ContextSwitchOut = 4,
+    Upcall = 5,
+}
+
+/// Why a process stopped executing, as encoded in the subtype of a
+/// `ContextSwitchOut` record.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SwitchOutReason {
+    SyscallFired = 0,
+    Fault = 1,
+    Interrupted = 2,
+    /// Switching to the process failed.
+    Error = 3,
+}
+
+/// An event reported by the kernel to a [`SyscallTracer`].
+#[derive(Copy, Clone)]
+pub enum TraceEvent<'a> {
+    /// The process made a system call.
+    Syscall(&'a Syscall),
+    /// A system call return value was set for the process.
+    SyscallReturn(&'a SyscallReturn),
+    /// The kernel is about to switch to the process.
+    ContextSwitchIn,
+    /// The process returned to the kernel after executing for
+    /// `executed_us` microseconds.
+    ContextSwitchOut {
+        reason: Option<&'a ContextSwitchReason>,
+        executed_us: u32,
+    },
+    /// An upcall was scheduled for the process.
+    Upcall {
+        upcall_id: UpcallId,
+        args: (usize, usize, usize),
+    },
+}
+
+impl TraceEvent<'_> {
+    /// The driver the event relates to, if any.
+    fn driver_num(&self) -> Option<usize> {
+        match *self {
+            TraceEvent::Syscall(syscall) => match *syscall {
+                Syscall::Subscribe { driver_number, .. }
+                | Syscall::Command { driver_number, .. }
+                | Syscall::ReadWriteAllow { driver_number, .. }
+                | Syscall::UserspaceReadableAllow { driver_number, .. }
+                | Syscall::ReadOnlyAllow { driver_number, .. } => Some(driver_number),
+                Syscall::Yield { .. } | Syscall::Memop { .. } | Syscall::Exit { .. } => None,
+            },
+            TraceEvent::Upcall { upcall_id, .. } => Some(upcall_id.driver_num),
+            _ => None,
+        }
+    }
+
+    /// Encode the kind, subtype and arguments of the event.
+    fn encode(&self) -> (RecordKind, u8, [u32; 4]) {
+        match *self {
+            TraceEvent::Syscall(syscall) => {
+                let (class, args) = match *syscall {
+                    Syscall::Yield {
+                        which,
+                        param_a,
+                        param_b,
+                    } => (SyscallClass::Yield, [which, param_a, param_b, 0]),
+                    Syscall::Subscribe {
+                        driver_number,
+                        subdriver_number,
+                        upcall_ptr,
+                        appdata,
+                    } => (
+                        SyscallClass::Subscribe,
+                        [
+                            driver_number,
+                            subdriver_number,
+                            upcall_ptr as usize,
+                            appdata,
+                        ],
+                    ),
+                    Syscall::Command {
+                        driver_number,
+                        subdriver_number,
+                        arg0,
+                        arg1,
+                    } => (
+                        SyscallClass::Command,
+                        [driver_number, subdriver_number, arg0, arg1],
+                    ),
+                    Syscall::ReadWriteAllow {
+                        driver_number,
+                        subdriver_number,
+                        allow_address,
+                        allow_size,
+                    } => (
+                        SyscallClass::ReadWriteAllow,
+                        [
+                            driver_number,
+                            subdriver_number,
+                            allow_address as usize,
+                            allow_size,
+                        ],
+                    ),
+                    Syscall::UserspaceReadableAllow {
+                        driver_number,
+                        subdriver_number,
+                        allow_address,
+                        allow_size,
+                    } => (
+                        SyscallClass::UserspaceReadableAllow,
+                        [
+                            driver_number,
+                            subdriver_number,
+                            allow_address as usize,
+                            allow_size,
+                        ],
+                    ),
+                    Syscall::ReadOnlyAllow {
+                        driver_number,
+                        subdriver_number,
+                        allow_address,
+                        allow_size,
+                    } => (
+                        SyscallClass::ReadOnlyAllow,
+                        [
+                            driver_number,
+                            subdriver_number,
+                            allow_address as usize,
+                            allow_size,
+                        ],
+                    ),
+                    Syscall::Memop { operand, arg0 } => {
+                        (SyscallClass::Memop, [operand, arg0, 0, 0])
+                    }
+                    Syscall::Exit {
+                        which,
+                        completion_code,
+                    } => (SyscallClass::Exit, [which, completion_code, 0, 0]),
+                };
+                (RecordKind::Syscall, class as u8, args.map(|arg| arg as u32))
+            }
+            TraceEvent::SyscallReturn(return_value) => {
+                let mut args = [0; 4];
+                let [a0, a1, a2, a3] = &mut args;
+                return_value.encode_syscall_return(a0, a1, a2, a3);
+                (RecordKind::SyscallReturn, 0, args)
+            }
+            TraceEvent::ContextSwitchIn => (RecordKind::ContextSwitchIn, 0, [0; 4]),
+            TraceEvent::ContextSwitchOut {
+                reason,
+                executed_us,
+            } => {
+                let reason = match reason {
+                    Some(ContextSwitchReason::SyscallFired { .. }) => SwitchOutReason::SyscallFired,
+                    Some(ContextSwitchReason::Fault) => SwitchOutReason::Fault,
+                    Some(ContextSwitchReason::Interrupted) => SwitchOutReason::Interrupted,
+                    None => SwitchOutReason::Error,
+                };
+                (
+                    RecordKind::ContextSwitchOut,
+                    reason as u8,
+                    [executed_us, 0, 0, 0],
+                )
+            }
+            TraceEvent::Upcall { upcall_id, args } => (
+                RecordKind::Upcall,
+                upcall_id.subscribe_num as u8,
+                [
+                    upcall_id.driver_num as u32,
+                    args.0 as u32,
+                    args.1 as u32,
+                    args.2 as u32,
+                ],
+            ),
+        }
+    }
+}
+
+/// Receives trace events from the kernel.
+pub trait SyscallTracer {
+    /// Called by the kernel for every traced event of `processid`.
+    fn trace(&self, processid: ProcessId, event: TraceEvent);
+}
+
+/// A [`SyscallTracer`] that stores encoded records in a ring buffer until
+/// they are drained with [`SyscallTraceBuffer::dequeue`].
+///
+/// When the buffer is full new records are dropped, so the oldest records
+/// are kept. Records can be restricted to a single process and to system
+/// calls and upcalls of a single driver.
+pub struct SyscallTraceBuffer {
+    records: MapCell<RingBuffer<'static, TraceRecord>>,
+    /// Sequence number of the next record.
+    sequence: Cell<u16>,
+    /// Number of records dropped because the buffer was full.
+    dropped: Cell<usize>,
+    /// Only trace this process, if set.
+    process_filter: OptionalCell<ProcessId>,
+    /// Only trace system calls and upcalls for this driver, if set.
+    driver_filter: OptionalCell<usize>,
+    /// Whether the most recent system call was filtered out, in which case
+    /// its return value is filtered out as well.
+    syscall_filtered: Cell<bool>,
+}
+
+impl SyscallTraceBuffer {
+    pub fn new(buffer: &'static mut [TraceRecord]) -> SyscallTraceBuffer {
+        SyscallTraceBuffer {
+            records: MapCell::new(RingBuffer::new(buffer)),
+            sequence: Cell::new(0),
+            dropped: Cell::new(0),
+            process_filter: OptionalCell::empty(),
+            driver_filter: OptionalCell::empty(),
+            syscall_filtered: Cell::new(false),
+        }
+    }
+
+    /// Only trace events of `processid`, or of all processes if `None`.
+    pub fn set_process_filter(&self, processid: Option<ProcessId>) {
+        self.process_filter.insert(processid);
+    }
+
+    /// Only trace system calls, their return values, and upcalls for
+    /// `driver_num`, or for all drivers if `None`. Context switches are
+    /// always traced. Yield, Memop and Exit calls are not traced while a
+    /// driver filter is set.
+    pub fn set_driver_filter(&self, driver_num: Option<usize>) {
+        self.driver_filter.insert(driver_num);
+    }
+
+    /// Number of records dropped because the buffer was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped.get()
+    }
+
+    /// Number of records waiting to be drained.
+    pub fn len(&self) -> usize {
+        self.records.map_or(0, |records| records.len())
+    }
+
+    /// Whether there are no records waiting to be drained.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove the oldest record from the buffer.
+    pub fn dequeue(&self) -> Option<TraceRecord> {
+        self.records.and_then(|records| records.dequeue())
+    }
+
+    /// Whether `event` of `processid` passes the configured filters.
+    fn accept(&self, processid: ProcessId, event: &TraceEvent) -> bool {
+        if self
+            .process_filter
+            .map_or(false, |filter| filter != processid)
+        {
+            return false;
+        }
+        self.driver_filter.map_or(true, |driver_num| match event {
+            TraceEvent::Syscall(_) | TraceEvent::Upcall { .. } => {
+                event.driver_num() == Some(driver_num)
+            }
+            TraceEvent::SyscallReturn(_) => !self.syscall_filtered.get(),
+            TraceEvent::ContextSwitchIn | TraceEvent::ContextSwitchOut { .. } => true,
+        })
+    }
+}
+
+impl SyscallTracer for SyscallTraceBuffer {
+    fn trace(&self, processid: ProcessId, event: TraceEvent) {
+        let accepted = self.accept(processid, &event);
+        if let TraceEvent::Syscall(_) = event {
+            self.syscall_filtered.set(!accepted);
+        }
+        if !accepted {
+            return;
+        }
+
+        let (kind, subtype, args) = event.encode();
+        let sequence = self.sequence.get();
+        self.sequence.set(sequence.wrapping_add(1));
+
+        let mut record = [0; RECORD_LEN];
+        record[0] = kind as u8;
+        record[1] = subtype;
+        record[2..4].copy_from_slice(&sequence.to_le_bytes());
+        record[4..8].copy_from_slice(&(processid.id() as u32).to_le_bytes());
+        for (chunk, arg) in record[8..].chunks_exact_mut(4).zip(args) {
+            chunk.copy_from_slice(&arg.to_le_bytes());
+        }
+
+        let queued = self
+            .records
+            .map_or(false, |records| records.enqueue(record));
+        if !queued {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+}
@@ -11,12 +11,13 @@ use crate::debug;
 use crate::process;
 use crate::process::ProcessId;
 use crate::syscall::SyscallReturn;
+use crate::syscall_trace::TraceEvent;
 use crate::ErrorCode;
 
 /// Type to uniquely identify an upcall subscription across all drivers.
 ///
 /// This contains the driver number and the subscribe number within the driver.
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct UpcallId {
     pub driver_num: usize,
     pub subscribe_num: usize,
@@ -82,8 +83,9 @@ pub(crate) struct Upcall {
     /// associated with processid.
     ///
     /// If this value is `None`, this is a null upcall, which cannot actually be
-    /// scheduled. An `Upcall` can be null when it is first created, or after an
-    /// app unsubscribes from an upcall.
+    /// scheduled unless the process waits for it with `yield-wait-for`. An
+    /// `Upcall` can be null when it is first created, or after an app
+    /// unsubscribes from an upcall.
     pub(crate) fn_ptr: Option<NonNull<()>>,
 }
 
@@ -124,11 +126,24 @@ impl Upcall {
         r1: usize,
         r2: usize,
     ) -> Result<(), UpcallError> {
-        let res = self.fn_ptr.map_or(
+        // A process waiting for this upcall with yield-wait-for does not run
+        // the upcall function, so the upcall is delivered even if it is null.
+        let waiting_for_upcall = match process.get_state() {
+            process::State::YieldedFor(upcall_id)
+            | process::State::StoppedYieldedFor(upcall_id) => upcall_id == self.upcall_id,
+            _ => false,
+        };
+        let pc = match self.fn_ptr {
+            Some(fp) => Some(fp.as_ptr() as usize),
+            None if waiting_for_upcall => Some(0),
+            None => None,
+        };
+
+        let res = pc.map_or(
             // A null-Upcall is treated as being delivered to
             // the process and ignored
             Ok(()),
-            |fp| {
+            |pc| {
                 let enqueue_res =
                     process.enqueue_task(process::Task::FunctionCall(process::FunctionCall {
                         source: process::FunctionCallSource::Driver(self.upcall_id),
@@ -136,7 +151,7 @@ impl Upcall {
                         argument1: r1,
                         argument2: r2,
                         argument3: self.appdata,
-                        pc: fp.as_ptr() as usize,
+                        pc,
                     }));
 
                 match enqueue_res {
@@ -161,6 +176,16 @@ impl Upcall {
             },
         );
 
+        if res.is_ok() {
+            self.process_id.kernel.trace(
+                self.process_id,
+                TraceEvent::Upcall {
+                    upcall_id: self.upcall_id,
+                    args: (r0, r1, r2),
+                },
+            );
+        }
+
         if config::CONFIG.trace_syscalls {
             debug!(
                 "[{:?}] schedule[{:#x}:{}] @{:#x}({:#x}, {:#x}, {:#x}, {:#x}) = {:?}",
@@ -130,7 +130,7 @@
 use crate::error_codes::ErrorCode;
 use crate::flash_controller::FlashController;
 use crate::success_codes::SuccessCode;
-use crate::tickv::{State, TicKV};
+use crate::tickv::{KeyCursor, KeyInfo, RegionStats, State, TicKV};
 use core::cell::Cell;
 
 /// The return type from the continue operation
@@ -143,6 +143,18 @@ type ContinueReturn = (
     usize,
 );
 
+/// The result of a `next_key()`, `region_stats()` or `defragment()`
+/// operation, available from `take_report()` once it has completed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Report {
+    /// The key that was found, or `None` if there are no more keys
+    NextKey(Option<KeyInfo>),
+    /// The statistics of the region
+    RegionStats(RegionStats),
+    /// The region that was erased
+    Defragment(usize),
+}
+
 /// The struct storing all of the TicKV information for the async implementation.
 pub struct AsyncTicKV<'a, C: FlashController<S>, const S: usize> {
     /// The main TicKV struct
@@ -150,6 +162,9 @@ pub struct AsyncTicKV<'a, C: FlashController<S>, const S: usize> {
     key: Cell<Option<u64>>,
     value: Cell<Option<&'static mut [u8]>>,
     value_length: Cell<usize>,
+    cursor: Cell<KeyCursor>,
+    region: Cell<usize>,
+    report: Cell<Option<Report>>,
 }
 
 impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
@@ -165,6 +180,9 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
             key: Cell::new(None),
             value: Cell::new(None),
             value_length: Cell::new(0),
+            cursor: Cell::new(KeyCursor::default()),
+            region: Cell::new(0),
+            report: Cell::new(None),
         }
     }
 
@@ -309,6 +327,177 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
         }
     }
 
+    /// Find the next valid key in flash storage
+    ///
+    /// `cursor`: The position to start looking from.
+    ///
+    /// On success a `SuccessCode` will be returned, and the key will be
+    /// available from `take_report()` when the operation completes.
+    /// On error a `ErrorCode` will be returned.
+    pub fn next_key(&self, cursor: KeyCursor) -> Result<SuccessCode, ErrorCode> {
+        match self.tickv.next_key(cursor) {
+            Ok(_key) => Err(ErrorCode::ReadFail),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_) => {
+                    self.cursor.set(cursor);
+                    Ok(SuccessCode::Queued)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Get the usage and wear of a region
+    ///
+    /// `region`: The region number, less than `region_count()`.
+    ///
+    /// On success a `SuccessCode` will be returned, and the statistics will
+    /// be available from `take_report()` when the operation completes.
+    /// On error a `ErrorCode` will be returned.
+    pub fn region_stats(&self, region: usize) -> Result<SuccessCode, ErrorCode> {
+        match self.tickv.region_stats(region) {
+            Ok(_stats) => Err(ErrorCode::ReadFail),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_) => {
+                    self.region.set(region);
+                    Ok(SuccessCode::Queued)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Move the keys out of the most worn region and erase it
+    ///
+    /// `buf`: A buffer to copy values to. It must be large enough for the
+    ///        largest value in the region.
+    ///
+    /// On success a `SuccessCode` will be returned, and the region that was
+    /// erased will be available from `take_report()` when the operation
+    /// completes.
+    /// On error a `ErrorCode` will be returned.
+    pub fn defragment(
+        &self,
+        buf: &'static mut [u8],
+    ) -> Result<SuccessCode, (&'static mut [u8], ErrorCode)> {
+        match self.tickv.defragment(buf) {
+            Ok(_region) => Err((buf, ErrorCode::EraseFail)),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_)
+                | ErrorCode::EraseNotReady(_)
+                | ErrorCode::WriteNotReady(_) => {
+                    self.value.replace(Some(buf));
+                    Ok(SuccessCode::Queued)
+                }
+                _ => Err((buf, e)),
+            },
+        }
+    }
+
+    /// Begin a transaction
+    ///
+    /// On success a `SuccessCode` will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn begin_transaction(&self) -> Result<SuccessCode, ErrorCode> {
+        match self.tickv.begin_transaction() {
+            Ok(_code) => Err(ErrorCode::WriteFail),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_)
+                | ErrorCode::EraseNotReady(_)
+                | ErrorCode::WriteNotReady(_) => Ok(SuccessCode::Queued),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Stage a key/value pair in the open transaction
+    ///
+    /// `hash`: A hashed key.
+    /// `value`: A buffer containing the data to be stored to flash.
+    ///
+    /// On success a `SuccessCode` will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn stage_key(
+        &self,
+        hash: u64,
+        value: &'static mut [u8],
+        length: usize,
+    ) -> Result<SuccessCode, (&'static mut [u8], ErrorCode)> {
+        match self.tickv.stage_key(hash, &value[0..length]) {
+            Ok(_code) => Err((value, ErrorCode::WriteFail)),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_)
+                | ErrorCode::EraseNotReady(_)
+                | ErrorCode::WriteNotReady(_) => {
+                    self.key.replace(Some(hash));
+                    self.value.replace(Some(value));
+                    self.value_length.set(length);
+                    Ok(SuccessCode::Queued)
+                }
+                _ => Err((value, e)),
+            },
+        }
+    }
+
+    /// Stage the invalidation of a key in the open transaction
+    ///
+    /// `hash`: A hashed key.
+    ///
+    /// On success a `SuccessCode` will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn stage_invalidate(&self, hash: u64) -> Result<SuccessCode, ErrorCode> {
+        match self.tickv.stage_invalidate(hash) {
+            Ok(_code) => Err(ErrorCode::WriteFail),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_)
+                | ErrorCode::EraseNotReady(_)
+                | ErrorCode::WriteNotReady(_) => {
+                    self.key.replace(Some(hash));
+                    Ok(SuccessCode::Queued)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Commit the open transaction
+    ///
+    /// On success a `SuccessCode` will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn commit_transaction(&self) -> Result<SuccessCode, ErrorCode> {
+        match self.tickv.commit_transaction() {
+            Ok(_code) => Err(ErrorCode::WriteFail),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_)
+                | ErrorCode::EraseNotReady(_)
+                | ErrorCode::WriteNotReady(_) => Ok(SuccessCode::Queued),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Discard the keys staged in the open transaction
+    ///
+    /// On success a `SuccessCode` will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn abort_transaction(&self) -> Result<SuccessCode, ErrorCode> {
+        match self.tickv.abort_transaction() {
+            Ok(_code) => Err(ErrorCode::WriteFail),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_)
+                | ErrorCode::EraseNotReady(_)
+                | ErrorCode::WriteNotReady(_) => Ok(SuccessCode::Queued),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Take the result of the last `next_key()`, `region_stats()` or
+    /// `defragment()` operation to complete.
+    pub fn take_report(&self) -> Option<Report> {
+        self.report.take()
+    }
+
     /// Copy data from `read_buffer` argument to the internal read_buffer.
     /// This should be used to copy the data that the implementation wanted
     /// to read when calling `read_region` after the async operation has
@@ -338,6 +527,8 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     /// The buffers will only be returned on a non async error or on success.
     pub fn continue_operation(&self) -> ContinueReturn {
         let (ret, length) = match self.tickv.state.get() {
+            // The operation finished with the write that has completed
+            State::None => (Ok(SuccessCode::Complete), 0),
             State::Init(_) => (self.tickv.initialise(self.key.get().unwrap()), 0),
             State::AppendKey(_) => {
                 let value = self.value.take().unwrap();
@@ -357,13 +548,49 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
                     Err(e) => (Err(e), 0),
                 }
             }
+            State::StageKey(_) => {
+                let value = self.value.take().unwrap();
+                let value_length = self.value_length.get();
+                let ret = self
+                    .tickv
+                    .stage_key(self.key.get().unwrap(), &value[0..value_length]);
+                self.value.replace(Some(value));
+                (ret, value_length)
+            }
+            State::StageInvalidate(_) => (self.tickv.stage_invalidate(self.key.get().unwrap()), 0),
+            State::Transaction(_) => (self.tickv.continue_transaction(), 0),
             State::InvalidateKey(_) => (self.tickv.invalidate_key(self.key.get().unwrap()), 0),
             State::ZeroiseKey(_) => (self.tickv.zeroise_key(self.key.get().unwrap()), 0),
             State::GarbageCollect(_) => match self.tickv.garbage_collect() {
                 Ok(bytes_freed) => (Ok(SuccessCode::Complete), bytes_freed),
                 Err(e) => (Err(e), 0),
             },
-            _ => unreachable!(),
+            State::NextKey(_) => match self.tickv.next_key(self.cursor.get()) {
+                Ok(key) => {
+                    self.report.set(Some(Report::NextKey(key)));
+                    (Ok(SuccessCode::Complete), 0)
+                }
+                Err(e) => (Err(e), 0),
+            },
+            State::RegionStats(_) => match self.tickv.region_stats(self.region.get()) {
+                Ok(stats) => {
+                    self.report.set(Some(Report::RegionStats(stats)));
+                    (Ok(SuccessCode::Complete), 0)
+                }
+                Err(e) => (Err(e), 0),
+            },
+            State::Defragment(_) => {
+                let buf = self.value.take().unwrap();
+                let ret = self.tickv.defragment(buf);
+                self.value.replace(Some(buf));
+                match ret {
+                    Ok(region) => {
+                        self.report.set(Some(Report::Defragment(region)));
+                        (Ok(SuccessCode::Complete), 0)
+                    }
+                    Err(e) => (Err(e), 0),
+                }
+            }
         };
 
         match ret {
@@ -374,7 +601,13 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
             Err(e) => match e {
                 ErrorCode::ReadNotReady(_) | ErrorCode::EraseNotReady(_) => (ret, None, 0),
                 ErrorCode::WriteNotReady(_) => {
-                    self.tickv.state.set(State::None);
+                    // Garbage collection, defragmentation and transactions
+                    // continue once the write has completed.
+                    match self.tickv.state.get() {
+                        State::GarbageCollect(_) | State::Defragment(_) | State::Transaction(_) => {
+                        }
+                        _ => self.tickv.state.set(State::None),
+                    }
                     (ret, None, 0)
                 }
                 _ => {
@@ -392,11 +625,13 @@ mod tests {
 
     /// Tests using a flash controller that can store data
     mod store_flast_ctrl {
-        use crate::async_ops::AsyncTicKV;
+        use crate::async_ops::{AsyncTicKV, Report};
         use crate::error_codes::ErrorCode;
         use crate::flash_controller::FlashController;
         use crate::success_codes::SuccessCode;
-        use crate::tickv::{HASH_OFFSET, LEN_OFFSET, MAIN_KEY, VERSION, VERSION_OFFSET};
+        use crate::tickv::{
+            KeyCursor, RegionStats, HASH_OFFSET, LEN_OFFSET, MAIN_KEY, VERSION, VERSION_OFFSET,
+        };
         use core::hash::{Hash, Hasher};
         use std::cell::Cell;
         use std::cell::RefCell;
@@ -579,9 +814,7 @@ mod tests {
             fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
                 println!("Erase region: {}", region_number);
 
-                let mut local_buf = self.buf.borrow_mut()[region_number];
-
-                for d in local_buf.iter_mut() {
+                for d in self.buf.borrow_mut()[region_number].iter_mut() {
                     *d = 0xFF;
                 }
 
@@ -1180,5 +1413,213 @@ mod tests {
                 _ => unreachable!("ret: {:?}", ret),
             }
         }
+
+        /// Run an operation that continues after writes until it no longer
+        /// needs to wait for the flash.
+        fn finish_operation<const S: usize>(
+            tickv: &AsyncTicKV<FlashCtrl<S>, S>,
+        ) -> Result<SuccessCode, ErrorCode> {
+            loop {
+                flash_ctrl_callback(tickv);
+                match tickv.continue_operation().0 {
+                    Err(ErrorCode::ReadNotReady(_))
+                    | Err(ErrorCode::EraseNotReady(_))
+                    | Err(ErrorCode::WriteNotReady(_)) => {}
+                    ret => return ret,
+                }
+            }
+        }
+
+        fn region_stats<const S: usize>(
+            tickv: &AsyncTicKV<FlashCtrl<S>, S>,
+            region: usize,
+        ) -> RegionStats {
+            assert_eq!(tickv.region_stats(region), Ok(SuccessCode::Queued));
+            assert_eq!(finish_operation(tickv), Ok(SuccessCode::Complete));
+            match tickv.take_report() {
+                Some(Report::RegionStats(stats)) => stats,
+                report => panic!("Expected region statistics, got {report:?}"),
+            }
+        }
+
+        #[test]
+        fn test_defragment() {
+            let mut read_buf: [u8; 1024] = [0; 1024];
+            let mut hash_function = DefaultHasher::new();
+            MAIN_KEY.hash(&mut hash_function);
+
+            let tickv = AsyncTicKV::<FlashCtrl<1024>, 1024>::new(
+                FlashCtrl::new(false),
+                &mut read_buf,
+                0x10000,
+            );
+
+            let mut ret = tickv.initialise(hash_function.finish());
+            while ret.is_err() {
+                flash_ctrl_callback(&tickv);
+
+                // There is no actual delay in the test, just continue now
+                let (r, _buf, _len) = tickv.continue_operation();
+                ret = r;
+            }
+
+            static mut VALUE: [u8; 32] = [0x23; 32];
+            static mut BUF: [u8; 32] = [0; 32];
+
+            println!("Add, delete and garbage collect key ONE");
+            let ret = unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut VALUE, 32) };
+            assert_eq!(ret, Ok(SuccessCode::Queued));
+            flash_ctrl_callback(&tickv);
+            tickv.continue_operation().0.unwrap();
+            assert_eq!(
+                tickv.invalidate_key(get_hashed_key(b"ONE")),
+                Ok(SuccessCode::Queued)
+            );
+            flash_ctrl_callback(&tickv);
+            tickv.continue_operation().0.unwrap();
+            assert_eq!(tickv.garbage_collect(), Ok(SuccessCode::Queued));
+            assert_eq!(finish_operation(&tickv), Ok(SuccessCode::Complete));
+
+            println!("Add key ONE again");
+            let ret = unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut VALUE, 32) };
+            assert_eq!(ret, Ok(SuccessCode::Queued));
+            flash_ctrl_callback(&tickv);
+            tickv.continue_operation().0.unwrap();
+
+            println!("Find key ONE");
+            let mut cursor = KeyCursor::default();
+            let region = loop {
+                assert_eq!(tickv.next_key(cursor), Ok(SuccessCode::Queued));
+                assert_eq!(finish_operation(&tickv), Ok(SuccessCode::Complete));
+                match tickv.take_report() {
+                    Some(Report::NextKey(Some(key))) => {
+                        if key.hashed_key == get_hashed_key(b"ONE") {
+                            assert_eq!(key.value_length, 32);
+                            break key.region;
+                        }
+                        cursor = key.next;
+                    }
+                    report => panic!("Expected key ONE, got {report:?}"),
+                }
+            };
+
+            let stats = region_stats(&tickv, region);
+            assert_eq!(stats.erase_count, 1);
+            assert_eq!(stats.live_keys, 1);
+
+            println!("Defragment");
+            assert_eq!(
+                unsafe { tickv.defragment(&mut BUF) },
+                Ok(SuccessCode::Queued)
+            );
+            assert_eq!(finish_operation(&tickv), Ok(SuccessCode::Complete));
+            assert_eq!(tickv.take_report(), Some(Report::Defragment(region)));
+
+            let stats = region_stats(&tickv, region);
+            assert_eq!(stats.erase_count, 2);
+            assert_eq!(stats.live_keys, 0);
+
+            println!("Get key ONE");
+            match unsafe { tickv.get_key(get_hashed_key(b"ONE"), &mut BUF) } {
+                Ok(SuccessCode::Queued) => loop {
+                    flash_ctrl_callback(&tickv);
+                    let (ret, _buf, len) = tickv.continue_operation();
+                    match ret {
+                        Err(ErrorCode::ReadNotReady(_)) => {}
+                        ret => {
+                            assert_eq!(ret, Ok(SuccessCode::Complete));
+                            assert_eq!(len, 32);
+                            break;
+                        }
+                    }
+                },
+                ret => panic!("Expected Queued, got {ret:?}"),
+            }
+        }
+
+        fn initialise<const S: usize>(tickv: &AsyncTicKV<FlashCtrl<S>, S>) {
+            let mut hash_function = DefaultHasher::new();
+            MAIN_KEY.hash(&mut hash_function);
+
+            let mut ret = tickv.initialise(hash_function.finish());
+            while ret.is_err() {
+                flash_ctrl_callback(tickv);
+
+                // There is no actual delay in the test, just continue now
+                let (r, _buf, _len) = tickv.continue_operation();
+                ret = r;
+            }
+        }
+
+        fn get_value<const S: usize>(
+            tickv: &AsyncTicKV<FlashCtrl<S>, S>,
+            unhashed_key: &[u8],
+        ) -> Result<std::vec::Vec<u8>, ErrorCode> {
+            let buf = std::boxed::Box::leak(std::boxed::Box::new([0; 32]));
+            match tickv.get_key(get_hashed_key(unhashed_key), buf) {
+                Ok(SuccessCode::Queued) => loop {
+                    flash_ctrl_callback(tickv);
+                    match tickv.continue_operation() {
+                        (Err(ErrorCode::ReadNotReady(_)), _, _) => {}
+                        (Ok(_), Some(buf), len) => return Ok(buf[..len].to_vec()),
+                        (ret, _, _) => return Err(ret.unwrap_err()),
+                    }
+                },
+                ret => panic!("Expected Queued, got {:?}", ret.map_err(|(_, e)| e)),
+            }
+        }
+
+        #[test]
+        fn test_transaction() {
+            let mut read_buf: [u8; 1024] = [0; 1024];
+            let tickv = AsyncTicKV::<FlashCtrl<1024>, 1024>::new(
+                FlashCtrl::new(false),
+                &mut read_buf,
+                0x10000,
+            );
+            initialise(&tickv);
+
+            static mut VALUE: [u8; 32] = [0x23; 32];
+            static mut STAGED: [u8; 16] = [0x11; 16];
+
+            for key in [b"ONE", b"TWO"] {
+                let ret = unsafe { tickv.append_key(get_hashed_key(key), &mut VALUE, 32) };
+                assert_eq!(ret, Ok(SuccessCode::Queued));
+                flash_ctrl_callback(&tickv);
+                tickv.continue_operation().0.unwrap();
+            }
+
+            println!("Lose power with an open transaction");
+            assert_eq!(tickv.begin_transaction(), Ok(SuccessCode::Queued));
+            assert_eq!(finish_operation(&tickv), Ok(SuccessCode::Complete));
+            let ret = unsafe { tickv.stage_key(get_hashed_key(b"ONE"), &mut STAGED, 16) };
+            assert_eq!(ret, Ok(SuccessCode::Queued));
+            assert_eq!(finish_operation(&tickv), Ok(SuccessCode::Queued));
+
+            let flash = FlashCtrl::new(false);
+            *flash.buf.borrow_mut() = *tickv.tickv.controller.buf.borrow();
+            let mut read_buf: [u8; 1024] = [0; 1024];
+            let tickv = AsyncTicKV::<FlashCtrl<1024>, 1024>::new(flash, &mut read_buf, 0x10000);
+            initialise(&tickv);
+            assert_eq!(get_value(&tickv, b"ONE"), Ok([0x23; 32].to_vec()));
+
+            println!("Commit a transaction");
+            assert_eq!(tickv.begin_transaction(), Ok(SuccessCode::Queued));
+            assert_eq!(finish_operation(&tickv), Ok(SuccessCode::Complete));
+            let ret = unsafe { tickv.stage_key(get_hashed_key(b"ONE"), &mut STAGED, 16) };
+            assert_eq!(ret, Ok(SuccessCode::Queued));
+            assert_eq!(finish_operation(&tickv), Ok(SuccessCode::Queued));
+            assert_eq!(
+                tickv.stage_invalidate(get_hashed_key(b"TWO")),
+                Ok(SuccessCode::Queued)
+            );
+            assert_eq!(finish_operation(&tickv), Ok(SuccessCode::Queued));
+            assert_eq!(get_value(&tickv, b"TWO"), Ok([0x23; 32].to_vec()));
+
+            assert_eq!(tickv.commit_transaction(), Ok(SuccessCode::Queued));
+            assert_eq!(finish_operation(&tickv), Ok(SuccessCode::Complete));
+            assert_eq!(get_value(&tickv, b"ONE"), Ok([0x11; 16].to_vec()));
+            assert_eq!(get_value(&tickv, b"TWO"), Err(ErrorCode::KeyNotFound));
+        }
     }
 }
@@ -44,6 +44,11 @@ pub enum ErrorCode {
     WriteNotReady(usize),
     /// Indicates that the flash erase operation is not yet ready.
     EraseNotReady(usize),
+    /// A transaction is already open, or the operation can't run while
+    /// one is open.
+    TransactionInProgress,
+    /// The operation needs a transaction, but none is open.
+    NoTransaction,
 }
 
 impl From<ErrorCode> for isize {
@@ -64,6 +69,8 @@ impl From<ErrorCode> for isize {
             ErrorCode::ReadNotReady(_) => -13,
             ErrorCode::WriteNotReady(_) => -14,
             ErrorCode::EraseNotReady(_) => -15,
+            ErrorCode::TransactionInProgress => -16,
+            ErrorCode::NoTransaction => -17,
         }
     }
 }
@@ -228,6 +228,7 @@ pub use crate::flash_controller::FlashController;
 #[doc(inline)]
 pub use crate::tickv::TicKV;
 pub use crate::tickv::MAIN_KEY;
+pub use crate::tickv::{KeyCursor, KeyInfo, RegionStats};
 
 // This is used to run the tests on a host
 #[cfg(test)]
@@ -4,7 +4,11 @@
 
 use crate::error_codes::ErrorCode;
 use crate::flash_controller::FlashController;
-use crate::tickv::{TicKV, HASH_OFFSET, LEN_OFFSET, MAIN_KEY, VERSION, VERSION_OFFSET};
+use crate::success_codes::SuccessCode;
+use crate::tickv::{
+    KeyCursor, KeyInfo, RegionStats, TicKV, HASH_OFFSET, LEN_OFFSET, MAIN_KEY,
+    TRANSACTION_COMMIT_HASH, VERSION, VERSION_OFFSET,
+};
 use core::hash::{Hash, Hasher};
 use std::cell::Cell;
 use std::cell::RefCell;
@@ -312,9 +316,7 @@ mod store_flast_ctrl {
 
         fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
             println!("Erase region: {}", region_number);
-            let mut local_buf = self.buf.borrow_mut()[region_number];
-
-            for d in local_buf.iter_mut() {
+            for d in self.buf.borrow_mut()[region_number].iter_mut() {
                 *d = 0xFF;
             }
 
@@ -534,6 +536,381 @@ mod store_flast_ctrl {
         println!("Add Key ONE");
         tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
     }
+
+    fn all_keys(tickv: &TicKV<FlashCtrl, 1024>) -> std::vec::Vec<KeyInfo> {
+        let mut keys = std::vec::Vec::new();
+        let mut cursor = KeyCursor::default();
+        while let Some(key) = tickv.next_key(cursor).unwrap() {
+            cursor = key.next;
+            keys.push(key);
+        }
+        keys
+    }
+
+    fn find_key(tickv: &TicKV<FlashCtrl, 1024>, unhashed_key: &[u8]) -> KeyInfo {
+        let hash = get_hashed_key(unhashed_key);
+        *all_keys(tickv)
+            .iter()
+            .find(|key| key.hashed_key == hash)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_next_key() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+        // Skip checking the keys written
+        tickv.controller.run.set(100);
+
+        let value: [u8; 32] = [0x23; 32];
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        tickv
+            .append_key(get_hashed_key(b"TWO"), &value[..8])
+            .unwrap();
+        tickv.append_key(get_hashed_key(b"THREE"), &value).unwrap();
+        tickv.invalidate_key(get_hashed_key(b"THREE")).unwrap();
+
+        let keys = all_keys(&tickv);
+        assert_eq!(keys.len(), 3);
+        assert!(keys
+            .iter()
+            .any(|key| key.hashed_key == hash && key.value_length == 0));
+        assert_eq!(find_key(&tickv, b"ONE").value_length, 32);
+        assert_eq!(find_key(&tickv, b"TWO").value_length, 8);
+        assert!(!keys
+            .iter()
+            .any(|key| key.hashed_key == get_hashed_key(b"THREE")));
+
+        // Continuing from the last key finds nothing more
+        let last = keys.last().unwrap();
+        assert_eq!(tickv.next_key(last.next), Ok(None));
+    }
+
+    #[test]
+    fn test_region_stats() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+        tickv.controller.run.set(100);
+        assert_eq!(tickv.region_count(), 64);
+        assert_eq!(tickv.region_stats(64), Err(ErrorCode::ReadFail));
+
+        let value: [u8; 32] = [0x23; 32];
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        let region = find_key(&tickv, b"ONE").region;
+        assert_eq!(
+            tickv.region_stats(region),
+            Ok(RegionStats {
+                erase_count: 0,
+                live_keys: 1,
+                live_bytes: 47,
+                reclaimable_bytes: 0,
+                free_bytes: 1024 - 47,
+            })
+        );
+
+        tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+        assert_eq!(tickv.region_stats(region).unwrap().reclaimable_bytes, 47);
+
+        // Each garbage collection of the region is recorded
+        for erase_count in 1..=3 {
+            assert_eq!(tickv.garbage_collect(), Ok(1024));
+            assert_eq!(
+                tickv.region_stats(region),
+                Ok(RegionStats {
+                    erase_count,
+                    live_keys: 0,
+                    live_bytes: 0,
+                    reclaimable_bytes: 0,
+                    free_bytes: 1024 - 19,
+                })
+            );
+
+            // A region with only the wear record isn't erased again
+            assert_eq!(tickv.garbage_collect(), Ok(0));
+
+            tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+            assert_eq!(find_key(&tickv, b"ONE").region, region);
+            tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_defragment() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+        tickv.controller.run.set(100);
+
+        let value: [u8; 32] = [0x23; 32];
+        let mut buf: [u8; 32] = [0; 32];
+
+        // Wear out the region of key ONE
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        let region = find_key(&tickv, b"ONE").region;
+        tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+        assert_eq!(tickv.garbage_collect(), Ok(1024));
+
+        // Leave an invalid copy of key ONE next to the valid one
+        tickv
+            .append_key(get_hashed_key(b"ONE"), &[0x11; 16])
+            .unwrap();
+        tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        tickv.append_key(get_hashed_key(b"TWO"), &value).unwrap();
+        assert_eq!(tickv.garbage_collect(), Ok(0));
+        assert_eq!(tickv.region_stats(region).unwrap().reclaimable_bytes, 31);
+
+        assert_eq!(
+            tickv.defragment(&mut buf[..4]),
+            Err(ErrorCode::BufferTooSmall(32))
+        );
+        assert_eq!(tickv.defragment(&mut buf), Ok(region));
+
+        let stats = tickv.region_stats(region).unwrap();
+        assert_eq!(stats.erase_count, 2);
+        assert_eq!(stats.live_keys, 0);
+        assert_eq!(stats.reclaimable_bytes, 0);
+
+        // The key has moved and can still be used
+        assert_ne!(find_key(&tickv, b"ONE").region, region);
+        assert_eq!(all_keys(&tickv).len(), 3);
+        buf = [0; 32];
+        tickv.get_key(get_hashed_key(b"ONE"), &mut buf).unwrap();
+        assert_eq!(buf, value);
+        tickv.get_key(get_hashed_key(b"TWO"), &mut buf).unwrap();
+        tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf),
+            Err(ErrorCode::KeyNotFound)
+        );
+
+        // Keys moved out of their region are still found after a restart
+        let second = tickv.defragment(&mut buf).unwrap();
+        assert_ne!(second, region);
+        tickv.initialise(hash).unwrap();
+        assert_eq!(all_keys(&tickv).len(), 2);
+        tickv.get_key(get_hashed_key(b"TWO"), &mut buf).unwrap();
+        assert_eq!(buf, value);
+    }
+
+    /// Simulate a power loss by copying the contents of the flash to a new
+    /// controller.
+    fn power_loss(tickv: &TicKV<FlashCtrl, 1024>) -> FlashCtrl {
+        let flash = FlashCtrl::new();
+        *flash.buf.borrow_mut() = *tickv.controller.buf.borrow();
+        flash.run.set(100);
+        flash
+    }
+
+    #[test]
+    fn test_transaction_commit() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+        tickv.controller.run.set(100);
+
+        let value: [u8; 32] = [0x23; 32];
+        let mut buf: [u8; 32] = [0; 32];
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        tickv.append_key(get_hashed_key(b"TWO"), &value).unwrap();
+
+        assert_eq!(
+            tickv.stage_key(get_hashed_key(b"ONE"), &[0x11; 16]),
+            Err(ErrorCode::NoTransaction)
+        );
+        assert_eq!(tickv.commit_transaction(), Err(ErrorCode::NoTransaction));
+
+        tickv.begin_transaction().unwrap();
+        assert_eq!(
+            tickv.begin_transaction(),
+            Err(ErrorCode::TransactionInProgress)
+        );
+        tickv
+            .stage_key(get_hashed_key(b"ONE"), &[0x11; 16])
+            .unwrap();
+        tickv.stage_invalidate(get_hashed_key(b"TWO")).unwrap();
+        tickv
+            .stage_key(get_hashed_key(b"THREE"), &[0x33; 8])
+            .unwrap();
+        assert_eq!(
+            tickv.stage_key(get_hashed_key(b"ONE"), &value),
+            Err(ErrorCode::KeyAlreadyExists)
+        );
+        assert_eq!(
+            tickv.defragment(&mut buf),
+            Err(ErrorCode::TransactionInProgress)
+        );
+
+        // The staged keys have no effect until they are committed
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf),
+            Ok((SuccessCode::Complete, 32))
+        );
+        assert_eq!(buf, value);
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"THREE"), &mut buf),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(all_keys(&tickv).len(), 3);
+
+        tickv.commit_transaction().unwrap();
+        assert_eq!(
+            tickv.stage_invalidate(get_hashed_key(b"ONE")),
+            Err(ErrorCode::NoTransaction)
+        );
+
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf),
+            Ok((SuccessCode::Complete, 16))
+        );
+        assert_eq!(buf[..16], [0x11; 16]);
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"TWO"), &mut buf),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"THREE"), &mut buf),
+            Ok((SuccessCode::Complete, 8))
+        );
+        assert_eq!(buf[..8], [0x33; 8]);
+        assert_eq!(all_keys(&tickv).len(), 3);
+
+        // The committed keys are kept after a restart
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let tickv = TicKV::<FlashCtrl, 1024>::new(power_loss(&tickv), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+        assert_eq!(find_key(&tickv, b"ONE").value_length, 16);
+        assert_eq!(find_key(&tickv, b"THREE").value_length, 8);
+        assert_eq!(all_keys(&tickv).len(), 3);
+    }
+
+    #[test]
+    fn test_transaction_abort() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+        tickv.controller.run.set(100);
+
+        let value: [u8; 32] = [0x23; 32];
+        let mut buf: [u8; 32] = [0; 32];
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        let region = find_key(&tickv, b"ONE").region;
+
+        tickv.begin_transaction().unwrap();
+        tickv
+            .stage_key(get_hashed_key(b"ONE"), &[0x11; 16])
+            .unwrap();
+        tickv.stage_key(get_hashed_key(b"TWO"), &value).unwrap();
+        assert_eq!(tickv.region_stats(region).unwrap().live_keys, 1);
+        assert_eq!(tickv.region_stats(region).unwrap().live_bytes, 47 + 31);
+        tickv.abort_transaction().unwrap();
+        assert_eq!(tickv.abort_transaction(), Err(ErrorCode::NoTransaction));
+
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf),
+            Ok((SuccessCode::Complete, 32))
+        );
+        assert_eq!(buf, value);
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"TWO"), &mut buf),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(tickv.region_stats(region).unwrap().reclaimable_bytes, 31);
+
+        // The key can be staged again in a new transaction
+        tickv.begin_transaction().unwrap();
+        tickv
+            .stage_key(get_hashed_key(b"ONE"), &[0x11; 16])
+            .unwrap();
+        tickv.commit_transaction().unwrap();
+        assert_eq!(find_key(&tickv, b"ONE").value_length, 16);
+    }
+
+    #[test]
+    fn test_transaction_power_loss() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+        tickv.controller.run.set(100);
+
+        let value: [u8; 32] = [0x23; 32];
+        let mut buf: [u8; 32] = [0; 32];
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        tickv.append_key(get_hashed_key(b"TWO"), &value).unwrap();
+
+        println!("Power loss before the commit");
+        tickv.begin_transaction().unwrap();
+        tickv
+            .stage_key(get_hashed_key(b"ONE"), &[0x11; 16])
+            .unwrap();
+        tickv.stage_invalidate(get_hashed_key(b"TWO")).unwrap();
+
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let tickv = TicKV::<FlashCtrl, 1024>::new(power_loss(&tickv), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+        assert_eq!(
+            tickv.stage_key(get_hashed_key(b"ONE"), &[0x11; 16]),
+            Err(ErrorCode::NoTransaction)
+        );
+        assert_eq!(find_key(&tickv, b"ONE").value_length, 32);
+        assert_eq!(find_key(&tickv, b"TWO").value_length, 32);
+        assert_eq!(all_keys(&tickv).len(), 3);
+
+        println!("Power loss during the commit");
+        tickv.begin_transaction().unwrap();
+        tickv
+            .stage_key(get_hashed_key(b"ONE"), &[0x11; 16])
+            .unwrap();
+        tickv.stage_invalidate(get_hashed_key(b"TWO")).unwrap();
+        // The commit marker has been written and the old copy of ONE
+        // invalidated when the power is lost.
+        tickv.append_key(TRANSACTION_COMMIT_HASH, &[]).unwrap();
+        tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let tickv = TicKV::<FlashCtrl, 1024>::new(power_loss(&tickv), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf),
+            Ok((SuccessCode::Complete, 16))
+        );
+        assert_eq!(buf[..16], [0x11; 16]);
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"TWO"), &mut buf),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(all_keys(&tickv).len(), 2);
+
+        // Both markers have been removed
+        tickv.begin_transaction().unwrap();
+        tickv.commit_transaction().unwrap();
+    }
 }
 
 mod no_check_store_flast_ctrl {
@@ -583,9 +960,7 @@ mod no_check_store_flast_ctrl {
 
         fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
             println!("Erase region: {}", region_number);
-            let mut local_buf = self.buf.borrow_mut()[region_number];
-
-            for d in local_buf.iter_mut() {
+            for d in self.buf.borrow_mut()[region_number].iter_mut() {
                 *d = 0xFF;
             }
 
@@ -34,7 +34,119 @@ pub(crate) enum KeyState {
 #[derive(Clone, Copy, PartialEq)]
 pub(crate) enum RubbishState {
     ReadRegion(usize, usize),
-    EraseRegion(usize, usize),
+    /// Erasing a region, with the erase count from its wear record
+    EraseRegion(usize, usize, u32),
+    /// Writing the wear record of a region that has been erased
+    WriteWearRecord(usize, usize),
+}
+
+/// A region that `defragment()` could empty.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct Candidate {
+    region: usize,
+    erase_count: u32,
+    reclaimable_bytes: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum DefragState {
+    /// Reading a region while looking for the most worn region with keys
+    FindRegion(usize, Option<Candidate>),
+    /// Reading the region being emptied to find the next key to move
+    ReadRegion(usize),
+    /// Appending a copy of the key at `offset` in `region`
+    MoveKey {
+        region: usize,
+        offset: usize,
+        hash: u64,
+        length: usize,
+        target: Option<usize>,
+    },
+    /// Waiting for the copy of a key to be written
+    KeyMoved(usize, usize),
+    /// Reading the region to invalidate a key that has been copied
+    InvalidateKey(usize, usize),
+    /// Waiting for the invalidation of a key to be written
+    KeyInvalidated(usize),
+    /// Erasing the region, with the erase count from its wear record
+    EraseRegion(usize, u32),
+    /// Writing the wear record of the erased region
+    WriteWearRecord(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum TransactionState {
+    /// Looking for the commit marker, or the open marker if `commit` is
+    /// `false`, after a restart
+    Recover { commit: bool, target: Option<usize> },
+    /// Appending the marker with `hash`
+    AppendMarker { hash: u64, target: Option<usize> },
+    /// Waiting for the marker with `hash` to be written
+    MarkerWritten(u64),
+    /// Reading `region` to find the next staged object at or after `offset`
+    FindPending {
+        commit: bool,
+        region: usize,
+        offset: usize,
+    },
+    /// Invalidating the committed copy of the key staged at `offset` in
+    /// `region`
+    ReplaceKey {
+        region: usize,
+        offset: usize,
+        hash: u64,
+        target: Option<usize>,
+    },
+    /// Waiting for the committed copy of a key to be invalidated
+    KeyReplaced(usize, usize),
+    /// Reading `region` to commit or discard the object at `offset`
+    Resolve {
+        commit: bool,
+        region: usize,
+        offset: usize,
+    },
+    /// Waiting for an object to be committed or discarded, the object after
+    /// it is at `offset`
+    Resolved {
+        commit: bool,
+        region: usize,
+        offset: usize,
+    },
+    /// Invalidating the marker with `hash`
+    RemoveMarker { hash: u64, target: Option<usize> },
+    /// Waiting for the marker with `hash` to be invalidated
+    MarkerRemoved(u64),
+    /// The open marker has been written
+    Begun,
+    /// The transaction has been committed or discarded
+    Finished,
+}
+
+impl TransactionState {
+    /// The step after the marker with `hash` has been written.
+    fn marker_written(hash: u64) -> Self {
+        if hash == TRANSACTION_OPEN_HASH {
+            TransactionState::Begun
+        } else {
+            TransactionState::FindPending {
+                commit: true,
+                region: 0,
+                offset: 0,
+            }
+        }
+    }
+
+    /// The step after the marker with `hash` has been invalidated.
+    fn marker_removed(hash: u64) -> Self {
+        if hash == TRANSACTION_COMMIT_HASH {
+            TransactionState::RemoveMarker {
+                hash: TRANSACTION_OPEN_HASH,
+                target: None,
+            }
+        } else {
+            TransactionState::Finished
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -55,6 +167,58 @@ pub(crate) enum State {
     ZeroiseKey(KeyState),
     /// Running garbage collection
     GarbageCollect(RubbishState),
+    /// Finding the next key
+    NextKey(KeyState),
+    /// Reading the statistics of a region
+    RegionStats(KeyState),
+    /// Moving keys out of the most worn region
+    Defragment(DefragState),
+    /// Staging a key in a transaction
+    StageKey(KeyState),
+    /// Staging the invalidation of a key in a transaction
+    StageInvalidate(KeyState),
+    /// Beginning, committing, discarding or recovering a transaction
+    Transaction(TransactionState),
+}
+
+/// A position in the store, used to enumerate the keys with `next_key()`.
+///
+/// The default cursor is the start of the store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyCursor {
+    region: usize,
+    offset: usize,
+}
+
+/// A valid key found by `next_key()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyInfo {
+    /// The hashed key.
+    pub hashed_key: u64,
+    /// The length of the value stored for the key.
+    pub value_length: usize,
+    /// The region the key is stored in.
+    pub region: usize,
+    /// The cursor to pass to `next_key()` to find the keys after this one.
+    pub next: KeyCursor,
+}
+
+/// The usage and wear of a region, returned by `region_stats()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegionStats {
+    /// The number of times TicKV has erased the region since it was
+    /// initialised.
+    pub erase_count: u32,
+    /// The number of valid keys in the region.
+    pub live_keys: usize,
+    /// The bytes used by valid objects.
+    pub live_bytes: usize,
+    /// The bytes used by invalid objects. `garbage_collect()` reclaims these
+    /// once all of the objects in the region are invalid, `defragment()` can
+    /// reclaim them earlier.
+    pub reclaimable_bytes: usize,
+    /// The bytes that have not been written since the region was erased.
+    pub free_bytes: usize,
 }
 
 /// The struct storing all of the TicKV information.
@@ -64,6 +228,7 @@ pub struct TicKV<'a, C: FlashController<S>, const S: usize> {
     flash_size: usize,
     pub(crate) read_buffer: Cell<Option<&'a mut [u8; S]>>,
     pub(crate) state: Cell<State>,
+    transaction: Cell<bool>,
 }
 
 /// This is the current object header used for TicKV objects
@@ -77,13 +242,17 @@ struct ObjectHeader {
 }
 
 pub(crate) const FLAGS_VALID: u8 = 8;
+// Set on objects staged by a transaction, cleared when it is committed
+pub(crate) const FLAGS_PENDING: u8 = 4;
+// Set on staged objects that invalidate their key when committed
+pub(crate) const FLAGS_DELETE: u8 = 2;
 
 impl ObjectHeader {
-    fn new(hashed_key: u64, len: u16) -> Self {
+    fn new(hashed_key: u64, len: u16, flags: u8) -> Self {
         assert!(len < 0xFFF);
         Self {
             version: VERSION,
-            flags: FLAGS_VALID,
+            flags,
             len,
             hashed_key,
         }
@@ -97,6 +266,156 @@ pub(crate) const HASH_OFFSET: usize = 3;
 pub(crate) const HEADER_LENGTH: usize = HASH_OFFSET + 8;
 pub(crate) const CHECK_SUM_LEN: usize = 4;
 
+// The wear record is an invalid object with this hash, written to the start
+// of a region after TicKV erases it. Its value is the erase count of the
+// region. No key can have this hash, see `get_region()`.
+pub(crate) const WEAR_RECORD_HASH: u64 = 0;
+pub(crate) const WEAR_RECORD_LENGTH: usize = HEADER_LENGTH + 4 + CHECK_SUM_LEN;
+
+// The markers of an open transaction, stored as keys with no value. The open
+// marker is written by `begin_transaction()`, the commit marker once all of
+// the keys have been staged. Both are invalidated when the transaction is
+// finished.
+pub(crate) const TRANSACTION_OPEN_HASH: u64 = 0x7478_6e2d_6f70_656e;
+pub(crate) const TRANSACTION_COMMIT_HASH: u64 = 0x7478_6e2d_636f_6d74;
+
+/// The header of an object found in a region.
+#[derive(Clone, Copy)]
+struct StoredObject {
+    offset: usize,
+    length: usize,
+    valid: bool,
+    /// Staged by a transaction that hasn't been committed
+    pending: bool,
+    /// Invalidates its key when the transaction is committed
+    delete: bool,
+    hashed_key: u64,
+}
+
+impl StoredObject {
+    /// Returns `true` if this is the wear record of the region.
+    fn is_wear_record(&self) -> bool {
+        self.offset == 0
+            && !self.valid
+            && self.hashed_key == WEAR_RECORD_HASH
+            && self.length == WEAR_RECORD_LENGTH
+    }
+
+    /// Returns `true` if this is a transaction marker, which isn't a key.
+    fn is_transaction_marker(&self) -> bool {
+        self.hashed_key == TRANSACTION_OPEN_HASH || self.hashed_key == TRANSACTION_COMMIT_HASH
+    }
+}
+
+/// Read the header of the object at `offset` in `region_data`.
+///
+/// Returns `None` if there are no more objects in the region.
+fn read_object(region_data: &[u8], offset: usize) -> Result<Option<StoredObject>, ErrorCode> {
+    if offset + HEADER_LENGTH >= region_data.len() {
+        return Ok(None);
+    }
+
+    let header = region_data
+        .get(offset..(offset + HEADER_LENGTH))
+        .ok_or(ErrorCode::CorruptData)?;
+    if header[VERSION_OFFSET] == 0xFF {
+        return Ok(None);
+    }
+    if header[VERSION_OFFSET] != VERSION {
+        return Err(ErrorCode::UnsupportedVersion);
+    }
+
+    let length = ((header[LEN_OFFSET] & 0x0F) as usize) << 8 | header[LEN_OFFSET + 1] as usize;
+    if length < HEADER_LENGTH + CHECK_SUM_LEN || offset + length > region_data.len() {
+        return Err(ErrorCode::CorruptData);
+    }
+
+    let mut hashed_key = [0; 8];
+    hashed_key.copy_from_slice(&header[HASH_OFFSET..HEADER_LENGTH]);
+
+    Ok(Some(StoredObject {
+        offset,
+        length,
+        valid: header[LEN_OFFSET] & (FLAGS_VALID << 4) != 0,
+        pending: header[LEN_OFFSET] & (FLAGS_PENDING << 4) != 0,
+        delete: header[LEN_OFFSET] & (FLAGS_DELETE << 4) != 0,
+        hashed_key: u64::from_be_bytes(hashed_key),
+    }))
+}
+
+/// Find the first valid object at or after `start` in `region_data`, which
+/// is staged by a transaction if `pending` is `true` and committed otherwise.
+fn find_valid_object(
+    region_data: &[u8],
+    start: usize,
+    pending: bool,
+) -> Result<Option<StoredObject>, ErrorCode> {
+    let mut offset = 0;
+    while let Some(object) = read_object(region_data, offset)? {
+        if object.valid && object.pending == pending && object.offset >= start {
+            return Ok(Some(object));
+        }
+        offset += object.length;
+    }
+    Ok(None)
+}
+
+/// Check the check sum of `object` in `region_data`.
+fn check_object(region_data: &[u8], object: &StoredObject) -> Result<(), ErrorCode> {
+    let data = region_data
+        .get(object.offset..(object.offset + object.length))
+        .ok_or(ErrorCode::CorruptData)?;
+    let (data, stored_sum) = data.split_at(object.length - CHECK_SUM_LEN);
+
+    let mut check_sum = crc32::Crc32::new();
+    check_sum.update(data);
+    if check_sum.finalise().to_ne_bytes() != stored_sum {
+        return Err(ErrorCode::InvalidCheckSum);
+    }
+    Ok(())
+}
+
+/// Get the erase count from the wear record of a region. Regions without a
+/// wear record have not been erased since TicKV was initialised.
+fn erase_count(region_data: &[u8]) -> u32 {
+    match read_object(region_data, 0) {
+        Ok(Some(object)) if object.is_wear_record() => {
+            if check_object(region_data, &object).is_err() {
+                return 0;
+            }
+            let mut count = [0; 4];
+            count.copy_from_slice(&region_data[HEADER_LENGTH..(HEADER_LENGTH + 4)]);
+            u32::from_be_bytes(count)
+        }
+        _ => 0,
+    }
+}
+
+/// Count the objects in a region.
+fn count_objects(region_data: &[u8]) -> Result<RegionStats, ErrorCode> {
+    let mut stats = RegionStats {
+        erase_count: erase_count(region_data),
+        ..RegionStats::default()
+    };
+
+    let mut offset = 0;
+    while let Some(object) = read_object(region_data, offset)? {
+        if object.valid {
+            // Staged keys use space, but aren't keys until they're committed
+            if !object.pending && !object.is_transaction_marker() {
+                stats.live_keys += 1;
+            }
+            stats.live_bytes += object.length;
+        } else if !object.is_wear_record() {
+            stats.reclaimable_bytes += object.length;
+        }
+        offset += object.length;
+    }
+    stats.free_bytes = region_data.len() - offset;
+
+    Ok(stats)
+}
+
 /// The main key. A hashed version of this should be passed to
 /// `initialise()`.
 pub const MAIN_KEY: &[u8; 15] = b"tickv-super-key";
@@ -115,6 +434,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
             flash_size,
             read_buffer: Cell::new(Some(read_buffer)),
             state: Cell::new(State::None),
+            transaction: Cell::new(false),
         }
     }
 
@@ -126,6 +446,10 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
     /// If the specified region has not already been setup for TicKV
     /// the entire region will be erased.
     ///
+    /// If a transaction was interrupted by a power loss it is finished: if
+    /// its commit marker was written the staged keys are committed,
+    /// otherwise they are discarded.
+    ///
     /// On success nothing will be returned.
     /// On error a `ErrorCode` will be returned.
     pub fn initialise(&self, hashed_main_key: u64) -> Result<SuccessCode, ErrorCode> {
@@ -141,7 +465,10 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         };
 
         match key_ret {
-            Ok((ret, _len)) => Ok(ret),
+            Ok(_) => self.transaction_step(TransactionState::Recover {
+                commit: true,
+                target: None,
+            }),
             Err(e) => {
                 match e {
                     ErrorCode::ReadNotReady(reg) => {
@@ -262,7 +589,8 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         None
     }
 
-    /// Find a key in some loaded region data.
+    /// Find a key in some loaded region data, that is staged by a transaction
+    /// if `pending` is `true` and committed otherwise.
     ///
     /// On success return the offset in the region_data where the key is and the
     /// total length of the key.
@@ -272,6 +600,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         &self,
         hash: u64,
         region_data: &[u8],
+        pending: bool,
     ) -> Result<(usize, u16), (bool, ErrorCode)> {
         // Determine the total size of our payload
 
@@ -334,6 +663,18 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                     continue;
                 }
 
+                // Check to see if the entry is staged by a transaction
+                if (*region_data
+                    .get(offset + LEN_OFFSET)
+                    .ok_or((false, ErrorCode::CorruptData))?
+                    & (FLAGS_PENDING << 4)
+                    != 0)
+                    != pending
+                {
+                    offset += total_length as usize;
+                    continue;
+                }
+
                 // We have found a valid entry, see if it is ours.
                 if *region_data
                     .get(offset + HASH_OFFSET)
@@ -391,7 +732,30 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
     /// On success nothing will be returned.
     /// On error a `ErrorCode` will be returned.
     pub fn append_key(&self, hash: u64, value: &[u8]) -> Result<SuccessCode, ErrorCode> {
+        self.append_object(hash, value, None, FLAGS_VALID)
+    }
+
+    /// Appends the key/value pair with the header `flags`, but not to
+    /// `skip_region`.
+    fn append_object(
+        &self,
+        hash: u64,
+        value: &[u8],
+        skip_region: Option<usize>,
+        flags: u8,
+    ) -> Result<SuccessCode, ErrorCode> {
         let region = self.get_region(hash);
+        let pending = flags & FLAGS_PENDING != 0;
+        // The state to resume when a read of the region is not ready
+        let read_state = |reg| {
+            if flags & FLAGS_DELETE != 0 {
+                State::StageInvalidate(KeyState::ReadRegion(reg))
+            } else if pending {
+                State::StageKey(KeyState::ReadRegion(reg))
+            } else {
+                State::AppendKey(KeyState::ReadRegion(reg))
+            }
+        };
         let mut check_sum = crc32::Crc32::new();
 
         // Length not including check sum
@@ -403,7 +767,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         }
 
         // Create the header:
-        let header = ObjectHeader::new(hash, object_length as u16);
+        let header = ObjectHeader::new(hash, object_length as u16, flags);
 
         let mut region_offset: isize = 0;
 
@@ -419,14 +783,31 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                         }
                     }
                 }
-                State::AppendKey(key_state) => match key_state {
+                State::AppendKey(key_state)
+                | State::StageKey(key_state)
+                | State::StageInvalidate(key_state) => match key_state {
                     KeyState::ReadRegion(reg) => reg,
                 },
                 _ => unreachable!(),
             };
 
+            if skip_region == Some(new_region) {
+                // The object can't be stored here, try the next region
+                region_offset = new_region as isize - region as isize;
+                match self.increment_region_offset(region, region_offset) {
+                    Some(o) => {
+                        region_offset = o;
+                        self.state.set(State::None);
+                    }
+                    None => {
+                        return Err(ErrorCode::FlashFull);
+                    }
+                }
+                continue;
+            }
+
             let region_data = self.read_buffer.take().unwrap();
-            if self.state.get() != State::AppendKey(KeyState::ReadRegion(new_region))
+            if self.state.get() != read_state(new_region)
                 && self.state.get() != State::Init(InitState::AppendKeyReadRegion(new_region))
             {
                 match self.controller.read_region(new_region, 0, region_data) {
@@ -434,14 +815,14 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                     Err(e) => {
                         self.read_buffer.replace(Some(region_data));
                         if let ErrorCode::ReadNotReady(reg) = e {
-                            self.state.set(State::AppendKey(KeyState::ReadRegion(reg)));
+                            self.state.set(read_state(reg));
                         }
                         return Err(e);
                     }
                 };
             }
 
-            if self.find_key_offset(hash, region_data).is_ok() {
+            if self.find_key_offset(hash, region_data, pending).is_ok() {
                 // Check to make sure we don't already have this key
                 self.read_buffer.replace(Some(region_data));
                 return Err(ErrorCode::KeyAlreadyExists);
@@ -611,12 +992,16 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                     .get_mut(offset + HASH_OFFSET + 7)
                     .ok_or(ErrorCode::RegionFull)? = (header.hashed_key) as u8;
 
-                // Hash the new header data
-                check_sum.update(
+                // Hash the new header data. Staged objects are checked once
+                // they have been committed, so the pending flag isn't hashed.
+                let mut header_data = [0; HEADER_LENGTH];
+                header_data.copy_from_slice(
                     region_data
                         .get(offset + VERSION_OFFSET..=offset + HASH_OFFSET + 7)
                         .ok_or(ErrorCode::CorruptData)?,
                 );
+                header_data[LEN_OFFSET] &= !(FLAGS_PENDING << 4);
+                check_sum.update(&header_data);
 
                 // Copy the value
                 let slice = region_data
@@ -705,7 +1090,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 };
             }
 
-            match self.find_key_offset(hash, region_data) {
+            match self.find_key_offset(hash, region_data, false) {
                 Ok((offset, total_length)) => {
                     // Add the header data to the check hash
                     check_sum.update(
@@ -831,7 +1216,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 };
             }
 
-            match self.find_key_offset(hash, region_data) {
+            match self.find_key_offset(hash, region_data, false) {
                 Ok((offset, _data_len)) => {
                     // We found a key, let's delete it
                     *region_data
@@ -929,7 +1314,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 };
             }
 
-            match self.find_key_offset(hash, region_data) {
+            match self.find_key_offset(hash, region_data, false) {
                 Ok((offset, data_len)) => {
                     // We found a key, let's delete it
                     *region_data
@@ -1034,6 +1419,16 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                     return Err(ErrorCode::UnsupportedVersion);
                 }
 
+                // The wear record doesn't need to be garbage collected
+                if offset == 0 {
+                    if let Ok(Some(object)) = read_object(region_data, offset) {
+                        if object.is_wear_record() {
+                            offset += object.length;
+                            continue;
+                        }
+                    }
+                }
+
                 entry_found = true;
 
                 // Find this entries length
@@ -1079,6 +1474,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
             }
         }
 
+        let erase_count = erase_count(region_data);
         self.read_buffer.replace(Some(region_data));
 
         // If we got down here, the region is ready to be erased.
@@ -1089,6 +1485,18 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                     .set(State::GarbageCollect(RubbishState::EraseRegion(
                         reg,
                         flash_freed + S,
+                        erase_count,
+                    )));
+            }
+            return Err(e);
+        }
+
+        if let Err(e) = self.write_wear_record(region, erase_count.saturating_add(1)) {
+            if let ErrorCode::WriteNotReady(_) = e {
+                self.state
+                    .set(State::GarbageCollect(RubbishState::WriteWearRecord(
+                        region,
+                        flash_freed + S,
                     )));
             }
             return Err(e);
@@ -1097,6 +1505,25 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         Ok(S)
     }
 
+    /// Write the wear record to the start of `region`, which has just been
+    /// erased.
+    fn write_wear_record(&self, region: usize, erase_count: u32) -> Result<(), ErrorCode> {
+        let mut record = [0xFF; WEAR_RECORD_LENGTH];
+
+        // The valid flag is not set, so the record is never returned as a key
+        record[VERSION_OFFSET] = VERSION;
+        record[LEN_OFFSET] = (WEAR_RECORD_LENGTH >> 8) as u8 & 0x0F;
+        record[LEN_OFFSET + 1] = (WEAR_RECORD_LENGTH & 0xFF) as u8;
+        record[HASH_OFFSET..HEADER_LENGTH].copy_from_slice(&WEAR_RECORD_HASH.to_be_bytes());
+        record[HEADER_LENGTH..(HEADER_LENGTH + 4)].copy_from_slice(&erase_count.to_be_bytes());
+
+        let mut check_sum = crc32::Crc32::new();
+        check_sum.update(&record[..(HEADER_LENGTH + 4)]);
+        record[(HEADER_LENGTH + 4)..].copy_from_slice(&check_sum.finalise().to_ne_bytes());
+
+        self.controller.write(S * region, &record)
+    }
+
     /// Perform a garbage collection on TicKV
     ///
     /// On success the number of bytes freed will be returned.
@@ -1104,21 +1531,35 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
     pub fn garbage_collect(&self) -> Result<usize, ErrorCode> {
         let num_region = self.flash_size / S;
         let mut flash_freed = 0;
-        let start = match self.state.get() {
-            State::None => 0,
-            State::GarbageCollect(state) => match state {
-                RubbishState::ReadRegion(reg, ff) => {
-                    flash_freed += ff;
-                    reg
-                }
-                // We already erased region reg, so move to the next one
-                RubbishState::EraseRegion(reg, ff) => {
-                    flash_freed += ff;
-                    reg + 1
-                }
-            },
-            _ => unreachable!(),
-        };
+        let start =
+            match self.state.get() {
+                State::None => 0,
+                State::GarbageCollect(state) => match state {
+                    RubbishState::ReadRegion(reg, ff) => {
+                        flash_freed += ff;
+                        reg
+                    }
+                    // We already erased region reg, record that and move to the
+                    // next one
+                    RubbishState::EraseRegion(reg, ff, erase_count) => {
+                        flash_freed += ff;
+                        if let Err(e) = self.write_wear_record(reg, erase_count.saturating_add(1)) {
+                            if let ErrorCode::WriteNotReady(_) = e {
+                                self.state.set(State::GarbageCollect(
+                                    RubbishState::WriteWearRecord(reg, flash_freed),
+                                ));
+                            }
+                            return Err(e);
+                        }
+                        reg + 1
+                    }
+                    RubbishState::WriteWearRecord(reg, ff) => {
+                        flash_freed += ff;
+                        reg + 1
+                    }
+                },
+                _ => unreachable!(),
+            };
 
         for i in start..num_region {
             match self.garbage_collect_region(i, flash_freed) {
@@ -1129,4 +1570,692 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
 
         Ok(flash_freed)
     }
+
+    /// Get the number of regions used by TicKV.
+    pub fn region_count(&self) -> usize {
+        self.flash_size / S
+    }
+
+    /// Read `region` into the read buffer, unless the read has already
+    /// completed. `state` is the state to resume when the read is not ready.
+    fn read_region_for(&self, region: usize, state: State) -> Result<&'a mut [u8; S], ErrorCode> {
+        let region_data = self.read_buffer.take().unwrap();
+        if self.state.get() != state {
+            if let Err(e) = self.controller.read_region(region, 0, region_data) {
+                self.read_buffer.replace(Some(region_data));
+                if let ErrorCode::ReadNotReady(_) = e {
+                    self.state.set(state);
+                }
+                return Err(e);
+            }
+        }
+        Ok(region_data)
+    }
+
+    /// Find the next valid key in flash storage.
+    ///
+    /// Keys are returned in the order they are stored in flash. Pass
+    /// `KeyCursor::default()` to find the first key, and the `next` cursor
+    /// of the returned key to find the key after it. The main key is
+    /// returned like any other key, keys staged by an open transaction are
+    /// not returned until it is committed.
+    ///
+    /// `cursor`: The position to start looking from.
+    ///
+    /// On success the key will be returned, or `None` if there are no more
+    /// keys.
+    /// On error a `ErrorCode` will be returned.
+    pub fn next_key(&self, cursor: KeyCursor) -> Result<Option<KeyInfo>, ErrorCode> {
+        let mut region = match self.state.get() {
+            State::None => cursor.region,
+            State::NextKey(key_state) => match key_state {
+                KeyState::ReadRegion(reg) => reg,
+            },
+            _ => unreachable!(),
+        };
+
+        while region < self.region_count() {
+            let region_data =
+                self.read_region_for(region, State::NextKey(KeyState::ReadRegion(region)))?;
+
+            // Keys before the cursor have already been returned
+            let mut start = if region == cursor.region {
+                cursor.offset
+            } else {
+                0
+            };
+            let mut object = find_valid_object(region_data, start, false);
+            while let Ok(Some(marker)) = object {
+                if !marker.is_transaction_marker() {
+                    break;
+                }
+                start = marker.offset + marker.length;
+                object = find_valid_object(region_data, start, false);
+            }
+            self.read_buffer.replace(Some(region_data));
+            self.state.set(State::None);
+
+            if let Some(object) = object? {
+                return Ok(Some(KeyInfo {
+                    hashed_key: object.hashed_key,
+                    value_length: object.length - HEADER_LENGTH - CHECK_SUM_LEN,
+                    region,
+                    next: KeyCursor {
+                        region,
+                        offset: object.offset + object.length,
+                    },
+                }));
+            }
+
+            region += 1;
+        }
+
+        Ok(None)
+    }
+
+    /// Get the usage and wear of a region.
+    ///
+    /// `region`: The region number, less than `region_count()`.
+    ///
+    /// On success the statistics of the region will be returned.
+    /// On error a `ErrorCode` will be returned, `ReadFail` if the region
+    /// doesn't exist.
+    pub fn region_stats(&self, region: usize) -> Result<RegionStats, ErrorCode> {
+        if region >= self.region_count() {
+            return Err(ErrorCode::ReadFail);
+        }
+
+        let region_data =
+            self.read_region_for(region, State::RegionStats(KeyState::ReadRegion(region)))?;
+        let stats = count_objects(region_data);
+        self.read_buffer.replace(Some(region_data));
+        self.state.set(State::None);
+
+        stats
+    }
+
+    /// Move the keys out of the most worn region and erase it.
+    ///
+    /// The region is chosen from the regions that contain keys. The region
+    /// erased the most times is chosen, or if several have been erased as
+    /// often the one with the most reclaimable bytes. Each key is appended
+    /// to another region and then invalidated, so no key is lost on a power
+    /// loss. Finally the region is erased, reclaiming the space used by
+    /// invalid objects even though the region contained valid keys.
+    ///
+    /// `buf`: A buffer to copy values to. It must be large enough for the
+    ///        largest value in the region.
+    ///
+    /// On success the number of the region that was erased will be returned.
+    /// On error a `ErrorCode` will be returned, `KeyNotFound` if there are
+    /// no keys to move and `TransactionInProgress` if a transaction is open,
+    /// as staged keys aren't moved.
+    pub fn defragment(&self, buf: &mut [u8]) -> Result<usize, ErrorCode> {
+        let mut step = match self.state.get() {
+            State::None if self.transaction.get() => return Err(ErrorCode::TransactionInProgress),
+            State::None => DefragState::FindRegion(0, None),
+            State::Defragment(defrag_state) => match defrag_state {
+                DefragState::KeyMoved(region, offset) => DefragState::InvalidateKey(region, offset),
+                DefragState::KeyInvalidated(region) => DefragState::ReadRegion(region),
+                DefragState::EraseRegion(region, erase_count) => {
+                    return self.finish_defragment(region, erase_count)
+                }
+                DefragState::WriteWearRecord(region) => {
+                    self.state.set(State::None);
+                    return Ok(region);
+                }
+                // A read has completed
+                defrag_state => defrag_state,
+            },
+            _ => unreachable!(),
+        };
+
+        loop {
+            step = match step {
+                DefragState::FindRegion(region, best) => {
+                    if region >= self.region_count() {
+                        match best {
+                            Some(candidate) => DefragState::ReadRegion(candidate.region),
+                            None => {
+                                self.state.set(State::None);
+                                return Err(ErrorCode::KeyNotFound);
+                            }
+                        }
+                    } else {
+                        let region_data = self.read_region_for(region, State::Defragment(step))?;
+                        let stats = count_objects(region_data);
+                        self.read_buffer.replace(Some(region_data));
+                        let stats = stats.map_err(|e| {
+                            self.state.set(State::None);
+                            e
+                        })?;
+
+                        let better = match best {
+                            _ if stats.live_keys == 0 => false,
+                            None => true,
+                            Some(candidate) => {
+                                (stats.erase_count, stats.reclaimable_bytes)
+                                    > (candidate.erase_count, candidate.reclaimable_bytes)
+                            }
+                        };
+                        let best = if better {
+                            Some(Candidate {
+                                region,
+                                erase_count: stats.erase_count,
+                                reclaimable_bytes: stats.reclaimable_bytes,
+                            })
+                        } else {
+                            best
+                        };
+                        DefragState::FindRegion(region + 1, best)
+                    }
+                }
+                DefragState::ReadRegion(region) => {
+                    let region_data = self.read_region_for(region, State::Defragment(step))?;
+                    let object = find_valid_object(region_data, 0, false).and_then(|object| {
+                        if let Some(object) = object {
+                            check_object(region_data, &object)?;
+                            let value_length = object.length - HEADER_LENGTH - CHECK_SUM_LEN;
+                            let value = region_data
+                                .get((object.offset + HEADER_LENGTH)..)
+                                .and_then(|value| value.get(..value_length))
+                                .ok_or(ErrorCode::CorruptData)?;
+                            buf.get_mut(..value_length)
+                                .ok_or(ErrorCode::BufferTooSmall(value_length))?
+                                .copy_from_slice(value);
+                        }
+                        Ok(object)
+                    });
+                    let erase_count = erase_count(region_data);
+                    self.read_buffer.replace(Some(region_data));
+
+                    match object {
+                        Ok(Some(object)) => DefragState::MoveKey {
+                            region,
+                            offset: object.offset,
+                            hash: object.hashed_key,
+                            length: object.length - HEADER_LENGTH - CHECK_SUM_LEN,
+                            target: None,
+                        },
+                        Ok(None) => {
+                            // All of the keys have been moved, erase the region
+                            if let Err(e) = self.controller.erase_region(region) {
+                                self.state.set(match e {
+                                    ErrorCode::EraseNotReady(_) => State::Defragment(
+                                        DefragState::EraseRegion(region, erase_count),
+                                    ),
+                                    _ => State::None,
+                                });
+                                return Err(e);
+                            }
+                            return self.finish_defragment(region, erase_count);
+                        }
+                        Err(e) => {
+                            self.state.set(State::None);
+                            return Err(e);
+                        }
+                    }
+                }
+                DefragState::MoveKey {
+                    region,
+                    offset,
+                    hash,
+                    length,
+                    target,
+                } => {
+                    // Continue the append if it is waiting for a read
+                    self.state.set(match target {
+                        Some(target) if self.state.get() == State::Defragment(step) => {
+                            State::AppendKey(KeyState::ReadRegion(target))
+                        }
+                        _ => State::None,
+                    });
+
+                    match self.append_object(hash, &buf[..length], Some(region), FLAGS_VALID) {
+                        Ok(SuccessCode::Queued) => {
+                            self.state
+                                .set(State::Defragment(DefragState::KeyMoved(region, offset)));
+                            return Err(ErrorCode::WriteNotReady(S * region + offset));
+                        }
+                        // A copy of the key from an earlier defragmentation
+                        // is already stored in another region.
+                        Ok(_) | Err(ErrorCode::KeyAlreadyExists) => {
+                            DefragState::InvalidateKey(region, offset)
+                        }
+                        Err(ErrorCode::ReadNotReady(reg)) => {
+                            self.state.set(State::Defragment(DefragState::MoveKey {
+                                region,
+                                offset,
+                                hash,
+                                length,
+                                target: Some(reg),
+                            }));
+                            return Err(ErrorCode::ReadNotReady(reg));
+                        }
+                        Err(e) => {
+                            self.state.set(State::None);
+                            return Err(e);
+                        }
+                    }
+                }
+                DefragState::InvalidateKey(region, offset) => {
+                    let region_data = self.read_region_for(region, State::Defragment(step))?;
+                    let ret = match region_data.get_mut(offset + LEN_OFFSET) {
+                        Some(flags) => {
+                            *flags &= !0x80;
+                            self.controller
+                                .write(S * region + offset + LEN_OFFSET, &[*flags])
+                        }
+                        None => Err(ErrorCode::CorruptData),
+                    };
+                    self.read_buffer.replace(Some(region_data));
+
+                    match ret {
+                        Ok(()) => DefragState::ReadRegion(region),
+                        Err(e) => {
+                            self.state.set(match e {
+                                ErrorCode::WriteNotReady(_) => {
+                                    State::Defragment(DefragState::KeyInvalidated(region))
+                                }
+                                _ => State::None,
+                            });
+                            return Err(e);
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            };
+        }
+    }
+
+    /// Write the wear record of the region emptied by `defragment()`.
+    fn finish_defragment(&self, region: usize, erase_count: u32) -> Result<usize, ErrorCode> {
+        match self.write_wear_record(region, erase_count.saturating_add(1)) {
+            Ok(()) => {
+                self.state.set(State::None);
+                Ok(region)
+            }
+            Err(e) => {
+                self.state.set(match e {
+                    ErrorCode::WriteNotReady(_) => {
+                        State::Defragment(DefragState::WriteWearRecord(region))
+                    }
+                    _ => State::None,
+                });
+                Err(e)
+            }
+        }
+    }
+
+    /// Begin a transaction.
+    ///
+    /// The keys staged with `stage_key()` and `stage_invalidate()` while the
+    /// transaction is open take effect together when `commit_transaction()`
+    /// completes. Until then `get_key()` and `next_key()` only return the
+    /// committed keys. If power is lost before the commit marker has been
+    /// written `initialise()` discards the staged keys, otherwise it
+    /// finishes the commit.
+    ///
+    /// Only one transaction can be open at a time.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned, `TransactionInProgress` if a
+    /// transaction is already open.
+    pub fn begin_transaction(&self) -> Result<SuccessCode, ErrorCode> {
+        if self.transaction.get() {
+            return Err(ErrorCode::TransactionInProgress);
+        }
+        self.transaction_step(TransactionState::AppendMarker {
+            hash: TRANSACTION_OPEN_HASH,
+            target: None,
+        })
+    }
+
+    /// Stage a key/value pair in the open transaction. When the transaction
+    /// is committed it replaces the value stored for the key, if there is
+    /// one.
+    ///
+    /// `hash`: A hashed key.
+    /// `value`: A buffer containing the data to be stored to flash.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned, `NoTransaction` if no
+    /// transaction is open and `KeyAlreadyExists` if the key has already
+    /// been staged.
+    pub fn stage_key(&self, hash: u64, value: &[u8]) -> Result<SuccessCode, ErrorCode> {
+        if !self.transaction.get() {
+            return Err(ErrorCode::NoTransaction);
+        }
+        self.append_object(hash, value, None, FLAGS_VALID | FLAGS_PENDING)
+    }
+
+    /// Stage the invalidation of a key in the open transaction. The key is
+    /// invalidated when the transaction is committed.
+    ///
+    /// `hash`: A hashed key.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned, `NoTransaction` if no
+    /// transaction is open and `KeyAlreadyExists` if the key has already
+    /// been staged.
+    pub fn stage_invalidate(&self, hash: u64) -> Result<SuccessCode, ErrorCode> {
+        if !self.transaction.get() {
+            return Err(ErrorCode::NoTransaction);
+        }
+        self.append_object(hash, &[], None, FLAGS_VALID | FLAGS_PENDING | FLAGS_DELETE)
+    }
+
+    /// Commit the open transaction.
+    ///
+    /// The commit marker is written first, from then on the transaction is
+    /// committed even if power is lost. Then every region is read to
+    /// replace the committed keys with the staged ones.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned, `NoTransaction` if no
+    /// transaction is open. The transaction is still open after an error,
+    /// so the commit can be retried.
+    pub fn commit_transaction(&self) -> Result<SuccessCode, ErrorCode> {
+        if !self.transaction.get() {
+            return Err(ErrorCode::NoTransaction);
+        }
+        self.transaction_step(TransactionState::AppendMarker {
+            hash: TRANSACTION_COMMIT_HASH,
+            target: None,
+        })
+    }
+
+    /// Discard the keys staged in the open transaction.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned, `NoTransaction` if no
+    /// transaction is open. The transaction is still open after an error.
+    pub fn abort_transaction(&self) -> Result<SuccessCode, ErrorCode> {
+        if !self.transaction.get() {
+            return Err(ErrorCode::NoTransaction);
+        }
+        self.transaction_step(TransactionState::FindPending {
+            commit: false,
+            region: 0,
+            offset: 0,
+        })
+    }
+
+    /// Continue the transaction operation waiting for the flash.
+    pub(crate) fn continue_transaction(&self) -> Result<SuccessCode, ErrorCode> {
+        let step = match self.state.get() {
+            State::Transaction(step) => match step {
+                TransactionState::MarkerWritten(hash) => TransactionState::marker_written(hash),
+                TransactionState::KeyReplaced(region, offset) => TransactionState::Resolve {
+                    commit: true,
+                    region,
+                    offset,
+                },
+                TransactionState::Resolved {
+                    commit,
+                    region,
+                    offset,
+                } => TransactionState::FindPending {
+                    commit,
+                    region,
+                    offset,
+                },
+                TransactionState::MarkerRemoved(hash) => TransactionState::marker_removed(hash),
+                // A read has completed
+                step => step,
+            },
+            _ => unreachable!(),
+        };
+
+        self.transaction_step(step)
+    }
+
+    /// Set the state of the key operation run by a transaction `step`, so
+    /// that it continues with the read of `target` if the step was waiting
+    /// for it.
+    fn resume_key_state(
+        &self,
+        step: TransactionState,
+        target: Option<usize>,
+        key_state: fn(KeyState) -> State,
+    ) {
+        self.state.set(match target {
+            Some(target) if self.state.get() == State::Transaction(step) => {
+                key_state(KeyState::ReadRegion(target))
+            }
+            _ => State::None,
+        });
+    }
+
+    /// Run a transaction from `step` until it has to wait for the flash.
+    fn transaction_step(&self, step: TransactionState) -> Result<SuccessCode, ErrorCode> {
+        let ret = self.run_transaction(step);
+        match ret {
+            Err(ErrorCode::ReadNotReady(_))
+            | Err(ErrorCode::WriteNotReady(_))
+            | Err(ErrorCode::EraseNotReady(_)) => {}
+            _ => self.state.set(State::None),
+        }
+        ret
+    }
+
+    fn run_transaction(&self, mut step: TransactionState) -> Result<SuccessCode, ErrorCode> {
+        loop {
+            step = match step {
+                TransactionState::Recover { commit, target } => {
+                    let hash = if commit {
+                        TRANSACTION_COMMIT_HASH
+                    } else {
+                        TRANSACTION_OPEN_HASH
+                    };
+                    self.resume_key_state(step, target, State::GetKey);
+
+                    match self.get_key(hash, &mut []) {
+                        Ok(_) => TransactionState::FindPending {
+                            commit,
+                            region: 0,
+                            offset: 0,
+                        },
+                        // A commit marker that wasn't completely written
+                        // doesn't commit the transaction.
+                        Err(ErrorCode::KeyNotFound) | Err(ErrorCode::InvalidCheckSum) if commit => {
+                            TransactionState::Recover {
+                                commit: false,
+                                target: None,
+                            }
+                        }
+                        Err(ErrorCode::InvalidCheckSum) => TransactionState::FindPending {
+                            commit: false,
+                            region: 0,
+                            offset: 0,
+                        },
+                        Err(ErrorCode::KeyNotFound) => TransactionState::Finished,
+                        Err(ErrorCode::ReadNotReady(reg)) => {
+                            self.state
+                                .set(State::Transaction(TransactionState::Recover {
+                                    commit,
+                                    target: Some(reg),
+                                }));
+                            return Err(ErrorCode::ReadNotReady(reg));
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                TransactionState::AppendMarker { hash, target } => {
+                    self.resume_key_state(step, target, State::AppendKey);
+
+                    match self.append_object(hash, &[], None, FLAGS_VALID) {
+                        Ok(SuccessCode::Queued) => {
+                            self.state
+                                .set(State::Transaction(TransactionState::MarkerWritten(hash)));
+                            return Err(ErrorCode::WriteNotReady(S * self.get_region(hash)));
+                        }
+                        Ok(_) => TransactionState::marker_written(hash),
+                        Err(ErrorCode::ReadNotReady(reg)) => {
+                            self.state
+                                .set(State::Transaction(TransactionState::AppendMarker {
+                                    hash,
+                                    target: Some(reg),
+                                }));
+                            return Err(ErrorCode::ReadNotReady(reg));
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                TransactionState::FindPending {
+                    commit,
+                    region,
+                    offset,
+                } => {
+                    if region >= self.region_count() {
+                        TransactionState::RemoveMarker {
+                            hash: TRANSACTION_COMMIT_HASH,
+                            target: None,
+                        }
+                    } else {
+                        let region_data = self.read_region_for(region, State::Transaction(step))?;
+                        let object = find_valid_object(region_data, offset, true);
+                        self.read_buffer.replace(Some(region_data));
+                        self.state.set(State::None);
+
+                        match object? {
+                            Some(object) if commit => TransactionState::ReplaceKey {
+                                region,
+                                offset: object.offset,
+                                hash: object.hashed_key,
+                                target: None,
+                            },
+                            Some(object) => TransactionState::Resolve {
+                                commit,
+                                region,
+                                offset: object.offset,
+                            },
+                            None => TransactionState::FindPending {
+                                commit,
+                                region: region + 1,
+                                offset: 0,
+                            },
+                        }
+                    }
+                }
+                TransactionState::ReplaceKey {
+                    region,
+                    offset,
+                    hash,
+                    target,
+                } => {
+                    self.resume_key_state(step, target, State::InvalidateKey);
+
+                    match self.invalidate_key(hash) {
+                        Ok(SuccessCode::Queued) => {
+                            self.state
+                                .set(State::Transaction(TransactionState::KeyReplaced(
+                                    region, offset,
+                                )));
+                            return Err(ErrorCode::WriteNotReady(S * region + offset));
+                        }
+                        // The key is new, or it was replaced before a power
+                        // loss.
+                        Ok(_) | Err(ErrorCode::KeyNotFound) => TransactionState::Resolve {
+                            commit: true,
+                            region,
+                            offset,
+                        },
+                        Err(ErrorCode::ReadNotReady(reg)) => {
+                            self.state
+                                .set(State::Transaction(TransactionState::ReplaceKey {
+                                    region,
+                                    offset,
+                                    hash,
+                                    target: Some(reg),
+                                }));
+                            return Err(ErrorCode::ReadNotReady(reg));
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                TransactionState::Resolve {
+                    commit,
+                    region,
+                    offset,
+                } => {
+                    let region_data = self.read_region_for(region, State::Transaction(step))?;
+                    self.state.set(State::None);
+
+                    let ret = match read_object(region_data, offset) {
+                        Ok(Some(object)) => {
+                            // Committing a staged key clears its pending
+                            // flag, anything else is invalidated.
+                            let flag = if commit && !object.delete {
+                                FLAGS_PENDING
+                            } else {
+                                FLAGS_VALID
+                            };
+                            let flags = region_data[offset + LEN_OFFSET] & !(flag << 4);
+                            region_data[offset + LEN_OFFSET] = flags;
+                            Ok((
+                                offset + object.length,
+                                self.controller
+                                    .write(S * region + offset + LEN_OFFSET, &[flags]),
+                            ))
+                        }
+                        Ok(None) => Err(ErrorCode::CorruptData),
+                        Err(e) => Err(e),
+                    };
+                    self.read_buffer.replace(Some(region_data));
+
+                    let (next, write) = ret?;
+                    match write {
+                        Ok(()) => TransactionState::FindPending {
+                            commit,
+                            region,
+                            offset: next,
+                        },
+                        Err(ErrorCode::WriteNotReady(address)) => {
+                            self.state
+                                .set(State::Transaction(TransactionState::Resolved {
+                                    commit,
+                                    region,
+                                    offset: next,
+                                }));
+                            return Err(ErrorCode::WriteNotReady(address));
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                TransactionState::RemoveMarker { hash, target } => {
+                    self.resume_key_state(step, target, State::InvalidateKey);
+
+                    match self.invalidate_key(hash) {
+                        Ok(SuccessCode::Queued) => {
+                            self.state
+                                .set(State::Transaction(TransactionState::MarkerRemoved(hash)));
+                            return Err(ErrorCode::WriteNotReady(S * self.get_region(hash)));
+                        }
+                        Ok(_) | Err(ErrorCode::KeyNotFound) => {
+                            TransactionState::marker_removed(hash)
+                        }
+                        Err(ErrorCode::ReadNotReady(reg)) => {
+                            self.state
+                                .set(State::Transaction(TransactionState::RemoveMarker {
+                                    hash,
+                                    target: Some(reg),
+                                }));
+                            return Err(ErrorCode::ReadNotReady(reg));
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                TransactionState::Begun => {
+                    self.transaction.set(true);
+                    return Ok(SuccessCode::Complete);
+                }
+                TransactionState::Finished => {
+                    self.transaction.set(false);
+                    return Ok(SuccessCode::Complete);
+                }
+                _ => unreachable!(),
+            };
+        }
+    }
 }
@@ -140,6 +140,12 @@ pub fn parse_tbf_header(
                     types::TbfHeaderV2StoragePermissions<8>,
                 > = None;
                 let mut kernel_version: Option<types::TbfHeaderV2KernelVersion> = None;
+                let mut real_time: Option<types::TbfHeaderV2RealTime> = None;
+                let mut memory_quota: Option<types::TbfHeaderV2MemoryQuota> = None;
+                let mut ipc_clients: Option<
+                    types::TbfHeaderV2IpcClients<{ types::NUM_IPC_CLIENTS }>,
+                > = None;
+                let mut fault_policy: Option<types::TbfHeaderV2FaultPolicy> = None;
 
                 // Iterate the remainder of the header looking for TLV entries.
                 while remaining.len() > 0 {
@@ -281,6 +287,63 @@ pub fn parse_tbf_header(
                             }
                         }
 
+                        types::TbfHeaderTypes::TbfHeaderRealTime => {
+                            let entry_len = mem::size_of::<types::TbfHeaderV2RealTime>();
+                            if tlv_header.length as usize == entry_len {
+                                real_time = Some(
+                                    remaining
+                                        .get(0..entry_len)
+                                        .ok_or(types::TbfParseError::NotEnoughFlash)?
+                                        .try_into()?,
+                                );
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
+                        types::TbfHeaderTypes::TbfHeaderMemoryQuota => {
+                            let entry_len = mem::size_of::<types::TbfHeaderV2MemoryQuota>();
+                            if tlv_header.length as usize == entry_len {
+                                memory_quota = Some(
+                                    remaining
+                                        .get(0..entry_len)
+                                        .ok_or(types::TbfParseError::NotEnoughFlash)?
+                                        .try_into()?,
+                                );
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
+                        types::TbfHeaderTypes::TbfHeaderIpcClients => {
+                            ipc_clients = Some(
+                                remaining
+                                    .get(0..tlv_header.length as usize)
+                                    .ok_or(types::TbfParseError::NotEnoughFlash)?
+                                    .try_into()?,
+                            );
+                        }
+
+                        types::TbfHeaderTypes::TbfHeaderFaultPolicy => {
+                            let entry_len = mem::size_of::<types::TbfHeaderV2FaultPolicy>();
+                            if tlv_header.length as usize == entry_len {
+                                fault_policy = Some(
+                                    remaining
+                                        .get(0..entry_len)
+                                        .ok_or(types::TbfParseError::NotEnoughFlash)?
+                                        .try_into()?,
+                                );
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
                         _ => {}
                     }
 
@@ -302,6 +365,10 @@ pub fn parse_tbf_header(
                     permissions: permissions_pointer,
                     storage_permissions: storage_permissions_pointer,
                     kernel_version: kernel_version,
+                    real_time: real_time,
+                    memory_quota: memory_quota,
+                    ipc_clients: ipc_clients,
+                    fault_policy: fault_policy,
                 };
 
                 Ok(types::TbfHeader::TbfHeaderV2(tbf_header))
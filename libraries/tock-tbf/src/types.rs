@@ -12,6 +12,10 @@ use core::mem::size_of;
 /// and modify. This simplification enables us to use fixed sized buffers.
 const NUM_STORAGE_PERMISSIONS: usize = 8;
 
+/// We only support up to a fixed number of IPC clients in the allow-list of a
+/// service. This simplification enables us to use fixed sized buffers.
+pub const NUM_IPC_CLIENTS: usize = 8;
+
 /// Error when parsing just the beginning of the TBF header. This is only used
 /// when establishing the linked list structure of apps installed in flash.
 pub enum InitialTbfParseError {
@@ -120,6 +124,38 @@ pub struct TbfHeaderV2Base {
     pub(crate) checksum: u32,
 }
 
+impl TbfHeaderV2Base {
+    /// Create the base header of a padding "app" which covers `total_size`
+    /// bytes of flash. A padding header has no TLV entries, so the kernel
+    /// skips over it when looking for processes.
+    pub fn new_padding(total_size: u32) -> TbfHeaderV2Base {
+        let version: u16 = 2;
+        let header_size: u16 = 16;
+        let flags: u32 = 0;
+        // The checksum is the XOR of all other 4 byte words in the header.
+        let checksum = (u32::from(header_size) << 16 | u32::from(version)) ^ total_size ^ flags;
+        TbfHeaderV2Base {
+            version,
+            header_size,
+            total_size,
+            flags,
+            checksum,
+        }
+    }
+
+    /// Serialize the base header into the little-endian layout used in
+    /// flash.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[0..2].copy_from_slice(&self.version.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.header_size.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.total_size.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+}
+
 /// Types in TLV structures for each optional block of the header.
 #[derive(Clone, Copy, Debug)]
 pub enum TbfHeaderTypes {
@@ -131,6 +167,10 @@ pub enum TbfHeaderTypes {
     TbfHeaderStoragePermissions = 7,
     TbfHeaderKernelVersion = 8,
     TbfHeaderProgram = 9,
+    TbfHeaderRealTime = 10,
+    TbfHeaderMemoryQuota = 11,
+    TbfHeaderIpcClients = 12,
+    TbfHeaderFaultPolicy = 13,
     TbfFooterCredentials = 128,
 
     /// Some field in the header that we do not understand. Since the TLV format
@@ -242,6 +282,36 @@ pub struct TbfHeaderV2KernelVersion {
     minor: u16,
 }
 
+/// The period and execution budget of a periodic real-time process.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2RealTime {
+    period_us: u32,
+    budget_us: u32,
+}
+
+/// How many bytes a process may grow its heap by and allocate for grants.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2MemoryQuota {
+    heap_size: u32,
+    grant_size: u32,
+}
+
+/// The `ShortID`s of the processes that may use a process as an IPC service.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2IpcClients<const L: usize> {
+    length: u16,
+    client_ids: [u32; L],
+}
+
+/// How the kernel should respond when a process faults.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2FaultPolicy {
+    policy: u32,
+    initial_delay_ms: u32,
+    max_delay_ms: u32,
+    max_restarts: u32,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum TbfFooterV2CredentialsType {
     Reserved = 0,
@@ -250,6 +320,8 @@ pub enum TbfFooterV2CredentialsType {
     SHA256 = 3,
     SHA384 = 4,
     SHA512 = 5,
+    EcdsaNistP256 = 6,
+    Ed25519 = 7,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -320,6 +392,10 @@ impl core::convert::TryFrom<u16> for TbfHeaderTypes {
             7 => Ok(TbfHeaderTypes::TbfHeaderStoragePermissions),
             8 => Ok(TbfHeaderTypes::TbfHeaderKernelVersion),
             9 => Ok(TbfHeaderTypes::TbfHeaderProgram),
+            10 => Ok(TbfHeaderTypes::TbfHeaderRealTime),
+            11 => Ok(TbfHeaderTypes::TbfHeaderMemoryQuota),
+            12 => Ok(TbfHeaderTypes::TbfHeaderIpcClients),
+            13 => Ok(TbfHeaderTypes::TbfHeaderFaultPolicy),
             128 => Ok(TbfHeaderTypes::TbfFooterCredentials),
             _ => Ok(TbfHeaderTypes::Unknown),
         }
@@ -601,6 +677,102 @@ impl core::convert::TryFrom<&[u8]> for TbfHeaderV2KernelVersion {
     }
 }
 
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2RealTime {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2RealTime, Self::Error> {
+        Ok(TbfHeaderV2RealTime {
+            period_us: u32::from_le_bytes(
+                b.get(0..4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            budget_us: u32::from_le_bytes(
+                b.get(4..8)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+        })
+    }
+}
+
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2MemoryQuota {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2MemoryQuota, Self::Error> {
+        Ok(TbfHeaderV2MemoryQuota {
+            heap_size: u32::from_le_bytes(
+                b.get(0..4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            grant_size: u32::from_le_bytes(
+                b.get(4..8)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+        })
+    }
+}
+
+impl<const L: usize> core::convert::TryFrom<&[u8]> for TbfHeaderV2IpcClients<L> {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2IpcClients<L>, Self::Error> {
+        let length = u16::from_le_bytes(
+            b.get(0..2)
+                .ok_or(TbfParseError::NotEnoughFlash)?
+                .try_into()?,
+        );
+
+        let mut client_ids: [u32; L] = [0; L];
+        for i in 0..length as usize {
+            let start = 2 + (i * size_of::<u32>());
+            let end = start + size_of::<u32>();
+            if let Some(client_id) = client_ids.get_mut(i) {
+                *client_id = u32::from_le_bytes(
+                    b.get(start..end)
+                        .ok_or(TbfParseError::NotEnoughFlash)?
+                        .try_into()?,
+                );
+            } else {
+                return Err(TbfParseError::BadTlvEntry(
+                    TbfHeaderTypes::TbfHeaderIpcClients as usize,
+                ));
+            }
+        }
+
+        Ok(TbfHeaderV2IpcClients { length, client_ids })
+    }
+}
+
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2FaultPolicy {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2FaultPolicy, Self::Error> {
+        let field = |i: usize| -> Result<u32, TbfParseError> {
+            Ok(u32::from_le_bytes(
+                b.get(i * 4..(i + 1) * 4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ))
+        };
+        let policy = field(0)?;
+        // Only stop (0) and restart with backoff (1) are defined.
+        if policy > 1 {
+            return Err(TbfParseError::BadTlvEntry(
+                TbfHeaderTypes::TbfHeaderFaultPolicy as usize,
+            ));
+        }
+        Ok(TbfHeaderV2FaultPolicy {
+            policy,
+            initial_delay_ms: field(1)?,
+            max_delay_ms: field(2)?,
+            max_restarts: field(3)?,
+        })
+    }
+}
+
 impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
     type Error = TbfParseError;
 
@@ -617,6 +789,8 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
             3 => TbfFooterV2CredentialsType::SHA256,
             4 => TbfFooterV2CredentialsType::SHA384,
             5 => TbfFooterV2CredentialsType::SHA512,
+            6 => TbfFooterV2CredentialsType::EcdsaNistP256,
+            7 => TbfFooterV2CredentialsType::Ed25519,
             _ => {
                 return Err(TbfParseError::InternalError);
             }
@@ -628,6 +802,8 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
             TbfFooterV2CredentialsType::SHA256 => 32,
             TbfFooterV2CredentialsType::SHA384 => 48,
             TbfFooterV2CredentialsType::SHA512 => 64,
+            TbfFooterV2CredentialsType::EcdsaNistP256 => 64,
+            TbfFooterV2CredentialsType::Ed25519 => 64,
         };
         let data = &b
             .get(4..(length + 4))
@@ -669,6 +845,10 @@ pub struct TbfHeaderV2 {
     pub(crate) permissions: Option<TbfHeaderV2Permissions<8>>,
     pub(crate) storage_permissions: Option<TbfHeaderV2StoragePermissions<NUM_STORAGE_PERMISSIONS>>,
     pub(crate) kernel_version: Option<TbfHeaderV2KernelVersion>,
+    pub(crate) real_time: Option<TbfHeaderV2RealTime>,
+    pub(crate) memory_quota: Option<TbfHeaderV2MemoryQuota>,
+    pub(crate) ipc_clients: Option<TbfHeaderV2IpcClients<NUM_IPC_CLIENTS>>,
+    pub(crate) fault_policy: Option<TbfHeaderV2FaultPolicy>,
 }
 
 /// Type that represents the fields of the Tock Binary Format header.
@@ -931,6 +1111,64 @@ impl TbfHeader {
         }
     }
 
+    /// Get the period and the execution budget per period, both in
+    /// microseconds, of a periodic real-time process. Returns `None` if the
+    /// real-time header is not included.
+    pub fn get_real_time_parameters(&self) -> Option<(u32, u32)> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => match hd.real_time {
+                Some(real_time) => Some((real_time.period_us, real_time.budget_us)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Get the number of bytes the process asks to be allowed to grow its heap
+    /// by and to allocate for grants. Returns `None` if the memory quota
+    /// header is not included.
+    pub fn get_memory_quota(&self) -> Option<(u32, u32)> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => match hd.memory_quota {
+                Some(memory_quota) => Some((memory_quota.heap_size, memory_quota.grant_size)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Get the number of valid IPC client ids and the ids of the processes
+    /// that may use this process as an IPC service. Returns `None` if the IPC
+    /// clients header is not included.
+    pub fn get_ipc_client_ids(&self) -> Option<(usize, [u32; NUM_IPC_CLIENTS])> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => match hd.ipc_clients {
+                Some(clients) => Some((clients.length.into(), clients.client_ids)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Get the fault behavior the process requests, as `(policy,
+    /// initial_delay_ms, max_delay_ms, max_restarts)`. `policy` is 0 to stop
+    /// the process and 1 to restart it with an exponential backoff. Returns
+    /// `None` if the fault policy header is not included.
+    pub fn get_fault_policy(&self) -> Option<(u32, u32, u32, u32)> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => match hd.fault_policy {
+                Some(fault_policy) => Some((
+                    fault_policy.policy,
+                    fault_policy.initial_delay_ms,
+                    fault_policy.max_delay_ms,
+                    fault_policy.max_restarts,
+                )),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     /// Return the offset where the binary ends in the TBF or 0 if there
     /// is no binary. If there is a Main header the end offset is the size
     /// of the TBF, while if there is a Program header it can be smaller.
@@ -952,3 +1190,114 @@ impl TbfHeader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::convert::TryFrom;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    /// A version 2 TBF header with the TLV entries `tlvs`, each given as its
+    /// type and body.
+    fn header(tlvs: &[(u16, &[u8])]) -> &'static [u8] {
+        let mut header = Vec::new();
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&[0; 14]);
+        for (tipe, body) in tlvs {
+            header.extend_from_slice(&tipe.to_le_bytes());
+            header.extend_from_slice(&(body.len() as u16).to_le_bytes());
+            header.extend_from_slice(body);
+            header.resize((header.len() + 3) & !3, 0);
+        }
+        let len = header.len() as u16;
+        header[2..4].copy_from_slice(&len.to_le_bytes());
+        header[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+        let checksum = header
+            .chunks_exact(4)
+            .fold(0, |c, w| c ^ u32::from_le_bytes(w.try_into().unwrap()));
+        header[12..16].copy_from_slice(&checksum.to_le_bytes());
+        Box::leak(header.into_boxed_slice())
+    }
+
+    /// Body of an IPC Clients TLV with `ids`, after the TLV header.
+    fn ipc_clients(count: u16, ids: &[u32]) -> ([u8; 64], usize) {
+        let mut body = [0; 64];
+        body[0..2].copy_from_slice(&count.to_le_bytes());
+        for (i, id) in ids.iter().enumerate() {
+            body[2 + 4 * i..6 + 4 * i].copy_from_slice(&id.to_le_bytes());
+        }
+        (body, 2 + 4 * ids.len())
+    }
+
+    #[test]
+    fn ipc_clients_are_parsed() {
+        let (body, len) = ipc_clients(3, &[0x10, 0x20, 0x30]);
+        let clients = TbfHeaderV2IpcClients::<NUM_IPC_CLIENTS>::try_from(&body[..len]).unwrap();
+        assert_eq!(clients.length, 3);
+        assert_eq!(clients.client_ids[..3], [0x10, 0x20, 0x30]);
+        assert_eq!(clients.client_ids[3..], [0; NUM_IPC_CLIENTS - 3]);
+    }
+
+    #[test]
+    fn ipc_clients_up_to_limit_are_parsed() {
+        let ids = [1, 2, 3, 4, 5, 6, 7, 8];
+        let (body, len) = ipc_clients(8, &ids);
+        let clients = TbfHeaderV2IpcClients::<NUM_IPC_CLIENTS>::try_from(&body[..len]).unwrap();
+        assert_eq!(clients.client_ids, ids);
+    }
+
+    #[test]
+    fn too_many_ipc_clients_are_rejected() {
+        let (body, len) = ipc_clients(9, &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert!(matches!(
+            TbfHeaderV2IpcClients::<NUM_IPC_CLIENTS>::try_from(&body[..len]),
+            Err(TbfParseError::BadTlvEntry(12))
+        ));
+    }
+
+    #[test]
+    fn truncated_ipc_clients_are_rejected() {
+        // The count promises three clients but the TLV ends within the
+        // second one.
+        let (body, _) = ipc_clients(3, &[0x10, 0x20, 0x30]);
+        assert!(matches!(
+            TbfHeaderV2IpcClients::<NUM_IPC_CLIENTS>::try_from(&body[..8]),
+            Err(TbfParseError::NotEnoughFlash)
+        ));
+        assert!(matches!(
+            TbfHeaderV2IpcClients::<NUM_IPC_CLIENTS>::try_from(&body[..1]),
+            Err(TbfParseError::NotEnoughFlash)
+        ));
+    }
+
+    #[test]
+    fn header_without_ipc_clients_has_no_list() {
+        let header = header(&[]);
+        let header = crate::parse::parse_tbf_header(header, 2).unwrap();
+        assert_eq!(header.get_ipc_client_ids(), None);
+    }
+
+    #[test]
+    fn header_with_ipc_clients_has_list() {
+        let (body, len) = ipc_clients(2, &[0x10, 0x20]);
+        let header = header(&[(12, &body[..len])]);
+        let header = crate::parse::parse_tbf_header(header, 2).unwrap();
+        assert_eq!(
+            header.get_ipc_client_ids(),
+            Some((2, [0x10, 0x20, 0, 0, 0, 0, 0, 0]))
+        );
+    }
+
+    #[test]
+    fn header_with_too_many_ipc_clients_is_rejected() {
+        let (body, len) = ipc_clients(9, &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let header = header(&[(12, &body[..len])]);
+        assert!(matches!(
+            crate::parse::parse_tbf_header(header, 2),
+            Err(TbfParseError::BadTlvEntry(12))
+        ));
+    }
+}
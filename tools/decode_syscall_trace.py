@@ -0,0 +1,210 @@
+#!/usr/bin/env python3
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2023.
+
+# Decodes a binary syscall trace into a readable timeline.
+#
+# The kernel's `syscall_trace` module records system calls, return values,
+# context switches and upcalls as 24-byte records, and the
+# `capsules_extra::syscall_trace` capsule sends each record as a line of the
+# form `#T <48 hex digits>`. This script reads a captured console or RTT log
+# (from a file or stdin), ignores every other line, and prints one line per
+# record. Gaps in the record sequence numbers are reported, as they mean the
+# kernel's trace buffer overflowed.
+#
+# Driver numbers are named using `capsules/core/src/driver.rs` when it can
+# be found relative to this script.
+#
+# Usage:
+#
+#     tools/decode_syscall_trace.py console.log
+#     tockloader listen | tools/decode_syscall_trace.py
+
+import argparse
+import os
+import re
+import struct
+import sys
+
+RECORD_LEN = 24
+LINE_PREFIX = "#T "
+
+SYSCALL_CLASSES = {
+    0: "yield",
+    1: "subscribe",
+    2: "command",
+    3: "allow-rw",
+    4: "allow-ro",
+    5: "memop",
+    6: "exit",
+    7: "allow-userspace-r",
+}
+
+RETURN_VARIANTS = {
+    0: "Failure",
+    1: "FailureU32",
+    2: "FailureU32U32",
+    3: "FailureU64",
+    128: "Success",
+    129: "SuccessU32",
+    130: "SuccessU32U32",
+    131: "SuccessU64",
+    132: "SuccessU32U32U32",
+    133: "SuccessU32U64",
+}
+
+# Number of data registers used by each return variant, after the error code
+# for failures.
+RETURN_ARGS = {0: 0, 1: 1, 2: 2, 3: 2, 128: 0, 129: 1, 130: 2, 131: 2, 132: 3, 133: 3}
+
+ERROR_CODES = {
+    1: "FAIL",
+    2: "BUSY",
+    3: "ALREADY",
+    4: "OFF",
+    5: "RESERVE",
+    6: "INVAL",
+    7: "SIZE",
+    8: "CANCEL",
+    9: "NOMEM",
+    10: "NOSUPPORT",
+    11: "NODEVICE",
+    12: "UNINSTALLED",
+    13: "NOACK",
+}
+
+SWITCH_OUT_REASONS = {
+    0: "syscall",
+    1: "fault",
+    2: "interrupted",
+    3: "error",
+}
+
+
+def load_driver_names():
+    """Read the driver number names from capsules/core/src/driver.rs."""
+    path = os.path.join(
+        os.path.dirname(os.path.abspath(__file__)),
+        "..",
+        "capsules",
+        "core",
+        "src",
+        "driver.rs",
+    )
+    names = {}
+    try:
+        with open(path) as f:
+            for line in f:
+                m = re.match(r"\s*(\w+)\s*=\s*(0x[0-9a-fA-F]+),", line)
+                if m:
+                    names[int(m.group(2), 16)] = m.group(1)
+    except OSError:
+        pass
+    return names
+
+
+class Decoder:
+    def __init__(self, driver_names):
+        self.driver_names = driver_names
+        self.next_sequence = None
+
+    def driver(self, num):
+        name = self.driver_names.get(num)
+        if name:
+            return "{:#x} {}".format(num, name)
+        return "{:#x}".format(num)
+
+    def syscall(self, subtype, args):
+        name = SYSCALL_CLASSES.get(subtype, "syscall-{}".format(subtype))
+        a0, a1, a2, a3 = args
+        if subtype == 0:
+            return "yield(which={}, {:#x}, {:#x})".format(a0, a1, a2)
+        if subtype == 1:
+            return "subscribe({}, {}, upcall={:#x}, appdata={:#x})".format(
+                self.driver(a0), a1, a2, a3
+            )
+        if subtype == 2:
+            return "command({}, {}, {:#x}, {:#x})".format(self.driver(a0), a1, a2, a3)
+        if subtype in (3, 4, 7):
+            return "{}({}, {}, addr={:#x}, len={})".format(
+                name, self.driver(a0), a1, a2, a3
+            )
+        if subtype == 5:
+            return "memop({}, {:#x})".format(a0, a1)
+        if subtype == 6:
+            return "exit(which={}, code={:#x})".format(a0, a1)
+        return "{}({:#x}, {:#x}, {:#x}, {:#x})".format(name, *args)
+
+    def syscall_return(self, args):
+        variant, rest = args[0], args[1:]
+        name = RETURN_VARIANTS.get(variant, "Variant{}".format(variant))
+        values = []
+        if variant < 128:
+            values.append(ERROR_CODES.get(rest[0], str(rest[0])))
+            rest = rest[1:]
+        values += ["{:#x}".format(r) for r in rest[: RETURN_ARGS.get(variant, 3)]]
+        return "  -> {}({})".format(name, ", ".join(values))
+
+    def decode(self, record):
+        kind, subtype, sequence, pid = struct.unpack_from("<BBHI", record, 0)
+        args = struct.unpack_from("<4I", record, 8)
+
+        out = []
+        if self.next_sequence is not None and sequence != self.next_sequence:
+            lost = (sequence - self.next_sequence) % 0x10000
+            out.append("        ... {} record(s) lost".format(lost))
+        self.next_sequence = (sequence + 1) % 0x10000
+
+        if kind == 1:
+            event = self.syscall(subtype, args)
+        elif kind == 2:
+            event = self.syscall_return(args)
+        elif kind == 3:
+            event = "== switch in"
+        elif kind == 4:
+            reason = SWITCH_OUT_REASONS.get(subtype, str(subtype))
+            event = "== switch out: {} after {} us".format(reason, args[0])
+        elif kind == 5:
+            event = "upcall {}:{} ({:#x}, {:#x}, {:#x})".format(
+                self.driver(args[0]), subtype, args[1], args[2], args[3]
+            )
+        else:
+            event = "unknown record kind {}: {}".format(kind, record.hex())
+
+        out.append("{:5} [{}] {}".format(sequence, pid, event))
+        return out
+
+
+def main():
+    parser = argparse.ArgumentParser(
+        description="Decode a Tock binary syscall trace into a timeline."
+    )
+    parser.add_argument(
+        "log",
+        nargs="?",
+        type=argparse.FileType("r", errors="replace"),
+        default=sys.stdin,
+        help="captured console or RTT output (default: stdin)",
+    )
+    args = parser.parse_args()
+
+    decoder = Decoder(load_driver_names())
+    for line in args.log:
+        idx = line.find(LINE_PREFIX)
+        if idx < 0:
+            continue
+        payload = line[idx + len(LINE_PREFIX) :].strip()
+        try:
+            record = bytes.fromhex(payload)
+        except ValueError:
+            continue
+        if len(record) != RECORD_LEN:
+            continue
+        for out in decoder.decode(record):
+            print(out, flush=True)
+
+
+if __name__ == "__main__":
+    main()